@@ -30,14 +30,17 @@ use clap::Parser;
 use console::style;
 use jmap_client::client::{Client, Credentials};
 use modules::{
+    audit::cmd_audit,
     cli::{Cli, Commands},
     database::cmd_database,
     export::cmd_export,
     get,
     import::cmd_import,
     is_localhost, post,
+    quarantine::cmd_quarantine,
     queue::cmd_queue,
     report::cmd_report,
+    suppression::cmd_suppression,
 };
 
 use crate::modules::OAuthResponse;
@@ -71,12 +74,21 @@ async fn main() -> std::io::Result<()> {
                 cmd_export(build_client(&args.url, credentials).await, command).await
             }
             Commands::Database(command) => cmd_database(&args.url, credentials, command).await,
-            Commands::Queue(_) | Commands::Report(_) => unreachable!(),
+            Commands::Queue(_)
+            | Commands::Report(_)
+            | Commands::Audit(_)
+            | Commands::Suppression(_)
+            | Commands::Quarantine(_) => unreachable!(),
         }
     } else {
         match args.command {
             Commands::Queue(command) => cmd_queue(&args.url, credentials, command).await,
             Commands::Report(command) => cmd_report(&args.url, credentials, command).await,
+            Commands::Audit(command) => cmd_audit(&args.url, credentials, command).await,
+            Commands::Suppression(command) => {
+                cmd_suppression(&args.url, credentials, command).await
+            }
+            Commands::Quarantine(command) => cmd_quarantine(&args.url, credentials, command).await,
             _ => unreachable!(),
         }
     }
@@ -0,0 +1,119 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::cli::AuditCommands;
+use crate::modules::queue::smtp_manage_request;
+use console::Term;
+use jmap_client::client::Credentials;
+use mail_parser::DateTime;
+use prettytable::{format::Alignment, Attr, Cell, Row, Table};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct AuditEvent {
+    pub timestamp: u64,
+    pub account: String,
+    pub remote_ip: String,
+    pub protocol: String,
+    pub action: String,
+    pub target: String,
+    pub details: String,
+}
+
+pub async fn cmd_audit(url: &str, credentials: Credentials, command: AuditCommands) {
+    match command {
+        AuditCommands::List {
+            account,
+            action,
+            before,
+            after,
+            limit,
+            page_size,
+        } => {
+            let stdout = Term::buffered_stdout();
+            let mut query = form_urlencoded::Serializer::new(format!("{url}/admin/audit/list?"));
+
+            if let Some(account) = &account {
+                query.append_pair("account", account);
+            }
+            if let Some(action) = &action {
+                query.append_pair("action", action);
+            }
+            if let Some(after) = &after {
+                query.append_pair("from", &after.to_timestamp().to_string());
+            }
+            if let Some(before) = &before {
+                query.append_pair("to", &before.to_timestamp().to_string());
+            }
+            if let Some(limit) = limit {
+                query.append_pair("limit", &limit.to_string());
+            }
+
+            let events =
+                smtp_manage_request::<Vec<AuditEvent>>(&query.finish(), &credentials).await;
+            let events_len = events.len();
+            let page_size = page_size.map(|p| std::cmp::max(p, 1)).unwrap_or(20);
+            let pages_total = (events_len as f64 / page_size as f64).ceil() as usize;
+            for (page_num, chunk) in events.chunks(page_size).enumerate() {
+                // Build table
+                let mut table = Table::new();
+                table.add_row(Row::new(
+                    [
+                        "Date",
+                        "Account",
+                        "Remote IP",
+                        "Protocol",
+                        "Action",
+                        "Target",
+                        "Details",
+                    ]
+                    .iter()
+                    .map(|p| Cell::new(p).with_style(Attr::Bold))
+                    .collect(),
+                ));
+                for event in chunk {
+                    table.add_row(Row::new(vec![
+                        Cell::new(&DateTime::from_timestamp(event.timestamp as i64).to_rfc822()),
+                        Cell::new(&event.account),
+                        Cell::new(&event.remote_ip),
+                        Cell::new_align(&event.protocol, Alignment::CENTER),
+                        Cell::new(&event.action),
+                        Cell::new(&event.target),
+                        Cell::new(&event.details),
+                    ]));
+                }
+
+                eprintln!();
+                table.printstd();
+                eprintln!();
+                if page_num + 1 != pages_total {
+                    eprintln!("\n--- Press any key to continue or 'q' to exit ---");
+                    if let Ok('q' | 'Q') = stdout.read_char() {
+                        break;
+                    }
+                }
+            }
+            eprintln!("\n{events_len} audit event(s) found.")
+        }
+    }
+}
@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use mail_parser::DateTime;
 use serde::Deserialize;
 
@@ -60,6 +60,18 @@ pub enum Commands {
     /// Manage SMTP DMARC/TLS report queue
     #[clap(subcommand)]
     Report(ReportCommands),
+
+    /// Query the administrative audit log
+    #[clap(subcommand)]
+    Audit(AuditCommands),
+
+    /// Manage the outbound recipient suppression list
+    #[clap(subcommand)]
+    Suppression(SuppressionCommands),
+
+    /// Manage quarantined messages
+    #[clap(subcommand)]
+    Quarantine(QuarantineCommands),
 }
 
 #[derive(Subcommand)]
@@ -126,8 +138,44 @@ pub enum DatabaseCommands {
         new_account: String,
     },
 
+    /// Place a JMAP account under legal hold
+    Hold {
+        /// Reason for the legal hold
+        #[clap(short, long)]
+        reason: Option<String>,
+
+        /// Account name to place under legal hold
+        account: String,
+    },
+
+    /// Release a JMAP account from legal hold
+    Release {
+        /// Account name to release
+        account: String,
+    },
+
     /// Purge expired blobs
     Purge {},
+
+    /// Back up the database and blob store
+    Backup {
+        /// Previous backup to create an incremental backup from
+        #[clap(short, long)]
+        base: Option<String>,
+
+        /// Server-side directory to write the backup to
+        path: String,
+    },
+
+    /// Restore the database and blob store from a backup
+    Restore {
+        /// Restore a single account instead of the whole server
+        #[clap(short, long)]
+        account: Option<String>,
+
+        /// Server-side directory containing the backup
+        path: String,
+    },
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -212,6 +260,9 @@ pub enum QueueCommands {
         // Cancel one or multiple message ids
         ids: Vec<String>,
     },
+
+    /// Shows adaptive delivery rates learned per destination
+    Adaptive,
 }
 
 #[derive(Subcommand)]
@@ -241,11 +292,169 @@ pub enum ReportCommands {
         #[clap(required = true)]
         ids: Vec<String>,
     },
+
+    /// Shows DMARC, TLS and ARF reports received from other domains
+    Received {
+        #[clap(flatten)]
+        filter: ReceivedReportFilter,
+        /// Maximum number of report records to fetch
+        #[clap(short, long)]
+        limit: Option<usize>,
+        /// Number of items to show per page
+        #[clap(short, long)]
+        page_size: Option<usize>,
+    },
+
+    /// Aggregates received reports, for example failures per source IP per day
+    Aggregate {
+        #[clap(flatten)]
+        filter: ReceivedReportFilter,
+        /// Fields to group by: 'type', 'domain', 'reporter', 'ip', 'disposition' or 'day'
+        #[clap(short, long, value_delimiter = ',', default_value = "ip,day")]
+        group_by: Vec<String>,
+    },
+}
+
+#[derive(Args)]
+pub struct ReceivedReportFilter {
+    /// Filter by reported domain
+    #[clap(short, long)]
+    pub domain: Option<String>,
+    /// Filter by report type
+    #[clap(short, long)]
+    #[clap(value_enum)]
+    pub format: Option<ReportFormat>,
+    /// Filter by reporting organization
+    #[clap(long)]
+    pub reporter: Option<String>,
+    /// Filter by source IP address
+    #[clap(long)]
+    pub ip: Option<String>,
+    /// Filter by disposition (e.g. 'reject', 'quarantine', 'abuse')
+    #[clap(long)]
+    pub disposition: Option<String>,
+    /// Only include records that failed authentication or delivery
+    #[clap(long)]
+    pub failures: bool,
+    /// Only include reports starting before a certain datetime
+    #[clap(short, long)]
+    #[arg(value_parser = parse_datetime)]
+    pub before: Option<DateTime>,
+    /// Only include reports starting after a certain datetime
+    #[clap(short, long)]
+    #[arg(value_parser = parse_datetime)]
+    pub after: Option<DateTime>,
+}
+
+#[derive(Subcommand)]
+pub enum AuditCommands {
+    /// Shows recorded audit events, newest first
+    List {
+        /// Filter by the account that performed the action
+        #[clap(long)]
+        account: Option<String>,
+        /// Filter by action or action prefix (e.g. 'acl', 'account.delete')
+        #[clap(long)]
+        action: Option<String>,
+        /// Show events recorded before a certain datetime
+        #[clap(short, long)]
+        #[arg(value_parser = parse_datetime)]
+        before: Option<DateTime>,
+        /// Show events recorded after a certain datetime
+        #[clap(short, long)]
+        #[arg(value_parser = parse_datetime)]
+        after: Option<DateTime>,
+        /// Maximum number of events to fetch
+        #[clap(short, long)]
+        limit: Option<usize>,
+        /// Number of items to show per page
+        #[clap(short, long)]
+        page_size: Option<usize>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SuppressionCommands {
+    /// Shows suppressed addresses and domains
+    List {
+        /// Only show entries for a certain domain
+        #[clap(long)]
+        domain: Option<String>,
+    },
+
+    /// Adds an address or a whole domain to the suppression list
+    Add {
+        /// Address or domain to suppress
+        address: String,
+        /// Suppression reason: 'manual', 'bounce' or 'complaint'
+        #[clap(short, long)]
+        reason: Option<String>,
+        /// Expire the entry after this duration (e.g. '30d'), never expires by default
+        #[clap(short, long)]
+        expires: Option<String>,
+        /// Additional details to store with the entry
+        #[clap(short, long)]
+        details: Option<String>,
+    },
+
+    /// Removes an address or domain from the suppression list
+    Remove {
+        /// Address or domain to remove
+        address: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum QuarantineCommands {
+    /// Shows quarantined messages
+    List {
+        /// Only show messages addressed to this recipient
+        #[clap(long)]
+        rcpt: Option<String>,
+        /// Only show messages quarantined by this source ('sieve' or 'milter')
+        #[clap(long)]
+        source: Option<String>,
+        /// Only show messages whose sender, subject or reason contain this text
+        #[clap(long)]
+        text: Option<String>,
+        /// Maximum number of messages to show
+        #[clap(short, long)]
+        limit: Option<usize>,
+    },
+
+    /// Displays the details and a text preview of a quarantined message
+    Preview {
+        /// Quarantined message id
+        id: u32,
+    },
+
+    /// Releases a quarantined message for delivery
+    Release {
+        /// Quarantined message id
+        id: u32,
+        /// Only release the message to this recipient
+        #[clap(long)]
+        rcpt: Option<String>,
+    },
+
+    /// Deletes a quarantined message
+    Delete {
+        /// Quarantined message ids
+        #[clap(required = true)]
+        ids: Vec<u32>,
+    },
 }
 
 impl Commands {
     pub fn is_jmap(&self) -> bool {
-        !matches!(self, Commands::Queue(_) | Commands::Report(_))
+        !matches!(
+            self,
+            Commands::Queue(_)
+                | Commands::Report(_)
+                | Commands::Audit(_)
+                | Commands::Suppression(_)
+                | Commands::Quarantine(_)
+        )
     }
 }
 
@@ -257,6 +466,9 @@ pub enum ReportFormat {
     /// TLS report
     #[serde(rename = "tls")]
     Tls,
+    /// Abuse feedback report (received reports only)
+    #[serde(rename = "arf")]
+    Arf,
 }
 
 fn parse_datetime(arg: &str) -> Result<DateTime, &'static str> {
@@ -33,7 +33,33 @@ pub async fn cmd_database(url: &str, credentials: Credentials, command: Database
             account,
             new_account,
         } => format!("{}/admin/account/rename/{}/{}", url, account, new_account),
+        DatabaseCommands::Hold { reason, account } => {
+            let mut query = form_urlencoded::Serializer::new(String::new());
+            if let Some(reason) = reason {
+                query.append_pair("reason", &reason);
+            }
+            format!("{}/admin/account/hold/{}?{}", url, account, query.finish())
+        }
+        DatabaseCommands::Release { account } => {
+            format!("{}/admin/account/release/{}", url, account)
+        }
         DatabaseCommands::Purge {} => format!("{}/admin/blob/purge", url),
+        DatabaseCommands::Backup { base, path } => {
+            let mut query = form_urlencoded::Serializer::new(String::new());
+            query.append_pair("path", &path);
+            if let Some(base) = base {
+                query.append_pair("base", &base);
+            }
+            format!("{}/admin/store/backup?{}", url, query.finish())
+        }
+        DatabaseCommands::Restore { account, path } => {
+            let mut query = form_urlencoded::Serializer::new(String::new());
+            query.append_pair("path", &path);
+            if let Some(account) = account {
+                query.append_pair("account", &account);
+            }
+            format!("{}/admin/store/restore?{}", url, query.finish())
+        }
     };
 
     let response = reqwest::Client::builder()
@@ -31,12 +31,15 @@ use jmap_client::{
     },
 };
 
+pub mod audit;
 pub mod cli;
 pub mod database;
 pub mod export;
 pub mod import;
+pub mod quarantine;
 pub mod queue;
 pub mod report;
+pub mod suppression;
 
 const RETRY_ATTEMPTS: usize = 5;
 
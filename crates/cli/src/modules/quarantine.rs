@@ -0,0 +1,197 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::cli::QuarantineCommands;
+use crate::modules::queue::smtp_manage_request;
+use human_size::{Byte, SpecificSize};
+use jmap_client::client::Credentials;
+use mail_parser::DateTime;
+use prettytable::{format::Alignment, Attr, Cell, Row, Table};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct QuarantinedMessage {
+    pub id: u32,
+    pub created: u64,
+    pub expires: u64,
+    pub size: u32,
+    pub notified: bool,
+    pub source: String,
+    pub reason: String,
+    pub return_path: String,
+    pub recipients: Vec<String>,
+    pub from: String,
+    pub subject: String,
+    pub remote_ip: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QuarantinePreview {
+    #[serde(flatten)]
+    pub message: QuarantinedMessage,
+    pub headers: String,
+    pub preview: String,
+}
+
+pub async fn cmd_quarantine(url: &str, credentials: Credentials, command: QuarantineCommands) {
+    match command {
+        QuarantineCommands::List {
+            rcpt,
+            source,
+            text,
+            limit,
+        } => {
+            let mut query =
+                form_urlencoded::Serializer::new(format!("{url}/admin/quarantine/list?"));
+            if let Some(rcpt) = &rcpt {
+                query.append_pair("rcpt", rcpt);
+            }
+            if let Some(source) = &source {
+                query.append_pair("source", source);
+            }
+            if let Some(text) = &text {
+                query.append_pair("text", text);
+            }
+            if let Some(limit) = limit {
+                query.append_pair("limit", &limit.to_string());
+            }
+
+            let messages =
+                smtp_manage_request::<Vec<QuarantinedMessage>>(&query.finish(), &credentials).await;
+            if messages.is_empty() {
+                eprintln!("No quarantined messages were found.");
+                return;
+            }
+
+            let mut table = Table::new();
+            table.add_row(Row::new(
+                ["Id", "Date", "Sender", "Recipients", "Subject", "Reason"]
+                    .iter()
+                    .map(|p| Cell::new(p).with_style(Attr::Bold))
+                    .collect(),
+            ));
+            for message in &messages {
+                table.add_row(Row::new(vec![
+                    Cell::new(&message.id.to_string()),
+                    Cell::new(&DateTime::from_timestamp(message.created as i64).to_rfc822()),
+                    Cell::new(if !message.from.is_empty() {
+                        &message.from
+                    } else {
+                        &message.return_path
+                    }),
+                    Cell::new(&message.recipients.join("\n")),
+                    Cell::new(&message.subject),
+                    Cell::new(&format!("{} ({})", message.reason, message.source)),
+                ]));
+            }
+
+            eprintln!();
+            table.printstd();
+            eprintln!("\n{} quarantined message(s) found.", messages.len());
+        }
+        QuarantineCommands::Preview { id } => {
+            let mut query =
+                form_urlencoded::Serializer::new(format!("{url}/admin/quarantine/preview?"));
+            query.append_pair("id", &id.to_string());
+
+            let preview =
+                smtp_manage_request::<QuarantinePreview>(&query.finish(), &credentials).await;
+            let message = &preview.message;
+            let mut table = Table::new();
+            for (name, value) in [
+                ("Id", message.id.to_string()),
+                ("Return-Path", message.return_path.clone()),
+                ("From", message.from.clone()),
+                ("Subject", message.subject.clone()),
+                ("Recipients", message.recipients.join("\n")),
+                ("Remote IP", message.remote_ip.clone()),
+                (
+                    "Size",
+                    SpecificSize::new(message.size, Byte).unwrap().to_string(),
+                ),
+                ("Source", message.source.clone()),
+                ("Reason", message.reason.clone()),
+                (
+                    "Quarantined",
+                    DateTime::from_timestamp(message.created as i64).to_rfc822(),
+                ),
+                (
+                    "Expires",
+                    DateTime::from_timestamp(message.expires as i64).to_rfc822(),
+                ),
+                (
+                    "Digest Sent",
+                    if message.notified { "Yes" } else { "No" }.to_string(),
+                ),
+            ] {
+                table.add_row(Row::new(vec![
+                    Cell::new(name).with_style(Attr::Bold),
+                    Cell::new(&value),
+                ]));
+            }
+
+            eprintln!();
+            table.printstd();
+            eprintln!();
+
+            let mut table = Table::new();
+            table.add_row(Row::new(vec![Cell::new_align(
+                "Headers",
+                Alignment::CENTER,
+            )
+            .with_style(Attr::Bold)]));
+            table.add_row(Row::new(vec![Cell::new(&preview.headers)]));
+            table.add_row(Row::new(vec![Cell::new_align(
+                "Preview",
+                Alignment::CENTER,
+            )
+            .with_style(Attr::Bold)]));
+            table.add_row(Row::new(vec![Cell::new(&preview.preview)]));
+            table.printstd();
+            eprintln!();
+        }
+        QuarantineCommands::Release { id, rcpt } => {
+            let mut query =
+                form_urlencoded::Serializer::new(format!("{url}/admin/quarantine/release?"));
+            query.append_pair("id", &id.to_string());
+            if let Some(rcpt) = &rcpt {
+                query.append_pair("rcpt", rcpt);
+            }
+
+            let released = smtp_manage_request::<Vec<String>>(&query.finish(), &credentials).await;
+            eprintln!(
+                "Released message {id} for delivery to {}.",
+                released.join(", ")
+            );
+        }
+        QuarantineCommands::Delete { ids } => {
+            for id in &ids {
+                let mut query =
+                    form_urlencoded::Serializer::new(format!("{url}/admin/quarantine/delete?"));
+                query.append_pair("id", &id.to_string());
+                smtp_manage_request::<bool>(&query.finish(), &credentials).await;
+            }
+            eprintln!("Deleted {} quarantined message(s).", ids.len());
+        }
+    }
+}
@@ -64,6 +64,18 @@ pub struct Recipient {
     pub orcpt: Option<String>,
 }
 
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct AdaptiveRate {
+    pub key: String,
+    pub rate: f64,
+    pub successes: u64,
+    pub deferrals: u64,
+    #[serde(deserialize_with = "deserialize_maybe_datetime")]
+    pub next_slot: Option<DateTime>,
+    #[serde(deserialize_with = "deserialize_maybe_datetime")]
+    pub last_deferral: Option<DateTime>,
+}
+
 #[derive(Debug, PartialEq, Eq, Deserialize)]
 pub enum Status {
     #[serde(rename = "scheduled")]
@@ -394,6 +406,56 @@ pub async fn cmd_queue(url: &str, credentials: Credentials, command: QueueComman
             }
             eprintln!();
         }
+        QueueCommands::Adaptive => {
+            let rates = smtp_manage_request::<Vec<AdaptiveRate>>(
+                &format!("{url}/admin/queue/adaptive"),
+                &credentials,
+            )
+            .await;
+            if rates.is_empty() {
+                eprintln!("No adaptive rates have been learned yet.");
+                return;
+            }
+
+            let mut table = Table::new();
+            table.add_row(Row::new(
+                [
+                    "Destination",
+                    "Rate (msg/min)",
+                    "Delivered",
+                    "Deferred",
+                    "Last Deferral",
+                    "Next Slot",
+                ]
+                .iter()
+                .map(|p| Cell::new(p).with_style(Attr::Bold))
+                .collect(),
+            ));
+            for rate in &rates {
+                table.add_row(Row::new(vec![
+                    Cell::new(&rate.key),
+                    Cell::new_align(&format!("{:.2}", rate.rate), Alignment::RIGHT),
+                    Cell::new_align(&rate.successes.to_string(), Alignment::RIGHT),
+                    Cell::new_align(&rate.deferrals.to_string(), Alignment::RIGHT),
+                    Cell::new(
+                        &rate
+                            .last_deferral
+                            .as_ref()
+                            .map_or_else(|| "Never".to_string(), |dt| dt.to_rfc822()),
+                    ),
+                    Cell::new(
+                        &rate
+                            .next_slot
+                            .as_ref()
+                            .map_or_else(|| "Now".to_string(), |dt| dt.to_rfc822()),
+                    ),
+                ]));
+            }
+
+            eprintln!();
+            table.printstd();
+            eprintln!("\n{} destination(s) found.", rates.len());
+        }
     }
 }
 
@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use super::cli::{ReportCommands, ReportFormat};
+use super::cli::{ReceivedReportFilter, ReportCommands, ReportFormat};
 use crate::modules::queue::{deserialize_datetime, smtp_manage_request};
 use console::Term;
 use human_size::{Byte, SpecificSize};
@@ -42,6 +42,33 @@ pub struct Report {
     pub size: usize,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ReceivedReport {
+    #[serde(rename = "type")]
+    pub type_: ReportFormat,
+    pub reporter: String,
+    pub domain: String,
+    pub date_begin: u64,
+    pub date_end: u64,
+    pub source_ip: Option<String>,
+    pub count: u64,
+    pub disposition: String,
+    pub failure: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReceivedReportAggregate {
+    #[serde(rename = "type")]
+    pub type_: Option<ReportFormat>,
+    pub domain: Option<String>,
+    pub reporter: Option<String>,
+    pub source_ip: Option<String>,
+    pub disposition: Option<String>,
+    pub day: Option<String>,
+    pub total: u64,
+    pub failures: u64,
+}
+
 pub async fn cmd_report(url: &str, credentials: Credentials, command: ReportCommands) {
     match command {
         ReportCommands::List {
@@ -186,6 +213,161 @@ pub async fn cmd_report(url: &str, credentials: Credentials, command: ReportComm
             }
             eprintln!();
         }
+        ReportCommands::Received {
+            filter,
+            limit,
+            page_size,
+        } => {
+            let stdout = Term::buffered_stdout();
+            let mut query =
+                form_urlencoded::Serializer::new(format!("{url}/admin/report/received?"));
+            filter.append_to(&mut query);
+            if let Some(limit) = limit {
+                query.append_pair("limit", &limit.to_string());
+            }
+
+            let reports =
+                smtp_manage_request::<Vec<ReceivedReport>>(&query.finish(), &credentials).await;
+            let reports_len = reports.len();
+            let page_size = page_size.map(|p| std::cmp::max(p, 1)).unwrap_or(20);
+            let pages_total = (reports_len as f64 / page_size as f64).ceil() as usize;
+            for (page_num, chunk) in reports.chunks(page_size).enumerate() {
+                // Build table
+                let mut table = Table::new();
+                table.add_row(Row::new(
+                    [
+                        "Type",
+                        "Domain",
+                        "Reporter",
+                        "From Date",
+                        "To Date",
+                        "Source IP",
+                        "Count",
+                        "Disposition",
+                        "Failure",
+                    ]
+                    .iter()
+                    .map(|p| Cell::new(p).with_style(Attr::Bold))
+                    .collect(),
+                ));
+                for report in chunk {
+                    table.add_row(Row::new(vec![
+                        Cell::new(report.type_.name()),
+                        Cell::new(&report.domain),
+                        Cell::new(&report.reporter),
+                        Cell::new(&DateTime::from_timestamp(report.date_begin as i64).to_rfc822()),
+                        Cell::new(&DateTime::from_timestamp(report.date_end as i64).to_rfc822()),
+                        Cell::new(report.source_ip.as_deref().unwrap_or("-")),
+                        Cell::new_align(&report.count.to_string(), Alignment::RIGHT),
+                        Cell::new(&report.disposition),
+                        Cell::new_align(
+                            if report.failure { "Yes" } else { "No" },
+                            Alignment::CENTER,
+                        ),
+                    ]));
+                }
+
+                eprintln!();
+                table.printstd();
+                eprintln!();
+                if page_num + 1 != pages_total {
+                    eprintln!("\n--- Press any key to continue or 'q' to exit ---");
+                    if let Ok('q' | 'Q') = stdout.read_char() {
+                        break;
+                    }
+                }
+            }
+            eprintln!("\n{reports_len} report record(s) found.")
+        }
+        ReportCommands::Aggregate { filter, group_by } => {
+            let mut query =
+                form_urlencoded::Serializer::new(format!("{url}/admin/report/aggregate?"));
+            filter.append_to(&mut query);
+            query.append_pair("group-by", &group_by.join(","));
+
+            let groups =
+                smtp_manage_request::<Vec<ReceivedReportAggregate>>(&query.finish(), &credentials)
+                    .await;
+            if groups.is_empty() {
+                eprintln!("No received reports were found.");
+                return;
+            }
+
+            let mut table = Table::new();
+            let mut header = group_by
+                .iter()
+                .map(|group| {
+                    Cell::new(match group.as_str() {
+                        "type" => "Type",
+                        "domain" => "Domain",
+                        "reporter" => "Reporter",
+                        "ip" | "source-ip" => "Source IP",
+                        "disposition" => "Disposition",
+                        "day" => "Day",
+                        group => group,
+                    })
+                    .with_style(Attr::Bold)
+                })
+                .collect::<Vec<_>>();
+            header.push(Cell::new("Total").with_style(Attr::Bold));
+            header.push(Cell::new("Failures").with_style(Attr::Bold));
+            table.add_row(Row::new(header));
+            for group in &groups {
+                let mut row = group_by
+                    .iter()
+                    .map(|item| {
+                        Cell::new(match item.as_str() {
+                            "type" => group.type_.map_or("-", |type_| type_.name()),
+                            "domain" => group.domain.as_deref().unwrap_or("-"),
+                            "reporter" => group.reporter.as_deref().unwrap_or("-"),
+                            "ip" | "source-ip" => group.source_ip.as_deref().unwrap_or("-"),
+                            "disposition" => group.disposition.as_deref().unwrap_or("-"),
+                            "day" => group.day.as_deref().unwrap_or("-"),
+                            _ => "-",
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                row.push(Cell::new_align(&group.total.to_string(), Alignment::RIGHT));
+                row.push(Cell::new_align(
+                    &group.failures.to_string(),
+                    Alignment::RIGHT,
+                ));
+                table.add_row(Row::new(row));
+            }
+
+            eprintln!();
+            table.printstd();
+            eprintln!("\n{} group(s) found.", groups.len());
+        }
+    }
+}
+
+impl ReceivedReportFilter {
+    fn append_to(&self, query: &mut form_urlencoded::Serializer<'_, String>) {
+        if let Some(domain) = &self.domain {
+            query.append_pair("domain", domain);
+        }
+        if let Some(format) = &self.format {
+            query.append_pair("type", format.id());
+        }
+        if let Some(reporter) = &self.reporter {
+            query.append_pair("reporter", reporter);
+        }
+        if let Some(ip) = &self.ip {
+            query.append_pair("ip", ip);
+        }
+        if let Some(disposition) = &self.disposition {
+            query.append_pair("disposition", disposition);
+        }
+        if self.failures {
+            query.append_pair("failures", "true");
+        }
+        if let Some(after) = &self.after {
+            query.append_pair("from", &after.to_timestamp().to_string());
+        }
+        if let Some(before) = &self.before {
+            query.append_pair("to", &before.to_timestamp().to_string());
+        }
     }
 }
 
@@ -194,6 +376,7 @@ impl ReportFormat {
         match self {
             ReportFormat::Dmarc => "dmarc",
             ReportFormat::Tls => "tls",
+            ReportFormat::Arf => "arf",
         }
     }
 
@@ -201,6 +384,7 @@ impl ReportFormat {
         match self {
             ReportFormat::Dmarc => "DMARC",
             ReportFormat::Tls => "TLS",
+            ReportFormat::Arf => "ARF",
         }
     }
 }
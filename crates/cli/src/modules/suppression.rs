@@ -0,0 +1,117 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::cli::SuppressionCommands;
+use crate::modules::queue::smtp_manage_request;
+use jmap_client::client::Credentials;
+use mail_parser::DateTime;
+use prettytable::{Attr, Cell, Row, Table};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct SuppressionEntry {
+    pub address: String,
+    pub reason: String,
+    pub created: u64,
+    pub expires: Option<u64>,
+    pub details: String,
+}
+
+pub async fn cmd_suppression(url: &str, credentials: Credentials, command: SuppressionCommands) {
+    match command {
+        SuppressionCommands::List { domain } => {
+            let mut query =
+                form_urlencoded::Serializer::new(format!("{url}/admin/suppression/list?"));
+            if let Some(domain) = &domain {
+                query.append_pair("domain", domain);
+            }
+
+            let entries =
+                smtp_manage_request::<Vec<SuppressionEntry>>(&query.finish(), &credentials).await;
+            if entries.is_empty() {
+                eprintln!("No suppressed addresses were found.");
+                return;
+            }
+
+            let mut table = Table::new();
+            table.add_row(Row::new(
+                ["Address", "Reason", "Created", "Expires", "Details"]
+                    .iter()
+                    .map(|p| Cell::new(p).with_style(Attr::Bold))
+                    .collect(),
+            ));
+            for entry in &entries {
+                table.add_row(Row::new(vec![
+                    Cell::new(&entry.address),
+                    Cell::new(&entry.reason),
+                    Cell::new(&DateTime::from_timestamp(entry.created as i64).to_rfc822()),
+                    Cell::new(
+                        &entry
+                            .expires
+                            .map(|expires| DateTime::from_timestamp(expires as i64).to_rfc822())
+                            .unwrap_or_else(|| "Never".to_string()),
+                    ),
+                    Cell::new(&entry.details),
+                ]));
+            }
+
+            eprintln!();
+            table.printstd();
+            eprintln!("\n{} suppressed address(es) found.", entries.len());
+        }
+        SuppressionCommands::Add {
+            address,
+            reason,
+            expires,
+            details,
+        } => {
+            let mut query =
+                form_urlencoded::Serializer::new(format!("{url}/admin/suppression/add?"));
+            query.append_pair("address", &address);
+            if let Some(reason) = &reason {
+                query.append_pair("reason", reason);
+            }
+            if let Some(expires) = &expires {
+                query.append_pair("expires", expires);
+            }
+            if let Some(details) = &details {
+                query.append_pair("details", details);
+            }
+
+            smtp_manage_request::<bool>(&query.finish(), &credentials).await;
+            eprintln!("Added {address} to the suppression list.");
+        }
+        SuppressionCommands::Remove { address } => {
+            let mut query =
+                form_urlencoded::Serializer::new(format!("{url}/admin/suppression/remove?"));
+            query.append_pair("address", &address);
+
+            if smtp_manage_request::<bool>(&query.finish(), &credentials).await {
+                eprintln!("Removed {address} from the suppression list.");
+            } else {
+                eprintln!("{address} is not on the suppression list.");
+                std::process::exit(1);
+            }
+        }
+    }
+}
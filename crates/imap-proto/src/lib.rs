@@ -89,6 +89,12 @@ pub enum Command {
 
     // RFC 2971
     Id,
+
+    // RFC 5267
+    CancelUpdate,
+
+    // RFC 8508
+    Replace(bool),
 }
 
 impl Command {
@@ -103,6 +109,7 @@ impl Command {
                 | Command::Expunge(true)
                 | Command::Sort(true)
                 | Command::Thread(true)
+                | Command::Replace(true)
         )
     }
 }
@@ -145,6 +152,7 @@ pub enum ResponseCode {
     ReadOnly,
     ReadWrite,
     ServerBug,
+    TooBig,
     TryCreate,
     UidNext,
     UidNotSticky,
@@ -167,6 +175,11 @@ pub enum ResponseCode {
 
     // USEATTR
     UseAttr,
+
+    // CONTEXT=SEARCH
+    NoUpdate {
+        tag: String,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
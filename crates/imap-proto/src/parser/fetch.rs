@@ -26,7 +26,10 @@ use std::iter::Peekable;
 use std::vec::IntoIter;
 
 use crate::{
-    protocol::fetch::{self, Attribute, Section},
+    protocol::{
+        fetch::{self, Attribute, Section},
+        search::PartialRange,
+    },
     receiver::{Request, Token},
     Command,
 };
@@ -336,6 +339,8 @@ impl Request<Command> {
                         attributes.push_unique(Attribute::EmailId);
                     } else if value.eq_ignore_ascii_case(b"THREADID") {
                         attributes.push_unique(Attribute::ThreadId);
+                    } else if value.eq_ignore_ascii_case(b"SAVEDATE") {
+                        attributes.push_unique(Attribute::SaveDate);
                     } else {
                         return Err((
                             self.tag,
@@ -371,6 +376,7 @@ impl Request<Command> {
         // CONDSTORE parameters
         let mut changed_since = None;
         let mut include_vanished = false;
+        let mut partial = None;
         if let Some(Token::ParenthesisOpen) = tokens.peek() {
             tokens.next();
             while let Some(token) = tokens.next() {
@@ -388,6 +394,16 @@ impl Request<Command> {
                     Token::Argument(param) if param.eq_ignore_ascii_case(b"VANISHED") => {
                         include_vanished = true;
                     }
+                    Token::Argument(param) if param.eq_ignore_ascii_case(b"PARTIAL") => {
+                        partial = PartialRange::parse(
+                            &tokens
+                                .next()
+                                .ok_or((self.tag.as_str(), "Missing PARTIAL parameter."))?
+                                .unwrap_bytes(),
+                        )
+                        .map_err(|v| (self.tag.as_str(), v))?
+                        .into();
+                    }
                     Token::ParenthesisClose => {
                         break;
                     }
@@ -409,6 +425,7 @@ impl Request<Command> {
                 attributes,
                 changed_since,
                 include_vanished,
+                partial,
             })
         } else {
             Err((self.tag, "No data items to fetch specified.").into())
@@ -498,6 +515,7 @@ mod tests {
     use crate::{
         protocol::{
             fetch::{self, Attribute, Section},
+            search::PartialRange,
             Sequence,
         },
         receiver::Receiver,
@@ -526,6 +544,7 @@ mod tests {
                     ],
                     changed_since: None,
                     include_vanished: false,
+                    partial: None,
                 },
             ),
             (
@@ -540,6 +559,7 @@ mod tests {
                     }],
                     changed_since: None,
                     include_vanished: false,
+                    partial: None,
                 },
             ),
             (
@@ -554,6 +574,7 @@ mod tests {
                     }],
                     changed_since: None,
                     include_vanished: false,
+                    partial: None,
                 },
             ),
             (
@@ -574,6 +595,7 @@ mod tests {
                     ],
                     changed_since: None,
                     include_vanished: false,
+                    partial: None,
                 },
             ),
             (
@@ -595,6 +617,7 @@ mod tests {
                     }],
                     changed_since: None,
                     include_vanished: false,
+                    partial: None,
                 },
             ),
             (
@@ -617,6 +640,7 @@ mod tests {
                     ],
                     changed_since: None,
                     include_vanished: false,
+                    partial: None,
                 },
             ),
             (
@@ -633,6 +657,7 @@ mod tests {
                     ],
                     changed_since: None,
                     include_vanished: false,
+                    partial: None,
                 },
             ),
             (
@@ -648,6 +673,7 @@ mod tests {
                     ],
                     changed_since: None,
                     include_vanished: false,
+                    partial: None,
                 },
             ),
             (
@@ -721,6 +747,7 @@ mod tests {
                     ],
                     changed_since: None,
                     include_vanished: false,
+                    partial: None,
                 },
             ),
             (
@@ -736,6 +763,7 @@ mod tests {
                     ],
                     changed_since: None,
                     include_vanished: false,
+                    partial: None,
                 },
             ),
             (
@@ -752,6 +780,7 @@ mod tests {
                     ],
                     changed_since: None,
                     include_vanished: false,
+                    partial: None,
                 },
             ),
             (
@@ -766,6 +795,7 @@ mod tests {
                     ],
                     changed_since: None,
                     include_vanished: false,
+                    partial: None,
                 },
             ),
             (
@@ -776,6 +806,18 @@ mod tests {
                     attributes: vec![Attribute::Flags, Attribute::ModSeq],
                     changed_since: 12345.into(),
                     include_vanished: true,
+                    partial: None,
+                },
+            ),
+            (
+                "A01 UID FETCH 1:* (UID FLAGS) (PARTIAL -1:-30)\r\n",
+                fetch::Arguments {
+                    tag: "A01".to_string(),
+                    sequence_set: Sequence::range(1.into(), None),
+                    attributes: vec![Attribute::Uid, Attribute::Flags],
+                    changed_since: None,
+                    include_vanished: false,
+                    partial: PartialRange::Last(1, 30).into(),
                 },
             ),
         ] {
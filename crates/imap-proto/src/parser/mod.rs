@@ -33,6 +33,7 @@ pub mod list;
 pub mod login;
 pub mod lsub;
 pub mod rename;
+pub mod replace;
 pub mod search;
 pub mod select;
 pub mod sort;
@@ -94,6 +95,8 @@ impl CommandParser for Command {
             b"MYRIGHTS" => Some(Command::MyRights),
             b"UNAUTHENTICATE" => Some(Command::Unauthenticate),
             b"ID" => Some(Command::Id),
+            b"CANCELUPDATE" => Some(Command::CancelUpdate),
+            b"REPLACE" => Some(Command::Replace(uid)),
             _ => None,
         }
     }
@@ -0,0 +1,116 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::{
+    protocol::replace,
+    receiver::{Request, Token},
+    Command,
+};
+
+use super::parse_sequence_set;
+
+impl Request<Command> {
+    pub fn parse_replace(self) -> crate::Result<replace::Arguments> {
+        if self.tokens.len() < 3 {
+            return Err(self.into_error("Missing arguments."));
+        }
+        let mut tokens = self.tokens.into_iter();
+        let sequence_set = parse_sequence_set(&tokens.next().unwrap().unwrap_bytes())
+            .map_err(|v| (self.tag.as_str(), v))?;
+
+        // The remaining arguments follow the APPEND syntax
+        let mut arguments = Request {
+            tag: self.tag,
+            command: self.command,
+            tokens: tokens.collect::<Vec<Token>>(),
+        }
+        .parse_append()?;
+        if arguments.messages.len() == 1 {
+            Ok(replace::Arguments {
+                tag: arguments.tag,
+                sequence_set,
+                mailbox_name: arguments.mailbox_name,
+                message: arguments.messages.pop().unwrap(),
+            })
+        } else {
+            Err((
+                arguments.tag.as_str(),
+                "REPLACE only accepts a single message.",
+            )
+                .into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        protocol::{append::Message, replace, Flag, Sequence},
+        receiver::Receiver,
+    };
+
+    #[test]
+    fn parse_replace() {
+        let mut receiver = Receiver::new();
+
+        for (command, arguments) in [
+            (
+                "A003 REPLACE 4 Drafts (\\Seen \\Draft) {1+}\r\na\r\n",
+                replace::Arguments {
+                    tag: "A003".to_string(),
+                    sequence_set: Sequence::Number { value: 4 },
+                    mailbox_name: "Drafts".to_string(),
+                    message: Message {
+                        message: vec![b'a'],
+                        flags: vec![Flag::Seen, Flag::Draft],
+                        received_at: None,
+                    },
+                },
+            ),
+            (
+                "A004 UID REPLACE 2000 \"Other\" \"20-Nov-2022 23:59:59 +0300\" {1+}\r\nb\r\n",
+                replace::Arguments {
+                    tag: "A004".to_string(),
+                    sequence_set: Sequence::Number { value: 2000 },
+                    mailbox_name: "Other".to_string(),
+                    message: Message {
+                        message: vec![b'b'],
+                        flags: vec![],
+                        received_at: Some(1668977999),
+                    },
+                },
+            ),
+        ] {
+            assert_eq!(
+                receiver
+                    .parse(&mut command.as_bytes().iter())
+                    .unwrap()
+                    .parse_replace()
+                    .unwrap(),
+                arguments,
+                "{:?}",
+                command
+            );
+        }
+    }
+}
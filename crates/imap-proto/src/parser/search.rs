@@ -29,7 +29,7 @@ use mail_parser::decoders::charsets::map::charset_decoder;
 use mail_parser::decoders::charsets::DecoderFnc;
 
 use crate::protocol::search::{self, Filter};
-use crate::protocol::search::{ModSeqEntry, ResultOption};
+use crate::protocol::search::{ModSeqEntry, PartialRange, ResultOption};
 use crate::protocol::{Flag, ProtocolVersion};
 use crate::receiver::{Request, Token};
 use crate::Command;
@@ -95,9 +95,17 @@ pub fn parse_result_options(
         return Err(Cow::from("Invalid result option, expected parenthesis."));
     }
 
-    for token in tokens {
+    while let Some(token) = tokens.next() {
         match token {
             Token::ParenthesisClose => break,
+            Token::Argument(value) if value.eq_ignore_ascii_case(b"partial") => {
+                result_options.push(ResultOption::Partial(PartialRange::parse(
+                    &tokens
+                        .next()
+                        .ok_or_else(|| Cow::from("Missing PARTIAL range."))?
+                        .unwrap_bytes(),
+                )?));
+            }
             Token::Argument(value) => {
                 result_options.push(ResultOption::parse(&value)?);
             }
@@ -105,6 +113,16 @@ pub fn parse_result_options(
         }
     }
 
+    if result_options
+        .iter()
+        .any(|option| matches!(option, ResultOption::Partial(_)))
+        && result_options.contains(&ResultOption::All)
+    {
+        return Err(Cow::from(
+            "PARTIAL and ALL result options are mutually exclusive.",
+        ));
+    }
+
     Ok(result_options)
 }
 
@@ -197,6 +215,29 @@ pub fn parse_filters(
                             .ok_or_else(|| Cow::from("Expected date"))?
                             .unwrap_bytes(),
                     )?));
+                } else if value.eq_ignore_ascii_case(b"SAVEDBEFORE") {
+                    filters.push(Filter::SavedBefore(parse_date(
+                        &tokens
+                            .next()
+                            .ok_or_else(|| Cow::from("Expected date"))?
+                            .unwrap_bytes(),
+                    )?));
+                } else if value.eq_ignore_ascii_case(b"SAVEDON") {
+                    filters.push(Filter::SavedOn(parse_date(
+                        &tokens
+                            .next()
+                            .ok_or_else(|| Cow::from("Expected date"))?
+                            .unwrap_bytes(),
+                    )?));
+                } else if value.eq_ignore_ascii_case(b"SAVEDSINCE") {
+                    filters.push(Filter::SavedSince(parse_date(
+                        &tokens
+                            .next()
+                            .ok_or_else(|| Cow::from("Expected date"))?
+                            .unwrap_bytes(),
+                    )?));
+                } else if value.eq_ignore_ascii_case(b"SAVEDATESUPPORTED") {
+                    filters.push(Filter::SaveDateSupported);
                 } else if value.eq_ignore_ascii_case(b"SMALLER") {
                     filters.push(Filter::Smaller(parse_number::<u32>(
                         &tokens
@@ -430,17 +471,76 @@ impl ResultOption {
             Ok(Self::Save)
         } else if value.eq_ignore_ascii_case(b"context") {
             Ok(Self::Context)
+        } else if value.eq_ignore_ascii_case(b"update") {
+            Ok(Self::Update)
         } else {
             Err(format!("Invalid result option {:?}", String::from_utf8_lossy(value)).into())
         }
     }
 }
 
+impl PartialRange {
+    pub fn parse(value: &[u8]) -> super::Result<Self> {
+        let invalid = || {
+            Cow::from(format!(
+                "Invalid PARTIAL range {:?}.",
+                String::from_utf8_lossy(value)
+            ))
+        };
+        let (low, high) = value
+            .iter()
+            .position(|&ch| ch == b':')
+            .map(|pos| (&value[..pos], &value[pos + 1..]))
+            .ok_or_else(invalid)?;
+
+        match (low.strip_prefix(b"-"), high.strip_prefix(b"-")) {
+            (Some(low), Some(high)) => {
+                let (low, high) = (parse_number::<u32>(low)?, parse_number::<u32>(high)?);
+                if low > 0 && high > 0 {
+                    Ok(PartialRange::Last(
+                        std::cmp::min(low, high),
+                        std::cmp::max(low, high),
+                    ))
+                } else {
+                    Err(invalid())
+                }
+            }
+            (None, None) => {
+                let (low, high) = (parse_number::<u32>(low)?, parse_number::<u32>(high)?);
+                if low > 0 && high > 0 {
+                    Ok(PartialRange::First(
+                        std::cmp::min(low, high),
+                        std::cmp::max(low, high),
+                    ))
+                } else {
+                    Err(invalid())
+                }
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Request<Command> {
+    pub fn parse_cancel_update(self) -> crate::Result<Vec<String>> {
+        if self.tokens.is_empty() {
+            return Err(self.into_error("Missing tags."));
+        }
+
+        let mut tags = Vec::with_capacity(self.tokens.len());
+        for token in self.tokens {
+            tags.push(token.unwrap_string().map_err(|v| (self.tag.as_str(), v))?);
+        }
+
+        Ok(tags)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         protocol::{
-            search::{self, Filter, ModSeqEntry, ResultOption},
+            search::{self, Filter, ModSeqEntry, PartialRange, ResultOption},
             Flag, ProtocolVersion, Sequence,
         },
         receiver::Receiver,
@@ -725,6 +825,48 @@ mod tests {
                     sort: None,
                 },
             ),
+            (
+                b"A01 SEARCH RETURN (PARTIAL -1:-100 COUNT) UNDELETED\r\n".to_vec(),
+                search::Arguments {
+                    tag: "A01".to_string(),
+                    result_options: vec![
+                        ResultOption::Partial(PartialRange::Last(1, 100)),
+                        ResultOption::Count,
+                    ],
+                    filter: vec![Filter::Undeleted],
+                    is_esearch: true,
+                    sort: None,
+                },
+            ),
+            (
+                b"A02 SEARCH RETURN (CONTEXT UPDATE PARTIAL 50:1) UNSEEN\r\n".to_vec(),
+                search::Arguments {
+                    tag: "A02".to_string(),
+                    result_options: vec![
+                        ResultOption::Context,
+                        ResultOption::Update,
+                        ResultOption::Partial(PartialRange::First(1, 50)),
+                    ],
+                    filter: vec![Filter::Unseen],
+                    is_esearch: true,
+                    sort: None,
+                },
+            ),
+            (
+                b"A03 SEARCH SAVEDATESUPPORTED SAVEDSINCE 1-Feb-1994 SAVEDBEFORE 1-Feb-1995\r\n"
+                    .to_vec(),
+                search::Arguments {
+                    tag: "A03".to_string(),
+                    result_options: vec![],
+                    filter: vec![
+                        Filter::SaveDateSupported,
+                        Filter::SavedSince(760060800),
+                        Filter::SavedBefore(791596800),
+                    ],
+                    is_esearch: true,
+                    sort: None,
+                },
+            ),
             (
                 b"t SEARCH OR NOT MODSEQ 720162338 LARGER 50000\r\n".to_vec(),
                 search::Arguments {
@@ -755,5 +897,17 @@ mod tests {
                 command_str
             );
         }
+
+        for command in [
+            b"A03 SEARCH RETURN (PARTIAL 1:-10) ALL\r\n".to_vec(),
+            b"A04 SEARCH RETURN (PARTIAL 0:10) ALL\r\n".to_vec(),
+            b"A05 SEARCH RETURN (PARTIAL 1:10 ALL) ALL\r\n".to_vec(),
+        ] {
+            assert!(receiver
+                .parse(&mut command.iter())
+                .unwrap()
+                .parse_search(ProtocolVersion::Rev2)
+                .is_err());
+        }
     }
 }
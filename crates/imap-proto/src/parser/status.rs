@@ -105,6 +105,8 @@ impl Status {
             Ok(Self::MailboxId)
         } else if value.eq_ignore_ascii_case(b"recent") {
             Ok(Self::Recent)
+        } else if value.eq_ignore_ascii_case(b"appendlimit") {
+            Ok(Self::AppendLimit)
         } else {
             Err(format!(
                 "Invalid status option '{}'.",
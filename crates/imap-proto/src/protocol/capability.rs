@@ -64,6 +64,12 @@ pub enum Capability {
     ObjectId,
     Preview,
     Utf8Accept,
+    ContextSearch, //CONTEXT=SEARCH
+    ContextSort,   //CONTEXT=SORT
+    Partial,
+    Replace,
+    SaveDate,
+    AppendLimit(usize), //APPENDLIMIT=<n>
     Auth(Mechanism),
 }
 
@@ -75,6 +81,11 @@ impl Capability {
                 mechanism.serialize(buf);
                 return;
             }
+            Capability::AppendLimit(limit) => {
+                buf.extend_from_slice(b"APPENDLIMIT=");
+                buf.extend_from_slice(limit.to_string().as_bytes());
+                return;
+            }
             Capability::IMAP4rev2 => b"IMAP4rev2",
             Capability::IMAP4rev1 => b"IMAP4rev1",
             Capability::StartTLS => b"STARTTLS",
@@ -109,6 +120,11 @@ impl Capability {
             Capability::CreateSpecialUse => b"CREATE-SPECIAL-USE",
             Capability::Move => b"MOVE",
             Capability::Utf8Accept => b"UTF8=ACCEPT",
+            Capability::ContextSearch => b"CONTEXT=SEARCH",
+            Capability::ContextSort => b"CONTEXT=SORT",
+            Capability::Partial => b"PARTIAL",
+            Capability::Replace => b"REPLACE",
+            Capability::SaveDate => b"SAVEDATE",
         });
     }
 
@@ -141,6 +157,9 @@ impl Capability {
                 Capability::ListExtended,
                 Capability::ESort,
                 Capability::SortDisplay,
+                Capability::ContextSearch,
+                Capability::ContextSort,
+                Capability::Partial,
                 Capability::SpecialUse,
                 Capability::CreateSpecialUse,
                 Capability::Move,
@@ -150,6 +169,8 @@ impl Capability {
                 Capability::StatusSize,
                 Capability::ObjectId,
                 Capability::Preview,
+                Capability::Replace,
+                Capability::SaveDate,
             ]);
         } else {
             capabilties.extend([
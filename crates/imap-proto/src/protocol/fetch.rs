@@ -27,7 +27,7 @@ use mail_parser::DateTime;
 
 use super::{
     literal_string, quoted_rfc2822_or_nil, quoted_string, quoted_string_or_nil, quoted_timestamp,
-    Flag, ImapResponse, Sequence,
+    search::PartialRange, Flag, ImapResponse, Sequence,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -37,6 +37,7 @@ pub struct Arguments {
     pub attributes: Vec<Attribute>,
     pub changed_since: Option<u64>,
     pub include_vanished: bool,
+    pub partial: Option<PartialRange>,
 }
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Response<'x> {
@@ -81,6 +82,7 @@ pub enum Attribute {
     ModSeq,
     EmailId,
     ThreadId,
+    SaveDate,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -150,6 +152,9 @@ pub enum DataItem<'x> {
     ThreadId {
         thread_id: String,
     },
+    SaveDate {
+        date: Option<i64>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -844,6 +849,14 @@ impl<'x> DataItem<'x> {
                 buf.extend_from_slice(thread_id.as_bytes());
                 buf.push(b')');
             }
+            DataItem::SaveDate { date } => {
+                buf.extend_from_slice(b"SAVEDATE ");
+                if let Some(date) = date {
+                    quoted_timestamp(buf, *date);
+                } else {
+                    buf.extend_from_slice(b"NIL");
+                }
+            }
         }
     }
 }
@@ -43,6 +43,7 @@ pub mod list;
 pub mod login;
 pub mod namespace;
 pub mod rename;
+pub mod replace;
 pub mod search;
 pub mod select;
 pub mod status;
@@ -354,6 +355,7 @@ impl ResponseCode {
             ResponseCode::ReadOnly => b"READ-ONLY",
             ResponseCode::ReadWrite => b"READ-WRITE",
             ResponseCode::ServerBug => b"SERVERBUG",
+            ResponseCode::TooBig => b"TOOBIG",
             ResponseCode::TryCreate => b"TRYCREATE",
             ResponseCode::UidNext => b"UIDNEXT",
             ResponseCode::UidNotSticky => b"UIDNOTSTICKY",
@@ -377,6 +379,11 @@ impl ResponseCode {
                 return;
             }
             ResponseCode::UseAttr => b"USEATTR",
+            ResponseCode::NoUpdate { tag } => {
+                buf.extend_from_slice(b"NOUPDATE ");
+                quoted_string(buf, tag);
+                return;
+            }
         });
     }
 }
@@ -505,6 +512,9 @@ impl Display for Command {
             Command::MyRights => write!(f, "MYRIGHTS"),
             Command::Unauthenticate => write!(f, "UNAUTHENTICATE"),
             Command::Id => write!(f, "ID"),
+            Command::CancelUpdate => write!(f, "CANCELUPDATE"),
+            Command::Replace(false) => write!(f, "REPLACE"),
+            Command::Replace(true) => write!(f, "UID REPLACE"),
         }
     }
 }
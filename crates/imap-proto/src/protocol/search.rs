@@ -60,6 +60,7 @@ pub struct Response {
     pub min: Option<u32>,
     pub max: Option<u32>,
     pub count: Option<u32>,
+    pub partial: Option<PartialRange>,
     pub highest_modseq: Option<u64>,
 }
 
@@ -71,6 +72,23 @@ pub enum ResultOption {
     Count,
     Save,
     Context,
+    Update,
+    Partial(PartialRange),
+}
+
+// RFC 9394 - Ranges are 1-based and normalized so that the first value is the
+// closest to the start (First) or to the end (Last) of the result list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartialRange {
+    First(u32, u32),
+    Last(u32, u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextUpdate {
+    pub is_uid: bool,
+    pub add_to: Vec<(u32, u32)>,
+    pub remove_from: Vec<(u32, u32)>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -127,6 +145,12 @@ pub enum Filter {
     // RFC 8474 - ObjectID
     EmailId(String),
     ThreadId(String),
+
+    // RFC 8514 - SAVEDATE
+    SavedBefore(i64),
+    SavedOn(i64),
+    SavedSince(i64),
+    SaveDateSupported,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -147,6 +171,59 @@ impl Filter {
     }
 }
 
+impl PartialRange {
+    /// Returns the offsets of this range within a result list of the given length.
+    pub fn range(&self, len: usize) -> std::ops::Range<usize> {
+        let (start, end) = match *self {
+            PartialRange::First(low, high) => (low as usize - 1, high as usize),
+            PartialRange::Last(low, high) => (
+                len.saturating_sub(high as usize),
+                len.saturating_sub(low as usize - 1),
+            ),
+        };
+        std::cmp::min(start, len)..std::cmp::min(end, len)
+    }
+
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        let (low, high, prefix) = match *self {
+            PartialRange::First(low, high) => (low, high, ""),
+            PartialRange::Last(low, high) => (low, high, "-"),
+        };
+        buf.extend_from_slice(format!("{prefix}{low}:{prefix}{high}").as_bytes());
+    }
+}
+
+impl ContextUpdate {
+    pub fn serialize(&self, tag: &str) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(64);
+        buf.extend_from_slice(b"* ESEARCH (TAG ");
+        quoted_string(&mut buf, tag);
+        buf.extend_from_slice(b")");
+        if self.is_uid {
+            buf.extend_from_slice(b" UID");
+        }
+        for (name, items) in [
+            (&b" REMOVETO ("[..], &self.remove_from),
+            (&b" ADDTO ("[..], &self.add_to),
+        ] {
+            if !items.is_empty() {
+                buf.extend_from_slice(name);
+                for (pos, (position, id)) in items.iter().enumerate() {
+                    if pos > 0 {
+                        buf.push(b' ');
+                    }
+                    buf.extend_from_slice(position.to_string().as_bytes());
+                    buf.push(b' ');
+                    buf.extend_from_slice(id.to_string().as_bytes());
+                }
+                buf.push(b')');
+            }
+        }
+        buf.extend_from_slice(b"\r\n");
+        buf
+    }
+}
+
 impl Response {
     pub fn serialize(self, tag: &str) -> Vec<u8> {
         let mut buf = Vec::with_capacity(64);
@@ -169,7 +246,17 @@ impl Response {
                 buf.extend_from_slice(b" MAX ");
                 buf.extend_from_slice(max.to_string().as_bytes());
             }
-            if !self.ids.is_empty() {
+            if let Some(partial) = &self.partial {
+                buf.extend_from_slice(b" PARTIAL (");
+                partial.serialize(&mut buf);
+                if !self.ids.is_empty() {
+                    buf.push(b' ');
+                    serialize_sequence(&mut buf, &self.ids);
+                } else {
+                    buf.extend_from_slice(b" NIL");
+                }
+                buf.push(b')');
+            } else if !self.ids.is_empty() {
                 buf.extend_from_slice(b" ALL ");
                 serialize_sequence(&mut buf, &self.ids);
             }
@@ -215,6 +302,7 @@ mod tests {
                     min: 2.into(),
                     max: 11.into(),
                     count: 3.into(),
+                    partial: None,
                     highest_modseq: None,
                 },
                 "A283",
@@ -232,6 +320,7 @@ mod tests {
                     min: None,
                     max: None,
                     count: None,
+                    partial: None,
                     highest_modseq: None,
                 },
                 "A283",
@@ -247,6 +336,7 @@ mod tests {
                     min: None,
                     max: None,
                     count: None,
+                    partial: None,
                     highest_modseq: None,
                 },
                 "A283",
@@ -262,6 +352,7 @@ mod tests {
                     min: None,
                     max: None,
                     count: None,
+                    partial: None,
                     highest_modseq: 12345.into(),
                 },
                 "A283",
@@ -277,4 +368,65 @@ mod tests {
             assert_eq!(response_v1, expected_v1);
         }
     }
+
+    #[test]
+    fn serialize_partial() {
+        for (partial, ids, expected) in [
+            (
+                super::PartialRange::First(1, 5),
+                vec![4, 5, 6, 9, 10],
+                "* ESEARCH (TAG \"A1\") UID COUNT 42 PARTIAL (1:5 4:6,9:10)\r\n",
+            ),
+            (
+                super::PartialRange::Last(1, 100),
+                vec![],
+                "* ESEARCH (TAG \"A1\") UID COUNT 42 PARTIAL (-1:-100 NIL)\r\n",
+            ),
+        ] {
+            assert_eq!(
+                String::from_utf8(
+                    super::Response {
+                        is_uid: true,
+                        is_esearch: true,
+                        is_sort: false,
+                        ids,
+                        min: None,
+                        max: None,
+                        count: 42.into(),
+                        partial: partial.into(),
+                        highest_modseq: None,
+                    }
+                    .serialize("A1")
+                )
+                .unwrap(),
+                expected
+            );
+        }
+
+        for (partial, len, expected) in [
+            (super::PartialRange::First(1, 3), 10, 0..3),
+            (super::PartialRange::First(8, 20), 10, 7..10),
+            (super::PartialRange::First(11, 20), 10, 10..10),
+            (super::PartialRange::Last(1, 3), 10, 7..10),
+            (super::PartialRange::Last(5, 20), 10, 0..6),
+        ] {
+            assert_eq!(partial.range(len), expected, "{partial:?}");
+        }
+    }
+
+    #[test]
+    fn serialize_context_update() {
+        assert_eq!(
+            String::from_utf8(
+                super::ContextUpdate {
+                    is_uid: true,
+                    add_to: vec![(1, 2733), (3, 2740)],
+                    remove_from: vec![(0, 2731)],
+                }
+                .serialize("B01")
+            )
+            .unwrap(),
+            "* ESEARCH (TAG \"B01\") UID REMOVETO (0 2731) ADDTO (1 2733 3 2740)\r\n"
+        );
+    }
 }
@@ -43,6 +43,7 @@ pub enum Status {
     Recent,
     HighestModSeq,
     MailboxId,
+    AppendLimit,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -81,6 +82,7 @@ impl StatusItem {
                 Status::HighestModSeq => b"HIGHESTMODSEQ ",
                 Status::MailboxId => b"MAILBOXID ",
                 Status::Recent => b"RECENT ",
+                Status::AppendLimit => b"APPENDLIMIT ",
             });
 
             match value {
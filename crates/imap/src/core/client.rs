@@ -99,6 +99,9 @@ impl<T: AsyncRead> Session<T> {
                 Command::Append => {
                     self.handle_append(request).await?;
                 }
+                Command::Replace(is_uid) => {
+                    self.handle_replace(request, is_uid).await?;
+                }
                 Command::Close => {
                     self.handle_close(request).await?;
                 }
@@ -193,6 +196,9 @@ impl<T: AsyncRead> Session<T> {
                 Command::Id => {
                     self.handle_id(request).await?;
                 }
+                Command::CancelUpdate => {
+                    self.handle_cancel_update(request).await?;
+                }
             }
         }
 
@@ -305,12 +311,17 @@ impl<T: AsyncRead> Session<T> {
             | Command::Move(_)
             | Command::Check
             | Command::Sort(_)
-            | Command::Thread(_) => match state {
+            | Command::Thread(_)
+            | Command::CancelUpdate
+            | Command::Replace(_) => match state {
                 State::Selected { mailbox, .. } => {
                     if mailbox.is_select
                         || !matches!(
                             request.command,
-                            Command::Store(_) | Command::Expunge(_) | Command::Move(_),
+                            Command::Store(_)
+                                | Command::Expunge(_)
+                                | Command::Move(_)
+                                | Command::Replace(_),
                         )
                     {
                         Ok(request)
@@ -28,6 +28,7 @@ use imap_proto::{
     protocol::{expunge, select::Exists, Sequence},
     StatusResponse,
 };
+use jmap::{email::added_dates::AddedDates, JMAP};
 use jmap_proto::types::{collection::Collection, property::Property};
 use store::{
     roaring::RoaringBitmap,
@@ -42,6 +43,11 @@ use super::{MailboxId, MailboxState, NextMailboxState, SelectedMailbox, SessionD
 
 const MAX_RETRIES: usize = 10;
 
+// Versioned uid maps start with a non-minimal LEB128 zero, which is never
+// written as the item count of maps that predate versioning.
+const UID_MAP_MARKER: [u8; 2] = [0x80, 0x00];
+const UID_MAP_VERSION: u8 = 1;
+
 #[derive(Debug)]
 struct UidMap {
     uid_next: u32,
@@ -55,6 +61,7 @@ struct Uid {
     uid: u32,
     id: u32,
     received: u32,
+    saved: u32,
 }
 
 struct UidMapBuilder {
@@ -63,17 +70,13 @@ struct UidMapBuilder {
     id_list: Vec<(u32, u32)>,
 }
 
-impl SessionData {
-    pub async fn fetch_messages(&self, mailbox: &MailboxId) -> crate::op::Result<MailboxState> {
+impl MailboxState {
+    pub async fn fetch(jmap: &JMAP, mailbox: &MailboxId) -> crate::op::Result<Self> {
         let mut try_count = 0;
 
         loop {
-            // Acquire lock on the mailbox
-            let _guard = self.mailbox_locks.lock_hash(mailbox).await;
-
             // Deserialize mailbox data
-            let uid_map = self
-                .jmap
+            let uid_map = jmap
                 .get_property::<HashedValue<UidMap>>(
                     mailbox.account_id,
                     Collection::Mailbox,
@@ -83,8 +86,7 @@ impl SessionData {
                 .await?;
 
             // Obtain current state
-            let modseq = self
-                .jmap
+            let modseq = jmap
                 .store
                 .get_last_change_id(mailbox.account_id, Collection::Email)
                 .await
@@ -100,26 +102,23 @@ impl SessionData {
 
             // Obtain message ids
             let message_ids = if let Some(mailbox_id) = mailbox.mailbox_id {
-                self.jmap
-                    .get_tag(
-                        mailbox.account_id,
-                        Collection::Email,
-                        Property::MailboxIds,
-                        mailbox_id,
-                    )
-                    .await?
-                    .unwrap_or_default()
+                jmap.get_tag(
+                    mailbox.account_id,
+                    Collection::Email,
+                    Property::MailboxIds,
+                    mailbox_id,
+                )
+                .await?
+                .unwrap_or_default()
             } else {
-                self.jmap
-                    .get_document_ids(mailbox.account_id, Collection::Email)
+                jmap.get_document_ids(mailbox.account_id, Collection::Email)
                     .await?
                     .unwrap_or_default()
             };
 
             // Obtain message data
             let (id_list, id_list_hash) = if !message_ids.is_empty() {
-                let uid_builder = self
-                    .jmap
+                let uid_builder = jmap
                     .store
                     .index_values(
                         UidMapBuilder {
@@ -176,11 +175,21 @@ impl SessionData {
                         }
                     }
 
-                    for (id, received) in id_list_map {
+                    let new_ids = id_list_map.into_iter().collect::<Vec<_>>();
+                    let added_dates = jmap
+                        .get_properties::<AddedDates>(
+                            mailbox.account_id,
+                            Collection::Email,
+                            new_ids.iter().map(|(id, _)| *id),
+                            Property::AddedDates,
+                        )
+                        .await?;
+                    for ((id, received), added_dates) in new_ids.into_iter().zip(added_dates) {
                         items.push(Uid {
                             uid: uid_map.inner.uid_next,
                             id,
                             received,
+                            saved: mailbox.saved_date(added_dates, received),
                         });
 
                         uid_map.inner.uid_next += 1;
@@ -198,7 +207,7 @@ impl SessionData {
                         .assert_value(Property::EmailIds, &uid_map)
                         .value(Property::EmailIds, &uid_map.inner, F_VALUE);
 
-                    match self.jmap.store.write(batch.build()).await {
+                    match jmap.store.write(batch.build()).await {
                         Ok(_) => (),
                         Err(store::Error::AssertValueFailed) if try_count < MAX_RETRIES => {
                             try_count += 1;
@@ -219,6 +228,7 @@ impl SessionData {
                 let uid_map = uid_map.inner;
                 let mut id_to_imap = AHashMap::with_capacity(uid_map.items.len());
                 let mut uid_to_id = AHashMap::with_capacity(uid_map.items.len());
+                let mut saved_dates = AHashMap::with_capacity(uid_map.items.len());
                 let mut uid_max = 0;
 
                 for (seqnum, item) in uid_map.items.into_iter().enumerate() {
@@ -230,6 +240,7 @@ impl SessionData {
                         },
                     );
                     uid_to_id.insert(item.uid, item.id);
+                    saved_dates.insert(item.id, item.saved);
                     uid_max = item.uid;
                 }
 
@@ -239,6 +250,7 @@ impl SessionData {
                     total_messages: id_to_imap.len(),
                     id_to_imap,
                     uid_to_id,
+                    saved_dates,
                     uid_max,
                     modseq,
                     next_state: None,
@@ -248,6 +260,7 @@ impl SessionData {
                 let uid_validity = now() as u32 ^ mailbox.mailbox_id.unwrap_or(0);
                 let mut id_to_imap = AHashMap::with_capacity(id_list.len());
                 let mut uid_to_id = AHashMap::with_capacity(id_list.len());
+                let mut saved_dates = AHashMap::with_capacity(id_list.len());
                 let mut uids = Vec::with_capacity(id_list.len());
                 let mut uid_map = UidMap {
                     uid_next,
@@ -256,12 +269,29 @@ impl SessionData {
                     items: Vec::with_capacity(id_list.len()),
                 };
 
-                for (uid, (id, received)) in id_list.into_iter().enumerate() {
+                let added_dates = jmap
+                    .get_properties::<AddedDates>(
+                        mailbox.account_id,
+                        Collection::Email,
+                        id_list.iter().map(|(id, _)| *id),
+                        Property::AddedDates,
+                    )
+                    .await?;
+                for (uid, ((id, received), added_dates)) in
+                    id_list.into_iter().zip(added_dates).enumerate()
+                {
                     let uid = (uid + 1) as u32;
+                    let saved = mailbox.saved_date(added_dates, received);
                     id_to_imap.insert(id, ImapId { uid, seqnum: uid });
                     uid_to_id.insert(uid, id);
+                    saved_dates.insert(id, saved);
                     uids.push(uid);
-                    uid_map.items.push(Uid { uid, id, received });
+                    uid_map.items.push(Uid {
+                        uid,
+                        id,
+                        received,
+                        saved,
+                    });
                 }
 
                 // Store uid map
@@ -273,7 +303,7 @@ impl SessionData {
                     .assert_value(Property::EmailIds, ())
                     .value(Property::EmailIds, &uid_map, F_VALUE);
 
-                match self.jmap.store.write(batch.build()).await {
+                match jmap.store.write(batch.build()).await {
                     Ok(_) => (),
                     Err(store::Error::AssertValueFailed) if try_count < MAX_RETRIES => {
                         try_count += 1;
@@ -296,6 +326,7 @@ impl SessionData {
                     total_messages: uids.len(),
                     id_to_imap,
                     uid_to_id,
+                    saved_dates,
                     uid_max: uid_next.saturating_sub(1),
                     modseq,
                     next_state: None,
@@ -303,6 +334,27 @@ impl SessionData {
             }
         }
     }
+}
+
+impl MailboxId {
+    // Messages stored before mailbox dates were tracked use their received date
+    fn saved_date(&self, added_dates: Option<AddedDates>, received: u32) -> u32 {
+        added_dates
+            .and_then(|dates| match self.mailbox_id {
+                Some(mailbox_id) => dates.get(mailbox_id),
+                None => dates.earliest(),
+            })
+            .map_or(received, |saved| saved as u32)
+    }
+}
+
+impl SessionData {
+    pub async fn fetch_messages(&self, mailbox: &MailboxId) -> crate::op::Result<MailboxState> {
+        // Acquire lock on the mailbox
+        let _guard = self.mailbox_locks.lock_hash(mailbox).await;
+
+        MailboxState::fetch(&self.jmap, mailbox).await
+    }
 
     pub async fn synchronize_messages(
         &self,
@@ -487,7 +539,9 @@ impl SelectedMailbox {
 
 impl Serialize for &UidMap {
     fn serialize(self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity((self.items.len() + 2) * std::mem::size_of::<u64>());
+        let mut buf = Vec::with_capacity((self.items.len() + 3) * std::mem::size_of::<u64>());
+        buf.extend_from_slice(&UID_MAP_MARKER);
+        buf.push(UID_MAP_VERSION);
         buf.push_leb128(self.items.len());
         buf.push_leb128(self.uid_next);
         buf.extend_from_slice(self.uid_validity.to_le_bytes().as_ref());
@@ -502,6 +556,7 @@ impl Serialize for &UidMap {
 
             buf.push_leb128(item.id + 1);
             buf.extend_from_slice(item.received.to_le_bytes().as_ref());
+            buf.extend_from_slice(item.saved.to_le_bytes().as_ref());
             last_uid = item.uid;
         }
 
@@ -510,7 +565,7 @@ impl Serialize for &UidMap {
 }
 
 impl UidMap {
-    fn deserialize_(bytes: &[u8]) -> Option<Self> {
+    fn deserialize_(bytes: &[u8], has_saved: bool) -> Option<Self> {
         let mut buf_u32 = [0u8; std::mem::size_of::<u32>()];
         let mut buf_u64 = [0u8; std::mem::size_of::<u64>()];
 
@@ -539,21 +594,45 @@ impl UidMap {
             buf_u32
                 .iter_mut()
                 .try_for_each(|b| bytes.next().map(|v| *b = *v))?;
+            let received = u32::from_le_bytes(buf_u32);
+            let saved = if has_saved {
+                buf_u32
+                    .iter_mut()
+                    .try_for_each(|b| bytes.next().map(|v| *b = *v))?;
+                u32::from_le_bytes(buf_u32)
+            } else {
+                received
+            };
             uid_map.items.push(Uid {
                 uid: next_uid,
                 id: id - 1,
-                received: u32::from_le_bytes(buf_u32),
+                received,
+                saved,
             });
             next_uid += 1;
         }
 
-        uid_map.into()
+        if bytes.next().is_none() {
+            uid_map.into()
+        } else {
+            None
+        }
     }
 }
 
 impl Deserialize for UidMap {
     fn deserialize(bytes: &[u8]) -> store::Result<Self> {
-        Self::deserialize_(bytes).ok_or(store::Error::InternalError(
+        let uid_map = if let Some(bytes) = bytes.strip_prefix(UID_MAP_MARKER.as_slice()) {
+            match bytes.split_first() {
+                Some((&UID_MAP_VERSION, bytes)) => Self::deserialize_(bytes, true),
+                _ => None,
+            }
+        } else {
+            // Uid maps written before versioning lack the saved field
+            Self::deserialize_(bytes, false)
+        };
+
+        uid_map.ok_or(store::Error::InternalError(
             "Failed to deserialize uid map".to_string(),
         ))
     }
@@ -30,7 +30,7 @@ use std::{
 use ahash::AHashMap;
 use dashmap::DashMap;
 use imap_proto::{
-    protocol::{list::Attribute, ProtocolVersion},
+    protocol::{list::Attribute, search, ProtocolVersion},
     receiver::Receiver,
     Command, ResponseCode, StatusResponse,
 };
@@ -146,6 +146,7 @@ pub struct SelectedMailbox {
     pub id: MailboxId,
     pub state: parking_lot::Mutex<MailboxState>,
     pub saved_search: parking_lot::Mutex<SavedSearch>,
+    pub contexts: parking_lot::Mutex<Vec<SearchContext>>,
     pub is_select: bool,
     pub is_condstore: bool,
 }
@@ -163,6 +164,7 @@ pub struct MailboxState {
     pub uid_max: u32,
     pub id_to_imap: AHashMap<u32, ImapId>,
     pub uid_to_id: AHashMap<u32, u32>,
+    pub saved_dates: AHashMap<u32, u32>,
     pub total_messages: usize,
     pub modseq: Option<u64>,
     pub next_state: Option<Box<NextMailboxState>>,
@@ -191,6 +193,16 @@ pub enum SavedSearch {
     None,
 }
 
+// RFC 5267 - Searches with the UPDATE result option, the last known
+// results are kept as UIDs so that changes can be reported as ADDTO/REMOVETO.
+#[derive(Debug)]
+pub struct SearchContext {
+    pub tag: String,
+    pub is_uid: bool,
+    pub arguments: search::Arguments,
+    pub uids: Vec<u32>,
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub struct ImapId {
     pub uid: u32,
@@ -229,6 +229,7 @@ impl<T: AsyncRead> Session<T> {
         match request.parse_acl() {
             Ok(arguments) => {
                 let data = self.state.session_data();
+                let remote_addr = self.remote_addr.clone();
 
                 tokio::spawn(async move {
                     // Validate mailbox
@@ -363,6 +364,7 @@ impl<T: AsyncRead> Session<T> {
                     }
 
                     // Write changes
+                    let acl = changes.get(&Property::Acl).clone();
                     let mailbox_id = mailbox.mailbox_id.unwrap();
                     let mut batch = BatchBuilder::new();
                     batch
@@ -387,6 +389,18 @@ impl<T: AsyncRead> Session<T> {
                                                     .with_change(TypeState::Mailbox, change_id),
                                             )
                                             .await;
+                                        if let Ok(access_token) = data.get_access_token().await {
+                                            data.jmap
+                                                .audit_acl_change(
+                                                    &access_token,
+                                                    &remote_addr,
+                                                    "imap",
+                                                    mailbox.account_id,
+                                                    &arguments.mailbox_name,
+                                                    &acl,
+                                                )
+                                                .await;
+                                        }
                                     }
                                     Err(_) => {
                                         data.write_bytes(
@@ -24,7 +24,9 @@
 use std::sync::Arc;
 
 use imap_proto::{
-    protocol::append::Arguments, receiver::Request, Command, ResponseCode, StatusResponse,
+    protocol::{append::Arguments, replace},
+    receiver::Request,
+    Command, ResponseCode, ResponseType, StatusResponse,
 };
 
 use jmap::email::ingest::IngestEmail;
@@ -34,6 +36,8 @@ use tokio::io::AsyncRead;
 
 use crate::core::{MailboxId, SelectedMailbox, Session, SessionData};
 
+use super::expunge::expunge_messages;
+
 impl<T: AsyncRead> Session<T> {
     pub async fn handle_append(&mut self, request: Request<Command>) -> crate::OpResult {
         match request.parse_append() {
@@ -48,32 +52,14 @@ impl<T: AsyncRead> Session<T> {
                 }
 
                 // Obtain mailbox
-                let mailbox =
-                    if let Some(mailbox) = data.get_mailbox_by_name(&arguments.mailbox_name) {
-                        if mailbox.mailbox_id.is_some() {
-                            mailbox
-                        } else {
-                            return self
-                                .write_bytes(
-                                    StatusResponse::no(
-                                        "Appending messages to this mailbox is not allowed.",
-                                    )
-                                    .with_tag(arguments.tag)
-                                    .with_code(ResponseCode::Cannot)
-                                    .into_bytes(),
-                                )
-                                .await;
-                        }
-                    } else {
+                let mailbox = match data.get_append_mailbox(&arguments.mailbox_name) {
+                    Ok(mailbox) => mailbox,
+                    Err(response) => {
                         return self
-                            .write_bytes(
-                                StatusResponse::no("Mailbox does not exist.")
-                                    .with_tag(arguments.tag)
-                                    .with_code(ResponseCode::TryCreate)
-                                    .into_bytes(),
-                            )
+                            .write_bytes(response.with_tag(arguments.tag).into_bytes())
                             .await;
-                    };
+                    }
+                };
                 let is_qresync = self.is_qresync;
 
                 tokio::spawn(async move {
@@ -94,9 +80,156 @@ impl<T: AsyncRead> Session<T> {
             Err(response) => self.write_bytes(response.into_bytes()).await,
         }
     }
+
+    pub async fn handle_replace(
+        &mut self,
+        request: Request<Command>,
+        is_uid: bool,
+    ) -> crate::OpResult {
+        match request.parse_replace() {
+            Ok(arguments) => {
+                let (data, selected_mailbox) = self.state.select_data();
+
+                // Refresh mailboxes
+                if let Err(err) = data.synchronize_mailboxes(false).await {
+                    return self
+                        .write_bytes(err.with_tag(arguments.tag).into_bytes())
+                        .await;
+                }
+
+                // Obtain mailbox
+                let mailbox = match data.get_append_mailbox(&arguments.mailbox_name) {
+                    Ok(mailbox) => mailbox,
+                    Err(response) => {
+                        return self
+                            .write_bytes(response.with_tag(arguments.tag).into_bytes())
+                            .await;
+                    }
+                };
+                let is_qresync = self.is_qresync;
+
+                tokio::spawn(async move {
+                    data.write_bytes(
+                        match data
+                            .replace_message(
+                                arguments,
+                                selected_mailbox,
+                                mailbox,
+                                is_uid,
+                                is_qresync,
+                            )
+                            .await
+                        {
+                            Ok(response) => response,
+                            Err(response) => response,
+                        }
+                        .into_bytes(),
+                    )
+                    .await;
+                });
+                Ok(())
+            }
+            Err(response) => self.write_bytes(response.into_bytes()).await,
+        }
+    }
 }
 
 impl SessionData {
+    fn get_append_mailbox(&self, mailbox_name: &str) -> crate::op::Result<MailboxId> {
+        if let Some(mailbox) = self.get_mailbox_by_name(mailbox_name) {
+            if mailbox.mailbox_id.is_some() {
+                Ok(mailbox)
+            } else {
+                Err(
+                    StatusResponse::no("Appending messages to this mailbox is not allowed.")
+                        .with_code(ResponseCode::Cannot),
+                )
+            }
+        } else {
+            Err(StatusResponse::no("Mailbox does not exist.").with_code(ResponseCode::TryCreate))
+        }
+    }
+
+    async fn replace_message(
+        &self,
+        arguments: replace::Arguments,
+        selected_mailbox: Arc<SelectedMailbox>,
+        mailbox: MailboxId,
+        is_uid: bool,
+        is_qresync: bool,
+    ) -> crate::op::Result<StatusResponse> {
+        // Obtain the message to replace
+        let ids = selected_mailbox
+            .sequence_to_ids(&arguments.sequence_set, is_uid)
+            .await
+            .map_err(|r| r.with_tag(&arguments.tag))?;
+        let id = match ids.len() {
+            1 => *ids.keys().next().unwrap(),
+            0 => {
+                return Ok(StatusResponse::no("Message not found.")
+                    .with_tag(arguments.tag)
+                    .with_code(ResponseCode::NonExistent));
+            }
+            _ => {
+                return Ok(StatusResponse::bad("Only one message can be replaced.")
+                    .with_tag(arguments.tag));
+            }
+        };
+
+        // Verify ACLs on the source mailbox
+        let account_id = selected_mailbox.id.account_id;
+        if !self
+            .check_mailbox_acl(
+                account_id,
+                selected_mailbox.id.mailbox_id.unwrap_or_default(),
+                Acl::RemoveItems,
+            )
+            .await
+            .map_err(|r| r.with_tag(&arguments.tag))?
+        {
+            return Ok(StatusResponse::no(
+                "You do not have the required permissions to remove messages from this mailbox.",
+            )
+            .with_tag(arguments.tag)
+            .with_code(ResponseCode::NoPerm));
+        }
+
+        // Append the replacement message
+        let tag = arguments.tag;
+        let response = self
+            .append_messages(
+                Arguments {
+                    tag: tag.clone(),
+                    mailbox_name: arguments.mailbox_name,
+                    messages: vec![arguments.message],
+                },
+                selected_mailbox.clone().into(),
+                mailbox,
+                is_qresync,
+            )
+            .await?;
+        if response.rtype != ResponseType::Ok {
+            return Ok(response);
+        } else if let Some(code) = response.code {
+            self.write_bytes(
+                StatusResponse::ok("Replacement message ready")
+                    .with_code(code)
+                    .into_bytes(),
+            )
+            .await;
+        }
+
+        // Expunge the original message
+        expunge_messages(&self.jmap, account_id, selected_mailbox.id.mailbox_id, [id])
+            .await
+            .map_err(|r| r.with_tag(&tag))?;
+        self.write_mailbox_changes(&selected_mailbox, is_qresync)
+            .await
+            .map_err(|r| r.with_tag(&tag))?;
+
+        Ok(StatusResponse::completed(Command::Replace(is_uid)).with_tag(tag))
+    }
+
     async fn append_messages(
         &self,
         arguments: Arguments,
@@ -131,6 +264,15 @@ impl SessionData {
         let mut created_ids = Vec::with_capacity(arguments.messages.len());
         let mut last_change_id = None;
         for message in arguments.messages {
+            if message.message.len() > self.jmap.config.mail_max_size {
+                response = StatusResponse::no(format!(
+                    "Message exceeds maximum size of {} bytes.",
+                    self.jmap.config.mail_max_size
+                ))
+                .with_code(ResponseCode::TooBig);
+                break;
+            }
+
             match self
                 .jmap
                 .email_ingest(IngestEmail {
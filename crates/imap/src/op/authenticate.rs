@@ -24,7 +24,7 @@
 use std::sync::Arc;
 
 use imap_proto::{
-    protocol::{authenticate::Mechanism, capability::Capability},
+    protocol::authenticate::Mechanism,
     receiver::{self, Request},
     Command, ResponseCode, StatusResponse,
 };
@@ -158,7 +158,7 @@ impl<T: AsyncRead> Session<T> {
                 self.write_bytes(
                     StatusResponse::ok("Authentication successful")
                         .with_code(ResponseCode::Capability {
-                            capabilities: Capability::all_capabilities(true, self.is_tls),
+                            capabilities: self.capabilities(),
                         })
                         .with_tag(tag)
                         .into_bytes(),
@@ -41,10 +41,7 @@ impl<T: AsyncRead> Session<T> {
                 .with_tag(request.tag)
                 .serialize(
                     Response {
-                        capabilities: Capability::all_capabilities(
-                            self.state.is_authenticated(),
-                            self.is_tls,
-                        ),
+                        capabilities: self.capabilities(),
                     }
                     .serialize(),
                 ),
@@ -52,6 +49,15 @@ impl<T: AsyncRead> Session<T> {
         .await
     }
 
+    pub fn capabilities(&self) -> Vec<Capability> {
+        let is_authenticated = self.state.is_authenticated();
+        let mut capabilities = Capability::all_capabilities(is_authenticated, self.is_tls);
+        if is_authenticated {
+            capabilities.push(Capability::AppendLimit(self.jmap.config.mail_max_size));
+        }
+        capabilities
+    }
+
     pub async fn handle_id(&mut self, request: Request<Command>) -> crate::OpResult {
         self.write_bytes(
             StatusResponse::completed(Command::Id)
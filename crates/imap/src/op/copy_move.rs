@@ -28,7 +28,7 @@ use imap_proto::{
     StatusResponse,
 };
 
-use jmap::email::set::TagManager;
+use jmap::{email::set::TagManager, mailbox::TRASH_ID, JMAP};
 use jmap_proto::{
     error::{method::MethodError, set::SetErrorType},
     types::{
@@ -213,10 +213,10 @@ impl SessionData {
             let account_id = src_mailbox.id.account_id;
             for (id, imap_id) in ids {
                 // Obtain mailbox tags
-                let (mut mailboxes, thread_id) = if let Some(result) = self
-                    .get_mailbox_tags(account_id, id)
-                    .await
-                    .map_err(|_| StatusResponse::database_failure().with_tag(&arguments.tag))?
+                let (mut mailboxes, thread_id) = if let Some(result) =
+                    get_mailbox_tags(&self.jmap, account_id, id)
+                        .await
+                        .map_err(|_| StatusResponse::database_failure().with_tag(&arguments.tag))?
                 {
                     result
                 } else {
@@ -249,6 +249,10 @@ impl SessionData {
                     .with_account_id(account_id)
                     .with_collection(Collection::Email)
                     .update_document(id);
+                self.jmap
+                    .update_added_dates(&mut batch, account_id, id, &mailboxes)
+                    .await
+                    .map_err(|_| StatusResponse::database_failure().with_tag(&arguments.tag))?;
                 mailboxes.update_batch(&mut batch, Property::MailboxIds);
                 if changelog.change_id == u64::MAX {
                     changelog.change_id =
@@ -328,11 +332,12 @@ impl SessionData {
 
                 if is_move {
                     // Obtain mailbox tags
-                    let (mut mailboxes, thread_id) = if let Some(result) = self
-                        .get_mailbox_tags(src_account_id, id)
-                        .await
-                        .map_err(|_| StatusResponse::database_failure().with_tag(&arguments.tag))?
-                    {
+                    let (mut mailboxes, thread_id) = if let Some(result) =
+                        get_mailbox_tags(&self.jmap, src_account_id, id)
+                            .await
+                            .map_err(|_| {
+                                StatusResponse::database_failure().with_tag(&arguments.tag)
+                            })? {
                         result
                     } else {
                         continue;
@@ -443,6 +448,30 @@ impl SessionData {
             .with_tag(arguments.tag));
         }
 
+        // Moving messages in or out of Junk trains the spam filter
+        if Some(dest_mailbox_id) == self.jmap.mailbox_junk_id(dest_mailbox.account_id).await {
+            self.jmap
+                .spam_train(
+                    dest_mailbox.account_id,
+                    copied_ids.iter().map(|(_, id)| *id).collect(),
+                    true,
+                )
+                .await;
+        } else if is_move
+            && dest_mailbox_id != TRASH_ID
+            && src_mailbox.id.mailbox_id.is_some()
+            && src_mailbox.id.mailbox_id
+                == self.jmap.mailbox_junk_id(src_mailbox.id.account_id).await
+        {
+            self.jmap
+                .spam_train(
+                    dest_mailbox.account_id,
+                    copied_ids.iter().map(|(_, id)| *id).collect(),
+                    false,
+                )
+                .await;
+        }
+
         let dest_mailbox = self
             .fetch_messages(&dest_mailbox)
             .await
@@ -487,34 +516,32 @@ impl SessionData {
 
         Ok(())
     }
+}
 
-    pub async fn get_mailbox_tags(
-        &self,
-        account_id: u32,
-        id: u32,
-    ) -> Result<Option<(TagManager<u32>, u32)>, MethodError> {
-        // Obtain mailbox tags
-        if let (Some(mailboxes), Some(thread_id)) = (
-            self.jmap
-                .get_property::<HashedValue<Vec<u32>>>(
-                    account_id,
-                    Collection::Email,
-                    id,
-                    Property::MailboxIds,
-                )
-                .await?,
-            self.jmap
-                .get_property::<u32>(account_id, Collection::Email, id, Property::ThreadId)
-                .await?,
-        ) {
-            Ok(Some((TagManager::new(mailboxes), thread_id)))
-        } else {
-            tracing::debug!(
-                account_id = account_id,
-                document_id = id,
-                "Message not found"
-            );
-            Ok(None)
-        }
+pub async fn get_mailbox_tags(
+    jmap: &JMAP,
+    account_id: u32,
+    id: u32,
+) -> Result<Option<(TagManager<u32>, u32)>, MethodError> {
+    // Obtain mailbox tags
+    if let (Some(mailboxes), Some(thread_id)) = (
+        jmap.get_property::<HashedValue<Vec<u32>>>(
+            account_id,
+            Collection::Email,
+            id,
+            Property::MailboxIds,
+        )
+        .await?,
+        jmap.get_property::<u32>(account_id, Collection::Email, id, Property::ThreadId)
+            .await?,
+    ) {
+        Ok(Some((TagManager::new(mailboxes), thread_id)))
+    } else {
+        tracing::debug!(
+            account_id = account_id,
+            document_id = id,
+            "Message not found"
+        );
+        Ok(None)
     }
 }
@@ -30,7 +30,7 @@ use imap_proto::{
     Command, ResponseCode, StatusResponse,
 };
 
-use jmap::email::set::TagManager;
+use jmap::{email::set::TagManager, JMAP};
 use jmap_proto::{
     error::method::MethodError,
     types::{
@@ -43,6 +43,8 @@ use tokio::io::AsyncRead;
 
 use crate::core::{ImapId, SavedSearch, SelectedMailbox, Session, SessionData};
 
+use super::copy_move::get_mailbox_tags;
+
 impl<T: AsyncRead> Session<T> {
     pub async fn handle_expunge(
         &mut self,
@@ -164,96 +166,104 @@ impl SessionData {
                 .unwrap_or_default()
         };
 
-        // Delete ids
-        let mut changelog = ChangeLogBuilder::new();
-        for id in deleted_ids {
-            if sequence
-                .as_ref()
-                .map_or(false, |ids| !ids.contains_key(&id))
-            {
-                continue;
-            }
+        expunge_messages(
+            &self.jmap,
+            account_id,
+            mailbox.id.mailbox_id,
+            deleted_ids
+                .into_iter()
+                .filter(|id| sequence.as_ref().map_or(true, |ids| ids.contains_key(id))),
+        )
+        .await
+    }
+}
 
-            if let Some(mailbox_id) = mailbox.id.mailbox_id {
-                // If the message is present in multiple mailboxes, untag it from this mailbox.
-                let (mut mailboxes, thread_id) =
-                    if let Some(result) = self.get_mailbox_tags(account_id, id).await? {
-                        result
-                    } else {
-                        continue;
-                    };
-                if !mailboxes.current().contains(&mailbox_id) {
+pub async fn expunge_messages(
+    jmap: &JMAP,
+    account_id: u32,
+    mailbox_id: Option<u32>,
+    ids: impl IntoIterator<Item = u32>,
+) -> crate::op::Result<()> {
+    // Delete ids
+    let mut changelog = ChangeLogBuilder::new();
+    for id in ids {
+        if let Some(mailbox_id) = mailbox_id {
+            // If the message is present in multiple mailboxes, untag it from this mailbox.
+            let (mut mailboxes, thread_id) =
+                if let Some(result) = get_mailbox_tags(jmap, account_id, id).await? {
+                    result
+                } else {
+                    continue;
+                };
+            if !mailboxes.current().contains(&mailbox_id) {
+                continue;
+            } else if mailboxes.current().len() > 1 {
+                // Remove deleted flag
+                let mut keywords = if let Some(keywords) = jmap
+                    .get_property::<HashedValue<Vec<Keyword>>>(
+                        account_id,
+                        Collection::Email,
+                        id,
+                        Property::Keywords,
+                    )
+                    .await?
+                {
+                    TagManager::new(keywords)
+                } else {
                     continue;
-                } else if mailboxes.current().len() > 1 {
-                    // Remove deleted flag
-                    let mut keywords = if let Some(keywords) = self
-                        .jmap
-                        .get_property::<HashedValue<Vec<Keyword>>>(
-                            account_id,
-                            Collection::Email,
-                            id,
-                            Property::Keywords,
-                        )
-                        .await?
-                    {
-                        TagManager::new(keywords)
-                    } else {
-                        continue;
-                    };
+                };
 
-                    // Untag message from this mailbox and remove Deleted flag
-                    mailboxes.update(mailbox_id, false);
-                    keywords.update(Keyword::Deleted, false);
+                // Untag message from this mailbox and remove Deleted flag
+                mailboxes.update(mailbox_id, false);
+                keywords.update(Keyword::Deleted, false);
 
-                    // Write changes
-                    let mut batch = BatchBuilder::new();
-                    batch
-                        .with_account_id(account_id)
-                        .with_collection(Collection::Email)
-                        .update_document(id);
-                    mailboxes.update_batch(&mut batch, Property::MailboxIds);
-                    keywords.update_batch(&mut batch, Property::Keywords);
-                    if changelog.change_id == u64::MAX {
-                        changelog.change_id = self.jmap.assign_change_id(account_id).await?
-                    }
-                    batch.value(Property::Cid, changelog.change_id, F_VALUE);
-                    match self.jmap.write_batch(batch).await {
-                        Ok(_) => {
-                            changelog.log_update(Collection::Email, Id::from_parts(thread_id, id));
-                            changelog.log_child_update(Collection::Mailbox, mailbox_id);
-                        }
-                        Err(MethodError::ServerUnavailable) => {}
-                        Err(_) => {
-                            return Err(StatusResponse::database_failure());
-                        }
+                // Write changes
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(Collection::Email)
+                    .update_document(id);
+                mailboxes.update_batch(&mut batch, Property::MailboxIds);
+                keywords.update_batch(&mut batch, Property::Keywords);
+                if changelog.change_id == u64::MAX {
+                    changelog.change_id = jmap.assign_change_id(account_id).await?
+                }
+                batch.value(Property::Cid, changelog.change_id, F_VALUE);
+                match jmap.write_batch(batch).await {
+                    Ok(_) => {
+                        changelog.log_update(Collection::Email, Id::from_parts(thread_id, id));
+                        changelog.log_child_update(Collection::Mailbox, mailbox_id);
                     }
-                } else {
-                    // Delete message from all mailboxes
-                    if let Ok(changes) = self.jmap.email_delete(account_id, id).await? {
-                        changelog.merge(changes);
+                    Err(MethodError::ServerUnavailable) => {}
+                    Err(_) => {
+                        return Err(StatusResponse::database_failure());
                     }
                 }
             } else {
                 // Delete message from all mailboxes
-                if let Ok(changes) = self.jmap.email_delete(account_id, id).await? {
+                if let Ok(changes) = jmap.email_delete(account_id, id).await? {
                     changelog.merge(changes);
                 }
             }
+        } else {
+            // Delete message from all mailboxes
+            if let Ok(changes) = jmap.email_delete(account_id, id).await? {
+                changelog.merge(changes);
+            }
         }
+    }
 
-        // Write changes on source account
-        if !changelog.is_empty() {
-            let change_id = self.jmap.commit_changes(account_id, changelog).await?;
-            self.jmap
-                .broadcast_state_change(
-                    StateChange::new(account_id)
-                        .with_change(TypeState::Email, change_id)
-                        .with_change(TypeState::Mailbox, change_id)
-                        .with_change(TypeState::Thread, change_id),
-                )
-                .await;
-        }
-
-        Ok(())
+    // Write changes on source account
+    if !changelog.is_empty() {
+        let change_id = jmap.commit_changes(account_id, changelog).await?;
+        jmap.broadcast_state_change(
+            StateChange::new(account_id)
+                .with_change(TypeState::Email, change_id)
+                .with_change(TypeState::Mailbox, change_id)
+                .with_change(TypeState::Thread, change_id),
+        )
+        .await;
     }
+
+    Ok(())
 }
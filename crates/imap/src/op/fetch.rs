@@ -111,6 +111,12 @@ impl SessionData {
             }
         }
 
+        // Validate PARTIAL parameter
+        if arguments.partial.is_some() && !is_uid {
+            return StatusResponse::bad("PARTIAL parameter is only available for UID FETCH.")
+                .with_tag(arguments.tag);
+        }
+
         // Resync messages if needed
         let account_id = mailbox.id.account_id;
         let mut modseq = match self.synchronize_messages(&mailbox).await {
@@ -129,6 +135,14 @@ impl SessionData {
             }
         };
 
+        // Restrict results to the requested range
+        if let Some(partial) = arguments.partial {
+            let mut sorted_ids = ids.into_iter().collect::<Vec<_>>();
+            sorted_ids.sort_unstable_by_key(|(_, imap_id)| imap_id.uid);
+            let range = partial.range(sorted_ids.len());
+            ids = sorted_ids.drain(range).collect();
+        }
+
         // Convert state to modseq
         if let Some(changed_since) = arguments.changed_since {
             // Obtain changes since the modseq.
@@ -499,6 +513,16 @@ impl SessionData {
                             thread_id: Id::from_parts(account_id, thread_id).to_string(),
                         });
                     }
+                    Attribute::SaveDate => {
+                        items.push(DataItem::SaveDate {
+                            date: mailbox
+                                .state
+                                .lock()
+                                .saved_dates
+                                .get(&id)
+                                .map(|date| *date as i64),
+                        });
+                    }
                 }
             }
 
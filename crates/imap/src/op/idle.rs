@@ -268,6 +268,7 @@ impl SessionData {
                             attributes: vec![fetch::Attribute::Flags, fetch::Attribute::Uid],
                             changed_since: None,
                             include_vanished: false,
+                            partial: None,
                         },
                         mailbox.clone(),
                         true,
@@ -276,6 +277,9 @@ impl SessionData {
                     )
                     .await;
                 }
+
+                // Send updates for searches with the UPDATE result option
+                self.write_search_updates(mailbox).await;
             }
         }
     }
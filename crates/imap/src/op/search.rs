@@ -23,13 +23,14 @@
 
 use std::sync::Arc;
 
+use ahash::{AHashMap, AHashSet};
 use imap_proto::{
     protocol::{
-        search::{self, Arguments, Filter, Response, ResultOption},
+        search::{self, Arguments, ContextUpdate, Filter, Response, ResultOption},
         Sequence,
     },
     receiver::Request,
-    Command, StatusResponse,
+    Command, ResponseCode, StatusResponse,
 };
 
 use jmap_proto::types::{collection::Collection, id::Id, keyword::Keyword, property::Property};
@@ -42,10 +43,14 @@ use store::{
 };
 use tokio::{io::AsyncRead, sync::watch};
 
-use crate::core::{ImapId, MailboxState, SavedSearch, SelectedMailbox, Session, SessionData};
+use crate::core::{
+    ImapId, MailboxState, SavedSearch, SearchContext, SelectedMailbox, Session, SessionData,
+};
 
 use super::{FromModSeq, ToModSeq};
 
+const MAX_SEARCH_CONTEXTS: usize = 10;
+
 impl<T: AsyncRead> Session<T> {
     pub async fn handle_search(
         &mut self,
@@ -58,7 +63,7 @@ impl<T: AsyncRead> Session<T> {
         } else {
             request.parse_sort()
         } {
-            Ok(mut arguments) => {
+            Ok(arguments) => {
                 let (data, mailbox) = self.state.mailbox_state();
 
                 // Create channel for results
@@ -73,7 +78,7 @@ impl<T: AsyncRead> Session<T> {
                     };
 
                 tokio::spawn(async move {
-                    let tag = std::mem::take(&mut arguments.tag);
+                    let tag = arguments.tag.clone();
                     let bytes = match data
                         .search(
                             arguments,
@@ -111,20 +116,66 @@ impl<T: AsyncRead> Session<T> {
             Err(response) => self.write_bytes(response.into_bytes()).await,
         }
     }
+
+    pub async fn handle_cancel_update(&mut self, request: Request<Command>) -> crate::OpResult {
+        let tag = request.tag.clone();
+        match request.parse_cancel_update() {
+            Ok(tags) => {
+                let (_, mailbox) = self.state.mailbox_state();
+                mailbox
+                    .contexts
+                    .lock()
+                    .retain(|context| !tags.contains(&context.tag));
+                self.write_bytes(
+                    StatusResponse::completed(Command::CancelUpdate)
+                        .with_tag(tag)
+                        .into_bytes(),
+                )
+                .await
+            }
+            Err(response) => self.write_bytes(response.into_bytes()).await,
+        }
+    }
 }
 
 impl SessionData {
     pub async fn search(
         &self,
-        arguments: Arguments,
+        mut arguments: Arguments,
         mailbox: Arc<SelectedMailbox>,
         results_tx: Option<watch::Sender<Arc<Vec<ImapId>>>>,
         prev_saved_search: Option<Option<Arc<Vec<ImapId>>>>,
         is_uid: bool,
     ) -> Result<search::Response, StatusResponse> {
+        let partial = arguments
+            .result_options
+            .iter()
+            .find_map(|option| match option {
+                ResultOption::Partial(partial) => Some(*partial),
+                _ => None,
+            });
+        let context = if arguments.result_options.contains(&ResultOption::Update) {
+            Some(arguments.clone())
+        } else {
+            None
+        };
+        let find_min = arguments.result_options.contains(&ResultOption::Min);
+        let find_max = arguments.result_options.contains(&ResultOption::Max);
+        let track_all = partial.is_some() || context.is_some();
+        let return_all = arguments.result_options.contains(&ResultOption::All)
+            || arguments
+                .result_options
+                .iter()
+                .all(|option| matches!(option, ResultOption::Context | ResultOption::Update));
+
         // Run query
-        let (result_set, include_highest_modseq) = self
-            .query(arguments.filter, &mailbox, &prev_saved_search, is_uid)
+        let (mut result_set, include_highest_modseq) = self
+            .query(
+                std::mem::take(&mut arguments.filter),
+                &mailbox,
+                &prev_saved_search,
+                is_uid,
+            )
             .await?;
 
         // Obtain modseq
@@ -142,66 +193,82 @@ impl SessionData {
         let mut max: Option<(u32, ImapId)> = None;
         let mut total = 0;
         let results_len = result_set.results.len() as usize;
-        let mut saved_results = if results_tx.is_some() {
+        let mut saved_results = if results_tx.is_some() || track_all {
             Some(Vec::with_capacity(results_len))
         } else {
             None
         };
         let mut imap_ids = Vec::with_capacity(results_len);
         let is_sort = if let Some(sort) = arguments.sort {
-            mailbox.map_search_results(
-                self.jmap
-                    .store
-                    .sort(
-                        result_set,
-                        sort.into_iter()
-                            .map(|item| match item.sort {
-                                search::Sort::Arrival => {
-                                    query::Comparator::field(Property::ReceivedAt, item.ascending)
-                                }
-                                search::Sort::Cc => {
-                                    query::Comparator::field(Property::Cc, item.ascending)
-                                }
-                                search::Sort::Date => {
-                                    query::Comparator::field(Property::SentAt, item.ascending)
-                                }
-                                search::Sort::From | search::Sort::DisplayFrom => {
-                                    query::Comparator::field(Property::From, item.ascending)
-                                }
-                                search::Sort::Size => {
-                                    query::Comparator::field(Property::Size, item.ascending)
-                                }
-                                search::Sort::Subject => {
-                                    query::Comparator::field(Property::Subject, item.ascending)
-                                }
-                                search::Sort::To | search::Sort::DisplayTo => {
-                                    query::Comparator::field(Property::To, item.ascending)
-                                }
-                            })
-                            .collect::<Vec<_>>(),
-                        Pagination::new(results_len, 0, None, 0),
-                    )
-                    .await
-                    .map_err(|_| StatusResponse::database_failure())?
-                    .ids
-                    .into_iter()
-                    .map(|id| id as u32),
-                is_uid,
-                arguments.result_options.contains(&ResultOption::Min),
-                arguments.result_options.contains(&ResultOption::Max),
-                &mut min,
-                &mut max,
-                &mut total,
-                &mut imap_ids,
-                &mut saved_results,
-            );
+            if let (Some(partial), None, None, false, false) =
+                (partial, &results_tx, &context, find_min, find_max)
+            {
+                // Only sort the requested page, positions are relative to the
+                // messages known to this session.
+                {
+                    let state = mailbox.state.lock();
+                    result_set.results = result_set
+                        .results
+                        .iter()
+                        .filter(|id| state.map_result_id(*id, is_uid).is_some())
+                        .collect();
+                }
+                total = result_set.results.len() as u32;
+                let range = partial.range(total as usize);
+                if !range.is_empty() {
+                    let sorted_ids = self
+                        .jmap
+                        .store
+                        .sort(
+                            result_set,
+                            sort_comparators(sort),
+                            Pagination::new(range.len(), range.start as i32, None, 0),
+                        )
+                        .await
+                        .map_err(|_| StatusResponse::database_failure())?
+                        .ids;
+                    let state = mailbox.state.lock();
+                    imap_ids.extend(
+                        sorted_ids
+                            .into_iter()
+                            .filter_map(|id| state.map_result_id(id as u32, is_uid))
+                            .map(|(id, _)| id),
+                    );
+                }
+            } else {
+                mailbox.map_search_results(
+                    self.jmap
+                        .store
+                        .sort(
+                            result_set,
+                            sort_comparators(sort),
+                            Pagination::new(results_len, 0, None, 0),
+                        )
+                        .await
+                        .map_err(|_| StatusResponse::database_failure())?
+                        .ids
+                        .into_iter()
+                        .map(|id| id as u32),
+                    is_uid,
+                    find_min && !track_all,
+                    find_max && !track_all,
+                    &mut min,
+                    &mut max,
+                    &mut total,
+                    &mut imap_ids,
+                    &mut saved_results,
+                );
+                if let Some(partial) = partial {
+                    imap_ids = imap_ids[partial.range(imap_ids.len())].to_vec();
+                }
+            }
             true
         } else {
             mailbox.map_search_results(
                 result_set.results.into_iter(),
                 is_uid,
-                arguments.result_options.contains(&ResultOption::Min),
-                arguments.result_options.contains(&ResultOption::Max),
+                find_min && !track_all,
+                find_max && !track_all,
                 &mut min,
                 &mut max,
                 &mut total,
@@ -209,9 +276,57 @@ impl SessionData {
                 &mut saved_results,
             );
             imap_ids.sort_unstable();
+            if let Some(partial) = partial {
+                imap_ids = imap_ids[partial.range(imap_ids.len())].to_vec();
+            }
             false
         };
 
+        // Obtain min and max from the full result list
+        if let (true, Some(saved_results)) = (track_all, &saved_results) {
+            for imap_id in saved_results {
+                let id = if is_uid { imap_id.uid } else { imap_id.seqnum };
+                if find_min && min.map_or(true, |(min, _)| id < min) {
+                    min = Some((id, *imap_id));
+                }
+                if find_max && max.map_or(true, |(max, _)| id > max) {
+                    max = Some((id, *imap_id));
+                }
+            }
+        }
+
+        // Register search context
+        if let (Some(mut context), Some(saved_results)) = (context, &saved_results) {
+            let mut uids = saved_results
+                .iter()
+                .map(|imap_id| imap_id.uid)
+                .collect::<Vec<_>>();
+            if !is_sort {
+                uids.sort_unstable();
+            }
+            context.result_options.clear();
+            let tag = std::mem::take(&mut context.tag);
+
+            let mut contexts = mailbox.contexts.lock();
+            contexts.retain(|context| context.tag != tag);
+            if contexts.len() < MAX_SEARCH_CONTEXTS {
+                contexts.push(SearchContext {
+                    tag,
+                    is_uid,
+                    arguments: context,
+                    uids,
+                });
+            } else {
+                drop(contexts);
+                self.write_bytes(
+                    StatusResponse::no("Too many active search contexts.")
+                        .with_code(ResponseCode::NoUpdate { tag })
+                        .into_bytes(),
+                )
+                .await;
+            }
+        }
+
         // Save results
         if let (Some(results_tx), Some(saved_results)) = (results_tx, saved_results) {
             let saved_results = Arc::new(saved_results);
@@ -231,19 +346,98 @@ impl SessionData {
             } else {
                 None
             },
-            ids: if arguments.result_options.is_empty()
-                || arguments.result_options.contains(&ResultOption::All)
-            {
+            ids: if return_all || partial.is_some() {
                 imap_ids
             } else {
                 vec![]
             },
+            partial,
             is_sort,
             is_esearch: arguments.is_esearch,
             highest_modseq,
         })
     }
 
+    pub async fn write_search_updates(&self, mailbox: &Arc<SelectedMailbox>) {
+        let contexts = mailbox
+            .contexts
+            .lock()
+            .iter()
+            .map(|context| {
+                (
+                    context.tag.clone(),
+                    context.is_uid,
+                    context.arguments.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for (tag, is_uid, arguments) in contexts {
+            // Obtain the current results as UIDs
+            let mut uids = match self
+                .search(arguments, mailbox.clone(), None, None, is_uid)
+                .await
+            {
+                Ok(response) => response.ids,
+                Err(response) => {
+                    self.write_bytes(response.into_bytes()).await;
+                    return;
+                }
+            };
+            if !is_uid {
+                let state = mailbox.state.lock();
+                let seq_to_uid = state
+                    .id_to_imap
+                    .values()
+                    .map(|imap_id| (imap_id.seqnum, imap_id.uid))
+                    .collect::<AHashMap<_, _>>();
+                uids = uids
+                    .into_iter()
+                    .filter_map(|seqnum| seq_to_uid.get(&seqnum).copied())
+                    .collect();
+            }
+
+            let update = {
+                let mut contexts = mailbox.contexts.lock();
+                let context =
+                    if let Some(context) = contexts.iter_mut().find(|context| context.tag == tag) {
+                        context
+                    } else {
+                        continue;
+                    };
+
+                // Expunged messages are removed by the client on EXPUNGE/VANISHED
+                let state = mailbox.state.lock();
+                let prev_uids = std::mem::replace(&mut context.uids, uids)
+                    .into_iter()
+                    .filter(|uid| state.uid_to_id.contains_key(uid))
+                    .collect::<Vec<_>>();
+                let (remove_from, add_to) = diff_results(&prev_uids, &context.uids);
+                let map_id = |(position, uid): (u32, u32)| {
+                    if is_uid {
+                        Some((position, uid))
+                    } else {
+                        state
+                            .uid_to_id
+                            .get(&uid)
+                            .and_then(|id| state.id_to_imap.get(id))
+                            .map(|imap_id| (position, imap_id.seqnum))
+                    }
+                };
+
+                ContextUpdate {
+                    is_uid,
+                    remove_from: remove_from.into_iter().filter_map(map_id).collect(),
+                    add_to: add_to.into_iter().filter_map(map_id).collect(),
+                }
+            };
+
+            if !update.remove_from.is_empty() || !update.add_to.is_empty() {
+                self.write_bytes(update.serialize(&tag)).await;
+            }
+        }
+    }
+
     pub async fn query(
         &self,
         imap_filter: Vec<Filter>,
@@ -595,6 +789,24 @@ impl SessionData {
                         now().saturating_sub(secs as u64),
                     ));
                 }
+                search::Filter::SavedBefore(date) => {
+                    filters.push(query::Filter::is_in_set(
+                        mailbox.state.lock().saved_between(i64::MIN, date),
+                    ));
+                }
+                search::Filter::SavedOn(date) => {
+                    filters.push(query::Filter::is_in_set(
+                        mailbox.state.lock().saved_between(date, date + 86400),
+                    ));
+                }
+                search::Filter::SavedSince(date) => {
+                    filters.push(query::Filter::is_in_set(
+                        mailbox.state.lock().saved_between(date, i64::MAX),
+                    ));
+                }
+                search::Filter::SaveDateSupported => {
+                    filters.push(query::Filter::is_in_set(message_ids.clone()));
+                }
                 search::Filter::ModSeq((modseq, _)) => {
                     let mut set = RoaringBitmap::new();
                     for change in self
@@ -650,6 +862,54 @@ impl SessionData {
     }
 }
 
+fn sort_comparators(sort: Vec<search::Comparator>) -> Vec<query::Comparator> {
+    sort.into_iter()
+        .map(|item| match item.sort {
+            search::Sort::Arrival => query::Comparator::field(Property::ReceivedAt, item.ascending),
+            search::Sort::Cc => query::Comparator::field(Property::Cc, item.ascending),
+            search::Sort::Date => query::Comparator::field(Property::SentAt, item.ascending),
+            search::Sort::From | search::Sort::DisplayFrom => {
+                query::Comparator::field(Property::From, item.ascending)
+            }
+            search::Sort::Size => query::Comparator::field(Property::Size, item.ascending),
+            search::Sort::Subject => query::Comparator::field(Property::Subject, item.ascending),
+            search::Sort::To | search::Sort::DisplayTo => {
+                query::Comparator::field(Property::To, item.ascending)
+            }
+        })
+        .collect()
+}
+
+/// Returns the REMOVETO and ADDTO (position, uid) pairs that turn the previous
+/// results into the current ones. Removals are listed from the last position so
+/// that each position is valid at the time it is applied.
+fn diff_results(prev: &[u32], current: &[u32]) -> (Vec<(u32, u32)>, Vec<(u32, u32)>) {
+    let prev_set = prev.iter().copied().collect::<AHashSet<_>>();
+    let current_set = current.iter().copied().collect::<AHashSet<_>>();
+
+    // If the order of the remaining results changed, replace the whole list
+    let reordered = !prev
+        .iter()
+        .filter(|uid| current_set.contains(uid))
+        .eq(current.iter().filter(|uid| prev_set.contains(uid)));
+
+    let remove_from = prev
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, uid)| reordered || !current_set.contains(uid))
+        .map(|(pos, uid)| (pos as u32 + 1, *uid))
+        .collect();
+    let add_to = current
+        .iter()
+        .enumerate()
+        .filter(|(_, uid)| reordered || !prev_set.contains(uid))
+        .map(|(pos, uid)| (pos as u32 + 1, *uid))
+        .collect();
+
+    (remove_from, add_to)
+}
+
 impl SelectedMailbox {
     pub async fn get_saved_search(&self) -> Option<Arc<Vec<ImapId>>> {
         let mut rx = match &*self.saved_search.lock() {
@@ -737,6 +997,19 @@ impl MailboxState {
             None
         }
     }
+
+    pub fn saved_between(&self, from: i64, to: i64) -> RoaringBitmap {
+        self.saved_dates
+            .iter()
+            .chain(
+                self.next_state
+                    .iter()
+                    .flat_map(|state| state.next_state.saved_dates.iter()),
+            )
+            .filter(|(_, saved)| (from..to).contains(&(**saved as i64)))
+            .map(|(id, _)| *id)
+            .collect()
+    }
 }
 
 impl SavedSearch {
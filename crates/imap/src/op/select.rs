@@ -71,6 +71,7 @@ impl<T: AsyncRead> Session<T> {
                                 id: mailbox,
                                 state: parking_lot::Mutex::new(state),
                                 saved_search: parking_lot::Mutex::new(SavedSearch::None),
+                                contexts: parking_lot::Mutex::new(Vec::new()),
                                 is_select,
                                 is_condstore,
                             });
@@ -97,6 +98,7 @@ impl<T: AsyncRead> Session<T> {
                                             attributes: vec![fetch::Attribute::Flags],
                                             changed_since: qresync.modseq.into(),
                                             include_vanished: true,
+                                            partial: None,
                                         },
                                         mailbox.clone(),
                                         true,
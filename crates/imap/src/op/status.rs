@@ -170,6 +170,12 @@ impl SessionData {
                         Status::Recent => {
                             items_response.push((*item, StatusItemType::Number(0)));
                         }
+                        Status::AppendLimit => {
+                            items_response.push((
+                                *item,
+                                StatusItemType::Number(self.jmap.config.mail_max_size as u64),
+                            ));
+                        }
                     }
                 }
                 break;
@@ -258,7 +264,10 @@ impl SessionData {
                                 0
                             }
                         }
-                        Status::HighestModSeq | Status::MailboxId | Status::Recent => {
+                        Status::HighestModSeq
+                        | Status::MailboxId
+                        | Status::Recent
+                        | Status::AppendLimit => {
                             unreachable!()
                         }
                     };
@@ -311,7 +320,10 @@ impl SessionData {
                                 0
                             }
                         }
-                        Status::HighestModSeq | Status::MailboxId | Status::Recent => {
+                        Status::HighestModSeq
+                        | Status::MailboxId
+                        | Status::Recent
+                        | Status::AppendLimit => {
                             unreachable!()
                         }
                     };
@@ -337,7 +349,10 @@ impl SessionData {
                             Status::Unseen => mailbox_state.total_unseen = value.into(),
                             Status::Deleted => mailbox_state.total_deleted = value.into(),
                             Status::Size => mailbox_state.size = value.into(),
-                            Status::HighestModSeq | Status::MailboxId | Status::Recent => {
+                            Status::HighestModSeq
+                            | Status::MailboxId
+                            | Status::Recent
+                            | Status::AppendLimit => {
                                 unreachable!()
                             }
                         }
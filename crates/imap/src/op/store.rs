@@ -33,7 +33,7 @@ use imap_proto::{
     receiver::Request,
     Command, ResponseCode, ResponseType, StatusResponse,
 };
-use jmap::email::set::TagManager;
+use jmap::email::{set::TagManager, spam::spam_train_class};
 use jmap_proto::{
     error::method::MethodError,
     types::{
@@ -197,6 +197,8 @@ impl SessionData {
             .collect::<Vec<_>>();
         let mut changelog = ChangeLogBuilder::new();
         let mut changed_mailboxes = AHashSet::new();
+        let mut train_spam = Vec::new();
+        let mut train_ham = Vec::new();
         for (id, imap_id) in ids {
             // Obtain current keywords
             let (mut keywords, thread_id) = if let (Some(keywords), Some(thread_id)) = (
@@ -256,6 +258,9 @@ impl SessionData {
                     vec![]
                 };
 
+                // Setting $Junk or $NotJunk trains the spam filter
+                let spam_class = spam_train_class(None, &[], &[], keywords.added());
+
                 // Write changes
                 let mut batch = BatchBuilder::new();
                 batch
@@ -295,6 +300,11 @@ impl SessionData {
                             }
                         }
                         changelog.log_update(Collection::Email, Id::from_parts(thread_id, id));
+                        match spam_class {
+                            Some(true) => train_spam.push(id),
+                            Some(false) => train_ham.push(id),
+                            None => (),
+                        }
 
                         // Add item to response
                         let modseq = changelog.change_id + 1;
@@ -336,6 +346,14 @@ impl SessionData {
             }
         }
 
+        // Train spam filter
+        if !train_spam.is_empty() {
+            self.jmap.spam_train(account_id, train_spam, true).await;
+        }
+        if !train_ham.is_empty() {
+            self.jmap.spam_train(account_id, train_ham, false).await;
+        }
+
         // Log mailbox changes
         for mailbox_id in &changed_mailboxes {
             changelog.log_child_update(Collection::Mailbox, *mailbox_id);
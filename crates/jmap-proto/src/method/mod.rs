@@ -32,6 +32,8 @@ pub mod query;
 pub mod query_changes;
 pub mod search_snippet;
 pub mod set;
+pub mod sieve_list;
+pub mod sieve_test;
 pub mod validate;
 
 #[inline(always)]
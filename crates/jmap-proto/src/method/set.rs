@@ -264,7 +264,10 @@ impl JsonObjectParser for Object<SetValue> {
                         .unwrap_bool_or_null("")?
                         .map(|bool| SetValue::Value(Value::Bool(bool)))
                         .unwrap_or(SetValue::Value(Value::Null)),
-                    Property::Size | Property::SortOrder | Property::Quota => parser
+                    Property::Size
+                    | Property::SortOrder
+                    | Property::Quota
+                    | Property::RetentionDays => parser
                         .next_token::<String>()?
                         .unwrap_uint_or_null("")?
                         .map(|uint| SetValue::Value(Value::UnsignedInt(uint)))
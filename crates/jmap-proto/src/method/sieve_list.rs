@@ -0,0 +1,135 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use serde::Serialize;
+use utils::map::vec_map::VecMap;
+
+use crate::{
+    error::set::SetError,
+    parser::{json::Parser, JsonObjectParser, Token},
+    request::RequestProperty,
+    types::id::Id,
+};
+
+#[derive(Debug, Clone)]
+pub struct GetSieveListRequest {
+    pub account_id: Id,
+    pub ids: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GetSieveListResponse {
+    #[serde(rename = "accountId")]
+    pub account_id: Id,
+    pub list: Vec<SieveList>,
+    #[serde(rename = "notFound")]
+    pub not_found: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SieveList {
+    pub id: String,
+    #[serde(rename = "isReadOnly")]
+    pub is_read_only: bool,
+    pub items: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SetSieveListRequest {
+    pub account_id: Id,
+    pub update: VecMap<String, Option<Vec<String>>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SetSieveListResponse {
+    #[serde(rename = "accountId")]
+    pub account_id: Id,
+    pub updated: Vec<String>,
+    #[serde(rename = "notUpdated")]
+    #[serde(skip_serializing_if = "VecMap::is_empty")]
+    pub not_updated: VecMap<String, SetError>,
+}
+
+impl JsonObjectParser for GetSieveListRequest {
+    fn parse(parser: &mut Parser<'_>) -> crate::parser::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut request = GetSieveListRequest {
+            account_id: Id::default(),
+            ids: None,
+        };
+
+        parser
+            .next_token::<String>()?
+            .assert_jmap(Token::DictStart)?;
+
+        while let Some(key) = parser.next_dict_key::<RequestProperty>()? {
+            match &key.hash[0] {
+                0x0064_4974_6e75_6f63_6361 if !key.is_ref => {
+                    request.account_id = parser.next_token::<Id>()?.unwrap_string("accountId")?;
+                }
+                0x0073_6469 if !key.is_ref => {
+                    request.ids = <Option<Vec<String>>>::parse(parser)?;
+                }
+                _ => {
+                    parser.skip_token(parser.depth_array, parser.depth_dict)?;
+                }
+            }
+        }
+
+        Ok(request)
+    }
+}
+
+impl JsonObjectParser for SetSieveListRequest {
+    fn parse(parser: &mut Parser<'_>) -> crate::parser::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut request = SetSieveListRequest {
+            account_id: Id::default(),
+            update: VecMap::new(),
+        };
+
+        parser
+            .next_token::<String>()?
+            .assert_jmap(Token::DictStart)?;
+
+        while let Some(key) = parser.next_dict_key::<RequestProperty>()? {
+            match &key.hash[0] {
+                0x0064_4974_6e75_6f63_6361 if !key.is_ref => {
+                    request.account_id = parser.next_token::<Id>()?.unwrap_string("accountId")?;
+                }
+                0x6574_6164_7075 if !key.is_ref => {
+                    request.update = <VecMap<String, Option<Vec<String>>>>::parse(parser)?;
+                }
+                _ => {
+                    parser.skip_token(parser.depth_array, parser.depth_dict)?;
+                }
+            }
+        }
+
+        Ok(request)
+    }
+}
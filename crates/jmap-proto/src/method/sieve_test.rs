@@ -0,0 +1,167 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use serde::Serialize;
+
+use crate::{
+    error::set::SetError,
+    parser::{json::Parser, Ignore, JsonObjectParser, Token},
+    request::RequestProperty,
+    types::{blob::BlobId, id::Id},
+};
+
+#[derive(Debug, Clone)]
+pub struct TestSieveScriptRequest {
+    pub account_id: Id,
+    pub script_id: Option<Id>,
+    pub script: Option<String>,
+    pub email_blob_id: BlobId,
+    pub envelope: Option<TestEnvelope>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TestEnvelope {
+    pub mail_from: Option<String>,
+    pub rcpt_to: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestSieveScriptResponse {
+    #[serde(rename = "accountId")]
+    pub account_id: Id,
+    pub actions: Vec<SieveAction>,
+    pub error: Option<SetError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum SieveAction {
+    Keep {
+        flags: Vec<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    FileInto {
+        mailbox: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        mailbox_id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        special_use: Option<String>,
+        flags: Vec<String>,
+        create: bool,
+    },
+    Discard,
+    Reject {
+        reason: String,
+    },
+    Redirect {
+        recipients: Vec<String>,
+    },
+    Vacation {
+        recipients: Vec<String>,
+        subject: Option<String>,
+    },
+    Notify {
+        method: String,
+        message: String,
+    },
+}
+
+impl JsonObjectParser for TestSieveScriptRequest {
+    fn parse(parser: &mut Parser<'_>) -> crate::parser::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut request = TestSieveScriptRequest {
+            account_id: Id::default(),
+            script_id: None,
+            script: None,
+            email_blob_id: BlobId::default(),
+            envelope: None,
+        };
+
+        parser
+            .next_token::<String>()?
+            .assert_jmap(Token::DictStart)?;
+
+        while let Some(key) = parser.next_dict_key::<RequestProperty>()? {
+            match &key.hash[0] {
+                0x0064_4974_6e75_6f63_6361 if !key.is_ref => {
+                    request.account_id = parser.next_token::<Id>()?.unwrap_string("accountId")?;
+                }
+                0x6449_7470_6972_6373 if !key.is_ref => {
+                    request.script_id = parser
+                        .next_token::<Id>()?
+                        .unwrap_string_or_null("scriptId")?;
+                }
+                0x7470_6972_6373 if !key.is_ref => {
+                    request.script = parser
+                        .next_token::<String>()?
+                        .unwrap_string_or_null("script")?;
+                }
+                0x0064_4962_6f6c_426c_6961_6d65 if !key.is_ref => {
+                    request.email_blob_id = parser
+                        .next_token::<BlobId>()?
+                        .unwrap_string("emailBlobId")?;
+                }
+                0x6570_6f6c_6576_6e65 if !key.is_ref => {
+                    request.envelope = TestEnvelope::parse_or_null(parser)?;
+                }
+                _ => {
+                    parser.skip_token(parser.depth_array, parser.depth_dict)?;
+                }
+            }
+        }
+
+        Ok(request)
+    }
+}
+
+impl TestEnvelope {
+    fn parse_or_null(parser: &mut Parser<'_>) -> crate::parser::Result<Option<Self>> {
+        match parser.next_token::<Ignore>()? {
+            Token::DictStart => {
+                let mut envelope = TestEnvelope::default();
+                while let Some(key) = parser.next_dict_key::<RequestProperty>()? {
+                    match &key.hash[0] {
+                        0x6d6f_7246_6c69_616d => {
+                            envelope.mail_from = parser
+                                .next_token::<String>()?
+                                .unwrap_string_or_null("mailFrom")?;
+                        }
+                        0x6f54_7470_6372 => {
+                            envelope.rcpt_to =
+                                <Option<Vec<String>>>::parse(parser)?.unwrap_or_default();
+                        }
+                        _ => {
+                            parser.skip_token(parser.depth_array, parser.depth_dict)?;
+                        }
+                    }
+                }
+                Ok(Some(envelope))
+            }
+            Token::Null => Ok(None),
+            token => Err(token.error("envelope", "object or null")),
+        }
+    }
+}
@@ -44,6 +44,8 @@ pub enum Capability {
     WebSocket = 1 << 6,
     #[serde(rename(serialize = "urn:ietf:params:jmap:sieve"))]
     Sieve = 1 << 7,
+    #[serde(rename(serialize = "urn:ietf:params:jmap:webpush-vapid"))]
+    WebPushVapid = 1 << 8,
 }
 
 impl JsonObjectParser for Capability {
@@ -71,6 +73,7 @@ impl JsonObjectParser for Capability {
                 0x0073_7261_646e_656c_6163 => Ok(Capability::Calendars),
                 0x0074_656b_636f_7362_6577 => Ok(Capability::WebSocket),
                 0x0065_7665_6973 => Ok(Capability::Sieve),
+                0x0064_6970_6176_2d68_7375_7062_6577 => Ok(Capability::WebPushVapid),
                 _ => Err(parser.error_capability()),
             },
             Err(Error::Method(_)) => Err(parser.error_capability()),
@@ -44,6 +44,7 @@ pub enum MethodObject {
     EmailSubmission,
     VacationResponse,
     SieveScript,
+    SieveList,
     Principal,
 }
 
@@ -58,6 +59,7 @@ pub enum MethodFunction {
     Import,
     Parse,
     Validate,
+    Test,
     Echo,
 }
 
@@ -108,6 +110,7 @@ impl JsonObjectParser for MethodName {
                 0x6573_6e6f_7073_6552_6e6f_6974_6163_6156 => MethodObject::VacationResponse,
                 0x6e6f_6974_7069_7263_7362_7553_6873_7550 => MethodObject::PushSubscription,
                 0x0074_7069_7263_5365_7665_6953 => MethodObject::SieveScript,
+                0x0074_7369_4c65_7665_6953 => MethodObject::SieveList,
                 0x006c_6170_6963_6e69_7250 => MethodObject::Principal,
                 0x6572_6f43 => MethodObject::Core,
                 _ => return Err(parser.error_value()),
@@ -122,6 +125,7 @@ impl JsonObjectParser for MethodName {
                 0x7472_6f70_6d69 => MethodFunction::Import,
                 0x0065_7372_6170 => MethodFunction::Parse,
                 0x6574_6164_696c_6176 => MethodFunction::Validate,
+                0x7473_6574 => MethodFunction::Test,
                 0x6f68_6365 => MethodFunction::Echo,
                 _ => return Err(parser.error_value()),
             },
@@ -185,6 +189,9 @@ impl MethodName {
             (MethodFunction::Set, MethodObject::SieveScript) => "SieveScript/set",
             (MethodFunction::Query, MethodObject::SieveScript) => "SieveScript/query",
             (MethodFunction::Validate, MethodObject::SieveScript) => "SieveScript/validate",
+            (MethodFunction::Test, MethodObject::SieveScript) => "SieveScript/test",
+            (MethodFunction::Get, MethodObject::SieveList) => "SieveList/get",
+            (MethodFunction::Set, MethodObject::SieveList) => "SieveList/set",
             (MethodFunction::Get, MethodObject::Principal) => "Principal/get",
             (MethodFunction::Set, MethodObject::Principal) => "Principal/set",
             (MethodFunction::Query, MethodObject::Principal) => "Principal/query",
@@ -203,6 +210,7 @@ impl Display for MethodObject {
             MethodObject::VacationResponse => "VacationResponse",
             MethodObject::PushSubscription => "PushSubscription",
             MethodObject::SieveScript => "SieveScript",
+            MethodObject::SieveList => "SieveList",
             MethodObject::Principal => "Principal",
             MethodObject::Core => "Core",
             MethodObject::Mailbox => "Mailbox",
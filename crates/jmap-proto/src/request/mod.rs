@@ -45,6 +45,8 @@ use crate::{
         query_changes::QueryChangesRequest,
         search_snippet::GetSearchSnippetRequest,
         set::{self, SetRequest},
+        sieve_list::{GetSieveListRequest, SetSieveListRequest},
+        sieve_test::TestSieveScriptRequest,
         validate::ValidateSieveScriptRequest,
     },
     parser::{json::Parser, JsonObjectParser},
@@ -86,6 +88,9 @@ pub enum RequestMethod {
     Query(QueryRequest<query::RequestArguments>),
     SearchSnippet(GetSearchSnippetRequest),
     ValidateScript(ValidateSieveScriptRequest),
+    TestScript(TestSieveScriptRequest),
+    GetSieveList(GetSieveListRequest),
+    SetSieveList(SetSieveListRequest),
     Echo(Echo),
     Error(MethodError),
 }
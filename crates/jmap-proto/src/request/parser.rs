@@ -38,6 +38,8 @@ use crate::{
         query_changes::QueryChangesRequest,
         search_snippet::GetSearchSnippetRequest,
         set::SetRequest,
+        sieve_list::{GetSieveListRequest, SetSieveListRequest},
+        sieve_test::TestSieveScriptRequest,
         validate::ValidateSieveScriptRequest,
     },
     parser::{json::Parser, Error, Ignore, JsonObjectParser, Token},
@@ -129,6 +131,12 @@ impl Request {
                         let start_depth_dict = parser.depth_dict;
 
                         let method = match (&method_name.fnc, &method_name.obj) {
+                            (MethodFunction::Get, MethodObject::SieveList) => {
+                                GetSieveListRequest::parse(parser).map(RequestMethod::GetSieveList)
+                            }
+                            (MethodFunction::Set, MethodObject::SieveList) => {
+                                SetSieveListRequest::parse(parser).map(RequestMethod::SetSieveList)
+                            }
                             (MethodFunction::Get, _) => {
                                 if method_name.obj != MethodObject::SearchSnippet {
                                     GetRequest::parse(parser).map(RequestMethod::Get)
@@ -165,6 +173,9 @@ impl Request {
                                 ValidateSieveScriptRequest::parse(parser)
                                     .map(RequestMethod::ValidateScript)
                             }
+                            (MethodFunction::Test, MethodObject::SieveScript) => {
+                                TestSieveScriptRequest::parse(parser).map(RequestMethod::TestScript)
+                            }
                             (MethodFunction::Echo, MethodObject::Core) => {
                                 Echo::parse(parser).map(RequestMethod::Echo)
                             }
@@ -38,6 +38,8 @@ use crate::{
         query_changes::QueryChangesResponse,
         search_snippet::GetSearchSnippetResponse,
         set::SetResponse,
+        sieve_list::{GetSieveListResponse, SetSieveListResponse},
+        sieve_test::TestSieveScriptResponse,
         validate::ValidateSieveScriptResponse,
     },
     request::{echo::Echo, method::MethodName, Call},
@@ -60,6 +62,9 @@ pub enum ResponseMethod {
     Query(QueryResponse),
     SearchSnippet(GetSearchSnippetResponse),
     ValidateScript(ValidateSieveScriptResponse),
+    TestScript(TestSieveScriptResponse),
+    GetSieveList(GetSieveListResponse),
+    SetSieveList(SetSieveListResponse),
     Echo(Echo),
     Error(MethodError),
 }
@@ -191,6 +196,24 @@ impl From<ValidateSieveScriptResponse> for ResponseMethod {
     }
 }
 
+impl From<TestSieveScriptResponse> for ResponseMethod {
+    fn from(test_script: TestSieveScriptResponse) -> Self {
+        ResponseMethod::TestScript(test_script)
+    }
+}
+
+impl From<GetSieveListResponse> for ResponseMethod {
+    fn from(get_sieve_list: GetSieveListResponse) -> Self {
+        ResponseMethod::GetSieveList(get_sieve_list)
+    }
+}
+
+impl From<SetSieveListResponse> for ResponseMethod {
+    fn from(set_sieve_list: SetSieveListResponse) -> Self {
+        ResponseMethod::SetSieveList(set_sieve_list)
+    }
+}
+
 impl<T: Into<ResponseMethod>> From<Result<T, MethodError>> for ResponseMethod {
     fn from(result: Result<T, MethodError>) -> Self {
         match result {
@@ -130,6 +130,8 @@ pub enum Property {
     MayCreateChild,
     MayRename,
     MaySubmit,
+    RetentionDays,
+    AddedDates,
     _T(String),
 }
 
@@ -418,6 +420,7 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x0073_6563_6e65_7265_6665 => Property::References,
             0x6f54_796c_7065 => Property::ReplyTo,
             0x0065_6c6f => Property::Role,
+            0x7379_6144_6e6f_6974_6e65_7465 => Property::RetentionDays,
             _ => return None,
         },
         b's' => match hash {
@@ -809,6 +812,8 @@ impl Display for Property {
             Property::MaySetKeywords => write!(f, "maySetKeywords"),
             Property::MayCreateChild => write!(f, "mayCreateChild"),
             Property::MayRename => write!(f, "mayRename"),
+            Property::RetentionDays => write!(f, "retentionDays"),
+            Property::AddedDates => write!(f, "addedDates"),
             Property::MaySubmit => write!(f, "maySubmit"),
             Property::_T(s) => write!(f, "{s}"),
         }
@@ -984,6 +989,8 @@ impl From<&Property> for u8 {
             Property::IdentityId => 95,
             Property::InReplyTo => 96,
             Property::_T(_) => 97,
+            Property::RetentionDays => 98,
+            Property::AddedDates => 99,
         }
     }
 }
@@ -1114,6 +1121,8 @@ impl SerializeInto for Property {
             Property::Id => 94,
             Property::IdentityId => 95,
             Property::InReplyTo => 96,
+            Property::RetentionDays => 98,
+            Property::AddedDates => 99,
             Property::_T(value) => {
                 buf.push(97);
                 value.serialize_into(buf);
@@ -1228,6 +1237,8 @@ impl DeserializeFrom for Property {
             95 => Some(Property::IdentityId),
             96 => Some(Property::InReplyTo),
             97 => String::deserialize_from(bytes).map(Property::_T),
+            98 => Some(Property::RetentionDays),
+            99 => Some(Property::AddedDates),
             _ => None,
         }
     }
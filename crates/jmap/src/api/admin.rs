@@ -84,6 +84,7 @@ impl JMAP {
 
         // Delete account
         self.store.purge_account(account_id).await?;
+        self.store.delete_sieve_lists(account_id).await?;
 
         Ok(())
     }
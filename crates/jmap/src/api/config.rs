@@ -28,6 +28,8 @@ use store::{
     rand::{distributions::Alphanumeric, thread_rng, Rng},
 };
 
+use crate::{mailbox::retention::parse_retention_rules, sieve::notify::parse_notify_methods};
+
 use super::session::BaseCapabilities;
 
 impl crate::Config {
@@ -92,6 +94,18 @@ impl crate::Config {
             sieve_max_scripts: settings
                 .property("jmap.sieve.limits.max-scripts")?
                 .unwrap_or(256),
+            sieve_notify_rate: settings.property_or_static("jmap.sieve.notify.rate", "10/1h")?,
+            sieve_notify_methods: parse_notify_methods(settings)?,
+            sieve_personal_lists: settings
+                .values("jmap.sieve.lists.personal")
+                .map(|(_, v)| v.to_string())
+                .collect(),
+            sieve_list_max_items: settings
+                .property("jmap.sieve.lists.max-items")?
+                .unwrap_or(1000),
+            sieve_list_max_item_size: settings
+                .property("jmap.sieve.lists.max-item-size")?
+                .unwrap_or(255),
             capabilities: BaseCapabilities::default(),
             session_cache_ttl: settings
                 .property("jmap.session.cache.ttl")?
@@ -140,6 +154,17 @@ impl crate::Config {
                 .unwrap_or(true),
             encrypt: settings.property_or_static("jmap.encryption.enable", "true")?,
             encrypt_append: settings.property_or_static("jmap.encryption.append", "false")?,
+            retention_rules: parse_retention_rules(settings)?,
+            retention_batch_size: settings
+                .property("jmap.retention.batch-size")?
+                .unwrap_or(100),
+            journal_archive_accounts: settings
+                .values("jmap.journal.archive-accounts")
+                .map(|(_, v)| v.to_string())
+                .collect(),
+            legal_hold_compliance_group: settings
+                .value("jmap.legal-hold.compliance-group")
+                .map(|v| v.to_string()),
         };
         config.add_capabilites(settings);
         Ok(config)
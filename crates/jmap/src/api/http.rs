@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::{net::IpAddr, sync::Arc};
+use std::{net::IpAddr, path::Path, sync::Arc};
 
 use http_body_util::{BodyExt, Full};
 use hyper::{
@@ -38,12 +38,20 @@ use jmap_proto::{
     response::Response,
     types::{blob::BlobId, id::Id},
 };
-use serde_json::Value;
+use serde_json::{json, Value};
+use store::{
+    audit::{AuditEvent, AuditFilter},
+    hold::{LegalHold, Tombstone, TombstoneFilter},
+    write::now,
+};
 use tokio::{
     io::{AsyncRead, AsyncWrite},
     net::TcpStream,
 };
-use utils::listener::{ServerInstance, SessionData, SessionManager};
+use utils::{
+    acme::{http_challenge_key, AcmeCache},
+    listener::{ServerInstance, SessionData, SessionManager},
+};
 
 use crate::{
     auth::{oauth::OAuthMetadata, AccessToken},
@@ -91,7 +99,15 @@ pub async fn parse_jmap_request(
                         Ok(request) => {
                             //let _ = println!("<- {}", String::from_utf8_lossy(&bytes));
 
-                            match jmap.handle_request(request, access_token, &instance).await {
+                            match jmap
+                                .handle_request(
+                                    request,
+                                    access_token,
+                                    &jmap.build_remote_addr(&req, remote_ip),
+                                    &instance,
+                                )
+                                .await
+                            {
                                 Ok(response) => response.into_http_response(),
                                 Err(err) => err.into_http_response(),
                             }
@@ -161,8 +177,15 @@ pub async fn parse_jmap_request(
                     return jmap.handle_event_source(req, access_token).await
                 }
                 ("ws", &Method::GET) => {
-                    return upgrade_websocket_connection(jmap, req, access_token, instance.clone())
-                        .await;
+                    let remote_addr = jmap.build_remote_addr(&req, remote_ip);
+                    return upgrade_websocket_connection(
+                        jmap,
+                        req,
+                        access_token,
+                        remote_addr,
+                        instance.clone(),
+                    )
+                    .await;
                 }
                 _ => (),
             }
@@ -192,6 +215,30 @@ pub async fn parse_jmap_request(
                     Err(err) => err.into_http_response(),
                 };
             }
+            ("acme-challenge", &Method::GET) => {
+                let token = path.next().unwrap_or_default();
+                return match jmap.store.read(&http_challenge_key(token)).await {
+                    Ok(Some(key_authorization)) => hyper::Response::builder()
+                        .status(StatusCode::OK)
+                        .header(header::CONTENT_TYPE, "application/octet-stream")
+                        .body(
+                            Full::new(Bytes::from(key_authorization))
+                                .map_err(|never| match never {})
+                                .boxed(),
+                        )
+                        .unwrap(),
+                    Ok(None) => RequestError::not_found().into_http_response(),
+                    Err(err) => {
+                        tracing::error!(
+                            context = "acme",
+                            event = "error",
+                            reason = err,
+                            "Failed to read HTTP-01 challenge."
+                        );
+                        RequestError::internal_server_error().into_http_response()
+                    }
+                };
+            }
             _ => (),
         },
         "auth" => {
@@ -250,13 +297,133 @@ pub async fn parse_jmap_request(
             _ => (),
         },
 
+        "quarantine" => {
+            if let ("release", &Method::GET | &Method::POST) =
+                (path.next().unwrap_or(""), req.method())
+            {
+                let remote_addr = jmap.build_remote_addr(&req, remote_ip);
+                return match jmap.is_anonymous_allowed(remote_addr.clone()) {
+                    Ok(_) => jmap.handle_quarantine_release(&mut req, remote_addr).await,
+                    Err(err) => err.into_http_response(),
+                };
+            }
+        }
+        "compliance" => {
+            // Make sure the user is a superuser or a member of the compliance group
+            let access_token = match jmap.authenticate_headers(&req, remote_ip).await {
+                Ok(Some((_, access_token))) => access_token,
+                Ok(None) => return RequestError::unauthorized().into_http_response(),
+                Err(err) => return err.into_http_response(),
+            };
+            match jmap.is_compliance_officer(&access_token).await {
+                Ok(true) => (),
+                Ok(false) => return RequestError::forbidden().into_http_response(),
+                Err(_) => return RequestError::internal_server_error().into_http_response(),
+            }
+            let remote_addr = jmap.build_remote_addr(&req, remote_ip);
+            let audit_event = |action: &str, target: &str| {
+                AuditEvent::new("http", action)
+                    .with_account(access_token.name.as_str())
+                    .with_remote_ip(&remote_addr)
+                    .with_target(target)
+            };
+
+            let path_1 = path.next().unwrap_or("");
+            let account_name = path.next().unwrap_or("");
+            let account_id = match jmap.try_get_account_id(account_name).await {
+                Ok(Some(account_id)) => account_id,
+                Ok(None) => {
+                    return RequestError::blank(
+                        StatusCode::NOT_FOUND.as_u16(),
+                        "Not found",
+                        "Account not found.",
+                    )
+                    .into_http_response();
+                }
+                Err(_) => return RequestError::internal_server_error().into_http_response(),
+            };
+
+            match (path_1, req.method()) {
+                ("tombstones", &Method::GET) => {
+                    let mut filter = TombstoneFilter::default();
+                    let mut limit = 100;
+                    for (key, value) in
+                        form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+                    {
+                        match key.as_ref() {
+                            "from" => filter.from = value.parse().ok(),
+                            "to" => filter.to = value.parse().ok(),
+                            "text" => filter.text = Some(value.into_owned()),
+                            "limit" => limit = value.parse().unwrap_or(limit),
+                            _ => (),
+                        }
+                    }
+                    let details = filter.text.clone().unwrap_or_default();
+
+                    return match jmap.store.tombstone_query(account_id, filter, limit).await {
+                        Ok(tombstones) => {
+                            jmap.audit
+                                .record(
+                                    &jmap.store,
+                                    audit_event("compliance.search", account_name)
+                                        .with_details(details),
+                                )
+                                .await;
+                            JsonResponse::new(json!({ "data": tombstones })).into_http_response()
+                        }
+                        Err(err) => RequestError::blank(
+                            StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                            "Tombstone query failed",
+                            err.to_string(),
+                        )
+                        .into_http_response(),
+                    };
+                }
+                ("tombstone", &Method::GET) => {
+                    if let Some(id) = path.next().and_then(|id| id.parse::<u32>().ok()) {
+                        return match jmap
+                            .get_blob(&Tombstone::blob_kind(account_id, id), 0..u32::MAX)
+                            .await
+                        {
+                            Ok(Some(blob)) => {
+                                jmap.audit
+                                    .record(
+                                        &jmap.store,
+                                        audit_event(
+                                            "compliance.download",
+                                            &format!("{account_name}/{id}"),
+                                        ),
+                                    )
+                                    .await;
+                                DownloadResponse {
+                                    filename: format!("{id}.eml"),
+                                    content_type: "message/rfc822".to_string(),
+                                    blob,
+                                }
+                                .into_http_response()
+                            }
+                            Ok(None) => RequestError::not_found().into_http_response(),
+                            Err(_) => RequestError::internal_server_error().into_http_response(),
+                        };
+                    }
+                }
+                _ => (),
+            }
+        }
         "admin" => {
             // Make sure the user is a superuser
-            match jmap.authenticate_headers(&req, remote_ip).await {
-                Ok(Some((_, access_token))) if access_token.is_super_user() => (),
+            let access_token = match jmap.authenticate_headers(&req, remote_ip).await {
+                Ok(Some((_, access_token))) if access_token.is_super_user() => access_token,
                 Ok(_) => return RequestError::unauthorized().into_http_response(),
                 Err(err) => return err.into_http_response(),
-            }
+            };
+            let remote_addr = jmap.build_remote_addr(&req, remote_ip);
+            let audit_event = |action: &str, target: &str| {
+                AuditEvent::new("http", action)
+                    .with_account(access_token.name.as_str())
+                    .with_remote_ip(&remote_addr)
+                    .with_target(target)
+            };
 
             match (
                 path.next().unwrap_or(""),
@@ -265,10 +432,41 @@ pub async fn parse_jmap_request(
             ) {
                 ("account", "delete", &Method::GET) => {
                     return if let Some(account_name) = path.next() {
+                        if jmap.is_archive_account_name(account_name) {
+                            return RequestError::blank(
+                                StatusCode::CONFLICT.as_u16(),
+                                "Journal archive account",
+                                "Journal archive accounts cannot be deleted.",
+                            )
+                            .into_http_response();
+                        }
                         if let Ok(Some(account_id)) = jmap.try_get_account_id(account_name).await {
+                            match jmap.is_on_hold(account_id).await {
+                                Ok(false) => (),
+                                Ok(true) => {
+                                    return RequestError::blank(
+                                        StatusCode::CONFLICT.as_u16(),
+                                        "Account under legal hold",
+                                        "Accounts under legal hold cannot be deleted.",
+                                    )
+                                    .into_http_response();
+                                }
+                                Err(_) => {
+                                    return RequestError::internal_server_error()
+                                        .into_http_response()
+                                }
+                            }
                             match jmap.delete_account(account_name, account_id).await {
-                                Ok(_) => JsonResponse::new(Value::String("success".into()))
-                                    .into_http_response(),
+                                Ok(_) => {
+                                    jmap.audit
+                                        .record(
+                                            &jmap.store,
+                                            audit_event("account.delete", account_name),
+                                        )
+                                        .await;
+                                    JsonResponse::new(Value::String("success".into()))
+                                        .into_http_response()
+                                }
                                 Err(err) => RequestError::blank(
                                     StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
                                     "Account deletion failed",
@@ -297,6 +495,14 @@ pub async fn parse_jmap_request(
                     return if let (Some(account_name), Some(new_account_name)) =
                         (path.next(), path.next())
                     {
+                        if jmap.is_archive_account_name(account_name) {
+                            return RequestError::blank(
+                                StatusCode::CONFLICT.as_u16(),
+                                "Journal archive account",
+                                "Journal archive accounts cannot be renamed.",
+                            )
+                            .into_http_response();
+                        }
                         match (
                             jmap.try_get_account_id(account_name).await,
                             jmap.try_get_account_id(new_account_name).await,
@@ -306,8 +512,17 @@ pub async fn parse_jmap_request(
                                     .rename_account(new_account_name, account_name, account_id)
                                     .await
                                 {
-                                    Ok(_) => JsonResponse::new(Value::String("success".into()))
-                                        .into_http_response(),
+                                    Ok(_) => {
+                                        jmap.audit
+                                            .record(
+                                                &jmap.store,
+                                                audit_event("account.rename", account_name)
+                                                    .with_details(new_account_name),
+                                            )
+                                            .await;
+                                        JsonResponse::new(Value::String("success".into()))
+                                            .into_http_response()
+                                    }
                                     Err(err) => RequestError::blank(
                                         StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
                                         "Account rename failed",
@@ -339,6 +554,64 @@ pub async fn parse_jmap_request(
                         .into_http_response()
                     };
                 }
+                ("account", action @ ("hold" | "release"), &Method::GET) => {
+                    return if let Some(account_name) = path.next() {
+                        let account_id = match jmap.try_get_account_id(account_name).await {
+                            Ok(Some(account_id)) => account_id,
+                            Ok(None) => {
+                                return RequestError::blank(
+                                    StatusCode::NOT_FOUND.as_u16(),
+                                    "Not found",
+                                    "Account not found.",
+                                )
+                                .into_http_response();
+                            }
+                            Err(_) => {
+                                return RequestError::internal_server_error().into_http_response()
+                            }
+                        };
+                        let hold = (action == "hold").then(|| LegalHold {
+                            since: now(),
+                            reason: form_urlencoded::parse(
+                                req.uri().query().unwrap_or_default().as_bytes(),
+                            )
+                            .find(|(k, _)| k == "reason")
+                            .map(|(_, v)| v.into_owned())
+                            .unwrap_or_default(),
+                        });
+                        let details = hold
+                            .as_ref()
+                            .map(|hold| hold.reason.clone())
+                            .unwrap_or_default();
+
+                        match jmap.store.set_legal_hold(account_id, hold).await {
+                            Ok(_) => {
+                                jmap.audit
+                                    .record(
+                                        &jmap.store,
+                                        audit_event(&format!("account.{action}"), account_name)
+                                            .with_details(details),
+                                    )
+                                    .await;
+                                JsonResponse::new(Value::String("success".into()))
+                                    .into_http_response()
+                            }
+                            Err(err) => RequestError::blank(
+                                StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                                "Legal hold update failed",
+                                err.to_string(),
+                            )
+                            .into_http_response(),
+                        }
+                    } else {
+                        RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Invalid parameters",
+                            "Expected account name",
+                        )
+                        .into_http_response()
+                    };
+                }
                 ("blob", "purge", &Method::GET) => {
                     return match jmap.store.purge_tmp_blobs(jmap.config.upload_tmp_ttl).await {
                         Ok(_) => {
@@ -352,10 +625,257 @@ pub async fn parse_jmap_request(
                         .into_http_response(),
                     };
                 }
-                (path_1 @ ("queue" | "report"), path_2, &Method::GET) => {
+                ("store", "backup", &Method::GET) => {
+                    let mut backup_path = None;
+                    let mut base_path = None;
+                    for (key, value) in
+                        form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+                    {
+                        match key.as_ref() {
+                            "path" => backup_path = Some(value.into_owned()),
+                            "base" => base_path = Some(value.into_owned()),
+                            _ => (),
+                        }
+                    }
+
+                    return if let Some(backup_path) = backup_path {
+                        match jmap
+                            .store
+                            .backup(&backup_path, base_path.as_ref().map(Path::new))
+                            .await
+                        {
+                            Ok(_) => {
+                                jmap.audit
+                                    .record(
+                                        &jmap.store,
+                                        audit_event("store.backup", &backup_path)
+                                            .with_details(base_path.unwrap_or_default()),
+                                    )
+                                    .await;
+                                JsonResponse::new(Value::String("success".into()))
+                                    .into_http_response()
+                            }
+                            Err(err) => RequestError::blank(
+                                StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                                "Backup failed",
+                                err.to_string(),
+                            )
+                            .into_http_response(),
+                        }
+                    } else {
+                        RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Invalid parameters",
+                            "Expected backup path",
+                        )
+                        .into_http_response()
+                    };
+                }
+                ("store", "restore", &Method::GET) => {
+                    let mut backup_path = None;
+                    let mut account_name = None;
+                    for (key, value) in
+                        form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+                    {
+                        match key.as_ref() {
+                            "path" => backup_path = Some(value.into_owned()),
+                            "account" => account_name = Some(value.into_owned()),
+                            _ => (),
+                        }
+                    }
+
+                    let backup_path = if let Some(backup_path) = backup_path {
+                        backup_path
+                    } else {
+                        return RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Invalid parameters",
+                            "Expected backup path",
+                        )
+                        .into_http_response();
+                    };
+                    let account_id = if let Some(account_name) = &account_name {
+                        match jmap.try_get_account_id(account_name).await {
+                            Ok(Some(account_id)) => Some(account_id),
+                            Ok(None) => {
+                                return RequestError::blank(
+                                    StatusCode::NOT_FOUND.as_u16(),
+                                    "Not found",
+                                    "Account not found.",
+                                )
+                                .into_http_response();
+                            }
+                            Err(_) => {
+                                return RequestError::internal_server_error().into_http_response()
+                            }
+                        }
+                    } else {
+                        None
+                    };
+
+                    return match jmap.store.restore(&backup_path, account_id).await {
+                        Ok(_) => {
+                            jmap.audit
+                                .record(
+                                    &jmap.store,
+                                    audit_event("store.restore", &backup_path)
+                                        .with_details(account_name.unwrap_or_default()),
+                                )
+                                .await;
+                            JsonResponse::new(Value::String("success".into())).into_http_response()
+                        }
+                        Err(err) => RequestError::blank(
+                            StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                            "Restore failed",
+                            err.to_string(),
+                        )
+                        .into_http_response(),
+                    };
+                }
+                ("audit", "list", &Method::GET) => {
+                    let mut filter = AuditFilter::default();
+                    let mut limit = 100;
+                    for (key, value) in
+                        form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+                    {
+                        match key.as_ref() {
+                            "from" => filter.from = value.parse().ok(),
+                            "to" => filter.to = value.parse().ok(),
+                            "account" => filter.account = Some(value.into_owned()),
+                            "action" => filter.action = Some(value.into_owned()),
+                            "limit" => limit = value.parse().unwrap_or(limit),
+                            _ => (),
+                        }
+                    }
+
+                    return match jmap.store.audit_query(filter, limit).await {
+                        Ok(events) => {
+                            JsonResponse::new(json!({ "data": events })).into_http_response()
+                        }
+                        Err(err) => RequestError::blank(
+                            StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                            "Audit log query failed",
+                            err.to_string(),
+                        )
+                        .into_http_response(),
+                    };
+                }
+                ("sieve", "test", &Method::POST) => {
+                    let uri = req.uri().clone();
+                    let mut account_name = None;
+                    let mut script_name = None;
+                    let mut envelope_from = None;
+                    let mut envelope_to = None;
+                    for (key, value) in
+                        form_urlencoded::parse(uri.query().unwrap_or_default().as_bytes())
+                    {
+                        match key.as_ref() {
+                            "account" => account_name = Some(value.into_owned()),
+                            "script" => script_name = Some(value.into_owned()),
+                            "from" => envelope_from = Some(value.into_owned()),
+                            "to" => envelope_to = Some(value.into_owned()),
+                            _ => (),
+                        }
+                    }
+                    let raw_message = fetch_body(&mut req, 0, &access_token)
+                        .await
+                        .unwrap_or_default();
+
+                    // Without an account, test one of the SMTP stage scripts
+                    let account_name = if let Some(account_name) = account_name {
+                        account_name
+                    } else {
+                        return jmap
+                            .smtp
+                            .handle_manage_request(
+                                &uri,
+                                &Method::POST,
+                                "sieve",
+                                "test",
+                                &raw_message,
+                                &access_token.name,
+                                &remote_addr,
+                            )
+                            .await;
+                    };
+                    let account_id = match jmap.try_get_account_id(&account_name).await {
+                        Ok(Some(account_id)) => account_id,
+                        Ok(None) => {
+                            return RequestError::blank(
+                                StatusCode::NOT_FOUND.as_u16(),
+                                "Not found",
+                                "Account not found.",
+                            )
+                            .into_http_response();
+                        }
+                        Err(_) => {
+                            return RequestError::internal_server_error().into_http_response()
+                        }
+                    };
+                    let script = match &script_name {
+                        Some(script_name) => jmap
+                            .sieve_script_get_by_name(account_id, script_name)
+                            .await
+                            .map(|script| script.map(Arc::new)),
+                        None => jmap
+                            .sieve_script_get_active(account_id)
+                            .await
+                            .map(|script| script.map(|script| script.script)),
+                    };
+                    let script = match script {
+                        Ok(Some(script)) => script,
+                        Ok(None) => {
+                            return RequestError::blank(
+                                StatusCode::NOT_FOUND.as_u16(),
+                                "Not found",
+                                "Script not found.",
+                            )
+                            .into_http_response();
+                        }
+                        Err(_) => {
+                            return RequestError::internal_server_error().into_http_response()
+                        }
+                    };
+
+                    return match jmap
+                        .sieve_script_dry_run(
+                            account_id,
+                            &account_name,
+                            script,
+                            &raw_message,
+                            envelope_from,
+                            envelope_to,
+                        )
+                        .await
+                    {
+                        Ok(Some(actions)) => {
+                            JsonResponse::new(json!({ "data": actions })).into_http_response()
+                        }
+                        Ok(None) => RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Invalid parameters",
+                            "Failed to parse message.",
+                        )
+                        .into_http_response(),
+                        Err(_) => RequestError::internal_server_error().into_http_response(),
+                    };
+                }
+                (
+                    path_1 @ ("queue" | "report" | "suppression" | "quarantine"),
+                    path_2,
+                    &Method::GET,
+                ) => {
                     return jmap
                         .smtp
-                        .handle_manage_request(req.uri(), req.method(), path_1, path_2)
+                        .handle_manage_request(
+                            req.uri(),
+                            req.method(),
+                            path_1,
+                            path_2,
+                            &[],
+                            &access_token.name,
+                            &remote_addr,
+                        )
                         .await;
                 }
                 _ => (),
@@ -371,33 +891,25 @@ impl SessionManager for JmapSessionManager {
         let jmap = self.inner.clone();
 
         tokio::spawn(async move {
-            if let Some(tls_acceptor) = &session.instance.tls_acceptor {
-                let span = session.span;
-                match tls_acceptor.accept(session.stream).await {
-                    Ok(stream) => {
-                        handle_request(
-                            jmap,
-                            SessionData {
-                                stream,
-                                local_ip: session.local_ip,
-                                remote_ip: session.remote_ip,
-                                remote_port: session.remote_port,
-                                span,
-                                in_flight: session.in_flight,
-                                instance: session.instance,
-                            },
-                        )
-                        .await;
-                    }
-                    Err(err) => {
-                        tracing::debug!(
-                            parent: &span,
-                            context = "tls",
-                            event = "error",
-                            "Failed to accept TLS connection: {}",
-                            err
-                        );
-                    }
+            if session.instance.tls_acceptor.is_some() {
+                if let Ok(stream) = session
+                    .instance
+                    .tls_accept(session.stream, &session.span)
+                    .await
+                {
+                    handle_request(
+                        jmap,
+                        SessionData {
+                            stream,
+                            local_ip: session.local_ip,
+                            remote_ip: session.remote_ip,
+                            remote_port: session.remote_port,
+                            span: session.span,
+                            in_flight: session.in_flight,
+                            instance: session.instance,
+                        },
+                    )
+                    .await;
                 }
             } else {
                 handle_request(jmap, session).await;
@@ -34,6 +34,7 @@ pub mod admin;
 pub mod config;
 pub mod event_source;
 pub mod http;
+pub mod quarantine;
 pub mod request;
 pub mod session;
 
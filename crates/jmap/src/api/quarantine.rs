@@ -0,0 +1,207 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use hyper::StatusCode;
+use store::audit::AuditEvent;
+
+use crate::{
+    auth::{oauth::FormData, rate_limit::RemoteAddress},
+    JMAP,
+};
+
+use super::{http::ToHttpResponse, HtmlResponse, HttpRequest, HttpResponse};
+
+const QUARANTINE_HTML_HEADER: &str =
+    include_str!("../../../../resources/htx/quarantine_header.htx");
+const QUARANTINE_HTML_FOOTER: &str = include_str!("../../../../resources/htx/footer.htx");
+const QUARANTINE_HTML_FORM: &str = include_str!("../../../../resources/htx/quarantine_form.htx");
+const QUARANTINE_HTML_HIDDEN: &str =
+    include_str!("../../../../resources/htx/quarantine_hidden.htx");
+const QUARANTINE_HTML_SUCCESS: &str =
+    include_str!("../../../../resources/htx/quarantine_success.htx");
+const QUARANTINE_HTML_ERROR: &str = include_str!("../../../../resources/htx/quarantine_error.htx");
+
+const MAX_POST_LEN: usize = 2048;
+
+struct ReleaseLink {
+    id: u32,
+    nonce: u64,
+    rcpt: String,
+    expires: u64,
+    sig: String,
+}
+
+impl JMAP {
+    // Release links are signed by the SMTP server and sent in quarantine digests.
+    // A GET request displays a confirmation form so that link scanners do not
+    // release messages on their own.
+    pub async fn handle_quarantine_release(
+        &self,
+        req: &mut HttpRequest,
+        remote_addr: RemoteAddress,
+    ) -> HttpResponse {
+        let mut response = QUARANTINE_HTML_HEADER.replace("@@@", "/quarantine/release");
+
+        let link = if req.method() == hyper::Method::POST {
+            match FormData::from_request(req, MAX_POST_LEN).await {
+                Ok(mut form) => ReleaseLink::parse(|key| form.remove(key)),
+                Err(err) => return err,
+            }
+        } else {
+            let query = req.uri().query().unwrap_or_default().to_string();
+            ReleaseLink::parse(|key| {
+                form_urlencoded::parse(query.as_bytes())
+                    .find(|(k, _)| k == key)
+                    .map(|(_, v)| v.into_owned())
+            })
+        };
+
+        let (status, body) = match link {
+            Some(link)
+                if self.smtp.quarantine_verify_link(
+                    link.id,
+                    link.nonce,
+                    &link.rcpt,
+                    link.expires,
+                    &link.sig,
+                ) =>
+            {
+                if req.method() == hyper::Method::POST {
+                    match self
+                        .smtp
+                        .quarantine_release(link.id, Some(link.nonce), Some(&link.rcpt))
+                        .await
+                    {
+                        Ok(Some(_)) => {
+                            self.audit
+                                .record(
+                                    &self.store,
+                                    AuditEvent::new("http", "quarantine.release")
+                                        .with_account(link.rcpt.as_str())
+                                        .with_remote_ip(&remote_addr)
+                                        .with_target(link.id.to_string()),
+                                )
+                                .await;
+                            (
+                                StatusCode::OK,
+                                QUARANTINE_HTML_SUCCESS.replace("@@@", &html_escape(&link.rcpt)),
+                            )
+                        }
+                        Ok(None) => (
+                            StatusCode::NOT_FOUND,
+                            QUARANTINE_HTML_ERROR
+                                .replace("@@@", "The message was already released or has expired."),
+                        ),
+                        Err(err) => {
+                            tracing::warn!(
+                                context = "quarantine",
+                                event = "error",
+                                reason = %err,
+                                "Failed to release quarantined message."
+                            );
+                            (
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                                QUARANTINE_HTML_ERROR
+                                    .replace("@@@", "An internal error occurred, try again later."),
+                            )
+                        }
+                    }
+                } else {
+                    match self.store.get_quarantined(link.id).await {
+                        Ok(Some(message))
+                            if message.nonce == link.nonce
+                                && message
+                                    .recipients
+                                    .iter()
+                                    .any(|rcpt| rcpt.eq_ignore_ascii_case(&link.rcpt)) =>
+                        {
+                            let mut body = QUARANTINE_HTML_FORM
+                                .replace("@@@", &html_escape(&message.subject))
+                                .replace("$$$", &html_escape(&link.rcpt));
+                            for (name, value) in [
+                                ("id", link.id.to_string()),
+                                ("nonce", link.nonce.to_string()),
+                                ("rcpt", link.rcpt),
+                                ("expires", link.expires.to_string()),
+                                ("sig", link.sig),
+                            ] {
+                                body.push_str(
+                                    &QUARANTINE_HTML_HIDDEN
+                                        .replace("@@@", name)
+                                        .replace("$$$", &html_escape(&value)),
+                                );
+                            }
+                            (StatusCode::OK, body)
+                        }
+                        Ok(_) => (
+                            StatusCode::NOT_FOUND,
+                            QUARANTINE_HTML_ERROR
+                                .replace("@@@", "The message was already released or has expired."),
+                        ),
+                        Err(_) => (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            QUARANTINE_HTML_ERROR
+                                .replace("@@@", "An internal error occurred, try again later."),
+                        ),
+                    }
+                }
+            }
+            _ => (
+                StatusCode::BAD_REQUEST,
+                QUARANTINE_HTML_ERROR.replace("@@@", "The release link is invalid or has expired."),
+            ),
+        };
+
+        response.push_str(&body);
+        response.push_str(QUARANTINE_HTML_FOOTER);
+
+        HtmlResponse::with_status(status, response).into_http_response()
+    }
+}
+
+impl ReleaseLink {
+    fn parse(mut param: impl FnMut(&str) -> Option<String>) -> Option<Self> {
+        Some(ReleaseLink {
+            id: param("id")?.parse().ok()?,
+            nonce: param("nonce")?.parse().ok()?,
+            rcpt: param("rcpt")?,
+            expires: param("expires")?.parse().ok()?,
+            sig: param("sig")?,
+        })
+    }
+}
+
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
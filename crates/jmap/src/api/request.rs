@@ -35,13 +35,17 @@ use jmap_proto::{
 };
 use utils::listener::ServerInstance;
 
-use crate::{auth::AccessToken, JMAP};
+use crate::{
+    auth::{rate_limit::RemoteAddress, AccessToken},
+    JMAP,
+};
 
 impl JMAP {
     pub async fn handle_request(
         &self,
         request: Request,
         access_token: Arc<AccessToken>,
+        remote_addr: &RemoteAddress,
         instance: &Arc<ServerInstance>,
     ) -> Result<Response, RequestError> {
         let mut response = Response::new(
@@ -63,7 +67,13 @@ impl JMAP {
 
                 // Add response
                 match self
-                    .handle_method_call(call.method, &access_token, &mut next_call, instance)
+                    .handle_method_call(
+                        call.method,
+                        &access_token,
+                        remote_addr,
+                        &mut next_call,
+                        instance,
+                    )
                     .await
                 {
                     Ok(mut method_response) => {
@@ -123,6 +133,7 @@ impl JMAP {
         &self,
         method: RequestMethod,
         access_token: &AccessToken,
+        remote_addr: &RemoteAddress,
         next_call: &mut Option<Call<RequestMethod>>,
         instance: &Arc<ServerInstance>,
     ) -> Result<ResponseMethod, MethodError> {
@@ -222,7 +233,7 @@ impl JMAP {
                 set::RequestArguments::Mailbox(arguments) => {
                     access_token.assert_has_access(req.account_id, Collection::Mailbox)?;
 
-                    self.mailbox_set(req.with_arguments(arguments), access_token)
+                    self.mailbox_set(req.with_arguments(arguments), access_token, remote_addr)
                         .await?
                         .into()
                 }
@@ -284,6 +295,21 @@ impl JMAP {
 
                 self.sieve_script_validate(req, access_token).await?.into()
             }
+            RequestMethod::TestScript(req) => {
+                access_token.assert_is_member(req.account_id)?;
+
+                self.sieve_script_test(req, access_token).await?.into()
+            }
+            RequestMethod::GetSieveList(req) => {
+                access_token.assert_is_member(req.account_id)?;
+
+                self.sieve_list_get(req).await?.into()
+            }
+            RequestMethod::SetSieveList(req) => {
+                access_token.assert_is_member(req.account_id)?;
+
+                self.sieve_list_set(req).await?.into()
+            }
             RequestMethod::Echo(req) => req.into(),
             RequestMethod::Error(error) => return Err(error),
         })
@@ -32,7 +32,7 @@ use jmap_proto::{
 use store::ahash::AHashSet;
 use utils::{listener::ServerInstance, map::vec_map::VecMap, UnwrapFailure};
 
-use crate::{auth::AccessToken, JMAP};
+use crate::{auth::AccessToken, push::vapid::VapidKey, JMAP};
 
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct Session {
@@ -81,6 +81,7 @@ pub enum Capabilities {
     VacationResponse(VacationResponseCapabilities),
     WebSocket(WebSocketCapabilities),
     Sieve(SieveCapabilities),
+    WebPushVapid(WebPushVapidCapabilities),
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -158,6 +159,12 @@ pub struct SubmissionCapabilities {
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct VacationResponseCapabilities {}
 
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WebPushVapidCapabilities {
+    #[serde(rename(serialize = "applicationServerKey"))]
+    application_server_key: String,
+}
+
 #[derive(Default)]
 pub struct BaseCapabilities {
     pub capabilities: VecMap<Capability, Capabilities>,
@@ -234,6 +241,15 @@ impl crate::Config {
             Capabilities::Sieve(SieveCapabilities::new(self, settings)),
         );
     }
+
+    pub fn add_vapid_capability(&mut self, vapid_key: &VapidKey) {
+        self.capabilities.capabilities.append(
+            Capability::WebPushVapid,
+            Capabilities::WebPushVapid(WebPushVapidCapabilities {
+                application_server_key: vapid_key.public_key().to_string(),
+            }),
+        );
+    }
 }
 
 impl Session {
@@ -21,6 +21,8 @@
  * for more details.
 */
 
+use std::fmt::Display;
+
 use jmap_proto::{
     error::{method::MethodError, set::SetError},
     object::Object,
@@ -32,6 +34,7 @@ use jmap_proto::{
     },
 };
 use store::{
+    audit::AuditEvent,
     roaring::RoaringBitmap,
     write::{assert::HashedValue, key::DeserializeBigEndian},
     AclKey, Deserialize, Error,
@@ -490,6 +493,58 @@ impl JMAP {
         }
     }
 
+    pub async fn audit_acl_change(
+        &self,
+        access_token: &AccessToken,
+        remote_addr: impl Display,
+        protocol: &str,
+        account_id: u32,
+        mailbox_name: &str,
+        acl: &Value,
+    ) {
+        // Describe the resulting ACL as "grantee: right, right; ..."
+        let mut details = String::new();
+        if let Value::List(acl) = acl {
+            for item in acl.chunks_exact(2) {
+                if let (Some(Value::Id(id)), Some(Value::UnsignedInt(acl_bits))) =
+                    (item.first(), item.last())
+                {
+                    if !details.is_empty() {
+                        details.push_str("; ");
+                    }
+                    details.push_str(
+                        &self
+                            .get_account_name(id.document_id())
+                            .await
+                            .unwrap_or_default()
+                            .unwrap_or_else(|| id.to_string()),
+                    );
+                    details.push(':');
+                    for (pos, acl) in Bitmap::<Acl>::from(*acl_bits).enumerate() {
+                        details.push_str(if pos > 0 { ", " } else { " " });
+                        details.push_str(&acl.to_string());
+                    }
+                }
+            }
+        }
+        let owner = self
+            .get_account_name(account_id)
+            .await
+            .unwrap_or_default()
+            .unwrap_or_else(|| account_id.to_string());
+
+        self.audit
+            .record(
+                &self.store,
+                AuditEvent::new(protocol, "acl.set")
+                    .with_account(access_token.name.as_str())
+                    .with_remote_ip(remote_addr)
+                    .with_target(format!("{owner}/{mailbox_name}"))
+                    .with_details(details),
+            )
+            .await;
+    }
+
     async fn map_acl_accounts(&self, mut acl_set: Vec<Value>) -> Result<Vec<Value>, SetError> {
         for item in &mut acl_set {
             if let Value::Text(account_name) = item {
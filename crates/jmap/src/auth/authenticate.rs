@@ -36,7 +36,7 @@ use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
 use store::{
     write::{key::KeySerializer, BatchBuilder, Operation, ValueClass},
-    CustomValueKey, Serialize,
+    CustomValueKey, Serialize, ServerNamespace, SERVER_ACCOUNT_ID,
 };
 use utils::{listener::limiter::InFlight, map::ttl_dashmap::TtlMap};
 
@@ -320,15 +320,15 @@ pub struct AccountKey();
 impl AccountKey {
     pub fn name_to_id(name: &str) -> Vec<u8> {
         KeySerializer::new(name.len() + std::mem::size_of::<u32>() + 1)
-            .write(u32::MAX)
-            .write(0u8)
+            .write(SERVER_ACCOUNT_ID)
+            .write(ServerNamespace::AccountName as u8)
             .write(name)
             .finalize()
     }
     pub fn id_to_name(id: u32) -> Vec<u8> {
         KeySerializer::new(std::mem::size_of::<u32>() * 2 + 1)
-            .write(u32::MAX)
-            .write(1u8)
+            .write(SERVER_ACCOUNT_ID)
+            .write(ServerNamespace::AccountId as u8)
             .write(id)
             .finalize()
     }
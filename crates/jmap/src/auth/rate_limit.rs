@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::{net::IpAddr, sync::Arc};
+use std::{fmt::Display, net::IpAddr, sync::Arc};
 
 use jmap_proto::error::request::{RequestError, RequestLimitError};
 use store::parking_lot::Mutex;
@@ -37,10 +37,20 @@ pub enum RemoteAddress {
     IpAddressFwd(String),
 }
 
+impl Display for RemoteAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemoteAddress::IpAddress(ip) => ip.fmt(f),
+            RemoteAddress::IpAddressFwd(ip) => f.write_str(ip),
+        }
+    }
+}
+
 pub struct AuthenticatedLimiter {
     pub request_limiter: RateLimiter,
     pub concurrent_requests: ConcurrencyLimiter,
     pub concurrent_uploads: ConcurrencyLimiter,
+    pub sieve_notify: RateLimiter,
 }
 
 #[derive(Debug)]
@@ -66,6 +76,10 @@ impl JMAP {
                     concurrent_uploads: ConcurrencyLimiter::new(
                         self.config.upload_max_concurrent as u64,
                     ),
+                    sieve_notify: RateLimiter::new(
+                        self.config.sieve_notify_rate.requests,
+                        self.config.sieve_notify_rate.period,
+                    ),
                 }));
                 self.rate_limit_auth.insert(account_id, limiter.clone());
                 limiter
@@ -158,6 +172,7 @@ impl AuthenticatedLimiter {
         self.request_limiter.is_active()
             || self.concurrent_requests.is_active()
             || self.concurrent_uploads.is_active()
+            || self.sieve_notify.is_active()
     }
 }
 
@@ -0,0 +1,141 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::method::MethodError,
+    types::{collection::Collection, property::Property},
+};
+use store::{
+    write::{now, BatchBuilder, Operation, ToBitmaps, F_VALUE},
+    Deserialize, Serialize,
+};
+use utils::codec::leb128::{Leb128Iterator, Leb128Vec};
+
+use crate::JMAP;
+
+use super::set::TagManager;
+
+/// Time at which a message was added to each of its mailboxes, used by
+/// IMAP SAVEDATE and mailbox retention. Messages stored before these dates
+/// were tracked have no entry and fall back to their receivedAt date.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AddedDates {
+    dates: Vec<(u32, u64)>,
+}
+
+impl AddedDates {
+    pub fn new(mailbox_ids: &[u32], added_at: u64) -> Self {
+        AddedDates {
+            dates: mailbox_ids
+                .iter()
+                .map(|mailbox_id| (*mailbox_id, added_at))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, mailbox_id: u32) -> Option<u64> {
+        self.dates
+            .iter()
+            .find_map(|(id, added_at)| (*id == mailbox_id).then_some(*added_at))
+    }
+
+    pub fn earliest(&self) -> Option<u64> {
+        self.dates.iter().map(|(_, added_at)| *added_at).min()
+    }
+
+    pub fn update(&mut self, added: &[u32], removed: &[u32], added_at: u64) {
+        self.dates
+            .retain(|(id, _)| !added.contains(id) && !removed.contains(id));
+        self.dates
+            .extend(added.iter().map(|mailbox_id| (*mailbox_id, added_at)));
+    }
+}
+
+impl JMAP {
+    /// Adds the dates of the mailboxes a message is being added to, must be
+    /// called with the batch positioned on the message.
+    pub async fn update_added_dates(
+        &self,
+        batch: &mut BatchBuilder,
+        account_id: u32,
+        document_id: u32,
+        mailboxes: &TagManager<u32>,
+    ) -> Result<(), MethodError> {
+        if mailboxes.added().is_empty() && mailboxes.removed().is_empty() {
+            return Ok(());
+        }
+
+        let mut dates = self
+            .get_property::<AddedDates>(
+                account_id,
+                Collection::Email,
+                document_id,
+                Property::AddedDates,
+            )
+            .await?
+            .unwrap_or_default();
+        dates.update(mailboxes.added(), mailboxes.removed(), now());
+        batch.value(Property::AddedDates, &dates, F_VALUE);
+
+        Ok(())
+    }
+}
+
+impl Serialize for &AddedDates {
+    fn serialize(self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.dates.len() * 8 + 1);
+        buf.push_leb128(self.dates.len());
+        for (mailbox_id, added_at) in &self.dates {
+            buf.push_leb128(*mailbox_id);
+            buf.push_leb128(*added_at);
+        }
+        buf
+    }
+}
+
+impl Deserialize for AddedDates {
+    fn deserialize(bytes: &[u8]) -> store::Result<Self> {
+        let mut bytes = bytes.iter();
+        let len: usize = bytes.next_leb128().ok_or_else(|| {
+            store::Error::InternalError("Failed to deserialize added dates".to_string())
+        })?;
+        let mut dates = Vec::with_capacity(len);
+        for _ in 0..len {
+            dates.push(
+                bytes
+                    .next_leb128::<u32>()
+                    .and_then(|mailbox_id| Some((mailbox_id, bytes.next_leb128::<u64>()?)))
+                    .ok_or_else(|| {
+                        store::Error::InternalError("Failed to deserialize added dates".to_string())
+                    })?,
+            );
+        }
+        Ok(AddedDates { dates })
+    }
+}
+
+impl ToBitmaps for &AddedDates {
+    fn to_bitmaps(&self, _: &mut Vec<Operation>, _: u8, _: bool) {
+        unreachable!()
+    }
+}
@@ -51,7 +51,7 @@ use mail_parser::parsers::fields::thread::thread_name;
 use store::{
     fts::term_index::TokenIndex,
     query::RawValue,
-    write::{BatchBuilder, F_BITMAP, F_VALUE},
+    write::{now, BatchBuilder, F_BITMAP, F_VALUE},
     BlobKind,
 };
 use utils::map::vec_map::VecMap;
@@ -59,6 +59,7 @@ use utils::map::vec_map::VecMap;
 use crate::{auth::AccessToken, JMAP};
 
 use super::{
+    added_dates::AddedDates,
     index::{EmailIndexBuilder, TrimTextValue, MAX_SORT_FIELD_LENGTH},
     ingest::IngestedEmail,
 };
@@ -427,6 +428,11 @@ impl JMAP {
             .with_collection(Collection::Email)
             .create_document(message_id)
             .value(Property::ThreadId, thread_id, F_VALUE | F_BITMAP)
+            .value(
+                Property::AddedDates,
+                &AddedDates::new(&mailboxes, now()),
+                F_VALUE,
+            )
             .value(Property::MailboxIds, mailboxes, F_VALUE | F_BITMAP)
             .value(Property::Keywords, keywords, F_VALUE | F_BITMAP)
             .value(Property::Cid, changes.change_id, F_VALUE)
@@ -0,0 +1,131 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::error::method::MethodError;
+use mail_parser::{HeaderValue, Message};
+use store::{
+    hold::{LegalHold, Tombstone},
+    write::now,
+};
+
+use crate::{auth::AccessToken, JMAP};
+
+impl JMAP {
+    pub async fn get_legal_hold(&self, account_id: u32) -> Result<Option<LegalHold>, MethodError> {
+        self.store.get_legal_hold(account_id).await.map_err(|err| {
+            tracing::error!(
+                    event = "error",
+                    context = "legal_hold",
+                    account_id = account_id,
+                    error = ?err,
+                    "Failed to retrieve legal hold.");
+            MethodError::ServerPartialFail
+        })
+    }
+
+    pub async fn is_on_hold(&self, account_id: u32) -> Result<bool, MethodError> {
+        self.get_legal_hold(account_id)
+            .await
+            .map(|hold| hold.is_some())
+    }
+
+    pub async fn tombstone_message(
+        &self,
+        account_id: u32,
+        raw_message: &[u8],
+        reason: &str,
+    ) -> Result<(), MethodError> {
+        let mut tombstone = Tombstone {
+            deleted_at: now(),
+            size: raw_message.len() as u32,
+            reason: reason.to_string(),
+            ..Default::default()
+        };
+        if let Some(message) = Message::parse(raw_message) {
+            tombstone.sent_at = message.date().map_or(0, |date| date.to_timestamp() as u64);
+            tombstone.from = addresses(message.from());
+            tombstone.to = [addresses(message.to()), addresses(message.cc())]
+                .into_iter()
+                .filter(|addresses| !addresses.is_empty())
+                .collect::<Vec<_>>()
+                .join(", ");
+            tombstone.subject = message.subject().unwrap_or_default().to_string();
+            tombstone.message_id = message.message_id().unwrap_or_default().to_string();
+        }
+
+        match self
+            .store
+            .create_tombstone(account_id, tombstone, raw_message)
+            .await
+        {
+            Ok(id) => {
+                tracing::debug!(
+                    context = "legal_hold",
+                    event = "tombstone",
+                    account_id = account_id,
+                    tombstone_id = id,
+                    reason = reason,
+                    "Message under legal hold kept as tombstone."
+                );
+                Ok(())
+            }
+            Err(err) => {
+                tracing::error!(
+                    event = "error",
+                    context = "legal_hold",
+                    account_id = account_id,
+                    error = ?err,
+                    "Failed to create tombstone.");
+                Err(MethodError::ServerPartialFail)
+            }
+        }
+    }
+
+    pub async fn is_compliance_officer(
+        &self,
+        access_token: &AccessToken,
+    ) -> Result<bool, MethodError> {
+        if access_token.is_super_user() {
+            Ok(true)
+        } else if let Some(group) = &self.config.legal_hold_compliance_group {
+            Ok(self
+                .try_get_account_id(group)
+                .await?
+                .map_or(false, |group_id| access_token.member_of.contains(&group_id)))
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+fn addresses(value: &HeaderValue) -> String {
+    match value {
+        HeaderValue::Address(addr) => addr.address.as_deref().unwrap_or_default().to_string(),
+        HeaderValue::AddressList(list) => list
+            .iter()
+            .filter_map(|addr| addr.address.as_deref())
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => String::new(),
+    }
+}
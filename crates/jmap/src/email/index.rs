@@ -42,10 +42,10 @@ use store::{
         builder::{FtsIndexBuilder, MAX_TOKEN_LENGTH},
         Language,
     },
-    write::{BatchBuilder, IntoOperations, F_BITMAP, F_CLEAR, F_INDEX, F_VALUE},
+    write::{now, BatchBuilder, IntoOperations, F_BITMAP, F_CLEAR, F_INDEX, F_VALUE},
 };
 
-use crate::email::headers::IntoForm;
+use crate::email::{added_dates::AddedDates, headers::IntoForm};
 
 pub const MAX_MESSAGE_PARTS: usize = 1000;
 pub const MAX_ID_LENGTH: usize = 100;
@@ -84,6 +84,11 @@ impl IndexMessage for BatchBuilder {
         self.value(Property::Keywords, keywords, F_VALUE | F_BITMAP);
 
         // Index mailboxIds
+        self.value(
+            Property::AddedDates,
+            &AddedDates::new(&mailbox_ids, now()),
+            F_VALUE,
+        );
         self.value(Property::MailboxIds, mailbox_ids, F_VALUE | F_BITMAP);
 
         // Index size
@@ -21,11 +21,13 @@
  * for more details.
 */
 
+pub mod added_dates;
 pub mod body;
 pub mod copy;
 pub mod crypto;
 pub mod get;
 pub mod headers;
+pub mod hold;
 pub mod import;
 pub mod index;
 pub mod ingest;
@@ -33,3 +35,4 @@ pub mod parse;
 pub mod query;
 pub mod set;
 pub mod snippet;
+pub mod spam;
@@ -67,6 +67,7 @@ use super::{
     headers::{BuildHeader, ValueToHeader},
     index::EmailIndexBuilder,
     ingest::IngestEmail,
+    spam::spam_train_class,
 };
 
 impl JMAP {
@@ -757,6 +758,9 @@ impl JMAP {
 
         // Process updates
         let mut changes = ChangeLogBuilder::new();
+        let mut junk_id = None;
+        let mut train_spam = Vec::new();
+        let mut train_ham = Vec::new();
         'update: for (id, object) in request.unwrap_update() {
             // Make sure id won't be destroyed
             if will_destroy.contains(&id) {
@@ -848,6 +852,17 @@ impl JMAP {
                 continue 'update;
             }
 
+            // Moving messages in or out of Junk or setting $junk/$notjunk trains the spam filter
+            if mailboxes.has_changes() && junk_id.is_none() {
+                junk_id = Some(self.mailbox_junk_id(account_id).await);
+            }
+            let spam_class = spam_train_class(
+                junk_id.flatten(),
+                mailboxes.added(),
+                mailboxes.removed(),
+                keywords.added(),
+            );
+
             // Log change
             batch.update_document(document_id);
             let mut changed_mailboxes = AHashSet::new();
@@ -944,6 +959,8 @@ impl JMAP {
                 }
 
                 // Update mailboxIds property
+                self.update_added_dates(&mut batch, account_id, document_id, &mailboxes)
+                    .await?;
                 mailboxes.update_batch(&mut batch, Property::MailboxIds);
             }
 
@@ -958,6 +975,12 @@ impl JMAP {
                     Ok(_) => {
                         // Add to updated list
                         response.updated.append(id, None);
+
+                        match spam_class {
+                            Some(true) => train_spam.push(document_id),
+                            Some(false) => train_ham.push(document_id),
+                            None => (),
+                        }
                     }
                     Err(store::Error::AssertValueFailed) => {
                         response.not_updated.append(
@@ -979,6 +1002,14 @@ impl JMAP {
             }
         }
 
+        // Train spam filter
+        if !train_spam.is_empty() {
+            self.spam_train(account_id, train_spam, true).await;
+        }
+        if !train_ham.is_empty() {
+            self.spam_train(account_id, train_ham, false).await;
+        }
+
         // Process deletions
         if !will_destroy.is_empty() {
             let email_ids = self
@@ -1043,11 +1074,36 @@ impl JMAP {
         Ok(response)
     }
 
+    pub async fn is_archive_account(&self, account_id: u32) -> Result<bool, MethodError> {
+        if !self.config.journal_archive_accounts.is_empty() {
+            Ok(self
+                .get_account_name(account_id)
+                .await?
+                .map_or(false, |name| self.is_archive_account_name(&name)))
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub fn is_archive_account_name(&self, account_name: &str) -> bool {
+        self.config
+            .journal_archive_accounts
+            .iter()
+            .any(|name| name == account_name)
+    }
+
     pub async fn email_delete(
         &self,
         account_id: u32,
         document_id: u32,
     ) -> Result<Result<ChangeLogBuilder, SetError>, MethodError> {
+        // Messages in journal archive accounts are immutable
+        if self.is_archive_account(account_id).await? {
+            return Ok(Err(SetError::forbidden().with_description(
+                "Messages in journal archive accounts cannot be deleted.",
+            )));
+        }
+
         // Create batch
         let mut batch = BatchBuilder::new();
         let mut changes = ChangeLogBuilder::with_change_id(0);
@@ -1058,8 +1114,12 @@ impl JMAP {
             .with_collection(Collection::Email)
             .delete_document(document_id);
 
-        // Remove last changeId
-        batch.value(Property::Cid, (), F_VALUE | F_CLEAR);
+        // Remove last changeId and mailbox dates
+        batch.value(Property::Cid, (), F_VALUE | F_CLEAR).value(
+            Property::AddedDates,
+            (),
+            F_VALUE | F_CLEAR,
+        );
 
         // Remove mailboxes
         let mailboxes = if let Some(mailboxes) = self
@@ -1232,6 +1292,33 @@ impl JMAP {
                 .delete_document(thread_id);
         }
 
+        // Under legal hold, keep a hidden tombstone of the message
+        let tombstone = if self.is_on_hold(account_id).await? {
+            if let Some(raw_message) = self
+                .get_blob(
+                    &BlobKind::LinkedMaildir {
+                        account_id,
+                        document_id,
+                    },
+                    0..u32::MAX,
+                )
+                .await?
+            {
+                Some(raw_message)
+            } else {
+                tracing::debug!(
+                    event = "error",
+                    context = "email_delete",
+                    account_id = account_id,
+                    document_id = document_id,
+                    "Failed to fetch message blob.",
+                );
+                return Ok(Err(SetError::not_found()));
+            }
+        } else {
+            None
+        };
+
         // Commit batch
         match self.store.write(batch.build()).await {
             Ok(_) => (),
@@ -1250,6 +1337,13 @@ impl JMAP {
             }
         }
 
+        // The tombstone is only written once the message is gone, and before
+        // unlinking the blob so that a failure leaves the message recoverable
+        if let Some(raw_message) = tombstone {
+            self.tombstone_message(account_id, &raw_message, "delete")
+                .await?;
+        }
+
         // Delete blob
         self.store
             .delete_blob(&BlobKind::LinkedMaildir {
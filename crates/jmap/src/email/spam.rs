@@ -0,0 +1,90 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::types::{blob::BlobId, keyword::Keyword};
+
+use crate::{mailbox::TRASH_ID, JMAP};
+
+impl JMAP {
+    pub async fn spam_train(&self, account_id: u32, document_ids: Vec<u32>, is_spam: bool) {
+        if !self.smtp.spam.bayes.enable {
+            return;
+        }
+
+        for document_id in document_ids {
+            let raw_message = match self
+                .get_blob(&BlobId::maildir(account_id, document_id).kind, 0..u32::MAX)
+                .await
+            {
+                Ok(Some(raw_message)) => raw_message,
+                Ok(None) => continue,
+                Err(_) => return,
+            };
+
+            if let Err(err) = self
+                .smtp
+                .spam_train(&self.store, account_id, &raw_message, is_spam)
+                .await
+            {
+                tracing::warn!(
+                    event = "error",
+                    context = "spam_train",
+                    account_id = account_id,
+                    document_id = document_id,
+                    error = ?err,
+                    "Failed to train spam filter.");
+            }
+        }
+    }
+
+    pub async fn mailbox_junk_id(&self, account_id: u32) -> Option<u32> {
+        self.mailbox_get_by_role(account_id, "junk")
+            .await
+            .unwrap_or_default()
+    }
+}
+
+/// Returns whether a message should be learned as spam (true) or ham (false)
+/// after its mailboxes and keywords were changed.
+pub fn spam_train_class(
+    junk_id: Option<u32>,
+    added_mailboxes: &[u32],
+    removed_mailboxes: &[u32],
+    added_keywords: &[Keyword],
+) -> Option<bool> {
+    if added_keywords.contains(&Keyword::Junk) {
+        Some(true)
+    } else if added_keywords.contains(&Keyword::NotJunk) {
+        Some(false)
+    } else if let Some(junk_id) = junk_id {
+        if added_mailboxes.contains(&junk_id) {
+            Some(true)
+        } else if removed_mailboxes.contains(&junk_id) && !added_mailboxes.contains(&TRASH_ID) {
+            Some(false)
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
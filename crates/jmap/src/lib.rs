@@ -23,6 +23,7 @@
 
 use std::{collections::hash_map::RandomState, sync::Arc, time::Duration};
 
+use crate::sieve::{lists::PERSONAL_LIST_PREFIX, notify::NotifyMethod};
 use ::sieve::{Compiler, Runtime};
 use api::session::BaseCapabilities;
 use auth::{
@@ -31,7 +32,7 @@ use auth::{
     AccessToken,
 };
 use dashmap::DashMap;
-use directory::{Directory, DirectoryConfig};
+use directory::{Directory, DirectoryConfig, Lookup};
 use jmap_proto::{
     error::method::MethodError,
     method::{
@@ -40,6 +41,8 @@ use jmap_proto::{
     },
     types::{collection::Collection, property::Property},
 };
+use mailbox::retention::RetentionRule;
+use push::vapid::VapidKey;
 use services::{
     delivery::spawn_delivery_manager,
     housekeeper::{self, init_housekeeper, spawn_housekeeper},
@@ -47,6 +50,8 @@ use services::{
 };
 use smtp::core::SMTP;
 use store::{
+    ahash::AHashMap,
+    audit::AuditLog,
     fts::Language,
     parking_lot::Mutex,
     query::{sort::Pagination, Comparator, Filter, ResultSet, SortedResultSet},
@@ -81,7 +86,7 @@ pub mod websocket;
 pub const LONG_SLUMBER: Duration = Duration::from_secs(60 * 60 * 24);
 
 pub struct JMAP {
-    pub store: Store,
+    pub store: Arc<Store>,
     pub config: Config,
     pub directory: Arc<dyn Directory>,
 
@@ -98,7 +103,11 @@ pub struct JMAP {
     pub smtp: Arc<SMTP>,
 
     pub sieve_compiler: Compiler,
+    pub sieve_lookups: AHashMap<String, Arc<Lookup>>,
     pub sieve_runtime: Runtime,
+
+    pub vapid_key: Option<Arc<VapidKey>>,
+    pub audit: AuditLog,
 }
 
 pub struct Config {
@@ -128,6 +137,11 @@ pub struct Config {
 
     pub sieve_max_script_name: usize,
     pub sieve_max_scripts: usize,
+    pub sieve_notify_rate: Rate,
+    pub sieve_notify_methods: AHashMap<String, NotifyMethod>,
+    pub sieve_personal_lists: Vec<String>,
+    pub sieve_list_max_items: usize,
+    pub sieve_list_max_item_size: usize,
 
     pub session_cache_ttl: Duration,
     pub rate_authenticated: Rate,
@@ -155,6 +169,12 @@ pub struct Config {
 
     pub principal_allow_lookups: bool,
 
+    pub retention_rules: AHashMap<String, RetentionRule>,
+    pub retention_batch_size: usize,
+
+    pub journal_archive_accounts: Vec<String>,
+    pub legal_hold_compliance_group: Option<String>,
+
     pub capabilities: BaseCapabilities,
 }
 
@@ -172,6 +192,7 @@ impl JMAP {
     pub async fn init(
         config: &utils::config::Config,
         directory_config: &DirectoryConfig,
+        store: Arc<Store>,
         delivery_rx: mpsc::Receiver<DeliveryEvent>,
         smtp: Arc<SMTP>,
     ) -> Result<Arc<Self>, String> {
@@ -183,6 +204,39 @@ impl JMAP {
             .unwrap_or(32)
             .next_power_of_two() as usize;
 
+        // Obtain the VAPID key used to authenticate Web Push requests
+        let mut jmap_config = Config::new(config).failed("Invalid configuration file");
+        let vapid_key = VapidKey::init(&store, config)
+            .await
+            .failed("Failed to initialize VAPID key")
+            .map(Arc::new);
+        if let Some(vapid_key) = &vapid_key {
+            jmap_config.add_vapid_capability(vapid_key);
+        }
+
+        // Obtain the lookups that user scripts are allowed to query
+        let mut sieve_lookups = AHashMap::new();
+        for (_, name) in config.values("jmap.sieve.lists.lookups") {
+            sieve_lookups.insert(
+                name.to_string(),
+                directory_config
+                    .lookups
+                    .get(name)
+                    .ok_or_else(|| format!("Unable to find lookup {name:?}."))?
+                    .clone(),
+            );
+        }
+        let sieve_lists = sieve_lookups
+            .keys()
+            .cloned()
+            .chain(
+                jmap_config
+                    .sieve_personal_lists
+                    .iter()
+                    .map(|name| format!("{PERSONAL_LIST_PREFIX}{name}")),
+            )
+            .collect::<Vec<_>>();
+
         let jmap_server = Arc::new(JMAP {
             directory: directory_config
                 .directories
@@ -192,8 +246,8 @@ impl JMAP {
                     config.value_require("jmap.directory")?
                 ))
                 .clone(),
-            store: Store::open(config).await.failed("Unable to open database"),
-            config: Config::new(config).failed("Invalid configuration file"),
+            store,
+            config: jmap_config,
             sessions: TtlDashMap::with_capacity(
                 config.property("jmap.session.cache.size")?.unwrap_or(100),
                 shard_amount,
@@ -269,7 +323,9 @@ impl JMAP {
                         .property("jmap.sieve.limits.header-size")?
                         .unwrap_or(1024),
                 )
-                .with_max_includes(config.property("jmap.sieve.limits.includes")?.unwrap_or(3)),
+                .with_max_includes(config.property("jmap.sieve.limits.includes")?.unwrap_or(3))
+                .with_valid_ext_lists(sieve_lists),
+            sieve_lookups,
             sieve_runtime: Runtime::new()
                 .with_max_nested_includes(
                     config
@@ -323,7 +379,7 @@ impl JMAP {
                     if !values.is_empty() {
                         values
                     } else {
-                        vec!["mailto".to_string()]
+                        vec!["mailto".to_string(), "push".to_string()]
                     }
                 })
                 .with_protected_headers({
@@ -358,6 +414,8 @@ impl JMAP {
                 .with_env_variable("version", env!("CARGO_PKG_VERSION"))
                 .with_env_variable("location", "MS")
                 .with_env_variable("phase", "during"),
+            vapid_key,
+            audit: AuditLog::parse(config)?,
         });
 
         // Spawn delivery manager
@@ -78,6 +78,7 @@ impl JMAP {
                     | Property::ParentId
                     | Property::Role
                     | Property::SortOrder
+                    | Property::RetentionDays
                     | Property::Acl
                     | Property::MyRights
             )
@@ -125,7 +126,9 @@ impl JMAP {
             for property in &properties {
                 let value = match property {
                     Property::Id => Value::Id(id),
-                    Property::Name | Property::Role => values.remove(property),
+                    Property::Name | Property::Role | Property::RetentionDays => {
+                        values.remove(property)
+                    }
                     Property::SortOrder => values
                         .properties
                         .remove(property)
@@ -0,0 +1,365 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use jmap_proto::{
+    error::method::MethodError,
+    object::Object,
+    types::{
+        collection::Collection, id::Id, property::Property, state::StateChange,
+        type_state::TypeState, value::Value,
+    },
+};
+use store::{
+    ahash::AHashMap,
+    query::Filter,
+    write::{assert::HashedValue, log::ChangeLogBuilder, now, BatchBuilder, F_VALUE},
+};
+use utils::config::{
+    utils::{AsKey, ParseValue},
+    Config,
+};
+
+use crate::{
+    email::{added_dates::AddedDates, set::TagManager},
+    JMAP,
+};
+
+pub const MAX_RETENTION_DAYS: u64 = 36500;
+
+#[derive(Debug, Clone)]
+pub struct RetentionRule {
+    pub expire: Duration,
+    pub action: RetentionAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionAction {
+    Delete,
+    Archive,
+}
+
+impl JMAP {
+    pub async fn purge_expired_messages(&self) -> Result<(), MethodError> {
+        for account_id in self
+            .get_document_ids(u32::MAX, Collection::Principal)
+            .await?
+            .unwrap_or_default()
+        {
+            // Accounts under legal hold are exempt from retention policies
+            match self.is_on_hold(account_id).await {
+                Ok(false) => (),
+                Ok(true) => {
+                    tracing::debug!(
+                        context = "retention",
+                        event = "skip",
+                        account_id = account_id,
+                        "Account is under legal hold, skipping retention policies."
+                    );
+                    continue;
+                }
+                Err(err) => {
+                    tracing::error!(
+                        context = "retention",
+                        event = "error",
+                        account_id = account_id,
+                        "Failed to obtain legal hold status: {}",
+                        err
+                    );
+                    continue;
+                }
+            }
+
+            // Errors in one account should not prevent purging the others
+            if let Err(err) = self.account_purge_expired_messages(account_id).await {
+                tracing::error!(
+                    context = "retention",
+                    event = "error",
+                    account_id = account_id,
+                    "Failed to apply retention policies: {}",
+                    err
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn account_purge_expired_messages(&self, account_id: u32) -> Result<(), MethodError> {
+        let mailbox_ids = if let Some(mailbox_ids) = self
+            .get_document_ids(account_id, Collection::Mailbox)
+            .await?
+        {
+            mailbox_ids
+        } else {
+            return Ok(());
+        };
+        let mut archive_id = None;
+
+        for mailbox_id in mailbox_ids {
+            let mailbox = if let Some(mailbox) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::Mailbox,
+                    mailbox_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                mailbox
+            } else {
+                continue;
+            };
+
+            // Rules set by the user take precedence over the role defaults
+            let rule = match mailbox.properties.get(&Property::RetentionDays) {
+                Some(Value::UnsignedInt(days)) if *days > 0 => RetentionRule {
+                    expire: Duration::from_secs((*days).min(MAX_RETENTION_DAYS) * 86400),
+                    action: RetentionAction::Delete,
+                },
+                _ => match mailbox
+                    .properties
+                    .get(&Property::Role)
+                    .and_then(|role| role.as_string())
+                    .and_then(|role| self.config.retention_rules.get(role))
+                {
+                    Some(rule) => rule.clone(),
+                    None => continue,
+                },
+            };
+
+            // Obtain the archive mailbox
+            let destination_id = match rule.action {
+                RetentionAction::Delete => None,
+                RetentionAction::Archive => {
+                    if archive_id.is_none() {
+                        archive_id = self
+                            .filter(
+                                account_id,
+                                Collection::Mailbox,
+                                vec![Filter::eq(Property::Role, "archive")],
+                            )
+                            .await?
+                            .results
+                            .min()
+                            .into();
+                    }
+                    match archive_id.flatten() {
+                        Some(archive_id) if archive_id != mailbox_id => Some(archive_id),
+                        _ => {
+                            tracing::debug!(
+                                context = "retention",
+                                event = "skip",
+                                account_id = account_id,
+                                mailbox_id = mailbox_id,
+                                "No archive mailbox found, skipping."
+                            );
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            // Obtain expired messages, messages can't be added to a mailbox before
+            // they are received so the receivedAt index narrows down the candidates
+            let expires_before = now().saturating_sub(rule.expire.as_secs());
+            let candidate_ids = self
+                .filter(
+                    account_id,
+                    Collection::Email,
+                    vec![
+                        Filter::is_in_bitmap(Property::MailboxIds, mailbox_id),
+                        Filter::lt(Property::ReceivedAt, expires_before),
+                    ],
+                )
+                .await?
+                .results
+                .into_iter()
+                .collect::<Vec<_>>();
+            if candidate_ids.is_empty() {
+                continue;
+            }
+
+            // Expire messages by the time they entered this mailbox, messages stored
+            // before these dates were tracked fall back to their receivedAt date
+            let message_ids = candidate_ids
+                .iter()
+                .zip(
+                    self.get_properties::<AddedDates>(
+                        account_id,
+                        Collection::Email,
+                        candidate_ids.iter().copied(),
+                        Property::AddedDates,
+                    )
+                    .await?,
+                )
+                .filter(|(_, added_dates)| {
+                    added_dates
+                        .as_ref()
+                        .and_then(|dates| dates.get(mailbox_id))
+                        .map_or(true, |added_at| added_at < expires_before)
+                })
+                .map(|(message_id, _)| *message_id)
+                .collect::<Vec<_>>();
+            if message_ids.is_empty() {
+                continue;
+            }
+
+            tracing::debug!(
+                context = "retention",
+                event = "purge",
+                account_id = account_id,
+                mailbox_id = mailbox_id,
+                total = message_ids.len(),
+                "Applying retention policy."
+            );
+
+            for batch_ids in message_ids.chunks(self.config.retention_batch_size) {
+                self.purge_expired_batch(account_id, mailbox_id, destination_id, batch_ids)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn purge_expired_batch(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+        destination_id: Option<u32>,
+        message_ids: &[u32],
+    ) -> Result<(), MethodError> {
+        let mut changes = ChangeLogBuilder::new();
+
+        for &message_id in message_ids {
+            let (mut mailboxes, thread_id) = if let (Some(mailboxes), Some(thread_id)) = (
+                self.get_property::<HashedValue<Vec<u32>>>(
+                    account_id,
+                    Collection::Email,
+                    message_id,
+                    Property::MailboxIds,
+                )
+                .await?,
+                self.get_property::<u32>(
+                    account_id,
+                    Collection::Email,
+                    message_id,
+                    Property::ThreadId,
+                )
+                .await?,
+            ) {
+                (TagManager::new(mailboxes), thread_id)
+            } else {
+                continue;
+            };
+
+            if !mailboxes.current().contains(&mailbox_id) {
+                continue;
+            } else if destination_id.is_none() && mailboxes.current().len() == 1 {
+                // Delete the message if it is not present in any other mailbox
+                if let Ok(change) = self.email_delete(account_id, message_id).await? {
+                    changes.merge(change);
+                }
+                continue;
+            }
+
+            // Untag the message from the mailbox, moving it to the archive if needed
+            mailboxes.update(mailbox_id, false);
+            if let Some(destination_id) = destination_id {
+                mailboxes.update(destination_id, true);
+            }
+            if changes.change_id == u64::MAX {
+                changes.change_id = self.assign_change_id(account_id).await?;
+            }
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Email)
+                .update_document(message_id)
+                .value(Property::Cid, changes.change_id, F_VALUE);
+            self.update_added_dates(&mut batch, account_id, message_id, &mailboxes)
+                .await?;
+            mailboxes.update_batch(&mut batch, Property::MailboxIds);
+            match self.write_batch(batch).await {
+                Ok(_) => {
+                    changes.log_update(Collection::Email, Id::from_parts(thread_id, message_id));
+                    changes.log_child_update(Collection::Mailbox, mailbox_id);
+                    if let Some(destination_id) = destination_id {
+                        changes.log_child_update(Collection::Mailbox, destination_id);
+                    }
+                }
+                // The message was modified concurrently, it will be retried on the next run
+                Err(MethodError::ServerUnavailable) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        // Write changes
+        if !changes.is_empty() {
+            let change_id = self.commit_changes(account_id, changes).await?;
+            self.broadcast_state_change(
+                StateChange::new(account_id)
+                    .with_change(TypeState::Email, change_id)
+                    .with_change(TypeState::Mailbox, change_id)
+                    .with_change(TypeState::Thread, change_id),
+            )
+            .await;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn parse_retention_rules(
+    config: &Config,
+) -> utils::config::Result<AHashMap<String, RetentionRule>> {
+    let mut rules = AHashMap::new();
+    for role in config.sub_keys("jmap.retention.role") {
+        rules.insert(
+            role.to_string(),
+            RetentionRule {
+                expire: config.property_require(("jmap.retention.role", role, "expire"))?,
+                action: config
+                    .property(("jmap.retention.role", role, "action"))?
+                    .unwrap_or(RetentionAction::Delete),
+            },
+        );
+    }
+    Ok(rules)
+}
+
+impl ParseValue for RetentionAction {
+    fn parse_value(key: impl AsKey, value: &str) -> utils::config::Result<Self> {
+        match value {
+            "delete" => Ok(RetentionAction::Delete),
+            "archive" => Ok(RetentionAction::Archive),
+            _ => Err(format!(
+                "Invalid retention action {:?} for property {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
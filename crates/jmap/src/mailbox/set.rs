@@ -50,11 +50,11 @@ use store::{
 };
 
 use crate::{
-    auth::{acl::EffectiveAcl, AccessToken},
+    auth::{acl::EffectiveAcl, rate_limit::RemoteAddress, AccessToken},
     JMAP,
 };
 
-use super::{INBOX_ID, TRASH_ID};
+use super::{retention::MAX_RETENTION_DAYS, INBOX_ID, TRASH_ID};
 
 struct SetContext<'x> {
     account_id: u32,
@@ -89,6 +89,7 @@ impl JMAP {
         &self,
         mut request: SetRequest<SetArguments>,
         access_token: &AccessToken,
+        remote_addr: &RemoteAddress,
     ) -> Result<SetResponse, MethodError> {
         // Prepare response
         let account_id = request.account_id.document_id();
@@ -107,8 +108,10 @@ impl JMAP {
         // Process creates
         let mut changes = ChangeLogBuilder::new();
         'create: for (id, object) in request.unwrap_create() {
+            let acl_changed = object.properties.contains_key(&Property::Acl);
             match self.mailbox_set_item(object, None, &ctx).await? {
                 Ok(builder) => {
+                    let acl_audit = acl_changed.then(|| mailbox_acl_audit(&builder));
                     let mut batch = BatchBuilder::new();
                     let document_id = self
                         .assign_document_id(account_id, Collection::Mailbox)
@@ -121,6 +124,17 @@ impl JMAP {
                     changes.log_insert(Collection::Mailbox, document_id);
                     ctx.mailbox_ids.insert(document_id);
                     self.write_batch(batch).await?;
+                    if let Some((mailbox_name, acl)) = acl_audit {
+                        self.audit_acl_change(
+                            access_token,
+                            remote_addr,
+                            "jmap",
+                            account_id,
+                            &mailbox_name,
+                            &acl,
+                        )
+                        .await;
+                    }
                     ctx.response.created(id, document_id);
                 }
                 Err(err) => {
@@ -174,11 +188,13 @@ impl JMAP {
                     }
                 }
 
+                let acl_changed = object.properties.contains_key(&Property::Acl);
                 match self
                     .mailbox_set_item(object, (document_id, mailbox).into(), &ctx)
                     .await?
                 {
                     Ok(builder) => {
+                        let acl_audit = acl_changed.then(|| mailbox_acl_audit(&builder));
                         let mut batch = BatchBuilder::new();
                         batch
                             .with_account_id(account_id)
@@ -189,6 +205,17 @@ impl JMAP {
                             match self.store.write(batch.build()).await {
                                 Ok(_) => {
                                     changes.log_update(Collection::Mailbox, document_id);
+                                    if let Some((mailbox_name, acl)) = acl_audit {
+                                        self.audit_acl_change(
+                                            access_token,
+                                            remote_addr,
+                                            "jmap",
+                                            account_id,
+                                            &mailbox_name,
+                                            &acl,
+                                        )
+                                        .await;
+                                    }
                                 }
                                 Err(store::Error::AssertValueFailed) => {
                                     ctx.response.not_updated.append(id, SetError::forbidden().with_description(
@@ -544,6 +571,12 @@ impl JMAP {
                 (Property::SortOrder, MaybePatchValue::Value(Value::UnsignedInt(value))) => {
                     Value::UnsignedInt(value)
                 }
+                (Property::RetentionDays, MaybePatchValue::Value(Value::UnsignedInt(value)))
+                    if (1..=MAX_RETENTION_DAYS).contains(&value) =>
+                {
+                    Value::UnsignedInt(value)
+                }
+                (Property::RetentionDays, MaybePatchValue::Value(Value::Null)) => Value::Null,
                 (Property::Acl, value) => {
                     match self
                         .acl_set(&mut changes, update.as_ref().map(|(_, obj)| obj), value)
@@ -859,6 +892,17 @@ impl JMAP {
     }
 }
 
+fn mailbox_acl_audit(builder: &ObjectIndexBuilder) -> (String, Value) {
+    (
+        builder
+            .get(&Property::Name)
+            .as_string()
+            .unwrap_or_default()
+            .to_string(),
+        builder.get(&Property::Acl).clone(),
+    )
+}
+
 pub trait MailboxSubscribe {
     fn mailbox_subscribe(&self, account_id: u32, subscribed: bool) -> Option<Value>;
 }
@@ -27,17 +27,25 @@ use store::ahash::{AHashMap, AHashSet};
 use tokio::sync::mpsc;
 use utils::{config::Config, UnwrapFailure};
 
-use crate::{api::StateChangeResponse, services::IPC_CHANNEL_BUFFER, LONG_SLUMBER};
+use crate::{api::StateChangeResponse, services::IPC_CHANNEL_BUFFER, JMAP, LONG_SLUMBER};
 
-use super::{ece::ece_encrypt, EncryptionKeys, Event, PushServer, PushUpdate};
+use super::{
+    ece::ece_encrypt, vapid::VapidKey, EncryptionKeys, Event, PushResult, PushServer, PushUpdate,
+};
 
-use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE, RETRY_AFTER},
+    StatusCode,
+};
 use std::{
     collections::hash_map::Entry,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
-pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
+const RETRY_AFTER_MAX: u64 = 3600;
+
+pub fn spawn_push_manager(core: Arc<JMAP>, settings: &Config) -> mpsc::Sender<Event> {
     let (push_tx_, mut push_rx) = mpsc::channel::<Event>(IPC_CHANNEL_BUFFER);
     let push_tx = push_tx_.clone();
 
@@ -60,6 +68,8 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
         .property_or_static("jmap.push.throttle", "1s")
         .failed("Invalid configuration");
 
+    let vapid = core.vapid_key.clone();
+
     tokio::spawn(async move {
         let mut subscriptions = AHashMap::default();
         let mut last_verify: AHashMap<u32, Instant> = AHashMap::default();
@@ -98,6 +108,7 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
                                         })
                                         .unwrap_or(true)
                                     {
+                                        let vapid = vapid.clone();
                                         tokio::spawn(async move {
                                             http_request(
                                                 url,
@@ -111,6 +122,7 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
                                                     code
                                                 ),
                                                 keys,
+                                                vapid,
                                                 push_timeout,
                                             )
                                             .await;
@@ -138,6 +150,7 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
                                                 - (push_throttle + Duration::from_millis(1)),
                                             state_changes: Vec::new(),
                                             in_flight: false,
+                                            retry_after: None,
                                         });
                                     }
                                 }
@@ -154,13 +167,19 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
                                 let last_request = subscription.last_request.elapsed();
 
                                 if !subscription.in_flight
+                                    && !subscription.is_backing_off()
                                     && ((subscription.num_attempts == 0
                                         && last_request > push_throttle)
                                         || ((1..push_attempts_max)
                                             .contains(&subscription.num_attempts)
                                             && last_request > push_attempt_interval))
                                 {
-                                    subscription.send(id, push_tx.clone(), push_timeout);
+                                    subscription.send(
+                                        id,
+                                        push_tx.clone(),
+                                        vapid.clone(),
+                                        push_timeout,
+                                    );
                                     retry_ids.remove(&id);
                                 } else {
                                     retry_ids.insert(id);
@@ -177,18 +196,47 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
                         if let Some(subscription) = subscriptions.get_mut(&id) {
                             subscription.num_attempts = 0;
                             subscription.in_flight = false;
+                            subscription.retry_after = None;
                             retry_ids.remove(&id);
                         }
                     }
-                    Event::DeliveryFailure { id, state_changes } => {
+                    Event::DeliveryFailure {
+                        id,
+                        state_changes,
+                        retry_after,
+                    } => {
                         if let Some(subscription) = subscriptions.get_mut(&id) {
                             subscription.last_request = Instant::now();
                             subscription.num_attempts += 1;
                             subscription.state_changes.extend(state_changes);
                             subscription.in_flight = false;
+                            subscription.retry_after =
+                                retry_after.map(|retry_after| Instant::now() + retry_after);
                             retry_ids.insert(id);
                         }
                     }
+                    Event::DeliveryGone { id } => {
+                        // The push service no longer knows about this subscription
+                        if let Some(subscription) = subscriptions.remove(&id) {
+                            tracing::debug!(
+                                "Push subscription {} is no longer valid, destroying it.",
+                                subscription.url
+                            );
+                        }
+                        retry_ids.remove(&id);
+
+                        let core = core.clone();
+                        tokio::spawn(async move {
+                            let account_id = id.prefix_id();
+                            if core
+                                .delete_push_subscription(account_id, id.document_id())
+                                .await
+                                .is_ok()
+                            {
+                                core.update_push_subscriptions(account_id).await;
+                            }
+                        });
+                    }
                 },
                 Ok(None) => {
                     break;
@@ -207,13 +255,19 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
                             let last_request = subscription.last_request.elapsed();
 
                             if !subscription.in_flight
+                                && !subscription.is_backing_off()
                                 && ((subscription.num_attempts == 0
                                     && last_request >= push_throttle)
                                     || (subscription.num_attempts > 0
                                         && last_request >= push_attempt_interval))
                             {
                                 if subscription.num_attempts < push_attempts_max {
-                                    subscription.send(*retry_id, push_tx.clone(), push_timeout);
+                                    subscription.send(
+                                        *retry_id,
+                                        push_tx.clone(),
+                                        vapid.clone(),
+                                        push_timeout,
+                                    );
                                 } else {
                                     tracing::debug!(
                                         concat!(
@@ -224,6 +278,7 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
                                     );
                                     subscription.state_changes.clear();
                                     subscription.num_attempts = 0;
+                                    subscription.retry_after = None;
                                 }
                                 remove_ids.push(*retry_id);
                             }
@@ -255,7 +310,13 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
 }
 
 impl PushServer {
-    fn send(&mut self, id: Id, push_tx: mpsc::Sender<Event>, push_timeout: Duration) {
+    fn send(
+        &mut self,
+        id: Id,
+        push_tx: mpsc::Sender<Event>,
+        vapid: Option<Arc<VapidKey>>,
+        push_timeout: Duration,
+    ) {
         let url = self.url.clone();
         let keys = self.keys.clone();
         let state_changes = std::mem::take(&mut self.state_changes);
@@ -276,31 +337,42 @@ impl PushServer {
 
             push_tx
                 .send(
-                    if http_request(
+                    match http_request(
                         url,
                         serde_json::to_string(&response).unwrap(),
                         keys,
+                        vapid,
                         push_timeout,
                     )
                     .await
                     {
-                        Event::DeliverySuccess { id }
-                    } else {
-                        Event::DeliveryFailure { id, state_changes }
+                        PushResult::Success => Event::DeliverySuccess { id },
+                        PushResult::Failure { retry_after } => Event::DeliveryFailure {
+                            id,
+                            state_changes,
+                            retry_after,
+                        },
+                        PushResult::Gone => Event::DeliveryGone { id },
                     },
                 )
                 .await
                 .ok();
         });
     }
+
+    fn is_backing_off(&self) -> bool {
+        self.retry_after
+            .map_or(false, |retry_after| retry_after > Instant::now())
+    }
 }
 
 async fn http_request(
     url: String,
     mut body: String,
     keys: Option<EncryptionKeys>,
+    vapid: Option<Arc<VapidKey>>,
     push_timeout: Duration,
-) -> bool {
+) -> PushResult {
     let client_builder = reqwest::Client::builder().timeout(push_timeout);
 
     #[cfg(feature = "test_mode")]
@@ -313,6 +385,10 @@ async fn http_request(
         .header(CONTENT_TYPE, "application/json")
         .header("TTL", "86400");
 
+    if let Some(authorization) = vapid.and_then(|vapid| vapid.authorization(&url)) {
+        client = client.header(AUTHORIZATION, authorization);
+    }
+
     if let Some(keys) = keys {
         match ece_encrypt(&keys.p256dh, &keys.auth, body.as_bytes())
             .map(|b| general_purpose::URL_SAFE.encode(b))
@@ -324,16 +400,44 @@ async fn http_request(
             Err(err) => {
                 // Do not reattempt if encryption fails.
                 tracing::debug!("Failed to encrypt push subscription to {}: {}", url, err);
-                return true;
+                return PushResult::Success;
             }
         }
     }
 
     match client.body(body).send().await {
-        Ok(response) => response.status().is_success(),
+        Ok(response) => match response.status() {
+            status if status.is_success() => PushResult::Success,
+            StatusCode::NOT_FOUND | StatusCode::GONE => PushResult::Gone,
+            status => {
+                tracing::debug!("HTTP post to {} failed with status: {}", url, status);
+                PushResult::Failure {
+                    retry_after: response
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(parse_retry_after),
+                }
+            }
+        },
         Err(err) => {
             tracing::debug!("HTTP post to {} failed with: {}", url, err);
-            false
+            PushResult::Failure { retry_after: None }
         }
     }
 }
+
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    // Retry-After is either a number of seconds or an HTTP-date (RFC 9110, section 10.2.3)
+    let seconds = if let Ok(seconds) = value.trim().parse::<u64>() {
+        seconds
+    } else {
+        (chrono::DateTime::parse_from_rfc2822(value.trim())
+            .ok()?
+            .timestamp()
+            - chrono::Utc::now().timestamp())
+        .max(0) as u64
+    };
+
+    Some(Duration::from_secs(std::cmp::min(seconds, RETRY_AFTER_MAX)))
+}
@@ -25,8 +25,9 @@ pub mod ece;
 pub mod get;
 pub mod manager;
 pub mod set;
+pub mod vapid;
 
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use jmap_proto::types::{id::Id, state::StateChange, type_state::TypeState};
 use utils::map::bitmap::Bitmap;
@@ -72,6 +73,10 @@ pub enum Event {
     DeliveryFailure {
         id: Id,
         state_changes: Vec<StateChange>,
+        retry_after: Option<Duration>,
+    },
+    DeliveryGone {
+        id: Id,
     },
     Reset,
 }
@@ -103,4 +108,12 @@ pub struct PushServer {
     last_request: Instant,
     state_changes: Vec<StateChange>,
     in_flight: bool,
+    retry_after: Option<Instant>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PushResult {
+    Success,
+    Failure { retry_after: Option<Duration> },
+    Gone,
 }
@@ -191,14 +191,8 @@ impl JMAP {
         for id in will_destroy {
             let document_id = id.document_id();
             if push_ids.contains(document_id) {
-                // Update record
-                let mut batch = BatchBuilder::new();
-                batch
-                    .with_account_id(account_id)
-                    .with_collection(Collection::PushSubscription)
-                    .delete_document(document_id)
-                    .value(Property::Value, (), F_VALUE | F_CLEAR);
-                self.write_batch(batch).await?;
+                self.delete_push_subscription(account_id, document_id)
+                    .await?;
                 response.destroyed.push(id);
             } else {
                 response.not_destroyed.append(id, SetError::not_found());
@@ -212,6 +206,20 @@ impl JMAP {
 
         Ok(response)
     }
+
+    pub async fn delete_push_subscription(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> Result<(), MethodError> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::PushSubscription)
+            .delete_document(document_id)
+            .value(Property::Value, (), F_VALUE | F_CLEAR);
+        self.write_batch(batch).await
+    }
 }
 
 fn validate_push_value(
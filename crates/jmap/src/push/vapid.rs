@@ -0,0 +1,177 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use base64::{engine::general_purpose, Engine};
+use p256::{
+    ecdsa::{signature::Signer, Signature, SigningKey},
+    elliptic_curve::{rand_core::OsRng, sec1::ToEncodedPoint},
+};
+use store::{
+    write::{key::KeySerializer, now, BatchBuilder, Operation, ValueClass},
+    CustomValueKey, Serialize, ServerNamespace, Store,
+};
+use utils::config::Config;
+
+const VAPID_KEY_NAMESPACE: u8 = ServerNamespace::Vapid as u8;
+const VAPID_MAX_EXPIRY: u64 = 24 * 3600;
+
+pub struct VapidKey {
+    signing_key: SigningKey,
+    public_key: String,
+    subject: String,
+    expiry: u64,
+}
+
+impl VapidKey {
+    pub async fn init(store: &Store, settings: &Config) -> Result<Option<Self>, String> {
+        if !settings.property_or_static::<bool>("jmap.push.vapid.enable", "true")? {
+            return Ok(None);
+        }
+
+        // Use the configured key, or obtain/generate one from the store
+        let signing_key = if let Some(key) = settings.text_file_contents("jmap.push.vapid.key")? {
+            general_purpose::URL_SAFE_NO_PAD
+                .decode(key.trim().trim_end_matches('='))
+                .ok()
+                .and_then(|key| SigningKey::from_slice(&key).ok())
+                .ok_or_else(|| {
+                    "Invalid VAPID private key found in 'jmap.push.vapid.key'.".to_string()
+                })?
+        } else {
+            load_or_generate_key(store).await?
+        };
+
+        let subject = settings
+            .value("jmap.push.vapid.subject")
+            .map(|subject| subject.to_string())
+            .unwrap_or_else(|| {
+                format!(
+                    "mailto:postmaster@{}",
+                    settings.value("server.hostname").unwrap_or("localhost")
+                )
+            });
+        if !subject.starts_with("mailto:") && !subject.starts_with("https://") {
+            return Err(format!(
+                "VAPID subject {subject:?} must be either a 'mailto:' or 'https:' URI."
+            ));
+        }
+
+        Ok(Some(VapidKey {
+            public_key: general_purpose::URL_SAFE_NO_PAD.encode(
+                signing_key
+                    .verifying_key()
+                    .to_encoded_point(false)
+                    .as_bytes(),
+            ),
+            signing_key,
+            subject,
+            expiry: std::cmp::min(
+                settings
+                    .property_or_static::<Duration>("jmap.push.vapid.expiry", "12h")?
+                    .as_secs(),
+                VAPID_MAX_EXPIRY,
+            ),
+        }))
+    }
+
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    pub fn authorization(&self, url: &str) -> Option<String> {
+        // The audience is the origin of the push resource (RFC 8292, section 2)
+        let audience = reqwest::Url::parse(url)
+            .ok()?
+            .origin()
+            .ascii_serialization();
+        let header = general_purpose::URL_SAFE_NO_PAD.encode(r#"{"typ":"JWT","alg":"ES256"}"#);
+        let claims = general_purpose::URL_SAFE_NO_PAD.encode(
+            serde_json::json!({
+                "aud": audience,
+                "exp": now() + self.expiry,
+                "sub": self.subject,
+            })
+            .to_string(),
+        );
+        let token = format!("{header}.{claims}");
+        let signature: Signature = self.signing_key.sign(token.as_bytes());
+
+        Some(format!(
+            "vapid t={}.{}, k={}",
+            token,
+            general_purpose::URL_SAFE_NO_PAD.encode(signature.to_bytes()),
+            self.public_key
+        ))
+    }
+}
+
+async fn load_or_generate_key(store: &Store) -> Result<SigningKey, String> {
+    let key = KeySerializer::new(std::mem::size_of::<u32>() + 6)
+        .write(u32::MAX)
+        .write(VAPID_KEY_NAMESPACE)
+        .write("vapid")
+        .finalize();
+
+    for _ in 0..3 {
+        if let Some(secret) = store
+            .get_value::<String>(CustomValueKey { value: key.clone() })
+            .await
+            .map_err(|err| format!("Failed to read VAPID key from store: {err}"))?
+        {
+            return general_purpose::URL_SAFE_NO_PAD
+                .decode(secret)
+                .ok()
+                .and_then(|secret| SigningKey::from_slice(&secret).ok())
+                .ok_or_else(|| "Corrupted VAPID key found in store.".to_string());
+        }
+
+        // Generate a new key, making sure another node did not store one first
+        let signing_key = SigningKey::random(&mut OsRng);
+        let mut batch = BatchBuilder::new();
+        batch
+            .assert_value(ValueClass::Custom { bytes: key.clone() }, ())
+            .op(Operation::Value {
+                class: ValueClass::Custom { bytes: key.clone() },
+                set: general_purpose::URL_SAFE_NO_PAD
+                    .encode(signing_key.to_bytes())
+                    .serialize()
+                    .into(),
+            });
+        match store.write(batch.build()).await {
+            Ok(_) => {
+                tracing::info!(
+                    context = "vapid",
+                    event = "generate",
+                    "Generated new VAPID key pair."
+                );
+                return Ok(signing_key);
+            }
+            Err(store::Error::AssertValueFailed) => continue,
+            Err(err) => return Err(format!("Failed to write VAPID key to store: {err}")),
+        }
+    }
+
+    Err("Failed to obtain VAPID key from store.".to_string())
+}
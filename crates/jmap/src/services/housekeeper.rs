@@ -38,6 +38,7 @@ pub enum Event {
     PurgeDb,
     PurgeBlobs,
     PurgeSessions,
+    PurgeExpired,
     Exit,
 }
 
@@ -50,6 +51,8 @@ enum SimpleCron {
 const TASK_PURGE_DB: usize = 0;
 const TASK_PURGE_BLOBS: usize = 1;
 const TASK_PURGE_SESSIONS: usize = 2;
+const TASK_PURGE_EXPIRED: usize = 3;
+const TASK_QUARANTINE_DIGEST: usize = 4;
 
 pub fn spawn_housekeeper(core: Arc<JMAP>, settings: &Config, mut rx: mpsc::Receiver<Event>) {
     let purge_db_at =
@@ -64,6 +67,16 @@ pub fn spawn_housekeeper(core: Arc<JMAP>, settings: &Config, mut rx: mpsc::Recei
             .value("jmap.purge.schedule.sessions")
             .unwrap_or("15 * *"),
     );
+    let purge_expired_at = SimpleCron::parse(
+        settings
+            .value("jmap.purge.schedule.retention")
+            .unwrap_or("45 3 *"),
+    );
+    let quarantine_digest_at = SimpleCron::parse(
+        settings
+            .value("quarantine.digest.schedule")
+            .unwrap_or("0 8 *"),
+    );
 
     tokio::spawn(async move {
         tracing::debug!("Housekeeper task started.");
@@ -72,8 +85,10 @@ pub fn spawn_housekeeper(core: Arc<JMAP>, settings: &Config, mut rx: mpsc::Recei
                 purge_db_at.time_to_next(),
                 purge_blobs_at.time_to_next(),
                 purge_cache.time_to_next(),
+                purge_expired_at.time_to_next(),
+                quarantine_digest_at.time_to_next(),
             ];
-            let mut tasks_to_run = [false, false, false];
+            let mut tasks_to_run = [false, false, false, false, false];
             let start_time = Instant::now();
 
             match tokio::time::timeout(time_to_next.iter().min().copied().unwrap(), rx.recv()).await
@@ -82,6 +97,7 @@ pub fn spawn_housekeeper(core: Arc<JMAP>, settings: &Config, mut rx: mpsc::Recei
                     Event::PurgeDb => tasks_to_run[TASK_PURGE_DB] = true,
                     Event::PurgeBlobs => tasks_to_run[TASK_PURGE_BLOBS] = true,
                     Event::PurgeSessions => tasks_to_run[TASK_PURGE_SESSIONS] = true,
+                    Event::PurgeExpired => tasks_to_run[TASK_PURGE_EXPIRED] = true,
                     Event::Exit => {
                         tracing::debug!("Housekeeper task exiting.");
                         return;
@@ -117,6 +133,27 @@ pub fn spawn_housekeeper(core: Arc<JMAP>, settings: &Config, mut rx: mpsc::Recei
                             if let Err(err) = core.store.purge_bitmaps().await {
                                 tracing::error!("Error while purging bitmaps: {}", err);
                             }
+                            if let Err(err) = core.smtp.greylist_purge(&core.store).await {
+                                tracing::error!("Error while purging greylist: {}", err);
+                            }
+                            if let Err(err) = core.smtp.suppression_purge(&core.store).await {
+                                tracing::error!("Error while purging suppression list: {}", err);
+                            }
+                            if let Err(err) = core.smtp.incoming_report_purge(&core.store).await {
+                                tracing::error!("Error while purging incoming reports: {}", err);
+                            }
+                            if let Err(err) = core.smtp.quarantine_purge(&core.store).await {
+                                tracing::error!("Error while purging quarantine: {}", err);
+                            }
+                            if let Err(err) = core.smtp.webhook_purge(&core.store).await {
+                                tracing::error!("Error while purging webhook outbox: {}", err);
+                            }
+                            if let Err(err) = core.store.purge_audit_log().await {
+                                tracing::error!("Error while purging audit log: {}", err);
+                            }
+                            if let Err(err) = core.store.purge_released_tombstones().await {
+                                tracing::error!("Error while purging tombstones: {}", err);
+                            }
                         }
                         TASK_PURGE_BLOBS => {
                             tracing::info!("Purging temporary blobs.",);
@@ -136,6 +173,16 @@ pub fn spawn_housekeeper(core: Arc<JMAP>, settings: &Config, mut rx: mpsc::Recei
                             core.rate_limit_unauth
                                 .retain(|_, limiter| limiter.lock().is_active());
                         }
+                        TASK_PURGE_EXPIRED => {
+                            tracing::info!("Applying mailbox retention policies.");
+                            if let Err(err) = core.purge_expired_messages().await {
+                                tracing::error!("Error while applying retention policies: {}", err);
+                            }
+                        }
+                        TASK_QUARANTINE_DIGEST => {
+                            tracing::info!("Sending quarantine digests.");
+                            core.smtp.quarantine_send_digests().await;
+                        }
                         _ => unreachable!(),
                     }
                 });
@@ -21,8 +21,9 @@
  * for more details.
 */
 
-use jmap_proto::types::{state::StateChange, type_state::TypeState};
+use jmap_proto::types::{keyword::Keyword, state::StateChange, type_state::TypeState};
 use mail_parser::Message;
+use smtp::core::spam::SpamStatus;
 use store::ahash::AHashMap;
 use utils::ipc::{DeliveryResult, IngestMessage};
 
@@ -42,6 +43,15 @@ impl JMAP {
             }
         };
 
+        // Messages flagged by the spam filter are filed into the Junk mailbox,
+        // the verdict is adjusted with the Bayes model of each recipient
+        let spam_status = SpamStatus::parse(&raw_message);
+        let bayes_message = if spam_status.is_some() && self.smtp.spam.bayes.enable {
+            Message::parse(&raw_message)
+        } else {
+            None
+        };
+
         // Obtain the UIDs for each recipient
         let mut recipients = Vec::with_capacity(message.recipients.len());
         let mut deliver_names = AHashMap::with_capacity(message.recipients.len());
@@ -95,13 +105,32 @@ impl JMAP {
                         }
                     };
 
+                    let is_spam = match (&spam_status, &bayes_message) {
+                        (Some(status), Some(bayes_message)) => self
+                            .smtp
+                            .bayes_rescore(&self.store, uid, bayes_message, status)
+                            .await
+                            .unwrap_or_default()
+                            .unwrap_or(status.is_spam),
+                        (Some(status), None) => status.is_spam,
+                        (None, _) => false,
+                    };
+                    let (mailbox_id, keywords) = if is_spam {
+                        (
+                            self.mailbox_junk_id(uid).await.unwrap_or(INBOX_ID),
+                            vec![Keyword::Junk],
+                        )
+                    } else {
+                        (INBOX_ID, vec![])
+                    };
+
                     self.email_ingest(IngestEmail {
                         raw_message: &raw_message,
                         message: Message::parse(&raw_message),
                         account_id: uid,
                         account_quota,
-                        mailbox_ids: vec![INBOX_ID],
-                        keywords: vec![],
+                        mailbox_ids: vec![mailbox_id],
+                        keywords,
                         received_at: None,
                         skip_duplicates: true,
                         encrypt: self.config.encrypt,
@@ -92,7 +92,7 @@ pub fn spawn_state_manager(
     settings: &Config,
     mut change_rx: mpsc::Receiver<Event>,
 ) {
-    let push_tx = spawn_push_manager(settings);
+    let push_tx = spawn_push_manager(core.clone(), settings);
 
     tokio::spawn(async move {
         let mut subscribers: AHashMap<u32, AHashMap<u32, Subscriber>> = AHashMap::default();
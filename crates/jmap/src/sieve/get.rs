@@ -185,7 +185,7 @@ impl JMAP {
         }
     }
 
-    async fn sieve_script_compile(
+    pub async fn sieve_script_compile(
         &self,
         account_id: u32,
         document_id: u32,
@@ -25,17 +25,22 @@ use std::borrow::Cow;
 
 use jmap_proto::types::{collection::Collection, id::Id, keyword::Keyword, property::Property};
 use mail_parser::Message;
-use sieve::{Envelope, Event, Input, Mailbox, Recipient};
+use sieve::{runtime::RuntimeError, Envelope, Event, Input, Mailbox, Recipient};
 use smtp::core::{NullIo, Session, SessionAddress};
 use store::{
     ahash::AHashSet,
+    roaring::RoaringBitmap,
     write::{now, BatchBuilder, F_VALUE},
 };
 
 use crate::{
     email::ingest::{IngestEmail, IngestedEmail},
     mailbox::{INBOX_ID, TRASH_ID},
-    sieve::SeenIdHash,
+    sieve::{
+        notify::{is_notification, NotifyTrigger},
+        run::SieveSink,
+        SeenIdHash, SeenIds,
+    },
     Bincode, IngestError, JMAP,
 };
 
@@ -74,6 +79,9 @@ impl JMAP {
             .await
             .map_err(|_| IngestError::Temporary)?;
 
+        // Keep the details required by notifications before handing the message over
+        let notify_trigger = NotifyTrigger::parse(&message, raw_message);
+
         // Create Sieve instance
         let mut instance = self.sieve_runtime.filter_parsed(message);
 
@@ -110,316 +118,76 @@ impl JMAP {
         instance.set_envelope(Envelope::From, envelope_from);
         instance.set_envelope(Envelope::To, envelope_to);
 
-        let mut input = Input::script(active_script.script_name, active_script.script.clone());
-
-        let mut do_discard = false;
-        let mut do_deliver = false;
-
-        let mut new_ids = AHashSet::new();
-        let mut reject_reason = None;
-        let mut messages: Vec<SieveMessage> = vec![SieveMessage {
-            raw_message: raw_message.into(),
-            file_into: Vec::new(),
-            flags: Vec::new(),
-        }];
-        let now = now();
+        // Run script
+        let mut sink = IngestSink {
+            jmap: self,
+            account_id,
+            mailbox_ids: &mailbox_ids,
+            mail_from: &mail_from,
+            notify_trigger: &notify_trigger,
+            seen_ids: &active_script.seen_ids,
+            now: now(),
+            do_discard: false,
+            do_deliver: false,
+            new_ids: AHashSet::new(),
+            reject_reason: None,
+            change_id: None,
+            messages: vec![SieveMessage {
+                raw_message: raw_message.into(),
+                file_into: Vec::new(),
+                flags: Vec::new(),
+            }],
+        };
+        self.sieve_run(
+            &mut instance,
+            Input::script(active_script.script_name, active_script.script.clone()),
+            account_id,
+            &mailbox_ids,
+            &mut sink,
+        )
+        .await;
+        let IngestSink {
+            do_discard,
+            do_deliver,
+            new_ids,
+            reject_reason,
+            change_id,
+            mut messages,
+            ..
+        } = sink;
         let mut ingested_message = IngestedEmail {
             id: Id::default(),
-            change_id: u64::MAX,
+            change_id: change_id.unwrap_or(u64::MAX),
             blob_id: Default::default(),
             size: raw_message.len(),
         };
 
-        while let Some(event) = instance.run(input) {
-            match event {
-                Ok(event) => match event {
-                    Event::IncludeScript { name, .. } => {
-                        if let Ok(Some(script)) =
-                            self.sieve_script_get_by_name(account_id, &name).await
-                        {
-                            input = Input::script(name, script);
-                        } else {
-                            input = false.into();
-                        }
-                    }
-                    Event::MailboxExists {
-                        mailboxes,
-                        special_use,
-                    } => {
-                        if !mailboxes.is_empty() {
-                            let mut special_use_ids = Vec::with_capacity(special_use.len());
-                            for role in special_use {
-                                special_use_ids.push(if role.eq_ignore_ascii_case("inbox") {
-                                    INBOX_ID
-                                } else if role.eq_ignore_ascii_case("trash") {
-                                    TRASH_ID
-                                } else {
-                                    let mut mailbox_id = u32::MAX;
-                                    let role = role.to_ascii_lowercase();
-                                    if is_valid_role(&role) {
-                                        if let Ok(Some(mailbox_id_)) =
-                                            self.mailbox_get_by_role(account_id, &role).await
-                                        {
-                                            mailbox_id = mailbox_id_;
-                                        }
-                                    }
-                                    mailbox_id
-                                });
-                            }
-
-                            let mut result = true;
-                            for mailbox in mailboxes {
-                                match mailbox {
-                                    Mailbox::Name(name) => {
-                                        if !matches!(
-                                            self.mailbox_get_by_name(account_id, &name).await,
-                                            Ok(Some(document_id)) if special_use_ids.is_empty() ||
-                                            special_use_ids.contains(&document_id)
-                                        ) {
-                                            result = false;
-                                            break;
-                                        }
-                                    }
-                                    Mailbox::Id(id) => {
-                                        if !matches!(Id::from_bytes(id.as_bytes()), Some(id) if
-                                                            mailbox_ids.contains(id.document_id()) &&
-                                                            (special_use_ids.is_empty() ||
-                                                            special_use_ids.contains(&id.document_id())))
-                                        {
-                                            result = false;
-                                            break;
-                                        }
-                                    }
-                                }
-                            }
-                            input = result.into();
-                        } else if !special_use.is_empty() {
-                            let mut result = true;
-
-                            for role in special_use {
-                                if !role.eq_ignore_ascii_case("inbox")
-                                    && !role.eq_ignore_ascii_case("trash")
-                                {
-                                    let role = role.to_ascii_lowercase();
-                                    if !is_valid_role(&role)
-                                        || !matches!(
-                                            self.mailbox_get_by_role(account_id, &role).await,
-                                            Ok(Some(_))
-                                        )
-                                    {
-                                        result = false;
-                                        break;
-                                    }
-                                }
-                            }
-                            input = result.into();
-                        } else {
-                            input = false.into();
-                        }
-                    }
-                    Event::DuplicateId { id, expiry, last } => {
-                        let id_hash = SeenIdHash::new(&id, expiry + now);
-                        let seen_id = active_script.seen_ids.ids.contains(&id_hash);
-                        if !seen_id || last {
-                            new_ids.insert(id_hash);
-                        }
-
-                        input = seen_id.into();
-                    }
-                    Event::Discard => {
-                        do_discard = true;
-                        input = true.into();
-                    }
-                    Event::Reject { reason, .. } => {
-                        reject_reason = reason.into();
-                        do_discard = true;
-                        input = true.into();
-                    }
-                    Event::Keep { flags, message_id } => {
-                        if let Some(message) = messages.get_mut(message_id) {
-                            message.flags = flags.into_iter().map(Keyword::from).collect();
-                            if !message.file_into.contains(&INBOX_ID) {
-                                message.file_into.push(INBOX_ID);
-                            }
-                            do_deliver = true;
-                        } else {
-                            tracing::error!(
-                                context = "sieve_script_ingest",
-                                event = "error",
-                                "Unknown message id {}.",
-                                message_id
-                            );
-                        }
-                        input = true.into();
-                    }
-                    Event::FileInto {
-                        folder,
-                        flags,
-                        mailbox_id,
-                        special_use,
-                        create,
-                        message_id,
-                    } => {
-                        let mut target_id = u32::MAX;
-
-                        // Find mailbox by Id
-                        if let Some(mailbox_id) =
-                            mailbox_id.and_then(|m| Id::from_bytes(m.as_bytes()))
-                        {
-                            let mailbox_id = mailbox_id.document_id();
-                            if mailbox_ids.contains(mailbox_id) {
-                                target_id = mailbox_id;
-                            }
-                        }
-
-                        // Find mailbox by role
-                        if let Some(special_use) = special_use {
-                            if target_id == u32::MAX {
-                                if special_use.eq_ignore_ascii_case("inbox") {
-                                    target_id = INBOX_ID;
-                                } else if special_use.eq_ignore_ascii_case("trash") {
-                                    target_id = TRASH_ID;
-                                } else {
-                                    let role = special_use.to_ascii_lowercase();
-                                    if is_valid_role(&role) {
-                                        if let Ok(Some(mailbox_id_)) =
-                                            self.mailbox_get_by_role(account_id, &role).await
-                                        {
-                                            target_id = mailbox_id_;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-
-                        // Find mailbox by name
-                        if target_id == u32::MAX {
-                            if !create {
-                                if let Ok(Some(document_id)) =
-                                    self.mailbox_get_by_name(account_id, &folder).await
-                                {
-                                    target_id = document_id;
-                                }
-                            } else if let Ok(Some((document_id, changes))) =
-                                self.mailbox_create_path(account_id, &folder).await
-                            {
-                                target_id = document_id;
-                                if let Some(change_id) = changes {
-                                    ingested_message.change_id = change_id;
-                                }
-                            }
-                        }
-
-                        // Default to Inbox
-                        if target_id == u32::MAX {
-                            target_id = INBOX_ID;
-                        }
-
-                        if let Some(message) = messages.get_mut(message_id) {
-                            message.flags = flags.into_iter().map(Keyword::from).collect();
-                            if !message.file_into.contains(&target_id) {
-                                message.file_into.push(target_id);
-                            }
-                            do_deliver = true;
-                        } else {
-                            tracing::error!(
-                                context = "sieve_script_ingest",
-                                event = "error",
-                                "Unknown message id {}.",
-                                message_id
-                            );
-                        }
-                        input = true.into();
-                    }
-                    Event::SendMessage {
-                        recipient,
-                        message_id,
-                        ..
-                    } => {
-                        input = true.into();
-                        if let Some(message) = messages.get(message_id) {
-                            if message.raw_message.len() <= self.config.mail_max_size {
-                                let result = Session::<NullIo>::sieve(
-                                    self.smtp.clone(),
-                                    SessionAddress::new(mail_from.clone()),
-                                    match recipient {
-                                        Recipient::Address(rcpt) => vec![SessionAddress::new(rcpt)],
-                                        Recipient::Group(rcpts) => {
-                                            rcpts.into_iter().map(SessionAddress::new).collect()
-                                        }
-                                        Recipient::List(_) => {
-                                            // Not yet implemented
-                                            continue;
-                                        }
-                                    },
-                                    message.raw_message.to_vec(),
-                                )
-                                .queue_message()
-                                .await;
-
-                                tracing::debug!(
-                                    context = "sieve_script_ingest",
-                                    event = "send_message",
-                                    smtp_response = std::str::from_utf8(&result).unwrap()
-                                );
-                            } else {
-                                tracing::warn!(
-                                    context = "sieve_script_ingest",
-                                    event = "message_too_large",
-                                    from = mail_from.as_str(),
-                                    size = message.raw_message.len(),
-                                    max_size = self.config.mail_max_size
-                                );
-                            }
-                        } else {
-                            tracing::error!(
-                                context = "sieve_script_ingest",
-                                event = "error",
-                                "Unknown message id {}.",
-                                message_id
-                            );
-                            continue;
-                        }
-                    }
-                    Event::ListContains { .. }
-                    | Event::Execute { .. }
-                    | Event::Notify { .. }
-                    | Event::SetEnvelope { .. } => {
-                        // Not allowed
-                        input = false.into();
-                    }
-                    Event::CreatedMessage { message, .. } => {
-                        messages.push(SieveMessage {
-                            raw_message: message.into(),
-                            file_into: Vec::new(),
-                            flags: Vec::new(),
-                        });
-                        input = true.into();
-                    }
-                },
-
-                #[cfg(feature = "test_mode")]
-                Err(sieve::runtime::RuntimeError::ScriptErrorMessage(err)) => {
-                    panic!("Sieve test failed: {}", err);
-                }
-
-                Err(err) => {
-                    tracing::debug!(
-                        context = "sieve_script_ingest",
-                        event = "error",
-                        reason = %err,
-                        "Runtime error",
-                    );
-                    input = true.into();
-                }
-            }
-        }
-
         // Fail-safe, no discard and no keep seen, assume that something went wrong and file anyway.
         if !do_deliver && !do_discard {
             messages[0].file_into.push(INBOX_ID);
         }
 
+        // Under legal hold, messages discarded by the script are kept as tombstones
+        if do_discard
+            && messages[0].file_into.is_empty()
+            && self
+                .is_on_hold(account_id)
+                .await
+                .map_err(|_| IngestError::Temporary)?
+        {
+            self.tombstone_message(
+                account_id,
+                raw_message,
+                if reject_reason.is_some() {
+                    "sieve.reject"
+                } else {
+                    "sieve.discard"
+                },
+            )
+            .await
+            .map_err(|_| IngestError::Temporary)?;
+        }
+
         // Deliver messages
         let mut last_temp_error = None;
         let mut has_delivered = false;
@@ -493,6 +261,311 @@ impl JMAP {
             Err(last_temp_error.unwrap())
         }
     }
+
+    pub async fn sieve_mailbox_exists(
+        &self,
+        account_id: u32,
+        mailbox_ids: &RoaringBitmap,
+        mailboxes: Vec<Mailbox>,
+        special_use: Vec<String>,
+    ) -> bool {
+        if !mailboxes.is_empty() {
+            let mut special_use_ids = Vec::with_capacity(special_use.len());
+            for role in special_use {
+                special_use_ids.push(if role.eq_ignore_ascii_case("inbox") {
+                    INBOX_ID
+                } else if role.eq_ignore_ascii_case("trash") {
+                    TRASH_ID
+                } else {
+                    let mut mailbox_id = u32::MAX;
+                    let role = role.to_ascii_lowercase();
+                    if is_valid_role(&role) {
+                        if let Ok(Some(mailbox_id_)) =
+                            self.mailbox_get_by_role(account_id, &role).await
+                        {
+                            mailbox_id = mailbox_id_;
+                        }
+                    }
+                    mailbox_id
+                });
+            }
+
+            let mut result = true;
+            for mailbox in mailboxes {
+                match mailbox {
+                    Mailbox::Name(name) => {
+                        if !matches!(
+                            self.mailbox_get_by_name(account_id, &name).await,
+                            Ok(Some(document_id)) if special_use_ids.is_empty() ||
+                            special_use_ids.contains(&document_id)
+                        ) {
+                            result = false;
+                            break;
+                        }
+                    }
+                    Mailbox::Id(id) => {
+                        if !matches!(Id::from_bytes(id.as_bytes()), Some(id) if
+                                            mailbox_ids.contains(id.document_id()) &&
+                                            (special_use_ids.is_empty() ||
+                                            special_use_ids.contains(&id.document_id())))
+                        {
+                            result = false;
+                            break;
+                        }
+                    }
+                }
+            }
+            result
+        } else if !special_use.is_empty() {
+            let mut result = true;
+
+            for role in special_use {
+                if !role.eq_ignore_ascii_case("inbox") && !role.eq_ignore_ascii_case("trash") {
+                    let role = role.to_ascii_lowercase();
+                    if !is_valid_role(&role)
+                        || !matches!(
+                            self.mailbox_get_by_role(account_id, &role).await,
+                            Ok(Some(_))
+                        )
+                    {
+                        result = false;
+                        break;
+                    }
+                }
+            }
+            result
+        } else {
+            false
+        }
+    }
+}
+
+struct IngestSink<'x> {
+    jmap: &'x JMAP,
+    account_id: u32,
+    mailbox_ids: &'x RoaringBitmap,
+    mail_from: &'x str,
+    notify_trigger: &'x NotifyTrigger,
+    seen_ids: &'x SeenIds,
+    now: u64,
+    do_discard: bool,
+    do_deliver: bool,
+    new_ids: AHashSet<SeenIdHash>,
+    reject_reason: Option<String>,
+    change_id: Option<u64>,
+    messages: Vec<SieveMessage<'x>>,
+}
+
+#[async_trait::async_trait]
+impl<'x> SieveSink for IngestSink<'x> {
+    async fn on_event(&mut self, event: Event) -> Input {
+        match event {
+            Event::DuplicateId { id, expiry, last } => {
+                let id_hash = SeenIdHash::new(&id, expiry + self.now);
+                let seen_id = self.seen_ids.ids.contains(&id_hash);
+                if !seen_id || last {
+                    self.new_ids.insert(id_hash);
+                }
+
+                seen_id.into()
+            }
+            Event::Discard => {
+                self.do_discard = true;
+                true.into()
+            }
+            Event::Reject { reason, .. } => {
+                self.reject_reason = reason.into();
+                self.do_discard = true;
+                true.into()
+            }
+            Event::Keep { flags, message_id } => {
+                if let Some(message) = self.messages.get_mut(message_id) {
+                    message.flags = flags.into_iter().map(Keyword::from).collect();
+                    if !message.file_into.contains(&INBOX_ID) {
+                        message.file_into.push(INBOX_ID);
+                    }
+                    self.do_deliver = true;
+                } else {
+                    tracing::error!(
+                        context = "sieve_script_ingest",
+                        event = "error",
+                        "Unknown message id {}.",
+                        message_id
+                    );
+                }
+                true.into()
+            }
+            Event::FileInto {
+                folder,
+                flags,
+                mailbox_id,
+                special_use,
+                create,
+                message_id,
+            } => {
+                let jmap = self.jmap;
+                let account_id = self.account_id;
+                let mut target_id = u32::MAX;
+
+                // Find mailbox by Id
+                if let Some(mailbox_id) = mailbox_id.and_then(|m| Id::from_bytes(m.as_bytes())) {
+                    let mailbox_id = mailbox_id.document_id();
+                    if self.mailbox_ids.contains(mailbox_id) {
+                        target_id = mailbox_id;
+                    }
+                }
+
+                // Find mailbox by role
+                if let Some(special_use) = special_use {
+                    if target_id == u32::MAX {
+                        if special_use.eq_ignore_ascii_case("inbox") {
+                            target_id = INBOX_ID;
+                        } else if special_use.eq_ignore_ascii_case("trash") {
+                            target_id = TRASH_ID;
+                        } else {
+                            let role = special_use.to_ascii_lowercase();
+                            if is_valid_role(&role) {
+                                if let Ok(Some(mailbox_id_)) =
+                                    jmap.mailbox_get_by_role(account_id, &role).await
+                                {
+                                    target_id = mailbox_id_;
+                                }
+                            }
+                        }
+                    }
+                }
+
+                // Find mailbox by name
+                if target_id == u32::MAX {
+                    if !create {
+                        if let Ok(Some(document_id)) =
+                            jmap.mailbox_get_by_name(account_id, &folder).await
+                        {
+                            target_id = document_id;
+                        }
+                    } else if let Ok(Some((document_id, changes))) =
+                        jmap.mailbox_create_path(account_id, &folder).await
+                    {
+                        target_id = document_id;
+                        if let Some(change_id) = changes {
+                            self.change_id = change_id.into();
+                        }
+                    }
+                }
+
+                // Default to Inbox
+                if target_id == u32::MAX {
+                    target_id = INBOX_ID;
+                }
+
+                if let Some(message) = self.messages.get_mut(message_id) {
+                    message.flags = flags.into_iter().map(Keyword::from).collect();
+                    if !message.file_into.contains(&target_id) {
+                        message.file_into.push(target_id);
+                    }
+                    self.do_deliver = true;
+                } else {
+                    tracing::error!(
+                        context = "sieve_script_ingest",
+                        event = "error",
+                        "Unknown message id {}.",
+                        message_id
+                    );
+                }
+                true.into()
+            }
+            Event::SendMessage {
+                recipient,
+                message_id,
+                ..
+            } => {
+                let jmap = self.jmap;
+                if let Some(message) = self.messages.get(message_id) {
+                    if is_notification(&message.raw_message)
+                        && !jmap.is_notify_allowed(self.account_id, self.notify_trigger, "mailto")
+                    {
+                        return true.into();
+                    }
+
+                    if message.raw_message.len() <= jmap.config.mail_max_size {
+                        let result = Session::<NullIo>::sieve(
+                            jmap.smtp.clone(),
+                            SessionAddress::new(self.mail_from.to_string()),
+                            match recipient {
+                                Recipient::Address(rcpt) => vec![SessionAddress::new(rcpt)],
+                                Recipient::Group(rcpts) => {
+                                    rcpts.into_iter().map(SessionAddress::new).collect()
+                                }
+                                Recipient::List(_) => {
+                                    // Not yet implemented
+                                    return true.into();
+                                }
+                            },
+                            message.raw_message.to_vec(),
+                        )
+                        .queue_message()
+                        .await;
+
+                        tracing::debug!(
+                            context = "sieve_script_ingest",
+                            event = "send_message",
+                            smtp_response = std::str::from_utf8(&result).unwrap()
+                        );
+                    } else {
+                        tracing::warn!(
+                            context = "sieve_script_ingest",
+                            event = "message_too_large",
+                            from = self.mail_from,
+                            size = message.raw_message.len(),
+                            max_size = jmap.config.mail_max_size
+                        );
+                    }
+                } else {
+                    tracing::error!(
+                        context = "sieve_script_ingest",
+                        event = "error",
+                        "Unknown message id {}.",
+                        message_id
+                    );
+                }
+                true.into()
+            }
+            Event::Notify {
+                message, method, ..
+            } => {
+                self.jmap
+                    .sieve_notify(self.account_id, self.notify_trigger, message, method)
+                    .await;
+                true.into()
+            }
+            Event::CreatedMessage { message, .. } => {
+                self.messages.push(SieveMessage {
+                    raw_message: message.into(),
+                    file_into: Vec::new(),
+                    flags: Vec::new(),
+                });
+                true.into()
+            }
+            _ => false.into(),
+        }
+    }
+
+    fn on_error(&mut self, err: RuntimeError) -> Input {
+        #[cfg(feature = "test_mode")]
+        {
+            if let RuntimeError::ScriptErrorMessage(err) = &err {
+                panic!("Sieve test failed: {}", err);
+            }
+        }
+
+        tracing::debug!(
+            context = "sieve_script_ingest",
+            event = "error",
+            reason = %err,
+            "Runtime error",
+        );
+        true.into()
+    }
 }
 
 #[inline(always)]
@@ -0,0 +1,240 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::{method::MethodError, set::SetError},
+    method::sieve_list::{
+        GetSieveListRequest, GetSieveListResponse, SetSieveListRequest, SetSieveListResponse,
+        SieveList,
+    },
+};
+use utils::map::vec_map::VecMap;
+
+use crate::JMAP;
+
+pub const PERSONAL_LIST_PREFIX: &str = "personal:";
+
+impl JMAP {
+    pub async fn sieve_list_get(
+        &self,
+        request: GetSieveListRequest,
+    ) -> Result<GetSieveListResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let ids = request.ids.unwrap_or_else(|| {
+            let mut ids = self.sieve_lookups.keys().cloned().collect::<Vec<_>>();
+            ids.sort_unstable();
+            ids.extend(
+                self.config
+                    .sieve_personal_lists
+                    .iter()
+                    .map(|name| format!("{PERSONAL_LIST_PREFIX}{name}")),
+            );
+            ids
+        });
+        let mut response = GetSieveListResponse {
+            account_id: request.account_id,
+            list: Vec::with_capacity(ids.len()),
+            not_found: vec![],
+        };
+
+        for id in ids {
+            if self.sieve_lookups.contains_key(&id) {
+                // Administrator lookups can be used but not listed
+                response.list.push(SieveList {
+                    id,
+                    is_read_only: true,
+                    items: None,
+                });
+            } else if let Some(name) = self.personal_list_name(&id) {
+                let items = self
+                    .get_sieve_list(account_id, name)
+                    .await?
+                    .unwrap_or_default();
+                response.list.push(SieveList {
+                    id,
+                    is_read_only: false,
+                    items: items.into(),
+                });
+            } else {
+                response.not_found.push(id);
+            }
+        }
+
+        Ok(response)
+    }
+
+    pub async fn sieve_list_set(
+        &self,
+        request: SetSieveListRequest,
+    ) -> Result<SetSieveListResponse, MethodError> {
+        if request.update.len() > self.config.set_max_objects {
+            return Err(MethodError::RequestTooLarge);
+        }
+        let account_id = request.account_id.document_id();
+        let mut response = SetSieveListResponse {
+            account_id: request.account_id,
+            updated: vec![],
+            not_updated: VecMap::new(),
+        };
+
+        for (id, items) in request.update {
+            if self.sieve_lookups.contains_key(&id) {
+                response.not_updated.append(
+                    id,
+                    SetError::forbidden().with_description("List is read-only."),
+                );
+                continue;
+            }
+            let name = if let Some(name) = self.personal_list_name(&id) {
+                name
+            } else {
+                response.not_updated.append(id, SetError::not_found());
+                continue;
+            };
+            let items = match items.map(|items| self.validate_list_items(items)) {
+                Some(Ok(items)) => Some(items),
+                Some(Err(err)) => {
+                    response.not_updated.append(id, err);
+                    continue;
+                }
+                None => None,
+            };
+            self.set_sieve_list(account_id, name, items).await?;
+            response.updated.push(id);
+        }
+
+        Ok(response)
+    }
+
+    pub async fn sieve_list_contains(
+        &self,
+        account_id: u32,
+        list: &str,
+        values: &[String],
+        lowercase: bool,
+    ) -> Option<bool> {
+        if let Some(lookup) = self.sieve_lookups.get(list) {
+            for value in values {
+                let result = if !lowercase {
+                    lookup.contains(value).await
+                } else {
+                    lookup.contains(&value.to_lowercase()).await
+                };
+                if result? {
+                    return Some(true);
+                }
+            }
+            Some(false)
+        } else if let Some(name) = self.personal_list_name(list) {
+            let items = self.get_sieve_list(account_id, name).await.ok()?;
+            Some(items.unwrap_or_default().iter().any(|item| {
+                values.iter().any(|value| {
+                    if !lowercase {
+                        item == value
+                    } else {
+                        item.to_lowercase() == value.to_lowercase()
+                    }
+                })
+            }))
+        } else {
+            None
+        }
+    }
+
+    pub async fn get_sieve_list(
+        &self,
+        account_id: u32,
+        name: &str,
+    ) -> Result<Option<Vec<String>>, MethodError> {
+        self.store
+            .get_sieve_list(account_id, name)
+            .await
+            .map_err(|err| {
+                tracing::error!(
+                    event = "error",
+                    context = "sieve_list",
+                    account_id = account_id,
+                    list = name,
+                    error = ?err,
+                    "Failed to retrieve Sieve list.");
+                MethodError::ServerPartialFail
+            })
+    }
+
+    pub async fn set_sieve_list(
+        &self,
+        account_id: u32,
+        name: &str,
+        items: Option<Vec<String>>,
+    ) -> Result<(), MethodError> {
+        self.store
+            .set_sieve_list(account_id, name, items)
+            .await
+            .map_err(|err| {
+                tracing::error!(
+                    event = "error",
+                    context = "sieve_list",
+                    account_id = account_id,
+                    list = name,
+                    error = ?err,
+                    "Failed to update Sieve list.");
+                MethodError::ServerPartialFail
+            })
+    }
+
+    pub fn personal_list_name<'x>(&self, list: &'x str) -> Option<&'x str> {
+        list.strip_prefix(PERSONAL_LIST_PREFIX).filter(|name| {
+            self.config
+                .sieve_personal_lists
+                .iter()
+                .any(|personal_list| personal_list == name)
+        })
+    }
+
+    pub fn validate_list_items(&self, items: Vec<String>) -> Result<Vec<String>, SetError> {
+        if items.len() > self.config.sieve_list_max_items {
+            return Err(SetError::over_quota().with_description(format!(
+                "Lists may not contain more than {} items.",
+                self.config.sieve_list_max_items
+            )));
+        }
+
+        let mut result = Vec::with_capacity(items.len());
+        for item in items {
+            let item = item.trim();
+            if item.is_empty() || item.contains(['\r', '\n']) {
+                return Err(SetError::invalid_properties()
+                    .with_description("List items must be non-empty single lines."));
+            } else if item.len() > self.config.sieve_list_max_item_size {
+                return Err(SetError::invalid_properties().with_description(format!(
+                    "List items may not exceed {} bytes.",
+                    self.config.sieve_list_max_item_size
+                )));
+            } else if !result.iter().any(|i| i == item) {
+                result.push(item.to_string());
+            }
+        }
+
+        Ok(result)
+    }
+}
@@ -29,8 +29,12 @@ use store::{ahash::AHashSet, blake3, write::now};
 
 pub mod get;
 pub mod ingest;
+pub mod lists;
+pub mod notify;
 pub mod query;
+pub mod run;
 pub mod set;
+pub mod test;
 pub mod validate;
 
 pub struct ActiveScript {
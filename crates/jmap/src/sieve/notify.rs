@@ -0,0 +1,297 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{process::Stdio, time::Duration};
+
+use jmap_proto::types::{collection::Collection, state::StateChange, type_state::TypeState};
+use mail_parser::Message;
+use store::ahash::AHashMap;
+use tokio::{io::AsyncWriteExt, process::Command};
+use utils::config::Config;
+
+use crate::JMAP;
+
+pub struct NotifyMethod {
+    pub command: String,
+    pub arguments: Vec<String>,
+    pub timeout: Duration,
+}
+
+pub struct NotifyTrigger {
+    pub from: String,
+    pub is_auto_submitted: bool,
+}
+
+impl NotifyTrigger {
+    pub fn parse(message: &Message, raw_message: &[u8]) -> Self {
+        // RFC 5436 section 2.7: notifications must not be sent in response
+        // to automatically generated messages.
+        let is_auto_submitted = message.root_part().headers().iter().any(|header| {
+            header.name.as_str().eq_ignore_ascii_case("Auto-Submitted")
+                && raw_message
+                    .get(header.offset_start..header.offset_end)
+                    .map_or(false, |value| {
+                        !std::str::from_utf8(value)
+                            .unwrap_or_default()
+                            .trim()
+                            .eq_ignore_ascii_case("no")
+                    })
+        });
+
+        NotifyTrigger {
+            from: match message.from() {
+                mail_parser::HeaderValue::Address(addr) => {
+                    addr.address.as_deref().unwrap_or_default().to_string()
+                }
+                mail_parser::HeaderValue::AddressList(list) => list
+                    .first()
+                    .and_then(|addr| addr.address.as_deref())
+                    .unwrap_or_default()
+                    .to_string(),
+                _ => String::new(),
+            },
+            is_auto_submitted,
+        }
+    }
+}
+
+impl JMAP {
+    pub async fn sieve_notify(
+        &self,
+        account_id: u32,
+        trigger: &NotifyTrigger,
+        message: String,
+        method: String,
+    ) -> bool {
+        if !self.is_notify_allowed(account_id, trigger, &method) {
+            return false;
+        }
+
+        // Mailto notifications are sent by the interpreter as regular messages
+        let scheme = method
+            .split_once(':')
+            .map_or(method.as_str(), |(scheme, _)| scheme)
+            .to_ascii_lowercase();
+        match scheme.as_str() {
+            "push" => self.notify_push(account_id).await,
+            _ => {
+                if let Some(notify_method) = self.config.sieve_notify_methods.get(&scheme) {
+                    notify_method.run(&method, &message).await
+                } else {
+                    tracing::debug!(
+                        context = "sieve_notify",
+                        event = "unsupported",
+                        account_id = account_id,
+                        method = method.as_str(),
+                        "Unsupported notification method."
+                    );
+                    false
+                }
+            }
+        }
+    }
+
+    pub fn is_notify_allowed(
+        &self,
+        account_id: u32,
+        trigger: &NotifyTrigger,
+        method: &str,
+    ) -> bool {
+        if trigger.is_auto_submitted {
+            tracing::debug!(
+                context = "sieve_notify",
+                event = "skip",
+                account_id = account_id,
+                method = method,
+                "Not notifying on an auto-submitted message."
+            );
+            false
+        } else if !self
+            .get_authenticated_limiter(account_id)
+            .lock()
+            .sieve_notify
+            .is_allowed()
+        {
+            tracing::debug!(
+                context = "sieve_notify",
+                event = "rate-limited",
+                account_id = account_id,
+                method = method,
+                "Notification rate limit exceeded."
+            );
+            false
+        } else {
+            true
+        }
+    }
+
+    async fn notify_push(&self, account_id: u32) -> bool {
+        match self
+            .store
+            .get_last_change_id(account_id, Collection::Email)
+            .await
+        {
+            Ok(change_id) => {
+                self.broadcast_state_change(
+                    StateChange::new(account_id)
+                        .with_change(TypeState::EmailDelivery, change_id.unwrap_or_default()),
+                )
+                .await
+            }
+            Err(err) => {
+                tracing::error!(
+                    context = "sieve_notify",
+                    event = "error",
+                    account_id = account_id,
+                    error = ?err,
+                    "Failed to obtain Email state."
+                );
+                false
+            }
+        }
+    }
+}
+
+impl NotifyMethod {
+    async fn run(&self, method: &str, message: &str) -> bool {
+        let mut command = Command::new(&self.command);
+        for argument in &self.arguments {
+            command.arg(argument);
+        }
+        command.arg(method);
+
+        match command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+        {
+            Ok(mut child) => {
+                if let Some(mut stdin) = child.stdin.take() {
+                    match tokio::time::timeout(self.timeout, stdin.write_all(message.as_bytes()))
+                        .await
+                    {
+                        Ok(Ok(_)) => {
+                            drop(stdin);
+                        }
+                        Ok(Err(err)) => {
+                            tracing::warn!(
+                                context = "sieve_notify",
+                                event = "write-error",
+                                command = self.command,
+                                reason = %err
+                            );
+                            return false;
+                        }
+                        Err(_) => {
+                            tracing::warn!(
+                                context = "sieve_notify",
+                                event = "stdin-timeout",
+                                command = self.command
+                            );
+                            return false;
+                        }
+                    }
+                }
+
+                match tokio::time::timeout(self.timeout, child.wait()).await {
+                    Ok(Ok(status)) => {
+                        tracing::debug!(
+                            context = "sieve_notify",
+                            event = "success",
+                            command = self.command,
+                            status = status.to_string()
+                        );
+                        status.success()
+                    }
+                    Ok(Err(err)) => {
+                        tracing::warn!(
+                            context = "sieve_notify",
+                            event = "exec-error",
+                            command = self.command,
+                            reason = %err
+                        );
+                        false
+                    }
+                    Err(_) => {
+                        tracing::warn!(
+                            context = "sieve_notify",
+                            event = "timeout",
+                            command = self.command
+                        );
+                        false
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::warn!(
+                    context = "sieve_notify",
+                    event = "spawn-error",
+                    command = self.command,
+                    reason = %err
+                );
+                false
+            }
+        }
+    }
+}
+
+pub fn parse_notify_methods(
+    config: &Config,
+) -> utils::config::Result<AHashMap<String, NotifyMethod>> {
+    let mut methods = AHashMap::new();
+    for scheme in config.sub_keys("jmap.sieve.notify.method") {
+        methods.insert(
+            scheme.to_ascii_lowercase(),
+            NotifyMethod {
+                command: config
+                    .value_require(("jmap.sieve.notify.method", scheme, "command"))?
+                    .to_string(),
+                arguments: config
+                    .values(("jmap.sieve.notify.method", scheme, "arguments"))
+                    .map(|(_, v)| v.to_string())
+                    .collect(),
+                timeout: config
+                    .property_or_static(("jmap.sieve.notify.method", scheme, "timeout"), "30s")?,
+            },
+        );
+    }
+    Ok(methods)
+}
+
+/// Returns true for messages generated by the enotify extension.
+pub fn is_notification(raw_message: &[u8]) -> bool {
+    for line in raw_message.split(|&ch| ch == b'\n') {
+        let line = std::str::from_utf8(line).unwrap_or_default().trim_end();
+        if line.is_empty() {
+            break;
+        } else if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Auto-Submitted")
+                && value.trim().eq_ignore_ascii_case("auto-notified")
+            {
+                return true;
+            }
+        }
+    }
+    false
+}
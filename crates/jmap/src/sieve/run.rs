@@ -0,0 +1,107 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use sieve::{runtime::RuntimeError, Context, Event, Input, MatchAs};
+use store::roaring::RoaringBitmap;
+
+use crate::JMAP;
+
+/// Receives the actions requested by a Sieve script, events that only query
+/// the account are answered by `sieve_run` itself. Delivery and dry runs use
+/// the same event loop and differ only in their sink.
+#[async_trait::async_trait]
+pub trait SieveSink: Send {
+    async fn on_event(&mut self, event: Event) -> Input;
+
+    fn on_error(&mut self, err: RuntimeError) -> Input;
+}
+
+impl JMAP {
+    pub async fn sieve_run(
+        &self,
+        instance: &mut Context<'_>,
+        mut input: Input,
+        account_id: u32,
+        mailbox_ids: &RoaringBitmap,
+        sink: &mut impl SieveSink,
+    ) {
+        while let Some(event) = instance.run(input) {
+            input = match event {
+                Ok(Event::IncludeScript { name, .. }) => {
+                    if let Ok(Some(script)) = self.sieve_script_get_by_name(account_id, &name).await
+                    {
+                        Input::script(name, script)
+                    } else {
+                        false.into()
+                    }
+                }
+                Ok(Event::MailboxExists {
+                    mailboxes,
+                    special_use,
+                }) => self
+                    .sieve_mailbox_exists(account_id, mailbox_ids, mailboxes, special_use)
+                    .await
+                    .into(),
+                Ok(Event::ListContains {
+                    lists,
+                    values,
+                    match_as,
+                }) => {
+                    let mut result = false;
+                    for list in lists {
+                        match self
+                            .sieve_list_contains(
+                                account_id,
+                                &list,
+                                &values,
+                                matches!(match_as, MatchAs::Lowercase),
+                            )
+                            .await
+                        {
+                            Some(true) => {
+                                result = true;
+                                break;
+                            }
+                            Some(false) => (),
+                            None => {
+                                tracing::debug!(
+                                    context = "sieve_script",
+                                    event = "list-not-found",
+                                    account_id = account_id,
+                                    list = list,
+                                );
+                            }
+                        }
+                    }
+                    result.into()
+                }
+                Ok(Event::Execute { .. } | Event::SetEnvelope { .. }) => {
+                    // Not allowed
+                    false.into()
+                }
+                Ok(event) => sink.on_event(event).await,
+                Err(err) => sink.on_error(err),
+            };
+        }
+    }
+}
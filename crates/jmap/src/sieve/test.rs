@@ -0,0 +1,311 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap_proto::{
+    error::{
+        method::MethodError,
+        set::{SetError, SetErrorType},
+    },
+    method::sieve_test::{SieveAction, TestSieveScriptRequest, TestSieveScriptResponse},
+    types::collection::Collection,
+};
+use mail_parser::Message;
+use sieve::{runtime::RuntimeError, Envelope, Event, Input, Recipient, Sieve};
+
+use crate::{auth::AccessToken, JMAP};
+
+use super::{
+    notify::{is_notification, NotifyTrigger},
+    run::SieveSink,
+};
+
+impl JMAP {
+    pub async fn sieve_script_test(
+        &self,
+        request: TestSieveScriptRequest,
+        access_token: &AccessToken,
+    ) -> Result<TestSieveScriptResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let mut response = TestSieveScriptResponse {
+            account_id: request.account_id,
+            actions: Vec::new(),
+            error: None,
+        };
+
+        // Obtain the script to test
+        let script = if let Some(script) = request.script {
+            match self.sieve_compiler.compile(script.as_bytes()) {
+                Ok(script) => Arc::new(script),
+                Err(err) => {
+                    response.error = SetError::new(SetErrorType::InvalidScript)
+                        .with_description(err.to_string())
+                        .into();
+                    return Ok(response);
+                }
+            }
+        } else if let Some(script_id) = request.script_id {
+            let document_id = script_id.document_id();
+            if self
+                .get_document_ids(account_id, Collection::SieveScript)
+                .await?
+                .map_or(false, |ids| ids.contains(document_id))
+            {
+                Arc::new(self.sieve_script_compile(account_id, document_id).await?.0)
+            } else {
+                response.error = SetError::new(SetErrorType::NotFound)
+                    .with_description("Script not found.")
+                    .into();
+                return Ok(response);
+            }
+        } else if let Some(active_script) = self.sieve_script_get_active(account_id).await? {
+            active_script.script
+        } else {
+            response.error = SetError::new(SetErrorType::NotFound)
+                .with_description("No script was provided and there is no active script.")
+                .into();
+            return Ok(response);
+        };
+
+        // Obtain the message
+        let raw_message = if let Some(raw_message) = self
+            .blob_download(&request.email_blob_id, access_token)
+            .await?
+        {
+            raw_message
+        } else {
+            response.error = SetError::new(SetErrorType::BlobNotFound).into();
+            return Ok(response);
+        };
+
+        let account_name = self
+            .get_account_name(account_id)
+            .await?
+            .unwrap_or_else(|| access_token.name.clone());
+        let (envelope_from, envelope_to) = request
+            .envelope
+            .map(|envelope| (envelope.mail_from, envelope.rcpt_to.into_iter().next()))
+            .unwrap_or_default();
+
+        match self
+            .sieve_script_dry_run(
+                account_id,
+                &account_name,
+                script,
+                &raw_message,
+                envelope_from,
+                envelope_to,
+            )
+            .await?
+        {
+            Some(actions) => {
+                response.actions = actions;
+            }
+            None => {
+                response.error = SetError::invalid_properties()
+                    .with_description("Failed to parse message.")
+                    .into();
+            }
+        }
+
+        Ok(response)
+    }
+
+    pub async fn sieve_script_dry_run(
+        &self,
+        account_id: u32,
+        account_name: &str,
+        script: Arc<Sieve>,
+        raw_message: &[u8],
+        envelope_from: Option<String>,
+        envelope_to: Option<String>,
+    ) -> Result<Option<Vec<SieveAction>>, MethodError> {
+        // Parse message
+        let message = if let Some(message) = Message::parse(raw_message) {
+            message
+        } else {
+            return Ok(None);
+        };
+        let trigger = NotifyTrigger::parse(&message, raw_message);
+        let mailbox_ids = self
+            .get_document_ids(account_id, Collection::Mailbox)
+            .await?
+            .unwrap_or_default();
+
+        // Create Sieve instance, no side effects are performed on this run
+        let mut instance = self.sieve_runtime.filter_parsed(message);
+        let user_address = self
+            .directory
+            .emails_by_name(account_name)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        instance.set_user_address(&user_address);
+        if let Ok(Some(p)) = self.directory.principal(account_name).await {
+            instance.set_user_full_name(p.description().unwrap_or_else(|| p.name()));
+        }
+        instance.set_envelope(
+            Envelope::From,
+            envelope_from.as_deref().unwrap_or(&trigger.from),
+        );
+        instance.set_envelope(
+            Envelope::To,
+            envelope_to.as_deref().unwrap_or(&user_address),
+        );
+
+        let mut sink = DryRunSink::default();
+        self.sieve_run(
+            &mut instance,
+            Input::script("__test", script),
+            account_id,
+            &mailbox_ids,
+            &mut sink,
+        )
+        .await;
+        let DryRunSink {
+            mut actions,
+            do_discard,
+            do_deliver,
+            ..
+        } = sink;
+
+        // Mirror the fail-safe of sieve_script_ingest
+        if !do_deliver && !do_discard {
+            actions.push(SieveAction::Keep { flags: Vec::new() });
+        }
+
+        Ok(Some(actions))
+    }
+}
+
+// Records the actions requested by the script without performing them.
+#[derive(Default)]
+struct DryRunSink {
+    actions: Vec<SieveAction>,
+    messages: Vec<Vec<u8>>,
+    do_discard: bool,
+    do_deliver: bool,
+}
+
+#[async_trait::async_trait]
+impl SieveSink for DryRunSink {
+    async fn on_event(&mut self, event: Event) -> Input {
+        match event {
+            Event::DuplicateId { .. } => false.into(),
+            Event::Discard => {
+                self.actions.push(SieveAction::Discard);
+                self.do_discard = true;
+                true.into()
+            }
+            Event::Reject { reason, .. } => {
+                self.actions.push(SieveAction::Reject { reason });
+                self.do_discard = true;
+                true.into()
+            }
+            Event::Keep { flags, .. } => {
+                self.actions.push(SieveAction::Keep { flags });
+                self.do_deliver = true;
+                true.into()
+            }
+            Event::FileInto {
+                folder,
+                flags,
+                mailbox_id,
+                special_use,
+                create,
+                ..
+            } => {
+                self.actions.push(SieveAction::FileInto {
+                    mailbox: folder,
+                    mailbox_id,
+                    special_use,
+                    flags,
+                    create,
+                });
+                self.do_deliver = true;
+                true.into()
+            }
+            Event::SendMessage {
+                recipient,
+                message_id,
+                ..
+            } => {
+                let recipients = match recipient {
+                    Recipient::Address(rcpt) => vec![rcpt],
+                    Recipient::Group(rcpts) => rcpts,
+                    Recipient::List(list) => vec![format!("list:{list}")],
+                };
+                let created_message = message_id
+                    .checked_sub(1)
+                    .and_then(|message_id| self.messages.get(message_id));
+                self.actions.push(match created_message {
+                    Some(raw_message) if is_notification(raw_message) => SieveAction::Notify {
+                        method: format!("mailto:{}", recipients.join(",")),
+                        message: Message::parse(raw_message)
+                            .and_then(|message| {
+                                message.subject().map(|subject| subject.to_string())
+                            })
+                            .unwrap_or_default(),
+                    },
+                    Some(raw_message) => match Message::parse(raw_message) {
+                        Some(message)
+                            if NotifyTrigger::parse(&message, raw_message).is_auto_submitted =>
+                        {
+                            SieveAction::Vacation {
+                                recipients,
+                                subject: message.subject().map(|s| s.to_string()),
+                            }
+                        }
+                        _ => SieveAction::Redirect { recipients },
+                    },
+                    None => SieveAction::Redirect { recipients },
+                });
+                true.into()
+            }
+            Event::Notify {
+                message, method, ..
+            } => {
+                self.actions.push(SieveAction::Notify { method, message });
+                true.into()
+            }
+            Event::CreatedMessage { message, .. } => {
+                self.messages.push(message);
+                true.into()
+            }
+            _ => false.into(),
+        }
+    }
+
+    fn on_error(&mut self, err: RuntimeError) -> Input {
+        tracing::debug!(
+            context = "sieve_script_test",
+            event = "error",
+            reason = %err,
+            "Runtime error",
+        );
+        true.into()
+    }
+}
@@ -37,13 +37,17 @@ use tokio_tungstenite::WebSocketStream;
 use tungstenite::Message;
 use utils::{listener::ServerInstance, map::bitmap::Bitmap};
 
-use crate::{auth::AccessToken, JMAP};
+use crate::{
+    auth::{rate_limit::RemoteAddress, AccessToken},
+    JMAP,
+};
 
 impl JMAP {
     pub async fn handle_websocket_stream(
         &self,
         mut stream: WebSocketStream<TokioIo<Upgraded>>,
         access_token: Arc<AccessToken>,
+        remote_addr: RemoteAddress,
         instance: Arc<ServerInstance>,
     ) {
         let span = tracing::info_span!(
@@ -99,6 +103,7 @@ impl JMAP {
                                                 .handle_request(
                                                     request.request,
                                                     access_token.clone(),
+                                                    &remote_addr,
                                                     &instance,
                                                 )
                                                 .await
@@ -33,7 +33,7 @@ use utils::listener::ServerInstance;
 
 use crate::{
     api::{http::ToHttpResponse, HttpRequest, HttpResponse},
-    auth::AccessToken,
+    auth::{rate_limit::RemoteAddress, AccessToken},
     JMAP,
 };
 
@@ -41,6 +41,7 @@ pub async fn upgrade_websocket_connection(
     jmap: Arc<JMAP>,
     req: HttpRequest,
     access_token: Arc<AccessToken>,
+    remote_addr: RemoteAddress,
     instance: Arc<ServerInstance>,
 ) -> HttpResponse {
     let headers = req.headers();
@@ -88,6 +89,7 @@ pub async fn upgrade_websocket_connection(
                     WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None)
                         .await,
                     access_token,
+                    remote_addr,
                     instance,
                 )
                 .await;
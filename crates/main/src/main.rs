@@ -21,13 +21,15 @@
  * for more details.
 */
 
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use directory::config::ConfigDirectory;
 use imap::core::{ImapSessionManager, IMAP};
 use jmap::{api::JmapSessionManager, services::IPC_CHANNEL_BUFFER, JMAP};
 use managesieve::core::ManageSieveSessionManager;
+use pop3::core::Pop3SessionManager;
 use smtp::core::{SmtpSessionManager, SMTP};
+use store::Store;
 use tokio::sync::mpsc;
 use utils::{
     config::{Config, ServerProtocol},
@@ -62,10 +64,14 @@ async fn main() -> std::io::Result<()> {
 
     // Init servers
     let (delivery_tx, delivery_rx) = mpsc::channel(IPC_CHANNEL_BUFFER);
-    let smtp = SMTP::init(&config, &servers, &directory, delivery_tx)
+    let store = Arc::new(Store::open(&config).await.failed("Unable to open database"));
+    for acme_manager in &servers.acme_managers {
+        acme_manager.clone().spawn(store.clone());
+    }
+    let smtp = SMTP::init(&config, &servers, &directory, store.clone(), delivery_tx)
         .await
         .failed("Invalid configuration file");
-    let jmap = JMAP::init(&config, &directory, delivery_rx, smtp.clone())
+    let jmap = JMAP::init(&config, &directory, store, delivery_rx, smtp.clone())
         .await
         .failed("Invalid configuration file");
     let imap = IMAP::init(&config)
@@ -92,6 +98,10 @@ async fn main() -> std::io::Result<()> {
                 ManageSieveSessionManager::new(jmap.clone(), imap.clone()),
                 shutdown_rx,
             ),
+            ServerProtocol::Pop3 => server.spawn(
+                Pop3SessionManager::new(jmap.clone(), imap.clone()),
+                shutdown_rx,
+            ),
         };
     });
 
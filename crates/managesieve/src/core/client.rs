@@ -76,6 +76,9 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
                 Command::DeleteScript => self.handle_deletescript(request).await,
                 Command::RenameScript => self.handle_renamescript(request).await,
                 Command::CheckScript => self.handle_checkscript(request).await,
+                Command::ListLists => self.handle_listlists().await,
+                Command::GetList => self.handle_getlist(request).await,
+                Command::PutList => self.handle_putlist(request).await,
                 Command::HaveSpace => self.handle_havespace(request).await,
                 Command::Capability => self.handle_capability("").await,
                 Command::Authenticate => self.handle_authenticate(request).await,
@@ -222,6 +225,9 @@ impl ValidateRequest for Request<Command> {
             | Command::DeleteScript
             | Command::RenameScript
             | Command::CheckScript
+            | Command::ListLists
+            | Command::GetList
+            | Command::PutList
             | Command::Unauthenticate => {
                 if let State::Authenticated { access_token, .. } = state {
                     if imap
@@ -96,6 +96,9 @@ pub enum Command {
     DeleteScript,
     RenameScript,
     CheckScript,
+    ListLists,
+    GetList,
+    PutList,
     #[default]
     Noop,
     Unauthenticate,
@@ -132,6 +135,9 @@ impl CommandParser for Command {
             b"DELETESCRIPT" => Some(Command::DeleteScript),
             b"RENAMESCRIPT" => Some(Command::RenameScript),
             b"CHECKSCRIPT" => Some(Command::CheckScript),
+            b"LISTLISTS" => Some(Command::ListLists),
+            b"GETLIST" => Some(Command::GetList),
+            b"PUTLIST" => Some(Command::PutList),
             b"NOOP" => Some(Command::Noop),
             b"UNAUTHENTICATE" => Some(Command::Unauthenticate),
             _ => None,
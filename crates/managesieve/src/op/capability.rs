@@ -69,6 +69,10 @@ impl<T: AsyncRead + AsyncWrite + IsTls> Session<T> {
         } else {
             response.extend_from_slice(b"\"SIEVE\" \"\"\r\n");
         }
+        if !self.jmap.sieve_lookups.is_empty() || !self.jmap.config.sieve_personal_lists.is_empty()
+        {
+            response.extend_from_slice(b"\"EXTLISTS\"\r\n");
+        }
 
         Ok(StatusResponse::ok(message).serialize(response))
     }
@@ -0,0 +1,120 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use imap_proto::receiver::Request;
+use jmap::sieve::lists::PERSONAL_LIST_PREFIX;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::core::{Command, ResponseCode, Session, StatusResponse};
+
+impl<T: AsyncRead + AsyncWrite> Session<T> {
+    pub async fn handle_listlists(&mut self) -> super::OpResult {
+        let mut names = self.jmap.sieve_lookups.keys().collect::<Vec<_>>();
+        names.sort_unstable();
+
+        let mut response = Vec::with_capacity(128);
+        for name in names {
+            response.push(b'\"');
+            response.extend_from_slice(name.as_bytes());
+            response.extend_from_slice(b"\" READONLY\r\n");
+        }
+        for name in &self.jmap.config.sieve_personal_lists {
+            response.push(b'\"');
+            response.extend_from_slice(PERSONAL_LIST_PREFIX.as_bytes());
+            response.extend_from_slice(name.as_bytes());
+            response.extend_from_slice(b"\"\r\n");
+        }
+
+        Ok(StatusResponse::ok("").serialize(response))
+    }
+
+    pub async fn handle_getlist(&mut self, request: Request<Command>) -> super::OpResult {
+        let name = request
+            .tokens
+            .into_iter()
+            .next()
+            .and_then(|s| s.unwrap_string().ok())
+            .ok_or_else(|| StatusResponse::no("Expected list name as a parameter."))?;
+        let account_id = self.state.access_token().primary_id();
+        let name = self.jmap.personal_list_name(&name).ok_or_else(|| {
+            StatusResponse::no("List not found").with_code(ResponseCode::NonExistent)
+        })?;
+
+        let mut items = Vec::with_capacity(128);
+        for item in self
+            .jmap
+            .get_sieve_list(account_id, name)
+            .await?
+            .unwrap_or_default()
+        {
+            items.extend_from_slice(item.as_bytes());
+            items.extend_from_slice(b"\r\n");
+        }
+
+        let mut response = Vec::with_capacity(items.len() + 30);
+        response.push(b'{');
+        response.extend_from_slice(items.len().to_string().as_bytes());
+        response.extend_from_slice(b"}\r\n");
+        response.extend(items);
+
+        Ok(StatusResponse::ok("").serialize(response))
+    }
+
+    pub async fn handle_putlist(&mut self, request: Request<Command>) -> super::OpResult {
+        let mut tokens = request.tokens.into_iter();
+        let name = tokens
+            .next()
+            .and_then(|s| s.unwrap_string().ok())
+            .ok_or_else(|| StatusResponse::no("Expected list name as a parameter."))?;
+        let items = tokens
+            .next()
+            .ok_or_else(|| StatusResponse::no("Expected list items as a parameter."))?
+            .unwrap_bytes();
+        let account_id = self.state.access_token().primary_id();
+        if self.jmap.sieve_lookups.contains_key(&name) {
+            return Err(StatusResponse::no("List is read-only."));
+        }
+        let name = self.jmap.personal_list_name(&name).ok_or_else(|| {
+            StatusResponse::no("List not found").with_code(ResponseCode::NonExistent)
+        })?;
+
+        // One item per line, an empty list removes all items
+        let items = self
+            .jmap
+            .validate_list_items(
+                String::from_utf8_lossy(&items)
+                    .lines()
+                    .filter(|item| !item.trim().is_empty())
+                    .map(|item| item.to_string())
+                    .collect(),
+            )
+            .map_err(|err| {
+                StatusResponse::no(err.description.unwrap_or_else(|| "Invalid list.".into()))
+            })?;
+        self.jmap
+            .set_sieve_list(account_id, name, items.into())
+            .await?;
+
+        Ok(StatusResponse::ok("Success.").into_bytes())
+    }
+}
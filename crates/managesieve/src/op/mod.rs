@@ -31,6 +31,7 @@ pub mod checkscript;
 pub mod deletescript;
 pub mod getscript;
 pub mod havespace;
+pub mod lists;
 pub mod listscripts;
 pub mod logout;
 pub mod noop;
@@ -0,0 +1,223 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use imap::core::IMAP;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::protocol::{request::Error, Command, ResponseCode, ResponseType, StatusResponse};
+
+use super::{IsTls, Session, State};
+
+impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
+    pub async fn ingest(&mut self, bytes: &[u8]) -> Result<bool, ()> {
+        let mut bytes = bytes.iter();
+
+        loop {
+            // Commands are executed as soon as they are parsed, as the outcome
+            // of a command (i.e. a SASL exchange) affects how the next line is read.
+            let command = match self.receiver.parse(&mut bytes) {
+                Ok(command) => command,
+                Err(Error::NeedsMoreData) => {
+                    break;
+                }
+                Err(Error::Parse { message }) => {
+                    self.write(&StatusResponse::err(message).into_bytes())
+                        .await?;
+                    continue;
+                }
+            };
+
+            let result = match command.validate_request(
+                &self.imap,
+                &self.state,
+                self.stream.is_tls(),
+                self.instance.tls_acceptor.is_some(),
+            ) {
+                Ok(command) => match command {
+                    Command::User { name } => self.handle_user(name).await,
+                    Command::Pass { string } => self.handle_pass(string).await,
+                    Command::Auth { mechanism, params } => {
+                        self.handle_auth(mechanism, params).await
+                    }
+                    Command::Stls => {
+                        self.write(b"+OK Begin TLS negotiation now\r\n").await?;
+                        return Ok(false);
+                    }
+                    Command::Quit => {
+                        let response = self.handle_quit().await;
+                        self.write(&match response {
+                            Ok(response) => response,
+                            Err(err) => err.into_bytes(),
+                        })
+                        .await?;
+                        return Err(());
+                    }
+                    Command::Stat => self.handle_stat().await,
+                    Command::List { msg } => self.handle_list(msg).await,
+                    Command::Uidl { msg } => self.handle_uidl(msg).await,
+                    Command::Retr { msg } => self.handle_fetch(msg, None).await,
+                    Command::Top { msg, n } => self.handle_fetch(msg, n.into()).await,
+                    Command::Dele { msg } => self.handle_dele(msg).await,
+                    Command::Rset => self.handle_rset().await,
+                    Command::Noop => Ok(StatusResponse::ok("").into_bytes()),
+                    Command::Capa => self.handle_capa().await,
+                },
+                Err(err) => Err(err),
+            };
+
+            match result {
+                Ok(response) => {
+                    self.write(&response).await?;
+                }
+                Err(err) => {
+                    let disconnect = err.rtype == ResponseType::Bye;
+                    self.write(&err.into_bytes()).await?;
+                    if disconnect {
+                        return Err(());
+                    }
+                }
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
+    #[inline(always)]
+    pub async fn write(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        let err = match self.stream.write_all(bytes).await {
+            Ok(_) => match self.stream.flush().await {
+                Ok(_) => {
+                    tracing::trace!(parent: &self.span,
+                            event = "write",
+                            data = std::str::from_utf8(bytes).unwrap_or_default() ,
+                            size = bytes.len());
+                    return Ok(());
+                }
+                Err(err) => err,
+            },
+            Err(err) => err,
+        };
+
+        tracing::debug!(parent: &self.span,
+            event = "error",
+            "Failed to write to stream: {:?}", err);
+        Err(())
+    }
+
+    #[inline(always)]
+    pub async fn read(&mut self, bytes: &mut [u8]) -> Result<usize, ()> {
+        match self.stream.read(bytes).await {
+            Ok(len) => {
+                tracing::trace!(parent: &self.span,
+                                event = "read",
+                                data =  bytes
+                                    .get(0..len)
+                                    .and_then(|bytes| std::str::from_utf8(bytes).ok())
+                                    .unwrap_or("[invalid UTF8]"),
+                                size = len);
+                Ok(len)
+            }
+            Err(err) => {
+                tracing::debug!(
+                    parent: &self.span,
+                    event = "error",
+                    "Failed to read from stream: {:?}", err
+                );
+                Err(())
+            }
+        }
+    }
+}
+
+trait ValidateRequest: Sized {
+    fn validate_request(
+        self,
+        imap: &IMAP,
+        state: &State,
+        is_tls: bool,
+        has_tls: bool,
+    ) -> Result<Self, StatusResponse>;
+}
+
+impl ValidateRequest for Command {
+    fn validate_request(
+        self,
+        imap: &IMAP,
+        state: &State,
+        is_tls: bool,
+        has_tls: bool,
+    ) -> Result<Self, StatusResponse> {
+        match &self {
+            Command::Capa | Command::Quit => Ok(self),
+            Command::User { .. } | Command::Pass { .. } | Command::Auth { .. } => {
+                if let State::NotAuthenticated { .. } = state {
+                    if is_tls || imap.allow_plain_auth {
+                        Ok(self)
+                    } else {
+                        Err(StatusResponse::err("Cannot authenticate over plain-text.")
+                            .with_code(ResponseCode::Auth))
+                    }
+                } else {
+                    Err(StatusResponse::err("Already authenticated."))
+                }
+            }
+            Command::Stls => {
+                if is_tls {
+                    Err(StatusResponse::err("Already in TLS mode."))
+                } else if !has_tls {
+                    Err(StatusResponse::err("TLS is not available."))
+                } else if !matches!(state, State::NotAuthenticated { .. }) {
+                    Err(StatusResponse::err("Already authenticated."))
+                } else {
+                    Ok(self)
+                }
+            }
+            Command::Stat
+            | Command::List { .. }
+            | Command::Retr { .. }
+            | Command::Dele { .. }
+            | Command::Top { .. }
+            | Command::Uidl { .. }
+            | Command::Rset
+            | Command::Noop => {
+                if let State::Authenticated { access_token, .. } = state {
+                    if imap
+                        .get_authenticated_limiter(access_token.primary_id())
+                        .lock()
+                        .request_limiter
+                        .is_allowed()
+                    {
+                        Ok(self)
+                    } else {
+                        Err(StatusResponse::err("Too many requests")
+                            .with_code(ResponseCode::SysTemp))
+                    }
+                } else {
+                    Err(StatusResponse::err("Not authenticated."))
+                }
+            }
+        }
+    }
+}
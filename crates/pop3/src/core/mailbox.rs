@@ -0,0 +1,149 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use ahash::AHashMap;
+use imap::core::{MailboxId, MailboxState};
+use jmap::mailbox::INBOX_ID;
+use jmap_proto::types::{collection::Collection, property::Property};
+use store::{roaring::RoaringBitmap, Deserialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::protocol::StatusResponse;
+
+use super::Session;
+
+#[derive(Debug, Default)]
+pub struct Mailbox {
+    pub account_id: u32,
+    pub uid_validity: u32,
+    pub messages: Vec<Message>,
+    pub total: u32,
+    pub size: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Message {
+    pub id: u32,
+    pub uid: u32,
+    pub size: u32,
+    pub deleted: bool,
+}
+
+impl<T: AsyncRead + AsyncWrite> Session<T> {
+    pub async fn fetch_mailbox(&self, account_id: u32) -> Result<Mailbox, StatusResponse> {
+        // Obtain the IMAP UIDs of the messages in the Inbox
+        let state = MailboxState::fetch(
+            &self.jmap,
+            &MailboxId {
+                account_id,
+                mailbox_id: Some(INBOX_ID),
+            },
+        )
+        .await
+        .map_err(|_| StatusResponse::database_failure())?;
+
+        // Obtain message sizes
+        let message_sizes = if !state.id_to_imap.is_empty() {
+            self.jmap
+                .store
+                .index_values(
+                    (
+                        state.id_to_imap.keys().copied().collect::<RoaringBitmap>(),
+                        AHashMap::with_capacity(state.id_to_imap.len()),
+                    ),
+                    account_id,
+                    Collection::Email,
+                    Property::Size,
+                    true,
+                    |(message_ids, message_sizes), message_id, bytes| {
+                        if message_ids.remove(message_id) {
+                            message_sizes.insert(message_id, u32::deserialize(bytes)?);
+                            Ok(!message_ids.is_empty())
+                        } else {
+                            Ok(true)
+                        }
+                    },
+                )
+                .await
+                .map_err(|err| {
+                    tracing::error!(parent: &self.span,
+                        event = "error",
+                        context = "store",
+                        account_id = account_id,
+                        collection = ?Collection::Email,
+                        error = ?err,
+                        "Failed to obtain message sizes");
+                    StatusResponse::database_failure()
+                })?
+                .1
+        } else {
+            AHashMap::new()
+        };
+
+        // Sort messages by sequence number
+        let mut messages = state
+            .id_to_imap
+            .into_iter()
+            .map(|(id, imap_id)| {
+                (
+                    imap_id.seqnum,
+                    Message {
+                        id,
+                        uid: imap_id.uid,
+                        size: message_sizes.get(&id).copied().unwrap_or_default(),
+                        deleted: false,
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+        messages.sort_unstable_by_key(|(seqnum, _)| *seqnum);
+
+        Ok(Mailbox {
+            account_id,
+            uid_validity: state.uid_validity,
+            total: messages.len() as u32,
+            size: messages
+                .iter()
+                .map(|(_, message)| message.size as u64)
+                .sum(),
+            messages: messages.into_iter().map(|(_, message)| message).collect(),
+        })
+    }
+}
+
+impl Mailbox {
+    pub fn get(&self, msg: u32) -> Result<&Message, StatusResponse> {
+        match self.messages.get(msg.saturating_sub(1) as usize) {
+            Some(message) if !message.deleted => Ok(message),
+            Some(_) => Err(StatusResponse::err("Message is deleted.")),
+            None => Err(StatusResponse::err("No such message.")),
+        }
+    }
+
+    // Unique ids are built from the IMAP UID and UIDVALIDITY, using the
+    // same format as Dovecot's default so that clients do not download
+    // messages again after a migration.
+    pub fn uidl(&self, message: &Message) -> String {
+        format!("{:08X}{:08X}", message.uid, self.uid_validity)
+    }
+}
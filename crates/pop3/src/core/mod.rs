@@ -0,0 +1,119 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod client;
+pub mod mailbox;
+pub mod session;
+
+use std::sync::Arc;
+
+use imap::core::IMAP;
+use jmap::{
+    auth::{rate_limit::RemoteAddress, AccessToken},
+    JMAP,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_rustls::server::TlsStream;
+use utils::listener::{limiter::InFlight, ServerInstance};
+
+use crate::protocol::request::Parser;
+
+use self::mailbox::Mailbox;
+
+pub struct Session<T: AsyncRead + AsyncWrite> {
+    pub jmap: Arc<JMAP>,
+    pub imap: Arc<IMAP>,
+    pub instance: Arc<ServerInstance>,
+    pub receiver: Parser,
+    pub state: State,
+    pub remote_addr: RemoteAddress,
+    pub stream: T,
+    pub span: tracing::Span,
+    pub in_flight: InFlight,
+}
+
+pub enum State {
+    NotAuthenticated {
+        auth_failures: u32,
+        username: Option<String>,
+    },
+    Authenticated {
+        access_token: Arc<AccessToken>,
+        mailbox: Mailbox,
+        in_flight: InFlight,
+    },
+}
+
+impl State {
+    pub fn access_token(&self) -> &AccessToken {
+        match self {
+            State::Authenticated { access_token, .. } => access_token,
+            State::NotAuthenticated { .. } => unreachable!("Not authenticated"),
+        }
+    }
+
+    pub fn mailbox(&self) -> &Mailbox {
+        match self {
+            State::Authenticated { mailbox, .. } => mailbox,
+            State::NotAuthenticated { .. } => unreachable!("Not authenticated"),
+        }
+    }
+
+    pub fn mailbox_mut(&mut self) -> &mut Mailbox {
+        match self {
+            State::Authenticated { mailbox, .. } => mailbox,
+            State::NotAuthenticated { .. } => unreachable!("Not authenticated"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Pop3SessionManager {
+    pub jmap: Arc<JMAP>,
+    pub imap: Arc<IMAP>,
+}
+
+impl Pop3SessionManager {
+    pub fn new(jmap: Arc<JMAP>, imap: Arc<IMAP>) -> Self {
+        Self { jmap, imap }
+    }
+}
+
+pub trait IsTls {
+    fn is_tls(&self) -> bool;
+}
+
+impl IsTls for TcpStream {
+    fn is_tls(&self) -> bool {
+        false
+    }
+}
+
+impl IsTls for TlsStream<TcpStream> {
+    fn is_tls(&self) -> bool {
+        true
+    }
+}
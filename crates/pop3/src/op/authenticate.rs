@@ -0,0 +1,186 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use imap::op::authenticate::{decode_challenge_oauth, decode_challenge_plain};
+use imap_proto::protocol::authenticate::Mechanism;
+use mail_parser::decoders::base64::base64_decode;
+use mail_send::Credentials;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    core::{Session, State},
+    protocol::{request, ResponseCode, StatusResponse},
+};
+
+impl<T: AsyncRead + AsyncWrite> Session<T> {
+    pub async fn handle_user(&mut self, name: String) -> super::OpResult {
+        if let State::NotAuthenticated { username, .. } = &mut self.state {
+            *username = Some(name);
+        }
+
+        Ok(StatusResponse::ok("").into_bytes())
+    }
+
+    pub async fn handle_pass(&mut self, secret: String) -> super::OpResult {
+        let username = if let State::NotAuthenticated { username, .. } = &mut self.state {
+            username.take()
+        } else {
+            None
+        };
+
+        if let Some(username) = username {
+            self.authenticate(Credentials::Plain { username, secret })
+                .await
+        } else {
+            Err(StatusResponse::err("Missing USER command."))
+        }
+    }
+
+    pub async fn handle_auth(
+        &mut self,
+        mechanism: Mechanism,
+        mut params: Vec<String>,
+    ) -> super::OpResult {
+        let credentials = match mechanism {
+            Mechanism::Plain | Mechanism::OAuthBearer => {
+                if !params.is_empty() {
+                    let challenge = base64_decode(params.pop().unwrap().as_bytes())
+                        .ok_or_else(|| StatusResponse::err("Failed to decode challenge."))?;
+                    (if mechanism == Mechanism::Plain {
+                        decode_challenge_plain(&challenge)
+                    } else {
+                        decode_challenge_oauth(&challenge)
+                    }
+                    .map_err(StatusResponse::err))?
+                } else {
+                    self.receiver.state = request::State::Sasl { mechanism };
+                    return Ok(b"+ \r\n".to_vec());
+                }
+            }
+            _ => {
+                return Err(StatusResponse::err(
+                    "Authentication mechanism not supported.",
+                ))
+            }
+        };
+
+        self.authenticate(credentials).await
+    }
+
+    pub async fn authenticate(&mut self, credentials: Credentials<String>) -> super::OpResult {
+        // Throttle authentication requests
+        if self.jmap.is_auth_allowed(self.remote_addr.clone()).is_err() {
+            tracing::debug!(parent: &self.span,
+                event = "disconnect",
+                "Too many authentication attempts, disconnecting.",
+            );
+            return Err(StatusResponse::bye(
+                "Too many authentication requests from this IP address.",
+            )
+            .with_code(ResponseCode::LoginDelay));
+        }
+
+        // Authenticate
+        let access_token = match credentials {
+            Credentials::Plain { username, secret } | Credentials::XOauth2 { username, secret } => {
+                self.jmap.authenticate_plain(&username, &secret).await
+            }
+            Credentials::OAuthBearer { token } => {
+                match self
+                    .jmap
+                    .validate_access_token("access_token", &token)
+                    .await
+                {
+                    Ok((account_id, _, _)) => self.jmap.get_access_token(account_id).await,
+                    Err(err) => {
+                        tracing::debug!(
+                            parent: &self.span,
+                            context = "authenticate",
+                            err = err,
+                            "Failed to validate access token."
+                        );
+                        None
+                    }
+                }
+            }
+        };
+
+        if let Some(access_token) = access_token {
+            // Enforce concurrency limits
+            let in_flight = self
+                .imap
+                .get_authenticated_limiter(access_token.primary_id())
+                .lock()
+                .concurrent_requests
+                .is_allowed();
+            if let Some(in_flight) = in_flight {
+                // Cache access token
+                let access_token = Arc::new(access_token);
+                self.jmap.cache_access_token(access_token.clone());
+
+                // POP3 only provides access to the Inbox of the authenticated account
+                let mailbox = self.fetch_mailbox(access_token.primary_id()).await?;
+                let response = format!("{} messages ({} octets)", mailbox.total, mailbox.size);
+
+                // Create session
+                self.state = State::Authenticated {
+                    access_token,
+                    mailbox,
+                    in_flight,
+                };
+
+                Ok(StatusResponse::ok(response).into_bytes())
+            } else {
+                tracing::debug!(parent: &self.span,
+                    event = "disconnect",
+                    "Too many concurrent connection.",
+                );
+                Err(StatusResponse::bye("Too many concurrent connections.")
+                    .with_code(ResponseCode::InUse))
+            }
+        } else {
+            match &self.state {
+                State::NotAuthenticated { auth_failures, .. }
+                    if *auth_failures < self.imap.max_auth_failures =>
+                {
+                    self.state = State::NotAuthenticated {
+                        auth_failures: auth_failures + 1,
+                        username: None,
+                    };
+                    Err(StatusResponse::err("Authentication failed").with_code(ResponseCode::Auth))
+                }
+                _ => {
+                    tracing::debug!(
+                        parent: &self.span,
+                        event = "disconnect",
+                        "Too many authentication failures, disconnecting.",
+                    );
+                    Err(StatusResponse::bye("Too many authentication failures")
+                        .with_code(ResponseCode::Auth))
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    core::{IsTls, Session, State},
+    protocol::StatusResponse,
+};
+
+impl<T: AsyncRead + AsyncWrite + IsTls> Session<T> {
+    pub async fn handle_capa(&self) -> super::OpResult {
+        let mut response = StatusResponse::ok("Capability list follows").into_bytes();
+        response
+            .extend_from_slice(b"TOP\r\nUIDL\r\nRESP-CODES\r\nAUTH-RESP-CODE\r\nPIPELINING\r\n");
+        if matches!(self.state, State::NotAuthenticated { .. }) {
+            if self.stream.is_tls() || self.imap.allow_plain_auth {
+                response.extend_from_slice(b"USER\r\nSASL PLAIN OAUTHBEARER\r\n");
+            }
+            if !self.stream.is_tls() && self.instance.tls_acceptor.is_some() {
+                response.extend_from_slice(b"STLS\r\n");
+            }
+        }
+        response.extend_from_slice(b"IMPLEMENTATION Stalwart POP3 v");
+        response.extend_from_slice(env!("CARGO_PKG_VERSION").as_bytes());
+        response.extend_from_slice(b"\r\n.\r\n");
+
+        Ok(response)
+    }
+}
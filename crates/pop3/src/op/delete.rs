@@ -0,0 +1,85 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use imap::op::expunge::expunge_messages;
+use jmap::mailbox::INBOX_ID;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    core::{Session, State},
+    protocol::{ResponseCode, StatusResponse},
+};
+
+impl<T: AsyncRead + AsyncWrite> Session<T> {
+    pub async fn handle_dele(&mut self, msg: u32) -> super::OpResult {
+        let mailbox = self.state.mailbox_mut();
+        let size = mailbox.get(msg)?.size;
+        mailbox.messages[(msg - 1) as usize].deleted = true;
+        mailbox.total -= 1;
+        mailbox.size -= size as u64;
+
+        Ok(StatusResponse::ok("Message marked for deletion.").into_bytes())
+    }
+
+    pub async fn handle_rset(&mut self) -> super::OpResult {
+        let mailbox = self.state.mailbox_mut();
+        for message in &mut mailbox.messages {
+            message.deleted = false;
+        }
+        mailbox.total = mailbox.messages.len() as u32;
+        mailbox.size = mailbox
+            .messages
+            .iter()
+            .map(|message| message.size as u64)
+            .sum();
+
+        Ok(StatusResponse::ok(format!(
+            "{} messages ({} octets)",
+            mailbox.total, mailbox.size
+        ))
+        .into_bytes())
+    }
+
+    pub async fn handle_quit(&mut self) -> super::OpResult {
+        if let State::Authenticated { mailbox, .. } = &self.state {
+            // Messages are removed from the Inbox the same way IMAP's EXPUNGE does,
+            // messages that are also present in other mailboxes are only untagged.
+            let deleted_ids = mailbox
+                .messages
+                .iter()
+                .filter(|message| message.deleted)
+                .map(|message| message.id)
+                .collect::<Vec<_>>();
+            if !deleted_ids.is_empty()
+                && expunge_messages(&self.jmap, mailbox.account_id, Some(INBOX_ID), deleted_ids)
+                    .await
+                    .is_err()
+            {
+                return Err(StatusResponse::err("Some deleted messages not removed.")
+                    .with_code(ResponseCode::SysTemp));
+            }
+        }
+
+        Ok(StatusResponse::ok("Stalwart POP3 bids you farewell.").into_bytes())
+    }
+}
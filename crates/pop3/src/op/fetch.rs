@@ -0,0 +1,53 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::types::{blob::BlobId, collection::Collection};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    core::Session,
+    protocol::{response::serialize_message, StatusResponse},
+};
+
+impl<T: AsyncRead + AsyncWrite> Session<T> {
+    pub async fn handle_fetch(&self, msg: u32, lines: Option<u32>) -> super::OpResult {
+        let mailbox = self.state.mailbox();
+        let message = mailbox.get(msg)?;
+        let blob_id = BlobId::maildir(mailbox.account_id, message.id);
+
+        if let Some(raw_message) = self.jmap.get_blob(&blob_id.kind, 0..u32::MAX).await? {
+            let mut response = StatusResponse::ok(format!("{} octets", message.size)).into_bytes();
+            serialize_message(&mut response, &raw_message, lines);
+            Ok(response)
+        } else {
+            tracing::warn!(parent: &self.span,
+                event = "not-found",
+                account_id = mailbox.account_id,
+                collection = ?Collection::Email,
+                document_id = message.id,
+                blob_id = ?blob_id,
+                "Blob not found");
+            Err(StatusResponse::err("Message no longer exists."))
+        }
+    }
+}
@@ -0,0 +1,78 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{core::Session, protocol::StatusResponse};
+
+impl<T: AsyncRead + AsyncWrite> Session<T> {
+    pub async fn handle_stat(&self) -> super::OpResult {
+        let mailbox = self.state.mailbox();
+
+        Ok(StatusResponse::ok(format!("{} {}", mailbox.total, mailbox.size)).into_bytes())
+    }
+
+    pub async fn handle_list(&self, msg: Option<u32>) -> super::OpResult {
+        let mailbox = self.state.mailbox();
+
+        if let Some(msg) = msg {
+            let message = mailbox.get(msg)?;
+            Ok(StatusResponse::ok(format!("{} {}", msg, message.size)).into_bytes())
+        } else {
+            let mut response = StatusResponse::ok(format!(
+                "{} messages ({} octets)",
+                mailbox.total, mailbox.size
+            ))
+            .into_bytes();
+            for (seqnum, message) in mailbox.messages.iter().enumerate() {
+                if !message.deleted {
+                    response.extend_from_slice(
+                        format!("{} {}\r\n", seqnum + 1, message.size).as_bytes(),
+                    );
+                }
+            }
+            response.extend_from_slice(b".\r\n");
+            Ok(response)
+        }
+    }
+
+    pub async fn handle_uidl(&self, msg: Option<u32>) -> super::OpResult {
+        let mailbox = self.state.mailbox();
+
+        if let Some(msg) = msg {
+            let message = mailbox.get(msg)?;
+            Ok(StatusResponse::ok(format!("{} {}", msg, mailbox.uidl(message))).into_bytes())
+        } else {
+            let mut response = StatusResponse::ok("Unique-ID listing follows").into_bytes();
+            for (seqnum, message) in mailbox.messages.iter().enumerate() {
+                if !message.deleted {
+                    response.extend_from_slice(
+                        format!("{} {}\r\n", seqnum + 1, mailbox.uidl(message)).as_bytes(),
+                    );
+                }
+            }
+            response.extend_from_slice(b".\r\n");
+            Ok(response)
+        }
+    }
+}
@@ -0,0 +1,94 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+use imap_proto::protocol::authenticate::Mechanism;
+
+pub mod request;
+pub mod response;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    // Authorization state
+    User {
+        name: String,
+    },
+    Pass {
+        string: String,
+    },
+    Auth {
+        mechanism: Mechanism,
+        params: Vec<String>,
+    },
+    Stls,
+    Quit,
+
+    // Transaction state
+    Stat,
+    List {
+        msg: Option<u32>,
+    },
+    Retr {
+        msg: u32,
+    },
+    Dele {
+        msg: u32,
+    },
+    Top {
+        msg: u32,
+        n: u32,
+    },
+    Uidl {
+        msg: Option<u32>,
+    },
+    Rset,
+    Noop,
+
+    // Any state
+    Capa,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusResponse {
+    pub code: Option<ResponseCode>,
+    pub message: Cow<'static, str>,
+    pub rtype: ResponseType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResponseType {
+    Ok,
+    Err,
+    Bye,
+}
+
+// RFC 2449 and RFC 3206 response codes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResponseCode {
+    InUse,
+    LoginDelay,
+    SysTemp,
+    SysPerm,
+    Auth,
+}
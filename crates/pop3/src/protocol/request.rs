@@ -0,0 +1,285 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{borrow::Cow, slice::Iter};
+
+use imap_proto::protocol::authenticate::Mechanism;
+
+use super::Command;
+
+const MAX_LINE_LENGTH: usize = 8192;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    NeedsMoreData,
+    Parse { message: Cow<'static, str> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum State {
+    #[default]
+    Command,
+    Sasl {
+        mechanism: Mechanism,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct Parser {
+    pub state: State,
+    buf: Vec<u8>,
+    is_overflow: bool,
+}
+
+impl Parser {
+    pub fn parse(&mut self, bytes: &mut Iter<'_, u8>) -> Result<Command, Error> {
+        for &ch in bytes {
+            if ch != b'\n' {
+                if self.buf.len() < MAX_LINE_LENGTH {
+                    self.buf.push(ch);
+                } else {
+                    self.is_overflow = true;
+                }
+                continue;
+            }
+
+            let mut line = std::mem::take(&mut self.buf);
+            if std::mem::take(&mut self.is_overflow) {
+                self.state = State::Command;
+                return Err(Error::err("Line is too long."));
+            }
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+
+            return match std::mem::take(&mut self.state) {
+                State::Command => Command::parse(&line),
+                State::Sasl { mechanism } => {
+                    if line != b"*" {
+                        Ok(Command::Auth {
+                            mechanism,
+                            params: vec![String::from_utf8(line)
+                                .map_err(|_| Error::err("Invalid UTF-8 in SASL response."))?],
+                        })
+                    } else {
+                        Err(Error::err("Authentication cancelled."))
+                    }
+                }
+            };
+        }
+
+        Err(Error::NeedsMoreData)
+    }
+}
+
+impl Command {
+    pub fn parse(line: &[u8]) -> Result<Self, Error> {
+        let line = std::str::from_utf8(line).map_err(|_| Error::err("Invalid UTF-8."))?;
+        let (command, args) = line
+            .trim_start()
+            .split_once(' ')
+            .unwrap_or((line.trim(), ""));
+        let mut tokens = args.split_ascii_whitespace();
+
+        match command.to_ascii_uppercase().as_str() {
+            "USER" => Ok(Command::User {
+                name: non_empty(args.trim())?,
+            }),
+            "PASS" => Ok(Command::Pass {
+                string: non_empty(args.trim_end_matches(' '))?,
+            }),
+            "AUTH" => {
+                let mechanism = Mechanism::parse(
+                    tokens
+                        .next()
+                        .ok_or_else(|| Error::err("Missing SASL mechanism."))?
+                        .as_bytes(),
+                )
+                .map_err(|message| Error::Parse { message })?;
+                Ok(Command::Auth {
+                    mechanism,
+                    params: tokens.map(|token| token.to_string()).collect(),
+                })
+            }
+            "STLS" => Ok(Command::Stls),
+            "QUIT" => Ok(Command::Quit),
+            "STAT" => Ok(Command::Stat),
+            "LIST" => Ok(Command::List {
+                msg: tokens.next().map(parse_msg).transpose()?,
+            }),
+            "RETR" => Ok(Command::Retr {
+                msg: parse_msg(tokens.next().unwrap_or_default())?,
+            }),
+            "DELE" => Ok(Command::Dele {
+                msg: parse_msg(tokens.next().unwrap_or_default())?,
+            }),
+            "TOP" => Ok(Command::Top {
+                msg: parse_msg(tokens.next().unwrap_or_default())?,
+                n: tokens
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .ok_or_else(|| Error::err("Invalid number of lines."))?,
+            }),
+            "UIDL" => Ok(Command::Uidl {
+                msg: tokens.next().map(parse_msg).transpose()?,
+            }),
+            "RSET" => Ok(Command::Rset),
+            "NOOP" => Ok(Command::Noop),
+            "CAPA" => Ok(Command::Capa),
+            "" => Err(Error::err("Empty command.")),
+            _ => Err(Error::err("Unknown command.")),
+        }
+    }
+}
+
+fn parse_msg(value: &str) -> Result<u32, Error> {
+    value
+        .parse::<u32>()
+        .ok()
+        .filter(|&msg| msg > 0)
+        .ok_or_else(|| Error::err("Invalid message number."))
+}
+
+fn non_empty(value: &str) -> Result<String, Error> {
+    if !value.is_empty() {
+        Ok(value.to_string())
+    } else {
+        Err(Error::err("Missing argument."))
+    }
+}
+
+impl Error {
+    pub fn err(message: impl Into<Cow<'static, str>>) -> Self {
+        Error::Parse {
+            message: message.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use imap_proto::protocol::authenticate::Mechanism;
+
+    use crate::protocol::Command;
+
+    use super::{Error, Parser, State};
+
+    #[test]
+    fn parse_pop3_command() {
+        let mut parser = Parser::default();
+
+        for (input, expected) in [
+            (
+                "USER john@example.org\r\n",
+                vec![Ok(Command::User {
+                    name: "john@example.org".to_string(),
+                })],
+            ),
+            (
+                "pass my secret pass\r\n",
+                vec![Ok(Command::Pass {
+                    string: "my secret pass".to_string(),
+                })],
+            ),
+            (
+                "STAT\r\nLIST\r\n",
+                vec![Ok(Command::Stat), Ok(Command::List { msg: None })],
+            ),
+            ("LIST 2\r\n", vec![Ok(Command::List { msg: Some(2) })]),
+            ("RETR 1\n", vec![Ok(Command::Retr { msg: 1 })]),
+            ("DELE 10\r\n", vec![Ok(Command::Dele { msg: 10 })]),
+            ("TOP 3 0\r\n", vec![Ok(Command::Top { msg: 3, n: 0 })]),
+            (
+                "UIDL\r\nUIDL 7\r\n",
+                vec![
+                    Ok(Command::Uidl { msg: None }),
+                    Ok(Command::Uidl { msg: Some(7) }),
+                ],
+            ),
+            (
+                "AUTH PLAIN dGVzdAB0ZXN0AHRlc3Q=\r\n",
+                vec![Ok(Command::Auth {
+                    mechanism: Mechanism::Plain,
+                    params: vec!["dGVzdAB0ZXN0AHRlc3Q=".to_string()],
+                })],
+            ),
+            (
+                "RETR 0\r\n",
+                vec![Err(Error::err("Invalid message number."))],
+            ),
+            (
+                "TOP 1\r\n",
+                vec![Err(Error::err("Invalid number of lines."))],
+            ),
+            ("USER\r\n", vec![Err(Error::err("Missing argument."))]),
+            (
+                "APOP john c4c9334bac560ecc979e58001b3e22fb\r\n",
+                vec![Err(Error::err("Unknown command."))],
+            ),
+            (
+                "CAPA\r\nNOOP\r\nRSET\r\nSTLS\r\nQUIT\r\n",
+                vec![
+                    Ok(Command::Capa),
+                    Ok(Command::Noop),
+                    Ok(Command::Rset),
+                    Ok(Command::Stls),
+                    Ok(Command::Quit),
+                ],
+            ),
+        ] {
+            let mut bytes = input.as_bytes().iter();
+            for expected in expected {
+                assert_eq!(parser.parse(&mut bytes), expected, "{input:?}");
+            }
+            assert_eq!(parser.parse(&mut bytes), Err(Error::NeedsMoreData));
+        }
+
+        // Partial commands
+        let mut bytes = b"RE".iter();
+        assert_eq!(parser.parse(&mut bytes), Err(Error::NeedsMoreData));
+        let mut bytes = b"TR 5\r\n".iter();
+        assert_eq!(parser.parse(&mut bytes), Ok(Command::Retr { msg: 5 }));
+
+        // SASL responses
+        parser.state = State::Sasl {
+            mechanism: Mechanism::Plain,
+        };
+        let mut bytes = b"dGVzdAB0ZXN0AHRlc3Q=\r\n".iter();
+        assert_eq!(
+            parser.parse(&mut bytes),
+            Ok(Command::Auth {
+                mechanism: Mechanism::Plain,
+                params: vec!["dGVzdAB0ZXN0AHRlc3Q=".to_string()],
+            })
+        );
+        parser.state = State::Sasl {
+            mechanism: Mechanism::Plain,
+        };
+        let mut bytes = b"*\r\nNOOP\r\n".iter();
+        assert_eq!(
+            parser.parse(&mut bytes),
+            Err(Error::err("Authentication cancelled."))
+        );
+        assert_eq!(parser.parse(&mut bytes), Ok(Command::Noop));
+    }
+}
@@ -0,0 +1,220 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+use super::{ResponseCode, ResponseType, StatusResponse};
+
+impl ResponseCode {
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(match self {
+            ResponseCode::InUse => b"IN-USE",
+            ResponseCode::LoginDelay => b"LOGIN-DELAY",
+            ResponseCode::SysTemp => b"SYS/TEMP",
+            ResponseCode::SysPerm => b"SYS/PERM",
+            ResponseCode::Auth => b"AUTH",
+        });
+    }
+}
+
+impl ResponseType {
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(match self {
+            ResponseType::Ok => b"+OK",
+            ResponseType::Err | ResponseType::Bye => b"-ERR",
+        });
+    }
+}
+
+impl StatusResponse {
+    pub fn serialize(self, mut buf: Vec<u8>) -> Vec<u8> {
+        self.rtype.serialize(&mut buf);
+        if let Some(code) = &self.code {
+            buf.extend_from_slice(b" [");
+            code.serialize(&mut buf);
+            buf.push(b']');
+        }
+        if !self.message.is_empty() {
+            buf.push(b' ');
+            buf.extend_from_slice(self.message.as_bytes());
+        }
+        buf.extend_from_slice(b"\r\n");
+        buf
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.serialize(Vec::with_capacity(16))
+    }
+
+    pub fn with_code(mut self, code: ResponseCode) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn ok(message: impl Into<Cow<'static, str>>) -> Self {
+        StatusResponse {
+            code: None,
+            message: message.into(),
+            rtype: ResponseType::Ok,
+        }
+    }
+
+    pub fn err(message: impl Into<Cow<'static, str>>) -> Self {
+        StatusResponse {
+            code: None,
+            message: message.into(),
+            rtype: ResponseType::Err,
+        }
+    }
+
+    pub fn bye(message: impl Into<Cow<'static, str>>) -> Self {
+        StatusResponse {
+            code: None,
+            message: message.into(),
+            rtype: ResponseType::Bye,
+        }
+    }
+
+    pub fn database_failure() -> Self {
+        StatusResponse {
+            code: Some(ResponseCode::SysTemp),
+            message: Cow::Borrowed("Database failure"),
+            rtype: ResponseType::Err,
+        }
+    }
+}
+
+// Writes a message as a multi-line response, normalizing line endings and
+// byte-stuffing lines that start with a termination octet (RFC 1939, section 3).
+// When 'max_body_lines' is set, only the headers and up to that many body lines are sent.
+pub fn serialize_message(buf: &mut Vec<u8>, message: &[u8], max_body_lines: Option<u32>) {
+    let mut in_body = false;
+    let mut body_lines = 0;
+    let mut lines = message.split(|&ch| ch == b'\n').peekable();
+
+    while let Some(line) = lines.next() {
+        if line.is_empty() && lines.peek().is_none() {
+            break;
+        }
+        if in_body {
+            if max_body_lines.map_or(false, |max| body_lines >= max) {
+                break;
+            }
+            body_lines += 1;
+        }
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.first() == Some(&b'.') {
+            buf.push(b'.');
+        }
+        buf.extend_from_slice(line);
+        buf.extend_from_slice(b"\r\n");
+        if line.is_empty() {
+            in_body = true;
+        }
+    }
+
+    buf.extend_from_slice(b".\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{ResponseCode, StatusResponse};
+
+    use super::serialize_message;
+
+    #[test]
+    fn serialize_pop3_response() {
+        for (response, expected) in [
+            (StatusResponse::ok("2 320"), "+OK 2 320\r\n"),
+            (
+                StatusResponse::err("No such message"),
+                "-ERR No such message\r\n",
+            ),
+            (
+                StatusResponse::err("Authentication failed").with_code(ResponseCode::Auth),
+                "-ERR [AUTH] Authentication failed\r\n",
+            ),
+            (
+                StatusResponse::database_failure(),
+                "-ERR [SYS/TEMP] Database failure\r\n",
+            ),
+        ] {
+            assert_eq!(String::from_utf8(response.into_bytes()).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn serialize_pop3_message() {
+        let message = concat!(
+            "Subject: test\r\n",
+            "From: john@example.org\r\n",
+            "\r\n",
+            "line 1\n",
+            ".line 2\r\n",
+            "..line 3\r\n",
+            ".\r\n",
+            "line 5"
+        );
+
+        for (max_body_lines, expected) in [
+            (
+                None,
+                concat!(
+                    "Subject: test\r\n",
+                    "From: john@example.org\r\n",
+                    "\r\n",
+                    "line 1\r\n",
+                    "..line 2\r\n",
+                    "...line 3\r\n",
+                    "..\r\n",
+                    "line 5\r\n",
+                    ".\r\n"
+                ),
+            ),
+            (
+                Some(0),
+                concat!(
+                    "Subject: test\r\n",
+                    "From: john@example.org\r\n",
+                    "\r\n",
+                    ".\r\n"
+                ),
+            ),
+            (
+                Some(2),
+                concat!(
+                    "Subject: test\r\n",
+                    "From: john@example.org\r\n",
+                    "\r\n",
+                    "line 1\r\n",
+                    "..line 2\r\n",
+                    ".\r\n"
+                ),
+            ),
+        ] {
+            let mut buf = Vec::new();
+            serialize_message(&mut buf, message.as_bytes(), max_body_lines);
+            assert_eq!(String::from_utf8(buf).unwrap(), expected);
+        }
+    }
+}
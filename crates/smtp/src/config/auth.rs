@@ -36,8 +36,8 @@ use utils::config::{
 use super::{
     if_block::ConfigIf, ArcAuthConfig, ArcSealer, ConfigContext, DkimAuthConfig,
     DkimCanonicalization, DkimSigner, DmarcAuthConfig, DnsBlConfig, EnvelopeKey, IfBlock, IfThen,
-    IpRevAuthConfig, MailAuthConfig, SpfAuthConfig, VerifyStrategy, DNSBL_EHLO, DNSBL_FROM,
-    DNSBL_IP, DNSBL_IPREV, DNSBL_RETURN_PATH,
+    IpRevAuthConfig, MailAuthConfig, SpfAuthConfig, SrsConfig, VerifyStrategy, DNSBL_EHLO,
+    DNSBL_FROM, DNSBL_IP, DNSBL_IPREV, DNSBL_RETURN_PATH,
 };
 
 pub trait ConfigAuth {
@@ -109,6 +109,21 @@ impl ConfigAuth for Config {
                     .unwrap_or_else(|| IfBlock::new(VerifyStrategy::Relaxed)),
             },
             dnsbl: self.parse_dnsbl(ctx)?,
+            srs: SrsConfig {
+                enable: self
+                    .parse_if_block("auth.srs.enable", ctx, &envelope_sender_keys)?
+                    .unwrap_or_else(|| IfBlock::new(false)),
+                domain: self
+                    .value_or_default("auth.srs.domain", "server.hostname")
+                    .unwrap_or("localhost")
+                    .to_lowercase(),
+                secrets: self
+                    .values("auth.srs.secret")
+                    .filter(|(_, v)| !v.is_empty())
+                    .map(|(_, v)| v.as_bytes().to_vec())
+                    .collect(),
+                max_age: self.property_or_static("auth.srs.max-age", "21d")?,
+            },
         })
     }
 
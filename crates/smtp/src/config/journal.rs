@@ -0,0 +1,71 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use utils::config::{Config, DynValue};
+
+use super::{if_block::ConfigIf, ConfigContext, EnvelopeKey, IfBlock, JournalConfig};
+
+pub trait ConfigJournal {
+    fn parse_journal(&self, ctx: &ConfigContext) -> super::Result<JournalConfig>;
+}
+
+impl ConfigJournal for Config {
+    fn parse_journal(&self, ctx: &ConfigContext) -> super::Result<JournalConfig> {
+        let rcpt_envelope_keys = [
+            EnvelopeKey::Recipient,
+            EnvelopeKey::RecipientDomain,
+            EnvelopeKey::Sender,
+            EnvelopeKey::SenderDomain,
+            EnvelopeKey::Priority,
+        ];
+        let sender_envelope_keys = [
+            EnvelopeKey::Sender,
+            EnvelopeKey::SenderDomain,
+            EnvelopeKey::Priority,
+        ];
+        let default_hostname = self.value_require("server.hostname")?;
+
+        Ok(JournalConfig {
+            enable: self
+                .parse_if_block("journal.enable", ctx, &rcpt_envelope_keys)?
+                .unwrap_or_else(|| IfBlock::new(false)),
+            address: self
+                .parse_if_block("journal.address", ctx, &sender_envelope_keys)?
+                .unwrap_or_else(|| IfBlock::new(None)),
+            name: self
+                .parse_if_block("journal.from-name", ctx, &sender_envelope_keys)?
+                .unwrap_or_else(|| IfBlock::new("Journal".to_string())),
+            from_address: self
+                .parse_if_block("journal.from-address", ctx, &sender_envelope_keys)?
+                .unwrap_or_else(|| IfBlock::new(format!("MAILER-DAEMON@{default_hostname}"))),
+            sign: self
+                .parse_if_block::<Vec<DynValue<EnvelopeKey>>>(
+                    "journal.sign",
+                    ctx,
+                    &sender_envelope_keys,
+                )?
+                .unwrap_or_default()
+                .map_if_block(&ctx.signers, "journal.sign", "signature")?,
+        })
+    }
+}
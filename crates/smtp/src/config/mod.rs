@@ -24,13 +24,17 @@
 pub mod auth;
 pub mod condition;
 pub mod if_block;
+pub mod journal;
+pub mod quarantine;
 pub mod queue;
 pub mod remote;
 pub mod report;
 pub mod resolver;
 pub mod scripts;
 pub mod session;
+pub mod spam;
 pub mod throttle;
+pub mod webhook;
 
 use std::{
     net::{Ipv4Addr, Ipv6Addr, SocketAddr},
@@ -39,7 +43,7 @@ use std::{
     time::Duration,
 };
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use directory::{Directory, DirectoryConfig, Lookup};
 use mail_auth::{
     common::crypto::{Ed25519Key, RsaKey, Sha256},
@@ -48,11 +52,12 @@ use mail_auth::{
 };
 use mail_send::Credentials;
 use regex::Regex;
+use serde::Serialize;
 use sieve::Sieve;
 use smtp_proto::MtPriority;
 use utils::config::{DynValue, Rate, Server, ServerProtocol};
 
-use crate::inbound::milter;
+use crate::inbound::{clamav, hooks, milter};
 
 #[derive(Debug)]
 pub struct Host {
@@ -246,12 +251,35 @@ pub struct Rcpt {
 
     // Limits
     pub max_recipients: IfBlock<usize>,
+
+    // Greylisting
+    pub greylist: Greylist,
+
+    // Suppression list
+    pub suppression: Suppression,
+}
+
+pub struct Greylist {
+    pub enable: IfBlock<bool>,
+    pub delay: IfBlock<Duration>,
+    pub retry_window: IfBlock<Duration>,
+    pub lifetime: IfBlock<Duration>,
+    pub auto_whitelist: IfBlock<bool>,
+}
+
+pub struct Suppression {
+    pub enable: IfBlock<bool>,
+    pub expire_bounce: Duration,
+    pub expire_complaint: Duration,
+    pub complaint_senders: AHashSet<String>,
 }
 
 pub struct Data {
     pub script: IfBlock<Option<Arc<Sieve>>>,
     pub pipe_commands: Vec<Pipe>,
     pub milters: Vec<Milter>,
+    pub clamav: Vec<ClamAv>,
+    pub attachments: AttachmentPolicy,
 
     // Limits
     pub max_messages: IfBlock<usize>,
@@ -288,6 +316,71 @@ pub struct Milter {
     pub protocol_version: milter::Version,
 }
 
+#[derive(Default)]
+pub struct AttachmentPolicy {
+    pub enable: IfBlock<bool>,
+    pub action: IfBlock<AttachmentAction>,
+    pub blocked_extensions: AHashSet<String>,
+    pub blocked_content_types: AHashSet<String>,
+    pub blocked_file_types: AHashSet<String>,
+    pub block_macros: bool,
+    pub block_encrypted_archives: bool,
+    pub max_size: Option<usize>,
+    pub archive_max_depth: usize,
+    pub archive_max_size: usize,
+    pub archive_max_total_size: usize,
+    pub notice: String,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AttachmentAction {
+    #[default]
+    Reject,
+    Strip,
+    Quarantine,
+}
+
+pub struct ClamAv {
+    pub id: String,
+    pub enable: IfBlock<bool>,
+    pub action: IfBlock<VirusAction>,
+    pub address: ClamdAddress,
+    pub timeout_connect: Duration,
+    pub timeout_scan: Duration,
+    pub tempfail_on_error: bool,
+    pub max_size: usize,
+    pub max_idle: usize,
+    pub idle_timeout: Duration,
+    pub pool: clamav::ConnectionPool,
+}
+
+#[derive(Debug, Clone)]
+pub enum ClamdAddress {
+    Tcp(Vec<SocketAddr>),
+    Unix(PathBuf),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum VirusAction {
+    #[default]
+    Reject,
+    Quarantine,
+    Tag,
+}
+
+pub struct MtaHook {
+    pub enable: IfBlock<bool>,
+    pub url: String,
+    pub stages: Vec<hooks::Stage>,
+    pub timeout: Duration,
+    pub tls_allow_invalid_certs: bool,
+    pub tempfail_on_error: bool,
+    pub max_response_size: usize,
+    pub auth_username: Option<String>,
+    pub auth_secret: Option<String>,
+    pub client: reqwest::Client,
+}
+
 pub struct SessionConfig {
     pub timeout: IfBlock<Duration>,
     pub duration: IfBlock<Duration>,
@@ -300,6 +393,7 @@ pub struct SessionConfig {
     pub mail: Mail,
     pub rcpt: Rcpt,
     pub data: Data,
+    pub hooks: Vec<MtaHook>,
     pub extensions: Extensions,
 }
 
@@ -335,6 +429,8 @@ pub struct QueueConfig {
     pub ip_strategy: IfBlock<IpLookupStrategy>,
     pub source_ip: QueueOutboundSourceIp,
     pub tls: QueueOutboundTls,
+    pub connection: QueueOutboundConnection,
+    pub adaptive: QueueOutboundAdaptive,
     pub dsn: Dsn,
 
     // Timeouts
@@ -368,6 +464,8 @@ pub struct ReportAnalysis {
     pub addresses: Vec<AddressMatch>,
     pub forward: bool,
     pub store: Option<PathBuf>,
+    pub retention: Duration,
+    pub max_rows: usize,
     pub report_id: AtomicU64,
 }
 
@@ -416,6 +514,30 @@ pub struct QueueOutboundTls {
     pub start: IfBlock<RequireOptional>,
 }
 
+pub struct QueueOutboundConnection {
+    pub reuse: IfBlock<bool>,
+    pub max_messages: IfBlock<usize>,
+    pub max_idle: IfBlock<usize>,
+    pub idle_timeout: IfBlock<Duration>,
+}
+
+pub struct QueueOutboundAdaptive {
+    pub enable: IfBlock<bool>,
+    pub key: AdaptiveKey,
+    pub initial_rate: f64,
+    pub min_rate: f64,
+    pub max_rate: f64,
+    pub backoff_factor: f64,
+    pub ramp_up_factor: f64,
+    pub cooldown: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdaptiveKey {
+    Mx,
+    Domain,
+}
+
 pub struct QueueOutboundTimeout {
     pub connect: IfBlock<Duration>,
     pub greeting: IfBlock<Duration>,
@@ -456,14 +578,14 @@ pub enum AggregateFrequency {
     Never,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
 pub struct TlsStrategy {
     pub dane: RequireOptional,
     pub mta_sts: RequireOptional,
     pub tls: RequireOptional,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
 pub enum RequireOptional {
     #[default]
     Optional,
@@ -478,6 +600,14 @@ pub struct MailAuthConfig {
     pub dmarc: DmarcAuthConfig,
     pub iprev: IpRevAuthConfig,
     pub dnsbl: DnsBlConfig,
+    pub srs: SrsConfig,
+}
+
+pub struct SrsConfig {
+    pub enable: IfBlock<bool>,
+    pub domain: String,
+    pub secrets: Vec<Vec<u8>>,
+    pub max_age: Duration,
 }
 
 pub enum DkimSigner {
@@ -524,6 +654,114 @@ pub const DNSBL_EHLO: u32 = 1 << 2;
 pub const DNSBL_RETURN_PATH: u32 = 1 << 3;
 pub const DNSBL_FROM: u32 = 1 << 4;
 
+pub struct JournalConfig {
+    pub enable: IfBlock<bool>,
+    pub address: IfBlock<Option<String>>,
+    pub name: IfBlock<String>,
+    pub from_address: IfBlock<String>,
+    pub sign: IfBlock<Vec<MaybeDynValue<DkimSigner>>>,
+}
+
+pub struct QuarantineConfig {
+    pub retention: Duration,
+    pub digest: QuarantineDigestConfig,
+}
+
+pub struct QuarantineDigestConfig {
+    pub enable: IfBlock<bool>,
+    pub from_name: String,
+    pub from_address: String,
+    pub subject: String,
+    pub url: String,
+    pub secret: Vec<u8>,
+    pub link_expiry: Duration,
+    pub sign: IfBlock<Vec<MaybeDynValue<DkimSigner>>>,
+}
+
+pub struct WebhookConfig {
+    pub hooks: Vec<Arc<Webhook>>,
+}
+
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<WebhookType>,
+    pub secret: Option<Vec<u8>>,
+    pub timeout: Duration,
+    pub tls_allow_invalid_certs: bool,
+    pub batch_size: usize,
+    pub batch_wait: Duration,
+    pub retry_max_attempts: u32,
+    pub retry_backoff: Duration,
+    pub retry_max_backoff: Duration,
+    pub expire: Duration,
+    pub max_pending: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum WebhookType {
+    #[serde(rename = "message.queued")]
+    MessageQueued,
+    #[serde(rename = "message.rejected")]
+    MessageRejected,
+    #[serde(rename = "delivery.completed")]
+    DeliveryCompleted,
+    #[serde(rename = "delivery.deferred")]
+    DeliveryDeferred,
+    #[serde(rename = "delivery.failed")]
+    DeliveryFailed,
+    #[serde(rename = "dsn.sent")]
+    DsnSent,
+    #[serde(rename = "auth.failed")]
+    AuthFailed,
+}
+
+pub struct SpamFilterConfig {
+    pub enable: IfBlock<bool>,
+    pub threshold_spam: f64,
+    pub threshold_reject: Option<f64>,
+    pub url_lookup: Vec<String>,
+    pub block_list: Option<Arc<Lookup>>,
+    pub allow_list: Option<Arc<Lookup>>,
+    pub scores: AHashMap<&'static str, f64>,
+    pub bayes: BayesConfig,
+}
+
+// Tests evaluated by the spam filter and their default scores,
+// which can be overridden under 'spam-filter.scores'.
+pub static SPAM_FILTER_TESTS: &[(&str, f64)] = &[
+    ("MISSING_DATE", 1.0),
+    ("MISSING_MESSAGE_ID", 1.0),
+    ("MISSING_SUBJECT", 0.5),
+    ("MISSING_TO", 0.5),
+    ("SUBJECT_ALL_CAPS", 1.0),
+    ("SUBJECT_EXCESS_PUNCT", 0.5),
+    ("FROM_NAME_HAS_ADDRESS", 1.5),
+    ("REPLY_TO_DIFF_DOMAIN", 0.5),
+    ("DATE_IN_FUTURE", 1.5),
+    ("HTML_ONLY", 0.5),
+    ("SPF_FAIL", 2.0),
+    ("SPF_SOFTFAIL", 1.0),
+    ("DKIM_FAIL", 1.0),
+    ("DKIM_NONE", 0.5),
+    ("DMARC_FAIL", 2.5),
+    ("IPREV_FAIL", 1.0),
+    ("URL_IP_HOST", 1.0),
+    ("URL_LISTED", 4.0),
+    ("DOMAIN_BLOCKED", 6.0),
+    ("DOMAIN_ALLOWED", -6.0),
+    ("BAYES_SPAM", 3.0),
+    ("BAYES_HAM", -3.0),
+];
+
+pub struct BayesConfig {
+    pub enable: bool,
+    pub min_learns: u32,
+    pub min_token_hits: u32,
+    pub spam_threshold: f64,
+    pub ham_threshold: f64,
+}
+
 #[derive(Debug, Clone)]
 pub struct DkimCanonicalization {
     pub headers: Canonicalization,
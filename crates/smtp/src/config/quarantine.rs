@@ -0,0 +1,80 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use utils::config::{Config, DynValue};
+
+use super::{
+    if_block::ConfigIf, ConfigContext, EnvelopeKey, IfBlock, QuarantineConfig,
+    QuarantineDigestConfig,
+};
+
+pub trait ConfigQuarantine {
+    fn parse_quarantine(&self, ctx: &ConfigContext) -> super::Result<QuarantineConfig>;
+}
+
+impl ConfigQuarantine for Config {
+    fn parse_quarantine(&self, ctx: &ConfigContext) -> super::Result<QuarantineConfig> {
+        let rcpt_envelope_keys = [EnvelopeKey::Recipient, EnvelopeKey::RecipientDomain];
+        let default_hostname = self.value_require("server.hostname")?;
+
+        Ok(QuarantineConfig {
+            retention: self.property_or_static("quarantine.retention", "30d")?,
+            digest: QuarantineDigestConfig {
+                enable: self
+                    .parse_if_block("quarantine.digest.enable", ctx, &rcpt_envelope_keys)?
+                    .unwrap_or_else(|| IfBlock::new(false)),
+                from_name: self
+                    .value("quarantine.digest.from-name")
+                    .unwrap_or("Quarantine")
+                    .to_string(),
+                from_address: self
+                    .value("quarantine.digest.from-address")
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| format!("MAILER-DAEMON@{default_hostname}")),
+                subject: self
+                    .value("quarantine.digest.subject")
+                    .unwrap_or("Quarantined messages digest")
+                    .to_string(),
+                url: self
+                    .value("quarantine.digest.url")
+                    .map(|v| v.trim_end_matches('/').to_string())
+                    .unwrap_or_else(|| format!("https://{default_hostname}")),
+                secret: self
+                    .value("quarantine.digest.secret")
+                    .filter(|v| !v.is_empty())
+                    .map(|v| v.as_bytes().to_vec())
+                    // Release links do not survive a restart without a configured secret
+                    .unwrap_or_else(|| rand::random::<[u8; 32]>().to_vec()),
+                link_expiry: self.property_or_static("quarantine.digest.link-expiry", "7d")?,
+                sign: self
+                    .parse_if_block::<Vec<DynValue<EnvelopeKey>>>(
+                        "quarantine.digest.sign",
+                        ctx,
+                        &[EnvelopeKey::Sender, EnvelopeKey::SenderDomain],
+                    )?
+                    .unwrap_or_default()
+                    .map_if_block(&ctx.signers, "quarantine.digest.sign", "signature")?,
+            },
+        })
+    }
+}
@@ -41,6 +41,7 @@ pub trait ConfigQueue {
     fn parse_queue(&self, ctx: &ConfigContext) -> super::Result<QueueConfig>;
     fn parse_queue_throttle(&self, ctx: &ConfigContext) -> super::Result<QueueThrottle>;
     fn parse_queue_quota(&self, ctx: &ConfigContext) -> super::Result<QueueQuotas>;
+    fn parse_queue_adaptive(&self, ctx: &ConfigContext) -> super::Result<QueueOutboundAdaptive>;
     fn parse_queue_quota_item(
         &self,
         prefix: impl AsKey,
@@ -149,6 +150,29 @@ impl ConfigQueue for Config {
                     .parse_if_block("queue.outbound.tls.starttls", ctx, &mx_envelope_keys)?
                     .unwrap_or_else(|| IfBlock::new(RequireOptional::Optional)),
             },
+            connection: QueueOutboundConnection {
+                reuse: self
+                    .parse_if_block("queue.outbound.connection.reuse", ctx, &mx_envelope_keys)?
+                    .unwrap_or_else(|| IfBlock::new(false)),
+                max_messages: self
+                    .parse_if_block(
+                        "queue.outbound.connection.max-messages",
+                        ctx,
+                        &mx_envelope_keys,
+                    )?
+                    .unwrap_or_else(|| IfBlock::new(100)),
+                max_idle: self
+                    .parse_if_block("queue.outbound.connection.max-idle", ctx, &mx_envelope_keys)?
+                    .unwrap_or_else(|| IfBlock::new(5)),
+                idle_timeout: self
+                    .parse_if_block(
+                        "queue.outbound.connection.idle-timeout",
+                        ctx,
+                        &mx_envelope_keys,
+                    )?
+                    .unwrap_or_else(|| IfBlock::new(Duration::from_secs(30))),
+            },
+            adaptive: self.parse_queue_adaptive(ctx)?,
             throttle: self.parse_queue_throttle(ctx)?,
             quota: self.parse_queue_quota(ctx)?,
             timeout: QueueOutboundTimeout {
@@ -219,6 +243,72 @@ impl ConfigQueue for Config {
         }
     }
 
+    fn parse_queue_adaptive(&self, ctx: &ConfigContext) -> super::Result<QueueOutboundAdaptive> {
+        let mx_envelope_keys = [
+            EnvelopeKey::RecipientDomain,
+            EnvelopeKey::Sender,
+            EnvelopeKey::SenderDomain,
+            EnvelopeKey::Priority,
+            EnvelopeKey::Mx,
+        ];
+        let mut rates = [0.0; 3];
+        for (rate, (key, default)) in
+            rates
+                .iter_mut()
+                .zip([("initial", "60/1m"), ("min", "1/1m"), ("max", "1000/1m")])
+        {
+            let key = ("queue.outbound.adaptive.rate", key);
+            let value = self.property_or_static::<Rate>(key, default)?;
+            if value.period.is_zero() {
+                return Err(format!("Property {:?} cannot be unlimited.", key.as_key()));
+            }
+            *rate = value.requests as f64 / value.period.as_secs_f64();
+        }
+        let [initial_rate, min_rate, max_rate] = rates;
+        if min_rate <= 0.0 {
+            return Err(
+                "Property \"queue.outbound.adaptive.rate.min\" must be greater than zero."
+                    .to_string(),
+            );
+        }
+        if min_rate > initial_rate || initial_rate > max_rate {
+            return Err(
+                "Property \"queue.outbound.adaptive.rate.initial\" must be between the minimum and maximum rates."
+                    .to_string(),
+            );
+        }
+
+        let backoff_factor =
+            self.property_or_static::<f64>("queue.outbound.adaptive.backoff-factor", "0.5")?;
+        if !(backoff_factor > 0.0 && backoff_factor < 1.0) {
+            return Err(
+                "Property \"queue.outbound.adaptive.backoff-factor\" must be between 0 and 1."
+                    .to_string(),
+            );
+        }
+        let ramp_up_factor =
+            self.property_or_static::<f64>("queue.outbound.adaptive.ramp-up-factor", "1.05")?;
+        if ramp_up_factor < 1.0 {
+            return Err(
+                "Property \"queue.outbound.adaptive.ramp-up-factor\" must be at least 1."
+                    .to_string(),
+            );
+        }
+
+        Ok(QueueOutboundAdaptive {
+            enable: self
+                .parse_if_block("queue.outbound.adaptive.enable", ctx, &mx_envelope_keys)?
+                .unwrap_or_else(|| IfBlock::new(false)),
+            key: self.property_or_static("queue.outbound.adaptive.key", "mx")?,
+            initial_rate,
+            min_rate,
+            max_rate,
+            backoff_factor,
+            ramp_up_factor,
+            cooldown: self.property_or_static("queue.outbound.adaptive.cooldown", "5m")?,
+        })
+    }
+
     fn parse_queue_throttle(&self, ctx: &ConfigContext) -> super::Result<QueueThrottle> {
         // Parse throttle
         let mut throttle = QueueThrottle {
@@ -461,3 +551,17 @@ impl ParseValue for RequireOptional {
         }
     }
 }
+
+impl ParseValue for AdaptiveKey {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "mx" => Ok(AdaptiveKey::Mx),
+            "domain" => Ok(AdaptiveKey::Domain),
+            _ => Err(format!(
+                "Invalid adaptive rate key {:?} for property {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
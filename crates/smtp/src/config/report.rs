@@ -97,6 +97,8 @@ impl ConfigReport for Config {
                 addresses,
                 forward: self.property("report.analysis.forward")?.unwrap_or(false),
                 store: self.property("report.analysis.store")?,
+                retention: self.property_or_static("report.analysis.retention", "90d")?,
+                max_rows: self.property_or_static("report.analysis.max-rows", "1000")?,
                 report_id: 0.into(),
             },
         })
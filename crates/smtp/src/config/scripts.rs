@@ -49,7 +49,6 @@ impl ConfigSieve for Config {
             .with_max_includes(10);
         let mut runtime = Runtime::new()
             .without_capabilities([
-                Capability::FileInto,
                 Capability::Vacation,
                 Capability::VacationSeconds,
                 Capability::Fcc,
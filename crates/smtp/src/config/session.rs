@@ -40,6 +40,8 @@ pub trait ConfigSession {
     fn parse_session_auth(&self, ctx: &ConfigContext) -> super::Result<Auth>;
     fn parse_session_mail(&self, ctx: &ConfigContext) -> super::Result<Mail>;
     fn parse_session_rcpt(&self, ctx: &ConfigContext) -> super::Result<Rcpt>;
+    fn parse_session_greylist(&self, ctx: &ConfigContext) -> super::Result<Greylist>;
+    fn parse_session_suppression(&self, ctx: &ConfigContext) -> super::Result<Suppression>;
     fn parse_session_data(&self, ctx: &ConfigContext) -> super::Result<Data>;
     fn parse_pipes(
         &self,
@@ -51,6 +53,13 @@ pub trait ConfigSession {
         ctx: &ConfigContext,
         available_keys: &[EnvelopeKey],
     ) -> super::Result<Vec<Milter>>;
+    fn parse_clamav(
+        &self,
+        ctx: &ConfigContext,
+        available_keys: &[EnvelopeKey],
+    ) -> super::Result<Vec<ClamAv>>;
+    fn parse_attachment_policy(&self, ctx: &ConfigContext) -> super::Result<AttachmentPolicy>;
+    fn parse_mta_hooks(&self, ctx: &ConfigContext) -> super::Result<Vec<MtaHook>>;
 }
 
 impl ConfigSession for Config {
@@ -80,6 +89,7 @@ impl ConfigSession for Config {
             mail: self.parse_session_mail(ctx)?,
             rcpt: self.parse_session_rcpt(ctx)?,
             data: self.parse_session_data(ctx)?,
+            hooks: self.parse_mta_hooks(ctx)?,
             extensions: self.parse_extensions(ctx)?,
         })
     }
@@ -356,6 +366,65 @@ impl ConfigSession for Config {
                     &available_keys,
                 )?
                 .unwrap_or_default(),
+            greylist: self.parse_session_greylist(ctx)?,
+            suppression: self.parse_session_suppression(ctx)?,
+        })
+    }
+
+    fn parse_session_greylist(&self, ctx: &ConfigContext) -> super::Result<Greylist> {
+        let available_keys = [
+            EnvelopeKey::Sender,
+            EnvelopeKey::SenderDomain,
+            EnvelopeKey::Recipient,
+            EnvelopeKey::RecipientDomain,
+            EnvelopeKey::AuthenticatedAs,
+            EnvelopeKey::Listener,
+            EnvelopeKey::RemoteIp,
+            EnvelopeKey::LocalIp,
+            EnvelopeKey::HeloDomain,
+        ];
+        Ok(Greylist {
+            enable: self
+                .parse_if_block("session.rcpt.greylist.enable", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(false)),
+            delay: self
+                .parse_if_block("session.rcpt.greylist.delay", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(Duration::from_secs(5 * 60))),
+            retry_window: self
+                .parse_if_block("session.rcpt.greylist.retry-window", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(Duration::from_secs(24 * 60 * 60))),
+            lifetime: self
+                .parse_if_block("session.rcpt.greylist.lifetime", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(Duration::from_secs(35 * 24 * 60 * 60))),
+            auto_whitelist: self
+                .parse_if_block("session.rcpt.greylist.auto-whitelist", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(true)),
+        })
+    }
+
+    fn parse_session_suppression(&self, ctx: &ConfigContext) -> super::Result<Suppression> {
+        let available_keys = [
+            EnvelopeKey::Sender,
+            EnvelopeKey::SenderDomain,
+            EnvelopeKey::Recipient,
+            EnvelopeKey::RecipientDomain,
+            EnvelopeKey::AuthenticatedAs,
+            EnvelopeKey::Listener,
+            EnvelopeKey::RemoteIp,
+            EnvelopeKey::LocalIp,
+        ];
+        Ok(Suppression {
+            enable: self
+                .parse_if_block("session.rcpt.suppression.enable", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(false)),
+            expire_bounce: self
+                .property_or_static("session.rcpt.suppression.expire.bounce", "30d")?,
+            expire_complaint: self
+                .property_or_static("session.rcpt.suppression.expire.complaint", "365d")?,
+            complaint_senders: self
+                .values("session.rcpt.suppression.complaint-senders")
+                .map(|(_, value)| value.trim().to_lowercase())
+                .collect(),
         })
     }
 
@@ -412,6 +481,8 @@ impl ConfigSession for Config {
                 .unwrap_or_else(|| IfBlock::new(true)),
             pipe_commands: self.parse_pipes(ctx, &available_keys)?,
             milters: self.parse_milters(ctx, &available_keys)?,
+            clamav: self.parse_clamav(ctx, &available_keys)?,
+            attachments: self.parse_attachment_policy(ctx)?,
         })
     }
 
@@ -489,8 +560,257 @@ impl ConfigSession for Config {
         }
         Ok(milters)
     }
+
+    fn parse_clamav(
+        &self,
+        ctx: &ConfigContext,
+        available_keys: &[EnvelopeKey],
+    ) -> super::Result<Vec<ClamAv>> {
+        let mut scanners = Vec::new();
+        for id in self.sub_keys("session.data.clamav") {
+            let address = if let Some(path) = self.value(("session.data.clamav", id, "socket")) {
+                if cfg!(not(unix)) {
+                    return Err(format!(
+                        "Unix sockets are not supported on this platform (clamav {id:?})."
+                    ));
+                }
+                ClamdAddress::Unix(path.into())
+            } else {
+                let hostname = self
+                    .value(("session.data.clamav", id, "hostname"))
+                    .unwrap_or("127.0.0.1");
+                let port: u16 =
+                    self.property_or_static(("session.data.clamav", id, "port"), "3310")?;
+                ClamdAddress::Tcp(
+                    format!("{}:{}", hostname, port)
+                        .to_socket_addrs()
+                        .map_err(|err| {
+                            format!("Unable to resolve clamd hostname {hostname}: {err}")
+                        })?
+                        .collect(),
+                )
+            };
+
+            scanners.push(ClamAv {
+                id: id.to_string(),
+                enable: self
+                    .parse_if_block(("session.data.clamav", id, "enable"), ctx, available_keys)?
+                    .unwrap_or_default(),
+                action: self
+                    .parse_if_block(("session.data.clamav", id, "action"), ctx, available_keys)?
+                    .unwrap_or_default(),
+                address,
+                timeout_connect: self
+                    .property_or_static(("session.data.clamav", id, "timeout.connect"), "10s")?,
+                timeout_scan: self
+                    .property_or_static(("session.data.clamav", id, "timeout.scan"), "60s")?,
+                tempfail_on_error: self.property_or_static(
+                    ("session.data.clamav", id, "options.tempfail-on-error"),
+                    "true",
+                )?,
+                max_size: self.property_or_static(
+                    ("session.data.clamav", id, "options.max-size"),
+                    "26214400",
+                )?,
+                max_idle: self
+                    .property_or_static(("session.data.clamav", id, "pool.max-idle"), "8")?,
+                idle_timeout: self
+                    .property_or_static(("session.data.clamav", id, "pool.idle-timeout"), "20s")?,
+                pool: Default::default(),
+            });
+        }
+        Ok(scanners)
+    }
+
+    fn parse_attachment_policy(&self, ctx: &ConfigContext) -> super::Result<AttachmentPolicy> {
+        let available_keys = [
+            EnvelopeKey::Sender,
+            EnvelopeKey::SenderDomain,
+            EnvelopeKey::Recipient,
+            EnvelopeKey::RecipientDomain,
+            EnvelopeKey::AuthenticatedAs,
+            EnvelopeKey::Listener,
+            EnvelopeKey::RemoteIp,
+            EnvelopeKey::LocalIp,
+            EnvelopeKey::Priority,
+        ];
+        let parse_list = |key: &str, default: &[&str]| {
+            let list = self
+                .values(("session.data.attachments", key))
+                .map(|(_, value)| value.trim_start_matches('.').to_lowercase())
+                .collect::<AHashSet<_>>();
+            if !list.is_empty() {
+                list
+            } else {
+                default.iter().map(|value| value.to_string()).collect()
+            }
+        };
+
+        Ok(AttachmentPolicy {
+            enable: self
+                .parse_if_block("session.data.attachments.enable", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(false)),
+            action: self
+                .parse_if_block("session.data.attachments.action", ctx, &available_keys)?
+                .unwrap_or_default(),
+            blocked_extensions: parse_list("block.extensions", BLOCKED_EXTENSIONS),
+            blocked_content_types: parse_list("block.content-types", BLOCKED_CONTENT_TYPES),
+            blocked_file_types: parse_list("block.file-types", BLOCKED_FILE_TYPES),
+            block_macros: self
+                .property_or_static("session.data.attachments.block.macros", "true")?,
+            block_encrypted_archives: self
+                .property_or_static("session.data.attachments.block.encrypted-archives", "false")?,
+            max_size: self.property("session.data.attachments.limits.size")?,
+            archive_max_depth: self
+                .property_or_static("session.data.attachments.archives.max-depth", "3")?,
+            archive_max_size: self
+                .property_or_static("session.data.attachments.archives.max-size", "52428800")?,
+            archive_max_total_size: self.property_or_static(
+                "session.data.attachments.archives.max-total-size",
+                "104857600",
+            )?,
+            notice: self
+                .value("session.data.attachments.notice")
+                .unwrap_or(concat!(
+                    "This attachment was removed because it violates ",
+                    "the attachment policy of this mail server."
+                ))
+                .to_string(),
+        })
+    }
+
+    fn parse_mta_hooks(&self, ctx: &ConfigContext) -> super::Result<Vec<MtaHook>> {
+        let available_keys = [
+            EnvelopeKey::Sender,
+            EnvelopeKey::SenderDomain,
+            EnvelopeKey::AuthenticatedAs,
+            EnvelopeKey::Listener,
+            EnvelopeKey::RemoteIp,
+            EnvelopeKey::LocalIp,
+            EnvelopeKey::Priority,
+            EnvelopeKey::HeloDomain,
+        ];
+        let mut mta_hooks = Vec::new();
+        for id in self.sub_keys("session.hook") {
+            let mut stages = self
+                .properties::<hooks::Stage>(("session.hook", id, "stages"))
+                .map(|result| result.map(|(_, stage)| stage))
+                .collect::<super::Result<Vec<_>>>()?;
+            if stages.is_empty() {
+                stages.push(hooks::Stage::Data);
+            }
+
+            let timeout = self.property_or_static(("session.hook", id, "timeout"), "30s")?;
+            let tls_allow_invalid_certs =
+                self.property_or_static(("session.hook", id, "allow-invalid-certs"), "false")?;
+
+            mta_hooks.push(MtaHook {
+                enable: self
+                    .parse_if_block(("session.hook", id, "enable"), ctx, &available_keys)?
+                    .unwrap_or_default(),
+                url: self.value_require(("session.hook", id, "url"))?.to_string(),
+                stages,
+                timeout,
+                tls_allow_invalid_certs,
+                tempfail_on_error: self.property_or_static(
+                    ("session.hook", id, "options.tempfail-on-error"),
+                    "true",
+                )?,
+                max_response_size: self.property_or_static(
+                    ("session.hook", id, "options.max-response-size"),
+                    "52428800",
+                )?,
+                auth_username: self
+                    .value(("session.hook", id, "auth.username"))
+                    .map(|v| v.to_string()),
+                auth_secret: self
+                    .value(("session.hook", id, "auth.secret"))
+                    .map(|v| v.to_string()),
+                client: reqwest::Client::builder()
+                    .user_agent(crate::USER_AGENT)
+                    .timeout(timeout)
+                    .danger_accept_invalid_certs(tls_allow_invalid_certs)
+                    .redirect(reqwest::redirect::Policy::none())
+                    .build()
+                    .map_err(|err| {
+                        format!("Failed to build HTTP client for MTA hook {id:?}: {err}")
+                    })?,
+            });
+        }
+        Ok(mta_hooks)
+    }
+}
+
+impl ParseValue for AttachmentAction {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "reject" => Ok(AttachmentAction::Reject),
+            "strip" => Ok(AttachmentAction::Strip),
+            "quarantine" => Ok(AttachmentAction::Quarantine),
+            _ => Err(format!(
+                "Invalid attachment action {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
 }
 
+impl ParseValue for VirusAction {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "reject" => Ok(VirusAction::Reject),
+            "quarantine" => Ok(VirusAction::Quarantine),
+            "tag" => Ok(VirusAction::Tag),
+            _ => Err(format!(
+                "Invalid virus action {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
+impl ParseValue for hooks::Stage {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "connect" => Ok(hooks::Stage::Connect),
+            "ehlo" => Ok(hooks::Stage::Ehlo),
+            "mail" => Ok(hooks::Stage::Mail),
+            "rcpt" => Ok(hooks::Stage::Rcpt),
+            "data" => Ok(hooks::Stage::Data),
+            _ => Err(format!(
+                "Invalid MTA hook stage {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
+const BLOCKED_EXTENSIONS: &[&str] = &[
+    "ade", "adp", "app", "bat", "chm", "cmd", "com", "cpl", "dll", "docm", "dotm", "exe", "hta",
+    "img", "iso", "jar", "js", "jse", "lnk", "msc", "msi", "msp", "pif", "potm", "ppam", "ppsm",
+    "pptm", "ps1", "reg", "scr", "sldm", "vbe", "vbs", "vhd", "vhdx", "wsc", "wsf", "wsh", "xlam",
+    "xlsm", "xltm",
+];
+
+const BLOCKED_CONTENT_TYPES: &[&str] = &[
+    "application/x-msdownload",
+    "application/x-msdos-program",
+    "application/x-executable",
+    "application/x-ms-installer",
+    "application/x-iso9660-image",
+    "application/java-archive",
+    "application/javascript",
+    "text/javascript",
+    "application/vnd.ms-excel.sheet.macroenabled.12",
+    "application/vnd.ms-powerpoint.presentation.macroenabled.12",
+    "application/vnd.ms-word.document.macroenabled.12",
+];
+
+const BLOCKED_FILE_TYPES: &[&str] = &["exe", "elf", "macho", "iso", "lnk"];
+
 struct Mechanism {
     mechanism: u64,
 }
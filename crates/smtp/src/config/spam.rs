@@ -0,0 +1,119 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use ahash::AHashMap;
+use utils::config::Config;
+
+use super::{
+    BayesConfig, ConfigContext, EnvelopeKey, IfBlock, SpamFilterConfig, SPAM_FILTER_TESTS,
+};
+
+pub trait ConfigSpamFilter {
+    fn parse_spam_filter(&self, ctx: &ConfigContext) -> super::Result<SpamFilterConfig>;
+}
+
+impl ConfigSpamFilter for Config {
+    fn parse_spam_filter(&self, ctx: &ConfigContext) -> super::Result<SpamFilterConfig> {
+        let available_keys = [
+            EnvelopeKey::Sender,
+            EnvelopeKey::SenderDomain,
+            EnvelopeKey::AuthenticatedAs,
+            EnvelopeKey::Listener,
+            EnvelopeKey::RemoteIp,
+            EnvelopeKey::LocalIp,
+            EnvelopeKey::Priority,
+        ];
+
+        // Parse scores, using the built-in defaults for tests not present in the configuration
+        let mut scores = AHashMap::with_capacity(SPAM_FILTER_TESTS.len());
+        for (test, default_score) in SPAM_FILTER_TESTS {
+            let key = test.to_ascii_lowercase().replace('_', "-");
+            scores.insert(
+                *test,
+                self.property::<f64>(("spam-filter.scores", key.as_str()))?
+                    .unwrap_or(*default_score),
+            );
+        }
+        for key in self.sub_keys("spam-filter.scores") {
+            if !scores
+                .keys()
+                .any(|test| test.to_ascii_lowercase().replace('_', "-") == key)
+            {
+                return Err(format!(
+                    "Unknown spam filter test {key:?} found in \"spam-filter.scores\"."
+                ));
+            }
+        }
+
+        Ok(SpamFilterConfig {
+            enable: self
+                .parse_if_block("spam-filter.enable", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(false)),
+            threshold_spam: self.property_or_static("spam-filter.threshold.spam", "5.0")?,
+            threshold_reject: self.property("spam-filter.threshold.reject")?,
+            url_lookup: self
+                .values("spam-filter.lists.url-lookup")
+                .filter_map(|(_, v)| {
+                    if !v.is_empty() {
+                        if !v.ends_with('.') {
+                            format!("{v}.")
+                        } else {
+                            v.to_string()
+                        }
+                        .into()
+                    } else {
+                        None
+                    }
+                })
+                .collect(),
+            block_list: parse_lookup(self, ctx, "spam-filter.lists.block")?,
+            allow_list: parse_lookup(self, ctx, "spam-filter.lists.allow")?,
+            scores,
+            bayes: BayesConfig {
+                enable: self.property_or_static("spam-filter.bayes.enable", "true")?,
+                min_learns: self.property_or_static("spam-filter.bayes.min-learns", "20")?,
+                min_token_hits: self.property_or_static("spam-filter.bayes.min-token-hits", "2")?,
+                spam_threshold: self
+                    .property_or_static("spam-filter.bayes.threshold.spam", "0.9")?,
+                ham_threshold: self.property_or_static("spam-filter.bayes.threshold.ham", "0.1")?,
+            },
+        })
+    }
+}
+
+fn parse_lookup(
+    config: &Config,
+    ctx: &ConfigContext,
+    key: &str,
+) -> super::Result<Option<std::sync::Arc<directory::Lookup>>> {
+    if let Some(id) = config.value(key) {
+        ctx.directory
+            .lookups
+            .get(id)
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| format!("Lookup {id:?} not found for property {key:?}."))
+    } else {
+        Ok(None)
+    }
+}
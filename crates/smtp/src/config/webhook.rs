@@ -0,0 +1,101 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use utils::config::{
+    utils::{AsKey, ParseValue},
+    Config,
+};
+
+use super::{Webhook, WebhookConfig, WebhookType};
+
+pub trait ConfigWebhook {
+    fn parse_webhooks(&self) -> super::Result<WebhookConfig>;
+}
+
+impl ConfigWebhook for Config {
+    fn parse_webhooks(&self) -> super::Result<WebhookConfig> {
+        let mut hooks = Vec::new();
+        for id in self.sub_keys("webhook") {
+            let url = self.value_require(("webhook", id, "url"))?.to_string();
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(format!(
+                    "Invalid URL {url:?} for webhook {id:?}, expected an HTTP(S) endpoint."
+                ));
+            }
+
+            hooks.push(Arc::new(Webhook {
+                id: id.to_string(),
+                url,
+                events: self
+                    .properties::<WebhookType>(("webhook", id, "events"))
+                    .map(|result| result.map(|(_, event)| event))
+                    .collect::<super::Result<Vec<_>>>()?,
+                secret: self
+                    .value(("webhook", id, "secret"))
+                    .filter(|v| !v.is_empty())
+                    .map(|v| v.as_bytes().to_vec()),
+                timeout: self.property_or_static(("webhook", id, "timeout"), "30s")?,
+                tls_allow_invalid_certs: self
+                    .property_or_static(("webhook", id, "allow-invalid-certs"), "false")?,
+                batch_size: std::cmp::max(
+                    self.property_or_static(("webhook", id, "batch.size"), "50")?,
+                    1,
+                ),
+                batch_wait: self.property_or_static(("webhook", id, "batch.wait"), "5s")?,
+                retry_max_attempts: self
+                    .property_or_static(("webhook", id, "retry.max-attempts"), "10")?,
+                retry_backoff: self.property_or_static(("webhook", id, "retry.backoff"), "30s")?,
+                retry_max_backoff: self
+                    .property_or_static(("webhook", id, "retry.max-backoff"), "1h")?,
+                expire: self.property_or_static(("webhook", id, "expire"), "3d")?,
+                max_pending: std::cmp::max(
+                    self.property_or_static(("webhook", id, "max-pending"), "10000")?,
+                    1,
+                ),
+            }));
+        }
+
+        Ok(WebhookConfig { hooks })
+    }
+}
+
+impl ParseValue for WebhookType {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "message.queued" => Ok(WebhookType::MessageQueued),
+            "message.rejected" => Ok(WebhookType::MessageRejected),
+            "delivery.completed" => Ok(WebhookType::DeliveryCompleted),
+            "delivery.deferred" => Ok(WebhookType::DeliveryDeferred),
+            "delivery.failed" => Ok(WebhookType::DeliveryFailed),
+            "dsn.sent" => Ok(WebhookType::DsnSent),
+            "auth.failed" => Ok(WebhookType::AuthFailed),
+            _ => Err(format!(
+                "Invalid webhook event type {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
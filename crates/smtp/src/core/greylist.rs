@@ -0,0 +1,297 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    net::IpAddr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use mail_auth::{DkimOutput, DkimResult, SpfResult};
+use serde::Serialize;
+use store::{
+    write::{key::KeySerializer, now, BatchBuilder, Operation, ValueClass},
+    CustomValueKey, Deserialize, ServerNamespace, Store,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::{Session, SMTP};
+
+const GREYLIST_NAMESPACE: u8 = ServerNamespace::Greylist as u8;
+const GREYLIST_TRIPLET: u8 = 0;
+const GREYLIST_DOMAIN: u8 = 1;
+
+#[derive(Debug, Default)]
+pub struct GreylistStats {
+    pub greylisted: AtomicU64,
+    pub passed: AtomicU64,
+    pub whitelisted: AtomicU64,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct GreylistReport {
+    pub greylisted: u64,
+    pub passed: u64,
+    pub whitelisted: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Triplet {
+    expires: u64,
+    not_before: u64,
+}
+
+impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
+    /// Returns true if delivery to the last recipient has to be deferred.
+    pub async fn is_greylisted(&self) -> bool {
+        let config = &self.core.session.config.rcpt.greylist;
+        let store = match &self.core.store {
+            Some(store) if *config.enable.eval(self).await => store,
+            _ => return false,
+        };
+        let mail_from = self.data.mail_from.as_ref().unwrap();
+        let rcpt = self.data.rcpt_to.last().unwrap();
+        let stats = &self.core.session.greylist;
+        let auto_whitelist = *config.auto_whitelist.eval(self).await;
+        let lifetime = *config.lifetime.eval(self).await;
+        let now = now();
+
+        let result = async {
+            // Domains that authenticated or retried before skip greylisting
+            if auto_whitelist
+                && !mail_from.domain.is_empty()
+                && store
+                    .get_value::<u64>(CustomValueKey {
+                        value: greylist_domain_key(&mail_from.domain),
+                    })
+                    .await?
+                    .map_or(false, |expires| expires > now)
+            {
+                stats.whitelisted.fetch_add(1, Ordering::Relaxed);
+                return Ok(false);
+            }
+
+            let triplet_key = greylist_triplet_key(
+                self.data.remote_ip,
+                &mail_from.address_lcase,
+                &rcpt.address_lcase,
+            );
+            let mut batch = BatchBuilder::new();
+            let is_greylisted = match store
+                .get_value::<Triplet>(CustomValueKey {
+                    value: triplet_key.clone(),
+                })
+                .await?
+            {
+                Some(triplet) if triplet.expires > now && triplet.not_before > now => true,
+                Some(triplet) if triplet.expires > now => {
+                    // Sender retried after the delay, whitelist its domain
+                    if auto_whitelist && triplet.not_before != 0 && !mail_from.domain.is_empty() {
+                        batch.op(Operation::Value {
+                            class: ValueClass::Custom {
+                                bytes: greylist_domain_key(&mail_from.domain),
+                            },
+                            set: store::Serialize::serialize(now + lifetime.as_secs()).into(),
+                        });
+                    }
+                    batch.op(Operation::Value {
+                        class: ValueClass::Custom { bytes: triplet_key },
+                        set: Triplet {
+                            expires: now + lifetime.as_secs(),
+                            not_before: 0,
+                        }
+                        .serialize()
+                        .into(),
+                    });
+                    false
+                }
+                _ => {
+                    let delay = config.delay.eval(self).await.as_secs();
+                    let retry_window = config.retry_window.eval(self).await.as_secs();
+                    batch.op(Operation::Value {
+                        class: ValueClass::Custom { bytes: triplet_key },
+                        set: Triplet {
+                            expires: now + delay + retry_window,
+                            not_before: now + delay,
+                        }
+                        .serialize()
+                        .into(),
+                    });
+                    true
+                }
+            };
+            store.write(batch.build()).await?;
+
+            Ok::<_, store::Error>(is_greylisted)
+        }
+        .await;
+
+        match result {
+            Ok(true) => {
+                stats.greylisted.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Ok(false) => {
+                stats.passed.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            Err(err) => {
+                tracing::warn!(parent: &self.span,
+                    context = "greylist",
+                    event = "error",
+                    reason = %err,
+                    "Failed to check greylist triplet.");
+                false
+            }
+        }
+    }
+
+    /// Whitelists the sender domain when it was authenticated by SPF or an aligned DKIM signature.
+    pub async fn greylist_whitelist_authenticated(&self, dkim_output: &[DkimOutput<'_>]) {
+        let config = &self.core.session.config.rcpt.greylist;
+        let mail_from = self.data.mail_from.as_ref().unwrap();
+        let store = match &self.core.store {
+            Some(store)
+                if !mail_from.domain.is_empty()
+                    && *config.enable.eval(self).await
+                    && *config.auto_whitelist.eval(self).await =>
+            {
+                store
+            }
+            _ => return,
+        };
+
+        if self
+            .data
+            .spf_mail_from
+            .as_ref()
+            .map_or(false, |spf| spf.result() == SpfResult::Pass)
+            || dkim_output.iter().any(|output| {
+                matches!(output.result(), DkimResult::Pass)
+                    && output.signature().map_or(false, |s| {
+                        s.domain().eq_ignore_ascii_case(&mail_from.domain)
+                    })
+            })
+        {
+            let expires = now() + config.lifetime.eval(self).await.as_secs();
+            let mut batch = BatchBuilder::new();
+            batch.op(Operation::Value {
+                class: ValueClass::Custom {
+                    bytes: greylist_domain_key(&mail_from.domain),
+                },
+                set: store::Serialize::serialize(expires).into(),
+            });
+            if let Err(err) = store.write(batch.build()).await {
+                tracing::warn!(parent: &self.span,
+                    context = "greylist",
+                    event = "error",
+                    reason = %err,
+                    "Failed to whitelist sender domain.");
+            }
+        }
+    }
+}
+
+impl SMTP {
+    pub async fn greylist_purge(&self, store: &Store) -> store::Result<()> {
+        let now = now();
+        for kind in [GREYLIST_TRIPLET, GREYLIST_DOMAIN] {
+            store
+                .purge_expired_values(
+                    KeySerializer::new(std::mem::size_of::<u32>() + 2)
+                        .write(u32::MAX)
+                        .write(GREYLIST_NAMESPACE)
+                        .write(kind)
+                        .finalize(),
+                    now,
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub fn greylist_report(&self) -> GreylistReport {
+        let stats = &self.session.greylist;
+        GreylistReport {
+            greylisted: stats.greylisted.load(Ordering::Relaxed),
+            passed: stats.passed.load(Ordering::Relaxed),
+            whitelisted: stats.whitelisted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn greylist_triplet_key(remote_ip: IpAddr, sender: &str, rcpt: &str) -> Vec<u8> {
+    // Senders retrying from a different host on the same network are not greylisted again
+    let mut hasher = blake3::Hasher::new();
+    match remote_ip {
+        IpAddr::V4(ip) => {
+            hasher.update(&ip.octets()[..3]);
+        }
+        IpAddr::V6(ip) => {
+            hasher.update(&ip.octets()[..8]);
+        }
+    }
+    hasher.update(&[0]);
+    hasher.update(sender.as_bytes());
+    hasher.update(&[0]);
+    hasher.update(rcpt.as_bytes());
+
+    KeySerializer::new(std::mem::size_of::<u32>() + 34)
+        .write(u32::MAX)
+        .write(GREYLIST_NAMESPACE)
+        .write(GREYLIST_TRIPLET)
+        .write(hasher.finalize().as_bytes().as_slice())
+        .finalize()
+}
+
+fn greylist_domain_key(domain: &str) -> Vec<u8> {
+    KeySerializer::new(std::mem::size_of::<u32>() + 2 + domain.len())
+        .write(u32::MAX)
+        .write(GREYLIST_NAMESPACE)
+        .write(GREYLIST_DOMAIN)
+        .write(domain.as_bytes())
+        .finalize()
+}
+
+impl Triplet {
+    fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(2 * std::mem::size_of::<u64>());
+        bytes.extend_from_slice(&self.expires.to_be_bytes());
+        bytes.extend_from_slice(&self.not_before.to_be_bytes());
+        bytes
+    }
+}
+
+impl Deserialize for Triplet {
+    fn deserialize(bytes: &[u8]) -> store::Result<Self> {
+        if bytes.len() == 2 * std::mem::size_of::<u64>() {
+            Ok(Triplet {
+                expires: u64::from_be_bytes(bytes[..8].try_into().unwrap()),
+                not_before: u64::from_be_bytes(bytes[8..].try_into().unwrap()),
+            })
+        } else {
+            Err(store::Error::InternalError(
+                "Failed to deserialize greylist triplet".to_string(),
+            ))
+        }
+    }
+}
@@ -21,10 +21,16 @@
  * for more details.
 */
 
-use std::{borrow::Cow, fmt::Display, net::IpAddr, sync::Arc, time::Instant};
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use directory::Type;
-use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full};
+use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full, Limited};
 use hyper::{
     body::{self, Bytes},
     header::{self, AUTHORIZATION},
@@ -36,22 +42,30 @@ use hyper_util::rt::TokioIo;
 use mail_parser::{decoders::base64::base64_decode, DateTime};
 use mail_send::Credentials;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sieve::Envelope;
+use store::{audit::AuditEvent, quarantine::QuarantineFilter};
 use tokio::{
     io::{AsyncRead, AsyncWrite},
     sync::oneshot,
 };
 
-use utils::listener::{limiter::InFlight, SessionManager};
+use utils::{
+    config::utils::ParseValue,
+    listener::{limiter::InFlight, SessionManager},
+};
 
 use crate::{
     queue::{self, instant_to_timestamp, InstantFromTimestamp, QueueId, Status},
     reporting::{
         self,
+        incoming::IncomingReportFilter,
         scheduler::{ReportKey, ReportPolicy, ReportType, ReportValue},
     },
 };
 
-use super::{SmtpAdminSessionManager, SMTP};
+use super::{suppression::SuppressionReason, SmtpAdminSessionManager, SMTP};
+
+const MAX_REQUEST_SIZE: usize = 50 * 1024 * 1024;
 
 #[derive(Debug)]
 pub enum QueueRequest {
@@ -156,24 +170,31 @@ pub struct Report {
     pub size: usize,
 }
 
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct AdaptiveRate {
+    pub key: String,
+    pub rate: f64,
+    pub successes: u64,
+    pub deferrals: u64,
+    #[serde(deserialize_with = "deserialize_maybe_datetime")]
+    #[serde(serialize_with = "serialize_maybe_datetime")]
+    pub next_slot: Option<DateTime>,
+    #[serde(deserialize_with = "deserialize_maybe_datetime")]
+    #[serde(serialize_with = "serialize_maybe_datetime")]
+    pub last_deferral: Option<DateTime>,
+}
+
 impl SessionManager for SmtpAdminSessionManager {
     fn spawn(&self, session: utils::listener::SessionData<tokio::net::TcpStream>) {
         let core = self.inner.clone();
         tokio::spawn(async move {
-            if let Some(tls_acceptor) = &session.instance.tls_acceptor {
-                match tls_acceptor.accept(session.stream).await {
-                    Ok(stream) => {
-                        handle_request(stream, core, session.remote_ip, session.in_flight).await;
-                    }
-                    Err(err) => {
-                        tracing::debug!(
-                            context = "tls",
-                            event = "error",
-                            remote.ip = session.remote_ip.to_string(),
-                            "Failed to accept TLS management connection: {}",
-                            err
-                        );
-                    }
+            if session.instance.tls_acceptor.is_some() {
+                if let Ok(stream) = session
+                    .instance
+                    .tls_accept(session.stream, &session.span)
+                    .await
+                {
+                    handle_request(stream, core, session.remote_ip, session.in_flight).await;
                 }
             } else {
                 handle_request(session.stream, core, session.remote_ip, session.in_flight).await;
@@ -200,13 +221,14 @@ async fn handle_request(
                 let core = core.clone();
 
                 async move {
-                    let response = core.parse_request(&req).await;
+                    let uri = req.uri().to_string();
+                    let response = core.parse_request(req, remote_addr).await;
 
                     tracing::debug!(
                         context = "management",
                         event = "request",
                         remote.ip = remote_addr.to_string(),
-                        uri = req.uri().to_string(),
+                        uri = uri,
                         status = match &response {
                             Ok(response) => response.status().to_string(),
                             Err(error) => error.to_string(),
@@ -231,10 +253,11 @@ async fn handle_request(
 impl SMTP {
     async fn parse_request(
         &self,
-        req: &hyper::Request<hyper::body::Incoming>,
+        req: hyper::Request<hyper::body::Incoming>,
+        remote_addr: IpAddr,
     ) -> Result<hyper::Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
         // Authenticate request
-        let mut is_authenticated = false;
+        let mut account_name = None;
         if let Some((mechanism, payload)) = req
             .headers()
             .get(AUTHORIZATION)
@@ -259,7 +282,7 @@ impl SMTP {
                         .await
                     {
                         Ok(Some(principal)) if principal.typ == Type::Superuser => {
-                            is_authenticated = true;
+                            account_name = principal.name.into();
                         }
                         Ok(Some(_)) => {
                             tracing::debug!(
@@ -299,7 +322,9 @@ impl SMTP {
                 );
             }
         }
-        if !is_authenticated {
+        let account_name = if let Some(account_name) = account_name {
+            account_name
+        } else {
             return Ok(hyper::Response::builder()
                 .status(StatusCode::UNAUTHORIZED)
                 .header(header::WWW_AUTHENTICATE, "Basic realm=\"Stalwart SMTP\"")
@@ -309,17 +334,39 @@ impl SMTP {
                         .boxed(),
                 )
                 .unwrap());
-        }
+        };
 
-        let mut path = req.uri().path().split('/');
+        let (parts, body) = req.into_parts();
+        let body = if parts.method == Method::POST {
+            match Limited::new(body, MAX_REQUEST_SIZE).collect().await {
+                Ok(body) => body.to_bytes(),
+                Err(_) => {
+                    return Ok(hyper::Response::builder()
+                        .status(StatusCode::PAYLOAD_TOO_LARGE)
+                        .body(
+                            Empty::<Bytes>::new()
+                                .map_err(|never| match never {})
+                                .boxed(),
+                        )
+                        .unwrap());
+                }
+            }
+        } else {
+            Bytes::new()
+        };
+
+        let mut path = parts.uri.path().split('/');
         path.next();
         path.next(); // Skip the leading /admin
         Ok(self
             .handle_manage_request(
-                req.uri(),
-                req.method(),
+                &parts.uri,
+                &parts.method,
                 path.next().unwrap_or_default(),
                 path.next().unwrap_or_default(),
+                &body,
+                &account_name,
+                remote_addr,
             )
             .await)
     }
@@ -330,6 +377,9 @@ impl SMTP {
         method: &Method,
         path_1: &str,
         path_2: &str,
+        body: &[u8],
+        account_name: &str,
+        remote_addr: impl Display,
     ) -> hyper::Response<BoxBody<Bytes, hyper::Error>> {
         let (status, response) = match (method, path_1, path_2) {
             (&Method::GET, "queue", "list") => {
@@ -516,16 +566,32 @@ impl SMTP {
 
                 match error {
                     None => {
+                        let event = AuditEvent::new("http", "queue.cancel")
+                            .with_account(account_name)
+                            .with_remote_ip(&remote_addr)
+                            .with_target(
+                                queue_ids
+                                    .iter()
+                                    .map(|id| format!("{id:X}"))
+                                    .collect::<Vec<_>>()
+                                    .join(","),
+                            )
+                            .with_details(item.clone().unwrap_or_default());
                         let (result_tx, result_rx) = oneshot::channel();
-                        self.send_queue_event(
-                            QueueRequest::Cancel {
-                                queue_ids,
-                                item,
-                                result_tx,
-                            },
-                            result_rx,
-                        )
-                        .await
+                        let response = self
+                            .send_queue_event(
+                                QueueRequest::Cancel {
+                                    queue_ids,
+                                    item,
+                                    result_tx,
+                                },
+                                result_rx,
+                            )
+                            .await;
+                        if response.0 == StatusCode::OK {
+                            self.record_audit(event).await;
+                        }
+                        response
                     }
                     Some(error) => error.into_bad_request(),
                 }
@@ -642,19 +708,492 @@ impl SMTP {
 
                 match error {
                     None => {
+                        let event = AuditEvent::new("http", "report.cancel")
+                            .with_account(account_name)
+                            .with_remote_ip(&remote_addr)
+                            .with_target(
+                                report_ids
+                                    .iter()
+                                    .map(|id| id.to_string())
+                                    .collect::<Vec<_>>()
+                                    .join(","),
+                            );
                         let (result_tx, result_rx) = oneshot::channel();
-                        self.send_report_event(
-                            ReportRequest::Cancel {
-                                report_ids,
-                                result_tx,
+                        let response = self
+                            .send_report_event(
+                                ReportRequest::Cancel {
+                                    report_ids,
+                                    result_tx,
+                                },
+                                result_rx,
+                            )
+                            .await;
+                        if response.0 == StatusCode::OK {
+                            self.record_audit(event).await;
+                        }
+                        response
+                    }
+                    Some(error) => error.into_bad_request(),
+                }
+            }
+            (&Method::GET, "report", path_2 @ ("received" | "aggregate")) => {
+                let mut filter = IncomingReportFilter::default();
+                let mut group_by = Vec::new();
+                let mut limit = 100;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "type" => match value.parse() {
+                                Ok(type_) => {
+                                    filter.type_ = Some(type_);
+                                }
+                                Err(_) => {
+                                    error = format!("Invalid report type {value:?}.").into();
+                                    break;
+                                }
                             },
-                            result_rx,
-                        )
-                        .await
+                            "domain" => {
+                                filter.domain = value.into_owned().into();
+                            }
+                            "reporter" => {
+                                filter.reporter = value.into_owned().into();
+                            }
+                            "ip" => match value.parse() {
+                                Ok(ip) => {
+                                    filter.source_ip = Some(ip);
+                                }
+                                Err(_) => {
+                                    error = format!("Invalid IP address {value:?}.").into();
+                                    break;
+                                }
+                            },
+                            "disposition" => {
+                                filter.disposition = value.into_owned().into();
+                            }
+                            "failures" => {
+                                filter.failures_only = value == "true" || value == "1";
+                            }
+                            "from" | "to" => match value.parse::<u64>() {
+                                Ok(timestamp) if key == "from" => {
+                                    filter.from = timestamp.into();
+                                }
+                                Ok(timestamp) => {
+                                    filter.to = timestamp.into();
+                                }
+                                Err(_) => {
+                                    error = format!("Invalid timestamp {value:?}.").into();
+                                    break;
+                                }
+                            },
+                            "limit" if path_2 == "received" => match value.parse() {
+                                Ok(value) => {
+                                    limit = value;
+                                }
+                                Err(_) => {
+                                    error = format!("Invalid limit {value:?}.").into();
+                                    break;
+                                }
+                            },
+                            "group-by" if path_2 == "aggregate" => {
+                                for item in value.split(',').filter(|item| !item.is_empty()) {
+                                    match item.parse() {
+                                        Ok(item) => {
+                                            group_by.push(item);
+                                        }
+                                        Err(_) => {
+                                            error = format!("Invalid group {item:?}.").into();
+                                            break;
+                                        }
+                                    }
+                                }
+                                if error.is_some() {
+                                    break;
+                                }
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match error {
+                    None if path_2 == "received" => {
+                        match self.incoming_report_query(filter, limit).await {
+                            Ok(reports) => (
+                                StatusCode::OK,
+                                serde_json::to_string(&Response { data: reports })
+                                    .unwrap_or_default(),
+                            ),
+                            Err(err) => err.into_internal_error(),
+                        }
                     }
+                    None => match self.incoming_report_aggregate(filter, group_by).await {
+                        Ok(groups) => (
+                            StatusCode::OK,
+                            serde_json::to_string(&Response { data: groups }).unwrap_or_default(),
+                        ),
+                        Err(err) => err.into_internal_error(),
+                    },
                     Some(error) => error.into_bad_request(),
                 }
             }
+            (&Method::POST, "sieve", "test") => {
+                let mut script = None;
+                let mut envelope = Vec::new();
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "script" => {
+                                script = value.into_owned().into();
+                            }
+                            "from" => {
+                                envelope.push((Envelope::From, value.into_owned()));
+                            }
+                            "to" => {
+                                envelope.push((Envelope::To, value.into_owned()));
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match (error, script) {
+                    (None, Some(script)) if !body.is_empty() => {
+                        match self.test_script(&script, body, envelope).await {
+                            Some(actions) => (
+                                StatusCode::OK,
+                                serde_json::to_string(&Response { data: actions })
+                                    .unwrap_or_default(),
+                            ),
+                            None => (
+                                StatusCode::NOT_FOUND,
+                                format!(
+                                    "{{\"error\": \"not-found\", \"details\": {}}}",
+                                    serde_json::to_string(&format!(
+                                        "Script {script:?} does not exist."
+                                    ))
+                                    .unwrap()
+                                ),
+                            ),
+                        }
+                    }
+                    (None, Some(_)) => "Missing message body.".to_string().into_bad_request(),
+                    (None, None) => "Missing script name.".to_string().into_bad_request(),
+                    (Some(error), _) => error.into_bad_request(),
+                }
+            }
+            (&Method::GET, "queue", "adaptive") => (
+                StatusCode::OK,
+                serde_json::to_string(&Response {
+                    data: self.queue.adaptive_report(),
+                })
+                .unwrap_or_default(),
+            ),
+            (&Method::GET, "suppression", "list") => {
+                let mut domain = None;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "domain" => {
+                                domain = value.into_owned().into();
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match error {
+                    None => match self.suppression_list(domain.as_deref()).await {
+                        Ok(entries) => (
+                            StatusCode::OK,
+                            serde_json::to_string(&Response { data: entries }).unwrap_or_default(),
+                        ),
+                        Err(err) => err.into_internal_error(),
+                    },
+                    Some(error) => error.into_bad_request(),
+                }
+            }
+            (&Method::GET, "suppression", "add") => {
+                let mut address = None;
+                let mut reason = SuppressionReason::Manual;
+                let mut expires = None;
+                let mut details = String::new();
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "address" => {
+                                address = value.into_owned().into();
+                            }
+                            "reason" => match value.parse() {
+                                Ok(value) => {
+                                    reason = value;
+                                }
+                                Err(_) => {
+                                    error = format!("Invalid reason {value:?}.").into();
+                                    break;
+                                }
+                            },
+                            "expires" => match Duration::parse_value("expires", &value) {
+                                Ok(value) => {
+                                    expires = (store::write::now() + value.as_secs()).into();
+                                }
+                                Err(reason) => {
+                                    error = reason.into();
+                                    break;
+                                }
+                            },
+                            "details" => {
+                                details = value.into_owned();
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match (error, address) {
+                    (None, Some(address)) if !address.trim().is_empty() => {
+                        match self.suppress(&address, reason, &details, expires).await {
+                            Ok(_) => {
+                                self.record_audit(
+                                    AuditEvent::new("http", "suppression.add")
+                                        .with_account(account_name)
+                                        .with_remote_ip(&remote_addr)
+                                        .with_target(&address)
+                                        .with_details(details),
+                                )
+                                .await;
+                                (
+                                    StatusCode::OK,
+                                    serde_json::to_string(&Response { data: true })
+                                        .unwrap_or_default(),
+                                )
+                            }
+                            Err(err) => err.into_internal_error(),
+                        }
+                    }
+                    (None, _) => "Missing address.".to_string().into_bad_request(),
+                    (Some(error), _) => error.into_bad_request(),
+                }
+            }
+            (&Method::GET, "suppression", "remove") => {
+                let mut address = None;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "address" => {
+                                address = value.into_owned().into();
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match (error, address) {
+                    (None, Some(address)) => match self.unsuppress(&address).await {
+                        Ok(removed) => {
+                            if removed {
+                                self.record_audit(
+                                    AuditEvent::new("http", "suppression.remove")
+                                        .with_account(account_name)
+                                        .with_remote_ip(&remote_addr)
+                                        .with_target(&address),
+                                )
+                                .await;
+                            }
+                            (
+                                StatusCode::OK,
+                                serde_json::to_string(&Response { data: removed })
+                                    .unwrap_or_default(),
+                            )
+                        }
+                        Err(err) => err.into_internal_error(),
+                    },
+                    (None, None) => "Missing address.".to_string().into_bad_request(),
+                    (Some(error), _) => error.into_bad_request(),
+                }
+            }
+            (&Method::GET, "quarantine", "list") => {
+                let mut filter = QuarantineFilter::default();
+                let mut limit = 100;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "rcpt" => {
+                                filter.recipient = value.into_owned().into();
+                            }
+                            "source" => {
+                                filter.source = value.into_owned().into();
+                            }
+                            "text" => {
+                                filter.text = value.into_owned().into();
+                            }
+                            "from" | "to" => match value.parse::<u64>() {
+                                Ok(timestamp) if key == "from" => {
+                                    filter.from = timestamp.into();
+                                }
+                                Ok(timestamp) => {
+                                    filter.to = timestamp.into();
+                                }
+                                Err(_) => {
+                                    error = format!("Invalid timestamp {value:?}.").into();
+                                    break;
+                                }
+                            },
+                            "limit" => match value.parse() {
+                                Ok(value) => {
+                                    limit = value;
+                                }
+                                Err(_) => {
+                                    error = format!("Invalid limit {value:?}.").into();
+                                    break;
+                                }
+                            },
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match (error, &self.store) {
+                    (None, Some(store)) => match store.quarantine_query(filter, limit).await {
+                        Ok(messages) => (
+                            StatusCode::OK,
+                            serde_json::to_string(&Response { data: messages }).unwrap_or_default(),
+                        ),
+                        Err(err) => err.into_internal_error(),
+                    },
+                    (None, None) => (
+                        StatusCode::OK,
+                        serde_json::to_string(&Response {
+                            data: Vec::<()>::new(),
+                        })
+                        .unwrap_or_default(),
+                    ),
+                    (Some(error), _) => error.into_bad_request(),
+                }
+            }
+            (&Method::GET, "quarantine", path_2 @ ("preview" | "release" | "delete")) => {
+                let mut id = None;
+                let mut rcpt = None;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "id" => match value.parse::<u32>() {
+                                Ok(value) => {
+                                    id = value.into();
+                                }
+                                Err(_) => {
+                                    error = format!("Invalid id {value:?}.").into();
+                                    break;
+                                }
+                            },
+                            "rcpt" if path_2 == "release" => {
+                                rcpt = value.into_owned().into();
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match (error, id) {
+                    (None, Some(id)) => {
+                        let result = match path_2 {
+                            "preview" => self.quarantine_preview(id).await.map(|preview| {
+                                preview.map(|preview| {
+                                    serde_json::to_string(&Response { data: preview })
+                                        .unwrap_or_default()
+                                })
+                            }),
+                            "release" => self
+                                .quarantine_release(id, None, rcpt.as_deref())
+                                .await
+                                .map(|released| {
+                                    released.map(|released| {
+                                        serde_json::to_string(&Response { data: released })
+                                            .unwrap_or_default()
+                                    })
+                                }),
+                            _ => match &self.store {
+                                Some(store) => match store.get_quarantined(id).await {
+                                    Ok(Some(_)) => store.delete_quarantined(id).await.map(|_| {
+                                        serde_json::to_string(&Response { data: true })
+                                            .unwrap_or_default()
+                                            .into()
+                                    }),
+                                    Ok(None) => Ok(None),
+                                    Err(err) => Err(err),
+                                },
+                                None => Ok(None),
+                            },
+                        };
+
+                        match result {
+                            Ok(Some(response)) => {
+                                if path_2 != "preview" {
+                                    self.record_audit(
+                                        AuditEvent::new("http", format!("quarantine.{path_2}"))
+                                            .with_account(account_name)
+                                            .with_remote_ip(&remote_addr)
+                                            .with_target(id.to_string())
+                                            .with_details(rcpt.unwrap_or_default()),
+                                    )
+                                    .await;
+                                }
+                                (StatusCode::OK, response)
+                            }
+                            Ok(None) => (
+                                StatusCode::NOT_FOUND,
+                                format!(
+                                    "{{\"error\": \"not-found\", \"details\": \"Quarantined message {id} does not exist.\"}}",
+                                ),
+                            ),
+                            Err(err) => err.into_internal_error(),
+                        }
+                    }
+                    (None, None) => "Missing id.".to_string().into_bad_request(),
+                    (Some(error), _) => error.into_bad_request(),
+                }
+            }
+            (&Method::GET, "greylist", "stats") => (
+                StatusCode::OK,
+                serde_json::to_string(&Response {
+                    data: self.greylist_report(),
+                })
+                .unwrap_or_default(),
+            ),
             _ => (
                 StatusCode::NOT_FOUND,
                 format!(
@@ -675,6 +1214,12 @@ impl SMTP {
             .unwrap()
     }
 
+    async fn record_audit(&self, event: AuditEvent) {
+        if let Some(store) = &self.store {
+            self.audit.record(store, event).await;
+        }
+    }
+
     async fn send_queue_event<T: Serialize>(
         &self,
         request: QueueRequest,
@@ -940,6 +1485,27 @@ impl BadRequest for String {
     }
 }
 
+trait InternalError {
+    fn into_internal_error(self) -> (StatusCode, String);
+}
+
+impl InternalError for store::Error {
+    fn into_internal_error(self) -> (StatusCode, String) {
+        tracing::warn!(
+            context = "manage",
+            event = "error",
+            reason = %self,
+            "Store operation failed."
+        );
+
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "{\"error\": \"internal-error\", \"details\": \"Store operation failed, try again later.\"}"
+                .to_string(),
+        )
+    }
+}
+
 fn is_zero(num: &i16) -> bool {
     *num == 0
 }
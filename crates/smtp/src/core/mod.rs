@@ -36,6 +36,7 @@ use sieve::{Runtime, Sieve};
 use smtp_proto::request::receiver::{
     BdatReceiver, DataReceiver, DummyDataReceiver, DummyLineReceiver, LineReceiver, RequestReceiver,
 };
+use store::{audit::AuditLog, Store};
 use tokio::{
     io::{AsyncRead, AsyncWrite},
     sync::mpsc,
@@ -49,24 +50,37 @@ use utils::{
 
 use crate::{
     config::{
-        DkimSigner, MailAuthConfig, QueueConfig, ReportConfig, SessionConfig, VerifyStrategy,
+        DkimSigner, JournalConfig, MailAuthConfig, QuarantineConfig, QueueConfig, ReportConfig,
+        SessionConfig, SpamFilterConfig, VerifyStrategy, WebhookConfig,
     },
     inbound::auth::SaslToken,
     outbound::{
         dane::{DnssecResolver, Tlsa},
         mta_sts,
+        pool::ConnectionPool,
     },
-    queue::{self, DomainPart, QueueId, QuotaLimiter},
+    queue::{self, adaptive::AdaptiveLimiter, DomainPart, QueueId, QuotaLimiter},
     reporting,
 };
 
-use self::throttle::{Limiter, ThrottleKey, ThrottleKeyHasherBuilder};
+use self::{
+    greylist::GreylistStats,
+    quarantine::QuarantineReason,
+    throttle::{Limiter, ThrottleKey, ThrottleKeyHasherBuilder},
+    webhook::WebhookEvent,
+};
 
+pub mod greylist;
 pub mod if_block;
 pub mod management;
 pub mod params;
+pub mod quarantine;
 pub mod scripts;
+pub mod spam;
+pub mod srs;
+pub mod suppression;
 pub mod throttle;
+pub mod webhook;
 pub mod worker;
 
 #[derive(Clone)]
@@ -99,6 +113,11 @@ pub struct SMTP {
     pub mail_auth: MailAuthConfig,
     pub report: ReportCore,
     pub sieve: SieveCore,
+    pub spam: SpamFilterConfig,
+    pub quarantine: QuarantineConfig,
+    pub webhook: WebhookCore,
+    pub store: Option<Arc<Store>>,
+    pub audit: AuditLog,
     #[cfg(feature = "local_delivery")]
     pub delivery_tx: mpsc::Sender<DeliveryEvent>,
 }
@@ -132,6 +151,7 @@ pub struct DnsCache {
 pub struct SessionCore {
     pub config: SessionConfig,
     pub throttle: DashMap<ThrottleKey, Limiter, ThrottleKeyHasherBuilder>,
+    pub greylist: GreylistStats,
 }
 
 pub struct QueueCore {
@@ -141,6 +161,9 @@ pub struct QueueCore {
     pub tx: mpsc::Sender<queue::Event>,
     pub id_seq: AtomicU32,
     pub connectors: TlsConnectors,
+    pub connections: ConnectionPool,
+    pub adaptive: DashMap<String, AdaptiveLimiter>,
+    pub journal: JournalConfig,
 }
 
 pub struct ReportCore {
@@ -148,6 +171,11 @@ pub struct ReportCore {
     pub tx: mpsc::Sender<reporting::Event>,
 }
 
+pub struct WebhookCore {
+    pub config: WebhookConfig,
+    pub tx: mpsc::Sender<WebhookEvent>,
+}
+
 pub struct TlsConnectors {
     pub pki_verify: TlsConnector,
     pub dummy_verify: TlsConnector,
@@ -201,6 +229,8 @@ pub struct SessionData {
     pub spf_ehlo: Option<SpfOutput>,
     pub spf_mail_from: Option<SpfOutput>,
     pub dnsbl_error: Option<Vec<u8>>,
+
+    pub quarantine: Option<QuarantineReason>,
 }
 
 #[derive(Clone)]
@@ -267,6 +297,7 @@ impl SessionData {
             spf_ehlo: None,
             spf_mail_from: None,
             dnsbl_error: None,
+            quarantine: None,
         }
     }
 }
@@ -386,6 +417,7 @@ static ref SIEVE: Arc<ServerInstance> = Arc::new(utils::listener::ServerInstance
     hostname: "localhost".to_string(),
     data: "localhost".to_string(),
     tls_acceptor: None,
+    acme_acceptor: None,
     is_tls_implicit: true,
     limiter: utils::listener::limiter::ConcurrencyLimiter::new(0),
     shutdown_rx: tokio::sync::watch::channel(false).1,
@@ -501,6 +533,7 @@ impl SessionData {
             spf_ehlo: None,
             spf_mail_from: None,
             dnsbl_error: None,
+            quarantine: None,
         }
     }
 }
@@ -0,0 +1,470 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    borrow::Cow,
+    fmt::Write,
+    net::{IpAddr, Ipv4Addr},
+};
+
+use ahash::AHashMap;
+use hmac::{Hmac, Mac};
+use mail_builder::{headers::date::Date, mime::make_boundary};
+use mail_parser::{DateTime, HeaderValue, Message as ParsedMessage};
+use serde::Serialize;
+use sha2::Sha256;
+use store::{
+    quarantine::{QuarantineFilter, QuarantinedMessage},
+    write::now,
+    Store,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use utils::config::KeyLookup;
+
+use crate::{
+    config::EnvelopeKey,
+    queue::{DomainPart, Message},
+};
+
+use super::{Session, SMTP};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuarantineReason {
+    pub source: &'static str,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuarantinePreview {
+    #[serde(flatten)]
+    pub message: QuarantinedMessage,
+    pub headers: String,
+    pub preview: String,
+}
+
+const PREVIEW_MAX_LEN: usize = 4096;
+
+impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
+    /// Stores a message in quarantine instead of queueing it, returns false on failure.
+    pub async fn hold_in_quarantine(
+        &self,
+        quarantine: QuarantineReason,
+        message: &Message,
+        headers: &[u8],
+        raw_message: &[u8],
+    ) -> bool {
+        let store = if let Some(store) = &self.core.store {
+            store
+        } else {
+            tracing::warn!(parent: &self.span,
+                context = "quarantine",
+                event = "error",
+                reason = "No store configured",
+                "Failed to quarantine message.");
+            return false;
+        };
+
+        let parsed = ParsedMessage::parse(raw_message);
+        let created = now();
+        let entry = QuarantinedMessage {
+            created,
+            expires: created + self.core.quarantine.retention.as_secs(),
+            source: quarantine.source.to_string(),
+            reason: quarantine.reason,
+            return_path: message.return_path.clone(),
+            recipients: message
+                .recipients
+                .iter()
+                .map(|rcpt| rcpt.address.clone())
+                .collect(),
+            from: parsed
+                .as_ref()
+                .and_then(|parsed| match parsed.from() {
+                    HeaderValue::Address(addr) => addr.address.as_ref().map(|a| a.to_string()),
+                    HeaderValue::AddressList(list) => list
+                        .first()
+                        .and_then(|addr| addr.address.as_ref().map(|a| a.to_string())),
+                    _ => None,
+                })
+                .unwrap_or_default(),
+            subject: parsed
+                .as_ref()
+                .and_then(|parsed| parsed.subject())
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+            remote_ip: self.data.remote_ip.to_string(),
+            ..Default::default()
+        };
+
+        let mut bytes = Vec::with_capacity(headers.len() + raw_message.len());
+        bytes.extend_from_slice(headers);
+        bytes.extend_from_slice(raw_message);
+
+        match store.quarantine_message(entry, &bytes).await {
+            Ok(id) => {
+                tracing::info!(parent: &self.span,
+                    context = "quarantine",
+                    event = "hold",
+                    id = id,
+                    source = quarantine.source,
+                    return_path = message.return_path,
+                    nrcpts = message.recipients.len(),
+                    size = bytes.len(),
+                    "Message held in quarantine.");
+                true
+            }
+            Err(err) => {
+                tracing::warn!(parent: &self.span,
+                    context = "quarantine",
+                    event = "error",
+                    reason = %err,
+                    "Failed to quarantine message.");
+                false
+            }
+        }
+    }
+}
+
+impl SMTP {
+    /// Queues a quarantined message for delivery to all its recipients, or to a single one.
+    /// Returns the released recipients, or None if the message or recipient was not found
+    /// or the message does not match the nonce of a release link.
+    pub async fn quarantine_release(
+        &self,
+        id: u32,
+        nonce: Option<u64>,
+        rcpt: Option<&str>,
+    ) -> store::Result<Option<Vec<String>>> {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return Ok(None),
+        };
+
+        // Recipients are removed from the entry before queueing so they are released only once
+        let (entry, released) = match store.claim_quarantined(id, nonce, rcpt).await? {
+            Some(claimed) => claimed,
+            None => return Ok(None),
+        };
+        let raw_message = match store.get_quarantined_blob(id).await {
+            Ok(Some(raw_message)) => raw_message,
+            result => {
+                store.update_quarantined(&entry).await?;
+                return Err(result.err().unwrap_or_else(|| {
+                    store::Error::InternalError(format!(
+                        "Blob of quarantined message {id} not found"
+                    ))
+                }));
+            }
+        };
+
+        // Queue a copy of the message for the released recipients
+        let span = tracing::info_span!("quarantine-release", id = id);
+        let return_path_lcase = entry.return_path.to_lowercase();
+        let return_path_domain = return_path_lcase.domain_part().to_string();
+        let mut message = Message::new_boxed(
+            entry.return_path.as_str(),
+            return_path_lcase,
+            return_path_domain,
+        );
+        for rcpt in &released {
+            message
+                .add_recipient(rcpt.as_str(), &self.queue.config)
+                .await;
+        }
+        if !self
+            .queue
+            .queue_message(message, None, &raw_message, &span)
+            .await
+        {
+            store.update_quarantined(&entry).await?;
+            return Err(store::Error::InternalError(format!(
+                "Failed to queue quarantined message {id}"
+            )));
+        }
+
+        tracing::info!(parent: &span,
+            context = "quarantine",
+            event = "release",
+            id = id,
+            recipients = ?released,
+            "Quarantined message released.");
+
+        // The message is removed once all of its recipients have been released
+        if entry.recipients.len() == released.len() {
+            store.delete_quarantined(id).await?;
+        }
+
+        Ok(Some(released))
+    }
+
+    /// Returns the metadata, headers and the beginning of the text body of a quarantined message.
+    pub async fn quarantine_preview(&self, id: u32) -> store::Result<Option<QuarantinePreview>> {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return Ok(None),
+        };
+        let message = match store.get_quarantined(id).await? {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+        let raw_message = store.get_quarantined_blob(id).await?.unwrap_or_default();
+        let (headers, preview) = match ParsedMessage::parse(&raw_message) {
+            Some(parsed) => (
+                raw_message
+                    .get(parsed.root_part().offset_header..parsed.root_part().offset_body)
+                    .map(|headers| String::from_utf8_lossy(headers).into_owned())
+                    .unwrap_or_default(),
+                parsed
+                    .body_text(0)
+                    .map(|text| text.chars().take(PREVIEW_MAX_LEN).collect())
+                    .unwrap_or_default(),
+            ),
+            None => Default::default(),
+        };
+
+        Ok(Some(QuarantinePreview {
+            message,
+            headers,
+            preview,
+        }))
+    }
+
+    pub fn quarantine_release_url(&self, id: u32, nonce: u64, rcpt: &str, expires: u64) -> String {
+        format!(
+            "{}/quarantine/release?id={}&nonce={}&rcpt={}&expires={}&sig={}",
+            self.quarantine.digest.url,
+            id,
+            nonce,
+            form_urlencoded::byte_serialize(rcpt.as_bytes()).collect::<String>(),
+            expires,
+            self.quarantine_link_mac(id, nonce, rcpt, expires)
+                .finalize()
+                .into_bytes()
+                .iter()
+                .fold(String::with_capacity(64), |mut sig, byte| {
+                    let _ = write!(sig, "{byte:02x}");
+                    sig
+                })
+        )
+    }
+
+    /// Verifies the signature and expiration of a release link. The nonce still has
+    /// to be checked against the quarantined message, which is done on release.
+    pub fn quarantine_verify_link(
+        &self,
+        id: u32,
+        nonce: u64,
+        rcpt: &str,
+        expires: u64,
+        sig: &str,
+    ) -> bool {
+        if expires < now() || sig.len() != 64 || !sig.is_ascii() {
+            return false;
+        }
+        let sig = (0..sig.len())
+            .step_by(2)
+            .filter_map(|pos| u8::from_str_radix(&sig[pos..pos + 2], 16).ok())
+            .collect::<Vec<_>>();
+        sig.len() == 32
+            && self
+                .quarantine_link_mac(id, nonce, rcpt, expires)
+                .verify_slice(&sig)
+                .is_ok()
+    }
+
+    /// Sends each recipient a digest of the messages quarantined since the last run.
+    pub async fn quarantine_send_digests(&self) {
+        let store = match &self.store {
+            Some(store) if self.has_quarantine_digest() => store,
+            _ => return,
+        };
+        let entries = match store
+            .quarantine_query(
+                QuarantineFilter {
+                    not_notified: true,
+                    ..Default::default()
+                },
+                0,
+            )
+            .await
+        {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::warn!(
+                    context = "quarantine",
+                    event = "error",
+                    reason = %err,
+                    "Failed to obtain quarantined messages."
+                );
+                return;
+            }
+        };
+
+        // Group messages by recipient
+        let config = &self.quarantine.digest;
+        let mut digests: AHashMap<String, Vec<&QuarantinedMessage>> = AHashMap::new();
+        let mut disabled = Vec::new();
+        for entry in &entries {
+            for rcpt in &entry.recipients {
+                let rcpt = rcpt.to_lowercase();
+                if let Some(digest) = digests.get_mut(&rcpt) {
+                    digest.push(entry);
+                } else if !disabled.contains(&rcpt) {
+                    let envelope = DigestEnvelope {
+                        rcpt: &rcpt,
+                        domain: rcpt.domain_part(),
+                    };
+                    if *config.enable.eval(&envelope).await {
+                        digests.insert(rcpt, vec![entry]);
+                    } else {
+                        disabled.push(rcpt);
+                    }
+                }
+            }
+        }
+
+        let span = tracing::info_span!("quarantine-digest", nrcpts = digests.len());
+        let expires = now() + config.link_expiry.as_secs();
+        let retention_days = self.quarantine.retention.as_secs() / 86400;
+        for (rcpt, messages) in digests {
+            let mut body = String::with_capacity(256 * messages.len());
+            let _ = write!(
+                body,
+                concat!(
+                    "The following messages addressed to you have been held in quarantine.\r\n",
+                    "They will be deleted after {} days unless released.\r\n\r\n"
+                ),
+                retention_days
+            );
+            for message in &messages {
+                let _ = write!(
+                    body,
+                    concat!(
+                        "From: {}\r\n",
+                        "Subject: {}\r\n",
+                        "Date: {}\r\n",
+                        "Reason: {}\r\n",
+                        "Release: {}\r\n\r\n"
+                    ),
+                    if !message.from.is_empty() {
+                        &message.from
+                    } else {
+                        &message.return_path
+                    },
+                    message.subject,
+                    DateTime::from_timestamp(message.created as i64).to_rfc822(),
+                    message.reason,
+                    self.quarantine_release_url(message.id, message.nonce, &rcpt, expires)
+                );
+            }
+
+            let from_name = config.from_name.replace('"', "");
+            let report = format!(
+                concat!(
+                    "From: \"{from_name}\" <{from_addr}>\r\n",
+                    "To: <{to_addr}>\r\n",
+                    "Subject: {subject}\r\n",
+                    "Date: {date}\r\n",
+                    "Message-ID: <{message_id}@{hostname}>\r\n",
+                    "Auto-Submitted: auto-generated\r\n",
+                    "MIME-Version: 1.0\r\n",
+                    "Content-Type: text/plain; charset=\"utf-8\"\r\n",
+                    "Content-Transfer-Encoding: 8bit\r\n\r\n",
+                    "{body}"
+                ),
+                from_name = from_name,
+                from_addr = config.from_address,
+                to_addr = rcpt,
+                subject = config.subject,
+                date = Date::now().to_rfc822(),
+                message_id = make_boundary("."),
+                hostname = config.from_address.domain_part(),
+                body = body,
+            );
+            self.send_report(
+                &config.from_address,
+                [rcpt.as_str()].iter(),
+                report.into_bytes(),
+                &config.sign,
+                &span,
+                true,
+            )
+            .await;
+        }
+
+        // Messages are only included in one digest
+        for entry in entries {
+            if let Err(err) = store.set_quarantined_notified(entry.id).await {
+                tracing::warn!(parent: &span,
+                    context = "quarantine",
+                    event = "error",
+                    reason = %err,
+                    "Failed to update quarantined message.");
+            }
+        }
+    }
+
+    pub async fn quarantine_purge(&self, store: &Store) -> store::Result<()> {
+        store.purge_expired_quarantine(now()).await
+    }
+}
+
+impl SMTP {
+    fn has_quarantine_digest(&self) -> bool {
+        let enable = &self.quarantine.digest.enable;
+        enable.default || enable.if_then.iter().any(|i| i.then)
+    }
+
+    fn quarantine_link_mac(&self, id: u32, nonce: u64, rcpt: &str, expires: u64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.quarantine.digest.secret)
+            .expect("HMAC can take keys of any size");
+        mac.update(format!("{id}\n{nonce}\n{}\n{expires}", rcpt.to_lowercase()).as_bytes());
+        mac
+    }
+}
+
+struct DigestEnvelope<'x> {
+    rcpt: &'x str,
+    domain: &'x str,
+}
+
+impl<'x> KeyLookup for DigestEnvelope<'x> {
+    type Key = EnvelopeKey;
+
+    fn key(&self, key: &Self::Key) -> Cow<'_, str> {
+        match key {
+            EnvelopeKey::Recipient => self.rcpt.into(),
+            EnvelopeKey::RecipientDomain => self.domain.into(),
+            _ => "".into(),
+        }
+    }
+
+    fn key_as_int(&self, _: &Self::Key) -> i32 {
+        0
+    }
+
+    fn key_as_ip(&self, _: &Self::Key) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0))
+    }
+}
@@ -26,6 +26,7 @@ use std::{borrow::Cow, process::Command, sync::Arc, time::Duration};
 use ahash::AHashMap;
 use directory::Lookup;
 use mail_auth::common::headers::HeaderWriter;
+use mail_parser::Message as ParsedMessage;
 use sieve::{
     compiler::grammar::actions::action_redirect::{ByMode, ByTime, Notify, NotifyItem, Ret},
     CommandType, Envelope, Event, Input, MatchAs, Recipient, Sieve,
@@ -39,6 +40,8 @@ use tokio::{
     runtime::Handle,
 };
 
+use serde::Serialize;
+
 use crate::queue::{DomainPart, InstantFromTimestamp, Message};
 
 use super::{Session, SessionAddress, SessionData, SMTP};
@@ -52,14 +55,43 @@ pub enum ScriptResult {
         modifications: Vec<(Envelope, String)>,
     },
     Reject(String),
+    Quarantine {
+        reason: String,
+        modifications: Vec<(Envelope, String)>,
+    },
+    Discard,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ScriptAction {
+    Accept,
+    Replace,
     Discard,
+    Reject {
+        reason: String,
+    },
+    Quarantine {
+        reason: String,
+    },
+    Redirect {
+        recipients: Vec<String>,
+    },
+    Execute {
+        command: String,
+        arguments: Vec<String>,
+    },
+    SetEnvelope {
+        envelope: String,
+        value: String,
+    },
 }
 
 impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
     pub async fn run_script(
         &self,
         script: Arc<Sieve>,
-        message: Option<Arc<Vec<u8>>>,
+        message: Option<ParsedMessage<'static>>,
     ) -> ScriptResult {
         let core = self.core.clone();
         let span = self.span.clone();
@@ -125,15 +157,16 @@ impl SMTP {
         script: Arc<Sieve>,
         vars_env: AHashMap<String, Cow<'static, str>>,
         envelope: Vec<(Envelope, Cow<'static, str>)>,
-        message: Option<Arc<Vec<u8>>>,
+        message: Option<ParsedMessage<'static>>,
         handle: Handle,
         span: tracing::Span,
     ) -> ScriptResult {
         // Create filter instance
-        let mut instance = self
-            .sieve
-            .runtime
-            .filter(message.as_deref().map_or(b"", |m| &m[..]))
+        let instance = match message {
+            Some(message) => self.sieve.runtime.filter_parsed(message),
+            None => self.sieve.runtime.filter(b""),
+        };
+        let mut instance = instance
             .with_vars_env(vars_env)
             .with_envelope_list(envelope)
             .with_user_address(&self.sieve.config.from_addr)
@@ -142,6 +175,7 @@ impl SMTP {
         let mut messages: Vec<Vec<u8>> = Vec::new();
 
         let mut reject_reason = None;
+        let mut quarantine_reason = None;
         let mut modifications = vec![];
         let mut keep_id = usize::MAX;
 
@@ -253,6 +287,11 @@ impl SMTP {
                         reject_reason = reason.into();
                         input = true.into();
                     }
+                    Event::FileInto { folder, .. } => {
+                        // At the SMTP stage filing a message means holding it in quarantine
+                        quarantine_reason = folder.into();
+                        input = true.into();
+                    }
                     Event::SendMessage {
                         recipient,
                         notify,
@@ -470,6 +509,11 @@ impl SMTP {
             } else {
                 ScriptResult::Reject(format!("503 5.5.3 {reject_reason}"))
             }
+        } else if let Some(reason) = quarantine_reason {
+            ScriptResult::Quarantine {
+                reason,
+                modifications,
+            }
         } else if keep_id != usize::MAX - 1 {
             if let Some(message) = messages.into_iter().nth(keep_id - 1) {
                 ScriptResult::Replace {
@@ -485,6 +529,163 @@ impl SMTP {
     }
 }
 
+impl SMTP {
+    pub async fn test_script(
+        &self,
+        name: &str,
+        message: &[u8],
+        envelope: Vec<(Envelope, String)>,
+    ) -> Option<Vec<ScriptAction>> {
+        let script = self.sieve.scripts.get(name)?.clone();
+        let span = tracing::debug_span!("sieve-test", script = name);
+
+        // Create filter instance, no side effects are performed on this run
+        let mut instance = self
+            .sieve
+            .runtime
+            .filter(message)
+            .with_envelope_list(
+                envelope
+                    .into_iter()
+                    .map(|(name, value)| (name, Cow::Owned(value)))
+                    .collect::<Vec<(Envelope, Cow<str>)>>(),
+            )
+            .with_user_address(&self.sieve.config.from_addr)
+            .with_user_full_name(&self.sieve.config.from_name);
+        let mut input = Input::script("__script", script);
+        let mut actions = Vec::new();
+        let mut has_messages = 0;
+        let mut has_reject = false;
+        let mut has_quarantine = false;
+        let mut keep_id = usize::MAX;
+
+        while let Some(result) = instance.run(input) {
+            match result {
+                Ok(event) => match event {
+                    Event::IncludeScript { name, optional } => {
+                        if let Some(script) = self.sieve.scripts.get(name.as_str()) {
+                            input = Input::script(name, script.clone());
+                        } else if optional {
+                            input = false.into();
+                        } else {
+                            tracing::debug!(
+                                parent: &span,
+                                context = "sieve",
+                                event = "script-not-found",
+                                script = name.as_str()
+                            );
+                            break;
+                        }
+                    }
+                    Event::ListContains {
+                        lists,
+                        values,
+                        match_as,
+                    } => {
+                        input = false.into();
+                        'outer: for list in lists {
+                            if let Some(list) = self.sieve.lookup.get(&list) {
+                                for value in &values {
+                                    let result = if !matches!(match_as, MatchAs::Lowercase) {
+                                        list.contains(value).await
+                                    } else {
+                                        list.contains(&value.to_lowercase()).await
+                                    };
+                                    if let Some(true) = result {
+                                        input = true.into();
+                                        break 'outer;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    Event::Execute {
+                        command, arguments, ..
+                    } => {
+                        actions.push(ScriptAction::Execute { command, arguments });
+                        input = false.into();
+                    }
+                    Event::Keep { message_id, .. } => {
+                        keep_id = message_id;
+                        input = true.into();
+                    }
+                    Event::Discard => {
+                        keep_id = usize::MAX - 1;
+                        input = true.into();
+                    }
+                    Event::Reject { reason, .. } => {
+                        actions.push(ScriptAction::Reject { reason });
+                        has_reject = true;
+                        input = true.into();
+                    }
+                    Event::FileInto { folder, .. } => {
+                        actions.push(ScriptAction::Quarantine { reason: folder });
+                        has_quarantine = true;
+                        input = true.into();
+                    }
+                    Event::SendMessage { recipient, .. } => {
+                        let recipients = match recipient {
+                            Recipient::Address(rcpt) => vec![rcpt],
+                            Recipient::Group(rcpt_list) => rcpt_list,
+                            Recipient::List(list) => {
+                                match self.sieve.lookup.get(&list).map(|list| list.as_ref()) {
+                                    Some(Lookup::List { list }) => list.iter().cloned().collect(),
+                                    _ => vec![format!("list:{list}")],
+                                }
+                            }
+                        };
+                        actions.push(ScriptAction::Redirect { recipients });
+                        input = true.into();
+                    }
+                    Event::CreatedMessage { .. } => {
+                        has_messages += 1;
+                        input = true.into();
+                    }
+                    Event::SetEnvelope { envelope, value } => {
+                        actions.push(ScriptAction::SetEnvelope {
+                            envelope: envelope_name(&envelope).to_string(),
+                            value,
+                        });
+                        input = true.into();
+                    }
+                    unsupported => {
+                        tracing::debug!(
+                            parent: &span,
+                            context = "sieve",
+                            event = "runtime-error",
+                            reason = format!("Unsupported event: {unsupported:?}")
+                        );
+                        break;
+                    }
+                },
+                Err(err) => {
+                    tracing::debug!(parent: &span,
+                        context = "sieve",
+                        event = "runtime-error",
+                        reason = %err
+                    );
+                    break;
+                }
+            }
+        }
+
+        // Mirror the final disposition of run_script_blocking
+        if keep_id == 0 {
+            actions.push(ScriptAction::Accept);
+        } else if !has_reject && !has_quarantine {
+            actions.push(if keep_id == usize::MAX - 1 {
+                ScriptAction::Discard
+            } else if keep_id <= has_messages {
+                ScriptAction::Replace
+            } else {
+                ScriptAction::Accept
+            });
+        }
+
+        Some(actions)
+    }
+}
+
 impl SessionData {
     pub fn apply_sieve_modifications(&mut self, modifications: Vec<(Envelope, String)>) {
         for (envelope, value) in modifications {
@@ -597,3 +798,18 @@ impl SessionData {
         }
     }
 }
+
+fn envelope_name(envelope: &Envelope) -> &'static str {
+    match envelope {
+        Envelope::From => "from",
+        Envelope::To => "to",
+        Envelope::ByTimeAbsolute => "bytimeabsolute",
+        Envelope::ByTimeRelative => "bytimerelative",
+        Envelope::ByMode => "bymode",
+        Envelope::ByTrace => "bytrace",
+        Envelope::Notify => "notify",
+        Envelope::Orcpt => "orcpt",
+        Envelope::Ret => "ret",
+        Envelope::Envid => "envid",
+    }
+}
@@ -0,0 +1,560 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use ahash::AHashSet;
+use mail_parser::{decoders::html::html_to_text, HeaderValue, Message, PartType};
+use store::{
+    write::{key::KeySerializer, BatchBuilder, Operation, ValueClass},
+    CustomValueKey, Serialize, ServerNamespace, Store, SERVER_ACCOUNT_ID,
+};
+
+use super::SMTP;
+
+const BAYES_NAMESPACE: u8 = ServerNamespace::Bayes as u8;
+const BAYES_TOTALS: u8 = 0;
+const BAYES_TOKEN: u8 = 1;
+const BAYES_MESSAGE: u8 = 2;
+
+const MAX_TOKENS: usize = 2048;
+const MAX_INTERESTING_TOKENS: usize = 150;
+
+// Robinson's "unknown word" strength and assumed probability
+const ROBINSON_S: f64 = 1.0;
+const ROBINSON_X: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BayesCounts {
+    pub spam: u32,
+    pub ham: u32,
+}
+
+/// Spam filter verdict parsed from the X-Spam-Status header added by this server.
+#[derive(Debug, Clone)]
+pub struct SpamStatus {
+    pub is_spam: bool,
+    pub score: f64,
+    pub required: f64,
+    pub tests: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpamResult {
+    pub score: f64,
+    pub required: f64,
+    pub is_spam: bool,
+    pub tests: Vec<&'static str>,
+}
+
+impl SMTP {
+    /// Classifies a message using the model trained by an account, or the
+    /// server-wide model (which aggregates all accounts) when `account_id` is `None`.
+    pub async fn bayes_classify(
+        &self,
+        store: &Store,
+        account_id: Option<u32>,
+        message: &Message<'_>,
+    ) -> store::Result<Option<f64>> {
+        let config = &self.spam.bayes;
+        let totals = bayes_totals(store, account_id).await?.unwrap_or_default();
+        if totals.spam < config.min_learns || totals.ham < config.min_learns {
+            return Ok(None);
+        }
+
+        let tokens = bayes_tokenize(message).into_iter().collect::<Vec<_>>();
+        let counts = store
+            .get_values::<u64>(
+                tokens
+                    .iter()
+                    .map(|token| CustomValueKey {
+                        value: bayes_token_key(account_id, token),
+                    })
+                    .collect(),
+            )
+            .await?;
+
+        Ok(bayes_probability(
+            counts
+                .into_iter()
+                .flatten()
+                .map(BayesCounts::from)
+                .filter(|c| c.spam + c.ham >= std::cmp::max(config.min_token_hits, 1)),
+            totals,
+        ))
+    }
+
+    /// Re-evaluates the verdict of the spam filter using the Bayes model of the
+    /// recipient's account. Returns `None` if the account has not learned enough
+    /// messages yet, in which case the server-wide verdict applies.
+    pub async fn bayes_rescore(
+        &self,
+        store: &Store,
+        account_id: u32,
+        message: &Message<'_>,
+        status: &SpamStatus,
+    ) -> store::Result<Option<bool>> {
+        let config = &self.spam;
+        let test = match self
+            .bayes_classify(store, account_id.into(), message)
+            .await?
+        {
+            Some(probability) if probability >= config.bayes.spam_threshold => Some("BAYES_SPAM"),
+            Some(probability) if probability <= config.bayes.ham_threshold => Some("BAYES_HAM"),
+            Some(_) => None,
+            None => return Ok(None),
+        };
+
+        // Replace the score of the server-wide Bayes test with the account's one
+        let score = status.score
+            - status
+                .tests
+                .iter()
+                .filter(|test| test.starts_with("BAYES_"))
+                .map(|test| {
+                    config
+                        .scores
+                        .get(test.as_str())
+                        .copied()
+                        .unwrap_or_default()
+                })
+                .sum::<f64>()
+            + test
+                .and_then(|test| config.scores.get(test).copied())
+                .unwrap_or_default();
+
+        Ok(Some(score >= status.required))
+    }
+
+    /// Trains the classifier of an account with a message, returns false if the message
+    /// was already learned with the same classification. Learning a message with the
+    /// opposite classification reverts its previous contribution. The server-wide
+    /// model is updated along with the account's model.
+    pub async fn spam_train(
+        &self,
+        store: &Store,
+        account_id: u32,
+        raw_message: &[u8],
+        is_spam: bool,
+    ) -> store::Result<bool> {
+        let message = if let Some(message) = Message::parse(raw_message) {
+            message
+        } else {
+            return Ok(false);
+        };
+        let message_key = KeySerializer::new(std::mem::size_of::<u32>() * 2 + 34)
+            .write(SERVER_ACCOUNT_ID)
+            .write(BAYES_NAMESPACE)
+            .write(account_id)
+            .write(BAYES_MESSAGE)
+            .write(blake3::hash(raw_message).as_bytes().as_slice())
+            .finalize();
+        let tokens = bayes_tokenize(&message);
+        let token_keys = [Some(account_id), None]
+            .into_iter()
+            .flat_map(|account_id| {
+                tokens
+                    .iter()
+                    .map(move |token| bayes_token_key(account_id, token))
+            })
+            .collect::<Vec<_>>();
+        let totals_keys = [bayes_totals_key(Some(account_id)), bayes_totals_key(None)];
+
+        for _ in 0..3 {
+            let previous = store
+                .get_value::<u32>(CustomValueKey {
+                    value: message_key.clone(),
+                })
+                .await?
+                .map(|class| class == 1);
+            if previous == Some(is_spam) {
+                return Ok(false);
+            }
+            let totals = store
+                .get_values::<u64>(
+                    totals_keys
+                        .iter()
+                        .map(|key| CustomValueKey { value: key.clone() })
+                        .collect(),
+                )
+                .await?;
+            let counts = store
+                .get_values::<u64>(
+                    token_keys
+                        .iter()
+                        .map(|key| CustomValueKey { value: key.clone() })
+                        .collect(),
+                )
+                .await?;
+
+            // All updates are serialized by asserting the totals, which change on every write
+            let mut batch = BatchBuilder::new();
+            for (key, totals) in totals_keys.iter().zip(totals) {
+                let class = ValueClass::Custom { bytes: key.clone() };
+                if let Some(totals) = totals {
+                    batch.assert_value(class, totals);
+                } else {
+                    batch.assert_value(class, ());
+                }
+                batch.op(Operation::Value {
+                    class: ValueClass::Custom { bytes: key.clone() },
+                    set: u64::from(
+                        BayesCounts::from(totals.unwrap_or_default())
+                            .learn(is_spam, previous.is_some()),
+                    )
+                    .serialize()
+                    .into(),
+                });
+            }
+            for (key, count) in token_keys.iter().zip(counts) {
+                batch.op(Operation::Value {
+                    class: ValueClass::Custom { bytes: key.clone() },
+                    set: u64::from(
+                        BayesCounts::from(count.unwrap_or_default())
+                            .learn(is_spam, previous.is_some()),
+                    )
+                    .serialize()
+                    .into(),
+                });
+            }
+            batch.op(Operation::Value {
+                class: ValueClass::Custom {
+                    bytes: message_key.clone(),
+                },
+                set: (is_spam as u32).serialize().into(),
+            });
+
+            match store.write(batch.build()).await {
+                Ok(_) => {
+                    tracing::debug!(
+                        context = "spam-filter",
+                        event = "train",
+                        account_id = account_id,
+                        is_spam = is_spam,
+                        relearn = previous.is_some(),
+                        tokens = tokens.len(),
+                        "Trained Bayes classifier."
+                    );
+                    return Ok(true);
+                }
+                Err(store::Error::AssertValueFailed) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(store::Error::AssertValueFailed)
+    }
+}
+
+pub async fn bayes_totals(
+    store: &Store,
+    account_id: Option<u32>,
+) -> store::Result<Option<BayesCounts>> {
+    store
+        .get_value::<u64>(CustomValueKey {
+            value: bayes_totals_key(account_id),
+        })
+        .await
+        .map(|totals| totals.map(BayesCounts::from))
+}
+
+// The server-wide model is stored under SERVER_ACCOUNT_ID
+fn bayes_totals_key(account_id: Option<u32>) -> Vec<u8> {
+    KeySerializer::new(std::mem::size_of::<u32>() * 2 + 2)
+        .write(SERVER_ACCOUNT_ID)
+        .write(BAYES_NAMESPACE)
+        .write(account_id.unwrap_or(SERVER_ACCOUNT_ID))
+        .write(BAYES_TOTALS)
+        .finalize()
+}
+
+fn bayes_token_key(account_id: Option<u32>, token: &str) -> Vec<u8> {
+    let hash = blake3::hash(token.as_bytes());
+    KeySerializer::new(std::mem::size_of::<u32>() * 2 + std::mem::size_of::<u64>() + 2)
+        .write(SERVER_ACCOUNT_ID)
+        .write(BAYES_NAMESPACE)
+        .write(account_id.unwrap_or(SERVER_ACCOUNT_ID))
+        .write(BAYES_TOKEN)
+        .write(&hash.as_bytes()[..std::mem::size_of::<u64>()])
+        .finalize()
+}
+
+/// Combines the token probabilities using Robinson's method and Fisher's
+/// chi-square, returning the probability of the message being spam.
+pub fn bayes_probability(
+    counts: impl Iterator<Item = BayesCounts>,
+    totals: BayesCounts,
+) -> Option<f64> {
+    if totals.spam == 0 || totals.ham == 0 {
+        return None;
+    }
+
+    let mut probabilities = counts
+        .filter(|c| c.spam + c.ham > 0)
+        .map(|c| {
+            let spam_ratio = c.spam as f64 / totals.spam as f64;
+            let ham_ratio = c.ham as f64 / totals.ham as f64;
+            let p = spam_ratio / (spam_ratio + ham_ratio);
+            let n = (c.spam + c.ham) as f64;
+            ((ROBINSON_S * ROBINSON_X + n * p) / (ROBINSON_S + n)).clamp(0.01, 0.99)
+        })
+        .collect::<Vec<_>>();
+    if probabilities.is_empty() {
+        return None;
+    }
+    probabilities.sort_unstable_by(|a, b| (b - 0.5).abs().total_cmp(&(a - 0.5).abs()));
+    probabilities.truncate(MAX_INTERESTING_TOKENS);
+
+    let (ln_ham, ln_spam) = probabilities
+        .iter()
+        .fold((0.0, 0.0), |(ln_ham, ln_spam), p| {
+            (ln_ham + p.ln(), ln_spam + (1.0 - p).ln())
+        });
+    let degrees = probabilities.len() * 2;
+    let spam = 1.0 - chi2q(-2.0 * ln_spam, degrees);
+    let ham = 1.0 - chi2q(-2.0 * ln_ham, degrees);
+
+    Some((1.0 + spam - ham) / 2.0)
+}
+
+fn chi2q(x2: f64, degrees: usize) -> f64 {
+    let m = x2 / 2.0;
+    let mut term = (-m).exp();
+    let mut sum = term;
+    for i in 1..degrees / 2 {
+        term *= m / i as f64;
+        sum += term;
+    }
+    sum.min(1.0)
+}
+
+pub fn bayes_tokenize(message: &Message<'_>) -> AHashSet<String> {
+    let mut tokens = AHashSet::new();
+
+    // Sender domain and linked hosts
+    if let Some(domain) = from_address(message)
+        .and_then(|addr| addr.rsplit_once('@'))
+        .map(|(_, domain)| domain.to_lowercase())
+    {
+        tokens.insert(format!("from:{domain}"));
+    }
+    for host in message_url_hosts(message) {
+        tokens.insert(format!("url:{host}"));
+    }
+
+    // Subject and text bodies
+    if let Some(subject) = message.subject() {
+        tokenize_text(&mut tokens, subject, "s:");
+    }
+    for part in &message.parts {
+        match &part.body {
+            PartType::Text(text) => tokenize_text(&mut tokens, text, ""),
+            PartType::Html(html) => tokenize_text(&mut tokens, &html_to_text(html), ""),
+            _ => (),
+        }
+        if tokens.len() >= MAX_TOKENS {
+            break;
+        }
+    }
+
+    tokens
+}
+
+fn tokenize_text(tokens: &mut AHashSet<String>, text: &str, prefix: &str) {
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        if (3..=40).contains(&word.len()) && !word.chars().all(|c| c.is_ascii_digit()) {
+            tokens.insert(format!("{prefix}{}", word.to_lowercase()));
+            if tokens.len() >= MAX_TOKENS {
+                break;
+            }
+        }
+    }
+}
+
+pub fn message_url_hosts(message: &Message<'_>) -> Vec<String> {
+    let mut hosts = Vec::new();
+    for part in &message.parts {
+        let text = match &part.body {
+            PartType::Text(text) | PartType::Html(text) => text.as_ref(),
+            _ => continue,
+        };
+        for (pos, _) in text.match_indices("://") {
+            if !text[..pos].ends_with("http") && !text[..pos].ends_with("https") {
+                continue;
+            }
+            let authority = text[pos + 3..]
+                .split(|c: char| {
+                    c.is_whitespace() || matches!(c, '/' | '?' | '#' | '"' | '\'' | '<' | '>' | ')')
+                })
+                .next()
+                .unwrap_or_default();
+            let host = authority
+                .rsplit_once('@')
+                .map_or(authority, |(_, host)| host);
+            let host = if host.starts_with('[') {
+                host.split_once(']')
+                    .map_or(host, |(host, _)| host)
+                    .trim_start_matches('[')
+            } else {
+                host.split_once(':').map_or(host, |(host, _)| host)
+            }
+            .trim_end_matches('.')
+            .to_lowercase();
+            if !host.is_empty() && !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+    }
+    hosts
+}
+
+pub fn from_address<'x>(message: &'x Message<'_>) -> Option<&'x str> {
+    match message.from() {
+        HeaderValue::Address(addr) => addr.address.as_deref(),
+        HeaderValue::AddressList(list) => list.first().and_then(|addr| addr.address.as_deref()),
+        _ => None,
+    }
+}
+
+impl BayesCounts {
+    fn learn(mut self, is_spam: bool, relearn: bool) -> Self {
+        if is_spam {
+            self.spam += 1;
+            if relearn {
+                self.ham = self.ham.saturating_sub(1);
+            }
+        } else {
+            self.ham += 1;
+            if relearn {
+                self.spam = self.spam.saturating_sub(1);
+            }
+        }
+        self
+    }
+}
+
+impl From<u64> for BayesCounts {
+    fn from(value: u64) -> Self {
+        BayesCounts {
+            spam: (value >> 32) as u32,
+            ham: value as u32,
+        }
+    }
+}
+
+impl From<BayesCounts> for u64 {
+    fn from(value: BayesCounts) -> Self {
+        ((value.spam as u64) << 32) | value.ham as u64
+    }
+}
+
+/// Removes any X-Spam-* and X-Virus-Scan headers supplied by the sender, local
+/// deliveries trust these headers so only the ones added by this server may be kept.
+/// Returns `None` if the message has no such headers.
+pub fn strip_trust_headers(message: &[u8]) -> Option<Vec<u8>> {
+    let mut ranges = Vec::new();
+    let mut pos = 0;
+    let mut is_trust_header = false;
+    for line in message.split_inclusive(|&ch| ch == b'\n') {
+        if line == b"\n" || line == b"\r\n" {
+            break;
+        }
+        if !matches!(line.first(), Some(b' ' | b'\t')) {
+            is_trust_header = (line.len() > 7 && line[..7].eq_ignore_ascii_case(b"X-Spam-"))
+                || (line.len() > 13 && line[..13].eq_ignore_ascii_case(b"X-Virus-Scan:"));
+        }
+        if is_trust_header {
+            ranges.push(pos..pos + line.len());
+        }
+        pos += line.len();
+    }
+    if ranges.is_empty() {
+        return None;
+    }
+
+    let mut stripped = Vec::with_capacity(message.len());
+    let mut last_pos = 0;
+    for range in ranges {
+        stripped.extend_from_slice(&message[last_pos..range.start]);
+        last_pos = range.end;
+    }
+    stripped.extend_from_slice(&message[last_pos..]);
+    Some(stripped)
+}
+
+impl SpamStatus {
+    pub fn parse(raw_message: &[u8]) -> Option<Self> {
+        for line in raw_message.split(|&ch| ch == b'\n') {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.is_empty() {
+                break;
+            } else if line.len() > 14 && line[..14].eq_ignore_ascii_case(b"X-Spam-Status:") {
+                let value = std::str::from_utf8(&line[14..]).ok()?;
+                let mut status = SpamStatus {
+                    is_spam: value
+                        .trim_start()
+                        .get(..3)
+                        .map_or(false, |value| value.eq_ignore_ascii_case("yes")),
+                    score: 0.0,
+                    required: 0.0,
+                    tests: vec![],
+                };
+                for param in value.split_ascii_whitespace() {
+                    if let Some(score) = param.strip_prefix("score=") {
+                        status.score = score.parse().unwrap_or_default();
+                    } else if let Some(required) = param.strip_prefix("required=") {
+                        status.required = required.parse().unwrap_or_default();
+                    } else if let Some(tests) = param.strip_prefix("tests=") {
+                        status.tests = tests
+                            .split(',')
+                            .filter(|test| *test != "NONE")
+                            .map(|test| test.to_string())
+                            .collect();
+                    }
+                }
+                return Some(status);
+            }
+        }
+        None
+    }
+}
+
+impl SpamResult {
+    pub fn write_header(&self, headers: &mut Vec<u8>) {
+        headers.extend_from_slice(
+            format!(
+                "X-Spam-Status: {}, score={:.2} required={:.2} tests={}\r\n",
+                if self.is_spam { "Yes" } else { "No" },
+                self.score,
+                self.required,
+                if !self.tests.is_empty() {
+                    self.tests.join(",")
+                } else {
+                    "NONE".to_string()
+                }
+            )
+            .as_bytes(),
+        );
+        if self.is_spam {
+            headers.extend_from_slice(b"X-Spam-Flag: YES\r\n");
+        }
+    }
+}
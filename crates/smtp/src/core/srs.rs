@@ -0,0 +1,239 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::SystemTime;
+
+use hmac::{Hmac, Mac};
+use mail_builder::encoders::base64::base64_encode;
+use sha1::Sha1;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{config::SrsConfig, queue::Message};
+
+use super::Session;
+
+const HASH_LEN: usize = 4;
+const BASE32: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SrsError {
+    Invalid,
+    Expired,
+}
+
+impl SrsConfig {
+    // Rewrites a sender address as SRS0, or as SRS1 when it already is an SRS address
+    pub fn forward(&self, address: &str) -> Option<String> {
+        let secret = self.secrets.first()?;
+        let (local, domain) = address.rsplit_once('@')?;
+        if domain.eq_ignore_ascii_case(&self.domain) || local.is_empty() {
+            return None;
+        }
+
+        Some(if has_prefix(local, "SRS0=") {
+            // SRS0=HHH=TT=domain=local@hop -> SRS1=HHH=hop==HHH=TT=domain=local@srs-domain
+            let rest = &local[4..];
+            format!(
+                "SRS1={}={}={}@{}",
+                srs_hash(secret, &[domain, rest]),
+                domain,
+                rest,
+                self.domain
+            )
+        } else if let Some((first_hop, rest)) = has_prefix(local, "SRS1=")
+            .then(|| parse_srs1(local))
+            .flatten()
+        {
+            // Keep the first hop, only the hash and the SRS domain change
+            format!(
+                "SRS1={}={}={}@{}",
+                srs_hash(secret, &[first_hop, rest]),
+                first_hop,
+                rest,
+                self.domain
+            )
+        } else {
+            let timestamp = srs_timestamp(today());
+            format!(
+                "SRS0={}={}={}={}@{}",
+                srs_hash(secret, &[&timestamp, domain, local]),
+                timestamp,
+                domain,
+                local,
+                self.domain
+            )
+        })
+    }
+
+    // Obtains the address an SRS bounce has to be delivered to
+    pub fn reverse(&self, address: &str) -> Result<Option<String>, SrsError> {
+        let local = match address.rsplit_once('@') {
+            Some((local, domain)) if domain.eq_ignore_ascii_case(&self.domain) => local,
+            _ => return Ok(None),
+        };
+        if self.secrets.is_empty() {
+            return Ok(None);
+        }
+
+        if has_prefix(local, "SRS0=") {
+            let mut parts = local[5..].splitn(4, '=');
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some(hash), Some(timestamp), Some(orig_domain), Some(orig_local))
+                    if !orig_domain.is_empty() && !orig_local.is_empty() =>
+                {
+                    if !self.verify_hash(hash, &[timestamp, orig_domain, orig_local]) {
+                        return Err(SrsError::Invalid);
+                    }
+                    let age = (today() + 1024 - parse_srs_timestamp(timestamp)?) % 1024;
+                    if age > self.max_age.as_secs() / 86400 {
+                        return Err(SrsError::Expired);
+                    }
+                    Ok(Some(format!("{orig_local}@{orig_domain}")))
+                }
+                _ => Err(SrsError::Invalid),
+            }
+        } else if has_prefix(local, "SRS1=") {
+            let (first_hop, rest) = parse_srs1(local).ok_or(SrsError::Invalid)?;
+            let hash = local[5..].split('=').next().unwrap_or_default();
+            if self.verify_hash(hash, &[first_hop, rest]) {
+                Ok(Some(format!("SRS0{rest}@{first_hop}")))
+            } else {
+                Err(SrsError::Invalid)
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn verify_hash(&self, hash: &str, parts: &[&str]) -> bool {
+        // Any of the configured secrets is accepted so they can be rotated
+        hash.len() == HASH_LEN
+            && self
+                .secrets
+                .iter()
+                .any(|secret| srs_hash(secret, parts).eq_ignore_ascii_case(hash))
+    }
+}
+
+impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
+    pub async fn srs_forward(&self, message: &Message) -> Option<String> {
+        let srs = &self.core.mail_auth.srs;
+        if message.return_path.is_empty() || !*srs.enable.eval(self).await {
+            return None;
+        }
+
+        if let Some(directory) = self
+            .core
+            .session
+            .config
+            .rcpt
+            .directory
+            .eval_and_capture(self)
+            .await
+            .into_value(self)
+        {
+            // Senders from local domains already pass SPF
+            if !matches!(
+                directory.is_local_domain(&message.return_path_domain).await,
+                Ok(false)
+            ) {
+                return None;
+            }
+
+            // Only forwards to remote domains require rewriting
+            let mut has_remote = false;
+            for domain in &message.domains {
+                if matches!(directory.is_local_domain(&domain.domain).await, Ok(false)) {
+                    has_remote = true;
+                    break;
+                }
+            }
+            if !has_remote {
+                return None;
+            }
+        }
+
+        srs.forward(&message.return_path)
+    }
+}
+
+fn has_prefix(local: &str, prefix: &str) -> bool {
+    local
+        .get(..prefix.len())
+        .map_or(false, |p| p.eq_ignore_ascii_case(prefix))
+}
+
+fn parse_srs1(local: &str) -> Option<(&str, &str)> {
+    // SRS1=HHH=first-hop==HHH=TT=domain=local
+    let mut parts = local.get(5..)?.splitn(3, '=');
+    parts.next()?;
+    let first_hop = parts.next()?;
+    let rest = parts.next()?;
+    if !first_hop.is_empty() && rest.starts_with('=') {
+        Some((first_hop, rest))
+    } else {
+        None
+    }
+}
+
+fn srs_hash(secret: &[u8], parts: &[&str]) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC can take keys of any size");
+    for part in parts {
+        mac.update(part.to_lowercase().as_bytes());
+    }
+    let mut hash = base64_encode(&mac.finalize().into_bytes()).unwrap_or_default();
+    hash.truncate(HASH_LEN);
+    String::from_utf8(hash).unwrap_or_default()
+}
+
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+        / 86400
+        % 1024
+}
+
+fn srs_timestamp(days: u64) -> String {
+    [
+        BASE32[((days >> 5) & 31) as usize] as char,
+        BASE32[(days & 31) as usize] as char,
+    ]
+    .into_iter()
+    .collect()
+}
+
+fn parse_srs_timestamp(timestamp: &str) -> Result<u64, SrsError> {
+    let mut days = 0;
+    if timestamp.len() != 2 {
+        return Err(SrsError::Invalid);
+    }
+    for ch in timestamp.bytes() {
+        let pos = BASE32
+            .iter()
+            .position(|&c| c == ch.to_ascii_uppercase())
+            .ok_or(SrsError::Invalid)?;
+        days = (days << 5) | pos as u64;
+    }
+    Ok(days)
+}
@@ -0,0 +1,483 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use mail_auth::{
+    report::{Feedback, FeedbackType},
+    DkimOutput, DkimResult, SpfResult,
+};
+use mail_parser::HeaderValue;
+use serde::{Deserialize, Serialize};
+use store::{
+    write::{key::KeySerializer, now, BatchBuilder, Operation, ValueClass},
+    CustomValueKey, ServerNamespace, Store,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::queue::{DeliveryAttempt, Status, RCPT_DSN_SENT};
+
+use super::{Session, SMTP};
+
+// Suppressed recipients are keyed by domain first so that all entries of a
+// domain can be listed with a single range scan. Domain-wide entries use an
+// empty local part.
+const SUPPRESSION_NAMESPACE: u8 = ServerNamespace::Suppression as u8;
+const NEVER_EXPIRES: u64 = u64::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SuppressionReason {
+    Bounce,
+    Complaint,
+    Manual,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SuppressionEntry {
+    pub address: String,
+    pub reason: SuppressionReason,
+    pub created: u64,
+    pub expires: Option<u64>,
+    pub details: String,
+}
+
+impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
+    /// Returns true if an authenticated sender is submitting to a suppressed recipient.
+    pub async fn is_rcpt_suppressed(&self) -> bool {
+        if self.data.authenticated_as.is_empty()
+            || !*self
+                .core
+                .session
+                .config
+                .rcpt
+                .suppression
+                .enable
+                .eval(self)
+                .await
+        {
+            return false;
+        }
+
+        let rcpt = self.data.rcpt_to.last().unwrap();
+        match self.core.is_suppressed(&rcpt.address_lcase).await {
+            Ok(is_suppressed) => is_suppressed,
+            Err(err) => {
+                tracing::warn!(parent: &self.span,
+                    context = "suppression",
+                    event = "error",
+                    reason = %err,
+                    "Failed to check suppression list.");
+                false
+            }
+        }
+    }
+
+    /// Returns the feedback loop domain that sent this message, if it is a configured
+    /// complaint sender and the message passed DKIM or SPF authentication for it.
+    pub fn complaint_sender(&self, dkim_output: &[DkimOutput<'_>]) -> Option<String> {
+        let senders = &self.core.session.config.rcpt.suppression.complaint_senders;
+        if senders.is_empty() {
+            return None;
+        }
+
+        dkim_output
+            .iter()
+            .filter(|output| matches!(output.result(), DkimResult::Pass))
+            .filter_map(|output| output.signature())
+            .map(|signature| signature.domain().to_lowercase())
+            .find(|domain| senders.contains(domain))
+            .or_else(|| {
+                let mail_from = self.data.mail_from.as_ref()?;
+                (self
+                    .data
+                    .spf_mail_from
+                    .as_ref()
+                    .map_or(false, |spf| spf.result() == SpfResult::Pass)
+                    && senders.contains(&mail_from.domain))
+                .then(|| mail_from.domain.clone())
+            })
+    }
+}
+
+impl SMTP {
+    /// Adds an address (or a whole domain when no local part is given) to the suppression list.
+    pub async fn suppress(
+        &self,
+        address: &str,
+        reason: SuppressionReason,
+        details: &str,
+        expires: Option<u64>,
+    ) -> store::Result<()> {
+        let (store, key) = match (&self.store, suppression_key(address)) {
+            (Some(store), Some(key)) => (store, key),
+            _ => return Ok(()),
+        };
+        let mut value = Vec::with_capacity(2 * std::mem::size_of::<u64>() + 1 + details.len());
+        value.extend_from_slice(&expires.unwrap_or(NEVER_EXPIRES).to_be_bytes());
+        value.extend_from_slice(&now().to_be_bytes());
+        value.push(reason.as_u8());
+        value.extend_from_slice(details.as_bytes());
+
+        let mut batch = BatchBuilder::new();
+        batch.op(Operation::Value {
+            class: ValueClass::Custom { bytes: key },
+            set: value.into(),
+        });
+        store.write(batch.build()).await
+    }
+
+    /// Returns true if the address or its domain is on the suppression list and has not expired.
+    pub async fn is_suppressed(&self, address: &str) -> store::Result<bool> {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return Ok(false),
+        };
+        let now = now();
+        let domain = address
+            .rsplit_once('@')
+            .map_or(address, |(_, domain)| domain);
+        for key in [suppression_key(address), suppression_key(domain)]
+            .into_iter()
+            .flatten()
+        {
+            if store
+                .get_value::<SuppressionValue>(CustomValueKey { value: key })
+                .await?
+                .map_or(false, |value| value.expires > now)
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Removes an address or domain from the suppression list, returns false if it was not listed.
+    pub async fn unsuppress(&self, address: &str) -> store::Result<bool> {
+        let (store, key) = match (&self.store, suppression_key(address)) {
+            (Some(store), Some(key)) => (store, key),
+            _ => return Ok(false),
+        };
+        if store
+            .get_value::<SuppressionValue>(CustomValueKey { value: key.clone() })
+            .await?
+            .is_none()
+        {
+            return Ok(false);
+        }
+
+        let mut batch = BatchBuilder::new();
+        batch.op(Operation::Value {
+            class: ValueClass::Custom { bytes: key },
+            set: None,
+        });
+        store.write(batch.build()).await.map(|_| true)
+    }
+
+    /// Lists the active suppression entries, optionally restricted to a single domain.
+    pub async fn suppression_list(
+        &self,
+        domain: Option<&str>,
+    ) -> store::Result<Vec<SuppressionEntry>> {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return Ok(Vec::new()),
+        };
+        let prefix_len = std::mem::size_of::<u32>() + 1;
+        let (begin, end) = match domain {
+            Some(domain) => {
+                let domain = domain.trim().to_lowercase();
+                (
+                    suppression_raw_key(&domain, &[0]),
+                    suppression_raw_key(&domain, &[1]),
+                )
+            }
+            None => (
+                suppression_raw_key("", &[]),
+                suppression_raw_key("", &[u8::MAX]),
+            ),
+        };
+        let now = now();
+
+        store
+            .iterate(
+                Vec::new(),
+                CustomValueKey { value: begin },
+                CustomValueKey { value: end },
+                false,
+                true,
+                move |entries, key, value| {
+                    let value = SuppressionValue::deserialize(value)?;
+                    if value.expires > now {
+                        let key = key.get(prefix_len..).unwrap_or_default();
+                        let (domain, local) = match key.iter().position(|&ch| ch == 0) {
+                            Some(pos) => (&key[..pos], &key[pos + 1..]),
+                            None => (key, &[][..]),
+                        };
+                        let domain = String::from_utf8_lossy(domain);
+                        entries.push(SuppressionEntry {
+                            address: if local.is_empty() {
+                                domain.into_owned()
+                            } else {
+                                format!("{}@{}", String::from_utf8_lossy(local), domain)
+                            },
+                            reason: value.reason,
+                            created: value.created,
+                            expires: (value.expires != NEVER_EXPIRES).then_some(value.expires),
+                            details: value.details,
+                        });
+                    }
+                    Ok(true)
+                },
+            )
+            .await
+    }
+
+    /// Adds the recipient of a message reported as abusive through an ARF feedback report
+    /// received from an authenticated feedback loop sender.
+    pub async fn suppress_complaint(&self, feedback: &Feedback<'_>, sender: &str) {
+        if !self.has_suppression() || !matches!(feedback.feedback_type(), FeedbackType::Abuse) {
+            return;
+        }
+        let rcpt = if let Some(rcpt) = feedback.original_rcpt_to() {
+            rcpt.trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        } else if let Some(rcpt) = feedback
+            .message()
+            .or_else(|| feedback.headers())
+            .and_then(|headers| mail_parser::Message::parse(headers.as_bytes()))
+            .and_then(|message| match message.to() {
+                HeaderValue::Address(addr) => addr.address.as_ref().map(|a| a.to_string()),
+                HeaderValue::AddressList(list) => list
+                    .first()
+                    .and_then(|addr| addr.address.as_ref().map(|a| a.to_string())),
+                _ => None,
+            })
+        {
+            rcpt
+        } else {
+            tracing::debug!(
+                context = "suppression",
+                event = "skip",
+                sender = sender,
+                "Feedback report does not include the original recipient."
+            );
+            return;
+        };
+
+        // Complaints only ever suppress a single address, never a whole domain
+        if !rcpt.rsplit_once('@').map_or(false, |(local, domain)| {
+            !local.is_empty() && !domain.is_empty()
+        }) {
+            tracing::debug!(
+                context = "suppression",
+                event = "skip",
+                sender = sender,
+                rcpt = rcpt,
+                "Feedback report does not include a valid recipient address."
+            );
+            return;
+        }
+
+        let expires = now()
+            + self
+                .session
+                .config
+                .rcpt
+                .suppression
+                .expire_complaint
+                .as_secs();
+        let details = format!("Complaint reported by {sender}");
+        match self
+            .suppress(
+                &rcpt,
+                SuppressionReason::Complaint,
+                &details,
+                expires.into(),
+            )
+            .await
+        {
+            Ok(_) => {
+                tracing::info!(
+                    context = "suppression",
+                    event = "add",
+                    rcpt = rcpt,
+                    reason = "complaint",
+                    "Recipient added to the suppression list."
+                );
+            }
+            Err(err) => {
+                tracing::warn!(
+                    context = "suppression",
+                    event = "error",
+                    reason = %err,
+                    "Failed to add recipient to the suppression list."
+                );
+            }
+        }
+    }
+
+    /// Adds recipients rejected with a permanent "no such user" error to the suppression list.
+    pub async fn suppress_hard_bounces(&self, attempt: &DeliveryAttempt) {
+        if !self.has_suppression() {
+            return;
+        }
+        let expires = now() + self.session.config.rcpt.suppression.expire_bounce.as_secs();
+
+        for rcpt in &attempt.message.recipients {
+            if rcpt.has_flag(RCPT_DSN_SENT) {
+                continue;
+            }
+            if let Status::PermanentFailure(response) = &rcpt.status {
+                if matches!(response.response.esc, [5, 1, 1] | [5, 1, 10]) {
+                    let details = format!(
+                        "{} {} {}",
+                        response.hostname.entity, response.response.code, response.response.message
+                    );
+                    match self
+                        .suppress(
+                            &rcpt.address_lcase,
+                            SuppressionReason::Bounce,
+                            &details,
+                            expires.into(),
+                        )
+                        .await
+                    {
+                        Ok(_) => {
+                            tracing::info!(parent: &attempt.span,
+                                context = "suppression",
+                                event = "add",
+                                rcpt = rcpt.address_lcase,
+                                reason = "bounce",
+                                "Recipient added to the suppression list.");
+                        }
+                        Err(err) => {
+                            tracing::warn!(parent: &attempt.span,
+                                context = "suppression",
+                                event = "error",
+                                reason = %err,
+                                "Failed to add recipient to the suppression list.");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn suppression_purge(&self, store: &Store) -> store::Result<()> {
+        store
+            .purge_expired_values(
+                KeySerializer::new(std::mem::size_of::<u32>() + 1)
+                    .write(u32::MAX)
+                    .write(SUPPRESSION_NAMESPACE)
+                    .finalize(),
+                now(),
+            )
+            .await
+    }
+}
+
+impl SMTP {
+    fn has_suppression(&self) -> bool {
+        let enable = &self.session.config.rcpt.suppression.enable;
+        self.store.is_some() && (enable.default || enable.if_then.iter().any(|i| i.then))
+    }
+}
+
+impl SuppressionReason {
+    fn as_u8(&self) -> u8 {
+        match self {
+            SuppressionReason::Bounce => 0,
+            SuppressionReason::Complaint => 1,
+            SuppressionReason::Manual => 2,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(SuppressionReason::Bounce),
+            1 => Some(SuppressionReason::Complaint),
+            2 => Some(SuppressionReason::Manual),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for SuppressionReason {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bounce" => Ok(SuppressionReason::Bounce),
+            "complaint" => Ok(SuppressionReason::Complaint),
+            "manual" => Ok(SuppressionReason::Manual),
+            _ => Err(()),
+        }
+    }
+}
+
+struct SuppressionValue {
+    expires: u64,
+    created: u64,
+    reason: SuppressionReason,
+    details: String,
+}
+
+impl store::Deserialize for SuppressionValue {
+    fn deserialize(bytes: &[u8]) -> store::Result<Self> {
+        if bytes.len() > 2 * std::mem::size_of::<u64>() {
+            if let Some(reason) = SuppressionReason::from_u8(bytes[16]) {
+                return Ok(SuppressionValue {
+                    expires: u64::from_be_bytes(bytes[..8].try_into().unwrap()),
+                    created: u64::from_be_bytes(bytes[8..16].try_into().unwrap()),
+                    reason,
+                    details: String::from_utf8_lossy(&bytes[17..]).into_owned(),
+                });
+            }
+        }
+        Err(store::Error::InternalError(
+            "Failed to deserialize suppression entry".to_string(),
+        ))
+    }
+}
+
+fn suppression_key(address: &str) -> Option<Vec<u8>> {
+    let address = address.trim().to_lowercase();
+    let (local, domain) = address.rsplit_once('@').unwrap_or(("", address.as_str()));
+    if !domain.is_empty() && !domain.contains('\0') && !local.contains('\0') {
+        let mut suffix = Vec::with_capacity(local.len() + 1);
+        suffix.push(0);
+        suffix.extend_from_slice(local.as_bytes());
+        Some(suppression_raw_key(domain, &suffix))
+    } else {
+        None
+    }
+}
+
+fn suppression_raw_key(domain: &str, suffix: &[u8]) -> Vec<u8> {
+    KeySerializer::new(std::mem::size_of::<u32>() + 1 + domain.len() + suffix.len())
+        .write(u32::MAX)
+        .write(SUPPRESSION_NAMESPACE)
+        .write(domain.as_bytes())
+        .write(suffix)
+        .finalize()
+}
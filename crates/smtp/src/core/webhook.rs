@@ -0,0 +1,771 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use ahash::AHashSet;
+use hmac::{Hmac, Mac};
+use mail_parser::DateTime;
+use serde::Serialize;
+use sha2::Sha256;
+use store::{
+    write::{key::KeySerializer, now, BatchBuilder, Operation, ValueClass},
+    CustomValueKey, ServerNamespace, Store,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::mpsc::{self, error::TrySendError},
+};
+
+use crate::{
+    config::{Webhook, WebhookType},
+    queue::{DeliveryAttempt, Error, Message, Status, RCPT_STATUS_CHANGED},
+};
+
+use super::{Session, SMTP};
+
+// Outbox entries are keyed by webhook id followed by the event id, which
+// is derived from the creation time so that entries are replayed in order.
+const WEBHOOK_NAMESPACE: u8 = ServerNamespace::Webhook as u8;
+const LONG_WAIT: Duration = Duration::from_secs(86400 * 365);
+
+pub struct WebhookEvent {
+    pub type_: WebhookType,
+    pub created: u64,
+    pub data: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'x> {
+    id: String,
+    #[serde(rename = "type")]
+    type_: WebhookType,
+    #[serde(rename = "createdAt")]
+    created_at: String,
+    data: &'x serde_json::Value,
+}
+
+#[derive(Debug)]
+struct OutboxEntry {
+    id: u64,
+    expires: u64,
+    next_retry: u64,
+    attempts: u32,
+    payload: Arc<Vec<u8>>,
+}
+
+struct Outbox {
+    core: Arc<SMTP>,
+    hook: Arc<Webhook>,
+    client: reqwest::Client,
+    pending: Vec<OutboxEntry>,
+    overflow: Arc<AtomicBool>,
+}
+
+pub trait SpawnWebhooks {
+    fn spawn(self, core: Arc<SMTP>);
+}
+
+impl SpawnWebhooks for mpsc::Receiver<WebhookEvent> {
+    fn spawn(mut self, core: Arc<SMTP>) {
+        if core.webhook.config.hooks.is_empty() {
+            return;
+        }
+
+        tokio::spawn(async move {
+            // Each webhook is served by its own outbox so that a slow or
+            // unreachable endpoint does not delay the others.
+            let mut outboxes = Vec::with_capacity(core.webhook.config.hooks.len());
+            for hook in &core.webhook.config.hooks {
+                let (tx, rx) = mpsc::channel(1024);
+                let overflow = Arc::new(AtomicBool::new(false));
+                match Outbox::new(core.clone(), hook.clone(), overflow.clone()) {
+                    Ok(outbox) => {
+                        tokio::spawn(outbox.run(rx));
+                        outboxes.push((hook.clone(), tx, overflow));
+                    }
+                    Err(err) => {
+                        tracing::error!(
+                            context = "webhook",
+                            event = "error",
+                            id = hook.id,
+                            reason = %err,
+                            "Failed to build HTTP client for webhook."
+                        );
+                    }
+                }
+            }
+
+            let mut last_id = 0;
+            while let Some(event) = self.recv().await {
+                // Event ids are nanosecond timestamps, bumped when needed to keep them unique
+                last_id = std::cmp::max(
+                    SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .map_or(0, |d| d.as_nanos() as u64),
+                    last_id + 1,
+                );
+                let payload = Arc::new(
+                    serde_json::to_vec(&WebhookPayload {
+                        id: last_id.to_string(),
+                        type_: event.type_,
+                        created_at: DateTime::from_timestamp(event.created as i64).to_rfc3339(),
+                        data: &event.data,
+                    })
+                    .unwrap_or_default(),
+                );
+
+                for (hook, tx, overflow) in &outboxes {
+                    if !hook.accepts(event.type_) {
+                        continue;
+                    }
+                    let entry = OutboxEntry {
+                        id: last_id,
+                        expires: event.created + hook.expire.as_secs(),
+                        next_retry: 0,
+                        attempts: 0,
+                        payload: payload.clone(),
+                    };
+                    if let Some(store) = &core.store {
+                        if let Err(err) = write_entry(store, hook, &entry).await {
+                            tracing::warn!(
+                                context = "webhook",
+                                event = "error",
+                                id = hook.id,
+                                reason = %err,
+                                "Failed to store event in webhook outbox."
+                            );
+                        }
+                    }
+                    match tx.try_send(entry) {
+                        Ok(_) => (),
+                        Err(TrySendError::Full(_)) => {
+                            // Stored events are loaded by the outbox once it catches up
+                            overflow.store(true, Ordering::Relaxed);
+                            if core.store.is_none() {
+                                tracing::warn!(
+                                    context = "webhook",
+                                    event = "overflow",
+                                    id = hook.id,
+                                    "Webhook outbox is full, dropping event."
+                                );
+                            }
+                        }
+                        Err(TrySendError::Closed(_)) => {
+                            tracing::warn!(
+                                context = "webhook",
+                                id = hook.id,
+                                "Channel closed while trying to notify webhook outbox."
+                            );
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl Outbox {
+    fn new(
+        core: Arc<SMTP>,
+        hook: Arc<Webhook>,
+        overflow: Arc<AtomicBool>,
+    ) -> reqwest::Result<Self> {
+        Ok(Outbox {
+            client: reqwest::Client::builder()
+                .user_agent(crate::USER_AGENT)
+                .timeout(hook.timeout)
+                .danger_accept_invalid_certs(hook.tls_allow_invalid_certs)
+                .redirect(reqwest::redirect::Policy::none())
+                .build()?,
+            core,
+            hook,
+            pending: Vec::new(),
+            overflow,
+        })
+    }
+
+    async fn run(mut self, mut rx: mpsc::Receiver<OutboxEntry>) {
+        // Resume delivery of any events left over from a previous run
+        self.overflow.store(true, Ordering::Relaxed);
+        let mut batch_due = self.reload().await.then(Instant::now);
+
+        loop {
+            match tokio::time::timeout(self.wake_up_time(batch_due), rx.recv()).await {
+                Ok(Some(entry)) => {
+                    // Events persisted while the outbox was starting up may
+                    // have already been loaded from the store.
+                    if self.pending.iter().any(|e| e.id == entry.id) {
+                        continue;
+                    } else if self.pending.len() >= self.hook.max_pending {
+                        // Keep memory bounded, the event is loaded from the store later
+                        self.overflow.store(true, Ordering::Relaxed);
+                        if self.core.store.is_none() {
+                            tracing::warn!(
+                                context = "webhook",
+                                event = "overflow",
+                                id = self.hook.id,
+                                "Webhook outbox is full, dropping event."
+                            );
+                        }
+                        continue;
+                    }
+                    self.pending.push(entry);
+                    let now = now();
+                    if self.pending.iter().filter(|e| e.next_retry <= now).count()
+                        < self.hook.batch_size
+                    {
+                        batch_due.get_or_insert_with(|| Instant::now() + self.hook.batch_wait);
+                        continue;
+                    }
+                }
+                Ok(None) => break,
+                Err(_) => (),
+            }
+
+            self.flush().await;
+            batch_due = self.reload().await.then(Instant::now);
+        }
+    }
+
+    // Loads events from the store that did not fit in memory, returns true if any were added
+    async fn reload(&mut self) -> bool {
+        let max_pending = self.hook.max_pending;
+        if self.pending.len() >= max_pending || !self.overflow.swap(false, Ordering::Relaxed) {
+            return false;
+        }
+
+        let entries = self.load(max_pending).await;
+        if entries.len() >= max_pending {
+            self.overflow.store(true, Ordering::Relaxed);
+        }
+        let ids = self.pending.iter().map(|e| e.id).collect::<AHashSet<_>>();
+        let num_pending = self.pending.len();
+        for entry in entries {
+            if self.pending.len() >= max_pending {
+                break;
+            } else if !ids.contains(&entry.id) {
+                self.pending.push(entry);
+            }
+        }
+        self.pending.len() > num_pending
+    }
+
+    fn wake_up_time(&self, batch_due: Option<Instant>) -> Duration {
+        let now = now();
+        let mut wake_up = batch_due.map_or(LONG_WAIT, |due| {
+            due.saturating_duration_since(Instant::now())
+        });
+        if let Some(next_retry) = self
+            .pending
+            .iter()
+            .filter(|e| e.next_retry > now)
+            .map(|e| e.next_retry)
+            .min()
+        {
+            wake_up = std::cmp::min(wake_up, Duration::from_secs(next_retry - now));
+        }
+        wake_up
+    }
+
+    async fn flush(&mut self) {
+        let now = now();
+
+        // Discard events that expired or ran out of attempts
+        let mut discarded = Vec::new();
+        let max_attempts = self.hook.retry_max_attempts;
+        self.pending.retain(|entry| {
+            if entry.expires > now && entry.attempts < max_attempts {
+                true
+            } else {
+                discarded.push(entry.id);
+                false
+            }
+        });
+        if !discarded.is_empty() {
+            tracing::warn!(
+                context = "webhook",
+                event = "discard",
+                id = self.hook.id,
+                count = discarded.len(),
+                "Discarding webhook events that could not be delivered."
+            );
+            self.delete(&discarded).await;
+        }
+
+        loop {
+            let batch = self
+                .pending
+                .iter()
+                .filter(|entry| entry.next_retry <= now)
+                .take(self.hook.batch_size)
+                .collect::<Vec<_>>();
+            if batch.is_empty() {
+                break;
+            }
+
+            let mut body = Vec::with_capacity(
+                batch
+                    .iter()
+                    .map(|entry| entry.payload.len() + 1)
+                    .sum::<usize>()
+                    + 1,
+            );
+            body.push(b'[');
+            for (pos, entry) in batch.iter().enumerate() {
+                if pos > 0 {
+                    body.push(b',');
+                }
+                body.extend_from_slice(&entry.payload);
+            }
+            body.push(b']');
+            let ids = batch.iter().map(|entry| entry.id).collect::<Vec<_>>();
+
+            match self.post(body).await {
+                Ok(_) => {
+                    tracing::debug!(
+                        context = "webhook",
+                        event = "success",
+                        id = self.hook.id,
+                        count = ids.len(),
+                        "Delivered webhook events."
+                    );
+                    self.pending.retain(|entry| !ids.contains(&entry.id));
+                    self.delete(&ids).await;
+                }
+                Err(err) => {
+                    tracing::info!(
+                        context = "webhook",
+                        event = "failed",
+                        id = self.hook.id,
+                        count = ids.len(),
+                        reason = err,
+                        "Failed to deliver webhook events, will retry later."
+                    );
+
+                    // Back off exponentially and leave the remaining events for the next retry
+                    for entry in self.pending.iter_mut() {
+                        if ids.contains(&entry.id) {
+                            entry.attempts += 1;
+                            entry.next_retry = now + self.hook.backoff(entry.attempts).as_secs();
+                        }
+                    }
+                    if let Some(store) = &self.core.store {
+                        for entry in self.pending.iter().filter(|e| ids.contains(&e.id)) {
+                            if let Err(err) = write_entry(store, &self.hook, entry).await {
+                                tracing::warn!(
+                                    context = "webhook",
+                                    event = "error",
+                                    id = self.hook.id,
+                                    reason = %err,
+                                    "Failed to update webhook outbox."
+                                );
+                            }
+                        }
+                    }
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn post(&self, body: Vec<u8>) -> Result<(), String> {
+        let timestamp = now().to_string();
+        let mut request = self
+            .client
+            .post(&self.hook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Webhook-Id", &self.hook.id)
+            .header("X-Webhook-Timestamp", &timestamp);
+
+        // Sign the timestamp and body so receivers can reject forged or replayed requests
+        if let Some(secret) = &self.hook.secret {
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(secret).expect("HMAC can take keys of any size");
+            mac.update(timestamp.as_bytes());
+            mac.update(b".");
+            mac.update(&body);
+            request = request.header(
+                "X-Webhook-Signature",
+                mac.finalize().into_bytes().iter().fold(
+                    String::from("sha256="),
+                    |mut sig, byte| {
+                        let _ = write!(sig, "{byte:02x}");
+                        sig
+                    },
+                ),
+            );
+        }
+
+        let response = request
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("Endpoint returned HTTP {}", response.status()))
+        }
+    }
+
+    async fn load(&self, limit: usize) -> Vec<OutboxEntry> {
+        let store = match &self.core.store {
+            Some(store) => store,
+            None => return Vec::new(),
+        };
+        match store
+            .iterate(
+                Vec::new(),
+                CustomValueKey {
+                    value: outbox_key(&self.hook.id, 0),
+                },
+                CustomValueKey {
+                    value: outbox_key(&self.hook.id, u64::MAX),
+                },
+                false,
+                true,
+                move |entries, key, value| {
+                    if let (Some(id), Some(entry)) = (
+                        key.get(key.len().saturating_sub(std::mem::size_of::<u64>())..)
+                            .and_then(|id| id.try_into().ok())
+                            .map(u64::from_be_bytes),
+                        OutboxEntry::deserialize(value),
+                    ) {
+                        entries.push(OutboxEntry { id, ..entry });
+                    }
+                    Ok(entries.len() < limit)
+                },
+            )
+            .await
+        {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::warn!(
+                    context = "webhook",
+                    event = "error",
+                    id = self.hook.id,
+                    reason = %err,
+                    "Failed to load webhook outbox."
+                );
+                Vec::new()
+            }
+        }
+    }
+
+    async fn delete(&self, ids: &[u64]) {
+        let store = match &self.core.store {
+            Some(store) => store,
+            None => return,
+        };
+        let mut batch = BatchBuilder::new();
+        for id in ids {
+            batch.op(Operation::Value {
+                class: ValueClass::Custom {
+                    bytes: outbox_key(&self.hook.id, *id),
+                },
+                set: None,
+            });
+        }
+        if let Err(err) = store.write(batch.build()).await {
+            tracing::warn!(
+                context = "webhook",
+                event = "error",
+                id = self.hook.id,
+                reason = %err,
+                "Failed to remove events from webhook outbox."
+            );
+        }
+    }
+}
+
+impl SMTP {
+    /// Returns true if any webhook is subscribed to the event type.
+    pub fn has_webhook(&self, type_: WebhookType) -> bool {
+        self.webhook
+            .config
+            .hooks
+            .iter()
+            .any(|hook| hook.accepts(type_))
+    }
+
+    pub async fn webhook_event(&self, type_: WebhookType, data: serde_json::Value) {
+        if !self.has_webhook(type_) {
+            return;
+        }
+
+        // Never hold up message processing when the webhook dispatcher falls behind
+        match self.webhook.tx.try_send(WebhookEvent {
+            type_,
+            created: now(),
+            data,
+        }) {
+            Ok(_) => (),
+            Err(TrySendError::Full(_)) => {
+                tracing::warn!(
+                    context = "webhook",
+                    event = "overflow",
+                    "Webhook event queue is full, dropping event."
+                );
+            }
+            Err(TrySendError::Closed(_)) => {
+                tracing::warn!(
+                    context = "webhook",
+                    "Channel closed while trying to send webhook event."
+                );
+            }
+        }
+    }
+
+    /// Reports the outcome of a delivery attempt for every recipient whose
+    /// status was updated, or whose domain failed before reaching the
+    /// recipient stage.
+    pub async fn webhook_delivery_attempt(&self, attempt: &DeliveryAttempt) {
+        if ![
+            WebhookType::DeliveryCompleted,
+            WebhookType::DeliveryDeferred,
+            WebhookType::DeliveryFailed,
+        ]
+        .into_iter()
+        .any(|type_| self.has_webhook(type_))
+        {
+            return;
+        }
+
+        let message = &attempt.message;
+        for rcpt in &message.recipients {
+            let domain = &message.domains[rcpt.domain_idx];
+            let (type_, status) = if rcpt.has_flag(RCPT_STATUS_CHANGED) {
+                (
+                    match &rcpt.status {
+                        Status::Completed(_) => WebhookType::DeliveryCompleted,
+                        Status::TemporaryFailure(_) => WebhookType::DeliveryDeferred,
+                        Status::PermanentFailure(_) => WebhookType::DeliveryFailed,
+                        Status::Scheduled => continue,
+                    },
+                    rcpt.status.to_string(),
+                )
+            } else if domain.changed
+                && matches!(
+                    &rcpt.status,
+                    Status::Scheduled | Status::TemporaryFailure(_)
+                )
+            {
+                (
+                    match &domain.status {
+                        // Throttled domains were not attempted
+                        Status::TemporaryFailure(
+                            Error::ConcurrencyLimited | Error::RateLimited,
+                        ) => continue,
+                        Status::TemporaryFailure(_) => WebhookType::DeliveryDeferred,
+                        Status::PermanentFailure(_) => WebhookType::DeliveryFailed,
+                        Status::Scheduled | Status::Completed(_) => continue,
+                    },
+                    domain.status.to_string(),
+                )
+            } else {
+                continue;
+            };
+
+            self.webhook_event(
+                type_,
+                serde_json::json!({
+                    "queueId": message.id,
+                    "from": message.return_path,
+                    "to": rcpt.address,
+                    "domain": domain.domain,
+                    "status": status,
+                    "size": message.size,
+                }),
+            )
+            .await;
+        }
+    }
+
+    pub async fn webhook_dsn_sent(&self, attempt: &DeliveryAttempt) {
+        if !self.has_webhook(WebhookType::DsnSent) {
+            return;
+        }
+        let message = &attempt.message;
+        self.webhook_event(
+            WebhookType::DsnSent,
+            serde_json::json!({
+                "queueId": message.id,
+                "to": message.return_path,
+                "recipients": message
+                    .recipients
+                    .iter()
+                    .map(|rcpt| serde_json::json!({
+                        "address": rcpt.address,
+                        "status": rcpt.status.to_string(),
+                    }))
+                    .collect::<Vec<_>>(),
+            }),
+        )
+        .await;
+    }
+
+    pub async fn webhook_purge(&self, store: &Store) -> store::Result<()> {
+        store
+            .purge_expired_values(
+                KeySerializer::new(std::mem::size_of::<u32>() + 1)
+                    .write(u32::MAX)
+                    .write(WEBHOOK_NAMESPACE)
+                    .finalize(),
+                now(),
+            )
+            .await
+    }
+}
+
+impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
+    /// Builds the payload of a message queued event, taken before the message
+    /// is handed over to the queue.
+    pub fn webhook_queued_data(&self, message: &Message) -> Option<serde_json::Value> {
+        if !self.core.has_webhook(WebhookType::MessageQueued) {
+            return None;
+        }
+        serde_json::json!({
+            "queueId": message.id,
+            "from": message.return_path,
+            "to": message
+                .recipients
+                .iter()
+                .map(|rcpt| rcpt.address.as_str())
+                .collect::<Vec<_>>(),
+            "size": message.size,
+            "remoteIp": self.data.remote_ip.to_string(),
+            "authenticatedAs": self.data.authenticated_as,
+            "listener": self.instance.id,
+        })
+        .into()
+    }
+
+    pub async fn webhook_rejected(&self, source: &str, response: &str) {
+        if !self.core.has_webhook(WebhookType::MessageRejected) {
+            return;
+        }
+        self.core
+            .webhook_event(
+                WebhookType::MessageRejected,
+                serde_json::json!({
+                    "source": source,
+                    "response": response.trim_end(),
+                    "from": self.data.mail_from.as_ref().map(|from| from.address.as_str()),
+                    "to": self
+                        .data
+                        .rcpt_to
+                        .iter()
+                        .map(|rcpt| rcpt.address.as_str())
+                        .collect::<Vec<_>>(),
+                    "helo": self.data.helo_domain,
+                    "remoteIp": self.data.remote_ip.to_string(),
+                    "authenticatedAs": self.data.authenticated_as,
+                    "listener": self.instance.id,
+                }),
+            )
+            .await;
+    }
+
+    pub async fn webhook_auth_failed(&self, username: &str) {
+        if !self.core.has_webhook(WebhookType::AuthFailed) {
+            return;
+        }
+        self.core
+            .webhook_event(
+                WebhookType::AuthFailed,
+                serde_json::json!({
+                    "username": username,
+                    "remoteIp": self.data.remote_ip.to_string(),
+                    "listener": self.instance.id,
+                    "attempts": self.data.auth_errors + 1,
+                }),
+            )
+            .await;
+    }
+}
+
+impl Webhook {
+    pub fn accepts(&self, type_: WebhookType) -> bool {
+        self.events.is_empty() || self.events.contains(&type_)
+    }
+
+    fn backoff(&self, attempts: u32) -> Duration {
+        std::cmp::min(
+            self.retry_backoff
+                .saturating_mul(1u32 << std::cmp::min(attempts.saturating_sub(1), 16)),
+            self.retry_max_backoff,
+        )
+    }
+}
+
+impl OutboxEntry {
+    fn serialize(&self) -> Vec<u8> {
+        let mut value = Vec::with_capacity(
+            2 * std::mem::size_of::<u64>() + std::mem::size_of::<u32>() + self.payload.len(),
+        );
+        value.extend_from_slice(&self.expires.to_be_bytes());
+        value.extend_from_slice(&self.next_retry.to_be_bytes());
+        value.extend_from_slice(&self.attempts.to_be_bytes());
+        value.extend_from_slice(&self.payload);
+        value
+    }
+
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        Some(OutboxEntry {
+            id: 0,
+            expires: u64::from_be_bytes(bytes.get(..8)?.try_into().ok()?),
+            next_retry: u64::from_be_bytes(bytes.get(8..16)?.try_into().ok()?),
+            attempts: u32::from_be_bytes(bytes.get(16..20)?.try_into().ok()?),
+            payload: Arc::new(bytes.get(20..)?.to_vec()),
+        })
+    }
+}
+
+async fn write_entry(store: &Store, hook: &Webhook, entry: &OutboxEntry) -> store::Result<()> {
+    let mut batch = BatchBuilder::new();
+    batch.op(Operation::Value {
+        class: ValueClass::Custom {
+            bytes: outbox_key(&hook.id, entry.id),
+        },
+        set: entry.serialize().into(),
+    });
+    store.write(batch.build()).await
+}
+
+fn outbox_key(hook_id: &str, id: u64) -> Vec<u8> {
+    KeySerializer::new(std::mem::size_of::<u32>() + 2 + hook_id.len() + std::mem::size_of::<u64>())
+        .write(u32::MAX)
+        .write(WEBHOOK_NAMESPACE)
+        .write(hook_id)
+        .write(0u8)
+        .write(id)
+        .finalize()
+}
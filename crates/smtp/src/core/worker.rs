@@ -66,6 +66,7 @@ impl SMTP {
         self.queue.quota.retain(|_, v| {
             v.messages.load(Ordering::Relaxed) > 0 || v.size.load(Ordering::Relaxed) > 0
         });
+        self.queue.adaptive_cleanup();
     }
 }
 
@@ -0,0 +1,288 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    borrow::Cow,
+    io::{Cursor, Read},
+};
+
+use mail_auth::zip::{self, result::ZipError};
+use mail_parser::{Message, MessagePart, MimeHeaders, PartType};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    config::{AttachmentAction, AttachmentPolicy},
+    core::{quarantine::QuarantineReason, Session},
+};
+
+use super::IsTls;
+
+#[derive(Default)]
+pub struct AttachmentCheck {
+    pub message: Option<Vec<u8>>,
+    pub quarantine: Option<QuarantineReason>,
+}
+
+#[derive(Debug)]
+pub struct Violation {
+    pub part_id: usize,
+    pub name: String,
+    pub reason: String,
+}
+
+impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
+    pub async fn check_attachments(
+        &self,
+        message: Option<&Message<'_>>,
+    ) -> Result<AttachmentCheck, Cow<'static, [u8]>> {
+        let policy = &self.core.session.config.data.attachments;
+        let message = match message {
+            Some(message) if *policy.enable.eval(self).await => message,
+            _ => return Ok(AttachmentCheck::default()),
+        };
+
+        let violations = policy.check_message(message);
+        if violations.is_empty() {
+            return Ok(AttachmentCheck::default());
+        }
+
+        let action = *policy.action.eval(self).await;
+        for violation in &violations {
+            tracing::info!(
+                parent: &self.span,
+                context = "attachments",
+                event = "violation",
+                action = ?action,
+                name = &violation.name,
+                reason = &violation.reason,
+                return_path = self.data.mail_from.as_ref().map_or("", |m| m.address.as_str()),
+                "Attachment policy violation.");
+        }
+
+        let violation = &violations[0];
+        match action {
+            AttachmentAction::Strip => {
+                // Single part messages can't be stripped without losing the whole message
+                if violations.iter().all(|v| v.part_id != 0) {
+                    return Ok(AttachmentCheck {
+                        message: policy.strip(message, &violations).into(),
+                        quarantine: None,
+                    });
+                }
+            }
+            AttachmentAction::Quarantine => {
+                return Ok(AttachmentCheck {
+                    message: None,
+                    quarantine: QuarantineReason {
+                        source: "attachments",
+                        reason: format!(
+                            "Attachment {:?} is not allowed: {}",
+                            violation.name, violation.reason
+                        ),
+                    }
+                    .into(),
+                });
+            }
+            AttachmentAction::Reject => (),
+        }
+
+        let response = format!(
+            "550 5.7.1 Message rejected: attachment {:?} is not allowed ({}).\r\n",
+            violation.name, violation.reason
+        );
+        self.webhook_rejected("attachments", &response).await;
+        Err(response.into_bytes().into())
+    }
+}
+
+impl AttachmentPolicy {
+    pub fn check_message(&self, message: &Message<'_>) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        for (part_id, part) in message.parts.iter().enumerate() {
+            self.check_part(part_id, part, &mut violations);
+        }
+        violations
+    }
+
+    fn check_part(&self, part_id: usize, part: &MessagePart<'_>, violations: &mut Vec<Violation>) {
+        let name = part.attachment_name().unwrap_or_default();
+        let contents = match &part.body {
+            PartType::Text(_) | PartType::Html(_) if name.is_empty() => {
+                return;
+            }
+            PartType::Message(message) => {
+                // Violations inside attached messages strip the entire attached message
+                for nested_part in &message.parts {
+                    self.check_part(part_id, nested_part, violations);
+                }
+                return;
+            }
+            PartType::Multipart(_) => {
+                return;
+            }
+            _ => part.contents(),
+        };
+        let mut add_violation = |reason: String| {
+            violations.push(Violation {
+                part_id,
+                name: name.to_string(),
+                reason,
+            })
+        };
+
+        if let Some(reason) = self.check_name(name) {
+            add_violation(reason);
+        } else if let Some(content_type) = part.content_type().map(|ct| {
+            ct.subtype().map_or_else(
+                || ct.ctype().to_lowercase(),
+                |st| format!("{}/{}", ct.ctype(), st).to_lowercase(),
+            )
+        }) {
+            if self.blocked_content_types.contains(&content_type) {
+                add_violation(format!("blocked content type {content_type}"));
+            }
+        }
+        if self
+            .max_size
+            .map_or(false, |max_size| contents.len() > max_size)
+        {
+            add_violation("size limit exceeded".to_string());
+        }
+        let mut budget = self.archive_max_total_size;
+        if let Some(reason) = self.check_contents(contents, 0, &mut budget) {
+            add_violation(reason);
+        }
+    }
+
+    fn check_name(&self, name: &str) -> Option<String> {
+        // Windows ignores trailing dots and spaces in file names
+        let name = name.trim_end_matches(['.', ' ']);
+        let (_, extension) = name.rsplit_once('.')?;
+        let extension = extension.trim().to_lowercase();
+        if self.blocked_extensions.contains(&extension) {
+            Some(format!("blocked extension .{extension}"))
+        } else if self.block_macros && name.ends_with("vbaProject.bin") {
+            Some("macro-enabled document".to_string())
+        } else {
+            None
+        }
+    }
+
+    fn check_contents(&self, contents: &[u8], depth: usize, budget: &mut usize) -> Option<String> {
+        let file_type = detect_file_type(contents)?;
+        if self.blocked_file_types.contains(file_type) {
+            return Some(format!("{file_type} content"));
+        } else if file_type != "zip" {
+            return None;
+        }
+
+        // Inspect archive contents
+        if depth >= self.archive_max_depth {
+            return Some("too many nested archives".to_string());
+        }
+        let mut archive = match zip::ZipArchive::new(Cursor::new(contents)) {
+            Ok(archive) => archive,
+            Err(_) => return None,
+        };
+        for idx in 0..archive.len() {
+            let mut file = match archive.by_index(idx) {
+                Ok(file) => file,
+                Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)) => {
+                    if self.block_encrypted_archives {
+                        return Some("encrypted archive".to_string());
+                    }
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            if let Some(reason) = self.check_name(file.name()) {
+                return Some(format!("archive entry {:?}: {}", file.name(), reason));
+            }
+
+            // Large entries are not decompressed to avoid archive bombs, and
+            // all entries of an attachment share a decompression budget
+            if file.is_file() && file.size() <= self.archive_max_size as u64 {
+                if file.size() > *budget as u64 {
+                    return Some("archive exceeds the decompression limit".to_string());
+                }
+                let mut entry = Vec::with_capacity(file.size() as usize);
+                let limit = std::cmp::min(self.archive_max_size, *budget) as u64;
+                if (&mut file).take(limit).read_to_end(&mut entry).is_ok() {
+                    *budget -= entry.len();
+                    if let Some(reason) = self.check_contents(&entry, depth + 1, budget) {
+                        return Some(format!("archive entry {:?}: {}", file.name(), reason));
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    pub fn strip(&self, message: &Message<'_>, violations: &[Violation]) -> Vec<u8> {
+        let raw_message = message.raw_message.as_ref();
+        let mut part_ids = violations.iter().map(|v| v.part_id).collect::<Vec<_>>();
+        part_ids.sort_unstable();
+        part_ids.dedup();
+
+        let mut stripped = Vec::with_capacity(raw_message.len());
+        let mut last_offset = 0;
+        for part_id in part_ids {
+            let part = &message.parts[part_id];
+            let mut names = violations
+                .iter()
+                .filter(|v| v.part_id == part_id && !v.name.is_empty())
+                .map(|v| v.name.as_str())
+                .collect::<Vec<_>>();
+            names.dedup();
+            let names = names.join(", ");
+            stripped.extend_from_slice(&raw_message[last_offset..part.offset_header]);
+            stripped.extend_from_slice(b"Content-Type: text/plain; charset=\"utf-8\"\r\n");
+            stripped.extend_from_slice(b"Content-Disposition: inline\r\n\r\n");
+            stripped.extend_from_slice(self.notice.as_bytes());
+            if !names.is_empty() {
+                stripped.extend_from_slice(b"\r\n\r\nRemoved: ");
+                stripped.extend_from_slice(names.as_bytes());
+            }
+            stripped.extend_from_slice(b"\r\n");
+            last_offset = part.offset_end;
+        }
+        stripped.extend_from_slice(&raw_message[last_offset..]);
+        stripped
+    }
+}
+
+fn detect_file_type(contents: &[u8]) -> Option<&'static str> {
+    match contents {
+        [b'M', b'Z', ..] => Some("exe"),
+        [0x7f, b'E', b'L', b'F', ..] => Some("elf"),
+        [0xfe, 0xed, 0xfa, 0xce | 0xcf, ..] | [0xce | 0xcf, 0xfa, 0xed, 0xfe, ..] => Some("macho"),
+        [0x4c, 0x00, 0x00, 0x00, 0x01, 0x14, 0x02, 0x00, ..] => Some("lnk"),
+        [0xd0, 0xcf, 0x11, 0xe0, 0xa1, 0xb1, 0x1a, 0xe1, ..] => Some("ole"),
+        [b'P', b'K', 0x03, 0x04, ..] => Some("zip"),
+        [b'R', b'a', b'r', b'!', 0x1a, 0x07, ..] => Some("rar"),
+        [b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c, ..] => Some("7z"),
+        _ if contents.get(0x8001..0x8006) == Some(b"CD001") => Some("iso"),
+        _ => None,
+    }
+}
@@ -196,6 +196,7 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
                         .await?;
                     Ok(false)
                 } else {
+                    self.webhook_auth_failed(&authenticated_as).await;
                     self.auth_error(b"535 5.7.8 Authentication credentials invalid.\r\n")
                         .await
                 };
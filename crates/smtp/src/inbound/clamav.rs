@@ -0,0 +1,368 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::{
+    config::{ClamAv, ClamdAddress, VirusAction},
+    core::{quarantine::QuarantineReason, Session},
+};
+
+use super::IsTls;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const MAX_REPLY_LEN: usize = 1024;
+
+pub enum ClamdStream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixStream),
+}
+
+pub struct ClamdConnection {
+    stream: ClamdStream,
+    idle_since: Instant,
+}
+
+#[derive(Default)]
+pub struct ConnectionPool {
+    connections: parking_lot::Mutex<Vec<ClamdConnection>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanResult {
+    Clean,
+    Infected(String),
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Timeout,
+    Disconnected,
+    Clamd(String),
+}
+
+#[derive(Default)]
+pub struct VirusScan {
+    pub header: Option<Vec<u8>>,
+    pub quarantine: Option<QuarantineReason>,
+}
+
+impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
+    pub async fn scan_viruses(&self, message: &[u8]) -> Result<VirusScan, Cow<'static, [u8]>> {
+        let scanners = &self.core.session.config.data.clamav;
+        if scanners.is_empty() {
+            return Ok(VirusScan::default());
+        }
+
+        let mut result = VirusScan::default();
+        let mut header = Vec::new();
+        for scanner in scanners {
+            if !*scanner.enable.eval(self).await {
+                continue;
+            }
+
+            if message.len() > scanner.max_size {
+                tracing::debug!(
+                    parent: &self.span,
+                    context = "clamav",
+                    event = "skip",
+                    id = &scanner.id,
+                    size = message.len(),
+                    "Message exceeds the maximum scan size.");
+                write_result(&mut header, &scanner.id, "none", None);
+                continue;
+            }
+
+            let time = Instant::now();
+            match scanner.scan(message).await {
+                Ok(ScanResult::Clean) => {
+                    tracing::debug!(
+                        parent: &self.span,
+                        context = "clamav",
+                        event = "clean",
+                        id = &scanner.id,
+                        elapsed = ?time.elapsed());
+                    write_result(&mut header, &scanner.id, "clean", None);
+                }
+                Ok(ScanResult::Infected(virus)) => {
+                    let action = *scanner.action.eval(self).await;
+                    tracing::info!(
+                        parent: &self.span,
+                        context = "clamav",
+                        event = "virus-found",
+                        id = &scanner.id,
+                        virus = &virus,
+                        action = ?action,
+                        return_path = self.data.mail_from.as_ref().map_or("", |m| m.address.as_str()),
+                        "Virus found in message.");
+
+                    match action {
+                        VirusAction::Reject => {
+                            let response =
+                                format!("554 5.7.1 Message rejected: virus {virus} found.\r\n");
+                            self.webhook_rejected("clamav", &response).await;
+                            return Err(response.into_bytes().into());
+                        }
+                        VirusAction::Quarantine => {
+                            result.quarantine = QuarantineReason {
+                                source: "clamav",
+                                reason: format!("Virus found: {virus}"),
+                            }
+                            .into();
+                        }
+                        VirusAction::Tag => (),
+                    }
+                    write_result(&mut header, &scanner.id, "infected", Some(&virus));
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        parent: &self.span,
+                        context = "clamav",
+                        event = "error",
+                        id = &scanner.id,
+                        reason = %err,
+                        "Virus scan failed.");
+                    if scanner.tempfail_on_error {
+                        return Err(
+                            (b"451 4.3.5 Unable to scan message for viruses at this time.\r\n"[..])
+                                .into(),
+                        );
+                    }
+                    write_result(&mut header, &scanner.id, "temperror", None);
+                }
+            }
+        }
+
+        if !header.is_empty() {
+            let mut value = Vec::with_capacity(header.len() + 64);
+            value.extend_from_slice(b"X-Virus-Scan: ");
+            value.extend_from_slice(self.instance.hostname.as_bytes());
+            value.extend_from_slice(&header);
+            value.extend_from_slice(b"\r\n");
+            result.header = value.into();
+        }
+
+        Ok(result)
+    }
+}
+
+fn write_result(header: &mut Vec<u8>, id: &str, result: &str, virus: Option<&str>) {
+    header.extend_from_slice(b";\r\n\t");
+    header.extend_from_slice(id.as_bytes());
+    header.push(b'=');
+    header.extend_from_slice(result.as_bytes());
+    if let Some(virus) = virus {
+        header.extend_from_slice(b" (");
+        header.extend_from_slice(virus.as_bytes());
+        header.push(b')');
+    }
+}
+
+impl ClamAv {
+    pub async fn scan(&self, message: &[u8]) -> Result<ScanResult, Error> {
+        // Idle sessions might have been closed by clamd, retry with another connection
+        while let Some(mut connection) = self.pool.checkout(self.idle_timeout) {
+            match connection.instream(message, self.timeout_scan).await {
+                Ok(result) => {
+                    self.pool.checkin(connection, self.max_idle);
+                    return Ok(result);
+                }
+                Err(Error::Io(_) | Error::Disconnected) => (),
+                Err(err) => return Err(err),
+            }
+        }
+
+        let mut connection = ClamdConnection::connect(self).await?;
+        let result = connection.instream(message, self.timeout_scan).await?;
+        self.pool.checkin(connection, self.max_idle);
+        Ok(result)
+    }
+}
+
+impl ConnectionPool {
+    /// Returns the most recently used idle session, dropping expired ones.
+    pub fn checkout(&self, idle_timeout: Duration) -> Option<ClamdConnection> {
+        let mut connections = self.connections.lock();
+        while let Some(connection) = connections.pop() {
+            if connection.idle_since.elapsed() < idle_timeout {
+                return connection.into();
+            }
+        }
+        None
+    }
+
+    pub fn checkin(&self, mut connection: ClamdConnection, max_idle: usize) {
+        let mut connections = self.connections.lock();
+        if connections.len() < max_idle {
+            connection.idle_since = Instant::now();
+            connections.push(connection);
+        }
+    }
+
+    pub fn idle_count(&self) -> usize {
+        self.connections.lock().len()
+    }
+}
+
+impl ClamdConnection {
+    pub async fn connect(config: &ClamAv) -> Result<Self, Error> {
+        let mut stream = tokio::time::timeout(config.timeout_connect, async {
+            match &config.address {
+                ClamdAddress::Tcp(addrs) => {
+                    let mut last_err = Error::Disconnected;
+                    for addr in addrs {
+                        match TcpStream::connect(addr).await {
+                            Ok(stream) => return Ok(ClamdStream::Tcp(stream)),
+                            Err(err) => {
+                                last_err = Error::Io(err);
+                            }
+                        }
+                    }
+                    Err(last_err)
+                }
+                #[cfg(unix)]
+                ClamdAddress::Unix(path) => tokio::net::UnixStream::connect(path)
+                    .await
+                    .map(ClamdStream::Unix)
+                    .map_err(Error::Io),
+                #[cfg(not(unix))]
+                ClamdAddress::Unix(_) => Err(Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "Unix sockets are not supported on this platform",
+                ))),
+            }
+        })
+        .await
+        .map_err(|_| Error::Timeout)??;
+
+        // Keep the connection open for multiple commands
+        match &mut stream {
+            ClamdStream::Tcp(stream) => stream.write_all(b"zIDSESSION\0").await?,
+            #[cfg(unix)]
+            ClamdStream::Unix(stream) => stream.write_all(b"zIDSESSION\0").await?,
+        }
+
+        Ok(ClamdConnection {
+            stream,
+            idle_since: Instant::now(),
+        })
+    }
+
+    pub async fn instream(
+        &mut self,
+        message: &[u8],
+        timeout: Duration,
+    ) -> Result<ScanResult, Error> {
+        tokio::time::timeout(timeout, async {
+            match &mut self.stream {
+                ClamdStream::Tcp(stream) => instream(stream, message).await,
+                #[cfg(unix)]
+                ClamdStream::Unix(stream) => instream(stream, message).await,
+            }
+        })
+        .await
+        .map_err(|_| Error::Timeout)?
+    }
+}
+
+async fn instream(
+    stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
+    message: &[u8],
+) -> Result<ScanResult, Error> {
+    stream.write_all(b"zINSTREAM\0").await?;
+    for chunk in message.chunks(CHUNK_SIZE) {
+        stream
+            .write_all(&(chunk.len() as u32).to_be_bytes())
+            .await?;
+        stream.write_all(chunk).await?;
+    }
+    stream.write_all(&[0u8; 4]).await?;
+    stream.flush().await?;
+
+    // Replies are terminated by a NUL character
+    let mut reply = Vec::with_capacity(64);
+    let mut buf = [0u8; 256];
+    loop {
+        let bytes_read = stream.read(&mut buf).await?;
+        if bytes_read == 0 {
+            return Err(Error::Disconnected);
+        }
+        if let Some(pos) = buf[..bytes_read].iter().position(|&ch| ch == 0) {
+            reply.extend_from_slice(&buf[..pos]);
+            break;
+        }
+        reply.extend_from_slice(&buf[..bytes_read]);
+        if reply.len() > MAX_REPLY_LEN {
+            return Err(Error::Clamd("Reply too long".to_string()));
+        }
+    }
+
+    parse_reply(&String::from_utf8_lossy(&reply))
+}
+
+fn parse_reply(reply: &str) -> Result<ScanResult, Error> {
+    // Replies within a session are prefixed with the request id, i.e. "1: stream: OK"
+    let reply = reply.trim();
+    let reply = reply
+        .split_once(": ")
+        .filter(|(id, _)| id.chars().all(|ch| ch.is_ascii_digit()))
+        .map_or(reply, |(_, reply)| reply);
+
+    if let Some(result) = reply.strip_prefix("stream: ") {
+        if result == "OK" {
+            return Ok(ScanResult::Clean);
+        } else if let Some(virus) = result.strip_suffix(" FOUND") {
+            return Ok(ScanResult::Infected(virus.to_string()));
+        }
+    }
+
+    Err(Error::Clamd(reply.to_string()))
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "I/O error: {err}"),
+            Error::Timeout => f.write_str("Connection timed out"),
+            Error::Disconnected => f.write_str("Connection closed by clamd"),
+            Error::Clamd(reply) => write!(f, "Unexpected clamd reply: {reply}"),
+        }
+    }
+}
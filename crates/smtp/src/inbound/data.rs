@@ -43,13 +43,16 @@ use tokio::{
 };
 
 use crate::{
-    config::DNSBL_FROM,
-    core::{scripts::ScriptResult, Session, SessionAddress, State},
+    config::{WebhookType, DNSBL_FROM},
+    core::{
+        quarantine::QuarantineReason, scripts::ScriptResult, spam::strip_trust_headers, Session,
+        SessionAddress, State,
+    },
     queue::{self, DomainPart, Message, SimpleEnvelope},
     reporting::analysis::AnalyzeReport,
 };
 
-use super::IsTls;
+use super::{hooks::Stage, milter::Modification, IsTls};
 
 impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
     pub async fn queue_message(&mut self) -> Cow<'static, [u8]> {
@@ -204,6 +207,7 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
         }
 
         // Verify DMARC
+        let mut dmarc_pass = None;
         match &self.data.spf_mail_from {
             Some(spf_output) if dmarc.verify() => {
                 let dmarc_output = self
@@ -232,6 +236,11 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
 
                 // Add to DMARC output to the Authentication-Results header
                 auth_results = auth_results.with_dmarc_result(&dmarc_output);
+                dmarc_pass = match (dmarc_output.spf_result(), dmarc_output.dkim_result()) {
+                    (DmarcResult::Pass, _) | (_, DmarcResult::Pass) => Some(true),
+                    (DmarcResult::Fail(_), _) | (_, DmarcResult::Fail(_)) => Some(false),
+                    _ => None,
+                };
 
                 if !rejected {
                     tracing::debug!(parent: &self.span,
@@ -277,7 +286,8 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
 
         // Analyze reports
         if self.is_report() {
-            self.core.analyze_report(raw_message.clone());
+            self.core
+                .analyze_report(raw_message.clone(), self.complaint_sender(&dkim_output));
             if !rc.analysis.forward {
                 self.data.messages_sent += 1;
                 return (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into();
@@ -285,7 +295,7 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
         }
 
         // Run Milter filters
-        let mut edited_message = match self.run_milters(&auth_message).await {
+        let mut modifications = match self.run_milters(&auth_message).await {
             Ok(modifications) => {
                 tracing::debug!(
                     parent: &self.span,
@@ -301,13 +311,37 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
                     }),
                     "Milter filter(s) accepted message.");
 
-                self.data
-                    .apply_milter_modifications(modifications, &auth_message)
-                    .map(Arc::new)
+                modifications
             }
             Err(response) => return response,
         };
 
+        // Run MTA hooks
+        match self
+            .run_mta_hooks_with_message(Stage::Data, Some(&auth_message))
+            .await
+        {
+            Ok(result) => {
+                if result
+                    .modifications
+                    .iter()
+                    .any(|m| matches!(m, Modification::ReplaceBody { .. }))
+                {
+                    // The message body can only be replaced once
+                    modifications.retain(|m| !matches!(m, Modification::ReplaceBody { .. }));
+                }
+                modifications.extend(result.modifications);
+                if result.quarantine.is_some() {
+                    self.data.quarantine = result.quarantine;
+                }
+            }
+            Err(response) => return response,
+        }
+        let mut edited_message = self
+            .data
+            .apply_milter_modifications(modifications, &auth_message)
+            .map(Arc::new);
+
         // Pipe message
         for pipe in &dc.pipe_commands {
             if let Some(command_) = pipe.command.eval(self).await {
@@ -395,15 +429,49 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
             }
         }
 
+        // Parse the message once for both the attachment policy and Sieve
+        let script = dc.script.eval(self).await.clone();
+        let mut parsed_message = if script.is_some() || *dc.attachments.enable.eval(self).await {
+            mail_parser::Message::parse(edited_message.as_ref().unwrap_or(&raw_message))
+                .map(|message| message.into_owned())
+        } else {
+            None
+        };
+
+        // Enforce attachment policy
+        match self.check_attachments(parsed_message.as_ref()).await {
+            Ok(result) => {
+                if let Some(message) = result.message {
+                    if script.is_some() {
+                        parsed_message = mail_parser::Message::parse(&message)
+                            .map(|message| message.into_owned());
+                    }
+                    edited_message = Arc::new(message).into();
+                }
+                if result.quarantine.is_some() {
+                    self.data.quarantine = result.quarantine;
+                }
+            }
+            Err(response) => return response,
+        }
+
+        // Scan for viruses
+        let virus_scan = match self
+            .scan_viruses(edited_message.as_ref().unwrap_or(&raw_message))
+            .await
+        {
+            Ok(virus_scan) => {
+                if virus_scan.quarantine.is_some() {
+                    self.data.quarantine = virus_scan.quarantine;
+                }
+                virus_scan.header
+            }
+            Err(response) => return response,
+        };
+
         // Sieve filtering
-        if let Some(script) = dc.script.eval(self).await {
-            match self
-                .run_script(
-                    script.clone(),
-                    Some(edited_message.as_ref().unwrap_or(&raw_message).clone()),
-                )
-                .await
-            {
+        if let Some(script) = script {
+            match self.run_script(script, parsed_message).await {
                 ScriptResult::Accept { modifications } => {
                     if !modifications.is_empty() {
                         self.data.apply_sieve_modifications(modifications)
@@ -424,14 +492,61 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
                         event = "reject",
                         reason = message);
 
+                    self.webhook_rejected("sieve", &message).await;
                     return message.into_bytes().into();
                 }
+                ScriptResult::Quarantine {
+                    reason,
+                    modifications,
+                } => {
+                    if !modifications.is_empty() {
+                        self.data.apply_sieve_modifications(modifications)
+                    }
+                    self.data.quarantine = QuarantineReason {
+                        source: "sieve",
+                        reason,
+                    }
+                    .into();
+                }
                 ScriptResult::Discard => {
                     return (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into();
                 }
             }
         }
 
+        // Spam filtering
+        let spam_result = self
+            .spam_filter(
+                edited_message.as_ref().unwrap_or(&raw_message),
+                &dkim_output,
+                dmarc_pass,
+            )
+            .await;
+        if let Some(spam_result) = &spam_result {
+            if self
+                .core
+                .spam
+                .threshold_reject
+                .map_or(false, |threshold| spam_result.score >= threshold)
+            {
+                tracing::info!(parent: &self.span,
+                    context = "spam-filter",
+                    event = "reject",
+                    return_path = self.data.mail_from.as_ref().unwrap().address,
+                    from = auth_message.from(),
+                    score = spam_result.score,
+                    tests = ?spam_result.tests,
+                    "Message rejected as spam.");
+
+                return (&b"550 5.7.1 Message rejected as spam.\r\n"[..]).into();
+            }
+        }
+
+        // Greylist auto-whitelisting
+        if !spam_result.as_ref().map_or(false, |result| result.is_spam) {
+            self.greylist_whitelist_authenticated(&dkim_output).await;
+        }
+
         // Build message
         let mail_from = self.data.mail_from.clone().unwrap();
         let rcpt_to = std::mem::take(&mut self.data.rcpt_to);
@@ -481,6 +596,16 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
             }
         }
 
+        // Add spam filter headers
+        if let Some(spam_result) = &spam_result {
+            spam_result.write_header(&mut headers);
+        }
+
+        // Add antivirus headers
+        if let Some(virus_scan) = &virus_scan {
+            headers.extend_from_slice(virus_scan);
+        }
+
         // Add any missing headers
         if !auth_message.has_date_header() && *dc.add_date.eval(self).await {
             headers.extend_from_slice(b"Date: ");
@@ -500,8 +625,16 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
             headers.extend_from_slice(b">\r\n");
         }
 
-        // DKIM sign
+        // Remove spam and virus scan headers added by unauthenticated senders, this is
+        // done after DKIM and ARC verification as these might be covered by a signature
         let raw_message = edited_message.unwrap_or(raw_message);
+        let raw_message = if self.data.authenticated_as.is_empty() {
+            strip_trust_headers(&raw_message).map_or(raw_message, Arc::new)
+        } else {
+            raw_message
+        };
+
+        // DKIM sign
         for signer in ac.dkim.sign.eval_and_capture(self).await.into_value(self) {
             match signer.sign_chained(&[headers.as_ref(), &raw_message]) {
                 Ok(signature) => {
@@ -517,18 +650,50 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
             }
         }
 
+        // Sender Rewriting Scheme
+        if let Some(srs_address) = self.srs_forward(&message).await {
+            tracing::debug!(parent: &self.span,
+                context = "srs",
+                event = "rewrite",
+                return_path = message.return_path,
+                srs_address = srs_address);
+
+            message.return_path_lcase = srs_address.to_lowercase();
+            message.return_path_domain = message.return_path_lcase.domain_part().to_string();
+            message.return_path = srs_address;
+        }
+
         // Update size
         message.size = raw_message.len() + headers.len();
 
+        // Hold message in quarantine
+        if let Some(quarantine) = self.data.quarantine.take() {
+            return if self
+                .hold_in_quarantine(quarantine, &message, &headers, &raw_message)
+                .await
+            {
+                self.data.messages_sent += 1;
+                (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into()
+            } else {
+                (b"451 4.3.5 Unable to accept message at this time.\r\n"[..]).into()
+            };
+        }
+
         // Verify queue quota
         if self.core.queue.has_quota(&mut message).await {
             let queue_id = message.id;
+            let webhook_data = self.webhook_queued_data(&message);
             if self
                 .core
                 .queue
                 .queue_message(message, Some(&headers), &raw_message, &self.span)
                 .await
             {
+                if let Some(webhook_data) = webhook_data {
+                    self.core
+                        .webhook_event(WebhookType::MessageQueued, webhook_data)
+                        .await;
+                }
                 self.state = State::Accepted(queue_id);
                 self.data.messages_sent += 1;
                 (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into()
@@ -31,7 +31,7 @@ use mail_auth::spf::verify::HasLabels;
 use smtp_proto::*;
 use tokio::io::{AsyncRead, AsyncWrite};
 
-use super::IsTls;
+use super::{hooks::Stage, IsTls};
 
 impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
     pub async fn handle_ehlo(&mut self, domain: String) -> Result<(), ()> {
@@ -103,6 +103,7 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
                         domain = &self.data.helo_domain,
                         reason = message);
 
+                    self.webhook_rejected("sieve", &message).await;
                     self.data.mail_from = None;
                     self.data.helo_domain = prev_helo_domain;
                     self.data.spf_ehlo = None;
@@ -110,6 +111,14 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
                 }
             }
 
+            // Run MTA hooks
+            if let Err(response) = self.run_mta_hooks(Stage::Ehlo).await {
+                self.data.mail_from = None;
+                self.data.helo_domain = prev_helo_domain;
+                self.data.spf_ehlo = None;
+                return self.write(&response).await;
+            }
+
             tracing::debug!(parent: &self.span,
                 context = "ehlo",
                 event = "ehlo",
@@ -284,7 +293,7 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
         true
     }
 
-    async fn is_dns_blocked(&self, domain: String) -> bool {
+    pub async fn is_dns_blocked(&self, domain: String) -> bool {
         match self.core.resolvers.dns.ipv4_lookup(&domain).await {
             Ok(ips) => {
                 for ip in ips.iter() {
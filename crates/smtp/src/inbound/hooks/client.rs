@@ -0,0 +1,68 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::config::MtaHook;
+
+use super::{Request, Response};
+
+pub async fn send_mta_hook_request(hook: &MtaHook, request: Request) -> Result<Response, String> {
+    let body = serde_json::to_vec(&request)
+        .map_err(|err| format!("Failed to serialize hook request: {err}"))?;
+    let mut builder = hook
+        .client
+        .post(&hook.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body);
+    if let Some(username) = &hook.auth_username {
+        builder = builder.basic_auth(username, hook.auth_secret.as_ref());
+    }
+
+    let mut response = builder
+        .send()
+        .await
+        .map_err(|err| format!("Hook request failed: {err}"))?;
+    if !response.status().is_success() {
+        return Err(format!("Hook returned HTTP {}", response.status()));
+    }
+    if response
+        .content_length()
+        .map_or(false, |len| len as usize > hook.max_response_size)
+    {
+        return Err("Hook response exceeds the maximum allowed size".to_string());
+    }
+
+    // Stop reading as soon as the limit is exceeded, the length header is optional
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|err| format!("Failed to read hook response: {err}"))?
+    {
+        if bytes.len() + chunk.len() > hook.max_response_size {
+            return Err("Hook response exceeds the maximum allowed size".to_string());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    serde_json::from_slice(&bytes).map_err(|err| format!("Failed to parse hook response: {err}"))
+}
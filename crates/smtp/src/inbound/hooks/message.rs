@@ -0,0 +1,199 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+use mail_auth::AuthenticatedMessage;
+use mail_builder::encoders::base64::base64_encode;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    core::{quarantine::QuarantineReason, Session},
+    inbound::{milter::Modification, IsTls},
+};
+
+use super::{
+    client::send_mta_hook_request, Action, Address, Client, Context, Envelope, HookResult, Message,
+    Protocol, Request, Sasl, Server, Stage, Tls,
+};
+
+impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
+    pub async fn run_mta_hooks(&mut self, stage: Stage) -> Result<(), Cow<'static, [u8]>> {
+        let result = self.run_mta_hooks_with_message(stage, None).await?;
+        if result.quarantine.is_some() {
+            self.data.quarantine = result.quarantine;
+        }
+        Ok(())
+    }
+
+    pub async fn run_mta_hooks_with_message(
+        &self,
+        stage: Stage,
+        message: Option<&AuthenticatedMessage<'_>>,
+    ) -> Result<HookResult, Cow<'static, [u8]>> {
+        let mut result = HookResult::default();
+        for hook in &self.core.session.config.hooks {
+            if !hook.stages.contains(&stage) || !*hook.enable.eval(self).await {
+                continue;
+            }
+
+            let response = match send_mta_hook_request(
+                hook,
+                self.build_mta_hook_request(stage, message),
+            )
+            .await
+            {
+                Ok(response) => response,
+                Err(err) => {
+                    tracing::warn!(
+                        parent: &self.span,
+                        context = "mta-hook",
+                        event = "error",
+                        url = &hook.url,
+                        reason = err,
+                        "MTA hook failed");
+                    if hook.tempfail_on_error {
+                        return Err(
+                            (b"451 4.3.5 Unable to accept message at this time.\r\n"[..]).into(),
+                        );
+                    }
+                    continue;
+                }
+            };
+
+            tracing::debug!(
+                parent: &self.span,
+                context = "mta-hook",
+                event = "response",
+                url = &hook.url,
+                stage = ?stage,
+                action = ?response.action,
+                modifications = response.modifications.len());
+
+            match response.action {
+                Action::Accept | Action::Quarantine => {
+                    if response.action == Action::Quarantine {
+                        result.quarantine = QuarantineReason {
+                            source: "hook",
+                            reason: response
+                                .response
+                                .and_then(|r| r.message)
+                                .unwrap_or_else(|| "Quarantined by MTA hook".to_string()),
+                        }
+                        .into();
+                    }
+
+                    // Message modifications are only possible once the message is available
+                    if stage == Stage::Data {
+                        for modification in response.modifications {
+                            let modification = Modification::from(modification);
+                            if matches!(modification, Modification::ReplaceBody { .. }) {
+                                // The message body can only be replaced once
+                                result
+                                    .modifications
+                                    .retain(|m| !matches!(m, Modification::ReplaceBody { .. }));
+                            }
+                            result.modifications.push(modification);
+                        }
+                    }
+                }
+                Action::Discard if stage == Stage::Data => {
+                    return Err((b"250 2.0.0 Message queued for delivery.\r\n"[..]).into());
+                }
+                Action::Discard => (),
+                Action::Reject => {
+                    let response = response.response.unwrap_or_default().to_bytes(
+                        550,
+                        "5.7.1",
+                        "Rejected by policy.",
+                    );
+                    self.webhook_rejected("hook", &String::from_utf8_lossy(&response))
+                        .await;
+                    return Err(response.into());
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn build_mta_hook_request(
+        &self,
+        stage: Stage,
+        message: Option<&AuthenticatedMessage<'_>>,
+    ) -> Request {
+        let (tls_version, tls_cipher) = self.stream.tls_version_and_cipher();
+        Request {
+            context: Context {
+                stage,
+                client: Client {
+                    ip: self.data.remote_ip.to_string(),
+                    port: self.data.remote_port,
+                    helo: (!self.data.helo_domain.is_empty())
+                        .then(|| self.data.helo_domain.clone()),
+                },
+                sasl: (!self.data.authenticated_as.is_empty()).then(|| Sasl {
+                    login: self.data.authenticated_as.clone(),
+                }),
+                tls: self.stream.is_tls().then(|| Tls {
+                    version: tls_version.to_string(),
+                    cipher: tls_cipher.to_string(),
+                }),
+                server: Server {
+                    name: self.instance.hostname.clone(),
+                    ip: self.data.local_ip.to_string(),
+                    listener: self.instance.id.clone(),
+                },
+                protocol: Protocol { version: 1 },
+            },
+            envelope: self.data.mail_from.as_ref().map(|from| Envelope {
+                from: Address {
+                    address: from.address.clone(),
+                },
+                to: self
+                    .data
+                    .rcpt_to
+                    .iter()
+                    .map(|rcpt| Address {
+                        address: rcpt.address.clone(),
+                    })
+                    .collect(),
+            }),
+            message: message.map(|message| Message {
+                headers: message
+                    .raw_parsed_headers()
+                    .iter()
+                    .map(|(name, value)| {
+                        (
+                            String::from_utf8_lossy(name).into_owned(),
+                            String::from_utf8_lossy(value).trim().to_string(),
+                        )
+                    })
+                    .collect(),
+                contents: String::from_utf8(base64_encode(message.raw_body()).unwrap_or_default())
+                    .unwrap_or_default(),
+                size: message.raw_message().len(),
+            }),
+        }
+    }
+}
@@ -0,0 +1,251 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::quarantine::QuarantineReason;
+
+use super::milter;
+
+pub mod client;
+pub mod message;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    Connect,
+    Ehlo,
+    Mail,
+    Rcpt,
+    Data,
+}
+
+#[derive(Default)]
+pub struct HookResult {
+    pub modifications: Vec<milter::Modification>,
+    pub quarantine: Option<QuarantineReason>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Request {
+    pub context: Context,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub envelope: Option<Envelope>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<Message>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Context {
+    pub stage: Stage,
+    pub client: Client,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sasl: Option<Sasl>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<Tls>,
+    pub server: Server,
+    pub protocol: Protocol,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Client {
+    pub ip: String,
+    pub port: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub helo: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Sasl {
+    pub login: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Tls {
+    pub version: String,
+    pub cipher: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Server {
+    pub name: String,
+    pub ip: String,
+    pub listener: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Protocol {
+    pub version: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Envelope {
+    pub from: Address,
+    pub to: Vec<Address>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Address {
+    pub address: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Message {
+    pub headers: Vec<(String, String)>,
+    // Base64 encoded, the body may contain 8-bit or binary data
+    pub contents: String,
+    pub size: usize,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Response {
+    #[serde(default)]
+    pub action: Action,
+    #[serde(default)]
+    pub response: Option<SmtpResponse>,
+    #[serde(default)]
+    pub modifications: Vec<Modification>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    #[default]
+    Accept,
+    Discard,
+    Reject,
+    Quarantine,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SmtpResponse {
+    pub status: Option<u16>,
+    pub enhanced_status: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Modification {
+    ChangeFrom {
+        value: String,
+    },
+    AddRecipient {
+        value: String,
+    },
+    DeleteRecipient {
+        value: String,
+    },
+    ReplaceContents {
+        value: String,
+    },
+    AddHeader {
+        name: String,
+        value: String,
+    },
+    InsertHeader {
+        index: u32,
+        name: String,
+        value: String,
+    },
+    ChangeHeader {
+        index: u32,
+        name: String,
+        value: String,
+    },
+    DeleteHeader {
+        index: u32,
+        name: String,
+    },
+}
+
+impl From<Modification> for milter::Modification {
+    fn from(modification: Modification) -> Self {
+        match modification {
+            Modification::ChangeFrom { value } => milter::Modification::ChangeFrom {
+                sender: value,
+                args: String::new(),
+            },
+            Modification::AddRecipient { value } => milter::Modification::AddRcpt {
+                recipient: value,
+                args: String::new(),
+            },
+            Modification::DeleteRecipient { value } => {
+                milter::Modification::DeleteRcpt { recipient: value }
+            }
+            Modification::ReplaceContents { value } => milter::Modification::ReplaceBody {
+                value: value.into_bytes(),
+            },
+            Modification::AddHeader { name, value } => {
+                milter::Modification::AddHeader { name, value }
+            }
+            Modification::InsertHeader { index, name, value } => {
+                milter::Modification::InsertHeader { index, name, value }
+            }
+            Modification::ChangeHeader { index, name, value } => {
+                milter::Modification::ChangeHeader { index, name, value }
+            }
+            // Milters delete headers by changing them to an empty value
+            Modification::DeleteHeader { index, name } => milter::Modification::ChangeHeader {
+                index,
+                name,
+                value: String::new(),
+            },
+        }
+    }
+}
+
+impl SmtpResponse {
+    pub fn to_bytes(&self, status: u16, enhanced_status: &str, message: &str) -> Vec<u8> {
+        // Hooks may only reject, the enhanced status code has to match the status class
+        let hook_status = self.status.filter(|status| (400..600).contains(status));
+        let class = hook_status.unwrap_or(status) / 100;
+        let enhanced_status = self
+            .enhanced_status
+            .as_deref()
+            .filter(|es| is_enhanced_status(es, class))
+            .unwrap_or(if class == status / 100 {
+                enhanced_status
+            } else if class == 4 {
+                "4.0.0"
+            } else {
+                "5.0.0"
+            });
+        let status = hook_status.unwrap_or(status);
+        let message = self.message.as_deref().unwrap_or(message);
+        format!(
+            "{status} {enhanced_status} {}\r\n",
+            message.replace(['\r', '\n'], " ").trim()
+        )
+        .into_bytes()
+    }
+}
+
+fn is_enhanced_status(value: &str, class: u16) -> bool {
+    let mut parts = value.split('.');
+    parts.next() == Some(class.to_string().as_str())
+        && parts.clone().count() == 2
+        && parts
+            .all(|part| (1..=3).contains(&part.len()) && part.bytes().all(|ch| ch.is_ascii_digit()))
+}
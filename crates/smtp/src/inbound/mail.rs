@@ -29,11 +29,11 @@ use tokio::io::{AsyncRead, AsyncWrite};
 
 use crate::{
     config::{DNSBL_IPREV, DNSBL_RETURN_PATH},
-    core::{scripts::ScriptResult, Session, SessionAddress},
+    core::{quarantine::QuarantineReason, scripts::ScriptResult, Session, SessionAddress},
     queue::DomainPart,
 };
 
-use super::IsTls;
+use super::{hooks::Stage, IsTls};
 
 impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
     pub async fn handle_mail_from(&mut self, from: MailFrom<String>) -> Result<(), ()> {
@@ -150,12 +150,26 @@ impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
                         self.data.apply_sieve_modifications(modifications)
                     }
                 }
+                ScriptResult::Quarantine {
+                    reason,
+                    modifications,
+                } => {
+                    if !modifications.is_empty() {
+                        self.data.apply_sieve_modifications(modifications);
+                    }
+                    self.data.quarantine = QuarantineReason {
+                        source: "sieve",
+                        reason,
+                    }
+                    .into();
+                }
                 ScriptResult::Reject(message) => {
                     tracing::debug!(parent: &self.span,
                         context = "sieve",
                         event = "reject",
                         address = &self.data.mail_from.as_ref().unwrap().address,
                         reason = message);
+                    self.webhook_rejected("sieve", &message).await;
                     self.data.mail_from = None;
                     return self.write(message.as_bytes()).await;
                 }
@@ -163,6 +177,12 @@ impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
             }
         }
 
+        // Run MTA hooks
+        if let Err(response) = self.run_mta_hooks(Stage::Mail).await {
+            self.data.mail_from = None;
+            return self.write(&response).await;
+        }
+
         // Address rewriting
         if let Some(new_address) = self
             .core
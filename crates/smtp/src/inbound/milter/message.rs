@@ -29,7 +29,7 @@ use tokio::io::{AsyncRead, AsyncWrite};
 
 use crate::{
     config::Milter,
-    core::{Session, SessionAddress, SessionData},
+    core::{quarantine::QuarantineReason, Session, SessionAddress, SessionData},
     inbound::{milter::MilterClient, IsTls},
     queue::DomainPart,
     DAEMON_NAME,
@@ -79,7 +79,11 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
                         action = ?action,
                         "Milter rejected message.");
 
-                    return Err(match action {
+                    let is_reject = matches!(
+                        action,
+                        Action::Reject | Action::TempFail | Action::ReplyCode { .. }
+                    );
+                    let response: Cow<'static, [u8]> = match action {
                         Action::Discard => {
                             (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into()
                         }
@@ -100,7 +104,12 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
                         Action::Shutdown => (b"421 4.3.0 Server shutting down.\r\n"[..]).into(),
                         Action::ConnectionFailure => (b""[..]).into(), // TODO: Not very elegant design, fix.
                         Action::Accept | Action::Continue => unreachable!(),
-                    });
+                    };
+                    if is_reject {
+                        self.webhook_rejected("milter", &String::from_utf8_lossy(&response))
+                            .await;
+                    }
+                    return Err(response);
                 }
                 Err(Rejection::Error(err)) => {
                     tracing::warn!(
@@ -353,7 +362,12 @@ impl SessionData {
                     }
                 }
                 Modification::Quarantine { reason } => {
-                    header_changes.push((0, "X-Quarantine".to_string(), reason, false));
+                    header_changes.push((0, "X-Quarantine".to_string(), reason.clone(), false));
+                    self.quarantine = QuarantineReason {
+                        source: "milter",
+                        reason,
+                    }
+                    .into();
                 }
             }
         }
@@ -29,13 +29,17 @@ use tokio_rustls::server::TlsStream;
 
 use crate::config::{ArcSealer, DkimSigner};
 
+pub mod attachments;
 pub mod auth;
+pub mod clamav;
 pub mod data;
 pub mod ehlo;
+pub mod hooks;
 pub mod mail;
 pub mod milter;
 pub mod rcpt;
 pub mod session;
+pub mod spam;
 pub mod spawn;
 pub mod vrfy;
 
@@ -27,11 +27,15 @@ use smtp_proto::{
 use tokio::io::{AsyncRead, AsyncWrite};
 
 use crate::{
-    core::{scripts::ScriptResult, Session, SessionAddress},
+    core::{
+        quarantine::QuarantineReason, scripts::ScriptResult, srs::SrsError, Session, SessionAddress,
+    },
     queue::DomainPart,
 };
 
-impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
+use super::{hooks::Stage, IsTls};
+
+impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
     pub async fn handle_rcpt_to(&mut self, to: RcptTo<String>) -> Result<(), ()> {
         #[cfg(feature = "test_mode")]
         if self.instance.id.ends_with("-debug") {
@@ -39,6 +43,10 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
                 return self.write(b"503 5.5.1 Invalid recipient.\r\n").await;
             } else if to.address.contains("delay@") {
                 return self.write(b"451 4.5.3 Try again later.\r\n").await;
+            } else if to.address.contains("ratelimit@") {
+                return self
+                    .write(b"451 4.7.28 Rate limit exceeded, try again later.\r\n")
+                    .await;
             }
         }
 
@@ -60,12 +68,44 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
                 .await;
         }
 
+        // Reverse SRS rewriting of bounces
+        let mut address = to.address;
+        let mut is_srs_bounce = false;
+        match self.core.mail_auth.srs.reverse(&address) {
+            Ok(Some(orig_address)) => {
+                tracing::debug!(parent: &self.span,
+                    context = "srs",
+                    event = "reverse",
+                    address = address,
+                    orig_address = orig_address);
+
+                address = orig_address;
+                is_srs_bounce = true;
+            }
+            Ok(None) => (),
+            Err(err) => {
+                tracing::debug!(parent: &self.span,
+                    context = "srs",
+                    event = "invalid",
+                    address = address,
+                    reason = ?err);
+
+                return self
+                    .rcpt_error(if err == SrsError::Expired {
+                        &b"550 5.1.1 SRS address has expired.\r\n"[..]
+                    } else {
+                        &b"550 5.1.1 Invalid SRS address.\r\n"[..]
+                    })
+                    .await;
+            }
+        }
+
         // Build RCPT
-        let address_lcase = to.address.to_lowercase();
+        let address_lcase = address.to_lowercase();
         let rcpt = SessionAddress {
             domain: address_lcase.domain_part().to_string(),
             address_lcase,
-            address: to.address,
+            address,
             flags: to.flags,
             dsn_info: to.orcpt,
         };
@@ -99,12 +139,26 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
                             self.data.apply_sieve_modifications(modifications);
                         }
                     }
+                    ScriptResult::Quarantine {
+                        reason,
+                        modifications,
+                    } => {
+                        if !modifications.is_empty() {
+                            self.data.apply_sieve_modifications(modifications);
+                        }
+                        self.data.quarantine = QuarantineReason {
+                            source: "sieve",
+                            reason,
+                        }
+                        .into();
+                    }
                     ScriptResult::Reject(message) => {
                         tracing::debug!(parent: &self.span,
                         context = "sieve",
                         event = "reject",
                         address = self.data.rcpt_to.last().unwrap().address,
                         reason = message);
+                        self.webhook_rejected("sieve", &message).await;
                         self.data.rcpt_to.pop();
                         return self.write(message.as_bytes()).await;
                     }
@@ -140,6 +194,12 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
             }
         }
 
+        // Run MTA hooks
+        if let Err(response) = self.run_mta_hooks(Stage::Rcpt).await {
+            self.data.rcpt_to.pop();
+            return self.write(&response).await;
+        }
+
         // Verify address
         let rcpt = self.data.rcpt_to.last().unwrap();
         if let Some(directory) = self
@@ -179,7 +239,7 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
                             .write(b"451 4.4.3 Unable to verify address at this time.\r\n")
                             .await;
                     }
-                } else if !self.params.rcpt_relay {
+                } else if !self.params.rcpt_relay && !is_srs_bounce {
                     tracing::debug!(parent: &self.span,
                         context = "rcpt", 
                         event = "error",
@@ -201,7 +261,7 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
                     .write(b"451 4.4.3 Unable to verify address at this time.\r\n")
                     .await;
             }
-        } else if !self.params.rcpt_relay {
+        } else if !self.params.rcpt_relay && !is_srs_bounce {
             tracing::debug!(parent: &self.span,
                 context = "rcpt", 
                 event = "error",
@@ -212,6 +272,34 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
             return self.rcpt_error(b"550 5.1.2 Relay not allowed.\r\n").await;
         }
 
+        // Suppression list
+        if self.is_rcpt_suppressed().await {
+            tracing::debug!(parent: &self.span,
+                context = "suppression",
+                event = "reject",
+                address = &self.data.rcpt_to.last().unwrap().address_lcase,
+                "Recipient is on the suppression list.");
+
+            self.data.rcpt_to.pop();
+            return self
+                .rcpt_error(b"550 5.1.1 Recipient address is on the suppression list.\r\n")
+                .await;
+        }
+
+        // Greylisting
+        if self.is_greylisted().await {
+            tracing::debug!(parent: &self.span,
+                context = "greylist",
+                event = "defer",
+                address = &self.data.rcpt_to.last().unwrap().address_lcase,
+                "Recipient greylisted.");
+
+            self.data.rcpt_to.pop();
+            return self
+                .write(b"451 4.7.1 Greylisted, please try again later.\r\n")
+                .await;
+        }
+
         if self.is_allowed().await {
             tracing::debug!(parent: &self.span,
                     context = "rcpt",
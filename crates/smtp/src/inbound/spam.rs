@@ -0,0 +1,241 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{net::IpAddr, time::SystemTime};
+
+use mail_auth::{DkimOutput, DkimResult, IprevOutput, IprevResult, SpfResult};
+use mail_parser::{HeaderValue, Message, PartType};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::core::{
+    spam::{from_address, message_url_hosts, SpamResult},
+    Session,
+};
+
+use super::IsTls;
+
+const MAX_URL_LOOKUPS: usize = 16;
+
+impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
+    pub async fn spam_filter(
+        &self,
+        raw_message: &[u8],
+        dkim_output: &[DkimOutput<'_>],
+        dmarc_pass: Option<bool>,
+    ) -> Option<SpamResult> {
+        let config = &self.core.spam;
+        if !*config.enable.eval(self).await {
+            return None;
+        }
+        let message = Message::parse(raw_message)?;
+        let mut tests = Vec::new();
+
+        // Header heuristics
+        if let Some(date) = message.date() {
+            let now = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()) as i64;
+            if date.to_timestamp() > now + 86400 {
+                tests.push("DATE_IN_FUTURE");
+            }
+        } else {
+            tests.push("MISSING_DATE");
+        }
+        if message.message_id().is_none() {
+            tests.push("MISSING_MESSAGE_ID");
+        }
+        match message.subject().map(|s| s.trim()) {
+            Some(subject) if !subject.is_empty() => {
+                let letters = subject.chars().filter(|c| c.is_alphabetic());
+                if letters.clone().count() >= 10 && letters.clone().all(|c| c.is_uppercase()) {
+                    tests.push("SUBJECT_ALL_CAPS");
+                }
+                if ["!!!", "???", "$$$"].iter().any(|p| subject.contains(p)) {
+                    tests.push("SUBJECT_EXCESS_PUNCT");
+                }
+            }
+            _ => {
+                tests.push("MISSING_SUBJECT");
+            }
+        }
+        if matches!(message.to(), HeaderValue::Empty) && matches!(message.cc(), HeaderValue::Empty)
+        {
+            tests.push("MISSING_TO");
+        }
+        let from = from_address(&message).unwrap_or_default().to_lowercase();
+        let from_domain = from.rsplit_once('@').map_or("", |(_, domain)| domain);
+        if let HeaderValue::Address(addr) = message.from() {
+            if addr.name.as_ref().map_or(false, |name| {
+                name.contains('@') && !name.to_lowercase().contains(from.as_str())
+            }) {
+                tests.push("FROM_NAME_HAS_ADDRESS");
+            }
+        }
+        if let HeaderValue::Address(addr) = message.reply_to() {
+            if let Some((_, domain)) = addr.address.as_ref().and_then(|a| a.rsplit_once('@')) {
+                if !from_domain.is_empty() && !domain.eq_ignore_ascii_case(from_domain) {
+                    tests.push("REPLY_TO_DIFF_DOMAIN");
+                }
+            }
+        }
+        if message
+            .parts
+            .iter()
+            .any(|p| matches!(p.body, PartType::Html(_)))
+            && !message
+                .parts
+                .iter()
+                .any(|p| matches!(p.body, PartType::Text(_)))
+        {
+            tests.push("HTML_ONLY");
+        }
+
+        // Authentication results
+        match self.data.spf_mail_from.as_ref().map(|spf| spf.result()) {
+            Some(SpfResult::Fail) => tests.push("SPF_FAIL"),
+            Some(SpfResult::SoftFail) => tests.push("SPF_SOFTFAIL"),
+            _ => (),
+        }
+        if !dkim_output.is_empty() {
+            if !dkim_output
+                .iter()
+                .any(|d| matches!(d.result(), DkimResult::Pass))
+                && dkim_output
+                    .iter()
+                    .any(|d| matches!(d.result(), DkimResult::Fail(_) | DkimResult::PermError(_)))
+            {
+                tests.push("DKIM_FAIL");
+            }
+        } else if self.core.mail_auth.dkim.verify.eval(self).await.verify() {
+            tests.push("DKIM_NONE");
+        }
+        if dmarc_pass == Some(false) {
+            tests.push("DMARC_FAIL");
+        }
+        if matches!(
+            &self.data.iprev,
+            Some(IprevOutput {
+                result: IprevResult::Fail(_) | IprevResult::PermError(_),
+                ..
+            })
+        ) {
+            tests.push("IPREV_FAIL");
+        }
+
+        // Domain and URL reputation
+        let hosts = message_url_hosts(&message);
+        if hosts.iter().any(|host| host.parse::<IpAddr>().is_ok()) {
+            tests.push("URL_IP_HOST");
+        }
+        if let Some(block_list) = &config.block_list {
+            'outer: for domain in std::iter::once(from_domain)
+                .filter(|d| !d.is_empty())
+                .chain(hosts.iter().map(|h| h.as_str()))
+            {
+                // Also match parent domains, i.e. 'www.example.org' is blocked by 'example.org'
+                let mut domain = domain;
+                loop {
+                    if block_list.contains(domain).await.unwrap_or(false) {
+                        tests.push("DOMAIN_BLOCKED");
+                        break 'outer;
+                    }
+                    match domain.split_once('.') {
+                        Some((_, parent)) if parent.contains('.') => domain = parent,
+                        _ => break,
+                    }
+                }
+            }
+        }
+        if let Some(allow_list) = &config.allow_list {
+            if dmarc_pass == Some(true)
+                && !from_domain.is_empty()
+                && allow_list.contains(from_domain).await.unwrap_or(false)
+            {
+                tests.push("DOMAIN_ALLOWED");
+            }
+        }
+        if !config.url_lookup.is_empty() {
+            'outer: for host in hosts
+                .iter()
+                .filter(|host| host.parse::<IpAddr>().is_err())
+                .take(MAX_URL_LOOKUPS)
+            {
+                let host = host.strip_prefix("www.").unwrap_or(host);
+                for list in &config.url_lookup {
+                    if self.is_dns_blocked(format!("{host}.{list}")).await {
+                        tracing::debug!(parent: &self.span,
+                            context = "spam-filter",
+                            event = "url-listed",
+                            list = list,
+                            host = host,
+                        );
+                        tests.push("URL_LISTED");
+                        break 'outer;
+                    }
+                }
+            }
+        }
+
+        // Bayes classifier
+        if let (true, Some(store)) = (config.bayes.enable, &self.core.store) {
+            match self.core.bayes_classify(store, None, &message).await {
+                Ok(Some(probability)) => {
+                    if probability >= config.bayes.spam_threshold {
+                        tests.push("BAYES_SPAM");
+                    } else if probability <= config.bayes.ham_threshold {
+                        tests.push("BAYES_HAM");
+                    }
+                }
+                Ok(None) => (),
+                Err(err) => {
+                    tracing::debug!(parent: &self.span,
+                        context = "spam-filter",
+                        event = "error",
+                        reason = %err,
+                        "Failed to classify message.");
+                }
+            }
+        }
+
+        let score = tests
+            .iter()
+            .map(|test| config.scores.get(test).copied().unwrap_or_default())
+            .sum::<f64>();
+        let result = SpamResult {
+            score,
+            required: config.threshold_spam,
+            is_spam: score >= config.threshold_spam,
+            tests,
+        };
+
+        tracing::debug!(parent: &self.span,
+            context = "spam-filter",
+            event = "classify",
+            score = result.score,
+            is_spam = result.is_spam,
+            tests = ?result.tests,
+        );
+
+        result.into()
+    }
+}
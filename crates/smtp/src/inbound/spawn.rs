@@ -37,7 +37,7 @@ use crate::{
     queue, reporting,
 };
 
-use super::IsTls;
+use super::{hooks::Stage, IsTls};
 
 impl SessionManager for SmtpSessionManager {
     fn spawn(&self, session: utils::listener::SessionData<TcpStream>) {
@@ -129,6 +129,12 @@ impl<T: AsyncRead + AsyncWrite + IsTls + Unpin> Session<T> {
             }
         }
 
+        // Run MTA hooks
+        if let Err(response) = self.run_mta_hooks(Stage::Connect).await {
+            let _ = self.write(&response).await;
+            return false;
+        }
+
         let instance = self.instance.clone();
         if self.write(instance.data.as_bytes()).await.is_err() {
             return false;
@@ -22,19 +22,22 @@
 */
 
 use crate::core::{
-    throttle::ThrottleKeyHasherBuilder, QueueCore, ReportCore, SessionCore, TlsConnectors, SMTP,
+    throttle::ThrottleKeyHasherBuilder, webhook::SpawnWebhooks, QueueCore, ReportCore, SessionCore,
+    TlsConnectors, WebhookCore, SMTP,
 };
 use std::sync::Arc;
 
 use config::{
-    auth::ConfigAuth, queue::ConfigQueue, remote::ConfigHost, report::ConfigReport,
-    resolver::ConfigResolver, scripts::ConfigSieve, session::ConfigSession, ConfigContext, Host,
+    auth::ConfigAuth, journal::ConfigJournal, quarantine::ConfigQuarantine, queue::ConfigQueue,
+    remote::ConfigHost, report::ConfigReport, resolver::ConfigResolver, scripts::ConfigSieve,
+    session::ConfigSession, spam::ConfigSpamFilter, webhook::ConfigWebhook, ConfigContext, Host,
 };
 use dashmap::DashMap;
 use directory::DirectoryConfig;
 use mail_send::smtp::tls::build_tls_connector;
 use queue::manager::SpawnQueue;
 use reporting::scheduler::SpawnReport;
+use store::{audit::AuditLog, Store};
 use tokio::sync::mpsc;
 use utils::{
     config::{Config, ServerProtocol, Servers},
@@ -56,6 +59,7 @@ impl SMTP {
         config: &Config,
         servers: &Servers,
         directory: &DirectoryConfig,
+        store: Arc<Store>,
         #[cfg(feature = "local_delivery")] delivery_tx: mpsc::Sender<utils::ipc::DeliveryEvent>,
     ) -> Result<Arc<Self>, String> {
         // Read configuration parameters
@@ -87,10 +91,15 @@ impl SMTP {
         let queue_config = config.parse_queue(&config_ctx)?;
         let mail_auth_config = config.parse_mail_auth(&config_ctx)?;
         let report_config = config.parse_reports(&config_ctx)?;
+        let spam_config = config.parse_spam_filter(&config_ctx)?;
+        let journal_config = config.parse_journal(&config_ctx)?;
+        let quarantine_config = config.parse_quarantine(&config_ctx)?;
+        let webhook_config = config.parse_webhooks()?;
 
         // Build core
         let (queue_tx, queue_rx) = mpsc::channel(1024);
         let (report_tx, report_rx) = mpsc::channel(1024);
+        let (webhook_tx, webhook_rx) = mpsc::channel(1024);
         let core = Arc::new(SMTP {
             worker_pool: rayon::ThreadPoolBuilder::new()
                 .num_threads(
@@ -112,6 +121,7 @@ impl SMTP {
                         .unwrap_or(32)
                         .next_power_of_two() as usize,
                 ),
+                greylist: Default::default(),
             },
             queue: QueueCore {
                 config: queue_config,
@@ -137,6 +147,9 @@ impl SMTP {
                     pki_verify: build_tls_connector(false),
                     dummy_verify: build_tls_connector(true),
                 },
+                connections: Default::default(),
+                adaptive: Default::default(),
+                journal: journal_config,
             },
             report: ReportCore {
                 tx: report_tx,
@@ -144,6 +157,14 @@ impl SMTP {
             },
             mail_auth: mail_auth_config,
             sieve: sieve_config,
+            spam: spam_config,
+            quarantine: quarantine_config,
+            webhook: WebhookCore {
+                config: webhook_config,
+                tx: webhook_tx,
+            },
+            store: store.into(),
+            audit: AuditLog::parse(config)?,
             #[cfg(feature = "local_delivery")]
             delivery_tx,
         });
@@ -154,6 +175,9 @@ impl SMTP {
         // Spawn report manager
         report_rx.spawn(core.clone(), core.report.read_reports().await);
 
+        // Spawn webhook dispatcher
+        webhook_rx.spawn(core.clone());
+
         Ok(core)
     }
 }
@@ -36,7 +36,7 @@ use smtp_proto::MAIL_REQUIRETLS;
 use utils::config::ServerProtocol;
 
 use crate::{
-    config::{AggregateFrequency, TlsStrategy},
+    config::{AdaptiveKey, AggregateFrequency, TlsStrategy},
     core::SMTP,
     queue::ErrorDetails,
     reporting::{tls::TlsRptOptions, PolicyType, TlsEvent},
@@ -45,6 +45,7 @@ use crate::{
 use super::{
     lookup::ToNextHop,
     mta_sts,
+    pool::{ConnectionCache, ConnectionKey, SmtpConnection},
     session::{read_greeting, say_helo, try_start_tls, SessionParams, StartTlsResult},
     NextHop,
 };
@@ -59,7 +60,10 @@ impl DeliveryAttempt {
         let has_pending_delivery = self.has_pending_delivery();
 
         // Send any due Delivery Status Notifications
-        core.queue.send_dsn(&mut self).await;
+        core.suppress_hard_bounces(&self).await;
+        if core.queue.send_dsn(&mut self).await {
+            core.webhook_dsn_sent(&self).await;
+        }
 
         if has_pending_delivery {
             // Re-queue the message if its not yet due for delivery
@@ -556,6 +560,131 @@ impl DeliveryAttempt {
 
                     // Try each IP address
                     envelope.local_ip = source_ip.unwrap_or(no_ip);
+
+                    // Adaptive rate control
+                    let adaptive_key = if *queue_config.adaptive.enable.eval(&envelope).await {
+                        let key = match queue_config.adaptive.key {
+                            AdaptiveKey::Mx => envelope.mx,
+                            AdaptiveKey::Domain => envelope.domain,
+                        };
+                        if let Err(err) = core.queue.adaptive_is_allowed(key, &span) {
+                            domain.set_throttle_error(err, &mut on_hold);
+                            continue 'next_domain;
+                        }
+                        Some(key.to_string())
+                    } else {
+                        None
+                    };
+
+                    // Reuse an idle session to this host when available
+                    let connection_cache = if *queue_config.connection.reuse.eval(&envelope).await {
+                        Some(ConnectionCache {
+                            pool: &core.queue.connections,
+                            key: ConnectionKey {
+                                hostname: envelope.mx.to_string(),
+                                port: remote_host.port(),
+                                source_ip,
+                                tls_strategy,
+                            },
+                            max_messages: *queue_config
+                                .connection
+                                .max_messages
+                                .eval(&envelope)
+                                .await,
+                            max_idle: *queue_config.connection.max_idle.eval(&envelope).await,
+                            idle_timeout: *queue_config
+                                .connection
+                                .idle_timeout
+                                .eval(&envelope)
+                                .await,
+                        })
+                    } else {
+                        None
+                    };
+                    if let Some(cache) = &connection_cache {
+                        while let Some(connection) = cache.pool.checkout(&cache.key) {
+                            // Make sure the session meets the TLS requirements of this message
+                            if !connection.is_tls()
+                                && (tls_strategy.is_tls_required()
+                                    || (self.message.flags & MAIL_REQUIRETLS) != 0
+                                    || mta_sts_policy.is_some()
+                                    || dane_policy.is_some())
+                            {
+                                connection.connection.quit().await;
+                                continue;
+                            }
+                            if let Some(dane_policy) = &dane_policy {
+                                if dane_policy
+                                    .verify(&span, envelope.mx, connection.peer_certificates())
+                                    .is_err()
+                                {
+                                    connection.connection.quit().await;
+                                    continue;
+                                }
+                            }
+
+                            // Throttle remote host, idle sessions are returned to the cache
+                            let mut in_flight_host = Vec::new();
+                            envelope.remote_ip = connection.remote_ip;
+                            for throttle in &queue_config.throttle.host {
+                                if let Err(err) = core
+                                    .queue
+                                    .is_allowed(throttle, &envelope, &mut in_flight_host, &span)
+                                    .await
+                                {
+                                    cache.pool.checkin(
+                                        cache.key.clone(),
+                                        connection,
+                                        cache.max_idle,
+                                    );
+                                    domain.set_throttle_error(err, &mut on_hold);
+                                    continue 'next_domain;
+                                }
+                            }
+
+                            tracing::debug!(
+                                parent: &span,
+                                context = "connection-cache",
+                                event = "reuse",
+                                mx = envelope.mx,
+                                remote_ip = %connection.remote_ip,
+                                messages = connection.messages,
+                            );
+
+                            let params = SessionParams {
+                                span: &span,
+                                credentials: remote_host.credentials(),
+                                is_smtp: remote_host.is_smtp(),
+                                hostname: envelope.mx,
+                                local_hostname: queue_config.hostname.eval(&envelope).await,
+                                timeout_ehlo: *queue_config.timeout.ehlo.eval(&envelope).await,
+                                timeout_mail: *queue_config.timeout.mail.eval(&envelope).await,
+                                timeout_rcpt: *queue_config.timeout.rcpt.eval(&envelope).await,
+                                timeout_data: *queue_config.timeout.data.eval(&envelope).await,
+                            };
+                            if let Some(status) = connection
+                                .deliver(
+                                    &self.message,
+                                    recipients.iter_mut().filter(|r| r.domain_idx == domain_idx),
+                                    params,
+                                    cache,
+                                )
+                                .await
+                            {
+                                if let Some(key) = &adaptive_key {
+                                    core.queue.adaptive_feedback(
+                                        key,
+                                        &status,
+                                        recipients.iter().filter(|r| r.domain_idx == domain_idx),
+                                        &span,
+                                    );
+                                }
+                                domain.set_status(status, queue_config.retry.eval(&envelope).await);
+                                continue 'next_domain;
+                            }
+                        }
+                    }
+
                     'next_ip: for remote_ip in remote_ips {
                         // Throttle remote host
                         let mut in_flight_host = Vec::new();
@@ -646,6 +775,14 @@ impl DeliveryAttempt {
                                     status = %status,
                                 );
 
+                                if let Some(key) = &adaptive_key {
+                                    core.queue.adaptive_feedback(
+                                        key,
+                                        &status,
+                                        std::iter::empty(),
+                                        &span,
+                                    );
+                                }
                                 last_status = status;
                                 continue 'next_host;
                             }
@@ -723,13 +860,15 @@ impl DeliveryAttempt {
                                     }
 
                                     // Deliver message over TLS
-                                    self.message
+                                    SmtpConnection::from(smtp_client)
                                         .deliver(
-                                            smtp_client,
+                                            &self.message,
+                                            remote_ip,
                                             recipients
                                                 .iter_mut()
                                                 .filter(|r| r.domain_idx == domain_idx),
                                             params,
+                                            connection_cache.as_ref(),
                                         )
                                         .await
                                 }
@@ -778,13 +917,15 @@ impl DeliveryAttempt {
                                         continue 'next_host;
                                     } else {
                                         // TLS is not required, proceed in plain-text
-                                        self.message
+                                        SmtpConnection::from(smtp_client)
                                             .deliver(
-                                                smtp_client,
+                                                &self.message,
+                                                remote_ip,
                                                 recipients
                                                     .iter_mut()
                                                     .filter(|r| r.domain_idx == domain_idx),
                                                 params,
+                                                connection_cache.as_ref(),
                                             )
                                             .await
                                     }
@@ -854,21 +995,39 @@ impl DeliveryAttempt {
                                     status = %status,
                                 );
 
+                                if let Some(key) = &adaptive_key {
+                                    core.queue.adaptive_feedback(
+                                        key,
+                                        &status,
+                                        std::iter::empty(),
+                                        &span,
+                                    );
+                                }
                                 last_status = status;
                                 continue 'next_host;
                             }
 
                             // Deliver message
-                            self.message
+                            SmtpConnection::from(smtp_client)
                                 .deliver(
-                                    smtp_client,
+                                    &self.message,
+                                    remote_ip,
                                     recipients.iter_mut().filter(|r| r.domain_idx == domain_idx),
                                     params,
+                                    connection_cache.as_ref(),
                                 )
                                 .await
                         };
 
                         // Update status for the current domain and continue with the next one
+                        if let Some(key) = &adaptive_key {
+                            core.queue.adaptive_feedback(
+                                key,
+                                &delivery_result,
+                                recipients.iter().filter(|r| r.domain_idx == domain_idx),
+                                &span,
+                            );
+                        }
                         domain
                             .set_status(delivery_result, queue_config.retry.eval(&envelope).await);
                         continue 'next_domain;
@@ -881,8 +1040,14 @@ impl DeliveryAttempt {
             self.message.domains = domains;
             self.message.recipients = recipients;
 
+            // Notify webhooks
+            core.webhook_delivery_attempt(&self).await;
+
             // Send Delivery Status Notifications
-            core.queue.send_dsn(&mut self).await;
+            core.suppress_hard_bounces(&self).await;
+            if core.queue.send_dsn(&mut self).await {
+                core.webhook_dsn_sent(&self).await;
+            }
 
             // Notify queue manager
             let span = self.span;
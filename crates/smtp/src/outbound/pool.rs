@@ -0,0 +1,370 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use ahash::AHashMap;
+use mail_send::{smtp::AssertReply, SmtpClient};
+use rustls::Certificate;
+use smtp_proto::EhloResponse;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_rustls::client::TlsStream;
+
+use crate::{
+    config::TlsStrategy,
+    queue::{Error, Message, Recipient, Status},
+};
+
+use super::session::{quit, start_session, SessionParams};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConnectionKey {
+    pub hostname: String,
+    pub port: u16,
+    pub source_ip: Option<IpAddr>,
+    pub tls_strategy: TlsStrategy,
+}
+
+pub enum SmtpConnection {
+    Plain(SmtpClient<TcpStream>),
+    Tls(SmtpClient<TlsStream<TcpStream>>),
+}
+
+pub struct PooledConnection {
+    pub connection: SmtpConnection,
+    pub capabilities: EhloResponse<String>,
+    pub remote_ip: IpAddr,
+    pub messages: usize,
+    idle_since: Instant,
+    idle_timeout: Duration,
+}
+
+pub struct ConnectionCache<'x> {
+    pub pool: &'x ConnectionPool,
+    pub key: ConnectionKey,
+    pub max_messages: usize,
+    pub idle_timeout: Duration,
+    pub max_idle: usize,
+}
+
+#[derive(Default)]
+pub struct ConnectionPool {
+    connections: parking_lot::Mutex<AHashMap<ConnectionKey, Vec<PooledConnection>>>,
+}
+
+impl ConnectionPool {
+    /// Returns the most recently used idle connection for the key, closing expired ones.
+    pub fn checkout(&self, key: &ConnectionKey) -> Option<PooledConnection> {
+        let mut expired = Vec::new();
+        let mut result = None;
+        {
+            let mut connections = self.connections.lock();
+            if let Some(idle) = connections.get_mut(key) {
+                while let Some(connection) = idle.pop() {
+                    if !connection.is_expired() {
+                        result = connection.into();
+                        break;
+                    } else {
+                        expired.push(connection);
+                    }
+                }
+                if idle.is_empty() {
+                    connections.remove(key);
+                }
+            }
+        }
+        close_all(expired);
+        result
+    }
+
+    pub fn checkin(&self, key: ConnectionKey, mut connection: PooledConnection, max_idle: usize) {
+        let rejected = {
+            let mut connections = self.connections.lock();
+            let idle = connections.entry(key).or_default();
+            if idle.len() < max_idle {
+                connection.idle_since = Instant::now();
+                idle.push(connection);
+                None
+            } else {
+                Some(connection)
+            }
+        };
+        if let Some(connection) = rejected {
+            close_all(vec![connection]);
+        }
+    }
+
+    /// Closes all connections that have been idle for longer than their timeout.
+    pub fn purge(&self) {
+        let mut expired = Vec::new();
+        {
+            let mut connections = self.connections.lock();
+            connections.retain(|_, idle| {
+                let mut idx = 0;
+                while idx < idle.len() {
+                    if idle[idx].is_expired() {
+                        expired.push(idle.swap_remove(idx));
+                    } else {
+                        idx += 1;
+                    }
+                }
+                !idle.is_empty()
+            });
+        }
+        close_all(expired);
+    }
+
+    pub fn idle_count(&self) -> usize {
+        self.connections
+            .lock()
+            .values()
+            .map(|idle| idle.len())
+            .sum()
+    }
+}
+
+impl PooledConnection {
+    pub fn is_tls(&self) -> bool {
+        matches!(self.connection, SmtpConnection::Tls(_))
+    }
+
+    pub fn peer_certificates(&self) -> Option<&[Certificate]> {
+        match &self.connection {
+            SmtpConnection::Tls(smtp_client) => smtp_client.tls_connection().peer_certificates(),
+            SmtpConnection::Plain(_) => None,
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        self.idle_since.elapsed() >= self.idle_timeout
+    }
+
+    /// Sends a message over an already established session. Returns `None` if the
+    /// connection was found to be unusable before the transaction took place.
+    pub async fn deliver(
+        mut self,
+        message: &Message,
+        recipients: impl Iterator<Item = &mut Recipient>,
+        params: SessionParams<'_>,
+        cache: &ConnectionCache<'_>,
+    ) -> Option<Status<(), Error>> {
+        let status = match &mut self.connection {
+            SmtpConnection::Plain(smtp_client) => {
+                send_pooled(
+                    smtp_client,
+                    message,
+                    &self.capabilities,
+                    recipients,
+                    &params,
+                )
+                .await
+            }
+            SmtpConnection::Tls(smtp_client) => {
+                send_pooled(
+                    smtp_client,
+                    message,
+                    &self.capabilities,
+                    recipients,
+                    &params,
+                )
+                .await
+            }
+        };
+
+        match status {
+            Ok(status) => {
+                self.release(&status, &params, cache).await;
+                Some(status)
+            }
+            Err(status) => {
+                // Stale connection, most likely closed by the remote host while idle
+                tracing::debug!(
+                    parent: params.span,
+                    context = "connection-cache",
+                    event = "stale",
+                    mx = &params.hostname,
+                    reason = %status,
+                );
+                self.connection.quit().await;
+                None
+            }
+        }
+    }
+
+    async fn release(
+        mut self,
+        status: &Status<(), Error>,
+        params: &SessionParams<'_>,
+        cache: &ConnectionCache<'_>,
+    ) {
+        self.messages += 1;
+        if matches!(status, Status::Completed(_) | Status::Scheduled)
+            && self.messages < cache.max_messages
+            && self.connection.reset(params).await
+        {
+            tracing::debug!(
+                parent: params.span,
+                context = "connection-cache",
+                event = "release",
+                mx = &params.hostname,
+                messages = self.messages,
+            );
+            self.idle_timeout = cache.idle_timeout;
+            cache.pool.checkin(cache.key.clone(), self, cache.max_idle);
+        } else {
+            self.connection.quit().await;
+        }
+    }
+}
+
+impl SmtpConnection {
+    /// Starts a new SMTP session and delivers the message, keeping the connection
+    /// open for further messages when a cache is provided.
+    pub async fn deliver(
+        mut self,
+        message: &Message,
+        remote_ip: IpAddr,
+        recipients: impl Iterator<Item = &mut Recipient>,
+        params: SessionParams<'_>,
+        cache: Option<&ConnectionCache<'_>>,
+    ) -> Status<(), Error> {
+        let capabilities = match match &mut self {
+            SmtpConnection::Plain(smtp_client) => start_session(smtp_client, &params).await,
+            SmtpConnection::Tls(smtp_client) => start_session(smtp_client, &params).await,
+        } {
+            Ok(capabilities) => capabilities,
+            Err(status) => {
+                self.quit().await;
+                return status;
+            }
+        };
+
+        let status = match &mut self {
+            SmtpConnection::Plain(smtp_client) => {
+                message
+                    .send_transaction(smtp_client, &capabilities, recipients, &params)
+                    .await
+            }
+            SmtpConnection::Tls(smtp_client) => {
+                message
+                    .send_transaction(smtp_client, &capabilities, recipients, &params)
+                    .await
+            }
+        };
+
+        if let Some(cache) = cache {
+            PooledConnection {
+                connection: self,
+                capabilities,
+                remote_ip,
+                messages: 0,
+                idle_since: Instant::now(),
+                idle_timeout: cache.idle_timeout,
+            }
+            .release(&status, &params, cache)
+            .await;
+        } else {
+            self.quit().await;
+        }
+
+        status
+    }
+
+    async fn reset(&mut self, params: &SessionParams<'_>) -> bool {
+        match self {
+            SmtpConnection::Plain(smtp_client) => {
+                smtp_client.timeout = params.timeout_mail;
+                smtp_client
+                    .cmd(b"RSET\r\n")
+                    .await
+                    .and_then(|r| r.assert_positive_completion())
+                    .is_ok()
+            }
+            SmtpConnection::Tls(smtp_client) => {
+                smtp_client.timeout = params.timeout_mail;
+                smtp_client
+                    .cmd(b"RSET\r\n")
+                    .await
+                    .and_then(|r| r.assert_positive_completion())
+                    .is_ok()
+            }
+        }
+    }
+
+    pub async fn quit(self) {
+        match self {
+            SmtpConnection::Plain(smtp_client) => quit(smtp_client).await,
+            SmtpConnection::Tls(smtp_client) => quit(smtp_client).await,
+        }
+    }
+}
+
+impl From<SmtpClient<TcpStream>> for SmtpConnection {
+    fn from(smtp_client: SmtpClient<TcpStream>) -> Self {
+        SmtpConnection::Plain(smtp_client)
+    }
+}
+
+impl From<SmtpClient<TlsStream<TcpStream>>> for SmtpConnection {
+    fn from(smtp_client: SmtpClient<TlsStream<TcpStream>>) -> Self {
+        SmtpConnection::Tls(smtp_client)
+    }
+}
+
+// Only a connection error on MAIL FROM means that the session went stale while idle,
+// failures after that point could happen once the remote host has received the message
+// so they are returned as the delivery status rather than retried on a new connection.
+async fn send_pooled<T: AsyncRead + AsyncWrite + Unpin>(
+    smtp_client: &mut SmtpClient<T>,
+    message: &Message,
+    capabilities: &EhloResponse<String>,
+    recipients: impl Iterator<Item = &mut Recipient>,
+    params: &SessionParams<'_>,
+) -> Result<Status<(), Error>, Status<(), Error>> {
+    match message
+        .send_mail_from(smtp_client, capabilities, params)
+        .await
+    {
+        Ok(_) => Ok(message
+            .send_recipients(smtp_client, capabilities, recipients, params)
+            .await),
+        Err(status @ Status::TemporaryFailure(Error::ConnectionError(_))) => Err(status),
+        Err(status) => Ok(status),
+    }
+}
+
+fn close_all(connections: Vec<PooledConnection>) {
+    if !connections.is_empty() {
+        tokio::spawn(async move {
+            for connection in connections {
+                connection.connection.quit().await;
+            }
+        });
+    }
+}
@@ -56,62 +56,30 @@ pub struct SessionParams<'x> {
 }
 
 impl Message {
-    pub async fn deliver<T: AsyncRead + AsyncWrite + Unpin>(
+    pub async fn send_transaction<T: AsyncRead + AsyncWrite + Unpin>(
         &self,
-        mut smtp_client: SmtpClient<T>,
+        smtp_client: &mut SmtpClient<T>,
+        capabilities: &EhloResponse<String>,
         recipients: impl Iterator<Item = &mut Recipient>,
-        params: SessionParams<'_>,
+        params: &SessionParams<'_>,
     ) -> Status<(), Error> {
-        // Obtain capabilities
-        let mut capabilities = match say_helo(&mut smtp_client, &params).await {
-            Ok(capabilities) => capabilities,
-            Err(status) => {
-                tracing::info!(
-                    parent: params.span,
-                    context = "ehlo",
-                    event = "rejected",
-                    mx = &params.hostname,
-                    reason = %status,
-                );
-                quit(smtp_client).await;
-                return status;
-            }
-        };
-
-        // Authenticate
-        if let Some(credentials) = params.credentials {
-            if let Err(err) = smtp_client.authenticate(credentials, &capabilities).await {
-                tracing::info!(
-                    parent: params.span,
-                    context = "auth",
-                    event = "failed",
-                    mx = &params.hostname,
-                    reason = %err,
-                );
-                quit(smtp_client).await;
-                return Status::from_smtp_error(params.hostname, "AUTH ...", err);
+        match self.send_mail_from(smtp_client, capabilities, params).await {
+            Ok(_) => {
+                self.send_recipients(smtp_client, capabilities, recipients, params)
+                    .await
             }
-
-            // Refresh capabilities
-            capabilities = match say_helo(&mut smtp_client, &params).await {
-                Ok(capabilities) => capabilities,
-                Err(status) => {
-                    tracing::info!(
-                        parent: params.span,
-                        context = "ehlo",
-                        event = "rejected",
-                        mx = &params.hostname,
-                        reason = %status,
-                    );
-                    quit(smtp_client).await;
-                    return status;
-                }
-            };
+            Err(status) => status,
         }
+    }
 
-        // MAIL FROM
+    pub async fn send_mail_from<T: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        smtp_client: &mut SmtpClient<T>,
+        capabilities: &EhloResponse<String>,
+        params: &SessionParams<'_>,
+    ) -> Result<(), Status<(), Error>> {
         smtp_client.timeout = params.timeout_mail;
-        let cmd = self.build_mail_from(&capabilities);
+        let cmd = self.build_mail_from(capabilities);
         if let Err(err) = smtp_client
             .cmd(cmd.as_bytes())
             .await
@@ -124,10 +92,20 @@ impl Message {
                 mx = &params.hostname,
                 reason = %err,
             );
-            quit(smtp_client).await;
-            return Status::from_smtp_error(params.hostname, &cmd, err);
+            Err(Status::from_smtp_error(params.hostname, &cmd, err))
+        } else {
+            Ok(())
         }
+    }
 
+    /// Sends the recipients and the message once MAIL FROM has been accepted.
+    pub async fn send_recipients<T: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        smtp_client: &mut SmtpClient<T>,
+        capabilities: &EhloResponse<String>,
+        recipients: impl Iterator<Item = &mut Recipient>,
+        params: &SessionParams<'_>,
+    ) -> Status<(), Error> {
         // RCPT TO
         let mut total_rcpt = 0;
         let mut total_completed = 0;
@@ -143,7 +121,7 @@ impl Message {
                 continue;
             }
 
-            let cmd = self.build_rcpt_to(rcpt, &capabilities);
+            let cmd = self.build_rcpt_to(rcpt, capabilities);
             match smtp_client.cmd(cmd.as_bytes()).await {
                 Ok(response) => match response.severity() {
                     Severity::PositiveCompletion => {
@@ -192,7 +170,6 @@ impl Message {
                     );
 
                     // Something went wrong, abort.
-                    quit(smtp_client).await;
                     return Status::from_smtp_error(params.hostname, "", err);
                 }
             }
@@ -206,7 +183,7 @@ impl Message {
                 None
             };
 
-            if let Err(status) = send_message(&mut smtp_client, self, &bdat_cmd, &params).await {
+            if let Err(status) = send_message(smtp_client, self, &bdat_cmd, params).await {
                 tracing::info!(
                     parent: params.span,
                     context = "message",
@@ -215,13 +192,12 @@ impl Message {
                     reason = %status,
                 );
 
-                quit(smtp_client).await;
                 return status;
             }
 
             if params.is_smtp {
                 // Handle SMTP response
-                match read_smtp_data_respone(&mut smtp_client, params.hostname, &bdat_cmd).await {
+                match read_smtp_data_respone(smtp_client, params.hostname, &bdat_cmd).await {
                     Ok(response) => {
                         // Mark recipients as delivered
                         if response.code() == 250 {
@@ -248,7 +224,6 @@ impl Message {
                                 reason = %response,
                             );
 
-                            quit(smtp_client).await;
                             return Status::from_smtp_error(
                                 params.hostname,
                                 bdat_cmd.as_deref().unwrap_or("DATA"),
@@ -265,18 +240,13 @@ impl Message {
                             reason = %status,
                         );
 
-                        quit(smtp_client).await;
                         return status;
                     }
                 }
             } else {
                 // Handle LMTP responses
-                match read_lmtp_data_respone(
-                    &mut smtp_client,
-                    params.hostname,
-                    accepted_rcpts.len(),
-                )
-                .await
+                match read_lmtp_data_respone(smtp_client, params.hostname, accepted_rcpts.len())
+                    .await
                 {
                     Ok(responses) => {
                         for ((rcpt, _), response) in accepted_rcpts.into_iter().zip(responses) {
@@ -337,14 +307,12 @@ impl Message {
                             reason = %status,
                         );
 
-                        quit(smtp_client).await;
                         return status;
                     }
                 }
             }
         }
 
-        quit(smtp_client).await;
         if total_completed == total_rcpt {
             Status::Completed(())
         } else {
@@ -470,6 +438,57 @@ pub async fn try_start_tls(
     }
 }
 
+pub async fn start_session<T: AsyncRead + AsyncWrite + Unpin>(
+    smtp_client: &mut SmtpClient<T>,
+    params: &SessionParams<'_>,
+) -> Result<EhloResponse<String>, Status<(), Error>> {
+    // Obtain capabilities
+    let capabilities = match say_helo(smtp_client, params).await {
+        Ok(capabilities) => capabilities,
+        Err(status) => {
+            tracing::info!(
+                parent: params.span,
+                context = "ehlo",
+                event = "rejected",
+                mx = &params.hostname,
+                reason = %status,
+            );
+            return Err(status);
+        }
+    };
+
+    // Authenticate
+    if let Some(credentials) = params.credentials {
+        if let Err(err) = smtp_client.authenticate(credentials, &capabilities).await {
+            tracing::info!(
+                parent: params.span,
+                context = "auth",
+                event = "failed",
+                mx = &params.hostname,
+                reason = %err,
+            );
+            return Err(Status::from_smtp_error(params.hostname, "AUTH ...", err));
+        }
+
+        // Refresh capabilities
+        match say_helo(smtp_client, params).await {
+            Ok(capabilities) => Ok(capabilities),
+            Err(status) => {
+                tracing::info!(
+                    parent: params.span,
+                    context = "ehlo",
+                    event = "rejected",
+                    mx = &params.hostname,
+                    reason = %status,
+                );
+                Err(status)
+            }
+        }
+    } else {
+        Ok(capabilities)
+    }
+}
+
 pub async fn read_greeting<T: AsyncRead + AsyncWrite + Unpin>(
     smtp_client: &mut SmtpClient<T>,
     hostname: &str,
@@ -0,0 +1,184 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::{Duration, Instant, SystemTime};
+
+use mail_parser::DateTime;
+use smtp_proto::Response;
+
+use crate::core::{management::AdaptiveRate, QueueCore};
+
+use super::{throttle, Error, Recipient, Status};
+
+const MAX_INTERVAL: Duration = Duration::from_secs(86400);
+const MIN_IDLE_TIMEOUT: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone)]
+pub struct AdaptiveLimiter {
+    pub rate: f64,
+    pub next_slot: Instant,
+    pub last_deferral: Option<Instant>,
+    pub successes: u64,
+    pub deferrals: u64,
+}
+
+impl QueueCore {
+    pub fn adaptive_is_allowed(
+        &self,
+        key: &str,
+        span: &tracing::Span,
+    ) -> Result<(), throttle::Error> {
+        let now = Instant::now();
+        if let Some(mut limiter) = self.adaptive.get_mut(key) {
+            if limiter.next_slot > now {
+                tracing::info!(
+                    parent: span,
+                    context = "throttle",
+                    event = "adaptive-rate-exceeded",
+                    key = key,
+                    rate = limiter.rate * 60.0,
+                    "Adaptive delivery rate exceeded."
+                );
+                return Err(throttle::Error::Rate {
+                    retry_at: limiter.next_slot,
+                });
+            }
+            limiter.next_slot = now + rate_interval(limiter.rate, self.config.adaptive.min_rate);
+        } else {
+            let rate = self.config.adaptive.initial_rate;
+            self.adaptive.insert(
+                key.to_string(),
+                AdaptiveLimiter {
+                    rate,
+                    next_slot: now + rate_interval(rate, self.config.adaptive.min_rate),
+                    last_deferral: None,
+                    successes: 0,
+                    deferrals: 0,
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn adaptive_feedback<'x>(
+        &self,
+        key: &str,
+        status: &Status<(), Error>,
+        mut recipients: impl Iterator<Item = &'x Recipient>,
+        span: &tracing::Span,
+    ) {
+        let is_deferred = matches!(status, Status::TemporaryFailure(Error::UnexpectedResponse(r))
+            if is_rate_limited(&r.response))
+            || recipients.any(|rcpt| {
+                matches!(&rcpt.status, Status::TemporaryFailure(r) if is_rate_limited(&r.response))
+            });
+        if !is_deferred && !matches!(status, Status::Completed(_)) {
+            return;
+        }
+
+        let config = &self.config.adaptive;
+        if let Some(mut limiter) = self.adaptive.get_mut(key) {
+            let now = Instant::now();
+            if is_deferred {
+                // Multiplicative decrease, pause for one interval at the new rate
+                limiter.rate = (limiter.rate * config.backoff_factor).max(config.min_rate);
+                limiter.next_slot = now + rate_interval(limiter.rate, config.min_rate);
+                limiter.last_deferral = Some(now);
+                limiter.deferrals += 1;
+
+                tracing::info!(
+                    parent: span,
+                    context = "throttle",
+                    event = "adaptive-backoff",
+                    key = key,
+                    rate = limiter.rate * 60.0,
+                    "Remote host deferred delivery, reducing rate."
+                );
+            } else {
+                // Ramp up slowly once the remote host stops deferring
+                limiter.successes += 1;
+                if limiter
+                    .last_deferral
+                    .map_or(true, |last| now.duration_since(last) >= config.cooldown)
+                {
+                    limiter.rate = (limiter.rate * config.ramp_up_factor).min(config.max_rate);
+                }
+            }
+        }
+    }
+
+    pub fn adaptive_report(&self) -> Vec<AdaptiveRate> {
+        let now = Instant::now();
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()) as i64;
+        let mut report = self
+            .adaptive
+            .iter()
+            .map(|entry| {
+                let limiter = entry.value();
+                AdaptiveRate {
+                    key: entry.key().to_string(),
+                    rate: (limiter.rate * 60.0 * 100.0).round() / 100.0,
+                    successes: limiter.successes,
+                    deferrals: limiter.deferrals,
+                    next_slot: (limiter.next_slot > now).then(|| {
+                        DateTime::from_timestamp(
+                            timestamp + (limiter.next_slot - now).as_secs() as i64,
+                        )
+                    }),
+                    last_deferral: limiter.last_deferral.map(|last| {
+                        DateTime::from_timestamp(timestamp - (now - last).as_secs() as i64)
+                    }),
+                }
+            })
+            .collect::<Vec<_>>();
+        report.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+        report
+    }
+
+    pub fn adaptive_cleanup(&self) {
+        // Forget hosts that have been idle for longer than the cooldown period
+        let idle_timeout = self.config.adaptive.cooldown.max(MIN_IDLE_TIMEOUT);
+        let now = Instant::now();
+        self.adaptive
+            .retain(|_, limiter| limiter.next_slot + idle_timeout > now);
+    }
+}
+
+fn rate_interval(rate: f64, min_rate: f64) -> Duration {
+    // Never wait longer than a day, even if the rate is misconfigured
+    Duration::try_from_secs_f64(1.0 / rate.max(min_rate))
+        .map_or(MAX_INTERVAL, |interval| interval.min(MAX_INTERVAL))
+}
+
+fn is_rate_limited(response: &Response<String>) -> bool {
+    // 421 service not available, 4.7.x policy deferrals and 4.2.1 receiving rate exceeded
+    response.code == 421
+        || (response.code / 100 == 4
+            && (response.esc[..2] == [4, 7] || response.esc == [4, 2, 1] || {
+                let message = response.message.to_lowercase();
+                message.contains("rate limit") || message.contains("too many")
+            }))
+}
@@ -43,7 +43,8 @@ use super::{
 };
 
 impl QueueCore {
-    pub async fn send_dsn(&self, attempt: &mut DeliveryAttempt) {
+    /// Queues a DSN for the recipients that require one, returns true if a DSN was sent.
+    pub async fn send_dsn(&self, attempt: &mut DeliveryAttempt) -> bool {
         if !attempt.message.return_path.is_empty() {
             if let Some(dsn) = attempt.build_dsn(&self.config).await {
                 let mut dsn_message = Message::new_boxed("", "", "");
@@ -61,12 +62,14 @@ impl QueueCore {
                     .message
                     .sign(&self.config.dsn.sign, &dsn, &attempt.span)
                     .await;
-                self.queue_message(dsn_message, signature.as_deref(), &dsn, &attempt.span)
+                return self
+                    .queue_message(dsn_message, signature.as_deref(), &dsn, &attempt.span)
                     .await;
             }
         } else {
             attempt.handle_double_bounce();
         }
+        false
     }
 }
 
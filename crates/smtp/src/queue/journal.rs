@@ -0,0 +1,154 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::fmt::Write;
+
+use mail_builder::{headers::date::Date, mime::make_boundary};
+use mail_parser::Message as ParsedMessage;
+
+use crate::core::QueueCore;
+
+use super::{DomainPart, Message, SimpleEnvelope};
+
+pub struct JournalReport {
+    pub message: Box<Message>,
+    pub signature: Option<Vec<u8>>,
+    pub report: Vec<u8>,
+}
+
+impl QueueCore {
+    pub async fn journal_report(
+        &self,
+        message: &Message,
+        raw_headers: Option<&[u8]>,
+        raw_message: &[u8],
+        span: &tracing::Span,
+    ) -> Option<JournalReport> {
+        // Journal the message if any of the envelope recipients matches
+        let config = &self.journal;
+        let mut enabled = false;
+        for rcpt in &message.recipients {
+            let envelope = SimpleEnvelope::new_rcpt(
+                message,
+                &message.domains[rcpt.domain_idx].domain,
+                &rcpt.address_lcase,
+            );
+            if *config.enable.eval(&envelope).await {
+                enabled = true;
+                break;
+            }
+        }
+        if !enabled {
+            return None;
+        }
+        let to_addr = if let Some(to_addr) = config.address.eval(message).await {
+            to_addr.clone()
+        } else {
+            tracing::debug!(parent: span,
+                context = "journal",
+                event = "skip",
+                "No journal address configured for message.");
+            return None;
+        };
+        let from_name = config.name.eval(message).await;
+        let from_addr = config.from_address.eval(message).await;
+
+        // Envelope details, including all recipients (Bcc included)
+        let parsed = ParsedMessage::parse(raw_message);
+        let mut envelope = String::with_capacity(256);
+        let _ = write!(envelope, "Sender: {}\r\n", message.return_path);
+        if let Some(parsed) = &parsed {
+            if let Some(subject) = parsed.subject() {
+                let _ = write!(envelope, "Subject: {}\r\n", subject.trim());
+            }
+            if let Some(message_id) = parsed.message_id() {
+                let _ = write!(envelope, "Message-ID: <{message_id}>\r\n");
+            }
+        }
+        let _ = write!(envelope, "Queue-ID: {:X}\r\n", message.id);
+        for rcpt in &message.recipients {
+            let _ = write!(envelope, "Recipient: {}\r\n", rcpt.address);
+        }
+
+        // Build report, the original message is attached as-is
+        let raw_headers = raw_headers.unwrap_or_default();
+        let boundary = make_boundary("_");
+        let mut report = Vec::with_capacity(raw_headers.len() + raw_message.len() + 1024);
+        let from_name = from_name.replace('"', "");
+        let date = Date::now().to_rfc822();
+        let message_id = make_boundary(".");
+        let hostname = from_addr.domain_part();
+        report.extend_from_slice(
+            format!(
+                concat!(
+                    "From: \"{from_name}\" <{from_addr}>\r\n",
+                    "To: <{to_addr}>\r\n",
+                    "Subject: Journal report\r\n",
+                    "Date: {date}\r\n",
+                    "Message-ID: <{message_id}@{hostname}>\r\n",
+                    "Auto-Submitted: auto-generated\r\n",
+                    "MIME-Version: 1.0\r\n",
+                    "Content-Type: multipart/mixed; boundary=\"{boundary}\"\r\n\r\n",
+                    "--{boundary}\r\n",
+                    "Content-Type: text/plain; charset=\"utf-8\"\r\n",
+                    "Content-Transfer-Encoding: 8bit\r\n\r\n",
+                    "{envelope}\r\n",
+                    "--{boundary}\r\n",
+                    "Content-Type: message/rfc822\r\n",
+                    "Content-Disposition: attachment\r\n\r\n",
+                ),
+                from_name = from_name,
+                from_addr = from_addr,
+                to_addr = to_addr,
+                date = date,
+                message_id = message_id,
+                hostname = hostname,
+                boundary = boundary,
+                envelope = envelope,
+            )
+            .as_bytes(),
+        );
+        report.extend_from_slice(raw_headers);
+        report.extend_from_slice(raw_message);
+        if !raw_message.ends_with(b"\n") {
+            report.extend_from_slice(b"\r\n");
+        }
+        report.extend_from_slice(b"--");
+        report.extend_from_slice(boundary.as_bytes());
+        report.extend_from_slice(b"--\r\n");
+
+        // Build and sign the report message
+        let from_addr_lcase = from_addr.to_lowercase();
+        let from_addr_domain = from_addr_lcase.domain_part().to_string();
+        let mut journal = Message::new_boxed(from_addr, from_addr_lcase, from_addr_domain);
+        journal.id = self.queue_id();
+        journal.add_recipient(to_addr, &self.config).await;
+        let signature = journal.sign(&config.sign, &report, span).await;
+
+        Some(JournalReport {
+            message: journal,
+            signature,
+            report,
+        })
+    }
+}
@@ -41,6 +41,8 @@ use super::{
     RCPT_STATUS_CHANGED,
 };
 
+const CONNECTION_PURGE_INTERVAL: Duration = Duration::from_secs(5);
+
 #[derive(Debug)]
 pub struct Queue {
     short_wait: Duration,
@@ -54,7 +56,13 @@ impl SpawnQueue for mpsc::Receiver<Event> {
     fn spawn(mut self, core: Arc<SMTP>, mut queue: Queue) {
         tokio::spawn(async move {
             loop {
-                let result = tokio::time::timeout(queue.wake_up_time(), self.recv()).await;
+                // Wake up periodically while there are idle outbound connections to expire
+                let wake_up_time = if core.queue.connections.idle_count() > 0 {
+                    std::cmp::min(queue.wake_up_time(), CONNECTION_PURGE_INTERVAL)
+                } else {
+                    queue.wake_up_time()
+                };
+                let result = tokio::time::timeout(wake_up_time, self.recv()).await;
 
                 // Deliver scheduled messages
                 while let Some(message) = queue.next_due() {
@@ -63,6 +71,9 @@ impl SpawnQueue for mpsc::Receiver<Event> {
                         .await;
                 }
 
+                // Close expired idle connections
+                core.queue.connections.purge();
+
                 match result {
                     Ok(Some(event)) => match event {
                         Event::Queue(item) => {
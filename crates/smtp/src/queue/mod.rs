@@ -38,7 +38,9 @@ use utils::{
 
 use crate::{config::EnvelopeKey, core::management};
 
+pub mod adaptive;
 pub mod dsn;
+pub mod journal;
 pub mod manager;
 pub mod quota;
 pub mod serialize;
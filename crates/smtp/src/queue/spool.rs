@@ -48,6 +48,38 @@ impl QueueCore {
         if message.id == 0 {
             message.id = self.queue_id();
         }
+
+        // Journal reports are built before spooling, as the message is
+        // moved into the queue afterwards.
+        let journal = self
+            .journal_report(&message, raw_headers, raw_message, span)
+            .await;
+        if !self
+            .spool_message(message, raw_headers, raw_message, span)
+            .await
+        {
+            return false;
+        }
+        if let Some(journal) = journal {
+            self.spool_message(
+                journal.message,
+                journal.signature.as_deref(),
+                &journal.report,
+                span,
+            )
+            .await;
+        }
+
+        true
+    }
+
+    async fn spool_message(
+        &self,
+        mut message: Box<Message>,
+        raw_headers: Option<&[u8]>,
+        raw_message: &[u8],
+        span: &tracing::Span,
+    ) -> bool {
         if message.size == 0 {
             message.size = raw_message.len() + raw_headers.as_ref().map_or(0, |h| h.len());
         }
@@ -36,9 +36,12 @@ use mail_auth::{
     zip,
 };
 use mail_parser::{DateTime, HeaderValue, Message, MimeHeaders, PartType};
+use tokio::runtime::Handle;
 
 use crate::core::SMTP;
 
+use super::incoming::ToIncomingReports;
+
 enum Compression {
     None,
     Gzip,
@@ -58,12 +61,13 @@ struct ReportData<'x> {
 }
 
 pub trait AnalyzeReport {
-    fn analyze_report(&self, message: Arc<Vec<u8>>);
+    fn analyze_report(&self, message: Arc<Vec<u8>>, complaint_sender: Option<String>);
 }
 
 impl AnalyzeReport for Arc<SMTP> {
-    fn analyze_report(&self, message: Arc<Vec<u8>>) {
+    fn analyze_report(&self, message: Arc<Vec<u8>>, complaint_sender: Option<String>) {
         let core = self.clone();
+        let handle = Handle::current();
         self.worker_pool.spawn(move || {
             let message = if let Some(message) = Message::parse(&message) {
                 message
@@ -221,6 +225,9 @@ impl AnalyzeReport for Arc<SMTP> {
                     Format::Dmarc => match Report::parse_xml(&data) {
                         Ok(report) => {
                             report.log();
+                            handle.block_on(
+                                core.store_incoming_reports(report.to_incoming_reports(from)),
+                            );
                         }
                         Err(err) => {
                             tracing::debug!(
@@ -235,6 +242,9 @@ impl AnalyzeReport for Arc<SMTP> {
                     Format::Tls => match TlsReport::parse_json(&data) {
                         Ok(report) => {
                             report.log();
+                            handle.block_on(
+                                core.store_incoming_reports(report.to_incoming_reports(from)),
+                            );
                         }
                         Err(err) => {
                             tracing::debug!(
@@ -249,6 +259,12 @@ impl AnalyzeReport for Arc<SMTP> {
                     Format::Arf => match Feedback::parse_arf(&data) {
                         Some(report) => {
                             report.log();
+                            handle.block_on(
+                                core.store_incoming_reports(report.to_incoming_reports(from)),
+                            );
+                            if let Some(sender) = &complaint_sender {
+                                handle.block_on(core.suppress_complaint(&report, sender));
+                            }
                         }
                         None => {
                             tracing::debug!(
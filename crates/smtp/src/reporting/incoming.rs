@@ -0,0 +1,690 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::net::IpAddr;
+
+use ahash::AHashMap;
+use mail_auth::report::{
+    tlsrpt::TlsReport, ActionDisposition, DmarcResult, Feedback, FeedbackType, Report,
+};
+use mail_parser::DateTime;
+use serde::{Deserialize, Serialize};
+use store::{
+    write::{
+        key::{DeserializeBigEndian, KeySerializer},
+        now, BatchBuilder, Operation, ValueClass,
+    },
+    CustomValueKey, ServerNamespace, Store,
+};
+
+use crate::core::SMTP;
+
+// Received reports are keyed by the beginning of their date range, each
+// report row is stored as a separate value so it can be filtered and aggregated.
+// Rows are also indexed by domain, reporter, source IP and disposition, the
+// index keys end with the date range and row id of the report they point to.
+const INCOMING_REPORT_NAMESPACE: u8 = ServerNamespace::IncomingReport as u8;
+const INCOMING_REPORT_INDEX_NAMESPACE: u8 = ServerNamespace::IncomingReportIndex as u8;
+
+const INDEX_DOMAIN: u8 = 0;
+const INDEX_REPORTER: u8 = 1;
+const INDEX_SOURCE_IP: u8 = 2;
+const INDEX_DISPOSITION: u8 = 3;
+const INDEX_REPORT_ID: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IncomingReportType {
+    Dmarc,
+    Tls,
+    Arf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IncomingReport {
+    #[serde(rename = "type")]
+    pub type_: IncomingReportType,
+    pub reporter: String,
+    pub report_id: String,
+    pub domain: String,
+    pub date_begin: u64,
+    pub date_end: u64,
+    pub source_ip: Option<IpAddr>,
+    pub count: u64,
+    pub disposition: String,
+    pub failure: bool,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct IncomingReportFilter {
+    pub type_: Option<IncomingReportType>,
+    pub domain: Option<String>,
+    pub reporter: Option<String>,
+    pub source_ip: Option<IpAddr>,
+    pub disposition: Option<String>,
+    pub failures_only: bool,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IncomingReportGroup {
+    Type,
+    Domain,
+    Reporter,
+    SourceIp,
+    Disposition,
+    Day,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct IncomingReportAggregate {
+    #[serde(rename = "type")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_: Option<IncomingReportType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reporter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_ip: Option<IpAddr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disposition: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub day: Option<String>,
+    pub total: u64,
+    pub failures: u64,
+}
+
+pub trait ToIncomingReports {
+    fn to_incoming_reports(&self, from: &str) -> Vec<IncomingReport>;
+}
+
+impl ToIncomingReports for Report {
+    fn to_incoming_reports(&self, from: &str) -> Vec<IncomingReport> {
+        let reporter = if !self.org_name().is_empty() {
+            self.org_name()
+        } else if !self.email().is_empty() {
+            self.email()
+        } else {
+            from
+        };
+
+        self.records()
+            .iter()
+            .map(|record| {
+                let dkim = record.dmarc_dkim_result();
+                let spf = record.dmarc_spf_result();
+                IncomingReport {
+                    type_: IncomingReportType::Dmarc,
+                    reporter: reporter.to_string(),
+                    report_id: self.report_id().to_string(),
+                    domain: self.domain().to_lowercase(),
+                    date_begin: self.date_range_begin(),
+                    date_end: self.date_range_end(),
+                    source_ip: record.source_ip(),
+                    count: record.count() as u64,
+                    disposition: match record.action_disposition() {
+                        ActionDisposition::Pass => "pass",
+                        ActionDisposition::Quarantine => "quarantine",
+                        ActionDisposition::Reject => "reject",
+                        ActionDisposition::None | ActionDisposition::Unspecified => "none",
+                    }
+                    .to_string(),
+                    failure: !matches!(dkim, DmarcResult::Pass)
+                        && !matches!(spf, DmarcResult::Pass),
+                }
+            })
+            .collect()
+    }
+}
+
+impl ToIncomingReports for TlsReport {
+    fn to_incoming_reports(&self, from: &str) -> Vec<IncomingReport> {
+        let reporter = self
+            .organization_name
+            .as_deref()
+            .or(self.contact_info.as_deref())
+            .unwrap_or(from);
+        let date_begin = self.date_range.start_datetime.to_timestamp() as u64;
+        let date_end = self.date_range.end_datetime.to_timestamp() as u64;
+        let mut reports = Vec::new();
+
+        for policy in &self.policies {
+            let domain = policy.policy.policy_domain.to_lowercase();
+            if policy.summary.total_success > 0 {
+                reports.push(IncomingReport {
+                    type_: IncomingReportType::Tls,
+                    reporter: reporter.to_string(),
+                    report_id: self.report_id.clone(),
+                    domain: domain.clone(),
+                    date_begin,
+                    date_end,
+                    source_ip: None,
+                    count: policy.summary.total_success as u64,
+                    disposition: "success".to_string(),
+                    failure: false,
+                });
+            }
+            for failure in &policy.failure_details {
+                reports.push(IncomingReport {
+                    type_: IncomingReportType::Tls,
+                    reporter: reporter.to_string(),
+                    report_id: self.report_id.clone(),
+                    domain: domain.clone(),
+                    date_begin,
+                    date_end,
+                    source_ip: failure.sending_mta_ip,
+                    count: failure.failed_session_count as u64,
+                    disposition: serde_json::to_value(failure.result_type)
+                        .ok()
+                        .and_then(|value| value.as_str().map(|value| value.to_string()))
+                        .unwrap_or_else(|| "other".to_string()),
+                    failure: true,
+                });
+            }
+        }
+
+        reports
+    }
+}
+
+impl ToIncomingReports for Feedback<'_> {
+    fn to_incoming_reports(&self, from: &str) -> Vec<IncomingReport> {
+        let arrival_date = self.arrival_date().map_or_else(now, |date| date as u64);
+        vec![IncomingReport {
+            type_: IncomingReportType::Arf,
+            reporter: self
+                .reporting_mta()
+                .map(|mta| mta.rsplit_once(';').map_or(mta, |(_, mta)| mta).trim())
+                .unwrap_or(from)
+                .to_string(),
+            report_id: String::new(),
+            domain: self
+                .reported_domain()
+                .first()
+                .map(|domain| domain.to_lowercase())
+                .unwrap_or_default(),
+            date_begin: arrival_date,
+            date_end: arrival_date,
+            source_ip: self.source_ip(),
+            count: std::cmp::max(self.incidents(), 1) as u64,
+            disposition: match self.feedback_type() {
+                FeedbackType::Abuse => "abuse",
+                FeedbackType::AuthFailure => "auth-failure",
+                FeedbackType::Fraud => "fraud",
+                FeedbackType::NotSpam => "not-spam",
+                FeedbackType::Virus => "virus",
+                FeedbackType::Other => "other",
+            }
+            .to_string(),
+            failure: true,
+        }]
+    }
+}
+
+impl SMTP {
+    pub async fn store_incoming_reports(&self, reports: Vec<IncomingReport>) {
+        let store = match &self.store {
+            Some(store) if !reports.is_empty() => store,
+            _ => return,
+        };
+        let config = &self.report.config.analysis;
+        let expires = now() + config.retention.as_secs();
+        let mut batch = BatchBuilder::new();
+
+        // Skip reports that were already received
+        let report = &reports[0];
+        if !report.report_id.is_empty() {
+            let key = incoming_index_key(
+                INDEX_REPORT_ID,
+                &format!("{}\0{}", report.reporter, report.report_id),
+                &[],
+            );
+            match store
+                .get_value::<u64>(CustomValueKey { value: key.clone() })
+                .await
+            {
+                Ok(None) => {
+                    batch.op(Operation::Value {
+                        class: ValueClass::Custom { bytes: key },
+                        set: expires.to_be_bytes().to_vec().into(),
+                    });
+                }
+                Ok(Some(_)) => {
+                    tracing::debug!(
+                        context = "report",
+                        event = "duplicate",
+                        reporter = report.reporter,
+                        report_id = report.report_id,
+                        "Ignoring duplicate incoming report."
+                    );
+                    return;
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        context = "report",
+                        event = "error",
+                        reason = %err,
+                        "Failed to store incoming report."
+                    );
+                    return;
+                }
+            }
+        }
+
+        if reports.len() > config.max_rows {
+            tracing::info!(
+                context = "report",
+                event = "truncated",
+                reporter = report.reporter,
+                report_id = report.report_id,
+                rows = reports.len(),
+                max_rows = config.max_rows,
+                "Incoming report has too many rows, ignoring the excess."
+            );
+        }
+
+        let id: u32 = rand::random();
+        for (row, report) in reports.iter().take(config.max_rows).enumerate() {
+            let suffix =
+                KeySerializer::new(std::mem::size_of::<u64>() + 2 * std::mem::size_of::<u32>())
+                    .write(report.date_begin)
+                    .write(id)
+                    .write(row as u32)
+                    .finalize();
+            let mut value = expires.to_be_bytes().to_vec();
+            value.extend_from_slice(&serde_json::to_vec(report).unwrap_or_default());
+            batch.op(Operation::Value {
+                class: ValueClass::Custom {
+                    bytes: incoming_report_key(report.date_begin, id, row as u32),
+                },
+                set: value.into(),
+            });
+
+            for (field, value) in report.index_values() {
+                batch.op(Operation::Value {
+                    class: ValueClass::Custom {
+                        bytes: incoming_index_key(field, &value, &suffix),
+                    },
+                    set: expires.to_be_bytes().to_vec().into(),
+                });
+            }
+        }
+        if let Err(err) = store.write(batch.build()).await {
+            tracing::warn!(
+                context = "report",
+                event = "error",
+                reason = %err,
+                "Failed to store incoming report."
+            );
+        }
+    }
+
+    pub async fn incoming_report_query(
+        &self,
+        filter: IncomingReportFilter,
+        limit: usize,
+    ) -> store::Result<Vec<IncomingReport>> {
+        match &self.store {
+            Some(store) => {
+                // Use an index when filtering by an indexed property
+                if let Some(keys) = incoming_report_index(store, &filter).await? {
+                    let mut reports = Vec::new();
+                    for key in keys {
+                        if let Some(IncomingReportValue(report)) =
+                            store.get_value(CustomValueKey { value: key }).await?
+                        {
+                            if filter.matches(&report) {
+                                reports.push(report);
+                                if limit != 0 && reports.len() >= limit {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    return Ok(reports);
+                }
+
+                // Newest reports are returned first
+                store
+                    .iterate(
+                        Vec::new(),
+                        CustomValueKey {
+                            value: incoming_report_key(filter.from.unwrap_or(0), 0, 0),
+                        },
+                        CustomValueKey {
+                            value: incoming_report_key(
+                                filter.to.unwrap_or(u64::MAX),
+                                u32::MAX,
+                                u32::MAX,
+                            ),
+                        },
+                        false,
+                        false,
+                        move |reports, key, value| {
+                            let report = deserialize_report(key, value)?;
+                            if filter.matches(&report) {
+                                reports.push(report);
+                            }
+                            Ok(limit == 0 || reports.len() < limit)
+                        },
+                    )
+                    .await
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub async fn incoming_report_aggregate(
+        &self,
+        filter: IncomingReportFilter,
+        group_by: Vec<IncomingReportGroup>,
+    ) -> store::Result<Vec<IncomingReportAggregate>> {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return Ok(Vec::new()),
+        };
+        let groups = if let Some(keys) = incoming_report_index(store, &filter).await? {
+            let mut groups = AHashMap::new();
+            for key in keys {
+                if let Some(IncomingReportValue(report)) =
+                    store.get_value(CustomValueKey { value: key }).await?
+                {
+                    if filter.matches(&report) {
+                        aggregate_report(&mut groups, &report, &group_by);
+                    }
+                }
+            }
+            groups
+        } else {
+            store
+                .iterate(
+                    AHashMap::new(),
+                    CustomValueKey {
+                        value: incoming_report_key(filter.from.unwrap_or(0), 0, 0),
+                    },
+                    CustomValueKey {
+                        value: incoming_report_key(
+                            filter.to.unwrap_or(u64::MAX),
+                            u32::MAX,
+                            u32::MAX,
+                        ),
+                    },
+                    false,
+                    true,
+                    move |groups, key, value| {
+                        let report = deserialize_report(key, value)?;
+                        if filter.matches(&report) {
+                            aggregate_report(groups, &report, &group_by);
+                        }
+                        Ok(true)
+                    },
+                )
+                .await?
+        };
+
+        // Sort by most failures first
+        let mut groups = groups
+            .into_iter()
+            .map(|(mut group, (total, failures))| {
+                group.total = total;
+                group.failures = failures;
+                group
+            })
+            .collect::<Vec<_>>();
+        groups.sort_unstable_by(|a, b| {
+            b.failures
+                .cmp(&a.failures)
+                .then_with(|| b.total.cmp(&a.total))
+                .then_with(|| a.day.cmp(&b.day))
+        });
+        Ok(groups)
+    }
+
+    pub async fn incoming_report_purge(&self, store: &Store) -> store::Result<()> {
+        for namespace in [INCOMING_REPORT_NAMESPACE, INCOMING_REPORT_INDEX_NAMESPACE] {
+            store
+                .purge_expired_values(
+                    KeySerializer::new(std::mem::size_of::<u32>() + 1)
+                        .write(u32::MAX)
+                        .write(namespace)
+                        .finalize(),
+                    now(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+impl IncomingReport {
+    fn index_values(&self) -> Vec<(u8, String)> {
+        [
+            (INDEX_DOMAIN, self.domain.clone()),
+            (INDEX_REPORTER, self.reporter.clone()),
+            (
+                INDEX_SOURCE_IP,
+                self.source_ip.map(|ip| ip.to_string()).unwrap_or_default(),
+            ),
+            (INDEX_DISPOSITION, self.disposition.clone()),
+        ]
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty() && !value.contains('\0'))
+        .collect()
+    }
+}
+
+impl IncomingReportFilter {
+    fn index_value(&self) -> Option<(u8, String)> {
+        self.domain
+            .as_ref()
+            .map(|domain| (INDEX_DOMAIN, domain.to_lowercase()))
+            .or_else(|| {
+                self.reporter
+                    .as_ref()
+                    .map(|reporter| (INDEX_REPORTER, reporter.clone()))
+            })
+            .or_else(|| self.source_ip.map(|ip| (INDEX_SOURCE_IP, ip.to_string())))
+            .or_else(|| {
+                self.disposition
+                    .as_ref()
+                    .map(|disposition| (INDEX_DISPOSITION, disposition.clone()))
+            })
+    }
+
+    fn matches(&self, report: &IncomingReport) -> bool {
+        self.type_.map_or(true, |type_| type_ == report.type_)
+            && self
+                .domain
+                .as_ref()
+                .map_or(true, |domain| domain.eq_ignore_ascii_case(&report.domain))
+            && self
+                .reporter
+                .as_ref()
+                .map_or(true, |reporter| reporter == &report.reporter)
+            && self
+                .source_ip
+                .map_or(true, |ip| Some(ip) == report.source_ip)
+            && self
+                .disposition
+                .as_ref()
+                .map_or(true, |disposition| disposition == &report.disposition)
+            && (!self.failures_only || report.failure)
+    }
+}
+
+impl std::str::FromStr for IncomingReportType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dmarc" => Ok(IncomingReportType::Dmarc),
+            "tls" => Ok(IncomingReportType::Tls),
+            "arf" => Ok(IncomingReportType::Arf),
+            _ => Err(()),
+        }
+    }
+}
+
+impl std::str::FromStr for IncomingReportGroup {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "type" => Ok(IncomingReportGroup::Type),
+            "domain" => Ok(IncomingReportGroup::Domain),
+            "reporter" => Ok(IncomingReportGroup::Reporter),
+            "ip" | "source-ip" => Ok(IncomingReportGroup::SourceIp),
+            "disposition" => Ok(IncomingReportGroup::Disposition),
+            "day" => Ok(IncomingReportGroup::Day),
+            _ => Err(()),
+        }
+    }
+}
+
+// Returns the keys of the matching report rows, newest first, or None if the
+// filter does not include an indexed property.
+async fn incoming_report_index(
+    store: &Store,
+    filter: &IncomingReportFilter,
+) -> store::Result<Option<Vec<Vec<u8>>>> {
+    let (field, value) = match filter.index_value() {
+        Some(index) => index,
+        None => return Ok(None),
+    };
+    let suffix_len = std::mem::size_of::<u64>() + 2 * std::mem::size_of::<u32>();
+    let index_key = |date: u64, id: u32, row: u32| {
+        incoming_index_key(
+            field,
+            &value,
+            &KeySerializer::new(suffix_len)
+                .write(date)
+                .write(id)
+                .write(row)
+                .finalize(),
+        )
+    };
+
+    store
+        .iterate(
+            Vec::new(),
+            CustomValueKey {
+                value: index_key(filter.from.unwrap_or(0), 0, 0),
+            },
+            CustomValueKey {
+                value: index_key(filter.to.unwrap_or(u64::MAX), u32::MAX, u32::MAX),
+            },
+            false,
+            false,
+            move |keys, key, _| {
+                let suffix = key
+                    .len()
+                    .checked_sub(suffix_len)
+                    .and_then(|pos| key.get(pos..))
+                    .ok_or_else(|| {
+                        store::Error::InternalError(format!(
+                            "Invalid incoming report index key {key:?}"
+                        ))
+                    })?;
+                keys.push(incoming_report_key(
+                    suffix.deserialize_be_u64(0)?,
+                    suffix.deserialize_be_u32(std::mem::size_of::<u64>())?,
+                    suffix.deserialize_be_u32(
+                        std::mem::size_of::<u64>() + std::mem::size_of::<u32>(),
+                    )?,
+                ));
+                Ok(true)
+            },
+        )
+        .await
+        .map(Some)
+}
+
+fn aggregate_report(
+    groups: &mut AHashMap<IncomingReportAggregate, (u64, u64)>,
+    report: &IncomingReport,
+    group_by: &[IncomingReportGroup],
+) {
+    let mut group = IncomingReportAggregate::default();
+    for item in group_by {
+        match item {
+            IncomingReportGroup::Type => group.type_ = report.type_.into(),
+            IncomingReportGroup::Domain => group.domain = report.domain.clone().into(),
+            IncomingReportGroup::Reporter => group.reporter = report.reporter.clone().into(),
+            IncomingReportGroup::SourceIp => group.source_ip = report.source_ip,
+            IncomingReportGroup::Disposition => {
+                group.disposition = report.disposition.clone().into()
+            }
+            IncomingReportGroup::Day => {
+                group.day = DateTime::from_timestamp(report.date_begin as i64)
+                    .to_rfc3339()
+                    .split_once('T')
+                    .map(|(day, _)| day.to_string())
+            }
+        }
+    }
+    let totals = groups.entry(group).or_insert((0, 0));
+    totals.0 += report.count;
+    if report.failure {
+        totals.1 += report.count;
+    }
+}
+
+struct IncomingReportValue(IncomingReport);
+
+impl store::Deserialize for IncomingReportValue {
+    fn deserialize(bytes: &[u8]) -> store::Result<Self> {
+        deserialize_report(&[], bytes).map(IncomingReportValue)
+    }
+}
+
+fn deserialize_report(key: &[u8], value: &[u8]) -> store::Result<IncomingReport> {
+    value
+        .get(std::mem::size_of::<u64>()..)
+        .and_then(|value| serde_json::from_slice(value).ok())
+        .ok_or_else(|| {
+            store::Error::InternalError(format!("Failed to deserialize incoming report {key:?}"))
+        })
+}
+
+fn incoming_report_key(date_begin: u64, id: u32, row: u32) -> Vec<u8> {
+    KeySerializer::new(std::mem::size_of::<u32>() * 3 + std::mem::size_of::<u64>() + 1)
+        .write(u32::MAX)
+        .write(INCOMING_REPORT_NAMESPACE)
+        .write(date_begin)
+        .write(id)
+        .write(row)
+        .finalize()
+}
+
+fn incoming_index_key(field: u8, value: &str, suffix: &[u8]) -> Vec<u8> {
+    KeySerializer::new(std::mem::size_of::<u32>() + 3 + value.len() + suffix.len())
+        .write(u32::MAX)
+        .write(INCOMING_REPORT_INDEX_NAMESPACE)
+        .write(field)
+        .write(value)
+        .write(0u8)
+        .write(suffix)
+        .finalize()
+}
@@ -48,6 +48,7 @@ use self::scheduler::{ReportKey, ReportValue};
 pub mod analysis;
 pub mod dkim;
 pub mod dmarc;
+pub mod incoming;
 pub mod scheduler;
 pub mod spf;
 pub mod tls;
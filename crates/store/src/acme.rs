@@ -0,0 +1,148 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use utils::acme::AcmeCache;
+
+use crate::{
+    write::{
+        assert::HashedValue,
+        key::{DeserializeBigEndian, KeySerializer},
+        now, BatchBuilder, Operation, ValueClass,
+    },
+    CustomValueKey, ServerNamespace, Store, SERVER_ACCOUNT_ID,
+};
+
+// ACME accounts and certificates are shared by all cluster nodes.
+const ACME_NAMESPACE: u8 = ServerNamespace::Acme as u8;
+
+#[async_trait::async_trait]
+impl AcmeCache for Store {
+    async fn read(&self, key: &str) -> utils::acme::Result<Option<Vec<u8>>> {
+        self.get_value::<Vec<u8>>(CustomValueKey {
+            value: acme_key(key),
+        })
+        .await
+        .map_err(|err| format!("Failed to read {key:?} from store: {err}"))
+    }
+
+    async fn write(&self, key: &str, value: Vec<u8>) -> utils::acme::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.op(Operation::Value {
+            class: ValueClass::Custom {
+                bytes: acme_key(key),
+            },
+            set: value.into(),
+        });
+        self.write(batch.build())
+            .await
+            .map_err(|err| format!("Failed to write {key:?} to store: {err}"))
+    }
+
+    async fn delete(&self, key: &str) -> utils::acme::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.op(Operation::Value {
+            class: ValueClass::Custom {
+                bytes: acme_key(key),
+            },
+            set: None,
+        });
+        self.write(batch.build())
+            .await
+            .map_err(|err| format!("Failed to delete {key:?} from store: {err}"))
+    }
+
+    async fn lock(&self, key: &str, holder: u64, lease: Duration) -> utils::acme::Result<bool> {
+        let key = acme_key(key);
+        let current = self
+            .get_value::<HashedValue<Vec<u8>>>(CustomValueKey { value: key.clone() })
+            .await
+            .map_err(|err| format!("Failed to read ACME lease: {err}"))?;
+        let now = now();
+
+        // Leases are taken with a compare-and-set so only one node can win
+        let mut batch = BatchBuilder::new();
+        if let Some(current) = current {
+            if lease_holder(&current.inner) != Some(holder)
+                && current.inner.deserialize_be_u64(8).unwrap_or(0) > now
+            {
+                return Ok(false);
+            }
+            batch.assert_value(ValueClass::Custom { bytes: key.clone() }, &current);
+        } else {
+            batch.assert_value(ValueClass::Custom { bytes: key.clone() }, ());
+        }
+        batch.op(Operation::Value {
+            class: ValueClass::Custom { bytes: key },
+            set: KeySerializer::new(std::mem::size_of::<u64>() * 2)
+                .write(holder)
+                .write(now + lease.as_secs())
+                .finalize()
+                .into(),
+        });
+
+        match self.write(batch.build()).await {
+            Ok(_) => Ok(true),
+            Err(crate::Error::AssertValueFailed) => Ok(false),
+            Err(err) => Err(format!("Failed to write ACME lease: {err}")),
+        }
+    }
+
+    async fn unlock(&self, key: &str, holder: u64) -> utils::acme::Result<()> {
+        let key = acme_key(key);
+        let current = self
+            .get_value::<HashedValue<Vec<u8>>>(CustomValueKey { value: key.clone() })
+            .await
+            .map_err(|err| format!("Failed to read ACME lease: {err}"))?;
+
+        match current {
+            Some(current) if lease_holder(&current.inner) == Some(holder) => {
+                let mut batch = BatchBuilder::new();
+                batch
+                    .assert_value(ValueClass::Custom { bytes: key.clone() }, &current)
+                    .op(Operation::Value {
+                        class: ValueClass::Custom { bytes: key },
+                        set: None,
+                    });
+                match self.write(batch.build()).await {
+                    Ok(_) | Err(crate::Error::AssertValueFailed) => Ok(()),
+                    Err(err) => Err(format!("Failed to release ACME lease: {err}")),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn lease_holder(value: &[u8]) -> Option<u64> {
+    value.deserialize_be_u64(0).ok()
+}
+
+fn acme_key(key: &str) -> Vec<u8> {
+    KeySerializer::new(std::mem::size_of::<u32>() + 1 + key.len())
+        .write(SERVER_ACCOUNT_ID)
+        .write(ACME_NAMESPACE)
+        .write(key)
+        .finalize()
+}
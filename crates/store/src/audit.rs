@@ -0,0 +1,399 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    fmt::Display,
+    net::{SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+use serde::Serialize;
+use utils::{codec::leb128::Leb128_, config::Config};
+
+use crate::{
+    write::{
+        key::{DeserializeBigEndian, KeySerializer},
+        now, BatchBuilder, Operation, ValueClass,
+    },
+    CustomValueKey, ServerNamespace, Store,
+};
+
+// Audit events are keyed by timestamp so they can be listed in order.
+const AUDIT_NAMESPACE: u8 = ServerNamespace::Audit as u8;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditEvent {
+    pub timestamp: u64,
+    pub account: String,
+    pub remote_ip: String,
+    pub protocol: String,
+    pub action: String,
+    pub target: String,
+    pub details: String,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct AuditFilter {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub account: Option<String>,
+    pub action: Option<String>,
+}
+
+pub struct AuditLog {
+    pub enable: bool,
+    pub retention: Duration,
+    pub syslog: Option<Syslog>,
+}
+
+pub struct Syslog {
+    facility: u8,
+    target: SyslogTarget,
+}
+
+enum SyslogTarget {
+    Udp {
+        socket: UdpSocket,
+        address: SocketAddr,
+    },
+    #[cfg(unix)]
+    Unix {
+        socket: std::os::unix::net::UnixDatagram,
+        path: std::path::PathBuf,
+    },
+}
+
+impl AuditEvent {
+    pub fn new(protocol: impl Into<String>, action: impl Into<String>) -> Self {
+        AuditEvent {
+            timestamp: now(),
+            account: String::new(),
+            remote_ip: String::new(),
+            protocol: protocol.into(),
+            action: action.into(),
+            target: String::new(),
+            details: String::new(),
+        }
+    }
+
+    pub fn with_account(mut self, account: impl Into<String>) -> Self {
+        self.account = account.into();
+        self
+    }
+
+    pub fn with_remote_ip(mut self, remote_ip: impl Display) -> Self {
+        self.remote_ip = remote_ip.to_string();
+        self
+    }
+
+    pub fn with_target(mut self, target: impl Into<String>) -> Self {
+        self.target = target.into();
+        self
+    }
+
+    pub fn with_details(mut self, details: impl Into<String>) -> Self {
+        self.details = details.into();
+        self
+    }
+
+    fn serialize(&self, expires: u64) -> Vec<u8> {
+        let fields = [
+            &self.account,
+            &self.remote_ip,
+            &self.protocol,
+            &self.action,
+            &self.target,
+            &self.details,
+        ];
+        let mut serializer = KeySerializer::new(
+            std::mem::size_of::<u64>() + fields.iter().map(|f| f.len() + 2).sum::<usize>(),
+        )
+        .write(expires);
+        for field in fields {
+            serializer = serializer.write_leb128(field.len()).write(field.as_str());
+        }
+        serializer.finalize()
+    }
+
+    fn deserialize(timestamp: u64, bytes: &[u8]) -> Option<Self> {
+        let mut bytes = bytes.get(std::mem::size_of::<u64>()..)?;
+        let mut fields = [(); 6].map(|_| String::new());
+        for field in fields.iter_mut() {
+            let (len, read) = usize::from_leb128_bytes(bytes)?;
+            *field = std::str::from_utf8(bytes.get(read..read + len)?)
+                .ok()?
+                .to_string();
+            bytes = bytes.get(read + len..)?;
+        }
+        let [account, remote_ip, protocol, action, target, details] = fields;
+        Some(AuditEvent {
+            timestamp,
+            account,
+            remote_ip,
+            protocol,
+            action,
+            target,
+            details,
+        })
+    }
+}
+
+impl Display for AuditEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "protocol={} account={:?} remote-ip={} action={} target={:?}",
+            self.protocol, self.account, self.remote_ip, self.action, self.target
+        )?;
+        if !self.details.is_empty() {
+            write!(f, " details={:?}", self.details)?;
+        }
+        Ok(())
+    }
+}
+
+impl AuditLog {
+    pub fn parse(config: &Config) -> utils::config::Result<Self> {
+        Ok(AuditLog {
+            enable: config.property_or_static("audit.enable", "true")?,
+            retention: config.property_or_static("audit.retention", "90d")?,
+            syslog: if config.property_or_static("audit.syslog.enable", "false")? {
+                Syslog::parse(config)?.into()
+            } else {
+                None
+            },
+        })
+    }
+
+    pub fn disabled() -> Self {
+        AuditLog {
+            enable: false,
+            retention: Duration::ZERO,
+            syslog: None,
+        }
+    }
+
+    pub async fn record(&self, store: &Store, event: AuditEvent) {
+        if !self.enable {
+            return;
+        }
+
+        tracing::info!(
+            context = "audit",
+            event = "record",
+            protocol = event.protocol.as_str(),
+            account = event.account.as_str(),
+            remote.ip = event.remote_ip.as_str(),
+            action = event.action.as_str(),
+            target = event.target.as_str(),
+        );
+
+        if let Some(syslog) = &self.syslog {
+            syslog.send(&event);
+        }
+
+        let mut batch = BatchBuilder::new();
+        batch.op(Operation::Value {
+            class: ValueClass::Custom {
+                bytes: audit_key(event.timestamp, rand::random()),
+            },
+            set: event
+                .serialize(event.timestamp + self.retention.as_secs())
+                .into(),
+        });
+        if let Err(err) = store.write(batch.build()).await {
+            tracing::error!(
+                context = "audit",
+                event = "error",
+                reason = %err,
+                "Failed to write audit event."
+            );
+        }
+    }
+}
+
+impl Syslog {
+    fn parse(config: &Config) -> utils::config::Result<Self> {
+        let facility = match config.value_or_default("audit.syslog.facility", "authpriv") {
+            Some("user") => 1,
+            Some("daemon") => 3,
+            Some("auth") => 4,
+            Some("authpriv") => 10,
+            Some("local0") => 16,
+            Some("local1") => 17,
+            Some("local2") => 18,
+            Some("local3") => 19,
+            Some("local4") => 20,
+            Some("local5") => 21,
+            Some("local6") => 22,
+            Some("local7") => 23,
+            Some(facility) => {
+                return Err(format!(
+                    "Invalid syslog facility {facility:?} for property \"audit.syslog.facility\"."
+                ))
+            }
+            None => 10,
+        };
+        let address = config
+            .value_or_default("audit.syslog.address", "/dev/log")
+            .unwrap_or("/dev/log");
+
+        #[cfg(unix)]
+        if address.starts_with('/') {
+            let socket = std::os::unix::net::UnixDatagram::unbound()
+                .and_then(|socket| socket.set_nonblocking(true).map(|_| socket))
+                .map_err(|err| format!("Failed to create syslog socket: {err}"))?;
+            return Ok(Syslog {
+                facility,
+                target: SyslogTarget::Unix {
+                    socket,
+                    path: address.into(),
+                },
+            });
+        }
+
+        let address = address.parse::<SocketAddr>().map_err(|_| {
+            format!("Invalid syslog address {address:?} for property \"audit.syslog.address\".")
+        })?;
+        let socket = UdpSocket::bind(if address.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        })
+        .and_then(|socket| socket.set_nonblocking(true).map(|_| socket))
+        .map_err(|err| format!("Failed to create syslog socket: {err}"))?;
+
+        Ok(Syslog {
+            facility,
+            target: SyslogTarget::Udp { socket, address },
+        })
+    }
+
+    fn send(&self, event: &AuditEvent) {
+        // Severity is always 'notice'
+        let message = format!(
+            "<{}>stalwart-mail[{}]: {}",
+            (self.facility << 3) | 5,
+            std::process::id(),
+            event
+        );
+        let result = match &self.target {
+            SyslogTarget::Udp { socket, address } => socket.send_to(message.as_bytes(), address),
+            #[cfg(unix)]
+            SyslogTarget::Unix { socket, path } => socket.send_to(message.as_bytes(), path),
+        };
+        if let Err(err) = result {
+            tracing::debug!(
+                context = "audit",
+                event = "error",
+                reason = %err,
+                "Failed to send audit event to syslog."
+            );
+        }
+    }
+}
+
+impl Store {
+    pub async fn audit_query(
+        &self,
+        filter: AuditFilter,
+        limit: usize,
+    ) -> crate::Result<Vec<AuditEvent>> {
+        // Newest events are returned first
+        self.iterate(
+            Vec::new(),
+            CustomValueKey {
+                value: audit_key(filter.from.unwrap_or(0), 0),
+            },
+            CustomValueKey {
+                value: audit_key(filter.to.unwrap_or(u64::MAX), u32::MAX),
+            },
+            false,
+            false,
+            move |events, key, value| {
+                let timestamp = key.deserialize_be_u64(std::mem::size_of::<u32>() + 1)?;
+                let event = AuditEvent::deserialize(timestamp, value).ok_or_else(|| {
+                    crate::Error::InternalError(format!(
+                        "Failed to deserialize audit event {key:?}"
+                    ))
+                })?;
+                if filter
+                    .account
+                    .as_ref()
+                    .map_or(true, |account| &event.account == account)
+                    && filter
+                        .action
+                        .as_ref()
+                        .map_or(true, |action| event.action.starts_with(action.as_str()))
+                {
+                    events.push(event);
+                }
+                Ok(limit == 0 || events.len() < limit)
+            },
+        )
+        .await
+    }
+
+    pub async fn purge_audit_log(&self) -> crate::Result<()> {
+        self.purge_expired_values(
+            KeySerializer::new(std::mem::size_of::<u32>() + 1)
+                .write(u32::MAX)
+                .write(AUDIT_NAMESPACE)
+                .finalize(),
+            now(),
+        )
+        .await
+    }
+}
+
+fn audit_key(timestamp: u64, seq: u32) -> Vec<u8> {
+    KeySerializer::new(std::mem::size_of::<u32>() * 2 + std::mem::size_of::<u64>() + 1)
+        .write(u32::MAX)
+        .write(AUDIT_NAMESPACE)
+        .write(timestamp)
+        .write(seq)
+        .finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AuditEvent;
+
+    #[test]
+    fn audit_event_roundtrip() {
+        let event = AuditEvent::new("imap", "acl.set")
+            .with_account("admin")
+            .with_remote_ip("192.168.1.1")
+            .with_target("jane/Inbox")
+            .with_details("john: read, readItems");
+        let bytes = event.serialize(u64::MAX);
+        assert_eq!(
+            AuditEvent::deserialize(event.timestamp, &bytes),
+            Some(event.clone())
+        );
+        assert_eq!(
+            AuditEvent::deserialize(event.timestamp, &bytes[..bytes.len() - 1]),
+            None
+        );
+    }
+}
@@ -0,0 +1,364 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::path::{Path, PathBuf};
+
+use ahash::AHashMap;
+use foundationdb::{options::StreamingMode, FdbError, KeySelector, RangeOption};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
+};
+
+use crate::{
+    backup::{account_value_ranges, audit_range},
+    write::key::{DeserializeBigEndian, KeySerializer},
+    Store, SUBSPACE_BITMAPS, SUBSPACE_INDEXES, SUBSPACE_LOGS, SUBSPACE_QUOTAS, SUBSPACE_VALUES,
+};
+
+const MAX_BACKUP_BATCH_SIZE: usize = 1 << 20;
+const MAX_BACKUP_ATTEMPTS: usize = 3;
+const TRANSACTION_TOO_OLD: i32 = 1007;
+
+impl Store {
+    pub(crate) async fn backup_change_ids(&self) -> crate::Result<AHashMap<u32, u64>> {
+        // Log keys are prefixed by the account id and collection, walk the log
+        // backwards reading only the last key of each prefix.
+        let mut change_ids = AHashMap::new();
+        let mut end = vec![SUBSPACE_LOGS + 1];
+
+        loop {
+            let trx = self.db.create_trx()?;
+            let values = trx
+                .get_range(
+                    &RangeOption {
+                        begin: KeySelector::first_greater_or_equal(&[SUBSPACE_LOGS][..]),
+                        end: KeySelector::first_greater_or_equal(end),
+                        limit: Some(1),
+                        mode: StreamingMode::Exact,
+                        reverse: true,
+                        ..Default::default()
+                    },
+                    1,
+                    true,
+                )
+                .await?;
+            let key = match values.first() {
+                Some(value) => value.key(),
+                None => break,
+            };
+
+            let account_id = key.deserialize_be_u32(1)?;
+            let change_id =
+                key.deserialize_be_u64(key.len().saturating_sub(std::mem::size_of::<u64>()))?;
+            let last_change_id = change_ids.entry(account_id).or_insert(change_id);
+            if change_id > *last_change_id {
+                *last_change_id = change_id;
+            }
+            end = key
+                .get(..std::mem::size_of::<u32>() + 2)
+                .ok_or_else(|| crate::Error::InternalError("Invalid change log key.".to_string()))?
+                .to_vec();
+        }
+
+        Ok(change_ids)
+    }
+
+    pub(crate) async fn backup_snapshot(&self, path: PathBuf) -> crate::Result<Option<(u64, u64)>> {
+        self.backup_ranges(path, vec![(vec![0u8], vec![u8::MAX])])
+            .await
+    }
+
+    pub(crate) async fn backup_accounts(
+        &self,
+        path: PathBuf,
+        account_ids: Vec<u32>,
+    ) -> crate::Result<Option<(u64, u64)>> {
+        self.backup_ranges(
+            path,
+            account_ids.into_iter().flat_map(account_ranges).collect(),
+        )
+        .await
+    }
+
+    async fn backup_ranges(
+        &self,
+        path: PathBuf,
+        ranges: Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> crate::Result<Option<(u64, u64)>> {
+        // All pages are read at the same version so that the snapshot is consistent.
+        // Versions older than the MVCC read window can no longer be read, in which
+        // case the backup is restarted at a newer version.
+        for _ in 0..MAX_BACKUP_ATTEMPTS {
+            let read_version = self.db.create_trx()?.get_read_version().await?;
+            match self.backup_ranges_at(&path, &ranges, read_version).await {
+                Ok(()) => {
+                    return Ok(Some((read_version as u64, read_version as u64)));
+                }
+                Err(BackupError::TooOld) => {
+                    tracing::debug!(
+                        context = "backup",
+                        event = "restart",
+                        read_version = read_version,
+                        "Read version expired while backing up, restarting backup."
+                    );
+                }
+                Err(BackupError::Other(err)) => return Err(err),
+            }
+        }
+
+        Err(crate::Error::InternalError(format!(
+            concat!(
+                "Failed to read a consistent snapshot of the store after {} attempts, ",
+                "the backup could not complete within the FoundationDB read window."
+            ),
+            MAX_BACKUP_ATTEMPTS
+        )))
+    }
+
+    async fn backup_ranges_at(
+        &self,
+        path: &Path,
+        ranges: &[(Vec<u8>, Vec<u8>)],
+        read_version: i64,
+    ) -> Result<(), BackupError> {
+        let mut writer = BufWriter::new(File::create(path).await?);
+
+        for (begin, end) in ranges {
+            let mut begin = KeySelector::first_greater_or_equal(begin.clone());
+            loop {
+                let trx = self.db.create_trx()?;
+                trx.set_read_version(read_version);
+                let values = trx
+                    .get_range(
+                        &RangeOption {
+                            begin: begin.clone(),
+                            end: KeySelector::first_greater_or_equal(end.clone()),
+                            mode: StreamingMode::WantAll,
+                            reverse: false,
+                            ..Default::default()
+                        },
+                        1,
+                        true,
+                    )
+                    .await?;
+
+                for value in values.iter() {
+                    let (key, value) = (value.key(), value.value());
+                    writer.write_u32(key.len() as u32).await?;
+                    writer.write_all(key).await?;
+                    writer.write_u32(value.len() as u32).await?;
+                    writer.write_all(value).await?;
+                }
+
+                match values.last() {
+                    Some(last) if values.more() => {
+                        begin = KeySelector::first_greater_than(last.key().to_vec());
+                    }
+                    _ => break,
+                }
+            }
+        }
+
+        writer.flush().await?;
+
+        Ok(())
+    }
+
+    pub(crate) async fn restore_accounts(
+        &self,
+        path: PathBuf,
+        account_ids: Option<Vec<u32>>,
+    ) -> crate::Result<()> {
+        self.restore_ranges(
+            path,
+            account_ids
+                .map(|account_ids| account_ids.into_iter().flat_map(account_ranges).collect()),
+        )
+        .await
+    }
+
+    pub(crate) async fn restore_account_values(
+        &self,
+        path: PathBuf,
+        account_id: u32,
+    ) -> crate::Result<()> {
+        self.restore_ranges(path, account_values(account_id).collect::<Vec<_>>().into())
+            .await
+    }
+
+    async fn restore_ranges(
+        &self,
+        path: PathBuf,
+        ranges: Option<Vec<(Vec<u8>, Vec<u8>)>>,
+    ) -> crate::Result<()> {
+        let mut reader = BufReader::new(File::open(&path).await.map_err(|err| {
+            crate::Error::InternalError(format!(
+                "Failed to open backup snapshot {}: {}",
+                path.display(),
+                err
+            ))
+        })?);
+
+        // Remove existing keys, the audit log is never replaced
+        let audit = {
+            let (begin, end) = audit_range();
+            let mut range = (vec![SUBSPACE_VALUES], vec![SUBSPACE_VALUES]);
+            range.0.extend(begin);
+            range.1.extend(end);
+            range
+        };
+        for (begin, end) in ranges
+            .clone()
+            .unwrap_or_else(|| vec![(vec![0u8], vec![u8::MAX])])
+        {
+            let trx = self.db.create_trx()?;
+            for (begin, end) in [
+                (begin.clone(), std::cmp::min(end.clone(), audit.0.clone())),
+                (std::cmp::max(begin, audit.1.clone()), end),
+            ] {
+                if begin < end {
+                    trx.clear_range(&begin, &end);
+                }
+            }
+            if let Err(err) = trx.commit().await {
+                return Err(FdbError::from(err).into());
+            }
+        }
+
+        // Write keys from the snapshot
+        let mut trx = self.db.create_trx()?;
+        let mut batch_size = 0;
+        loop {
+            let key = match reader.read_u32().await {
+                Ok(len) => {
+                    let mut key = vec![0u8; len as usize];
+                    reader.read_exact(&mut key).await?;
+                    key
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err.into()),
+            };
+            let mut value = vec![0u8; reader.read_u32().await? as usize];
+            reader.read_exact(&mut value).await?;
+
+            if key >= audit.0 && key < audit.1 {
+                continue;
+            } else if let Some(ranges) = &ranges {
+                if !ranges.iter().any(|(begin, end)| {
+                    key.as_slice() >= begin.as_slice() && key.as_slice() < end.as_slice()
+                }) {
+                    continue;
+                }
+            }
+
+            trx.set(&key, &value);
+            batch_size += key.len() + value.len();
+            if batch_size >= MAX_BACKUP_BATCH_SIZE {
+                if let Err(err) = trx.commit().await {
+                    return Err(FdbError::from(err).into());
+                }
+                trx = self.db.create_trx()?;
+                batch_size = 0;
+            }
+        }
+
+        if batch_size > 0 {
+            if let Err(err) = trx.commit().await {
+                return Err(FdbError::from(err).into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn account_ranges(account_id: u32) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut ranges = Vec::with_capacity(8);
+    for subspace in [
+        SUBSPACE_BITMAPS,
+        SUBSPACE_VALUES,
+        SUBSPACE_LOGS,
+        SUBSPACE_INDEXES,
+    ] {
+        let begin = KeySerializer::new(std::mem::size_of::<u32>() + 1)
+            .write(subspace)
+            .write(account_id)
+            .finalize();
+        let end = if account_id != u32::MAX {
+            KeySerializer::new(std::mem::size_of::<u32>() + 1)
+                .write(subspace)
+                .write(account_id + 1)
+                .finalize()
+        } else {
+            vec![subspace + 1]
+        };
+        ranges.push((begin, end));
+    }
+
+    let mut quota_key = KeySerializer::new(std::mem::size_of::<u32>() + 2)
+        .write(SUBSPACE_QUOTAS)
+        .write(account_id)
+        .finalize();
+    let begin = quota_key.clone();
+    quota_key.push(0);
+    ranges.push((begin, quota_key));
+
+    if account_id != u32::MAX {
+        ranges.extend(account_values(account_id));
+    }
+
+    ranges
+}
+
+fn account_values(account_id: u32) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> {
+    account_value_ranges(account_id)
+        .into_iter()
+        .map(|(begin, end)| {
+            let mut range = (vec![SUBSPACE_VALUES], vec![SUBSPACE_VALUES]);
+            range.0.extend(begin);
+            range.1.extend(end);
+            range
+        })
+}
+
+enum BackupError {
+    TooOld,
+    Other(crate::Error),
+}
+
+impl From<FdbError> for BackupError {
+    fn from(err: FdbError) -> Self {
+        if err.code() == TRANSACTION_TOO_OLD {
+            BackupError::TooOld
+        } else {
+            BackupError::Other(err.into())
+        }
+    }
+}
+
+impl From<std::io::Error> for BackupError {
+    fn from(err: std::io::Error) -> Self {
+        BackupError::Other(err.into())
+    }
+}
@@ -25,6 +25,7 @@ use foundationdb::FdbError;
 
 use crate::Error;
 
+pub mod backup;
 pub mod bitmap;
 pub mod main;
 pub mod purge;
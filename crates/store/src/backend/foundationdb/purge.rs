@@ -28,8 +28,8 @@ use foundationdb::{
 use futures::StreamExt;
 
 use crate::{
-    write::key::KeySerializer, Store, SUBSPACE_BITMAPS, SUBSPACE_INDEXES, SUBSPACE_LOGS,
-    SUBSPACE_QUOTAS, SUBSPACE_VALUES,
+    write::key::{DeserializeBigEndian, KeySerializer},
+    Store, SUBSPACE_BITMAPS, SUBSPACE_INDEXES, SUBSPACE_LOGS, SUBSPACE_QUOTAS, SUBSPACE_VALUES,
 };
 
 use super::bitmap::DenseBitmap;
@@ -130,4 +130,46 @@ impl Store {
 
         Ok(())
     }
+
+    pub async fn purge_expired_values(&self, prefix: Vec<u8>, now: u64) -> crate::Result<()> {
+        let mut from_key = Vec::with_capacity(prefix.len() + 1);
+        from_key.push(SUBSPACE_VALUES);
+        from_key.extend_from_slice(&prefix);
+        let mut to_key = from_key.clone();
+        *to_key.last_mut().unwrap() += 1;
+
+        // Values under the prefix start with their expiration timestamp
+        let trx = self.db.create_trx()?;
+        let mut iter = trx.get_ranges(
+            RangeOption {
+                begin: KeySelector::first_greater_or_equal(&from_key[..]),
+                end: KeySelector::first_greater_or_equal(&to_key[..]),
+                mode: options::StreamingMode::WantAll,
+                reverse: false,
+                ..Default::default()
+            },
+            true,
+        );
+        let mut delete_keys = Vec::new();
+
+        while let Some(values) = iter.next().await {
+            for value in values? {
+                if value.value().deserialize_be_u64(0)? <= now {
+                    delete_keys.push(value.key().to_vec());
+                }
+            }
+        }
+
+        for chunk in delete_keys.chunks(1024) {
+            let trx = self.db.create_trx()?;
+            for key in chunk {
+                trx.clear(key);
+            }
+            if let Err(err) = trx.commit().await {
+                return Err(FdbError::from(err).into());
+            }
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,79 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::path::PathBuf;
+
+use ahash::AHashMap;
+use rocksdb::checkpoint::Checkpoint;
+
+use crate::Store;
+
+// Full backups are RocksDB checkpoints, which are restored by replacing the
+// database directory while the server is stopped. Incremental backups and
+// online restores are not supported by this backend.
+impl Store {
+    pub(crate) async fn backup_change_ids(&self) -> crate::Result<AHashMap<u32, u64>> {
+        Ok(AHashMap::new())
+    }
+
+    pub(crate) async fn backup_snapshot(&self, path: PathBuf) -> crate::Result<Option<(u64, u64)>> {
+        Checkpoint::new(&self.db)?
+            .create_checkpoint(path)
+            .map(|_| None)
+            .map_err(Into::into)
+    }
+
+    pub(crate) async fn backup_accounts(
+        &self,
+        _path: PathBuf,
+        _account_ids: Vec<u32>,
+    ) -> crate::Result<Option<(u64, u64)>> {
+        Err(unsupported("Incremental backups"))
+    }
+
+    pub(crate) async fn restore_accounts(
+        &self,
+        _path: PathBuf,
+        _account_ids: Option<Vec<u32>>,
+    ) -> crate::Result<()> {
+        Err(unsupported("Online restores"))
+    }
+
+    pub(crate) async fn restore_account_values(
+        &self,
+        _path: PathBuf,
+        _account_id: u32,
+    ) -> crate::Result<()> {
+        Err(unsupported("Online restores"))
+    }
+}
+
+fn unsupported(what: &str) -> crate::Error {
+    crate::Error::InternalError(format!(
+        concat!(
+            "{} are not supported by the RocksDB backend, stop the server and ",
+            "replace the database directory with the backup checkpoint instead."
+        ),
+        what
+    ))
+}
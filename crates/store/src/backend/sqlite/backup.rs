@@ -0,0 +1,269 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::path::PathBuf;
+
+use ahash::AHashMap;
+use rusqlite::{Connection, DatabaseName, Transaction};
+
+use crate::{
+    backup::{account_value_ranges, audit_range},
+    write::key::{DeserializeBigEndian, KeySerializer},
+    Store, SUBSPACE_BITMAPS, SUBSPACE_INDEXES, SUBSPACE_LOGS, SUBSPACE_QUOTAS, SUBSPACE_VALUES,
+};
+
+use super::main::create_tables;
+
+impl Store {
+    pub(crate) async fn backup_change_ids(&self) -> crate::Result<AHashMap<u32, u64>> {
+        let conn = self.conn_pool.get()?;
+        self.spawn_worker(move || {
+            // Log keys are prefixed by the account id and collection, the last
+            // key of each prefix holds its most recent change id.
+            let mut change_ids = AHashMap::new();
+            let mut query = conn.prepare_cached("SELECT MAX(k) FROM l GROUP BY substr(k, 1, 5)")?;
+            let mut rows = query.query([])?;
+
+            while let Some(row) = rows.next()? {
+                let key = row.get_ref(0)?.as_bytes()?;
+                let account_id = key.deserialize_be_u32(0)?;
+                let change_id =
+                    key.deserialize_be_u64(key.len().saturating_sub(std::mem::size_of::<u64>()))?;
+                let last_change_id = change_ids.entry(account_id).or_insert(change_id);
+                if change_id > *last_change_id {
+                    *last_change_id = change_id;
+                }
+            }
+
+            Ok(change_ids)
+        })
+        .await
+    }
+
+    pub(crate) async fn backup_snapshot(&self, path: PathBuf) -> crate::Result<Option<(u64, u64)>> {
+        let conn = self.conn_pool.get()?;
+        self.spawn_worker(move || {
+            conn.backup(DatabaseName::Main, path, None)
+                .map(|_| None)
+                .map_err(Into::into)
+        })
+        .await
+    }
+
+    pub(crate) async fn backup_accounts(
+        &self,
+        path: PathBuf,
+        account_ids: Vec<u32>,
+    ) -> crate::Result<Option<(u64, u64)>> {
+        let conn = self.conn_pool.get()?;
+        self.spawn_worker(move || {
+            create_tables(&Connection::open(&path)?)?;
+            conn.execute(
+                "ATTACH DATABASE ? AS backup",
+                [path.to_string_lossy().as_ref()],
+            )?;
+            let result = copy_accounts(&conn, "main", "backup", Some(&account_ids), false);
+            conn.execute("DETACH DATABASE backup", [])?;
+            result.map(|_| None)
+        })
+        .await
+    }
+
+    pub(crate) async fn restore_accounts(
+        &self,
+        path: PathBuf,
+        account_ids: Option<Vec<u32>>,
+    ) -> crate::Result<()> {
+        let conn = self.conn_pool.get()?;
+        self.spawn_worker(move || {
+            if !path.exists() {
+                return Err(crate::Error::InternalError(format!(
+                    "Backup snapshot {} not found.",
+                    path.display()
+                )));
+            }
+            conn.execute(
+                "ATTACH DATABASE ? AS backup",
+                [path.to_string_lossy().as_ref()],
+            )?;
+            let result = copy_accounts(&conn, "backup", "main", account_ids.as_deref(), true);
+            conn.execute("DETACH DATABASE backup", [])?;
+            result
+        })
+        .await?;
+
+        // Cached document ids are no longer valid
+        self.id_assigner.lock().clear();
+
+        Ok(())
+    }
+
+    pub(crate) async fn restore_account_values(
+        &self,
+        path: PathBuf,
+        account_id: u32,
+    ) -> crate::Result<()> {
+        let conn = self.conn_pool.get()?;
+        self.spawn_worker(move || {
+            if !path.exists() {
+                return Err(crate::Error::InternalError(format!(
+                    "Backup snapshot {} not found.",
+                    path.display()
+                )));
+            }
+            conn.execute(
+                "ATTACH DATABASE ? AS backup",
+                [path.to_string_lossy().as_ref()],
+            )?;
+            let result = conn.unchecked_transaction().and_then(|trx| {
+                copy_account_values(&trx, "backup", "main", account_id)?;
+                trx.commit()
+            });
+            conn.execute("DETACH DATABASE backup", [])?;
+            result.map_err(Into::into)
+        })
+        .await
+    }
+}
+
+fn copy_accounts(
+    conn: &Connection,
+    from: &str,
+    to: &str,
+    account_ids: Option<&[u32]>,
+    keep_audit_log: bool,
+) -> crate::Result<()> {
+    let trx = conn.unchecked_transaction()?;
+    let (audit_from, audit_to) = audit_range();
+
+    if let Some(account_ids) = account_ids {
+        for &account_id in account_ids {
+            let from_key = KeySerializer::new(std::mem::size_of::<u32>())
+                .write(account_id)
+                .finalize();
+            let to_key = if account_id != u32::MAX {
+                KeySerializer::new(std::mem::size_of::<u32>())
+                    .write(account_id + 1)
+                    .finalize()
+            } else {
+                vec![u8::MAX; std::mem::size_of::<u32>() + 1]
+            };
+
+            for (table, i) in [
+                (SUBSPACE_BITMAPS, 'z'),
+                (SUBSPACE_VALUES, 'k'),
+                (SUBSPACE_LOGS, 'k'),
+                (SUBSPACE_INDEXES, 'k'),
+            ] {
+                // The audit log is never replaced on restores
+                let (filter, params) = if keep_audit_log && table == SUBSPACE_VALUES {
+                    (
+                        " AND NOT (k >= ? AND k < ?)",
+                        vec![&from_key, &to_key, &audit_from, &audit_to],
+                    )
+                } else {
+                    ("", vec![&from_key, &to_key])
+                };
+                let table = char::from(table);
+                trx.execute(
+                    &format!("DELETE FROM {to}.{table} WHERE {i} >= ? AND {i} < ?{filter}"),
+                    rusqlite::params_from_iter(&params),
+                )?;
+                trx.execute(
+                    &format!(
+                        "INSERT INTO {to}.{table} SELECT * FROM {from}.{table} WHERE {i} >= ? AND {i} < ?{filter}"
+                    ),
+                    rusqlite::params_from_iter(&params),
+                )?;
+            }
+
+            let table = char::from(SUBSPACE_QUOTAS);
+            trx.execute(
+                &format!("DELETE FROM {to}.{table} WHERE k = ?"),
+                [account_id as i64],
+            )?;
+            trx.execute(
+                &format!("INSERT INTO {to}.{table} SELECT * FROM {from}.{table} WHERE k = ?"),
+                [account_id as i64],
+            )?;
+
+            if account_id != u32::MAX {
+                copy_account_values(&trx, from, to, account_id)?;
+            }
+        }
+    } else {
+        for table in [
+            SUBSPACE_BITMAPS,
+            SUBSPACE_VALUES,
+            SUBSPACE_LOGS,
+            SUBSPACE_INDEXES,
+            SUBSPACE_QUOTAS,
+        ] {
+            if keep_audit_log && table == SUBSPACE_VALUES {
+                // The audit log is never replaced on restores
+                let table = char::from(table);
+                trx.execute(
+                    &format!("DELETE FROM {to}.{table} WHERE NOT (k >= ? AND k < ?)"),
+                    [&audit_from, &audit_to],
+                )?;
+                trx.execute(
+                    &format!(
+                        "INSERT INTO {to}.{table} SELECT * FROM {from}.{table} WHERE NOT (k >= ? AND k < ?)"
+                    ),
+                    [&audit_from, &audit_to],
+                )?;
+                continue;
+            }
+            let table = char::from(table);
+            trx.execute(&format!("DELETE FROM {to}.{table}"), [])?;
+            trx.execute(
+                &format!("INSERT INTO {to}.{table} SELECT * FROM {from}.{table}"),
+                [],
+            )?;
+        }
+    }
+
+    trx.commit().map_err(Into::into)
+}
+
+fn copy_account_values(
+    trx: &Transaction,
+    from: &str,
+    to: &str,
+    account_id: u32,
+) -> rusqlite::Result<()> {
+    let table = char::from(SUBSPACE_VALUES);
+    for (from_key, to_key) in account_value_ranges(account_id) {
+        trx.execute(
+            &format!("DELETE FROM {to}.{table} WHERE k >= ? AND k < ?"),
+            [&from_key, &to_key],
+        )?;
+        trx.execute(
+            &format!(
+                "INSERT INTO {to}.{table} SELECT * FROM {from}.{table} WHERE k >= ? AND k < ?"
+            ),
+            [&from_key, &to_key],
+        )?;
+    }
+    Ok(())
+}
@@ -26,6 +26,7 @@ use std::sync::Arc;
 use lru_cache::LruCache;
 use parking_lot::Mutex;
 use r2d2::Pool;
+use rusqlite::Connection;
 use tokio::sync::oneshot;
 use utils::{config::Config, UnwrapFailure};
 
@@ -76,66 +77,7 @@ impl Store {
     }
 
     pub(super) fn create_tables(&self) -> crate::Result<()> {
-        let conn = self.conn_pool.get()?;
-
-        for table in [SUBSPACE_VALUES, SUBSPACE_LOGS] {
-            let table = char::from(table);
-            conn.execute(
-                &format!(
-                    "CREATE TABLE IF NOT EXISTS {table} (
-                        k BLOB PRIMARY KEY,
-                        v BLOB NOT NULL
-                    )"
-                ),
-                [],
-            )?;
-        }
-
-        conn.execute(
-            &format!(
-                "CREATE TABLE IF NOT EXISTS {} (
-                    k BLOB PRIMARY KEY
-                )",
-                char::from(SUBSPACE_INDEXES)
-            ),
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS q (
-                    k INTEGER PRIMARY KEY,
-                    v INTEGER NOT NULL DEFAULT 0
-                )",
-            [],
-        )?;
-
-        conn.execute(
-            &format!(
-                "CREATE TABLE IF NOT EXISTS {} (
-                    z BLOB PRIMARY KEY,
-                    a INTEGER NOT NULL DEFAULT 0,
-                    b INTEGER NOT NULL DEFAULT 0,
-                    c INTEGER NOT NULL DEFAULT 0,
-                    d INTEGER NOT NULL DEFAULT 0,
-                    e INTEGER NOT NULL DEFAULT 0,
-                    f INTEGER NOT NULL DEFAULT 0,
-                    g INTEGER NOT NULL DEFAULT 0,
-                    h INTEGER NOT NULL DEFAULT 0,
-                    i INTEGER NOT NULL DEFAULT 0,
-                    j INTEGER NOT NULL DEFAULT 0,
-                    k INTEGER NOT NULL DEFAULT 0,
-                    l INTEGER NOT NULL DEFAULT 0,
-                    m INTEGER NOT NULL DEFAULT 0,
-                    n INTEGER NOT NULL DEFAULT 0,
-                    o INTEGER NOT NULL DEFAULT 0,
-                    p INTEGER NOT NULL DEFAULT 0
-                )",
-                char::from(SUBSPACE_BITMAPS)
-            ),
-            [],
-        )?;
-
-        Ok(())
+        create_tables(&self.conn_pool.get()?)
     }
 
     pub async fn spawn_worker<U, V>(&self, f: U) -> crate::Result<V>
@@ -158,3 +100,64 @@ impl Store {
         }
     }
 }
+
+pub(super) fn create_tables(conn: &Connection) -> crate::Result<()> {
+    for table in [SUBSPACE_VALUES, SUBSPACE_LOGS] {
+        let table = char::from(table);
+        conn.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {table} (
+                    k BLOB PRIMARY KEY,
+                    v BLOB NOT NULL
+                )"
+            ),
+            [],
+        )?;
+    }
+
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                k BLOB PRIMARY KEY
+            )",
+            char::from(SUBSPACE_INDEXES)
+        ),
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS q (
+                k INTEGER PRIMARY KEY,
+                v INTEGER NOT NULL DEFAULT 0
+            )",
+        [],
+    )?;
+
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                z BLOB PRIMARY KEY,
+                a INTEGER NOT NULL DEFAULT 0,
+                b INTEGER NOT NULL DEFAULT 0,
+                c INTEGER NOT NULL DEFAULT 0,
+                d INTEGER NOT NULL DEFAULT 0,
+                e INTEGER NOT NULL DEFAULT 0,
+                f INTEGER NOT NULL DEFAULT 0,
+                g INTEGER NOT NULL DEFAULT 0,
+                h INTEGER NOT NULL DEFAULT 0,
+                i INTEGER NOT NULL DEFAULT 0,
+                j INTEGER NOT NULL DEFAULT 0,
+                k INTEGER NOT NULL DEFAULT 0,
+                l INTEGER NOT NULL DEFAULT 0,
+                m INTEGER NOT NULL DEFAULT 0,
+                n INTEGER NOT NULL DEFAULT 0,
+                o INTEGER NOT NULL DEFAULT 0,
+                p INTEGER NOT NULL DEFAULT 0
+            )",
+            char::from(SUBSPACE_BITMAPS)
+        ),
+        [],
+    )?;
+
+    Ok(())
+}
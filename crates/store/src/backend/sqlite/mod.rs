@@ -21,6 +21,7 @@
  * for more details.
 */
 
+pub mod backup;
 pub mod id_assign;
 pub mod main;
 pub mod pool;
@@ -22,8 +22,8 @@
 */
 
 use crate::{
-    write::key::KeySerializer, Store, SUBSPACE_BITMAPS, SUBSPACE_INDEXES, SUBSPACE_LOGS,
-    SUBSPACE_VALUES,
+    write::key::{DeserializeBigEndian, KeySerializer},
+    Store, SUBSPACE_BITMAPS, SUBSPACE_INDEXES, SUBSPACE_LOGS, SUBSPACE_VALUES,
 };
 
 impl Store {
@@ -88,4 +88,32 @@ impl Store {
         })
         .await
     }
+
+    pub async fn purge_expired_values(&self, prefix: Vec<u8>, now: u64) -> crate::Result<()> {
+        let conn = self.conn_pool.get()?;
+        self.spawn_worker(move || {
+            let mut to_key = prefix.clone();
+            *to_key.last_mut().unwrap() += 1;
+
+            // Values under the prefix start with their expiration timestamp
+            let mut expired_keys = Vec::new();
+            {
+                let mut query = conn.prepare_cached("SELECT k, v FROM v WHERE k >= ? AND k < ?")?;
+                let mut rows = query.query([&prefix, &to_key])?;
+                while let Some(row) = rows.next()? {
+                    if row.get_ref(1)?.as_bytes()?.deserialize_be_u64(0)? <= now {
+                        expired_keys.push(row.get_ref(0)?.as_bytes()?.to_vec());
+                    }
+                }
+            }
+
+            for key in expired_keys {
+                conn.prepare_cached("DELETE FROM v WHERE k = ?")?
+                    .execute([&key])?;
+            }
+
+            Ok(())
+        })
+        .await
+    }
 }
@@ -0,0 +1,368 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::path::{Path, PathBuf};
+
+use ahash::{AHashMap, AHashSet};
+use tokio::fs;
+
+use crate::{
+    write::{key::KeySerializer, now},
+    ServerNamespace, Store, SERVER_ACCOUNT_ID,
+};
+
+pub const BACKUP_MANIFEST: &str = "manifest";
+pub const BACKUP_SNAPSHOT: &str = "db";
+pub const BACKUP_BLOBS: &str = "blobs";
+
+// Server-wide namespaces holding entries owned by an account, keyed by
+// the account id right after the namespace.
+const ACCOUNT_NAMESPACES: [ServerNamespace; 3] = [
+    ServerNamespace::LegalHold,
+    ServerNamespace::Tombstone,
+    ServerNamespace::SieveList,
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackupManifest {
+    pub created: u64,
+    pub base: Option<PathBuf>,
+    pub accounts: AHashMap<u32, u64>,
+    pub included: Option<Vec<u32>>,
+    pub versions: Option<(u64, u64)>,
+}
+
+impl Store {
+    /// Writes a consistent snapshot of the store and its blobs to `dest`.
+    /// When `base` points to a previous backup, only the accounts whose
+    /// change log advanced since then are included.
+    pub async fn backup(
+        &self,
+        dest: impl AsRef<Path>,
+        base: Option<&Path>,
+    ) -> crate::Result<BackupManifest> {
+        let dest = dest.as_ref();
+        if fs::metadata(dest.join(BACKUP_MANIFEST)).await.is_ok() {
+            return Err(crate::Error::InternalError(format!(
+                "Directory {} already contains a backup.",
+                dest.display()
+            )));
+        }
+        fs::create_dir_all(dest).await?;
+
+        // Change ids are obtained before the snapshot is taken, any changes written
+        // in the meantime will be included again in the next incremental backup.
+        let accounts = self.backup_change_ids().await?;
+        let manifest = if let Some(base) = base {
+            let base_manifest = BackupManifest::read(base).await?;
+            let mut included = accounts
+                .iter()
+                .filter(|(account_id, change_id)| {
+                    base_manifest.accounts.get(account_id) != Some(change_id)
+                })
+                .map(|(account_id, _)| *account_id)
+                .collect::<Vec<_>>();
+
+            // Server-wide values are always included
+            included.push(u32::MAX);
+            included.sort_unstable();
+
+            let versions = self
+                .backup_accounts(dest.join(BACKUP_SNAPSHOT), included.clone())
+                .await?;
+            for account_id in &included {
+                self.backup_blobs(&dest.join(BACKUP_BLOBS), Some(*account_id))
+                    .await?;
+            }
+
+            BackupManifest {
+                created: now(),
+                base: fs::canonicalize(base).await?.into(),
+                accounts,
+                included: included.into(),
+                versions,
+            }
+        } else {
+            let versions = self.backup_snapshot(dest.join(BACKUP_SNAPSHOT)).await?;
+            self.backup_blobs(&dest.join(BACKUP_BLOBS), None).await?;
+
+            BackupManifest {
+                created: now(),
+                base: None,
+                accounts,
+                included: None,
+                versions,
+            }
+        };
+        manifest.write(dest).await?;
+
+        Ok(manifest)
+    }
+
+    /// Restores the backup at `src`, following its chain of incremental
+    /// backups back to the full snapshot. When `account_id` is provided only
+    /// that account is replaced, otherwise the entire store is recovered.
+    /// The audit log is append-only and is never replaced by a restore.
+    /// This does not apply to RocksDB, which is restored offline by replacing
+    /// the whole database directory, audit log included, with a checkpoint.
+    pub async fn restore(
+        &self,
+        src: impl AsRef<Path>,
+        account_id: Option<u32>,
+    ) -> crate::Result<()> {
+        // Build backup chain, starting with the most recent one
+        let mut chain = Vec::new();
+        let mut next_path = Some(src.as_ref().to_path_buf());
+        while let Some(path) = next_path {
+            let manifest = BackupManifest::read(&path).await?;
+            next_path = manifest.base.clone();
+            chain.push((path, manifest));
+        }
+        let latest = &chain[0].1;
+
+        if let Some(account_id) = account_id {
+            if account_id != u32::MAX && !latest.accounts.contains_key(&account_id) {
+                return Err(crate::Error::InternalError(format!(
+                    "Account {account_id} not found in backup."
+                )));
+            }
+
+            // Use the most recent backup that contains the account
+            if let Some(pos) = chain
+                .iter()
+                .position(|(_, manifest)| manifest.contains(account_id))
+            {
+                let path = &chain[pos].0;
+                self.restore_accounts(path.join(BACKUP_SNAPSHOT), vec![account_id].into())
+                    .await?;
+                self.restore_blobs(&path.join(BACKUP_BLOBS), Some(account_id))
+                    .await?;
+
+                // Server-wide values are included in every backup, restore the
+                // account's entries from the most recent one.
+                if pos > 0 && account_id != u32::MAX {
+                    self.restore_account_values(chain[0].0.join(BACKUP_SNAPSHOT), account_id)
+                        .await?;
+                }
+            }
+        } else {
+            // Restore the full snapshot and replay incremental backups in order
+            for (path, manifest) in chain.iter().rev() {
+                if let Some(included) = &manifest.included {
+                    self.restore_accounts(path.join(BACKUP_SNAPSHOT), included.clone().into())
+                        .await?;
+                    for account_id in included {
+                        self.restore_blobs(&path.join(BACKUP_BLOBS), Some(*account_id))
+                            .await?;
+                    }
+                } else {
+                    self.restore_accounts(path.join(BACKUP_SNAPSHOT), None)
+                        .await?;
+                    self.restore_blobs(&path.join(BACKUP_BLOBS), None).await?;
+                }
+            }
+
+            // Remove accounts deleted after the full backup was taken
+            for account_id in chain
+                .iter()
+                .flat_map(|(_, manifest)| manifest.accounts.keys())
+                .filter(|account_id| !latest.accounts.contains_key(account_id))
+                .copied()
+                .collect::<AHashSet<_>>()
+            {
+                self.purge_account(account_id).await?;
+                self.delete_account_blobs(account_id).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl BackupManifest {
+    pub fn is_full(&self) -> bool {
+        self.included.is_none()
+    }
+
+    pub fn contains(&self, account_id: u32) -> bool {
+        self.included
+            .as_ref()
+            .map_or(true, |included| included.contains(&account_id))
+    }
+
+    pub async fn read(path: impl AsRef<Path>) -> crate::Result<Self> {
+        let path = path.as_ref().join(BACKUP_MANIFEST);
+        let contents = fs::read_to_string(&path).await.map_err(|err| {
+            crate::Error::InternalError(format!(
+                "Failed to read backup manifest {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+        Self::parse(&contents).ok_or_else(|| {
+            crate::Error::InternalError(format!("Invalid backup manifest {}.", path.display()))
+        })
+    }
+
+    pub async fn write(&self, path: impl AsRef<Path>) -> crate::Result<()> {
+        fs::write(path.as_ref().join(BACKUP_MANIFEST), self.to_string())
+            .await
+            .map_err(Into::into)
+    }
+
+    fn parse(contents: &str) -> Option<Self> {
+        let mut manifest = BackupManifest::default();
+        let mut has_created = false;
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once('=')?;
+            let (key, value) = (key.trim(), value.trim());
+            match key {
+                "created" => {
+                    manifest.created = value.parse().ok()?;
+                    has_created = true;
+                }
+                "base" => {
+                    manifest.base = PathBuf::from(value).into();
+                }
+                "versions" => {
+                    let (from, to) = value.split_once('-')?;
+                    manifest.versions = (from.trim().parse().ok()?, to.trim().parse().ok()?).into();
+                }
+                "include" => {
+                    manifest.included = value
+                        .split(',')
+                        .filter(|id| !id.is_empty())
+                        .map(|id| id.trim().parse().ok())
+                        .collect::<Option<Vec<_>>>()?
+                        .into();
+                }
+                _ => {
+                    let account_id = key.strip_prefix("account.")?.parse().ok()?;
+                    manifest.accounts.insert(account_id, value.parse().ok()?);
+                }
+            }
+        }
+
+        if has_created {
+            Some(manifest)
+        } else {
+            None
+        }
+    }
+}
+
+impl std::fmt::Display for BackupManifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "created = {}", self.created)?;
+        if let Some(base) = &self.base {
+            writeln!(f, "base = {}", base.display())?;
+        }
+        if let Some(included) = &self.included {
+            write!(f, "include = ")?;
+            for (pos, account_id) in included.iter().enumerate() {
+                if pos > 0 {
+                    write!(f, ",")?;
+                }
+                write!(f, "{account_id}")?;
+            }
+            writeln!(f)?;
+        }
+        if let Some((from, to)) = &self.versions {
+            writeln!(f, "versions = {from}-{to}")?;
+        }
+        let mut accounts = self.accounts.iter().collect::<Vec<_>>();
+        accounts.sort_unstable();
+        for (account_id, change_id) in accounts {
+            writeln!(f, "account.{account_id} = {change_id}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Key range of the audit log, which is left untouched by restores.
+pub(crate) fn audit_range() -> (Vec<u8>, Vec<u8>) {
+    let key = |namespace: u8| {
+        KeySerializer::new(std::mem::size_of::<u32>() + 1)
+            .write(SERVER_ACCOUNT_ID)
+            .write(namespace)
+            .finalize()
+    };
+    (
+        key(ServerNamespace::Audit as u8),
+        key(ServerNamespace::Audit as u8 + 1),
+    )
+}
+
+/// Key ranges of the server-wide values owned by `account_id`, which are
+/// copied along with the account on single-account backups and restores.
+pub(crate) fn account_value_ranges(account_id: u32) -> Vec<(Vec<u8>, Vec<u8>)> {
+    ACCOUNT_NAMESPACES
+        .iter()
+        .map(|namespace| {
+            let key = |account_id: u32| {
+                KeySerializer::new(std::mem::size_of::<u32>() * 2 + 1)
+                    .write(SERVER_ACCOUNT_ID)
+                    .write(*namespace as u8)
+                    .write(account_id)
+                    .finalize()
+            };
+            (key(account_id), key(account_id.saturating_add(1)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ahash::AHashMap;
+
+    use super::BackupManifest;
+
+    #[test]
+    fn manifest_roundtrip() {
+        for manifest in [
+            BackupManifest {
+                created: 1697000000,
+                base: None,
+                accounts: AHashMap::from_iter([(0, 10), (1, 3), (7, 0)]),
+                included: None,
+                versions: None,
+            },
+            BackupManifest {
+                created: 1697086400,
+                base: Some("/var/backup/full".into()),
+                accounts: AHashMap::from_iter([(0, 12), (7, 0)]),
+                included: Some(vec![0, u32::MAX]),
+                versions: Some((8130422, 9275311)),
+            },
+        ] {
+            assert_eq!(BackupManifest::parse(&manifest.to_string()), Some(manifest));
+        }
+        assert_eq!(BackupManifest::parse("account.1 = 2"), None);
+        assert_eq!(BackupManifest::parse("created = 1\ninvalid = 2"), None);
+    }
+}
@@ -0,0 +1,208 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::path::{Path, PathBuf};
+
+use tokio::fs;
+
+use crate::Store;
+
+use super::BlobStore;
+
+impl Store {
+    pub(crate) async fn backup_blobs(
+        &self,
+        dest: &Path,
+        account_id: Option<u32>,
+    ) -> crate::Result<()> {
+        match &self.blob {
+            BlobStore::Local(base_path) => {
+                // Temporary blobs are not included
+                for (name, path) in [
+                    ("emails", &base_path.path_email),
+                    ("blobs", &base_path.path_other),
+                ] {
+                    let mut src = path.to_path_buf();
+                    let mut dest = dest.join("local").join(name);
+                    if let Some(account_id) = account_id {
+                        src.push(format!("{:x}", account_id));
+                        dest.push(format!("{:x}", account_id));
+                    }
+                    copy_dir(&src, &dest).await?;
+                }
+
+                Ok(())
+            }
+            BlobStore::Remote(bucket) => {
+                let prefix = account_id.map_or_else(
+                    || "/".to_string(),
+                    |account_id| format!("/{:x}/", account_id),
+                );
+                let prefix_base = prefix.strip_prefix('/').unwrap();
+                for object in bucket
+                    .list(prefix.clone(), None)
+                    .await?
+                    .into_iter()
+                    .flat_map(|result| result.contents)
+                {
+                    let key = object.key.trim_start_matches('/');
+                    if key.starts_with("tmp/") {
+                        continue;
+                    } else if !object.key.starts_with(&prefix) && !key.starts_with(prefix_base) {
+                        tracing::debug!("Unexpected S3 object while backing up: {}", object.key);
+                        continue;
+                    }
+
+                    let response = bucket.get_object(&object.key).await?;
+                    if !(200..300).contains(&response.status_code()) {
+                        return Err(crate::Error::InternalError(format!(
+                            "Failed to fetch bucket item, code {}: {}",
+                            response.status_code(),
+                            String::from_utf8_lossy(response.as_slice())
+                        )));
+                    }
+                    let path = dest.join("s3").join(key);
+                    fs::create_dir_all(path.parent().unwrap()).await?;
+                    fs::write(path, response.as_slice()).await?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) async fn restore_blobs(
+        &self,
+        src: &Path,
+        account_id: Option<u32>,
+    ) -> crate::Result<()> {
+        // Remove existing blobs
+        if let Some(account_id) = account_id {
+            self.delete_account_blobs(account_id).await?;
+        } else {
+            match &self.blob {
+                BlobStore::Local(base_path) => {
+                    for path in [&base_path.path_email, &base_path.path_other] {
+                        if fs::metadata(path).await.is_ok() {
+                            fs::remove_dir_all(path).await?;
+                        }
+                    }
+                }
+                BlobStore::Remote(bucket) => {
+                    for object in bucket
+                        .list("/".to_string(), None)
+                        .await?
+                        .into_iter()
+                        .flat_map(|result| result.contents)
+                    {
+                        if !object.key.trim_start_matches('/').starts_with("tmp/") {
+                            let result = bucket.delete_object(object.key).await?;
+                            if !(200..300).contains(&result.status_code()) {
+                                return Err(crate::Error::InternalError(format!(
+                                    "Failed to delete bucket item, code {}: {}",
+                                    result.status_code(),
+                                    String::from_utf8_lossy(result.as_slice())
+                                )));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        match &self.blob {
+            BlobStore::Local(base_path) => {
+                for (name, path) in [
+                    ("emails", &base_path.path_email),
+                    ("blobs", &base_path.path_other),
+                ] {
+                    let mut src = src.join("local").join(name);
+                    let mut dest = path.to_path_buf();
+                    if let Some(account_id) = account_id {
+                        src.push(format!("{:x}", account_id));
+                        dest.push(format!("{:x}", account_id));
+                    }
+                    copy_dir(&src, &dest).await?;
+                }
+            }
+            BlobStore::Remote(bucket) => {
+                let mut base_path = src.join("s3");
+                if let Some(account_id) = account_id {
+                    base_path.push(format!("{:x}", account_id));
+                }
+                for path in list_files(&base_path).await? {
+                    let mut key = String::new();
+                    for component in path.strip_prefix(src.join("s3")).unwrap_or(&path) {
+                        key.push('/');
+                        key.push_str(&component.to_string_lossy());
+                    }
+                    let data = fs::read(&path).await?;
+                    match bucket.put_object(&key, &data).await {
+                        Ok(response) if (200..300).contains(&response.status_code()) => (),
+                        Ok(response) => {
+                            return Err(crate::Error::InternalError(format!(
+                                "S3 error code {}: {}",
+                                response.status_code(),
+                                String::from_utf8_lossy(response.as_slice())
+                            )))
+                        }
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn copy_dir(src: &Path, dest: &Path) -> crate::Result<()> {
+    for path in list_files(src).await? {
+        let dest = dest.join(path.strip_prefix(src).unwrap_or(&path));
+        fs::create_dir_all(dest.parent().unwrap()).await?;
+        fs::copy(&path, &dest).await?;
+    }
+    Ok(())
+}
+
+async fn list_files(path: &Path) -> crate::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    if fs::metadata(path).await.is_err() {
+        return Ok(files);
+    }
+
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else if metadata.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+
+    Ok(files)
+}
@@ -21,6 +21,7 @@
  * for more details.
 */
 
+pub mod backup;
 pub mod read;
 pub mod write;
 
@@ -0,0 +1,346 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use serde::Serialize;
+use utils::codec::leb128::Leb128_;
+
+use crate::{
+    write::{
+        key::{DeserializeBigEndian, KeySerializer},
+        BatchBuilder, Operation, ValueClass,
+    },
+    BlobKind, CustomValueKey, Deserialize, ServerNamespace, Store,
+};
+
+// Legal holds are keyed by account id, tombstones by account id and
+// tombstone id. Tombstone ids are assigned from their own collection so
+// they are never reused while the tombstone exists.
+const LEGAL_HOLD_NAMESPACE: u8 = ServerNamespace::LegalHold as u8;
+const TOMBSTONE_NAMESPACE: u8 = ServerNamespace::Tombstone as u8;
+pub const TOMBSTONE_COLLECTION: u8 = 254;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LegalHold {
+    pub since: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Tombstone {
+    pub id: u32,
+    pub deleted_at: u64,
+    pub sent_at: u64,
+    pub size: u32,
+    pub reason: String,
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub message_id: String,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TombstoneFilter {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub text: Option<String>,
+}
+
+impl LegalHold {
+    fn serialize(&self) -> Vec<u8> {
+        KeySerializer::new(std::mem::size_of::<u64>() + self.reason.len())
+            .write(self.since)
+            .write(self.reason.as_str())
+            .finalize()
+    }
+}
+
+impl Deserialize for LegalHold {
+    fn deserialize(bytes: &[u8]) -> crate::Result<Self> {
+        Ok(LegalHold {
+            since: bytes.deserialize_be_u64(0)?,
+            reason: std::str::from_utf8(&bytes[std::mem::size_of::<u64>()..])
+                .map_err(|_| crate::Error::InternalError("Invalid legal hold reason".to_string()))?
+                .to_string(),
+        })
+    }
+}
+
+impl Tombstone {
+    pub fn blob_kind(account_id: u32, id: u32) -> BlobKind {
+        BlobKind::Linked {
+            account_id,
+            collection: TOMBSTONE_COLLECTION,
+            document_id: id,
+        }
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let fields = [
+            &self.reason,
+            &self.from,
+            &self.to,
+            &self.subject,
+            &self.message_id,
+        ];
+        let mut serializer = KeySerializer::new(
+            std::mem::size_of::<u64>() * 2
+                + std::mem::size_of::<u32>()
+                + fields.iter().map(|f| f.len() + 2).sum::<usize>(),
+        )
+        .write(self.deleted_at)
+        .write(self.sent_at)
+        .write(self.size);
+        for field in fields {
+            serializer = serializer.write_leb128(field.len()).write(field.as_str());
+        }
+        serializer.finalize()
+    }
+
+    fn deserialize(id: u32, bytes: &[u8]) -> Option<Self> {
+        let deleted_at = bytes.deserialize_be_u64(0).ok()?;
+        let sent_at = bytes.deserialize_be_u64(std::mem::size_of::<u64>()).ok()?;
+        let size = bytes
+            .deserialize_be_u32(std::mem::size_of::<u64>() * 2)
+            .ok()?;
+        let mut bytes = bytes.get(std::mem::size_of::<u64>() * 2 + std::mem::size_of::<u32>()..)?;
+        let mut fields = [(); 5].map(|_| String::new());
+        for field in fields.iter_mut() {
+            let (len, read) = usize::from_leb128_bytes(bytes)?;
+            *field = std::str::from_utf8(bytes.get(read..read + len)?)
+                .ok()?
+                .to_string();
+            bytes = bytes.get(read + len..)?;
+        }
+        let [reason, from, to, subject, message_id] = fields;
+        Some(Tombstone {
+            id,
+            deleted_at,
+            sent_at,
+            size,
+            reason,
+            from,
+            to,
+            subject,
+            message_id,
+        })
+    }
+
+    fn matches(&self, filter: &TombstoneFilter) -> bool {
+        filter.from.map_or(true, |from| self.deleted_at >= from)
+            && filter.to.map_or(true, |to| self.deleted_at <= to)
+            && filter.text.as_ref().map_or(true, |text| {
+                let text = text.to_lowercase();
+                [&self.from, &self.to, &self.subject, &self.message_id]
+                    .iter()
+                    .any(|field| field.to_lowercase().contains(&text))
+            })
+    }
+}
+
+impl Store {
+    pub async fn get_legal_hold(&self, account_id: u32) -> crate::Result<Option<LegalHold>> {
+        self.get_value::<LegalHold>(CustomValueKey {
+            value: legal_hold_key(account_id),
+        })
+        .await
+    }
+
+    pub async fn set_legal_hold(
+        &self,
+        account_id: u32,
+        hold: Option<LegalHold>,
+    ) -> crate::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.op(Operation::Value {
+            class: ValueClass::Custom {
+                bytes: legal_hold_key(account_id),
+            },
+            set: hold.map(|hold| hold.serialize()),
+        });
+        self.write(batch.build()).await
+    }
+
+    pub async fn create_tombstone(
+        &self,
+        account_id: u32,
+        mut tombstone: Tombstone,
+        raw_message: &[u8],
+    ) -> crate::Result<u32> {
+        // Store a copy of the message before writing the tombstone
+        tombstone.id = self
+            .assign_document_id(account_id, TOMBSTONE_COLLECTION)
+            .await?;
+        self.put_blob(&Tombstone::blob_kind(account_id, tombstone.id), raw_message)
+            .await?;
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(TOMBSTONE_COLLECTION)
+            .create_document(tombstone.id)
+            .op(Operation::Value {
+                class: ValueClass::Custom {
+                    bytes: tombstone_key(account_id, tombstone.id),
+                },
+                set: tombstone.serialize().into(),
+            });
+        self.write(batch.build()).await?;
+
+        Ok(tombstone.id)
+    }
+
+    pub async fn tombstone_query(
+        &self,
+        account_id: u32,
+        filter: TombstoneFilter,
+        limit: usize,
+    ) -> crate::Result<Vec<Tombstone>> {
+        // Most recently deleted messages are returned first
+        self.iterate(
+            Vec::new(),
+            CustomValueKey {
+                value: tombstone_key(account_id, 0),
+            },
+            CustomValueKey {
+                value: tombstone_key(account_id, u32::MAX),
+            },
+            false,
+            false,
+            move |tombstones, key, value| {
+                let id = key.deserialize_be_u32(std::mem::size_of::<u32>() * 2 + 1)?;
+                let tombstone = Tombstone::deserialize(id, value).ok_or_else(|| {
+                    crate::Error::InternalError(format!("Failed to deserialize tombstone {key:?}"))
+                })?;
+                if tombstone.matches(&filter) {
+                    tombstones.push(tombstone);
+                }
+                Ok(limit == 0 || tombstones.len() < limit)
+            },
+        )
+        .await
+    }
+
+    pub async fn purge_released_tombstones(&self) -> crate::Result<()> {
+        // Obtain all tombstones of accounts that are no longer on hold
+        let mut tombstones: Vec<(u32, Vec<u32>)> = Vec::new();
+        for (account_id, id) in self
+            .iterate(
+                Vec::new(),
+                CustomValueKey {
+                    value: tombstone_key(0, 0),
+                },
+                CustomValueKey {
+                    value: tombstone_key(u32::MAX, u32::MAX),
+                },
+                false,
+                true,
+                |ids, key, _| {
+                    ids.push((
+                        key.deserialize_be_u32(std::mem::size_of::<u32>() + 1)?,
+                        key.deserialize_be_u32(std::mem::size_of::<u32>() * 2 + 1)?,
+                    ));
+                    Ok(true)
+                },
+            )
+            .await?
+        {
+            match tombstones.last_mut() {
+                Some((last_account_id, ids)) if *last_account_id == account_id => ids.push(id),
+                _ => tombstones.push((account_id, vec![id])),
+            }
+        }
+
+        for (account_id, ids) in tombstones {
+            if self.get_legal_hold(account_id).await?.is_some() {
+                continue;
+            }
+
+            tracing::debug!(
+                context = "legal_hold",
+                event = "purge",
+                account_id = account_id,
+                total = ids.len(),
+                "Purging tombstones of released account."
+            );
+
+            for id in ids {
+                self.delete_blob(&Tombstone::blob_kind(account_id, id))
+                    .await?;
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(TOMBSTONE_COLLECTION)
+                    .delete_document(id)
+                    .op(Operation::Value {
+                        class: ValueClass::Custom {
+                            bytes: tombstone_key(account_id, id),
+                        },
+                        set: None,
+                    });
+                self.write(batch.build()).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn legal_hold_key(account_id: u32) -> Vec<u8> {
+    KeySerializer::new(std::mem::size_of::<u32>() * 2 + 1)
+        .write(u32::MAX)
+        .write(LEGAL_HOLD_NAMESPACE)
+        .write(account_id)
+        .finalize()
+}
+
+fn tombstone_key(account_id: u32, id: u32) -> Vec<u8> {
+    KeySerializer::new(std::mem::size_of::<u32>() * 3 + 1)
+        .write(u32::MAX)
+        .write(TOMBSTONE_NAMESPACE)
+        .write(account_id)
+        .write(id)
+        .finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tombstone;
+
+    #[test]
+    fn tombstone_roundtrip() {
+        let tombstone = Tombstone {
+            id: 7,
+            deleted_at: 1700000000,
+            sent_at: 1690000000,
+            size: 1024,
+            reason: "expunge".to_string(),
+            from: "jane@example.org".to_string(),
+            to: "john@example.org, bill@example.org".to_string(),
+            subject: "Quarterly results".to_string(),
+            message_id: "abc@example.org".to_string(),
+        };
+        let bytes = tombstone.serialize();
+        assert_eq!(Tombstone::deserialize(7, &bytes), Some(tombstone));
+        assert_eq!(Tombstone::deserialize(7, &bytes[..bytes.len() - 1]), None);
+    }
+}
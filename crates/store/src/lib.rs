@@ -25,9 +25,15 @@ use std::fmt::Display;
 
 use blob::BlobStore;
 
+pub mod acme;
+pub mod audit;
 pub mod backend;
+pub mod backup;
 pub mod blob;
 pub mod fts;
+pub mod hold;
+pub mod lists;
+pub mod quarantine;
 pub mod query;
 pub mod write;
 
@@ -214,6 +220,31 @@ impl From<String> for Error {
     }
 }
 
+/// Account id under which server-wide values are stored.
+pub const SERVER_ACCOUNT_ID: u32 = u32::MAX;
+
+/// Namespaces of the server-wide values stored under [`SERVER_ACCOUNT_ID`],
+/// written right after the account id in custom value keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum ServerNamespace {
+    AccountName = 0,
+    AccountId = 1,
+    Vapid = 2,
+    Bayes = 3,
+    Greylist = 4,
+    Acme = 5,
+    Audit = 6,
+    LegalHold = 7,
+    Tombstone = 8,
+    SieveList = 9,
+    Suppression = 10,
+    IncomingReport = 11,
+    Quarantine = 12,
+    Webhook = 13,
+    IncomingReportIndex = 14,
+}
+
 pub const BM_DOCUMENT_IDS: u8 = 0;
 pub const BM_TAG: u8 = 1 << 5;
 pub const BM_HASH: u8 = 1 << 6;
@@ -248,6 +279,10 @@ impl Store {
         unimplemented!("No backend selected")
     }
 
+    pub async fn purge_expired_values(&self, _prefix: Vec<u8>, _now: u64) -> crate::Result<()> {
+        unimplemented!("No backend selected")
+    }
+
     pub async fn read_transaction(&self) -> crate::Result<ReadTransaction<'_>> {
         unimplemented!("No backend selected")
     }
@@ -268,6 +303,41 @@ impl Store {
         unimplemented!("No backend selected")
     }
 
+    pub(crate) async fn backup_change_ids(&self) -> crate::Result<ahash::AHashMap<u32, u64>> {
+        unimplemented!("No backend selected")
+    }
+
+    pub(crate) async fn backup_snapshot(
+        &self,
+        _path: std::path::PathBuf,
+    ) -> crate::Result<Option<(u64, u64)>> {
+        unimplemented!("No backend selected")
+    }
+
+    pub(crate) async fn backup_accounts(
+        &self,
+        _path: std::path::PathBuf,
+        _account_ids: Vec<u32>,
+    ) -> crate::Result<Option<(u64, u64)>> {
+        unimplemented!("No backend selected")
+    }
+
+    pub(crate) async fn restore_accounts(
+        &self,
+        _path: std::path::PathBuf,
+        _account_ids: Option<Vec<u32>>,
+    ) -> crate::Result<()> {
+        unimplemented!("No backend selected")
+    }
+
+    pub(crate) async fn restore_account_values(
+        &self,
+        _path: std::path::PathBuf,
+        _account_id: u32,
+    ) -> crate::Result<()> {
+        unimplemented!("No backend selected")
+    }
+
     #[cfg(feature = "test_mode")]
     pub async fn destroy(&self) {
         unimplemented!("No backend selected")
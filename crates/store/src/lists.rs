@@ -0,0 +1,166 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::{
+    write::{
+        key::{DeserializeBigEndian, KeySerializer},
+        BatchBuilder, Operation, ValueClass,
+    },
+    CustomValueKey, ServerNamespace, Store,
+};
+
+// Personal Sieve lists are keyed by account id and list name, each item is
+// stored under its own key so that lists are not bound by the maximum value
+// size of the backend. As they live under the server account, they have to be
+// removed explicitly when the account is deleted.
+const SIEVE_LIST_NAMESPACE: u8 = ServerNamespace::SieveList as u8;
+
+impl Store {
+    pub async fn get_sieve_list(
+        &self,
+        account_id: u32,
+        name: &str,
+    ) -> crate::Result<Option<Vec<String>>> {
+        let prefix_len = sieve_list_key(account_id, name, None).len();
+        self.iterate(
+            Vec::new(),
+            CustomValueKey {
+                value: sieve_list_key(account_id, name, None),
+            },
+            CustomValueKey {
+                value: sieve_list_key_end(account_id, name),
+            },
+            false,
+            true,
+            move |items, key, _| {
+                if let Some(item) = key.get(prefix_len..) {
+                    items.push(String::from_utf8_lossy(item).into_owned());
+                }
+                Ok(true)
+            },
+        )
+        .await
+        .map(|items| if !items.is_empty() { Some(items) } else { None })
+    }
+
+    pub async fn set_sieve_list(
+        &self,
+        account_id: u32,
+        name: &str,
+        items: Option<Vec<String>>,
+    ) -> crate::Result<()> {
+        let items = items.unwrap_or_default();
+        let current = self
+            .get_sieve_list(account_id, name)
+            .await?
+            .unwrap_or_default();
+        let mut batch = BatchBuilder::new();
+        for item in &current {
+            if !items.contains(item) {
+                batch.op(Operation::Value {
+                    class: ValueClass::Custom {
+                        bytes: sieve_list_key(account_id, name, item.as_str().into()),
+                    },
+                    set: None,
+                });
+            }
+        }
+        for item in &items {
+            if !current.contains(item) {
+                batch.op(Operation::Value {
+                    class: ValueClass::Custom {
+                        bytes: sieve_list_key(account_id, name, item.as_str().into()),
+                    },
+                    set: Some(Vec::new()),
+                });
+            }
+        }
+        if !batch.is_empty() {
+            self.write(batch.build()).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn delete_sieve_lists(&self, account_id: u32) -> crate::Result<()> {
+        for name in self.get_sieve_list_names(account_id).await? {
+            self.set_sieve_list(account_id, &name, None).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_sieve_list_names(&self, account_id: u32) -> crate::Result<Vec<String>> {
+        self.iterate(
+            Vec::new(),
+            CustomValueKey {
+                value: sieve_list_key(account_id, "", None),
+            },
+            CustomValueKey {
+                value: sieve_list_key(account_id.saturating_add(1), "", None),
+            },
+            false,
+            true,
+            move |names: &mut Vec<String>, key, _| {
+                if key.deserialize_be_u32(std::mem::size_of::<u32>() + 1)? == account_id {
+                    let name = key
+                        .get(std::mem::size_of::<u32>() * 2 + 1..)
+                        .unwrap_or_default();
+                    let name = String::from_utf8_lossy(
+                        name.split(|&ch| ch == 0).next().unwrap_or_default(),
+                    );
+                    if names
+                        .last()
+                        .map_or(true, |last| last.as_str() != name.as_ref())
+                    {
+                        names.push(name.into_owned());
+                    }
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
+            },
+        )
+        .await
+    }
+}
+
+fn sieve_list_key(account_id: u32, name: &str, item: Option<&str>) -> Vec<u8> {
+    let item = item.unwrap_or_default();
+    KeySerializer::new(std::mem::size_of::<u32>() * 2 + 2 + name.len() + item.len())
+        .write(u32::MAX)
+        .write(SIEVE_LIST_NAMESPACE)
+        .write(account_id)
+        .write(name)
+        .write(0u8)
+        .write(item)
+        .finalize()
+}
+
+fn sieve_list_key_end(account_id: u32, name: &str) -> Vec<u8> {
+    KeySerializer::new(std::mem::size_of::<u32>() * 2 + 2 + name.len())
+        .write(u32::MAX)
+        .write(SIEVE_LIST_NAMESPACE)
+        .write(account_id)
+        .write(name)
+        .write(1u8)
+        .finalize()
+}
@@ -0,0 +1,452 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use serde::Serialize;
+use utils::codec::leb128::Leb128_;
+
+use crate::{
+    write::{
+        assert::HashedValue,
+        key::{DeserializeBigEndian, KeySerializer},
+        BatchBuilder, Operation, ValueClass,
+    },
+    BlobKind, CustomValueKey, ServerNamespace, Store,
+};
+
+// Quarantined messages are not owned by any account, their ids are assigned
+// from a dedicated collection of the reserved account id. Values start with
+// the expiration timestamp.
+const QUARANTINE_NAMESPACE: u8 = ServerNamespace::Quarantine as u8;
+pub const QUARANTINE_COLLECTION: u8 = 253;
+const QUARANTINE_ACCOUNT_ID: u32 = u32::MAX;
+
+const FLAG_NOTIFIED: u8 = 0x01;
+const MAX_UPDATE_ATTEMPTS: usize = 10;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct QuarantinedMessage {
+    pub id: u32,
+    pub created: u64,
+    pub expires: u64,
+    // Ids are reused, release links are bound to the nonce of the entry they were issued for
+    #[serde(skip)]
+    pub nonce: u64,
+    pub size: u32,
+    pub notified: bool,
+    pub source: String,
+    pub reason: String,
+    pub return_path: String,
+    pub recipients: Vec<String>,
+    pub from: String,
+    pub subject: String,
+    pub remote_ip: String,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct QuarantineFilter {
+    pub recipient: Option<String>,
+    pub source: Option<String>,
+    pub text: Option<String>,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub not_notified: bool,
+}
+
+impl QuarantinedMessage {
+    pub fn blob_kind(id: u32) -> BlobKind {
+        BlobKind::Linked {
+            account_id: QUARANTINE_ACCOUNT_ID,
+            collection: QUARANTINE_COLLECTION,
+            document_id: id,
+        }
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let recipients = self.recipients.join("\n");
+        let fields = [
+            &self.source,
+            &self.reason,
+            &self.return_path,
+            &recipients,
+            &self.from,
+            &self.subject,
+            &self.remote_ip,
+        ];
+        let mut serializer = KeySerializer::new(
+            std::mem::size_of::<u64>() * 3
+                + std::mem::size_of::<u32>()
+                + 1
+                + fields.iter().map(|f| f.len() + 2).sum::<usize>(),
+        )
+        .write(self.expires)
+        .write(self.created)
+        .write(self.nonce)
+        .write(self.size)
+        .write(if self.notified { FLAG_NOTIFIED } else { 0 });
+        for field in fields {
+            serializer = serializer.write_leb128(field.len()).write(field.as_str());
+        }
+        serializer.finalize()
+    }
+
+    fn deserialize(id: u32, bytes: &[u8]) -> Option<Self> {
+        let expires = bytes.deserialize_be_u64(0).ok()?;
+        let created = bytes.deserialize_be_u64(std::mem::size_of::<u64>()).ok()?;
+        let nonce = bytes
+            .deserialize_be_u64(std::mem::size_of::<u64>() * 2)
+            .ok()?;
+        let size = bytes
+            .deserialize_be_u32(std::mem::size_of::<u64>() * 3)
+            .ok()?;
+        let flags = *bytes.get(std::mem::size_of::<u64>() * 3 + std::mem::size_of::<u32>())?;
+        let mut bytes =
+            bytes.get(std::mem::size_of::<u64>() * 3 + std::mem::size_of::<u32>() + 1..)?;
+        let mut fields = [(); 7].map(|_| String::new());
+        for field in fields.iter_mut() {
+            let (len, read) = usize::from_leb128_bytes(bytes)?;
+            *field = std::str::from_utf8(bytes.get(read..read + len)?)
+                .ok()?
+                .to_string();
+            bytes = bytes.get(read + len..)?;
+        }
+        let [source, reason, return_path, recipients, from, subject, remote_ip] = fields;
+        Some(QuarantinedMessage {
+            id,
+            created,
+            expires,
+            nonce,
+            size,
+            notified: flags & FLAG_NOTIFIED != 0,
+            source,
+            reason,
+            return_path,
+            recipients: recipients
+                .split('\n')
+                .filter(|rcpt| !rcpt.is_empty())
+                .map(|rcpt| rcpt.to_string())
+                .collect(),
+            from,
+            subject,
+            remote_ip,
+        })
+    }
+
+    fn matches(&self, filter: &QuarantineFilter) -> bool {
+        filter.from.map_or(true, |from| self.created >= from)
+            && filter.to.map_or(true, |to| self.created <= to)
+            && (!filter.not_notified || !self.notified)
+            && filter
+                .source
+                .as_ref()
+                .map_or(true, |source| self.source.eq_ignore_ascii_case(source))
+            && filter.recipient.as_ref().map_or(true, |recipient| {
+                self.recipients
+                    .iter()
+                    .any(|rcpt| rcpt.eq_ignore_ascii_case(recipient))
+            })
+            && filter.text.as_ref().map_or(true, |text| {
+                let text = text.to_lowercase();
+                [&self.return_path, &self.from, &self.subject, &self.reason]
+                    .iter()
+                    .any(|field| field.to_lowercase().contains(&text))
+            })
+    }
+}
+
+impl Store {
+    pub async fn quarantine_message(
+        &self,
+        mut message: QuarantinedMessage,
+        raw_message: &[u8],
+    ) -> crate::Result<u32> {
+        // Store a copy of the message before writing its metadata
+        message.id = self
+            .assign_document_id(QUARANTINE_ACCOUNT_ID, QUARANTINE_COLLECTION)
+            .await?;
+        message.nonce = rand::random();
+        message.size = raw_message.len() as u32;
+        self.put_blob(&QuarantinedMessage::blob_kind(message.id), raw_message)
+            .await?;
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(QUARANTINE_ACCOUNT_ID)
+            .with_collection(QUARANTINE_COLLECTION)
+            .create_document(message.id)
+            .op(Operation::Value {
+                class: ValueClass::Custom {
+                    bytes: quarantine_key(message.id),
+                },
+                set: message.serialize().into(),
+            });
+        self.write(batch.build()).await?;
+
+        Ok(message.id)
+    }
+
+    pub async fn quarantine_query(
+        &self,
+        filter: QuarantineFilter,
+        limit: usize,
+    ) -> crate::Result<Vec<QuarantinedMessage>> {
+        // Ids are reused, so all entries are read and sorted by creation date
+        let mut messages = self
+            .iterate(
+                Vec::new(),
+                CustomValueKey {
+                    value: quarantine_key(0),
+                },
+                CustomValueKey {
+                    value: quarantine_key(u32::MAX),
+                },
+                false,
+                true,
+                move |messages, key, value| {
+                    let id = key.deserialize_be_u32(std::mem::size_of::<u32>() + 1)?;
+                    let message = QuarantinedMessage::deserialize(id, value).ok_or_else(|| {
+                        crate::Error::InternalError(format!(
+                            "Failed to deserialize quarantined message {key:?}"
+                        ))
+                    })?;
+                    if message.matches(&filter) {
+                        messages.push(message);
+                    }
+                    Ok(true)
+                },
+            )
+            .await?;
+        messages.sort_unstable_by(|a, b| b.created.cmp(&a.created).then(b.id.cmp(&a.id)));
+        if limit > 0 {
+            messages.truncate(limit);
+        }
+        Ok(messages)
+    }
+
+    pub async fn get_quarantined(&self, id: u32) -> crate::Result<Option<QuarantinedMessage>> {
+        self.get_value::<Vec<u8>>(CustomValueKey {
+            value: quarantine_key(id),
+        })
+        .await?
+        .map(|value| {
+            QuarantinedMessage::deserialize(id, &value).ok_or_else(|| {
+                crate::Error::InternalError(format!(
+                    "Failed to deserialize quarantined message {id}"
+                ))
+            })
+        })
+        .transpose()
+    }
+
+    pub async fn get_quarantined_blob(&self, id: u32) -> crate::Result<Option<Vec<u8>>> {
+        self.get_blob(&QuarantinedMessage::blob_kind(id), 0..u32::MAX)
+            .await
+    }
+
+    pub async fn update_quarantined(&self, message: &QuarantinedMessage) -> crate::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.op(Operation::Value {
+            class: ValueClass::Custom {
+                bytes: quarantine_key(message.id),
+            },
+            set: message.serialize().into(),
+        });
+        self.write(batch.build()).await
+    }
+
+    /// Removes the released recipients from a quarantined message so that concurrent
+    /// releases can never queue the same recipient twice. The entry is deleted once no
+    /// recipients are left but its blob is kept until `delete_quarantined` is called.
+    /// When a nonce is provided, entries issued with a different nonce are not claimed.
+    /// Returns the entry as it was before the release and the released recipients.
+    pub async fn claim_quarantined(
+        &self,
+        id: u32,
+        nonce: Option<u64>,
+        rcpt: Option<&str>,
+    ) -> crate::Result<Option<(QuarantinedMessage, Vec<String>)>> {
+        self.modify_quarantined(id, |entry| {
+            if nonce.map_or(false, |nonce| nonce != entry.nonce) {
+                return None;
+            }
+            let released = match rcpt {
+                Some(rcpt) => vec![entry
+                    .recipients
+                    .iter()
+                    .find(|addr| addr.eq_ignore_ascii_case(rcpt))?
+                    .clone()],
+                None => entry.recipients.clone(),
+            };
+            entry
+                .recipients
+                .retain(|addr| !released.iter().any(|rcpt| rcpt == addr));
+            Some(released)
+        })
+        .await
+    }
+
+    pub async fn set_quarantined_notified(&self, id: u32) -> crate::Result<()> {
+        self.modify_quarantined(id, |entry| {
+            entry.notified = true;
+            Some(())
+        })
+        .await
+        .map(|_| ())
+    }
+
+    async fn modify_quarantined<T>(
+        &self,
+        id: u32,
+        f: impl Fn(&mut QuarantinedMessage) -> Option<T>,
+    ) -> crate::Result<Option<(QuarantinedMessage, T)>> {
+        let key = quarantine_key(id);
+        for _ in 0..MAX_UPDATE_ATTEMPTS {
+            let current = match self
+                .get_value::<HashedValue<Vec<u8>>>(CustomValueKey { value: key.clone() })
+                .await?
+            {
+                Some(current) => current,
+                None => return Ok(None),
+            };
+            let entry = QuarantinedMessage::deserialize(id, &current.inner).ok_or_else(|| {
+                crate::Error::InternalError(format!(
+                    "Failed to deserialize quarantined message {id}"
+                ))
+            })?;
+            let mut changed = entry.clone();
+            let result = match f(&mut changed) {
+                Some(result) => result,
+                None => return Ok(None),
+            };
+
+            let mut batch = BatchBuilder::new();
+            batch
+                .assert_value(ValueClass::Custom { bytes: key.clone() }, &current)
+                .op(Operation::Value {
+                    class: ValueClass::Custom { bytes: key.clone() },
+                    set: (!changed.recipients.is_empty()).then(|| changed.serialize()),
+                });
+            match self.write(batch.build()).await {
+                Ok(_) => return Ok(Some((entry, result))),
+                Err(crate::Error::AssertValueFailed) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(crate::Error::AssertValueFailed)
+    }
+
+    pub async fn delete_quarantined(&self, id: u32) -> crate::Result<()> {
+        self.delete_blob(&QuarantinedMessage::blob_kind(id)).await?;
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(QUARANTINE_ACCOUNT_ID)
+            .with_collection(QUARANTINE_COLLECTION)
+            .delete_document(id)
+            .op(Operation::Value {
+                class: ValueClass::Custom {
+                    bytes: quarantine_key(id),
+                },
+                set: None,
+            });
+        self.write(batch.build()).await
+    }
+
+    pub async fn purge_expired_quarantine(&self, now: u64) -> crate::Result<()> {
+        // Expired entries are removed one by one as their blobs need to be deleted as well
+        let expired_ids = self
+            .iterate(
+                Vec::new(),
+                CustomValueKey {
+                    value: quarantine_key(0),
+                },
+                CustomValueKey {
+                    value: quarantine_key(u32::MAX),
+                },
+                false,
+                true,
+                move |ids, key, value| {
+                    if value.deserialize_be_u64(0)? <= now {
+                        ids.push(key.deserialize_be_u32(std::mem::size_of::<u32>() + 1)?);
+                    }
+                    Ok(true)
+                },
+            )
+            .await?;
+
+        if !expired_ids.is_empty() {
+            tracing::debug!(
+                context = "quarantine",
+                event = "purge",
+                total = expired_ids.len(),
+                "Purging expired quarantined messages."
+            );
+
+            for id in expired_ids {
+                self.delete_quarantined(id).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn quarantine_key(id: u32) -> Vec<u8> {
+    KeySerializer::new(std::mem::size_of::<u32>() * 2 + 1)
+        .write(u32::MAX)
+        .write(QUARANTINE_NAMESPACE)
+        .write(id)
+        .finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QuarantinedMessage;
+
+    #[test]
+    fn quarantined_message_roundtrip() {
+        let message = QuarantinedMessage {
+            id: 3,
+            created: 1700000000,
+            expires: 1702592000,
+            nonce: 0x0123456789abcdef,
+            size: 2048,
+            notified: true,
+            source: "sieve".to_string(),
+            reason: "Suspicious attachment".to_string(),
+            return_path: "bill@example.net".to_string(),
+            recipients: vec![
+                "jane@example.org".to_string(),
+                "john@example.org".to_string(),
+            ],
+            from: "Bill <bill@example.net>".to_string(),
+            subject: "Invoice".to_string(),
+            remote_ip: "192.0.2.1".to_string(),
+        };
+        let bytes = message.serialize();
+        assert_eq!(QuarantinedMessage::deserialize(3, &bytes), Some(message));
+        assert_eq!(
+            QuarantinedMessage::deserialize(3, &bytes[..bytes.len() - 1]),
+            None
+        );
+    }
+}
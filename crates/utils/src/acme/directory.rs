@@ -0,0 +1,275 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{fmt::Display, time::Duration};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use p256::ecdsa::SigningKey;
+use reqwest::{
+    header::{ACCEPT, CONTENT_TYPE, LOCATION},
+    Client, Method, Response,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use super::jose::{key_authorization, sign};
+
+pub const LETS_ENCRYPT_PRODUCTION_DIRECTORY: &str =
+    "https://acme-v02.api.letsencrypt.org/directory";
+pub const LETS_ENCRYPT_STAGING_DIRECTORY: &str =
+    "https://acme-staging-v02.api.letsencrypt.org/directory";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Directory {
+    pub new_nonce: String,
+    pub new_account: String,
+    pub new_order: String,
+}
+
+pub struct Account {
+    pub client: Client,
+    pub directory: Directory,
+    pub key: SigningKey,
+    pub kid: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Status {
+    Pending,
+    Ready,
+    Processing,
+    Valid,
+    Invalid,
+    Revoked,
+    Deactivated,
+    Expired,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Order {
+    pub status: Status,
+    pub authorizations: Vec<String>,
+    pub finalize: String,
+    pub certificate: Option<String>,
+    pub error: Option<Problem>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum Identifier {
+    Dns(String),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Auth {
+    pub identifier: Identifier,
+    pub status: Status,
+    pub challenges: Vec<Challenge>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Challenge {
+    #[serde(rename = "type")]
+    pub typ: String,
+    pub url: String,
+    pub token: Option<String>,
+    pub error: Option<Problem>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub typ: Option<String>,
+    pub detail: Option<String>,
+}
+
+impl Account {
+    pub async fn create(
+        directory_url: &str,
+        contact: &[String],
+        key: SigningKey,
+    ) -> super::Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent(concat!("Stalwart/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(|err| format!("Failed to build HTTP client: {err}"))?;
+        let directory = client
+            .get(directory_url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| format!("Failed to fetch ACME directory {directory_url:?}: {err}"))?
+            .json::<Directory>()
+            .await
+            .map_err(|err| format!("Invalid ACME directory {directory_url:?}: {err}"))?;
+
+        let mut account = Account {
+            client,
+            directory,
+            key,
+            kid: String::new(),
+        };
+
+        // Registering an existing key returns the URL of its account
+        let payload = json!({
+            "termsOfServiceAgreed": true,
+            "contact": contact
+                .iter()
+                .map(|contact| if contact.starts_with("mailto:") {
+                    contact.to_string()
+                } else {
+                    format!("mailto:{contact}")
+                })
+                .collect::<Vec<_>>(),
+        })
+        .to_string();
+        let url = account.directory.new_account.clone();
+        let response = account.request(&url, &payload).await?;
+        account.kid = location(&response)?;
+
+        Ok(account)
+    }
+
+    pub async fn new_order(&self, domains: &[String]) -> super::Result<(String, Order)> {
+        let payload = json!({
+            "identifiers": domains
+                .iter()
+                .map(|domain| json!({ "type": "dns", "value": domain }))
+                .collect::<Vec<_>>(),
+        })
+        .to_string();
+        let response = self.request(&self.directory.new_order, &payload).await?;
+        let url = location(&response)?;
+
+        Ok((url, json_body(response).await?))
+    }
+
+    pub async fn order(&self, url: &str) -> super::Result<Order> {
+        json_body(self.request(url, "").await?).await
+    }
+
+    pub async fn auth(&self, url: &str) -> super::Result<Auth> {
+        json_body(self.request(url, "").await?).await
+    }
+
+    pub async fn challenge(&self, url: &str) -> super::Result<()> {
+        self.request(url, "{}").await.map(|_| ())
+    }
+
+    pub async fn finalize(&self, url: &str, csr: Vec<u8>) -> super::Result<Order> {
+        let payload = json!({ "csr": URL_SAFE_NO_PAD.encode(csr) }).to_string();
+        json_body(self.request(url, &payload).await?).await
+    }
+
+    pub async fn certificate(&self, url: &str) -> super::Result<String> {
+        self.request(url, "")
+            .await?
+            .text()
+            .await
+            .map_err(|err| format!("Failed to download certificate: {err}"))
+    }
+
+    pub fn key_authorization(&self, token: &str) -> String {
+        key_authorization(&self.key, token)
+    }
+
+    async fn request(&self, url: &str, payload: &str) -> super::Result<Response> {
+        let body = sign(
+            &self.key,
+            if !self.kid.is_empty() {
+                Some(self.kid.as_str())
+            } else {
+                None
+            },
+            self.nonce().await?,
+            url,
+            payload,
+        );
+        let response = self
+            .client
+            .post(url)
+            .header(CONTENT_TYPE, "application/jose+json")
+            .header(
+                ACCEPT,
+                "application/pem-certificate-chain, application/json",
+            )
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| format!("ACME request to {url:?} failed: {err}"))?;
+
+        if response.status().is_success() {
+            Ok(response)
+        } else {
+            let status = response.status();
+            Err(match response.json::<Problem>().await {
+                Ok(problem) => format!("ACME request to {url:?} failed: {problem}"),
+                Err(_) => format!("ACME request to {url:?} failed with status {status}"),
+            })
+        }
+    }
+
+    async fn nonce(&self) -> super::Result<String> {
+        self.client
+            .request(Method::HEAD, &self.directory.new_nonce)
+            .send()
+            .await
+            .map_err(|err| format!("Failed to obtain ACME nonce: {err}"))?
+            .headers()
+            .get("replay-nonce")
+            .and_then(|nonce| nonce.to_str().ok())
+            .map(|nonce| nonce.to_string())
+            .ok_or_else(|| "ACME server did not return a nonce.".to_string())
+    }
+}
+
+fn location(response: &Response) -> super::Result<String> {
+    response
+        .headers()
+        .get(LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .map(|location| location.to_string())
+        .ok_or_else(|| "ACME server did not return a location header.".to_string())
+}
+
+async fn json_body<T: serde::de::DeserializeOwned>(response: Response) -> super::Result<T> {
+    response
+        .json::<T>()
+        .await
+        .map_err(|err| format!("Invalid ACME server response: {err}"))
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.typ, &self.detail) {
+            (Some(typ), Some(detail)) => write!(f, "{detail} ({typ})"),
+            (None, Some(detail)) => f.write_str(detail),
+            (Some(typ), None) => f.write_str(typ),
+            (None, None) => f.write_str("unknown error"),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use p256::{
+    ecdsa::{signature::Signer, Signature, SigningKey},
+    elliptic_curve::sec1::ToEncodedPoint,
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+pub fn sign(
+    key: &SigningKey,
+    kid: Option<&str>,
+    nonce: String,
+    url: &str,
+    payload: &str,
+) -> String {
+    let protected = if let Some(kid) = kid {
+        json!({
+            "alg": "ES256",
+            "kid": kid,
+            "nonce": nonce,
+            "url": url,
+        })
+    } else {
+        json!({
+            "alg": "ES256",
+            "jwk": jwk(key),
+            "nonce": nonce,
+            "url": url,
+        })
+    };
+    let protected = URL_SAFE_NO_PAD.encode(protected.to_string());
+    let payload = URL_SAFE_NO_PAD.encode(payload);
+    let signature: Signature = key.sign(format!("{protected}.{payload}").as_bytes());
+
+    json!({
+        "protected": protected,
+        "payload": payload,
+        "signature": URL_SAFE_NO_PAD.encode(signature.to_bytes()),
+    })
+    .to_string()
+}
+
+pub fn jwk(key: &SigningKey) -> serde_json::Value {
+    let point = key.verifying_key().to_encoded_point(false);
+    json!({
+        "crv": "P-256",
+        "kty": "EC",
+        "x": URL_SAFE_NO_PAD.encode(point.x().unwrap()),
+        "y": URL_SAFE_NO_PAD.encode(point.y().unwrap()),
+    })
+}
+
+pub fn key_authorization(key: &SigningKey, token: &str) -> String {
+    // The JWK thumbprint is computed over the required members in lexicographic order (RFC 7638)
+    let point = key.verifying_key().to_encoded_point(false);
+    let thumbprint = Sha256::digest(format!(
+        "{{\"crv\":\"P-256\",\"kty\":\"EC\",\"x\":\"{}\",\"y\":\"{}\"}}",
+        URL_SAFE_NO_PAD.encode(point.x().unwrap()),
+        URL_SAFE_NO_PAD.encode(point.y().unwrap()),
+    ));
+
+    format!("{token}.{}", URL_SAFE_NO_PAD.encode(thumbprint))
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::ecdsa::SigningKey;
+
+    #[test]
+    fn key_authorization() {
+        let key = SigningKey::from_slice(&[1u8; 32]).unwrap();
+        let jwk = super::jwk(&key);
+        let key_auth = super::key_authorization(&key, "token");
+
+        assert!(key_auth.starts_with("token."));
+        assert_eq!(key_auth.len(), "token.".len() + 43);
+        assert_eq!(jwk["kty"], "EC");
+        assert_eq!(jwk["crv"], "P-256");
+    }
+}
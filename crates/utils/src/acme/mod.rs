@@ -0,0 +1,272 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod directory;
+pub mod jose;
+pub mod order;
+pub mod resolver;
+
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use rustls::{server::Acceptor, sign::CertifiedKey, ServerConfig};
+use tokio::net::TcpStream;
+use tokio_rustls::{server::TlsStream, LazyConfigAcceptor};
+
+use crate::config::{
+    utils::{AsKey, ParseValue},
+    Config,
+};
+
+use self::{
+    directory::LETS_ENCRYPT_PRODUCTION_DIRECTORY,
+    resolver::{challenge_config, is_tls_alpn_challenge},
+};
+
+pub type Result<T> = std::result::Result<T, String>;
+
+const RENEW_CHECK_INTERVAL: Duration = Duration::from_secs(86400);
+const RETRY_MIN_DELAY: Duration = Duration::from_secs(60);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(6 * 3600);
+
+pub struct AcmeManager {
+    pub id: String,
+    pub directory_url: String,
+    pub domains: Vec<String>,
+    pub contact: Vec<String>,
+    pub challenge: ChallengeType,
+    pub renew_before: Duration,
+    lease_id: u64,
+    cert: RwLock<Option<Arc<CertifiedKey>>>,
+    cache: RwLock<Option<Arc<dyn AcmeCache>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeType {
+    Http01,
+    TlsAlpn01,
+}
+
+/// Storage shared by all cluster nodes, used for account keys, issued
+/// certificates, pending challenge responses and the ordering lease.
+#[async_trait::async_trait]
+pub trait AcmeCache: Sync + Send {
+    async fn read(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    async fn write(&self, key: &str, value: Vec<u8>) -> Result<()>;
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Acquires or extends the lease on `key`, returns `false` while another
+    /// holder owns an unexpired lease.
+    async fn lock(&self, key: &str, holder: u64, lease: Duration) -> Result<bool>;
+    async fn unlock(&self, key: &str, holder: u64) -> Result<()>;
+}
+
+pub struct AcmeAcceptor {
+    pub config: Arc<ServerConfig>,
+    pub manager: Arc<AcmeManager>,
+}
+
+pub fn http_challenge_key(token: &str) -> String {
+    format!("acme:http-01:{token}")
+}
+
+pub fn tls_alpn_challenge_key(domain: &str) -> String {
+    format!("acme:tls-alpn-01:{domain}")
+}
+
+impl std::fmt::Debug for AcmeManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AcmeManager")
+            .field("id", &self.id)
+            .field("directory_url", &self.directory_url)
+            .field("domains", &self.domains)
+            .field("challenge", &self.challenge)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AcmeManager {
+    pub fn new(
+        id: String,
+        directory_url: String,
+        domains: Vec<String>,
+        contact: Vec<String>,
+        challenge: ChallengeType,
+        renew_before: Duration,
+    ) -> Self {
+        AcmeManager {
+            id,
+            directory_url,
+            domains,
+            contact,
+            challenge,
+            renew_before,
+            lease_id: rand::random(),
+            cert: RwLock::new(None),
+            cache: RwLock::new(None),
+        }
+    }
+
+    pub fn certificate(&self) -> Option<Arc<CertifiedKey>> {
+        self.cert.read().ok().and_then(|cert| cert.clone())
+    }
+
+    pub fn has_domain(&self, name: &str) -> bool {
+        self.domains.iter().any(|d| d.eq_ignore_ascii_case(name))
+    }
+
+    pub fn spawn(self: Arc<Self>, cache: Arc<dyn AcmeCache>) {
+        if let Ok(mut current) = self.cache.write() {
+            *current = Some(cache.clone());
+        }
+
+        tokio::spawn(async move {
+            let mut retry_delay = RETRY_MIN_DELAY;
+
+            loop {
+                let wait = match self.process(cache.as_ref()).await {
+                    Ok(renew_in) => {
+                        retry_delay = RETRY_MIN_DELAY;
+                        tracing::debug!(
+                            context = "acme",
+                            event = "renewal",
+                            id = self.id,
+                            "Certificate renewal due in {} seconds.",
+                            renew_in.as_secs()
+                        );
+                        std::cmp::min(renew_in, RENEW_CHECK_INTERVAL)
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            context = "acme",
+                            event = "error",
+                            id = self.id,
+                            reason = err,
+                            "Failed to obtain certificate, retrying in {} seconds.",
+                            retry_delay.as_secs()
+                        );
+                        let wait = retry_delay;
+                        retry_delay = std::cmp::min(retry_delay * 2, RETRY_MAX_DELAY);
+                        wait
+                    }
+                };
+
+                tokio::time::sleep(wait).await;
+            }
+        });
+    }
+}
+
+impl AcmeAcceptor {
+    pub async fn accept(&self, stream: TcpStream) -> std::io::Result<TlsStream<TcpStream>> {
+        let handshake = LazyConfigAcceptor::new(Acceptor::default(), stream).await?;
+        if is_tls_alpn_challenge(&handshake.client_hello()) {
+            // The challenge may have been published by any node in the cluster
+            let domain = handshake
+                .client_hello()
+                .server_name()
+                .map(|name| name.to_lowercase());
+            let cert = match &domain {
+                Some(domain) => self.manager.tls_alpn_certificate(domain).await,
+                None => None,
+            };
+
+            // Validation connections are closed once the handshake completes
+            if let Some(cert) = cert {
+                handshake
+                    .into_stream(Arc::new(challenge_config(cert)))
+                    .await?;
+                tracing::info!(
+                    context = "acme",
+                    event = "challenge",
+                    domain = ?domain,
+                    "Answered TLS-ALPN-01 challenge."
+                );
+            } else {
+                tracing::debug!(
+                    context = "acme",
+                    event = "challenge",
+                    domain = ?domain,
+                    "No pending TLS-ALPN-01 challenge found."
+                );
+            }
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "TLS-ALPN-01 challenge completed",
+            ))
+        } else {
+            handshake.into_stream(self.config.clone()).await
+        }
+    }
+}
+
+impl Config {
+    pub fn parse_acme_managers(&self) -> crate::config::Result<Vec<Arc<AcmeManager>>> {
+        let mut managers = Vec::new();
+        for id in self.sub_keys("acme") {
+            let domains = self
+                .values(("acme", id, "domains"))
+                .map(|(_, domain)| domain.trim().to_lowercase())
+                .collect::<Vec<_>>();
+            if domains.is_empty() {
+                return Err(format!("No domains defined for ACME provider {id:?}."));
+            }
+            let contact = self
+                .values(("acme", id, "contact"))
+                .map(|(_, contact)| contact.to_string())
+                .collect::<Vec<_>>();
+            if contact.is_empty() {
+                return Err(format!("No contact defined for ACME provider {id:?}."));
+            }
+
+            managers.push(Arc::new(AcmeManager::new(
+                id.to_string(),
+                self.value(("acme", id, "directory"))
+                    .unwrap_or(LETS_ENCRYPT_PRODUCTION_DIRECTORY)
+                    .to_string(),
+                domains,
+                contact,
+                self.property_or_static(("acme", id, "challenge"), "tls-alpn-01")?,
+                self.property_or_static(("acme", id, "renew-before"), "30d")?,
+            )));
+        }
+
+        Ok(managers)
+    }
+}
+
+impl ParseValue for ChallengeType {
+    fn parse_value(key: impl AsKey, value: &str) -> crate::config::Result<Self> {
+        match value {
+            "http-01" => Ok(ChallengeType::Http01),
+            "tls-alpn-01" => Ok(ChallengeType::TlsAlpn01),
+            _ => Err(format!(
+                "Invalid ACME challenge type {:?} for property {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
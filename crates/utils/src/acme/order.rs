@@ -0,0 +1,429 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    io::Cursor,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use p256::ecdsa::SigningKey;
+use rand::rngs::OsRng;
+use rcgen::{CertificateParams, CustomExtension, DistinguishedName, PKCS_ECDSA_P256_SHA256};
+use rustls::{
+    sign::{any_ecdsa_type, any_supported_type, CertifiedKey},
+    Certificate, PrivateKey,
+};
+use rustls_pemfile::{read_one, Item};
+use sha2::{Digest, Sha256};
+
+use super::{
+    directory::{Account, Identifier, Status},
+    http_challenge_key, tls_alpn_challenge_key, AcmeCache, AcmeManager, ChallengeType,
+};
+
+const MAX_POLL_ATTEMPTS: u32 = 10;
+const MIN_RENEW_INTERVAL: u64 = 3600;
+const ORDER_LEASE: Duration = Duration::from_secs(3600);
+const LEASE_RETRY_INTERVAL: Duration = Duration::from_secs(300);
+
+impl AcmeManager {
+    /// Installs the cached certificate or orders a new one when it is due for renewal,
+    /// returns the time left until the next renewal.
+    pub async fn process(&self, cache: &dyn AcmeCache) -> super::Result<Duration> {
+        let cert_key = self.cert_key();
+        if let Some(renew_in) = self.install_cached(cache, &cert_key).await? {
+            return Ok(renew_in);
+        }
+
+        // Only the lease holder orders, other nodes pick up the certificate from the store
+        let lock_key = self.lock_key();
+        if !cache.lock(&lock_key, self.lease_id, ORDER_LEASE).await? {
+            tracing::debug!(
+                context = "acme",
+                event = "locked",
+                id = self.id,
+                "Certificate is being ordered by another node."
+            );
+            return Ok(LEASE_RETRY_INTERVAL);
+        }
+
+        let result = match self.install_cached(cache, &cert_key).await {
+            Ok(Some(renew_in)) => Ok(renew_in),
+            Ok(None) => self.order_and_install(cache, &cert_key).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = cache.unlock(&lock_key, self.lease_id).await {
+            tracing::warn!(
+                context = "acme",
+                event = "error",
+                id = self.id,
+                reason = err,
+                "Failed to release ACME lease."
+            );
+        }
+
+        result
+    }
+
+    /// Installs the cached certificate, returns the time left until its renewal
+    /// or `None` when it is missing or due.
+    async fn install_cached(
+        &self,
+        cache: &dyn AcmeCache,
+        cert_key: &str,
+    ) -> super::Result<Option<Duration>> {
+        if let Some(pem) = cache.read(cert_key).await? {
+            match parse_certificate(&pem) {
+                Ok((cert, expires)) => {
+                    let now = now();
+                    self.set_certificate(cert);
+                    let renew_at = expires.saturating_sub(self.renew_before.as_secs());
+                    if renew_at > now {
+                        return Ok(Some(Duration::from_secs(renew_at - now)));
+                    }
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        context = "acme",
+                        event = "error",
+                        id = self.id,
+                        reason = err,
+                        "Ignoring invalid cached certificate."
+                    );
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn order_and_install(
+        &self,
+        cache: &dyn AcmeCache,
+        cert_key: &str,
+    ) -> super::Result<Duration> {
+        tracing::info!(
+            context = "acme",
+            event = "order",
+            id = self.id,
+            domains = ?self.domains,
+            "Ordering certificate."
+        );
+
+        let pem = self.order(cache).await?;
+        let (cert, expires) = parse_certificate(&pem)?;
+        cache.write(cert_key, pem).await?;
+        self.set_certificate(cert);
+
+        tracing::info!(
+            context = "acme",
+            event = "success",
+            id = self.id,
+            domains = ?self.domains,
+            "Certificate obtained."
+        );
+
+        Ok(Duration::from_secs(std::cmp::max(
+            expires
+                .saturating_sub(self.renew_before.as_secs())
+                .saturating_sub(now()),
+            MIN_RENEW_INTERVAL,
+        )))
+    }
+
+    async fn order(&self, cache: &dyn AcmeCache) -> super::Result<Vec<u8>> {
+        let account = Account::create(
+            &self.directory_url,
+            &self.contact,
+            self.account_key(cache).await?,
+        )
+        .await?;
+        let (order_url, mut order) = account.new_order(&self.domains).await?;
+        let mut private_key_pem = None;
+        let mut delay = Duration::from_secs(1);
+
+        for _ in 0..MAX_POLL_ATTEMPTS {
+            match order.status {
+                Status::Pending => {
+                    for auth_url in &order.authorizations {
+                        self.authorize(&account, cache, auth_url).await?;
+                    }
+                }
+                Status::Ready => {
+                    let mut params = CertificateParams::new(self.domains.clone());
+                    params.alg = &PKCS_ECDSA_P256_SHA256;
+                    params.distinguished_name = DistinguishedName::new();
+                    let cert = rcgen::Certificate::from_params(params)
+                        .map_err(|err| format!("Failed to generate certificate key: {err}"))?;
+                    let csr = cert
+                        .serialize_request_der()
+                        .map_err(|err| format!("Failed to generate CSR: {err}"))?;
+                    private_key_pem = cert.serialize_private_key_pem().into();
+                    order = account.finalize(&order.finalize, csr).await?;
+                    continue;
+                }
+                Status::Processing => {
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                Status::Valid => {
+                    let (private_key_pem, certificate_url) =
+                        match (private_key_pem, &order.certificate) {
+                            (Some(private_key_pem), Some(certificate_url)) => {
+                                (private_key_pem, certificate_url)
+                            }
+                            _ => return Err("ACME order is valid but was not finalized.".into()),
+                        };
+                    let cert_pem = account.certificate(certificate_url).await?;
+                    return Ok(format!("{private_key_pem}{cert_pem}").into_bytes());
+                }
+                status => {
+                    return Err(format!(
+                        "ACME order failed with status {status:?}: {}",
+                        order.error.map(|err| err.to_string()).unwrap_or_default()
+                    ));
+                }
+            }
+            order = account.order(&order_url).await?;
+        }
+
+        Err("Timed out waiting for ACME order.".into())
+    }
+
+    async fn authorize(
+        &self,
+        account: &Account,
+        cache: &dyn AcmeCache,
+        url: &str,
+    ) -> super::Result<()> {
+        let auth = account.auth(url).await?;
+        let Identifier::Dns(domain) = auth.identifier;
+        match auth.status {
+            Status::Pending => (),
+            Status::Valid => return Ok(()),
+            status => {
+                return Err(format!(
+                    "Authorization for {domain:?} failed with status {status:?}."
+                ))
+            }
+        }
+
+        let challenge_type = match self.challenge {
+            ChallengeType::Http01 => "http-01",
+            ChallengeType::TlsAlpn01 => "tls-alpn-01",
+        };
+        let challenge = auth
+            .challenges
+            .iter()
+            .find(|challenge| challenge.typ == challenge_type)
+            .ok_or_else(|| {
+                format!("ACME server does not offer {challenge_type} challenges for {domain:?}.")
+            })?;
+        let token = challenge
+            .token
+            .as_deref()
+            .ok_or_else(|| format!("Missing token in challenge for {domain:?}."))?;
+        let key_authorization = account.key_authorization(token);
+
+        // Publish the challenge response in the store, validation requests
+        // may reach any node in the cluster
+        let challenge_key = match self.challenge {
+            ChallengeType::Http01 => http_challenge_key(token),
+            ChallengeType::TlsAlpn01 => tls_alpn_challenge_key(&domain),
+        };
+        cache
+            .write(&challenge_key, key_authorization.into_bytes())
+            .await?;
+
+        let result = self.validate(account, url, &challenge.url).await;
+
+        // Remove the challenge response
+        cache.delete(&challenge_key).await?;
+
+        result.map_err(|err| format!("Authorization for {domain:?} failed: {err}"))
+    }
+
+    async fn validate(
+        &self,
+        account: &Account,
+        auth_url: &str,
+        challenge_url: &str,
+    ) -> super::Result<()> {
+        account.challenge(challenge_url).await?;
+
+        let mut delay = Duration::from_secs(1);
+        for _ in 0..MAX_POLL_ATTEMPTS {
+            tokio::time::sleep(delay).await;
+            let auth = account.auth(auth_url).await?;
+            match auth.status {
+                Status::Pending => {
+                    delay *= 2;
+                }
+                Status::Valid => return Ok(()),
+                status => {
+                    return Err(auth
+                        .challenges
+                        .into_iter()
+                        .find_map(|challenge| challenge.error)
+                        .map(|err| err.to_string())
+                        .unwrap_or_else(|| format!("status {status:?}")));
+                }
+            }
+        }
+
+        Err("timed out".into())
+    }
+
+    async fn account_key(&self, cache: &dyn AcmeCache) -> super::Result<SigningKey> {
+        let key = format!("acme:account:{}", self.directory_url);
+        if let Some(secret) = cache.read(&key).await? {
+            SigningKey::from_slice(&secret)
+                .map_err(|_| "Corrupted ACME account key found in store.".to_string())
+        } else {
+            let signing_key = SigningKey::random(&mut OsRng);
+            cache
+                .write(&key, signing_key.to_bytes().to_vec())
+                .await
+                .map(|_| signing_key)
+        }
+    }
+
+    /// Returns the TLS-ALPN-01 challenge certificate for a domain with a pending
+    /// authorization, as published by whichever node is placing the order.
+    pub async fn tls_alpn_certificate(&self, domain: &str) -> Option<Arc<CertifiedKey>> {
+        if !self.has_domain(domain) {
+            return None;
+        }
+        let cache = self.cache.read().ok()?.clone()?;
+        let key_authorization = match cache.read(&tls_alpn_challenge_key(domain)).await {
+            Ok(Some(key_authorization)) => String::from_utf8(key_authorization).ok()?,
+            Ok(None) => return None,
+            Err(err) => {
+                tracing::warn!(
+                    context = "acme",
+                    event = "error",
+                    id = self.id,
+                    reason = err,
+                    "Failed to read TLS-ALPN-01 challenge."
+                );
+                return None;
+            }
+        };
+
+        match challenge_certificate(domain, &key_authorization) {
+            Ok(cert) => Some(Arc::new(cert)),
+            Err(err) => {
+                tracing::warn!(
+                    context = "acme",
+                    event = "error",
+                    id = self.id,
+                    reason = err,
+                    "Failed to build TLS-ALPN-01 challenge certificate."
+                );
+                None
+            }
+        }
+    }
+
+    fn cert_key(&self) -> String {
+        format!("acme:cert:{}", self.domains.join(","))
+    }
+
+    fn lock_key(&self) -> String {
+        format!("acme:lock:{}", self.domains.join(","))
+    }
+
+    fn set_certificate(&self, cert: CertifiedKey) {
+        if let Ok(mut current) = self.cert.write() {
+            *current = Some(Arc::new(cert));
+        }
+    }
+}
+
+/// Builds the self-signed certificate used to answer TLS-ALPN-01 challenges (RFC 8737).
+fn challenge_certificate(domain: &str, key_authorization: &str) -> super::Result<CertifiedKey> {
+    let mut params = CertificateParams::new(vec![domain.to_string()]);
+    params.alg = &PKCS_ECDSA_P256_SHA256;
+    params.custom_extensions = vec![CustomExtension::new_acme_identifier(
+        Sha256::digest(key_authorization).as_slice(),
+    )];
+    let cert = rcgen::Certificate::from_params(params)
+        .map_err(|err| format!("Failed to generate challenge certificate: {err}"))?;
+
+    Ok(CertifiedKey {
+        cert: vec![Certificate(cert.serialize_der().map_err(|err| {
+            format!("Failed to serialize challenge certificate: {err}")
+        })?)],
+        key: any_ecdsa_type(&PrivateKey(cert.serialize_private_key_der()))
+            .map_err(|err| format!("Failed to sign challenge certificate: {err}"))?,
+        ocsp: None,
+        sct_list: None,
+    })
+}
+
+/// Parses a PEM private key followed by its certificate chain, returns the
+/// certified key and the expiration timestamp of the leaf certificate.
+pub fn parse_certificate(pem: &[u8]) -> super::Result<(CertifiedKey, u64)> {
+    let mut reader = Cursor::new(pem);
+    let mut certs = Vec::new();
+    let mut private_key = None;
+
+    while let Some(item) =
+        read_one(&mut reader).map_err(|err| format!("Failed to read certificate: {err}"))?
+    {
+        match item {
+            Item::X509Certificate(cert) => certs.push(Certificate(cert)),
+            Item::PKCS8Key(key) | Item::ECKey(key) | Item::RSAKey(key) => {
+                private_key = PrivateKey(key).into();
+            }
+            _ => (),
+        }
+    }
+
+    let private_key = private_key.ok_or("No private key found in certificate.")?;
+    let expires =
+        x509_parser::parse_x509_certificate(&certs.first().ok_or("No certificates found.")?.0)
+            .map_err(|err| format!("Failed to parse certificate: {err}"))?
+            .1
+            .validity()
+            .not_after
+            .timestamp();
+
+    Ok((
+        CertifiedKey {
+            cert: certs,
+            key: any_supported_type(&private_key)
+                .map_err(|err| format!("Failed to sign certificate: {err}"))?,
+            ocsp: None,
+            sct_list: None,
+        },
+        expires as u64,
+    ))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
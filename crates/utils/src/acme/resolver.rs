@@ -0,0 +1,56 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use rustls::{
+    server::{ClientHello, NoClientAuth, ResolvesServerCert},
+    sign::CertifiedKey,
+    ServerConfig,
+};
+
+pub const ACME_TLS_ALPN_NAME: &[u8] = b"acme-tls/1";
+
+pub struct ChallengeResolver {
+    pub cert: Arc<CertifiedKey>,
+}
+
+impl ResolvesServerCert for ChallengeResolver {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.cert.clone().into()
+    }
+}
+
+/// Builds the configuration used to answer a single TLS-ALPN-01 validation request.
+pub fn challenge_config(cert: Arc<CertifiedKey>) -> ServerConfig {
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(NoClientAuth::boxed())
+        .with_cert_resolver(Arc::new(ChallengeResolver { cert }));
+    config.alpn_protocols = vec![ACME_TLS_ALPN_NAME.to_vec()];
+    config
+}
+
+pub fn is_tls_alpn_challenge(hello: &ClientHello) -> bool {
+    hello.alpn().into_iter().flatten().eq([ACME_TLS_ALPN_NAME])
+}
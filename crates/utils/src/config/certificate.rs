@@ -31,6 +31,8 @@ use rustls::{
 };
 use rustls_pemfile::{certs, read_one, Item};
 
+use crate::acme::AcmeManager;
+
 use super::Config;
 
 pub static TLS13_VERSION: &[&SupportedProtocolVersion] = &[&TLS13];
@@ -39,14 +41,25 @@ pub static TLS12_VERSION: &[&SupportedProtocolVersion] = &[&TLS12];
 pub struct CertificateResolver {
     pub resolver: Option<ResolvesServerCertUsingSni>,
     pub default_cert: Option<Arc<CertifiedKey>>,
+    pub acme: Option<Arc<AcmeManager>>,
 }
 
 impl ResolvesServerCert for CertificateResolver {
     fn resolve(&self, hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        // Certificates obtained through ACME take precedence for their domains
+        if let (Some(acme), Some(name)) = (&self.acme, hello.server_name()) {
+            if acme.has_domain(name) {
+                if let Some(cert) = acme.certificate() {
+                    return Some(cert);
+                }
+            }
+        }
+
         self.resolver
             .as_ref()
             .and_then(|r| r.resolve(hello))
             .or_else(|| self.default_cert.clone())
+            .or_else(|| self.acme.as_ref().and_then(|acme| acme.certificate()))
     }
 }
 
@@ -36,7 +36,7 @@ use rustls::{
 };
 use tokio::net::TcpSocket;
 
-use crate::UnwrapFailure;
+use crate::{acme::AcmeManager, UnwrapFailure};
 
 use super::{
     certificate::{CertificateResolver, TLS12_VERSION, TLS13_VERSION},
@@ -46,9 +46,10 @@ use super::{
 
 impl Config {
     pub fn parse_servers(&self) -> super::Result<Servers> {
+        let acme_managers = self.parse_acme_managers()?;
         let mut servers: Vec<Server> = Vec::new();
         for (internal_id, id) in self.sub_keys("server.listener").enumerate() {
-            let mut server = self.parse_server(id)?;
+            let mut server = self.parse_server(id, &acme_managers)?;
             if !servers.iter().any(|s| s.id == server.id) {
                 server.internal_id = internal_id as u16;
                 servers.push(server);
@@ -58,15 +59,18 @@ impl Config {
         }
 
         if !servers.is_empty() {
-            Ok(Servers { inner: servers })
+            Ok(Servers {
+                inner: servers,
+                acme_managers,
+            })
         } else {
             Err("No server directives found in config file.".to_string())
         }
     }
 
-    fn parse_server(&self, id: &str) -> super::Result<Server> {
+    fn parse_server(&self, id: &str, acme_managers: &[Arc<AcmeManager>]) -> super::Result<Server> {
         // Build TLS config
-        let (tls, tls_implicit) = if self
+        let (tls, tls_implicit, acme) = if self
             .property_or_default(("server.listener", id, "tls.enable"), "server.tls.enable")?
             .unwrap_or(false)
         {
@@ -96,15 +100,37 @@ impl Config {
                 ciphers.push(protocol.parse_key(key)?);
             }
 
+            // Obtain ACME manager
+            let acme = if let Some(acme_id) =
+                self.value_or_default(("server.listener", id, "tls.acme"), "server.tls.acme")
+            {
+                acme_managers
+                    .iter()
+                    .find(|manager| manager.id == acme_id)
+                    .cloned()
+                    .ok_or_else(|| format!("Undefined ACME id {acme_id:?} for listener {id:?}."))?
+                    .into()
+            } else {
+                None
+            };
+
             // Obtain default certificate
-            let cert_id = self
-                .value_or_default(
-                    ("server.listener", id, "tls.certificate"),
-                    "server.tls.certificate",
-                )
-                .ok_or_else(|| format!("Undefined certificate id for listener {id:?}."))?;
-            let cert = self.rustls_certificate(cert_id)?;
-            let pki = self.rustls_private_key(cert_id)?;
+            let cert_id = self.value_or_default(
+                ("server.listener", id, "tls.certificate"),
+                "server.tls.certificate",
+            );
+            let default_cert = match cert_id {
+                Some(cert_id) => Some(Arc::new(CertifiedKey {
+                    cert: self.rustls_certificate(cert_id)?,
+                    key: any_supported_type(&self.rustls_private_key(cert_id)?).map_err(|err| {
+                        format!("Failed to sign certificate id {cert_id:?}: {err}")
+                    })?,
+                    ocsp: None,
+                    sct_list: None,
+                })),
+                None if acme.is_some() => None,
+                None => return Err(format!("Undefined certificate id for listener {id:?}.")),
+            };
 
             // Add SNI certificates
             let mut resolver = ResolvesServerCertUsingSni::new();
@@ -118,7 +144,7 @@ impl Config {
                         .add(
                             value,
                             match self.value((prefix, "certificate")) {
-                                Some(sni_cert_id) if sni_cert_id != cert_id => CertifiedKey {
+                                Some(sni_cert_id) if Some(sni_cert_id) != cert_id => CertifiedKey {
                                     cert: self.rustls_certificate(sni_cert_id)?,
                                     key: any_supported_type(&self.rustls_private_key(sni_cert_id)?)
                                         .map_err(|err| {
@@ -129,17 +155,12 @@ impl Config {
                                     ocsp: None,
                                     sct_list: None,
                                 },
-                                _ => CertifiedKey {
-                                    cert: cert.clone(),
-                                    key:
-                                        any_supported_type(&pki).map_err(|err| {
-                                            format!(
-                                                "Failed to sign SNI certificate for {key:?}: {err}",
-                                            )
-                                        })?,
-                                    ocsp: None,
-                                    sct_list: None,
-                                },
+                                _ => default_cert
+                                    .as_deref()
+                                    .ok_or_else(|| {
+                                        format!("Undefined SNI certificate id for {key:?}.")
+                                    })?
+                                    .clone(),
                             },
                         )
                         .map_err(|err| {
@@ -148,15 +169,6 @@ impl Config {
                 }
             }
 
-            // Add default certificate
-            let default_cert = Some(Arc::new(CertifiedKey {
-                cert,
-                key: any_supported_type(&pki)
-                    .map_err(|err| format!("Failed to sign certificate id {cert_id:?}: {err}"))?,
-                ocsp: None,
-                sct_list: None,
-            }));
-
             // Build server config
             let mut config = ServerConfig::builder()
                 .with_cipher_suites(if !ciphers.is_empty() {
@@ -177,6 +189,7 @@ impl Config {
                 .with_cert_resolver(Arc::new(CertificateResolver {
                     resolver: if has_sni { resolver.into() } else { None },
                     default_cert,
+                    acme: acme.clone(),
                 }));
 
             //config.key_log = Arc::new(KeyLogger::default());
@@ -193,9 +206,10 @@ impl Config {
                     "server.tls.implicit",
                 )?
                 .unwrap_or(true),
+                acme,
             )
         } else {
-            (None, false)
+            (None, false, None)
         };
 
         // Build listeners
@@ -285,7 +299,10 @@ impl Config {
                     .value_or_default(("server.listener", id, "url"), "server.url")
                     .failed(&format!("No 'url' directive found for listener {id:?}"))
                     .to_string(),
-                ServerProtocol::Imap | ServerProtocol::Http | ServerProtocol::ManageSieve => self
+                ServerProtocol::Imap
+                | ServerProtocol::Http
+                | ServerProtocol::ManageSieve
+                | ServerProtocol::Pop3 => self
                     .value_or_default(("server.listener", id, "url"), "server.url")
                     .unwrap_or_default()
                     .to_string(),
@@ -300,6 +317,7 @@ impl Config {
             listeners,
             tls,
             tls_implicit,
+            acme,
         })
     }
 }
@@ -318,6 +336,8 @@ impl ParseValue for ServerProtocol {
             Ok(Self::Http)
         } else if value.eq_ignore_ascii_case("managesieve") {
             Ok(Self::ManageSieve)
+        } else if value.eq_ignore_ascii_case("pop3") {
+            Ok(Self::Pop3)
         } else {
             Err(format!(
                 "Invalid server protocol type {:?} for property {:?}.",
@@ -32,13 +32,14 @@ use std::{
     collections::BTreeMap,
     fmt::Display,
     net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
     time::Duration,
 };
 
 use rustls::ServerConfig;
 use tokio::net::TcpSocket;
 
-use crate::{failed, UnwrapFailure};
+use crate::{acme::AcmeManager, failed, UnwrapFailure};
 
 use self::utils::ParseValue;
 
@@ -58,10 +59,12 @@ pub struct Server {
     pub tls: Option<ServerConfig>,
     pub tls_implicit: bool,
     pub max_connections: u64,
+    pub acme: Option<Arc<AcmeManager>>,
 }
 
 pub struct Servers {
     pub inner: Vec<Server>,
+    pub acme_managers: Vec<Arc<AcmeManager>>,
 }
 
 #[derive(Debug)]
@@ -81,6 +84,7 @@ pub enum ServerProtocol {
     Imap,
     Http,
     ManageSieve,
+    Pop3,
 }
 
 #[derive(Debug, Clone)]
@@ -130,6 +134,7 @@ impl Display for ServerProtocol {
             ServerProtocol::Imap => write!(f, "imap"),
             ServerProtocol::Http => write!(f, "http"),
             ServerProtocol::ManageSieve => write!(f, "managesieve"),
+            ServerProtocol::Pop3 => write!(f, "pop3"),
         }
     }
 }
@@ -31,6 +31,7 @@ use tokio_rustls::{server::TlsStream, TlsAcceptor};
 use tracing::Span;
 
 use crate::{
+    acme::{AcmeAcceptor, ChallengeType},
     config::{Config, Listener, Server, ServerProtocol, Servers},
     failed,
     listener::SessionData,
@@ -42,6 +43,7 @@ use super::{limiter::ConcurrencyLimiter, ServerInstance, SessionManager};
 impl Server {
     pub fn spawn(self, manager: impl SessionManager, shutdown_rx: watch::Receiver<bool>) {
         // Prepare instance
+        let tls = self.tls.map(Arc::new);
         let instance = Arc::new(ServerInstance {
             data: if matches!(self.protocol, ServerProtocol::Smtp | ServerProtocol::Lmtp) {
                 format!("220 {} {}\r\n", self.hostname, self.data)
@@ -52,7 +54,16 @@ impl Server {
             listener_id: self.internal_id,
             protocol: self.protocol,
             hostname: self.hostname,
-            tls_acceptor: self.tls.map(|config| TlsAcceptor::from(Arc::new(config))),
+            acme_acceptor: match (&self.acme, &tls) {
+                (Some(acme), Some(config)) if acme.challenge == ChallengeType::TlsAlpn01 => {
+                    Some(AcmeAcceptor {
+                        config: config.clone(),
+                        manager: acme.clone(),
+                    })
+                }
+                _ => None,
+            },
+            tls_acceptor: tls.map(TlsAcceptor::from),
             is_tls_implicit: self.tls_implicit,
             limiter: ConcurrencyLimiter::new(self.max_connections),
             shutdown_rx,
@@ -198,7 +209,13 @@ impl ServerInstance {
         stream: TcpStream,
         span: &Span,
     ) -> Result<TlsStream<TcpStream>, ()> {
-        match self.tls_acceptor.as_ref().unwrap().accept(stream).await {
+        let result = if let Some(acme_acceptor) = &self.acme_acceptor {
+            acme_acceptor.accept(stream).await
+        } else {
+            self.tls_acceptor.as_ref().unwrap().accept(stream).await
+        };
+
+        match result {
             Ok(stream) => {
                 tracing::info!(
                     parent: span,
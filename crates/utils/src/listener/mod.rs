@@ -30,7 +30,7 @@ use tokio::{
 };
 use tokio_rustls::TlsAcceptor;
 
-use crate::config::ServerProtocol;
+use crate::{acme::AcmeAcceptor, config::ServerProtocol};
 
 use self::limiter::{ConcurrencyLimiter, InFlight};
 
@@ -44,6 +44,7 @@ pub struct ServerInstance {
     pub hostname: String,
     pub data: String,
     pub tls_acceptor: Option<TlsAcceptor>,
+    pub acme_acceptor: Option<AcmeAcceptor>,
     pub is_tls_implicit: bool,
     pub limiter: ConcurrencyLimiter,
     pub shutdown_rx: watch::Receiver<bool>,
@@ -23,7 +23,7 @@
 
 use imap_proto::ResponseType;
 
-use super::{AssertResult, ImapConnection, Type};
+use super::{append::assert_append_message, AssertResult, ImapConnection, Type};
 
 pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
     // Check status
@@ -123,4 +123,76 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
         .assert_contains("\"Burrata al Tartufo\" (UIDNEXT 5 MESSAGES 0 UNSEEN 0 SIZE 0)")
         .assert_contains("\"Scamorza Affumicata\" (UIDNEXT 9 MESSAGES 4 UNSEEN 4 SIZE 5851)")
         .assert_contains("\"INBOX\" (UIDNEXT 11 MESSAGES 10 UNSEEN 10 SIZE 12193)");
+
+    // Replace a draft
+    assert_append_message(
+        imap,
+        "Burrata al Tartufo",
+        "Subject: draft\r\n\r\nfirst draft\r\n",
+        ResponseType::Ok,
+    )
+    .await;
+    imap.send("NOOP").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    let message = "Subject: draft\r\n\r\nsecond draft\r\n";
+    imap.send(&format!(
+        "UID REPLACE 5 \"Burrata al Tartufo\" (\\Draft) {{{}}}",
+        message.len()
+    ))
+    .await;
+    imap.assert_read(Type::Continuation, ResponseType::Ok).await;
+    imap.send_untagged(message).await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("* OK [APPENDUID")
+        .assert_contains("* 1 EXPUNGE");
+    imap.send("UID REPLACE 5 \"Burrata al Tartufo\" {1+}\r\na")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::No).await;
+
+    // Save dates
+    imap.send("UID FETCH 1:* (FLAGS SAVEDATE)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_count("FETCH (", 1)
+        .assert_contains("UID 6")
+        .assert_contains("\\Draft")
+        .assert_contains("SAVEDATE \"");
+    imap.send("UID SEARCH SAVEDATESUPPORTED SAVEDSINCE 1-Jan-2000")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("ALL 6");
+    imap.send("UID SEARCH SAVEDBEFORE 1-Jan-2000").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_count("ALL", 0);
+
+    // Append limit
+    imap.send("STATUS \"Burrata al Tartufo\" (MESSAGES APPENDLIMIT)")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("MESSAGES 1 APPENDLIMIT ");
+
+    // Save dates are recorded when messages are stored, not taken from their internal date
+    let message = "Subject: old\r\n\r\nold message\r\n";
+    imap.send(&format!(
+        "APPEND \"Burrata al Tartufo\" \"01-Jan-2001 00:00:00 +0000\" {{{}}}",
+        message.len()
+    ))
+    .await;
+    imap.assert_read(Type::Continuation, ResponseType::Ok).await;
+    imap.send_untagged(message).await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("NOOP").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("UID SEARCH BEFORE 1-Jan-2010").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("ALL 7");
+    imap.send("UID SEARCH SAVEDBEFORE 1-Jan-2010").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_count("ALL", 0);
 }
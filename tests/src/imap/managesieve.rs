@@ -153,6 +153,58 @@ pub async fn test() {
         .await
         .assert_count("minimalist script", 0)
         .assert_count("holidays", 0);
+
+    // ListLists
+    sieve.send("LISTLISTS").await;
+    sieve
+        .assert_read(ResponseType::Ok)
+        .await
+        .assert_contains("\"local/remote-domains\" READONLY")
+        .assert_contains("\"personal:vip\"");
+
+    // PutList and GetList
+    sieve
+        .send_literal(
+            "PUTLIST \"personal:vip\" ",
+            "boss@example.com\r\nceo@example.com\r\n",
+        )
+        .await;
+    sieve.assert_read(ResponseType::Ok).await;
+    sieve.send("GETLIST \"personal:vip\"").await;
+    sieve
+        .assert_read(ResponseType::Ok)
+        .await
+        .assert_contains("boss@example.com")
+        .assert_contains("ceo@example.com");
+    sieve
+        .send("PUTLIST \"local/remote-domains\" \"example.com\"")
+        .await;
+    sieve.assert_read(ResponseType::No).await;
+    sieve.send("GETLIST \"personal:friends\"").await;
+    sieve
+        .assert_read(ResponseType::No)
+        .await
+        .assert_contains("NONEXISTENT");
+
+    // Lists can be used from scripts
+    sieve
+        .send_literal(
+            "PUTSCRIPT \"vip\" ",
+            concat!(
+                "require [\"extlists\", \"imap4flags\"];\r\n",
+                "if address :list \"from\" \"personal:vip\" { addflag \"$vip\"; }\r\n",
+                "if address :domain :list \"from\" \"local/remote-domains\" ",
+                "{ addflag \"$remote\"; }\r\n"
+            ),
+        )
+        .await;
+    sieve.assert_read(ResponseType::Ok).await;
+    sieve.send("DELETESCRIPT \"vip\"").await;
+    sieve.assert_read(ResponseType::Ok).await;
+
+    // Remove list
+    sieve.send_literal("PUTLIST \"personal:vip\" ", "").await;
+    sieve.assert_read(ResponseType::Ok).await;
 }
 
 pub struct SieveConnection {
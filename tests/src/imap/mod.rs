@@ -31,6 +31,7 @@ pub mod fetch;
 pub mod idle;
 pub mod mailbox;
 pub mod managesieve;
+pub mod pop3;
 pub mod search;
 pub mod store;
 pub mod thread;
@@ -38,6 +39,7 @@ pub mod thread;
 use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use ::managesieve::core::ManageSieveSessionManager;
+use ::pop3::core::Pop3SessionManager;
 use directory::config::ConfigDirectory;
 use imap::core::{ImapSessionManager, IMAP};
 use imap_proto::ResponseType;
@@ -80,6 +82,11 @@ protocol = "managesieve"
 max-connections = 81920
 tls.implicit = true
 
+[server.listener.pop3]
+bind = ["127.0.0.1:4110"]
+protocol = "pop3"
+max-connections = 81920
+
 [server.listener.lmtp-debug]
 bind = ['127.0.0.1:11201']
 greeting = 'Test LMTP instance'
@@ -181,6 +188,10 @@ throttle = "500ms"
 throttle = "500ms"
 attempts.interval = "500ms"
 
+[jmap.sieve.lists]
+lookups = ["local/remote-domains"]
+personal = ["vip"]
+
 [directory."sql"]
 type = "sql"
 address = "sqlite::memory:"
@@ -245,10 +256,15 @@ async fn init_imap_tests(delete_if_exists: bool) -> IMAPTest {
     // Start JMAP and SMTP servers
     servers.bind(&config);
     let (delivery_tx, delivery_rx) = mpsc::channel(IPC_CHANNEL_BUFFER);
-    let smtp = SMTP::init(&config, &servers, &directory, delivery_tx)
+    let store = Arc::new(
+        ::store::Store::open(&config)
+            .await
+            .failed("Unable to open database"),
+    );
+    let smtp = SMTP::init(&config, &servers, &directory, store.clone(), delivery_tx)
         .await
         .failed("Invalid configuration file");
-    let jmap = JMAP::init(&config, &directory, delivery_rx, smtp.clone())
+    let jmap = JMAP::init(&config, &directory, store, delivery_rx, smtp.clone())
         .await
         .failed("Invalid configuration file");
     let imap: Arc<IMAP> = IMAP::init(&config)
@@ -267,6 +283,10 @@ async fn init_imap_tests(delete_if_exists: bool) -> IMAPTest {
                 ManageSieveSessionManager::new(jmap.clone(), imap.clone()),
                 shutdown_rx,
             ),
+            ServerProtocol::Pop3 => server.spawn(
+                Pop3SessionManager::new(jmap.clone(), imap.clone()),
+                shutdown_rx,
+            ),
             ServerProtocol::Smtp | ServerProtocol::Lmtp => {
                 server.spawn(SmtpSessionManager::new(smtp.clone()), shutdown_rx)
             }
@@ -386,6 +406,9 @@ pub async fn imap_tests() {
     // Run ManageSieve tests
     managesieve::test().await;
 
+    // Run POP3 tests
+    pop3::test(&handle).await;
+
     // Remove test data
     if delete {
         handle.temp_dir.delete();
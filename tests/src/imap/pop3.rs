@@ -0,0 +1,270 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use mail_send::smtp::tls::build_tls_connector;
+use rustls::ServerName;
+use tokio::{
+    io::{
+        AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines, ReadHalf,
+        WriteHalf,
+    },
+    net::TcpStream,
+};
+use tokio_rustls::client::TlsStream;
+
+use crate::{directory::sql::create_test_user_with_email, jmap::delivery::SmtpConnection};
+
+use super::{AssertResult, IMAPTest};
+
+pub async fn test(handle: &IMAPTest) {
+    // Deliver test messages
+    create_test_user_with_email(
+        handle.jmap.directory.as_ref(),
+        "popper@example.com",
+        "secret",
+        "Pop Per",
+    )
+    .await;
+    let mut lmtp = SmtpConnection::connect_port(11201).await;
+    for num in 1..=3 {
+        lmtp.ingest(
+            "bill@example.com",
+            &["popper@example.com"],
+            &format!(
+                concat!(
+                    "From: bill@example.com\r\n",
+                    "To: popper@example.com\r\n",
+                    "Subject: POP3 test {}\r\n",
+                    "\r\n",
+                    "First line of message {}.\r\n",
+                    "..hidden dot\r\n",
+                    "Last line."
+                ),
+                num, num
+            ),
+        )
+        .await;
+    }
+
+    // Plain-text authentication should not be offered before STLS
+    let mut pop3 = Pop3Connection::connect().await;
+    pop3.assert_read(true, false).await;
+    pop3.send("CAPA").await;
+    pop3.assert_read(true, true)
+        .await
+        .assert_contains("STLS")
+        .assert_count("USER", 0);
+    pop3.send("USER popper@example.com").await;
+    pop3.assert_read(false, false).await;
+    pop3.send("STAT").await;
+    pop3.assert_read(false, false).await;
+    pop3.send("STLS").await;
+    pop3.assert_read(true, false).await;
+    let mut pop3 = pop3.into_tls().await;
+
+    // Authenticate
+    pop3.send("CAPA").await;
+    pop3.assert_read(true, true)
+        .await
+        .assert_contains("USER")
+        .assert_contains("SASL PLAIN")
+        .assert_contains("UIDL")
+        .assert_count("STLS", 0);
+    pop3.send("PASS secret").await;
+    pop3.assert_read(false, false).await;
+    pop3.send("USER popper@example.com").await;
+    pop3.assert_read(true, false).await;
+    pop3.send("PASS wrong").await;
+    pop3.assert_read(false, false)
+        .await
+        .assert_contains("[AUTH]");
+    pop3.send("USER popper@example.com").await;
+    pop3.assert_read(true, false).await;
+    pop3.send("PASS secret").await;
+    pop3.assert_read(true, false)
+        .await
+        .assert_contains("3 messages");
+
+    // Obtain message list
+    pop3.send("STAT").await;
+    let stat = pop3.assert_read(true, false).await.pop().unwrap();
+    assert!(stat.starts_with("+OK 3 "), "{stat:?}");
+    pop3.send("LIST").await;
+    let list = pop3.assert_read(true, true).await;
+    assert_eq!(list.len(), 5, "{list:?}");
+    pop3.send("LIST 4").await;
+    pop3.assert_read(false, false).await;
+    pop3.send("UIDL").await;
+    let uidl = pop3.assert_read(true, true).await;
+    assert_eq!(uidl.len(), 5, "{uidl:?}");
+    pop3.send("UIDL 2").await;
+    pop3.assert_read(true, false)
+        .await
+        .assert_contains(&uidl[2][2..]);
+
+    // Fetch messages
+    pop3.send("TOP 1 0").await;
+    pop3.assert_read(true, true)
+        .await
+        .assert_contains("Subject: POP3 test 1")
+        .assert_count("First line", 0);
+    pop3.send("TOP 2 1").await;
+    pop3.assert_read(true, true)
+        .await
+        .assert_contains("First line of message 2.")
+        .assert_count("hidden dot", 0);
+    pop3.send("RETR 3").await;
+    pop3.assert_read(true, true)
+        .await
+        .assert_contains("Subject: POP3 test 3")
+        .assert_contains("..hidden dot")
+        .assert_contains("Last line.");
+
+    // Deleted messages can be restored with RSET
+    pop3.send("DELE 1").await;
+    pop3.assert_read(true, false).await;
+    pop3.send("RETR 1").await;
+    pop3.assert_read(false, false).await;
+    pop3.send("DELE 1").await;
+    pop3.assert_read(false, false).await;
+    pop3.send("STAT").await;
+    pop3.assert_read(true, false)
+        .await
+        .assert_contains("+OK 2 ");
+    pop3.send("RSET").await;
+    pop3.assert_read(true, false).await;
+    pop3.send("STAT").await;
+    pop3.assert_read(true, false)
+        .await
+        .assert_contains("+OK 3 ");
+
+    // Messages are removed on QUIT
+    pop3.send("DELE 1").await;
+    pop3.assert_read(true, false).await;
+    pop3.send("DELE 3").await;
+    pop3.assert_read(true, false).await;
+    pop3.send("QUIT").await;
+    pop3.assert_read(true, false).await;
+
+    // Authenticate using SASL and make sure the unique ids did not change
+    let mut pop3 = Pop3Connection::connect().await;
+    pop3.assert_read(true, false).await;
+    pop3.send("STLS").await;
+    pop3.assert_read(true, false).await;
+    let mut pop3 = pop3.into_tls().await;
+    pop3.send("AUTH PLAIN").await;
+    pop3.assert_read(true, false).await;
+    pop3.send("AHBvcHBlckBleGFtcGxlLmNvbQBzZWNyZXQ=").await;
+    pop3.assert_read(true, false)
+        .await
+        .assert_contains("1 messages");
+    pop3.send("UIDL 1").await;
+    pop3.assert_read(true, false)
+        .await
+        .assert_contains(&uidl[2][2..]);
+    pop3.send("RETR 1").await;
+    pop3.assert_read(true, true)
+        .await
+        .assert_contains("Subject: POP3 test 2");
+    pop3.send("QUIT").await;
+    pop3.assert_read(true, false).await;
+}
+
+pub struct Pop3Connection<T: AsyncRead + AsyncWrite> {
+    reader: Lines<BufReader<ReadHalf<T>>>,
+    writer: WriteHalf<T>,
+}
+
+impl Pop3Connection<TcpStream> {
+    pub async fn connect() -> Self {
+        let (reader, writer) =
+            tokio::io::split(TcpStream::connect("127.0.0.1:4110").await.unwrap());
+        Pop3Connection {
+            reader: BufReader::new(reader).lines(),
+            writer,
+        }
+    }
+
+    pub async fn into_tls(self) -> Pop3Connection<TlsStream<TcpStream>> {
+        let (reader, writer) = tokio::io::split(
+            build_tls_connector(true)
+                .connect(
+                    ServerName::try_from("imap.example.org").unwrap(),
+                    self.reader.into_inner().into_inner().unsplit(self.writer),
+                )
+                .await
+                .unwrap(),
+        );
+        Pop3Connection {
+            reader: BufReader::new(reader).lines(),
+            writer,
+        }
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite> Pop3Connection<T> {
+    pub async fn assert_read(&mut self, is_ok: bool, is_multiline: bool) -> Vec<String> {
+        let lines = self.read(is_ok && is_multiline).await;
+        if lines[0].starts_with(if is_ok { "+" } else { "-ERR" }) {
+            lines
+        } else {
+            panic!(
+                "Expected {} from server but got: {:?}",
+                if is_ok { "+OK" } else { "-ERR" },
+                lines
+            );
+        }
+    }
+
+    pub async fn read(&mut self, is_multiline: bool) -> Vec<String> {
+        let mut lines = Vec::new();
+        loop {
+            match tokio::time::timeout(Duration::from_millis(1500), self.reader.next_line()).await {
+                Ok(Ok(Some(line))) => {
+                    let is_done = (!is_multiline || line.starts_with("-ERR") && lines.is_empty())
+                        || line == ".";
+                    println!("<- {:?}", line);
+                    lines.push(line);
+                    if is_done {
+                        return lines;
+                    }
+                }
+                Ok(Ok(None)) => {
+                    panic!("Invalid response: {:?}.", lines);
+                }
+                Ok(Err(err)) => {
+                    panic!("Connection broken: {} ({:?})", err, lines);
+                }
+                Err(_) => panic!("Timeout while waiting for server response: {:?}", lines),
+            }
+        }
+    }
+
+    pub async fn send(&mut self, text: &str) {
+        println!("-> {:?}", text);
+        self.writer.write_all(text.as_bytes()).await.unwrap();
+        self.writer.write_all(b"\r\n").await.unwrap();
+    }
+}
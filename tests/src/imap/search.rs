@@ -135,4 +135,69 @@ pub async fn test(imap: &mut ImapConnection, imap_check: &mut ImapConnection) {
     imap.assert_read(Type::Tagged, ResponseType::Ok)
         .await
         .assert_contains("COUNT 10 ALL 6,4:5,1,10,9,3,7:8,2");
+
+    // Partial results
+    imap.send("UID SEARCH RETURN (PARTIAL 1:3 COUNT) ALL").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("COUNT 10 PARTIAL (1:3 1:3)");
+    imap.send("UID SEARCH RETURN (PARTIAL -1:-3 MIN MAX) ALL")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("MIN 1 MAX 10 PARTIAL (-1:-3 8:10)");
+    imap.send("UID SEARCH RETURN (PARTIAL 20:30) ALL").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("PARTIAL (20:30 NIL)");
+    imap.send("UID SORT RETURN (PARTIAL 1:4 COUNT) (DATE SUBJECT) UTF-8 ALL")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("COUNT 10 PARTIAL (1:4 6,4:5,1)");
+    imap.send("UID SORT RETURN (PARTIAL -1:-3) (DATE SUBJECT) UTF-8 ALL")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("PARTIAL (-1:-3 7:8,2)");
+    imap.send("UID FETCH 1:* (FLAGS) (PARTIAL -1:-2)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_count("FETCH (", 2)
+        .assert_contains("UID 9")
+        .assert_contains("UID 10");
+
+    // Search context updates
+    imap_check
+        .send("UID SEARCH RETURN (CONTEXT UPDATE) KEYWORD $Context")
+        .await;
+    imap_check
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("ESEARCH (TAG \"_y\") UID");
+    imap.send("UID STORE 3 +FLAGS ($Context)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_check.send("NOOP").await;
+    imap_check
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("ESEARCH (TAG \"_y\") UID ADDTO (1 3)");
+    imap.send("UID STORE 3 -FLAGS ($Context)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_check.send("NOOP").await;
+    imap_check
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("ESEARCH (TAG \"_y\") UID REMOVETO (1 3)");
+    imap_check.send("CANCELUPDATE \"_y\"").await;
+    imap_check.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("UID STORE 3 +FLAGS ($Context)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_check.send("NOOP").await;
+    imap_check
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_count("ESEARCH", 0);
+    imap.send("UID STORE 3 -FLAGS ($Context)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
 }
@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use jmap::{
     mailbox::{INBOX_ID, TRASH_ID},
@@ -38,8 +38,9 @@ use jmap_client::{
     principal::ACL,
 };
 use jmap_proto::types::id::Id;
+use serde_json::{json, Value};
 use std::fmt::Debug;
-use store::ahash::AHashMap;
+use store::{ahash::AHashMap, audit::AuditFilter};
 
 use crate::{
     directory::sql::{
@@ -181,6 +182,26 @@ pub async fn test(server: Arc<JMAP>, admin_client: &mut Client) {
         .await
         .unwrap();
 
+    // The ACL change should be recorded in the audit log
+    let events = server
+        .store
+        .audit_query(
+            AuditFilter {
+                account: "jane.smith@example.com".to_string().into(),
+                action: "acl".to_string().into(),
+                ..Default::default()
+            },
+            1,
+        )
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1, "{events:?}");
+    assert_eq!(events[0].protocol, "jmap");
+    assert_eq!(events[0].action, "acl.set");
+    assert_eq!(events[0].target, "jane.smith@example.com/Inbox");
+    assert_eq!(events[0].details, "jdoe@example.com: readItems");
+    assert!(!events[0].remote_ip.is_empty());
+
     // John shoud have ReadItems access to Inbox
     assert_eq!(
         john_client
@@ -772,6 +793,75 @@ pub async fn test(server: Arc<JMAP>, admin_client: &mut Client) {
             .await,
     );
 
+    // Account renames and deletions by administrators are recorded in the audit log
+    server
+        .get_account_id("audit.test@example.com")
+        .await
+        .unwrap();
+    assert_eq!(
+        admin_request("account/rename/audit.test@example.com/audit.renamed@example.com").await,
+        json!("success")
+    );
+    assert_eq!(
+        admin_request("account/delete/audit.renamed@example.com").await,
+        json!("success")
+    );
+    assert_eq!(
+        server
+            .try_get_account_id("audit.renamed@example.com")
+            .await
+            .unwrap(),
+        None
+    );
+    let response = admin_request("audit/list?account=admin&action=account").await;
+    let events = response["data"].as_array().unwrap();
+    assert_eq!(events.len(), 2, "{response}");
+    let rename = events
+        .iter()
+        .find(|event| event["action"] == "account.rename")
+        .unwrap();
+    assert_eq!(rename["protocol"], "http");
+    assert_eq!(rename["target"], "audit.test@example.com");
+    assert_eq!(rename["details"], "audit.renamed@example.com");
+    assert_ne!(rename["remote_ip"], "");
+    let delete = events
+        .iter()
+        .find(|event| event["action"] == "account.delete")
+        .unwrap();
+    assert_eq!(delete["target"], "audit.renamed@example.com");
+
+    // The audit list endpoint applies the query filters
+    let timestamp = delete["timestamp"].as_u64().unwrap();
+    for (query, expected) in [
+        ("action=account.delete".to_string(), 1),
+        ("action=account&limit=1".to_string(), 1),
+        (
+            "account=jane.smith@example.com&action=account".to_string(),
+            0,
+        ),
+        (
+            "account=jane.smith@example.com&action=acl&limit=1".to_string(),
+            1,
+        ),
+        (format!("action=account&from={}", timestamp + 86400), 0),
+        (format!("action=account&to={}", timestamp - 86400), 0),
+        (
+            format!(
+                "action=account&from={}&to={}",
+                timestamp - 86400,
+                timestamp + 86400
+            ),
+            2,
+        ),
+    ] {
+        let response = admin_request(&format!("audit/list?{query}")).await;
+        assert_eq!(
+            response["data"].as_array().unwrap().len(),
+            expected,
+            "{query}: {response}"
+        );
+    }
+
     // Destroy test account data
     for id in [john_id, bill_id, jane_id, sales_id] {
         admin_client.set_default_account_id(&id.to_string());
@@ -780,6 +870,23 @@ pub async fn test(server: Arc<JMAP>, admin_client: &mut Client) {
     server.store.assert_is_empty().await;
 }
 
+async fn admin_request(query: &str) -> Value {
+    let response = reqwest::Client::builder()
+        .timeout(Duration::from_millis(500))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap_or_default()
+        .get(format!("https://127.0.0.1:8899/admin/{query}"))
+        .basic_auth("admin", Some("secret"))
+        .send()
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    serde_json::from_slice::<Value>(&response).unwrap()
+}
+
 pub fn assert_forbidden<T: Debug>(result: Result<T, jmap_client::Error>) {
     if !matches!(
         result,
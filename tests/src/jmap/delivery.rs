@@ -25,7 +25,7 @@ use std::{sync::Arc, time::Duration};
 
 use jmap::JMAP;
 use jmap_client::client::Client;
-use jmap_proto::types::{collection::Collection, id::Id};
+use jmap_proto::types::{collection::Collection, id::Id, property::Property};
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines, ReadHalf, WriteHalf},
     net::TcpStream,
@@ -241,6 +241,38 @@ pub async fn test(server: Arc<JMAP>, client: &mut Client) {
         );
     }
 
+    // Messages flagged as spam are filed into the Junk mailbox
+    lmtp.ingest(
+        "bill@example.com",
+        &["jane@example.com"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jane@example.com\r\n",
+            "Subject: Cheap TPS reports\r\n",
+            "X-Spam-Status: Yes, score=7.50 required=5.00 tests=BAYES_SPAM\r\n",
+            "\r\n",
+            "Buy your TPS reports now."
+        ),
+    )
+    .await;
+    let account_id = Id::from_bytes(account_id_2.as_bytes())
+        .unwrap()
+        .document_id();
+    let junk_id = server
+        .mailbox_get_by_role(account_id, "junk")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        server
+            .get_tag(account_id, Collection::Email, Property::MailboxIds, junk_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .len(),
+        1
+    );
+
     // Remove test data
     for account_id in [&account_id_1, &account_id_2, &account_id_3] {
         client.set_default_account_id(account_id);
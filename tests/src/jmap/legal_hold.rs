@@ -0,0 +1,182 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::JMAP;
+use jmap_client::{client::Client, mailbox::Role};
+use jmap_proto::types::id::Id;
+use store::{
+    hold::{LegalHold, Tombstone, TombstoneFilter},
+    write::now,
+};
+
+use crate::jmap::mailbox::destroy_all_mailboxes;
+
+pub async fn test(server: Arc<JMAP>, client: &mut Client) {
+    println!("Running Legal hold tests...");
+    client.set_default_account_id(Id::new(1).to_string());
+
+    // Place the account under legal hold
+    server
+        .store
+        .set_legal_hold(
+            1,
+            LegalHold {
+                since: now(),
+                reason: "Case 2023-117".to_string(),
+            }
+            .into(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        server
+            .store
+            .get_legal_hold(1)
+            .await
+            .unwrap()
+            .unwrap()
+            .reason,
+        "Case 2023-117"
+    );
+
+    // Import messages
+    let inbox_id = client
+        .mailbox_create("Inbox", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let trash_id = client
+        .mailbox_create("Trash", None::<String>, Role::Trash)
+        .await
+        .unwrap()
+        .take_id();
+    let mut email_ids = Vec::new();
+    for (subject, mailbox_id, received_at) in [
+        ("Quarterly results", &inbox_id, None),
+        ("Expired", &trash_id, Some(311923920)),
+    ] {
+        email_ids.push(
+            client
+                .email_import(
+                    format!(
+                        "From: bill@example.com\r\nTo: jdoe@example.com\r\nSubject: {subject}\r\n\r\nTest."
+                    )
+                    .into_bytes(),
+                    [mailbox_id],
+                    None::<Vec<&str>>,
+                    received_at,
+                )
+                .await
+                .unwrap()
+                .take_id(),
+        );
+    }
+
+    // Destroyed messages are hidden from the user but kept as tombstones
+    client.email_destroy(&email_ids[0]).await.unwrap();
+    assert!(client
+        .email_get(&email_ids[0], None::<Vec<_>>)
+        .await
+        .unwrap()
+        .is_none());
+    let tombstones = server
+        .store
+        .tombstone_query(1, TombstoneFilter::default(), 0)
+        .await
+        .unwrap();
+    assert_eq!(tombstones.len(), 1);
+    let tombstone = &tombstones[0];
+    assert_eq!(tombstone.from, "bill@example.com");
+    assert_eq!(tombstone.to, "jdoe@example.com");
+    assert_eq!(tombstone.subject, "Quarterly results");
+    assert_eq!(tombstone.reason, "delete");
+    let raw_message = server
+        .store
+        .get_blob(&Tombstone::blob_kind(1, tombstone.id), 0..u32::MAX)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(String::from_utf8(raw_message)
+        .unwrap()
+        .contains("Subject: Quarterly results"));
+
+    // Tombstones are searchable
+    for (text, expected) in [("quarterly", 1), ("bill@example", 1), ("invoice", 0)] {
+        assert_eq!(
+            server
+                .store
+                .tombstone_query(
+                    1,
+                    TombstoneFilter {
+                        text: text.to_string().into(),
+                        ..Default::default()
+                    },
+                    0
+                )
+                .await
+                .unwrap()
+                .len(),
+            expected,
+            "{text}"
+        );
+    }
+
+    // Retention policies are not applied while on hold
+    server.purge_expired_messages().await.unwrap();
+    assert!(client
+        .email_get(&email_ids[1], None::<Vec<_>>)
+        .await
+        .unwrap()
+        .is_some());
+
+    // Tombstones are kept until the hold is released
+    server.store.purge_released_tombstones().await.unwrap();
+    assert_eq!(
+        server
+            .store
+            .tombstone_query(1, TombstoneFilter::default(), 0)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    server.store.set_legal_hold(1, None).await.unwrap();
+    server.store.purge_released_tombstones().await.unwrap();
+    assert!(server
+        .store
+        .tombstone_query(1, TombstoneFilter::default(), 0)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(server
+        .store
+        .get_blob(&Tombstone::blob_kind(1, tombstone.id), 0..u32::MAX)
+        .await
+        .unwrap()
+        .is_none());
+
+    destroy_all_mailboxes(client).await;
+    server.store.assert_is_empty().await;
+}
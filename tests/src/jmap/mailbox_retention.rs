@@ -0,0 +1,158 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{email::added_dates::AddedDates, JMAP};
+use jmap_client::{client::Client, mailbox::Role};
+use jmap_proto::types::{collection::Collection, id::Id, property::Property};
+use store::write::{BatchBuilder, F_VALUE};
+
+use crate::jmap::mailbox::destroy_all_mailboxes;
+
+pub async fn test(server: Arc<JMAP>, client: &mut Client) {
+    println!("Running Mailbox retention tests...");
+    client.set_default_account_id(Id::new(1).to_string());
+
+    // Create mailboxes
+    let mut mailbox_ids = Vec::new();
+    for (name, role) in [
+        ("Inbox", Role::None),
+        ("Trash", Role::Trash),
+        ("Junk", Role::Junk),
+        ("Archive", Role::Archive),
+    ] {
+        mailbox_ids.push(
+            client
+                .mailbox_create(name, None::<String>, role)
+                .await
+                .unwrap()
+                .take_id(),
+        );
+    }
+    let (inbox_id, trash_id, junk_id, archive_id) = (
+        &mailbox_ids[0],
+        &mailbox_ids[1],
+        &mailbox_ids[2],
+        &mailbox_ids[3],
+    );
+
+    // Import messages, backdating the time they were added to their mailboxes
+    let mut email_ids = Vec::new();
+    for (subject, mailboxes, received_at, is_backdated) in [
+        ("expired", vec![trash_id], Some(311923920), true),
+        ("recent", vec![trash_id], None, false),
+        ("shared", vec![trash_id, inbox_id], Some(311923920), true),
+        ("junk", vec![junk_id], Some(311923920), true),
+        ("moved", vec![inbox_id], Some(311923920), false),
+    ] {
+        let email_id = client
+            .email_import(
+                format!("From: bill@example.com\r\nSubject: {subject}\r\n\r\nTest.").into_bytes(),
+                mailboxes.clone(),
+                None::<Vec<&str>>,
+                received_at,
+            )
+            .await
+            .unwrap()
+            .take_id();
+        if is_backdated {
+            let mailbox_ids = mailboxes
+                .iter()
+                .map(|id| Id::from_bytes(id.as_bytes()).unwrap().document_id())
+                .collect::<Vec<_>>();
+            server
+                .store
+                .write(
+                    BatchBuilder::new()
+                        .with_account_id(1)
+                        .with_collection(Collection::Email)
+                        .update_document(Id::from_bytes(email_id.as_bytes()).unwrap().document_id())
+                        .value(
+                            Property::AddedDates,
+                            &AddedDates::new(&mailbox_ids, received_at.unwrap() as u64),
+                            F_VALUE,
+                        )
+                        .build_batch(),
+                )
+                .await
+                .unwrap();
+        }
+        email_ids.push(email_id);
+    }
+
+    // Old messages moved to Trash expire from the time they were moved
+    client
+        .email_set_mailbox(&email_ids[4], trash_id, true)
+        .await
+        .unwrap();
+    client
+        .email_set_mailbox(&email_ids[4], inbox_id, false)
+        .await
+        .unwrap();
+
+    // Apply retention policies
+    server.purge_expired_messages().await.unwrap();
+
+    // Expired messages in Trash are deleted
+    assert!(client
+        .email_get(&email_ids[0], None::<Vec<_>>)
+        .await
+        .unwrap()
+        .is_none());
+
+    // Recent messages are kept
+    let email = client
+        .email_get(&email_ids[1], None::<Vec<_>>)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(email.mailbox_ids(), &[trash_id]);
+
+    // Messages present in other mailboxes are only removed from Trash
+    let email = client
+        .email_get(&email_ids[2], None::<Vec<_>>)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(email.mailbox_ids(), &[inbox_id]);
+
+    // Expired messages in Junk are archived
+    let email = client
+        .email_get(&email_ids[3], None::<Vec<_>>)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(email.mailbox_ids(), &[archive_id]);
+
+    // Old messages that were recently moved to Trash are kept
+    let email = client
+        .email_get(&email_ids[4], None::<Vec<_>>)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(email.mailbox_ids(), &[trash_id]);
+
+    destroy_all_mailboxes(client).await;
+    server.store.assert_is_empty().await;
+}
@@ -52,7 +52,9 @@ pub mod email_search_snippet;
 pub mod email_set;
 pub mod email_submission;
 pub mod event_source;
+pub mod legal_hold;
 pub mod mailbox;
+pub mod mailbox_retention;
 pub mod push_subscription;
 pub mod quota;
 pub mod sieve_script;
@@ -173,6 +175,29 @@ throttle = "500ms"
 throttle = "500ms"
 attempts.interval = "500ms"
 
+[jmap.sieve]
+notification-uris = ["mailto", "push", "xmpp"]
+
+[jmap.sieve.notify]
+rate = "4/1h"
+
+[jmap.sieve.notify.method."xmpp"]
+command = "sh"
+arguments = ["-c", 'echo "$1" >> "$0"; cat >> "$0"; echo >> "$0"', "{TMP}/notify.txt"]
+
+[jmap.sieve.lists]
+personal = ["allow", "block"]
+max-items = 10
+max-item-size = 64
+
+[jmap.retention.role.trash]
+expire = "30d"
+action = "delete"
+
+[jmap.retention.role.junk]
+expire = "30d"
+action = "archive"
+
 [directory."sql"]
 type = "sql"
 address = "sqlite::memory:"
@@ -239,6 +264,8 @@ pub async fn jmap_tests() {
     thread_get::test(params.server.clone(), &mut params.client).await;
     thread_merge::test(params.server.clone(), &mut params.client).await;
     mailbox::test(params.server.clone(), &mut params.client).await;
+    mailbox_retention::test(params.server.clone(), &mut params.client).await;
+    legal_hold::test(params.server.clone(), &mut params.client).await;
     delivery::test(params.server.clone(), &mut params.client).await;
     auth_acl::test(params.server.clone(), &mut params.client).await;
     auth_limits::test(params.server.clone(), &mut params.client).await;
@@ -293,10 +320,15 @@ async fn init_jmap_tests(delete_if_exists: bool) -> JMAPTest {
     // Start JMAP and SMTP servers
     servers.bind(&config);
     let (delivery_tx, delivery_rx) = mpsc::channel(IPC_CHANNEL_BUFFER);
-    let smtp = SMTP::init(&config, &servers, &directory, delivery_tx)
+    let store = Arc::new(
+        ::store::Store::open(&config)
+            .await
+            .failed("Unable to open database"),
+    );
+    let smtp = SMTP::init(&config, &servers, &directory, store.clone(), delivery_tx)
         .await
         .failed("Invalid configuration file");
-    let jmap = JMAP::init(&config, &directory, delivery_rx, smtp.clone())
+    let jmap = JMAP::init(&config, &directory, store, delivery_rx, smtp.clone())
         .await
         .failed("Invalid configuration file");
     let shutdown_tx = servers.spawn(|server, shutdown_rx| {
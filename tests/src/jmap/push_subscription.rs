@@ -44,8 +44,8 @@ use jmap::{
     JMAP,
 };
 use jmap_client::{client::Client, mailbox::Role, push_subscription::Keys};
-use jmap_proto::types::{id::Id, type_state::TypeState};
-use reqwest::header::CONTENT_ENCODING;
+use jmap_proto::types::{collection::Collection, id::Id, type_state::TypeState};
+use reqwest::header::{AUTHORIZATION, CONTENT_ENCODING};
 use store::ahash::AHashSet;
 use tokio::{net::TcpStream, sync::mpsc};
 use utils::listener::SessionData;
@@ -101,6 +101,8 @@ pub async fn test(server: Arc<JMAP>, admin_client: &mut Client) {
         auth_secret: auth_secret.to_vec(),
         tx: event_tx,
         fail_requests: false.into(),
+        gone_requests: false.into(),
+        vapid_key: server.vapid_key.as_ref().unwrap().public_key().to_string(),
     });
 
     // Start mock push server
@@ -217,6 +219,35 @@ pub async fn test(server: Arc<JMAP>, admin_client: &mut Client) {
     client.mailbox_destroy(&mailbox_id, true).await.unwrap();
     expect_nothing(&mut event_rx).await;
 
+    // Subscriptions are destroyed when the push service reports them as gone
+    let push_id = client
+        .push_subscription_create("123", "https://127.0.0.1:9000/push?skip_checks=true", None)
+        .await
+        .unwrap()
+        .take_id();
+    let verification = expect_push(&mut event_rx).await.unwrap_verification();
+    assert_eq!(verification.push_subscription_id, push_id);
+    client
+        .push_subscription_verify(&push_id, verification.verification_code)
+        .await
+        .unwrap();
+    push_server.gone_requests.store(true, Ordering::Relaxed);
+    let mailbox_id = client
+        .mailbox_create("PushSubscription Gone", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    push_server.gone_requests.store(false, Ordering::Relaxed);
+    assert!(server
+        .get_document_ids(account_id.document_id(), Collection::PushSubscription)
+        .await
+        .unwrap()
+        .unwrap_or_default()
+        .is_empty());
+    client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+    expect_nothing(&mut event_rx).await;
+
     destroy_all_mailboxes(admin_client).await;
 
     server.store.assert_is_empty().await;
@@ -237,6 +268,8 @@ pub struct PushServer {
     auth_secret: Vec<u8>,
     tx: mpsc::Sender<PushMessage>,
     fail_requests: AtomicBool,
+    gone_requests: AtomicBool,
+    vapid_key: String,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -305,7 +338,27 @@ impl utils::listener::SessionManager for SessionManager {
                                     "too many requests".to_string(),
                                 )
                                 .into_http_response());
+                            } else if push.gone_requests.load(Ordering::Relaxed) {
+                                return Ok(HtmlResponse::with_status(
+                                    StatusCode::GONE,
+                                    "gone".to_string(),
+                                )
+                                .into_http_response());
                             }
+
+                            // Requests must be authenticated with the server's VAPID key
+                            let authorization = req
+                                .headers()
+                                .get(AUTHORIZATION)
+                                .expect("Missing VAPID authorization")
+                                .to_str()
+                                .unwrap();
+                            assert!(
+                                authorization.starts_with("vapid t=")
+                                    && authorization.ends_with(&format!(", k={}", push.vapid_key)),
+                                "Invalid VAPID authorization: {authorization}"
+                            );
+
                             let is_encrypted = req
                                 .headers()
                                 .get(CONTENT_ENCODING)
@@ -36,13 +36,17 @@ use jmap_client::{
     sieve::query::{Comparator, Filter},
     Error,
 };
-use jmap_proto::types::id::Id;
+use jmap_proto::types::{id::Id, type_state::TypeState};
+use serde_json::{json, Value};
+use utils::map::bitmap::Bitmap;
 
 use crate::{
     directory::sql::create_test_user_with_email,
     jmap::{
         delivery::SmtpConnection,
-        email_submission::{assert_message_delivery, spawn_mock_smtp_server, MockMessage},
+        email_submission::{
+            assert_message_delivery, expect_nothing, spawn_mock_smtp_server, MockMessage,
+        },
         mailbox::destroy_all_mailboxes,
     },
 };
@@ -479,6 +483,184 @@ pub async fn test(server: Arc<JMAP>, client: &mut Client) {
         panic!("Email {:?} not found in: {:#?}", subject, emails);
     }
 
+    // Notifications should not be sent for auto-submitted messages
+    lmtp.ingest(
+        "bill@remote.org",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@remote.org\r\n",
+            "To: jdoe@example.com\r\n",
+            "Auto-Submitted: auto-generated\r\n",
+            "Subject: Automated TPS Reports reminder\r\n",
+            "\r\n",
+            "Your TPS reports are overdue."
+        ),
+    )
+    .await;
+    expect_nothing(&mut smtp_rx).await;
+
+    // Personal lists are managed with SieveList/set and SieveList/get
+    let response = sieve_list_request(
+        &account_id,
+        "SieveList/set",
+        json!({"update": {
+            "personal:block": ["bill@remote.org", "x".repeat(300)],
+            "personal:unknown": ["bill@remote.org"]
+        }}),
+    )
+    .await;
+    assert_eq!(
+        response["notUpdated"]["personal:block"]["type"], "invalidProperties",
+        "{response}"
+    );
+    assert_eq!(
+        response["notUpdated"]["personal:unknown"]["type"], "notFound",
+        "{response}"
+    );
+    let response = sieve_list_request(
+        &account_id,
+        "SieveList/set",
+        json!({"update": {"personal:block": ["bill@remote.org", " bill@remote.org "]}}),
+    )
+    .await;
+    assert_eq!(response["updated"], json!(["personal:block"]), "{response}");
+    let response = sieve_list_request(&account_id, "SieveList/get", json!({})).await;
+    assert_eq!(
+        response["list"],
+        json!([
+            {"id": "personal:allow", "isReadOnly": false, "items": []},
+            {"id": "personal:block", "isReadOnly": false, "items": ["bill@remote.org"]}
+        ]),
+        "{response}"
+    );
+
+    // Lists are evaluated at delivery time
+    client
+        .sieve_script_create("test_lists", get_script("test_lists"), true)
+        .await
+        .unwrap();
+    for sender in ["bill@remote.org", "jane@remote.org"] {
+        lmtp.ingest(
+            sender,
+            &["jdoe@example.com"],
+            &format!(
+                concat!(
+                    "From: {}\r\n",
+                    "To: jdoe@example.com\r\n",
+                    "Subject: TPS Reports from {}\r\n",
+                    "\r\n",
+                    "Did you get the memo?"
+                ),
+                sender, sender
+            ),
+        )
+        .await;
+    }
+    let blocked_id = client
+        .mailbox_query(
+            mailbox::query::Filter::name("Blocked").into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .expect("Blocked mailbox not found");
+    let ids = client
+        .email_query(
+            email::query::Filter::in_mailbox(&blocked_id).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids();
+    assert_eq!(ids.len(), 1, "{ids:?}");
+    assert_eq!(
+        client
+            .email_get(&ids[0], [email::Property::Subject].into())
+            .await
+            .unwrap()
+            .unwrap()
+            .subject()
+            .unwrap(),
+        "TPS Reports from bill@remote.org"
+    );
+    let response = sieve_list_request(
+        &account_id,
+        "SieveList/set",
+        json!({"update": {"personal:block": null}}),
+    )
+    .await;
+    assert_eq!(response["updated"], json!(["personal:block"]), "{response}");
+
+    // Push and external notification methods share the notification rate limit
+    client
+        .sieve_script_create(
+            "test_notify_methods",
+            get_script("test_notify_methods"),
+            true,
+        )
+        .await
+        .unwrap();
+    let notify_file = PathBuf::from(
+        &server
+            .config
+            .sieve_notify_methods
+            .get("xmpp")
+            .unwrap()
+            .arguments[2],
+    );
+    let _ = fs::remove_file(&notify_file);
+    let mut change_rx = server
+        .subscribe_state_manager(
+            u32::MAX,
+            server.get_account_id("jdoe@example.com").await.unwrap(),
+            Bitmap::from_iter([TypeState::Email, TypeState::EmailDelivery]),
+        )
+        .await
+        .unwrap();
+    let mut push_count = 0;
+    for num in 1..=3 {
+        lmtp.ingest(
+            "bill@remote.org",
+            &["jdoe@example.com"],
+            &format!(
+                concat!(
+                    "From: bill@remote.org\r\n",
+                    "To: jdoe@example.com\r\n",
+                    "Subject: TPS Report #{}\r\n",
+                    "\r\n",
+                    "Yeah, if you could go ahead and file it, that'd be great."
+                ),
+                num
+            ),
+        )
+        .await;
+
+        // Push notifications only include EmailDelivery changes
+        while let Ok(Some(change)) =
+            tokio::time::timeout(Duration::from_millis(200), change_rx.recv()).await
+        {
+            if change
+                .types
+                .iter()
+                .all(|(type_state, _)| *type_state == TypeState::EmailDelivery)
+            {
+                push_count += 1;
+            }
+        }
+    }
+
+    // The earlier mailto notification used one token, leaving three for
+    // the push and xmpp notifications of the first message and the push
+    // notification of the second one.
+    assert_eq!(push_count, 2);
+    assert_eq!(
+        fs::read_to_string(&notify_file).unwrap(),
+        "xmpp:jdoe@example.com\nTPS Report #1\n"
+    );
+    let _ = fs::remove_file(&notify_file);
+
     // Remove test data
     client.sieve_script_deactivate().await.unwrap();
     let mut request = client.build();
@@ -490,6 +672,33 @@ pub async fn test(server: Arc<JMAP>, client: &mut Client) {
     server.store.assert_is_empty().await;
 }
 
+async fn sieve_list_request(account_id: &str, method: &str, mut arguments: Value) -> Value {
+    arguments["accountId"] = account_id.into();
+    let response = reqwest::Client::builder()
+        .timeout(Duration::from_millis(500))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap_or_default()
+        .post("https://127.0.0.1:8899/jmap")
+        .basic_auth("jdoe@example.com", Some("12345"))
+        .body(
+            json!({
+                "using": ["urn:ietf:params:jmap:core", "urn:ietf:params:jmap:sieve"],
+                "methodCalls": [[method, arguments, "c0"]]
+            })
+            .to_string(),
+        )
+        .send()
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    let mut response = serde_json::from_slice::<Value>(&response).unwrap();
+    assert_eq!(response["methodResponses"][0][0], method, "{response}");
+    response["methodResponses"][0][1].take()
+}
+
 fn get_script(name: &str) -> Vec<u8> {
     let mut script_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     script_path.push("resources");
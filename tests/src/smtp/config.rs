@@ -442,6 +442,7 @@ fn parse_servers() {
             tls: None,
             tls_implicit: false,
             max_connections: 8192,
+            acme: None,
         },
         Server {
             id: "smtps".to_string(),
@@ -466,6 +467,7 @@ fn parse_servers() {
             tls: None,
             tls_implicit: true,
             max_connections: 1024,
+            acme: None,
         },
         Server {
             id: "submission".to_string(),
@@ -482,6 +484,7 @@ fn parse_servers() {
             tls: None,
             tls_implicit: true,
             max_connections: 8192,
+            acme: None,
         },
     ];
 
@@ -0,0 +1,239 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    make_temp_dir,
+    session::{load_test_message, TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+use smtp::{
+    config::{session::ConfigSession, ConfigContext, IfBlock},
+    core::{Session, SMTP},
+};
+use store::{quarantine::QuarantineFilter, Store};
+use utils::config::Config;
+
+const CONFIG: &str = r#"
+[session.data.attachments]
+enable = true
+action = [ { if = "sender-domain", eq = "strip.org", then = "strip" },
+           { if = "sender-domain", eq = "quarantine.org", then = "quarantine" },
+           { else = "reject" } ]
+notice = "Attachment removed by policy."
+
+[session.data.attachments.limits]
+size = 1024
+
+[session.data.attachments.archives]
+max-depth = 3
+"#;
+
+#[tokio::test]
+async fn attachment_policy() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::DEBUG)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Configure tests
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_attachments_test");
+    let temp_dir = make_temp_dir("smtp_attachments_store", true);
+    let store = Arc::new(
+        Store::open(
+            &Config::parse(&format!(
+                concat!(
+                    "store.db.path = \"{}/sqlite.db\"\n",
+                    "store.blob.type = \"local\"\n",
+                    "store.blob.local.path = \"{}\"\n"
+                ),
+                temp_dir.temp_dir.display(),
+                temp_dir.temp_dir.display()
+            ))
+            .unwrap(),
+        )
+        .await
+        .unwrap(),
+    );
+    core.store = Some(store.clone());
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.session.config.data.attachments = Config::parse(CONFIG)
+        .unwrap()
+        .parse_attachment_policy(&ConfigContext::new(&[]))
+        .unwrap();
+    let policy = &core.session.config.data.attachments;
+    assert!(policy.blocked_extensions.contains("exe"));
+    assert!(policy.blocked_file_types.contains("exe"));
+    assert_eq!(policy.max_size, Some(1024));
+    let core = Arc::new(core);
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+
+    // Allowed attachments are accepted
+    session
+        .send_message(
+            "john@doe.org",
+            &["jane@foobar.org"],
+            &load_test_message("clean", "attachments"),
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("filename=\"report.pdf\"");
+
+    // Reject blocked extensions, executables with lying content types,
+    // nested archives and macro-enabled documents
+    for (file, reason) in [
+        (
+            "extension",
+            "\"setup.exe\" is not allowed (blocked extension .exe)",
+        ),
+        ("disguised", "\"invoice.pdf\" is not allowed (exe content)"),
+        (
+            "nested_archive",
+            concat!(
+                "\"documents.zip\" is not allowed (archive entry \"archive.zip\": ",
+                "archive entry \"payload.js\": blocked extension .js)"
+            ),
+        ),
+        (
+            "macro",
+            concat!(
+                "\"budget.docx\" is not allowed (archive entry ",
+                "\"word/vbaProject.bin\": macro-enabled document)"
+            ),
+        ),
+    ] {
+        session
+            .send_message(
+                "john@doe.org",
+                &["jane@foobar.org"],
+                &load_test_message(file, "attachments"),
+                &format!("550 5.7.1 Message rejected: attachment {reason}."),
+            )
+            .await;
+        qr.assert_empty_queue();
+    }
+
+    // Trailing dots do not hide blocked extensions
+    session
+        .send_message(
+            "john@doe.org",
+            &["jane@foobar.org"],
+            &load_test_message("extension", "attachments").replace("setup.exe", "setup.exe."),
+            concat!(
+                "550 5.7.1 Message rejected: attachment \"setup.exe.\" ",
+                "is not allowed (blocked extension .exe)."
+            ),
+        )
+        .await;
+    qr.assert_empty_queue();
+
+    // Archive entries share a decompression budget
+    let policy = Config::parse(&format!("{CONFIG}max-total-size = 16\n"))
+        .unwrap()
+        .parse_attachment_policy(&ConfigContext::new(&[]))
+        .unwrap();
+    let raw_message = load_test_message("nested_archive", "attachments");
+    let violations =
+        policy.check_message(&mail_parser::Message::parse(raw_message.as_bytes()).unwrap());
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].name, "documents.zip");
+    assert_eq!(
+        violations[0].reason,
+        "archive exceeds the decompression limit"
+    );
+
+    // Enforce size limits
+    session
+        .send_message(
+            "john@doe.org",
+            &["jane@foobar.org"],
+            &load_test_message("clean", "attachments").replace(
+                "--boundary-1--",
+                &format!(
+                    concat!(
+                        "--boundary-1\n",
+                        "Content-Type: text/plain; name=\"big.txt\"\n\n",
+                        "{}\n--boundary-1--"
+                    ),
+                    "0123456789\n".repeat(200)
+                ),
+            ),
+            concat!(
+                "550 5.7.1 Message rejected: attachment \"big.txt\" ",
+                "is not allowed (size limit exceeded)."
+            ),
+        )
+        .await;
+    qr.assert_empty_queue();
+
+    // Strip blocked attachments
+    session
+        .send_message(
+            "john@strip.org",
+            &["jane@foobar.org"],
+            &load_test_message("extension", "attachments"),
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("Please find the attached file.")
+        .assert_contains("Attachment removed by policy.")
+        .assert_contains("Removed: setup.exe")
+        .assert_not_contains("filename=\"setup.exe\"");
+
+    // Quarantine messages with blocked attachments
+    session
+        .send_message(
+            "john@quarantine.org",
+            &["jane@foobar.org"],
+            &load_test_message("disguised", "attachments"),
+            "250",
+        )
+        .await;
+    qr.assert_empty_queue();
+    let messages = store
+        .quarantine_query(QuarantineFilter::default(), 0)
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].source, "attachments");
+    assert_eq!(
+        messages[0].reason,
+        "Attachment \"invoice.pdf\" is not allowed: exe content"
+    );
+}
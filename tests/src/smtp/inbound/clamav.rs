@@ -0,0 +1,271 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    make_temp_dir,
+    session::{TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+use smtp::{
+    config::{session::ConfigSession, ConfigContext, EnvelopeKey, IfBlock},
+    core::{Session, SMTP},
+};
+use store::{quarantine::QuarantineFilter, Store};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use utils::config::Config;
+
+const CONFIG: &str = r#"
+[session.data.clamav."clamav"]
+hostname = "127.0.0.1"
+port = 9935
+enable = [ { if = "authenticated-as", ne = "", then = false },
+           { else = true } ]
+action = [ { if = "sender-domain", eq = "quarantine.org", then = "quarantine" },
+           { if = "sender-domain", eq = "tag.org", then = "tag" },
+           { else = "reject" } ]
+
+[session.data.clamav."offline"]
+hostname = "127.0.0.1"
+port = 9936
+enable = [ { if = "sender-domain", eq = "error.org", then = true },
+           { else = false } ]
+timeout.connect = "1s"
+"#;
+
+const INFECTED_MESSAGE: &str = concat!(
+    "From: john@doe.org\r\n",
+    "To: jane@foobar.org\r\n",
+    "Subject: Invoice\r\n",
+    "\r\n",
+    "VIRUS-TEST-SIGNATURE\r\n"
+);
+
+#[tokio::test]
+async fn clamav() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::DEBUG)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Start fake clamd
+    let connections = spawn_mock_clamd(9935).await;
+
+    // Configure tests
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_clamav_test");
+    let temp_dir = make_temp_dir("smtp_clamav_store", true);
+    let store = Arc::new(
+        Store::open(
+            &Config::parse(&format!(
+                concat!(
+                    "store.db.path = \"{}/sqlite.db\"\n",
+                    "store.blob.type = \"local\"\n",
+                    "store.blob.local.path = \"{}\"\n"
+                ),
+                temp_dir.temp_dir.display(),
+                temp_dir.temp_dir.display()
+            ))
+            .unwrap(),
+        )
+        .await
+        .unwrap(),
+    );
+    core.store = Some(store.clone());
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.session.config.data.clamav = Config::parse(CONFIG)
+        .unwrap()
+        .parse_clamav(
+            &ConfigContext::new(&[]),
+            &[
+                EnvelopeKey::Sender,
+                EnvelopeKey::SenderDomain,
+                EnvelopeKey::AuthenticatedAs,
+            ],
+        )
+        .unwrap();
+    assert_eq!(core.session.config.data.clamav.len(), 2);
+    let core = Arc::new(core);
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+
+    // Clean messages are tagged and delivered
+    session
+        .send_message("john@doe.org", &["jane@foobar.org"], "test:no_dkim", "250")
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("X-Virus-Scan: ")
+        .assert_contains("clamav=clean");
+
+    // Infected messages are rejected by default
+    session
+        .send_message(
+            "john@doe.org",
+            &["jane@foobar.org"],
+            INFECTED_MESSAGE,
+            "554 5.7.1 Message rejected: virus Test-Signature found.",
+        )
+        .await;
+    qr.assert_empty_queue();
+
+    // Tag infected messages, scan results supplied by the sender are removed
+    session
+        .send_message(
+            "bill@tag.org",
+            &["jane@foobar.org"],
+            &format!("X-Virus-Scan: forged;\r\n\tclamav=clean\r\n{INFECTED_MESSAGE}"),
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_count("X-Virus-Scan:", 1)
+        .assert_not_contains("forged")
+        .assert_contains("clamav=infected (Test-Signature)");
+
+    // Quarantine infected messages
+    session
+        .send_message(
+            "bill@quarantine.org",
+            &["jane@foobar.org"],
+            INFECTED_MESSAGE,
+            "250",
+        )
+        .await;
+    qr.assert_empty_queue();
+    let messages = store
+        .quarantine_query(QuarantineFilter::default(), 0)
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].source, "clamav");
+    assert_eq!(messages[0].reason, "Virus found: Test-Signature");
+
+    // Unreachable scanners return a temporary failure
+    session
+        .send_message(
+            "bill@error.org",
+            &["jane@foobar.org"],
+            "test:no_dkim",
+            "451 4.3.5",
+        )
+        .await;
+    qr.assert_empty_queue();
+
+    // Authenticated senders are not scanned
+    session.data.authenticated_as = "john".to_string();
+    session
+        .send_message(
+            "john@doe.org",
+            &["jane@foobar.org"],
+            INFECTED_MESSAGE,
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_not_contains("X-Virus-Scan: ");
+
+    // All scans should have reused the same clamd session
+    assert_eq!(connections.load(Ordering::Relaxed), 1);
+    assert_eq!(core.session.config.data.clamav[0].pool.idle_count(), 1);
+}
+
+async fn spawn_mock_clamd(port: u16) -> Arc<AtomicUsize> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let connections_ = connections.clone();
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            connections_.fetch_add(1, Ordering::Relaxed);
+            tokio::spawn(handle_clamd_session(stream));
+        }
+    });
+
+    connections
+}
+
+async fn handle_clamd_session(mut stream: TcpStream) {
+    let mut request_id = 0;
+    loop {
+        // Read NUL terminated command
+        let mut command = Vec::new();
+        loop {
+            match stream.read_u8().await {
+                Ok(0) => break,
+                Ok(ch) => command.push(ch),
+                Err(_) => return,
+            }
+        }
+
+        match command.as_slice() {
+            b"zIDSESSION" => (),
+            b"zEND" => return,
+            b"zINSTREAM" => {
+                let mut contents = Vec::new();
+                loop {
+                    let len = stream.read_u32().await.unwrap() as usize;
+                    if len == 0 {
+                        break;
+                    }
+                    let mut chunk = vec![0u8; len];
+                    stream.read_exact(&mut chunk).await.unwrap();
+                    contents.extend_from_slice(&chunk);
+                }
+
+                request_id += 1;
+                let result = if contents.windows(20).any(|w| w == b"VIRUS-TEST-SIGNATURE") {
+                    "Test-Signature FOUND"
+                } else {
+                    "OK"
+                };
+                stream
+                    .write_all(format!("{request_id}: stream: {result}\0").as_bytes())
+                    .await
+                    .unwrap();
+            }
+            command => panic!("Unexpected command: {:?}", String::from_utf8_lossy(command)),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{sync::Arc, time::Duration};
+
+use store::Store;
+use utils::config::Config;
+
+use crate::smtp::{
+    make_temp_dir,
+    session::{TestSession, VerifyResponse},
+    ParseTestConfig, TestConfig,
+};
+use smtp::{
+    config::{ConfigContext, IfBlock, VerifyStrategy},
+    core::{greylist::GreylistReport, Session, SMTP},
+};
+
+#[tokio::test]
+async fn greylist() {
+    let mut core = SMTP::test();
+    let temp_dir = make_temp_dir("smtp_greylist_store", true);
+    let store = Arc::new(
+        Store::open(
+            &Config::parse(&format!(
+                concat!(
+                    "store.db.path = \"{}/sqlite.db\"\n",
+                    "store.blob.type = \"local\"\n",
+                    "store.blob.local.path = \"{}\"\n"
+                ),
+                temp_dir.temp_dir.display(),
+                temp_dir.temp_dir.display()
+            ))
+            .unwrap(),
+        )
+        .await
+        .unwrap(),
+    );
+    core.store = Some(store.clone());
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.mail_auth.spf.verify_ehlo = IfBlock::new(VerifyStrategy::Disable);
+    core.mail_auth.spf.verify_mail_from = IfBlock::new(VerifyStrategy::Disable);
+    let config = &mut core.session.config.rcpt.greylist;
+    config.enable = r"[{if = 'remote-ip', eq = '10.0.0.100', then = false},
+    {else = true}]"
+        .parse_if(&ConfigContext::new(&[]));
+    config.delay = IfBlock::new(Duration::from_secs(1));
+
+    // First attempt is deferred
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx1.example.net").await;
+    session.mail_from("john@example.net", "250").await;
+    session.rcpt_to("jane@foobar.org", "451 4.7.1").await;
+
+    // Retrying before the delay or from the same network is deferred
+    session.rcpt_to("jane@foobar.org", "451 4.7.1").await;
+    session.data.remote_ip = "10.0.0.2".parse().unwrap();
+    session.rcpt_to("jane@foobar.org", "451 4.7.1").await;
+
+    // Greylisting can be disabled with a rule
+    session.data.remote_ip = "10.0.0.100".parse().unwrap();
+    session.rcpt_to("jane@foobar.org", "250").await;
+    session.data.rcpt_to.clear();
+
+    // Retrying after the delay is accepted
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.rcpt_to("jane@foobar.org", "250").await;
+
+    // Domains that retried successfully are whitelisted
+    session.rset().await;
+    session.mail_from("bill@example.net", "250").await;
+    session.rcpt_to("mike@foobar.org", "250").await;
+
+    // Other domains are still greylisted
+    session.rset().await;
+    session.mail_from("jdoe@example.org", "250").await;
+    session.rcpt_to("mike@foobar.org", "451 4.7.1").await;
+
+    // Purging keeps entries that did not expire
+    session.core.greylist_purge(&store).await.unwrap();
+    session.rcpt_to("mike@foobar.org", "451 4.7.1").await;
+    session.rset().await;
+    session.mail_from("bill@example.net", "250").await;
+    session.rcpt_to("mike@foobar.org", "250").await;
+
+    assert_eq!(
+        session.core.greylist_report(),
+        GreylistReport {
+            greylisted: 5,
+            passed: 1,
+            whitelisted: 2,
+        }
+    );
+}
@@ -0,0 +1,318 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{sync::Arc, time::Duration};
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    make_temp_dir,
+    session::{TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+use mail_parser::decoders::base64::base64_decode;
+use smtp::{
+    config::{session::ConfigSession, ConfigContext, IfBlock},
+    core::{Session, SMTP},
+};
+use store::{quarantine::QuarantineFilter, Store};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::mpsc,
+};
+use utils::config::Config;
+
+const CONFIG: &str = r#"
+[session.hook."filter"]
+url = "http://127.0.0.1:9933/hook"
+stages = ["mail", "rcpt", "data"]
+enable = true
+timeout = "5s"
+
+[session.hook."unreachable"]
+url = "http://127.0.0.1:9934/hook"
+stages = "ehlo"
+enable = [ { if = "remote-ip", eq = "10.0.0.2", then = true },
+           { else = false } ]
+options.tempfail-on-error = true
+"#;
+
+#[tokio::test]
+async fn mta_hooks() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::DEBUG)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Configure tests
+    let mut requests = spawn_mock_hook_server(9933).await;
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_hooks_test");
+    let temp_dir = make_temp_dir("smtp_hooks_store", true);
+    let store = Arc::new(
+        Store::open(
+            &Config::parse(&format!(
+                concat!(
+                    "store.db.path = \"{}/sqlite.db\"\n",
+                    "store.blob.type = \"local\"\n",
+                    "store.blob.local.path = \"{}\"\n"
+                ),
+                temp_dir.temp_dir.display(),
+                temp_dir.temp_dir.display()
+            ))
+            .unwrap(),
+        )
+        .await
+        .unwrap(),
+    );
+    core.store = Some(store.clone());
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.session.config.hooks = Config::parse(CONFIG)
+        .unwrap()
+        .parse_mta_hooks(&ConfigContext::new(&[]))
+        .unwrap();
+    assert_eq!(core.session.config.hooks.len(), 2);
+    let core = Arc::new(core);
+
+    // Failing hooks return a temporary error
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.2".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await.assert_code("451 4.3.5");
+
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+
+    // Reject at the MAIL FROM stage using a custom response
+    session
+        .mail_from("spammer@doe.org", "550 5.7.1 Sender blocked")
+        .await;
+    let request = requests.recv().await.unwrap();
+    assert_eq!(request["context"]["stage"], "mail");
+    assert_eq!(request["context"]["client"]["ip"], "10.0.0.1");
+    assert_eq!(request["context"]["client"]["helo"], "mx.doe.org");
+    assert_eq!(request["envelope"]["from"]["address"], "spammer@doe.org");
+    assert!(request.get("message").is_none());
+
+    // Hooks cannot turn a rejection into a success
+    session
+        .mail_from("sneaky@doe.org", "550 5.7.1 Sender blocked")
+        .await;
+    requests.recv().await.unwrap();
+
+    // Reject at the RCPT TO stage using the default response
+    session.mail_from("john@doe.org", "250").await;
+    session
+        .rcpt_to("blocked@foobar.org", "550 5.7.1 Rejected by policy.")
+        .await;
+    session.rcpt_to("jane@foobar.org", "250").await;
+    session.rcpt_to("bcc@foobar.org", "250").await;
+    for _ in 0..4 {
+        requests.recv().await.unwrap();
+    }
+
+    // Modify the message and its recipients at the DATA stage
+    session.data("test:no_dkim", "250").await;
+    let request = requests.recv().await.unwrap();
+    assert_eq!(request["context"]["stage"], "data");
+    assert_eq!(request["envelope"]["to"][0]["address"], "jane@foobar.org");
+    assert_eq!(request["envelope"]["to"][1]["address"], "bcc@foobar.org");
+    assert!(request["message"]["headers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|h| h[0] == "Subject" && h[1] == "Is dinner ready?"));
+    assert!(String::from_utf8(
+        base64_decode(request["message"]["contents"].as_str().unwrap().as_bytes()).unwrap()
+    )
+    .unwrap()
+    .contains("We lost the game."));
+    let message = qr.read_event().await.unwrap_message();
+    assert_eq!(
+        message
+            .recipients
+            .iter()
+            .map(|r| r.address.as_str())
+            .collect::<Vec<_>>(),
+        vec!["jane@foobar.org", "archive@foobar.org"]
+    );
+    message
+        .read_lines()
+        .assert_contains("X-Hook-Scanned: yes")
+        .assert_contains("Subject: [hooked] Is dinner ready?")
+        .assert_not_contains("Subject: Is dinner ready?");
+    qr.assert_empty_queue();
+
+    // Hold the message in quarantine
+    session
+        .send_message(
+            "quarantine@doe.org",
+            &["jane@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.assert_empty_queue();
+    let messages = store
+        .quarantine_query(QuarantineFilter::default(), 0)
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].source, "hook");
+    assert_eq!(messages[0].reason, "Suspicious content");
+    assert_eq!(messages[0].return_path, "quarantine@doe.org");
+
+    // Discard the message
+    session
+        .send_message(
+            "discard@doe.org",
+            &["jane@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.assert_empty_queue();
+}
+
+async fn spawn_mock_hook_server(port: u16) -> mpsc::Receiver<serde_json::Value> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    let (tx, rx) = mpsc::channel(128);
+
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let tx = tx.clone();
+
+            tokio::spawn(async move {
+                // Read request
+                let mut buf = Vec::new();
+                let mut bytes = [0u8; 4096];
+                let request: serde_json::Value = loop {
+                    match stream.read(&mut bytes).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => buf.extend_from_slice(&bytes[..n]),
+                    }
+                    if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                        let body_len = String::from_utf8_lossy(&buf[..pos])
+                            .lines()
+                            .find_map(|line| {
+                                let (name, value) = line.split_once(':')?;
+                                if name.eq_ignore_ascii_case("content-length") {
+                                    value.trim().parse::<usize>().ok()
+                                } else {
+                                    None
+                                }
+                            })
+                            .unwrap_or(0);
+                        if buf.len() >= pos + 4 + body_len {
+                            break serde_json::from_slice(&buf[pos + 4..pos + 4 + body_len])
+                                .unwrap();
+                        }
+                    }
+                };
+
+                let response = handle_hook_request(&request);
+                tx.send(request).await.unwrap();
+                stream
+                    .write_all(
+                        format!(
+                            concat!(
+                                "HTTP/1.1 200 OK\r\n",
+                                "Content-Type: application/json\r\n",
+                                "Content-Length: {}\r\n",
+                                "Connection: close\r\n\r\n{}"
+                            ),
+                            response.len(),
+                            response
+                        )
+                        .as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+            });
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    rx
+}
+
+fn handle_hook_request(request: &serde_json::Value) -> String {
+    let from = request["envelope"]["from"]["address"]
+        .as_str()
+        .unwrap_or("");
+    let last_rcpt = request["envelope"]["to"]
+        .as_array()
+        .and_then(|to| to.last())
+        .and_then(|rcpt| rcpt["address"].as_str())
+        .unwrap_or("");
+
+    match (request["context"]["stage"].as_str().unwrap(), from) {
+        ("mail", "spammer@doe.org") => serde_json::json!({
+            "action": "reject",
+            "response": {
+                "status": 550,
+                "enhancedStatus": "5.7.1",
+                "message": "Sender blocked"
+            }
+        }),
+        ("mail", "sneaky@doe.org") => serde_json::json!({
+            "action": "reject",
+            "response": {
+                "status": 250,
+                "enhancedStatus": "2.0.0",
+                "message": "Sender blocked"
+            }
+        }),
+        ("rcpt", _) if last_rcpt == "blocked@foobar.org" => serde_json::json!({
+            "action": "reject"
+        }),
+        ("data", "quarantine@doe.org") => serde_json::json!({
+            "action": "quarantine",
+            "response": {
+                "message": "Suspicious content"
+            }
+        }),
+        ("data", "discard@doe.org") => serde_json::json!({
+            "action": "discard"
+        }),
+        ("data", _) => serde_json::json!({
+            "action": "accept",
+            "modifications": [
+                {"type": "addHeader", "name": "X-Hook-Scanned", "value": "yes"},
+                {"type": "changeHeader", "index": 1, "name": "Subject", "value": "[hooked] Is dinner ready?"},
+                {"type": "deleteRecipient", "value": "bcc@foobar.org"},
+                {"type": "addRecipient", "value": "archive@foobar.org"}
+            ]
+        }),
+        _ => serde_json::json!({
+            "action": "accept"
+        }),
+    }
+    .to_string()
+}
@@ -0,0 +1,169 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use utils::config::Config;
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    session::{TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+use smtp::{
+    config::{journal::ConfigJournal, ConfigContext, IfBlock, VerifyStrategy},
+    core::{Session, SMTP},
+    queue::Message,
+};
+
+const CONFIG: &str = r#"
+[server]
+hostname = "mx.example.org"
+
+[journal]
+enable = [ { if = "rcpt-domain", eq = "legal.example.org", then = true },
+           { if = "sender", eq = "ceo@example.org", then = true },
+           { else = false } ]
+address = "journal@archive.example.org"
+"#;
+
+#[tokio::test]
+async fn journal() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::DEBUG)
+            .finish(),
+    )
+    .unwrap();*/
+
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_journal_test");
+
+    // Disable authentication checks, which require DNS lookups
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.mail_auth.dkim.verify = IfBlock::new(VerifyStrategy::Disable);
+    core.mail_auth.arc.verify = IfBlock::new(VerifyStrategy::Disable);
+    core.mail_auth.spf.verify_ehlo = IfBlock::new(VerifyStrategy::Disable);
+    core.mail_auth.spf.verify_mail_from = IfBlock::new(VerifyStrategy::Disable);
+    core.mail_auth.dmarc.verify = IfBlock::new(VerifyStrategy::Disable);
+    core.mail_auth.iprev.verify = IfBlock::new(VerifyStrategy::Disable);
+
+    // Configure journaling
+    let config = Config::parse(CONFIG).unwrap();
+    core.queue.journal = config.parse_journal(&ConfigContext::new(&[])).unwrap();
+
+    // Build session
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+
+    // Messages not matching any condition are not journaled
+    session
+        .send_message(
+            "jane@example.org",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.read_event().await.unwrap_message();
+    qr.assert_empty_queue();
+
+    // A blind copy to a journaled domain produces a report listing all recipients
+    session
+        .send_message(
+            "jane@example.org",
+            &["bill@foobar.org", "compliance@legal.example.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    let message = qr.read_event().await.unwrap_message();
+    assert_eq!(message.recipients.len(), 2);
+    let report = qr.read_event().await.unwrap_message();
+    assert_eq!(report.return_path, "MAILER-DAEMON@mx.example.org");
+    assert_eq!(
+        report
+            .recipients
+            .iter()
+            .map(|r| r.address.as_str())
+            .collect::<Vec<_>>(),
+        vec!["journal@archive.example.org"]
+    );
+    report
+        .read_lines()
+        .assert_contains("To: <journal@archive.example.org>")
+        .assert_contains("Subject: Journal report")
+        .assert_contains("Auto-Submitted: auto-generated")
+        .assert_contains("Sender: jane@example.org")
+        .assert_contains("Recipient: bill@foobar.org")
+        .assert_contains("Recipient: compliance@legal.example.org")
+        .assert_contains("Content-Type: message/rfc822")
+        .assert_contains("Received: from mx.doe.org")
+        .assert_contains("[10.0.0.1]");
+    qr.assert_empty_queue();
+
+    // Sender conditions are evaluated as well
+    session
+        .send_message(
+            "ceo@example.org",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.read_event().await.unwrap_message();
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("Sender: ceo@example.org")
+        .assert_contains("Recipient: bill@foobar.org");
+    qr.assert_empty_queue();
+
+    // Messages generated by the server, such as DSNs or Sieve redirects,
+    // are journaled as well
+    let mut message = Message::new_boxed("", "", "");
+    message
+        .add_recipient("compliance@legal.example.org", &session.core.queue.config)
+        .await;
+    assert!(
+        session
+            .core
+            .queue
+            .queue_message(
+                message,
+                None,
+                b"Subject: Delivery Status Notification\r\n\r\nFailed.\r\n",
+                &tracing::info_span!("journal")
+            )
+            .await
+    );
+    qr.read_event().await.unwrap_message();
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("Subject: Delivery Status Notification")
+        .assert_contains("Recipient: compliance@legal.example.org");
+    qr.assert_empty_queue();
+}
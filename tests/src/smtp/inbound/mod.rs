@@ -32,21 +32,31 @@ use smtp::{
 
 use super::{QueueReceiver, ReportReceiver};
 
+pub mod attachments;
 pub mod auth;
 pub mod basic;
+pub mod clamav;
 pub mod data;
 pub mod dmarc;
 pub mod dnsrbl;
 pub mod ehlo;
+pub mod greylist;
+pub mod hooks;
+pub mod journal;
 pub mod limits;
 pub mod mail;
 pub mod milter;
+pub mod quarantine;
 pub mod rcpt;
 pub mod rewrite;
 pub mod scripts;
 pub mod sign;
+pub mod spam;
+pub mod srs;
+pub mod suppression;
 pub mod throttle;
 pub mod vrfy;
+pub mod webhooks;
 
 impl QueueReceiver {
     pub async fn read_event(&mut self) -> queue::Event {
@@ -0,0 +1,337 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    make_temp_dir,
+    session::{load_test_message, TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+use sieve::Envelope;
+use smtp::{
+    config::{scripts::ConfigSieve, ConfigContext, IfBlock},
+    core::{scripts::ScriptAction, Session, SMTP},
+};
+use store::{quarantine::QuarantineFilter, write::now, Store};
+use utils::config::Config;
+
+const CONFIG: &str = r#"
+[sieve]
+hostname = "mx.foobar.org"
+
+[sieve.scripts]
+data = '''
+require ["envelope", "fileinto"];
+
+if envelope :localpart :is "to" "jane" {
+    fileinto "Suspicious attachment";
+}
+'''
+"#;
+
+#[tokio::test]
+async fn quarantine() {
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_quarantine_test");
+    let temp_dir = make_temp_dir("smtp_quarantine_store", true);
+    let store = Arc::new(
+        Store::open(
+            &Config::parse(&format!(
+                concat!(
+                    "store.db.path = \"{}/sqlite.db\"\n",
+                    "store.blob.type = \"local\"\n",
+                    "store.blob.local.path = \"{}\"\n"
+                ),
+                temp_dir.temp_dir.display(),
+                temp_dir.temp_dir.display()
+            ))
+            .unwrap(),
+        )
+        .await
+        .unwrap(),
+    );
+    let mut ctx = ConfigContext::new(&[]);
+    core.sieve = Config::parse(CONFIG)
+        .unwrap()
+        .parse_sieve(&mut ctx)
+        .unwrap();
+    core.store = Some(store.clone());
+    core.quarantine.digest.enable = IfBlock::new(true);
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.session.config.data.script = IfBlock::new(ctx.scripts.get("data").cloned());
+
+    // Dry-run the data script
+    let message = load_test_message("no_dkim", "messages");
+    assert_eq!(
+        core.test_script(
+            "data",
+            message.as_bytes(),
+            vec![
+                (Envelope::From, "bill@example.net".to_string()),
+                (Envelope::To, "jane@foobar.org".to_string()),
+            ],
+        )
+        .await
+        .unwrap(),
+        vec![ScriptAction::Quarantine {
+            reason: "Suspicious attachment".to_string()
+        }]
+    );
+
+    // Messages to jane are held in quarantine
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.example.net").await;
+    session
+        .send_message(
+            "bill@example.net",
+            &["john@foobar.org", "jane@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.assert_empty_queue();
+    session
+        .send_message(
+            "bill@example.net",
+            &["mike@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.read_event().await.unwrap_message();
+    qr.assert_empty_queue();
+
+    let messages = store
+        .quarantine_query(QuarantineFilter::default(), 0)
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 1);
+    let message = messages.into_iter().next().unwrap();
+    assert_eq!(message.source, "sieve");
+    assert_eq!(message.reason, "Suspicious attachment");
+    assert_eq!(message.return_path, "bill@example.net");
+    assert_eq!(message.subject, "Is dinner ready?");
+    assert_eq!(message.remote_ip, "10.0.0.1");
+    assert_eq!(
+        message.recipients,
+        vec!["jane@foobar.org".to_string(), "john@foobar.org".to_string()]
+    );
+    assert!(!message.notified);
+    assert!(store
+        .quarantine_query(
+            QuarantineFilter {
+                recipient: "mike@foobar.org".to_string().into(),
+                ..Default::default()
+            },
+            0
+        )
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Preview includes the original headers and text
+    let preview = session
+        .core
+        .quarantine_preview(message.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(preview.headers.contains("Subject: Is dinner ready?"));
+    assert!(!preview.preview.is_empty());
+
+    // Each recipient receives a digest with a signed release link
+    session.core.quarantine_send_digests().await;
+    let mut links = Vec::new();
+    for _ in 0..2 {
+        let digest = qr.read_event().await.unwrap_message();
+        assert_eq!(digest.recipients.len(), 1);
+        let rcpt = digest.recipients[0].address.clone();
+        let lines = digest
+            .read_lines()
+            .assert_contains("Subject: Quarantined messages digest")
+            .assert_contains("Reason: Suspicious attachment");
+        let link = lines
+            .iter()
+            .find_map(|line| line.strip_prefix("Release: "))
+            .unwrap()
+            .trim_end()
+            .to_string();
+        links.push((rcpt, link));
+    }
+    qr.assert_empty_queue();
+    assert!(
+        store
+            .get_quarantined(message.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .notified
+    );
+
+    // Digests are only sent once
+    session.core.quarantine_send_digests().await;
+    qr.assert_empty_queue();
+
+    // Verify release links
+    for (rcpt, link) in &links {
+        let query = link
+            .strip_prefix("https://mx.example.org/quarantine/release?")
+            .unwrap();
+        let params = query
+            .split('&')
+            .filter_map(|param| param.split_once('='))
+            .map(|(k, v)| (k.to_string(), v.replace("%40", "@")))
+            .collect::<std::collections::HashMap<_, _>>();
+        let id = params["id"].parse::<u32>().unwrap();
+        let nonce = params["nonce"].parse::<u64>().unwrap();
+        let expires = params["expires"].parse::<u64>().unwrap();
+        assert_eq!(id, message.id);
+        assert_eq!(nonce, message.nonce);
+        assert_eq!(&params["rcpt"], rcpt);
+        assert!(session
+            .core
+            .quarantine_verify_link(id, nonce, rcpt, expires, &params["sig"]));
+        assert!(!session.core.quarantine_verify_link(
+            id,
+            nonce,
+            "mike@foobar.org",
+            expires,
+            &params["sig"]
+        ));
+        assert!(!session
+            .core
+            .quarantine_verify_link(id, nonce + 1, rcpt, expires, &params["sig"]));
+        assert!(!session
+            .core
+            .quarantine_verify_link(id, nonce, rcpt, expires + 1, &params["sig"]));
+        assert!(!session
+            .core
+            .quarantine_verify_link(id, nonce, rcpt, now() - 1, &params["sig"]));
+    }
+
+    // Release the message to a single recipient, concurrent releases queue it only once
+    let (first, second) = tokio::join!(
+        session
+            .core
+            .quarantine_release(message.id, Some(message.nonce), Some("jane@foobar.org")),
+        session
+            .core
+            .quarantine_release(message.id, Some(message.nonce), Some("jane@foobar.org"))
+    );
+    let mut results = vec![first.unwrap(), second.unwrap()];
+    results.sort();
+    assert_eq!(
+        results,
+        vec![None, Some(vec!["jane@foobar.org".to_string()])]
+    );
+    let released = qr.read_event().await.unwrap_message();
+    assert_eq!(released.return_path, "bill@example.net");
+    assert_eq!(released.recipients.len(), 1);
+    assert_eq!(released.recipients[0].address, "jane@foobar.org");
+    released
+        .read_lines()
+        .assert_contains("Subject: Is dinner ready?");
+    qr.assert_empty_queue();
+    assert_eq!(
+        session
+            .core
+            .quarantine_release(message.id, Some(message.nonce), Some("jane@foobar.org"))
+            .await
+            .unwrap(),
+        None
+    );
+
+    // Releasing the remaining recipients removes the message from quarantine
+    assert_eq!(
+        session
+            .core
+            .quarantine_release(message.id, None, None)
+            .await
+            .unwrap(),
+        Some(vec!["john@foobar.org".to_string()])
+    );
+    assert_eq!(
+        qr.read_event().await.unwrap_message().recipients[0].address,
+        "john@foobar.org"
+    );
+    assert!(store.get_quarantined(message.id).await.unwrap().is_none());
+    assert!(store
+        .get_quarantined_blob(message.id)
+        .await
+        .unwrap()
+        .is_none());
+
+    // Expired messages are purged
+    session
+        .send_message(
+            "bill@example.net",
+            &["jane@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.assert_empty_queue();
+    let released_nonce = message.nonce;
+    let message = store
+        .quarantine_query(QuarantineFilter::default(), 0)
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    // Ids are reused, links issued for a released message can not release a newer one
+    assert_ne!(message.nonce, released_nonce);
+    assert_eq!(
+        session
+            .core
+            .quarantine_release(message.id, Some(released_nonce), Some("jane@foobar.org"))
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        store
+            .get_quarantined(message.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .recipients,
+        vec!["jane@foobar.org".to_string()]
+    );
+    session.core.quarantine_purge(&store).await.unwrap();
+    assert!(store.get_quarantined(message.id).await.unwrap().is_some());
+    store
+        .purge_expired_quarantine(message.expires)
+        .await
+        .unwrap();
+    assert!(store.get_quarantined(message.id).await.unwrap().is_none());
+    assert!(store
+        .get_quarantined_blob(message.id)
+        .await
+        .unwrap()
+        .is_none());
+}
@@ -25,13 +25,14 @@ use std::path::PathBuf;
 
 use crate::smtp::{
     inbound::{sign::TextConfigContext, TestMessage, TestQueueEvent},
-    session::{TestSession, VerifyResponse},
+    session::{load_test_message, TestSession, VerifyResponse},
     TestConfig, TestSMTP,
 };
 use directory::config::ConfigDirectory;
+use sieve::Envelope;
 use smtp::{
     config::{scripts::ConfigSieve, session::ConfigSession, ConfigContext, EnvelopeKey, IfBlock},
-    core::{Session, SMTP},
+    core::{scripts::ScriptAction, Session, SMTP},
 };
 use utils::config::Config;
 
@@ -349,6 +350,52 @@ async fn sieve_scripts() {
         .assert_not_contains("From: Joe SixPack <joe@football.example.com>");
     qr.assert_empty_queue();
 
+    // Dry-run the data script, nothing should be queued
+    let message = load_test_message("no_dkim", "messages");
+    assert_eq!(
+        session
+            .core
+            .test_script(
+                "data",
+                message.as_bytes(),
+                vec![
+                    (Envelope::From, "test@example.net".to_string()),
+                    (Envelope::To, "thomas@foobar.gov".to_string()),
+                ],
+            )
+            .await
+            .unwrap(),
+        vec![
+            ScriptAction::Redirect {
+                recipients: vec!["redirect@here.email".to_string()]
+            },
+            ScriptAction::Discard
+        ]
+    );
+    assert_eq!(
+        session
+            .core
+            .test_script(
+                "data",
+                message.as_bytes(),
+                vec![
+                    (Envelope::From, "test@example.net".to_string()),
+                    (Envelope::To, "bill@foobar.net".to_string()),
+                ],
+            )
+            .await
+            .unwrap(),
+        vec![ScriptAction::Reject {
+            reason: "Bill cannot receive messages.".to_string()
+        }]
+    );
+    assert!(session
+        .core
+        .test_script("unknown", message.as_bytes(), vec![])
+        .await
+        .is_none());
+    qr.assert_empty_queue();
+
     // Test pipes
     session.data.remote_ip = "10.0.0.123".parse().unwrap();
     session
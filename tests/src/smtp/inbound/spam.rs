@@ -0,0 +1,309 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use directory::config::ConfigDirectory;
+use store::Store;
+use utils::config::Config;
+
+use crate::smtp::{
+    inbound::TestMessage,
+    make_temp_dir,
+    session::{TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+use smtp::{
+    config::{spam::ConfigSpamFilter, ConfigContext, IfBlock, VerifyStrategy},
+    core::{
+        spam::{bayes_totals, BayesCounts, SpamStatus},
+        Session, SMTP,
+    },
+};
+
+const CONFIG: &str = r#"
+[directory."local"]
+type = "memory"
+
+[directory."local".lookup]
+spam-domains = ["blocked.example"]
+
+[spam-filter]
+enable = true
+
+[spam-filter.threshold]
+spam = 5.0
+reject = 8.0
+
+[spam-filter.lists]
+block = "local/spam-domains"
+
+[spam-filter.bayes]
+min-learns = 5
+"#;
+
+#[tokio::test]
+async fn spam_filter() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::DEBUG)
+            .finish(),
+    )
+    .unwrap();*/
+
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_spam_test");
+    let temp_dir = make_temp_dir("smtp_spam_store", true);
+    let store = Arc::new(
+        Store::open(
+            &Config::parse(&format!(
+                concat!(
+                    "store.db.path = \"{}/sqlite.db\"\n",
+                    "store.blob.type = \"local\"\n",
+                    "store.blob.local.path = \"{}\"\n"
+                ),
+                temp_dir.temp_dir.display(),
+                temp_dir.temp_dir.display()
+            ))
+            .unwrap(),
+        )
+        .await
+        .unwrap(),
+    );
+
+    // Disable authentication checks, which require DNS lookups
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.mail_auth.dkim.verify = IfBlock::new(VerifyStrategy::Disable);
+    core.mail_auth.arc.verify = IfBlock::new(VerifyStrategy::Disable);
+    core.mail_auth.spf.verify_ehlo = IfBlock::new(VerifyStrategy::Disable);
+    core.mail_auth.spf.verify_mail_from = IfBlock::new(VerifyStrategy::Disable);
+    core.mail_auth.dmarc.verify = IfBlock::new(VerifyStrategy::Disable);
+    core.mail_auth.iprev.verify = IfBlock::new(VerifyStrategy::Disable);
+
+    // Configure spam filter
+    let config = Config::parse(CONFIG).unwrap();
+    let mut ctx = ConfigContext::new(&[]);
+    ctx.directory = config.parse_directory().unwrap();
+    core.spam = config.parse_spam_filter(&ctx).unwrap();
+    core.store = Some(store.clone());
+
+    // Train the classifier
+    for num in 0..5 {
+        assert!(core
+            .spam_train(&store, 0, spam_message(num).as_bytes(), true)
+            .await
+            .unwrap());
+        assert!(core
+            .spam_train(&store, 0, ham_message(num).as_bytes(), false)
+            .await
+            .unwrap());
+    }
+    for account_id in [Some(0), None] {
+        assert_eq!(
+            bayes_totals(&store, account_id).await.unwrap(),
+            Some(BayesCounts { spam: 5, ham: 5 })
+        );
+    }
+
+    // Learning a message twice should have no effect, relearning should revert its class
+    assert!(!core
+        .spam_train(&store, 0, spam_message(0).as_bytes(), true)
+        .await
+        .unwrap());
+    assert!(core
+        .spam_train(&store, 0, ham_message(0).as_bytes(), true)
+        .await
+        .unwrap());
+    assert_eq!(
+        bayes_totals(&store, Some(0)).await.unwrap(),
+        Some(BayesCounts { spam: 6, ham: 4 })
+    );
+    assert!(core
+        .spam_train(&store, 0, ham_message(0).as_bytes(), false)
+        .await
+        .unwrap());
+    assert_eq!(
+        bayes_totals(&store, Some(0)).await.unwrap(),
+        Some(BayesCounts { spam: 5, ham: 5 })
+    );
+
+    // Build session
+    let core = Arc::new(core);
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+
+    // Ham should be accepted and flagged as such
+    session
+        .send_message(
+            "jane@example.org",
+            &["bill@foobar.org"],
+            &ham_message(100),
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("X-Spam-Status: No")
+        .assert_contains("BAYES_HAM")
+        .assert_not_contains("X-Spam-Flag");
+
+    // Spam headers supplied by the sender are removed
+    session
+        .send_message(
+            "jane@example.org",
+            &["bill@foobar.org"],
+            &format!(
+                "X-Spam-Status: Yes, score=99.00\r\nX-Spam-Flag: YES\r\n{}",
+                ham_message(101)
+            ),
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_count("X-Spam-Status:", 1)
+        .assert_contains("X-Spam-Status: No")
+        .assert_not_contains("X-Spam-Flag");
+
+    // Spam should be accepted and flagged
+    session
+        .send_message(
+            "winner@lucky-prize.biz",
+            &["bill@foobar.org"],
+            concat!(
+                "From: Lottery Office <winner@lucky-prize.biz>\r\n",
+                "To: bill@foobar.org\r\n",
+                "Subject: CLAIM YOUR FREE CASH PRIZE NOW\r\n",
+                "\r\n",
+                "Congratulations winner! You won a free cash prize. ",
+                "Click http://192.168.1.1/claim to claim your lottery money now.\r\n"
+            ),
+            "250",
+        )
+        .await;
+    let spam = qr.read_event().await.unwrap_message();
+    spam.read_lines()
+        .assert_contains("X-Spam-Status: Yes")
+        .assert_contains("X-Spam-Flag: YES")
+        .assert_contains("MISSING_DATE")
+        .assert_contains("MISSING_MESSAGE_ID")
+        .assert_contains("SUBJECT_ALL_CAPS")
+        .assert_contains("URL_IP_HOST")
+        .assert_contains("BAYES_SPAM");
+
+    // Messages linking to blocked domains exceed the reject threshold
+    session
+        .send_message(
+            "winner@lucky-prize.biz",
+            &["bill@foobar.org"],
+            concat!(
+                "From: Lottery Office <winner@lucky-prize.biz>\r\n",
+                "To: bill@foobar.org\r\n",
+                "Subject: CLAIM YOUR FREE CASH PRIZE NOW\r\n",
+                "\r\n",
+                "Visit https://www.blocked.example/claim today.\r\n"
+            ),
+            "550 5.7.1",
+        )
+        .await;
+    qr.assert_empty_queue();
+
+    // Each account scores messages with its own model, accounts that did not
+    // learn enough messages use the server-wide verdict
+    for num in 10..15 {
+        assert!(core
+            .spam_train(&store, 1, spam_message(num).as_bytes(), false)
+            .await
+            .unwrap());
+        assert!(core
+            .spam_train(&store, 1, ham_message(num).as_bytes(), true)
+            .await
+            .unwrap());
+    }
+    assert_eq!(
+        bayes_totals(&store, None).await.unwrap(),
+        Some(BayesCounts { spam: 10, ham: 10 })
+    );
+    let raw_message = spam.read_message();
+    let status = SpamStatus::parse(raw_message.as_bytes()).unwrap();
+    let message = mail_parser::Message::parse(raw_message.as_bytes()).unwrap();
+    assert!(status.is_spam);
+    assert_eq!(
+        core.bayes_rescore(&store, 0, &message, &status)
+            .await
+            .unwrap(),
+        Some(true)
+    );
+    assert_eq!(
+        core.bayes_rescore(&store, 1, &message, &status)
+            .await
+            .unwrap(),
+        Some(false)
+    );
+    assert_eq!(
+        core.bayes_rescore(&store, 2, &message, &status)
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+fn spam_message(num: usize) -> String {
+    format!(
+        concat!(
+            "From: Lottery Office <winner@lucky-prize.biz>\r\n",
+            "To: bill@foobar.org\r\n",
+            "Subject: Claim your free prize now {}\r\n",
+            "Date: Mon, 1 May 2023 10:00:00 +0000\r\n",
+            "Message-ID: <spam{}@lucky-prize.biz>\r\n",
+            "\r\n",
+            "Congratulations winner! You won a free cash prize. Click ",
+            "http://lucky-prize.biz/claim to claim your lottery money now. ",
+            "Limited offer, act fast!\r\n"
+        ),
+        num, num
+    )
+}
+
+fn ham_message(num: usize) -> String {
+    format!(
+        concat!(
+            "From: Jane Doe <jane@example.org>\r\n",
+            "To: bill@foobar.org\r\n",
+            "Subject: Quarterly planning meeting notes {}\r\n",
+            "Date: Mon, 1 May 2023 10:00:00 +0000\r\n",
+            "Message-ID: <ham{}@example.org>\r\n",
+            "\r\n",
+            "Hi Bill, here are the notes from our planning meeting. ",
+            "Let's review the budget and schedule for the project next week.\r\n",
+            "Regards, Jane\r\n"
+        ),
+        num, num
+    )
+}
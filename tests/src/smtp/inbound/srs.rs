@@ -0,0 +1,173 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use directory::config::ConfigDirectory;
+use utils::config::Config;
+
+use crate::smtp::{
+    inbound::TestQueueEvent, session::TestSession, ParseTestConfig, TestConfig, TestSMTP,
+};
+use smtp::{
+    config::{ConfigContext, IfBlock, MaybeDynValue, SrsConfig},
+    core::{srs::SrsError, Session, SMTP},
+};
+
+const DIRECTORY: &str = r#"
+[directory."local"]
+type = "memory"
+
+[[directory."local".users]]
+name = "jane"
+description = "Jane Doe"
+secret = "p4ssw0rd"
+email = "jane@foobar.org"
+
+[[directory."local".users]]
+name = "bill"
+description = "Bill Foobar"
+secret = "p4ssw0rd"
+email = "bill@foobar.org"
+
+[directory."local".lookup]
+domains = ["foobar.org"]
+"#;
+
+#[tokio::test]
+async fn srs() {
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_srs_test");
+    let directory = Config::parse(DIRECTORY).unwrap().parse_directory().unwrap();
+    let config = &mut core.session.config.rcpt;
+    config.directory = IfBlock::new(Some(MaybeDynValue::Static(
+        directory.directories.get("local").unwrap().clone(),
+    )));
+    config.relay = r"[{if = 'remote-ip', eq = '10.0.0.1', then = false},
+    {else = true}]"
+        .parse_if(&ConfigContext::new(&[]));
+    core.mail_auth.srs = SrsConfig {
+        enable: IfBlock::new(true),
+        domain: "foobar.org".to_string(),
+        secrets: vec![b"new-secret".to_vec(), b"old-secret".to_vec()],
+        max_age: Duration::from_secs(21 * 86400),
+    };
+
+    // Forwarding to a remote domain rewrites the envelope sender
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.2".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.example.net").await;
+    session
+        .send_message(
+            "john@example.net",
+            &["jane@external.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    let message = qr.read_event().await.unwrap_message();
+    let srs_address = message.return_path.clone();
+    assert!(srs_address.starts_with("SRS0="), "{srs_address}");
+    assert!(
+        srs_address.ends_with("=example.net=john@foobar.org"),
+        "{srs_address}"
+    );
+    assert_eq!(message.return_path_domain, "foobar.org");
+
+    // Local recipients and local senders are not rewritten
+    session
+        .send_message(
+            "john@example.net",
+            &["jane@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    assert_eq!(
+        qr.read_event().await.unwrap_message().return_path,
+        "john@example.net"
+    );
+    session
+        .send_message(
+            "bill@foobar.org",
+            &["jane@external.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    assert_eq!(
+        qr.read_event().await.unwrap_message().return_path,
+        "bill@foobar.org"
+    );
+    qr.assert_empty_queue();
+
+    // Already rewritten addresses become SRS1
+    let srs = &session.core.mail_auth.srs;
+    let srs1_address = srs
+        .forward("SRS0=HHHH=TT=example.net=john@otherhop.org")
+        .unwrap();
+    assert!(
+        srs1_address.starts_with("SRS1=")
+            && srs1_address.ends_with("=otherhop.org==HHHH=TT=example.net=john@foobar.org"),
+        "{srs1_address}"
+    );
+    assert_eq!(
+        srs.reverse(&srs1_address),
+        Ok(Some(
+            "SRS0=HHHH=TT=example.net=john@otherhop.org".to_string()
+        ))
+    );
+    assert_eq!(srs.reverse("john@example.net"), Ok(None));
+
+    // Bounces are delivered to the original sender, even when relaying is disabled
+    let mut session = Session::test(session.core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.external.org").await;
+    session.mail_from("<>", "250").await;
+    session.rcpt_to(&srs_address, "250").await;
+    assert_eq!(
+        session.data.rcpt_to.last().unwrap().address,
+        "john@example.net"
+    );
+
+    // Tampered addresses are rejected
+    let tampered = srs_address.replace("=john@", "=jane@");
+    session.rcpt_to(&tampered, "550 5.1.1").await;
+    session.rcpt_to("SRS0=abcd@foobar.org", "550 5.1.1").await;
+
+    // Secrets can be rotated, addresses signed with older secrets remain valid
+    let mut srs = SrsConfig {
+        enable: IfBlock::new(true),
+        domain: "foobar.org".to_string(),
+        secrets: vec![b"newer-secret".to_vec(), b"new-secret".to_vec()],
+        max_age: Duration::from_secs(21 * 86400),
+    };
+    assert_eq!(
+        srs.reverse(&srs_address),
+        Ok(Some("john@example.net".to_string()))
+    );
+    srs.secrets = vec![b"newer-secret".to_vec()];
+    assert_eq!(srs.reverse(&srs_address), Err(SrsError::Invalid));
+}
@@ -0,0 +1,177 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use mail_auth::report::Feedback;
+use store::{write::now, Store};
+use utils::config::Config;
+
+use crate::smtp::{make_temp_dir, session::TestSession, TestConfig};
+use smtp::{
+    config::IfBlock,
+    core::{suppression::SuppressionReason, Session, SMTP},
+};
+
+#[tokio::test]
+async fn suppression() {
+    let mut core = SMTP::test();
+    let temp_dir = make_temp_dir("smtp_suppression_store", true);
+    let store = Arc::new(
+        Store::open(
+            &Config::parse(&format!(
+                concat!(
+                    "store.db.path = \"{}/sqlite.db\"\n",
+                    "store.blob.type = \"local\"\n",
+                    "store.blob.local.path = \"{}\"\n"
+                ),
+                temp_dir.temp_dir.display(),
+                temp_dir.temp_dir.display()
+            ))
+            .unwrap(),
+        )
+        .await
+        .unwrap(),
+    );
+    core.store = Some(store.clone());
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.session.config.rcpt.suppression.enable = IfBlock::new(true);
+
+    // Add entries
+    let now = now();
+    core.suppress(
+        "Bounced@Foobar.org",
+        SuppressionReason::Bounce,
+        "550 5.1.1 No such user",
+        Some(now + 3600),
+    )
+    .await
+    .unwrap();
+    core.suppress("blocked.org", SuppressionReason::Manual, "", None)
+        .await
+        .unwrap();
+    core.suppress(
+        "expired@foobar.org",
+        SuppressionReason::Complaint,
+        "",
+        Some(now - 1),
+    )
+    .await
+    .unwrap();
+    assert!(core.is_suppressed("bounced@foobar.org").await.unwrap());
+    assert!(core.is_suppressed("anyone@blocked.org").await.unwrap());
+    assert!(!core.is_suppressed("expired@foobar.org").await.unwrap());
+    assert!(!core.is_suppressed("jane@foobar.org").await.unwrap());
+
+    // Suppressed recipients are rejected for authenticated senders only
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.example.org").await;
+    session.mail_from("john@example.org", "250").await;
+    session.rcpt_to("bounced@foobar.org", "250").await;
+    session.rcpt_to("mike@blocked.org", "250").await;
+    session.rset().await;
+    session.data.authenticated_as = "john".to_string();
+    session.mail_from("john@example.org", "250").await;
+    session.rcpt_to("bounced@foobar.org", "550 5.1.1").await;
+    session.rcpt_to("mike@blocked.org", "550 5.1.1").await;
+    session.rcpt_to("expired@foobar.org", "250").await;
+    session.rcpt_to("jane@foobar.org", "250").await;
+
+    // List entries
+    let entries = session.core.suppression_list(None).await.unwrap();
+    assert!(entries.iter().all(|entry| entry.created >= now));
+    assert_eq!(
+        entries
+            .into_iter()
+            .map(|entry| (entry.address, entry.reason, entry.expires, entry.details))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                "blocked.org".to_string(),
+                SuppressionReason::Manual,
+                None,
+                "".to_string()
+            ),
+            (
+                "bounced@foobar.org".to_string(),
+                SuppressionReason::Bounce,
+                Some(now + 3600),
+                "550 5.1.1 No such user".to_string()
+            ),
+        ]
+    );
+    assert_eq!(
+        session
+            .core
+            .suppression_list(Some("foobar.org"))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|e| e.address)
+            .collect::<Vec<_>>(),
+        vec!["bounced@foobar.org".to_string()]
+    );
+
+    // Remove entries
+    assert!(session.core.unsuppress("bounced@foobar.org").await.unwrap());
+    assert!(!session.core.unsuppress("bounced@foobar.org").await.unwrap());
+    session.rcpt_to("bounced@foobar.org", "250").await;
+
+    // Purge removes expired entries only
+    session.core.suppression_purge(&store).await.unwrap();
+    assert!(!session.core.unsuppress("expired@foobar.org").await.unwrap());
+    assert!(session
+        .core
+        .is_suppressed("mike@blocked.org")
+        .await
+        .unwrap());
+
+    // Complaints suppress exact addresses only
+    for (rcpt, expected) in [
+        ("<Complainer@Foobar.org>", true),
+        ("<complaints.org>", false),
+        ("<@complaints.org>", false),
+    ] {
+        let arf = format!(
+            "Feedback-Type: abuse\r\nUser-Agent: fbl/1.0\r\nVersion: 1\r\nOriginal-Rcpt-To: {rcpt}\r\n"
+        );
+        let feedback = Feedback::parse_arf(arf.as_bytes()).unwrap();
+        session
+            .core
+            .suppress_complaint(&feedback, "fbl.example.org")
+            .await;
+        let address = rcpt.trim_start_matches('<').trim_end_matches('>');
+        assert_eq!(
+            session.core.is_suppressed(address).await.unwrap(),
+            expected,
+            "{address}"
+        );
+    }
+    assert!(!session
+        .core
+        .is_suppressed("anyone@complaints.org")
+        .await
+        .unwrap());
+}
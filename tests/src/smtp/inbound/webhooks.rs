@@ -0,0 +1,312 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::smtp::{
+    inbound::TestQueueEvent, make_temp_dir, session::TestSession, TestConfig, TestSMTP,
+};
+use smtp::{
+    config::{scripts::ConfigSieve, webhook::ConfigWebhook, ConfigContext, IfBlock, WebhookType},
+    core::{
+        webhook::{SpawnWebhooks, WebhookEvent},
+        Session, SMTP,
+    },
+};
+use store::Store;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::mpsc,
+};
+use utils::config::Config;
+
+const CONFIG: &str = r#"
+[sieve]
+hostname = "mx.foobar.org"
+
+[sieve.scripts]
+rcpt = '''
+require ["envelope", "reject"];
+
+if envelope :localpart :is "to" "jane" {
+    reject "550 5.1.1 Mailbox unavailable.";
+}
+'''
+
+[webhook."crm"]
+url = "http://127.0.0.1:9931/events"
+events = ["message.queued", "message.rejected", "delivery.failed"]
+secret = "my-secret"
+batch.size = 10
+batch.wait = "100ms"
+retry.backoff = "1s"
+
+[webhook."security"]
+url = "https://security.example.org/"
+events = "auth.failed"
+retry.max-attempts = 3
+"#;
+
+struct WebhookRequest {
+    headers: Vec<(String, String)>,
+    body: serde_json::Value,
+}
+
+#[tokio::test]
+async fn webhooks() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::DEBUG)
+            .finish(),
+    )
+    .unwrap();*/
+
+    let config = Config::parse(CONFIG).unwrap();
+    let webhooks = config.parse_webhooks().unwrap();
+    assert_eq!(webhooks.hooks.len(), 2);
+    let crm = webhooks.hooks.iter().find(|h| h.id == "crm").unwrap();
+    assert_eq!(crm.batch_wait, Duration::from_millis(100));
+    assert_eq!(crm.secret.as_deref(), Some(&b"my-secret"[..]));
+    assert!(crm.accepts(WebhookType::MessageQueued));
+    assert!(!crm.accepts(WebhookType::AuthFailed));
+    let security = webhooks.hooks.iter().find(|h| h.id == "security").unwrap();
+    assert_eq!(security.events, vec![WebhookType::AuthFailed]);
+    assert_eq!(security.retry_max_attempts, 3);
+
+    // Queued messages and script rejections produce events
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_webhook_test");
+    let mut ctx = ConfigContext::new(&[]);
+    core.sieve = config.parse_sieve(&mut ctx).unwrap();
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.session.config.rcpt.script = IfBlock::new(ctx.scripts.get("rcpt").cloned());
+    core.webhook.config = config.parse_webhooks().unwrap();
+    let (tx, mut rx) = mpsc::channel(128);
+    core.webhook.tx = tx;
+
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.example.net").await;
+    session.mail_from("bill@example.net", "250").await;
+    session.rcpt_to("jane@foobar.org", "550 5.1.1").await;
+    let event = rx.recv().await.unwrap();
+    assert_eq!(event.type_, WebhookType::MessageRejected);
+    assert_eq!(event.data["source"], "sieve");
+    assert_eq!(event.data["from"], "bill@example.net");
+    assert_eq!(event.data["remoteIp"], "10.0.0.1");
+    assert_eq!(event.data["response"], "550 5.1.1 Mailbox unavailable.");
+
+    session.rcpt_to("john@foobar.org", "250").await;
+    session.data("test:no_dkim", "250").await;
+    let message = qr.read_event().await.unwrap_message();
+    let event = rx.recv().await.unwrap();
+    assert_eq!(event.type_, WebhookType::MessageQueued);
+    assert_eq!(event.data["queueId"], message.id);
+    assert_eq!(event.data["from"], "bill@example.net");
+    assert_eq!(event.data["to"][0], "john@foobar.org");
+    assert!(rx.try_recv().is_err());
+
+    // Events are batched, signed and retried until the endpoint accepts them
+    let temp_dir = make_temp_dir("smtp_webhook_store", true);
+    let store = Arc::new(
+        Store::open(
+            &Config::parse(&format!(
+                concat!(
+                    "store.db.path = \"{}/sqlite.db\"\n",
+                    "store.blob.type = \"local\"\n",
+                    "store.blob.local.path = \"{}\"\n"
+                ),
+                temp_dir.temp_dir.display(),
+                temp_dir.temp_dir.display()
+            ))
+            .unwrap(),
+        )
+        .await
+        .unwrap(),
+    );
+    let mut requests = spawn_webhook_server(9931, 1).await;
+    let core = build_dispatcher(&config, store.clone(), None);
+    core.webhook_event(
+        WebhookType::MessageQueued,
+        serde_json::json!({"queueId": 1}),
+    )
+    .await;
+    core.webhook_event(
+        WebhookType::AuthFailed,
+        serde_json::json!({"username": "john"}),
+    )
+    .await;
+    core.webhook_event(
+        WebhookType::DeliveryFailed,
+        serde_json::json!({"queueId": 2}),
+    )
+    .await;
+    let request = tokio::time::timeout(Duration::from_secs(5), requests.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    let events = request.body.as_array().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["type"], "message.queued");
+    assert_eq!(events[0]["data"]["queueId"], 1);
+    assert_eq!(events[1]["type"], "delivery.failed");
+    assert_eq!(events[1]["data"]["queueId"], 2);
+    assert_ne!(events[0]["id"], events[1]["id"]);
+    assert_eq!(request.header("x-webhook-id"), "crm");
+    assert!(!request.header("x-webhook-timestamp").is_empty());
+    let signature = request.header("x-webhook-signature");
+    assert!(
+        signature.starts_with("sha256=") && signature.len() == 71,
+        "{signature}"
+    );
+
+    // Undelivered events are kept in the outbox across restarts
+    build_dispatcher(&config, store.clone(), "http://127.0.0.1:9932/".into())
+        .webhook_event(
+            WebhookType::MessageRejected,
+            serde_json::json!({"source": "milter"}),
+        )
+        .await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    build_dispatcher(&config, store, None);
+    let request = tokio::time::timeout(Duration::from_secs(5), requests.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    let events = request.body.as_array().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["type"], "message.rejected");
+    assert_eq!(events[0]["data"]["source"], "milter");
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), requests.recv())
+            .await
+            .is_err()
+    );
+}
+
+fn build_dispatcher(config: &Config, store: Arc<Store>, url: Option<&str>) -> Arc<SMTP> {
+    let mut core = SMTP::test();
+    let mut webhooks = config.parse_webhooks().unwrap();
+    webhooks.hooks.retain(|hook| hook.id == "crm");
+    if let Some(url) = url {
+        // Point the outbox to an unreachable endpoint and never retry
+        let mut hook = Arc::try_unwrap(webhooks.hooks.pop().unwrap()).ok().unwrap();
+        hook.url = url.to_string();
+        hook.retry_backoff = Duration::from_secs(3600);
+        webhooks.hooks.push(Arc::new(hook));
+    }
+    core.webhook.config = webhooks;
+    core.store = Some(store);
+    let (tx, rx) = mpsc::channel::<WebhookEvent>(128);
+    core.webhook.tx = tx;
+    let core = Arc::new(core);
+    rx.spawn(core.clone());
+    core
+}
+
+async fn spawn_webhook_server(port: u16, fail_first: usize) -> mpsc::Receiver<WebhookRequest> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    let failures = Arc::new(AtomicUsize::new(fail_first));
+    let (tx, rx) = mpsc::channel(128);
+
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let failures = failures.clone();
+            let tx = tx.clone();
+
+            tokio::spawn(async move {
+                let mut buf = Vec::new();
+                let mut bytes = [0u8; 4096];
+                loop {
+                    // Read a full request
+                    let request = loop {
+                        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                            let headers = String::from_utf8_lossy(&buf[..pos])
+                                .lines()
+                                .skip(1)
+                                .filter_map(|line| {
+                                    line.split_once(':').map(|(name, value)| {
+                                        (name.trim().to_lowercase(), value.trim().to_string())
+                                    })
+                                })
+                                .collect::<Vec<_>>();
+                            let body_len = headers
+                                .iter()
+                                .find(|(name, _)| name == "content-length")
+                                .map_or(0, |(_, value)| value.parse::<usize>().unwrap());
+                            if buf.len() >= pos + 4 + body_len {
+                                let body =
+                                    serde_json::from_slice(&buf[pos + 4..pos + 4 + body_len])
+                                        .unwrap();
+                                buf.drain(..pos + 4 + body_len);
+                                break WebhookRequest { headers, body };
+                            }
+                        }
+                        match stream.read(&mut bytes).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => buf.extend_from_slice(&bytes[..n]),
+                        }
+                    };
+
+                    if failures
+                        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                        .is_ok()
+                    {
+                        stream
+                            .write_all(
+                                b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n",
+                            )
+                            .await
+                            .unwrap();
+                    } else {
+                        stream
+                            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                            .await
+                            .unwrap();
+                        tx.send(request).await.unwrap();
+                    }
+                }
+            });
+        }
+    });
+
+    rx
+}
+
+impl WebhookRequest {
+    fn header(&self, name: &str) -> &str {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map_or("", |(_, value)| value.as_str())
+    }
+}
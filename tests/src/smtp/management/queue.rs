@@ -31,11 +31,15 @@ use directory::config::ConfigDirectory;
 use hyper::{header::AUTHORIZATION, StatusCode};
 use mail_auth::MX;
 use mail_parser::DateTime;
+use store::{
+    audit::{AuditFilter, AuditLog},
+    Store,
+};
 use utils::config::{Config, ServerProtocol};
 
 use crate::smtp::{
-    inbound::TestQueueEvent, management::send_manage_request, outbound::start_test_server,
-    session::TestSession, TestConfig, TestSMTP,
+    inbound::TestQueueEvent, make_temp_dir, management::send_manage_request,
+    outbound::start_test_server, session::TestSession, TestConfig, TestSMTP,
 };
 use smtp::{
     config::IfBlock,
@@ -105,6 +109,31 @@ async fn manage_queue() {
     core.queue.config.notify = IfBlock::new(vec![Duration::from_secs(2000)]);
     core.queue.config.expire = IfBlock::new(Duration::from_secs(3000));
     let local_qr = core.init_test_queue("smtp_manage_queue_local");
+
+    // Management actions are recorded in the audit log
+    let temp_dir = make_temp_dir("smtp_manage_queue_store", true);
+    let store = Arc::new(
+        Store::open(
+            &Config::parse(&format!(
+                concat!(
+                    "store.db.path = \"{}/sqlite.db\"\n",
+                    "store.blob.type = \"local\"\n",
+                    "store.blob.local.path = \"{}\"\n"
+                ),
+                temp_dir.temp_dir.display(),
+                temp_dir.temp_dir.display()
+            ))
+            .unwrap(),
+        )
+        .await
+        .unwrap(),
+    );
+    core.store = Some(store.clone());
+    core.audit = AuditLog {
+        enable: true,
+        retention: Duration::from_secs(86400),
+        syslog: None,
+    };
     let core = Arc::new(core);
     local_qr.queue_rx.spawn(core.clone(), Queue::default());
     let _rx_manage = start_test_server(core.clone(), &[ServerProtocol::Http]);
@@ -386,6 +415,33 @@ async fn manage_queue() {
             "failed for {id}: {filter}"
         );
     }
+    let events = store
+        .audit_query(
+            AuditFilter {
+                action: "queue.cancel".to_string().into(),
+                ..Default::default()
+            },
+            0,
+        )
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 4, "{events:?}");
+    for (id, filter) in [
+        ("a", "example2.org"),
+        ("b", "example1.net"),
+        ("c", "rcpt6@example2.com"),
+        ("d", ""),
+    ] {
+        let target = format!("{:X}", id_map.get(id).unwrap());
+        let event = events
+            .iter()
+            .find(|event| event.target == target)
+            .unwrap_or_else(|| panic!("missing audit event for {id}: {events:?}"));
+        assert_eq!(event.account, "admin");
+        assert_eq!(event.protocol, "http");
+        assert_eq!(event.details, filter);
+        assert!(!event.remote_ip.is_empty());
+    }
     assert_eq!(
         send_manage_request::<Vec<QueueId>>("/admin/queue/list")
             .await
@@ -34,20 +34,24 @@ use mail_auth::{
 use mail_send::smtp::tls::build_tls_connector;
 use sieve::Runtime;
 use smtp_proto::{AUTH_LOGIN, AUTH_PLAIN};
+use store::audit::AuditLog;
 use tokio::sync::mpsc;
 
 use smtp::{
     config::{
-        if_block::ConfigIf, queue::ConfigQueue, session::ConfigSession, throttle::ConfigThrottle,
-        AggregateReport, ArcAuthConfig, Auth, ConfigContext, Connect, Data, DkimAuthConfig,
-        DmarcAuthConfig, DnsBlConfig, Dsn, Ehlo, EnvelopeKey, Extensions, IfBlock, IpRevAuthConfig,
-        Mail, MailAuthConfig, Milter, QueueConfig, QueueOutboundSourceIp, QueueOutboundTimeout,
-        QueueOutboundTls, QueueQuotas, QueueThrottle, Rcpt, Report, ReportAnalysis, ReportConfig,
-        SessionConfig, SessionThrottle, SpfAuthConfig, Throttle, VerifyStrategy,
+        if_block::ConfigIf, queue::ConfigQueue, session::ConfigSession, spam::ConfigSpamFilter,
+        throttle::ConfigThrottle, AdaptiveKey, AggregateReport, ArcAuthConfig, Auth, ConfigContext,
+        Connect, Data, DkimAuthConfig, DmarcAuthConfig, DnsBlConfig, Dsn, Ehlo, EnvelopeKey,
+        Extensions, Greylist, IfBlock, IpRevAuthConfig, JournalConfig, Mail, MailAuthConfig,
+        Milter, QuarantineConfig, QuarantineDigestConfig, QueueConfig, QueueOutboundAdaptive,
+        QueueOutboundConnection, QueueOutboundSourceIp, QueueOutboundTimeout, QueueOutboundTls,
+        QueueQuotas, QueueThrottle, Rcpt, Report, ReportAnalysis, ReportConfig, SessionConfig,
+        SessionThrottle, SpamFilterConfig, SpfAuthConfig, SrsConfig, Suppression, Throttle,
+        VerifyStrategy, WebhookConfig,
     },
     core::{
         throttle::ThrottleKeyHasherBuilder, QueueCore, ReportCore, Resolvers, SessionCore,
-        SieveConfig, SieveCore, TlsConnectors, SMTP,
+        SieveConfig, SieveCore, TlsConnectors, WebhookCore, SMTP,
     },
     outbound::dane::DnssecResolver,
 };
@@ -181,6 +185,11 @@ impl TestConfig for SMTP {
             mail_auth: MailAuthConfig::test(),
             report: ReportCore::test(),
             sieve: SieveCore::test(),
+            spam: SpamFilterConfig::test(),
+            quarantine: QuarantineConfig::test(),
+            webhook: WebhookCore::test(),
+            store: None,
+            audit: AuditLog::disabled(),
             delivery_tx: mpsc::channel(1).0,
         }
     }
@@ -195,6 +204,7 @@ impl TestConfig for SessionCore {
                 ThrottleKeyHasherBuilder::default(),
                 16,
             ),
+            greylist: Default::default(),
         }
     }
 }
@@ -218,6 +228,7 @@ impl TestConfig for SessionConfig {
                 require: IfBlock::new(true),
                 reject_non_fqdn: IfBlock::new(false),
             },
+            hooks: vec![],
             extensions: Extensions {
                 pipelining: IfBlock::new(true),
                 chunking: IfBlock::new(true),
@@ -249,6 +260,19 @@ impl TestConfig for SessionConfig {
                 errors_wait: IfBlock::new(Duration::from_secs(1)),
                 max_recipients: IfBlock::new(3),
                 rewrite: IfBlock::new(None),
+                greylist: Greylist {
+                    enable: IfBlock::new(false),
+                    delay: IfBlock::new(Duration::from_secs(5 * 60)),
+                    retry_window: IfBlock::new(Duration::from_secs(24 * 60 * 60)),
+                    lifetime: IfBlock::new(Duration::from_secs(35 * 24 * 60 * 60)),
+                    auto_whitelist: IfBlock::new(true),
+                },
+                suppression: Suppression {
+                    enable: IfBlock::new(false),
+                    expire_bounce: Duration::from_secs(30 * 86400),
+                    expire_complaint: Duration::from_secs(365 * 86400),
+                    complaint_senders: Default::default(),
+                },
             },
             data: Data {
                 script: IfBlock::new(None),
@@ -263,6 +287,8 @@ impl TestConfig for SessionConfig {
                 add_date: IfBlock::new(true),
                 pipe_commands: vec![],
                 milters: vec![],
+                clamav: vec![],
+                attachments: Default::default(),
             },
         }
     }
@@ -288,6 +314,9 @@ impl TestConfig for QueueCore {
                 pki_verify: build_tls_connector(false),
                 dummy_verify: build_tls_connector(true),
             },
+            connections: Default::default(),
+            adaptive: Default::default(),
+            journal: JournalConfig::test(),
         }
     }
 }
@@ -314,6 +343,22 @@ impl TestConfig for QueueConfig {
                 mta_sts: IfBlock::new(smtp::config::RequireOptional::Optional),
                 start: IfBlock::new(smtp::config::RequireOptional::Optional),
             },
+            connection: QueueOutboundConnection {
+                reuse: IfBlock::new(false),
+                max_messages: IfBlock::new(100),
+                max_idle: IfBlock::new(5),
+                idle_timeout: IfBlock::new(Duration::from_secs(30)),
+            },
+            adaptive: QueueOutboundAdaptive {
+                enable: IfBlock::new(false),
+                key: AdaptiveKey::Mx,
+                initial_rate: 1.0,
+                min_rate: 1.0 / 60.0,
+                max_rate: 10.0,
+                backoff_factor: 0.5,
+                ramp_up_factor: 1.05,
+                cooldown: Duration::from_secs(300),
+            },
             dsn: Dsn {
                 name: IfBlock::new("Mail Delivery Subsystem".to_string()),
                 address: IfBlock::new("MAILER-DAEMON@example.org".to_string()),
@@ -370,6 +415,12 @@ impl TestConfig for MailAuthConfig {
                 ip_lookup: vec![],
                 domain_lookup: vec![],
             },
+            srs: SrsConfig {
+                enable: IfBlock::new(false),
+                domain: "mx.example.org".to_string(),
+                secrets: vec![],
+                max_age: Duration::from_secs(21 * 86400),
+            },
         }
     }
 }
@@ -393,6 +444,8 @@ impl TestConfig for ReportConfig {
                 addresses: vec![],
                 forward: true,
                 store: None,
+                retention: Duration::from_secs(90 * 86400),
+                max_rows: 1000,
                 report_id: 0.into(),
             },
             dkim: Report::test(),
@@ -447,6 +500,54 @@ impl TestConfig for SieveCore {
     }
 }
 
+impl TestConfig for SpamFilterConfig {
+    fn test() -> Self {
+        Config::parse("")
+            .unwrap()
+            .parse_spam_filter(&ConfigContext::new(&[]))
+            .unwrap()
+    }
+}
+
+impl TestConfig for JournalConfig {
+    fn test() -> Self {
+        JournalConfig {
+            enable: IfBlock::new(false),
+            address: IfBlock::new(None),
+            name: IfBlock::new("Journal".to_string()),
+            from_address: IfBlock::new("MAILER-DAEMON@example.org".to_string()),
+            sign: IfBlock::default(),
+        }
+    }
+}
+
+impl TestConfig for QuarantineConfig {
+    fn test() -> Self {
+        QuarantineConfig {
+            retention: Duration::from_secs(30 * 86400),
+            digest: QuarantineDigestConfig {
+                enable: IfBlock::new(false),
+                from_name: "Quarantine".to_string(),
+                from_address: "MAILER-DAEMON@example.org".to_string(),
+                subject: "Quarantined messages digest".to_string(),
+                url: "https://mx.example.org".to_string(),
+                secret: b"quarantine-secret".to_vec(),
+                link_expiry: Duration::from_secs(7 * 86400),
+                sign: IfBlock::default(),
+            },
+        }
+    }
+}
+
+impl TestConfig for WebhookCore {
+    fn test() -> Self {
+        Self {
+            config: WebhookConfig { hooks: vec![] },
+            tx: mpsc::channel(1024).0,
+        }
+    }
+}
+
 pub struct TempDir {
     pub temp_dir: PathBuf,
     pub delete: bool,
@@ -0,0 +1,141 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use mail_auth::MX;
+use utils::config::ServerProtocol;
+
+use crate::smtp::{
+    inbound::TestQueueEvent, outbound::start_test_server, session::TestSession, TestConfig,
+    TestSMTP,
+};
+use smtp::{
+    config::IfBlock,
+    core::{Session, SMTP},
+    queue::{manager::Queue, DeliveryAttempt},
+};
+
+#[tokio::test]
+#[serial_test::serial]
+async fn adaptive_rate() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::DEBUG)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Start test server
+    let mut core = SMTP::test();
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    let mut remote_qr = core.init_test_queue("smtp_adaptive_remote");
+    let _rx = start_test_server(core.into(), &[ServerProtocol::Smtp]);
+
+    // Add mock DNS entries
+    let mut core = SMTP::test();
+    core.resolvers.dns.mx_add(
+        "foobar.org",
+        vec![MX {
+            exchanges: vec!["mx1.foobar.org".to_string()],
+            preference: 10,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.resolvers.dns.ipv4_add(
+        "mx1.foobar.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+
+    // Enable adaptive rate control starting at 10 messages per second
+    let mut local_qr = core.init_test_queue("smtp_adaptive_local");
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    let config = &mut core.queue.config.adaptive;
+    config.enable = IfBlock::new(true);
+    config.initial_rate = 10.0;
+    config.min_rate = 1.0;
+    config.max_rate = 20.0;
+    config.backoff_factor = 0.5;
+    config.ramp_up_factor = 2.0;
+    config.cooldown = Duration::ZERO;
+
+    let core = Arc::new(core);
+    let mut queue = Queue::default();
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+
+    // A rate limit deferral halves the rate
+    session
+        .send_message(
+            "john@test.org",
+            &["ratelimit@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    DeliveryAttempt::from(local_qr.read_event().await.unwrap_message())
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    local_qr.read_event().await.unwrap_retry();
+    remote_qr.assert_empty_queue();
+    let report = core.queue.adaptive_report();
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].key, "mx1.foobar.org");
+    assert_eq!(report[0].rate, 300.0);
+    assert_eq!(report[0].deferrals, 1);
+    assert_eq!(report[0].successes, 0);
+    assert!(report[0].last_deferral.is_some());
+
+    // Messages to the same MX are held until the next slot
+    session
+        .send_message("john@test.org", &["bill@foobar.org"], "test:no_dkim", "250")
+        .await;
+    DeliveryAttempt::from(local_qr.read_event().await.unwrap_message())
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    let retry = local_qr.read_event().await.unwrap_retry();
+    assert!(retry.due > Instant::now());
+    remote_qr.assert_empty_queue();
+
+    // Successful deliveries ramp the rate back up
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    DeliveryAttempt::from(retry.inner)
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    local_qr.read_event().await.unwrap_done();
+    remote_qr.read_event().await.unwrap_message();
+    let report = core.queue.adaptive_report();
+    assert_eq!(report[0].rate, 600.0);
+    assert_eq!(report[0].deferrals, 1);
+    assert_eq!(report[0].successes, 1);
+
+    // Recently used hosts survive cleanup
+    core.queue.adaptive_cleanup();
+    assert_eq!(core.queue.adaptive_report().len(), 1);
+}
@@ -30,10 +30,12 @@ use utils::config::{Config, ServerProtocol};
 
 use super::add_test_certs;
 
+pub mod adaptive;
 pub mod dane;
 pub mod extensions;
 pub mod lmtp;
 pub mod mta_sts;
+pub mod reuse;
 pub mod smtp;
 pub mod throttle;
 
@@ -88,7 +90,10 @@ pub fn start_test_server(core: Arc<SMTP>, protocols: &[ServerProtocol]) -> watch
                 server.spawn(smtp_manager.clone(), shutdown_rx)
             }
             ServerProtocol::Http => server.spawn(smtp_admin_manager.clone(), shutdown_rx),
-            ServerProtocol::Imap | ServerProtocol::Jmap | ServerProtocol::ManageSieve => {
+            ServerProtocol::Imap
+            | ServerProtocol::Jmap
+            | ServerProtocol::ManageSieve
+            | ServerProtocol::Pop3 => {
                 unreachable!()
             }
         };
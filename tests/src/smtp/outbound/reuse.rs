@@ -0,0 +1,248 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use mail_auth::MX;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+use utils::config::ServerProtocol;
+
+use crate::smtp::{
+    inbound::TestQueueEvent, outbound::start_test_server, session::TestSession, TestConfig,
+    TestSMTP,
+};
+use smtp::{
+    config::IfBlock,
+    core::{Session, SMTP},
+    queue::{manager::Queue, DeliveryAttempt},
+};
+
+#[tokio::test]
+#[serial_test::serial]
+async fn connection_reuse() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::DEBUG)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Start test server
+    let mut core = SMTP::test();
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    let mut remote_qr = core.init_test_queue("smtp_reuse_remote");
+    let _rx = start_test_server(core.into(), &[ServerProtocol::Smtp]);
+
+    // Add mock DNS entries
+    let mut core = SMTP::test();
+    core.resolvers.dns.mx_add(
+        "foobar.org",
+        vec![MX {
+            exchanges: vec!["mx1.foobar.org".to_string()],
+            preference: 10,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.resolvers.dns.ipv4_add(
+        "mx1.foobar.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+
+    // Enable connection reuse, up to two messages per connection
+    let mut local_qr = core.init_test_queue("smtp_reuse_local");
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    let config = &mut core.queue.config;
+    config.connection.reuse = IfBlock::new(true);
+    config.connection.max_messages = IfBlock::new(2);
+    config.connection.idle_timeout = IfBlock::new(Duration::from_secs(1));
+
+    let core = Arc::new(core);
+    let mut queue = Queue::default();
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+
+    // The first message opens a connection that is kept idle, the second one
+    // reuses it and closes it after reaching the limit, the third one opens a new one.
+    for (rcpt, expected_idle) in [
+        ("bill@foobar.org", 1),
+        ("jane@foobar.org", 0),
+        ("john@foobar.org", 1),
+    ] {
+        session
+            .send_message("john@test.org", &[rcpt], "test:no_dkim", "250")
+            .await;
+        DeliveryAttempt::from(local_qr.read_event().await.unwrap_message())
+            .try_deliver(core.clone(), &mut queue)
+            .await;
+        local_qr.read_event().await.unwrap_done();
+        assert_eq!(
+            remote_qr
+                .read_event()
+                .await
+                .unwrap_message()
+                .recipients
+                .first()
+                .unwrap()
+                .address,
+            rcpt
+        );
+        assert_eq!(core.queue.connections.idle_count(), expected_idle, "{rcpt}");
+    }
+    local_qr.assert_empty_queue();
+    remote_qr.assert_empty_queue();
+
+    // Idle connections expire
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    core.queue.connections.purge();
+    assert_eq!(core.queue.connections.idle_count(), 0);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn connection_reuse_failure_after_data() {
+    // Start mock server that drops the connection after the second DATA
+    let connections = Arc::new(AtomicUsize::new(0));
+    let transactions = Arc::new(AtomicUsize::new(0));
+    let listener = TcpListener::bind("127.0.0.1:9925").await.unwrap();
+    {
+        let connections = connections.clone();
+        let transactions = transactions.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.fetch_add(1, Ordering::Relaxed);
+                tokio::spawn(mock_mx_session(stream, transactions.clone()));
+            }
+        });
+    }
+
+    // Add mock DNS entries
+    let mut core = SMTP::test();
+    core.resolvers.dns.mx_add(
+        "foobar.org",
+        vec![MX {
+            exchanges: vec!["mx1.foobar.org".to_string()],
+            preference: 10,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.resolvers.dns.ipv4_add(
+        "mx1.foobar.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+
+    // Enable connection reuse
+    let mut local_qr = core.init_test_queue("smtp_reuse_failure_local");
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.queue.config.connection.reuse = IfBlock::new(true);
+
+    let core = Arc::new(core);
+    let mut queue = Queue::default();
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+
+    // The first message is delivered and the connection is kept idle
+    session
+        .send_message("john@test.org", &["bill@foobar.org"], "test:no_dkim", "250")
+        .await;
+    DeliveryAttempt::from(local_qr.read_event().await.unwrap_message())
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    local_qr.read_event().await.unwrap_done();
+    assert_eq!(core.queue.connections.idle_count(), 1);
+
+    // The remote host could have accepted the second message before the connection
+    // was dropped, so it must not be sent again over a new connection
+    session
+        .send_message("john@test.org", &["jane@foobar.org"], "test:no_dkim", "250")
+        .await;
+    DeliveryAttempt::from(local_qr.read_event().await.unwrap_message())
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    local_qr.read_event().await.unwrap_retry();
+    local_qr.assert_empty_queue();
+    assert_eq!(core.queue.connections.idle_count(), 0);
+    assert_eq!(connections.load(Ordering::Relaxed), 1);
+    assert_eq!(transactions.load(Ordering::Relaxed), 2);
+}
+
+async fn mock_mx_session(stream: TcpStream, transactions: Arc<AtomicUsize>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut in_data = false;
+    writer
+        .write_all(b"220 mx1.foobar.org ESMTP\r\n")
+        .await
+        .unwrap();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if in_data {
+            if line == "." {
+                in_data = false;
+                if transactions.fetch_add(1, Ordering::Relaxed) == 1 {
+                    return;
+                }
+                writer
+                    .write_all(b"250 2.0.0 Message queued\r\n")
+                    .await
+                    .unwrap();
+            }
+            continue;
+        }
+
+        let response: &[u8] = match line
+            .get(..4)
+            .unwrap_or_default()
+            .to_ascii_uppercase()
+            .as_str()
+        {
+            "EHLO" => b"250-mx1.foobar.org\r\n250 8BITMIME\r\n",
+            "DATA" => {
+                in_data = true;
+                b"354 Start mail input\r\n"
+            }
+            "QUIT" => {
+                let _ = writer.write_all(b"221 Bye\r\n").await;
+                return;
+            }
+            _ => b"250 OK\r\n",
+        };
+        if writer.write_all(response).await.is_err() {
+            return;
+        }
+    }
+}
@@ -23,12 +23,16 @@
 
 use std::{fs, sync::Arc, time::Duration};
 
+use store::Store;
+use utils::config::Config;
+
 use crate::smtp::{
     inbound::TestQueueEvent, make_temp_dir, session::TestSession, TestConfig, TestSMTP,
 };
 use smtp::{
     config::{AddressMatch, IfBlock},
     core::{Session, SMTP},
+    reporting::incoming::{IncomingReportFilter, IncomingReportGroup, IncomingReportType},
 };
 
 #[tokio::test]
@@ -52,6 +56,25 @@ async fn report_analyze() {
     config.forward = false;
     config.store = report_dir.temp_dir.clone().into();
 
+    // Parsed reports are also persisted in the store
+    let store_dir = make_temp_dir("smtp_report_incoming_store", true);
+    core.store = Some(Arc::new(
+        Store::open(
+            &Config::parse(&format!(
+                concat!(
+                    "store.db.path = \"{}/sqlite.db\"\n",
+                    "store.blob.type = \"local\"\n",
+                    "store.blob.local.path = \"{}\"\n"
+                ),
+                store_dir.temp_dir.display(),
+                store_dir.temp_dir.display()
+            ))
+            .unwrap(),
+        )
+        .await
+        .unwrap(),
+    ));
+
     // Create test message
     let core = Arc::new(core);
     let mut session = Session::test(core.clone());
@@ -91,6 +114,134 @@ async fn report_analyze() {
     }
     assert_eq!(total_reports, total_reports_received);
 
+    // Query stored reports
+    for type_ in [
+        IncomingReportType::Dmarc,
+        IncomingReportType::Tls,
+        IncomingReportType::Arf,
+    ] {
+        let reports = core
+            .incoming_report_query(
+                IncomingReportFilter {
+                    type_: type_.into(),
+                    ..Default::default()
+                },
+                0,
+            )
+            .await
+            .unwrap();
+        assert!(!reports.is_empty(), "{type_:?}");
+        assert!(reports.iter().all(|r| r.type_ == type_));
+    }
+    let reports = core
+        .incoming_report_query(
+            IncomingReportFilter {
+                type_: IncomingReportType::Arf.into(),
+                ..Default::default()
+            },
+            0,
+        )
+        .await
+        .unwrap();
+    assert_eq!(reports.len(), 5);
+    let reports = core
+        .incoming_report_query(
+            IncomingReportFilter {
+                type_: IncomingReportType::Arf.into(),
+                source_ip: "192.0.2.1".parse::<std::net::IpAddr>().unwrap().into(),
+                disposition: "auth-failure".to_string().into(),
+                ..Default::default()
+            },
+            0,
+        )
+        .await
+        .unwrap();
+    assert_eq!(reports.len(), 2);
+    assert_eq!(
+        core.incoming_report_query(IncomingReportFilter::default(), 3)
+            .await
+            .unwrap()
+            .len(),
+        3
+    );
+
+    // Aggregate failures per source IP per day
+    let all_failures = core
+        .incoming_report_query(
+            IncomingReportFilter {
+                failures_only: true,
+                ..Default::default()
+            },
+            0,
+        )
+        .await
+        .unwrap();
+    let groups = core
+        .incoming_report_aggregate(
+            IncomingReportFilter {
+                failures_only: true,
+                ..Default::default()
+            },
+            vec![IncomingReportGroup::SourceIp, IncomingReportGroup::Day],
+        )
+        .await
+        .unwrap();
+    assert!(!groups.is_empty());
+    assert!(groups
+        .iter()
+        .all(|g| g.day.is_some() && g.total == g.failures));
+    assert!(groups.windows(2).all(|g| g[0].failures >= g[1].failures));
+    assert_eq!(
+        groups.iter().map(|g| g.failures).sum::<u64>(),
+        all_failures.iter().map(|r| r.count).sum::<u64>()
+    );
+
+    // Reports received twice are only stored once
+    let dmarc_filter = IncomingReportFilter {
+        type_: IncomingReportType::Dmarc.into(),
+        ..Default::default()
+    };
+    let dmarc_reports = core
+        .incoming_report_query(dmarc_filter.clone(), 0)
+        .await
+        .unwrap();
+    session
+        .send_message(
+            "john@test.org",
+            &["reports@foobar.org"],
+            "report:dmarc1",
+            "250",
+        )
+        .await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(
+        core.incoming_report_query(dmarc_filter, 0)
+            .await
+            .unwrap()
+            .len(),
+        dmarc_reports.len()
+    );
+
+    // Indexed and unindexed queries return the same rows
+    let domain = dmarc_reports[0].domain.clone();
+    assert_eq!(
+        core.incoming_report_query(
+            IncomingReportFilter {
+                domain: domain.clone().into(),
+                ..Default::default()
+            },
+            0,
+        )
+        .await
+        .unwrap(),
+        core.incoming_report_query(IncomingReportFilter::default(), 0)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|r| r.domain == domain)
+            .collect::<Vec<_>>()
+    );
+
     // Test delivery to non-report addresses
     session
         .send_message("john@test.org", &["bill@foobar.org"], "test:no_dkim", "250")
@@ -369,6 +369,7 @@ impl TestServerInstance for ServerInstance {
             protocol: ServerProtocol::Smtp,
             data: "220 mx.example.org at your service.\r\n".to_string(),
             tls_acceptor: None,
+            acme_acceptor: None,
             is_tls_implicit: false,
             limiter: ConcurrencyLimiter::new(100),
             shutdown_rx,
@@ -0,0 +1,342 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use ::store::Store;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use utils::{
+    acme::{http_challenge_key, tls_alpn_challenge_key, AcmeCache, AcmeManager, ChallengeType},
+    config::Config,
+};
+
+use super::TempDir;
+
+const BASE_URL: &str = "http://127.0.0.1:9940";
+const DOMAIN: &str = "mx.example.org";
+const TOKEN: &str = "tok1";
+
+#[derive(Default)]
+struct MockAcme {
+    orders: u32,
+    validated: bool,
+    finalized: bool,
+}
+
+#[tokio::test]
+async fn acme_order() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::DEBUG)
+            .finish(),
+    )
+    .unwrap();*/
+
+    let temp_dir = TempDir::new("acme_tests", true);
+    let store = Arc::new(
+        Store::open(
+            &Config::parse(&format!(
+                concat!(
+                    "store.db.path = \"{}/sqlite.db\"\n",
+                    "store.blob.type = \"local\"\n",
+                    "store.blob.local.path = \"{}\"\n"
+                ),
+                temp_dir.path.display(),
+                temp_dir.path.display()
+            ))
+            .unwrap(),
+        )
+        .await
+        .unwrap(),
+    );
+    let state = spawn_mock_acme_server(store.clone()).await;
+    let managers = (0..2)
+        .map(|_| {
+            Arc::new(AcmeManager::new(
+                "test".to_string(),
+                format!("{BASE_URL}/directory"),
+                vec![DOMAIN.to_string()],
+                vec!["admin@example.org".to_string()],
+                ChallengeType::Http01,
+                Duration::from_secs(30 * 86400),
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    // Two nodes race to renew, only the lease holder places the order
+    let (a, b) = tokio::join!(
+        managers[0].process(store.as_ref()),
+        managers[1].process(store.as_ref())
+    );
+    let mut renew_in = [a.unwrap(), b.unwrap()];
+    renew_in.sort();
+    assert_eq!(renew_in[0], Duration::from_secs(300));
+    assert!(renew_in[1] > Duration::from_secs(86400), "{renew_in:?}");
+    assert_eq!(state.lock().unwrap().orders, 1);
+    assert_eq!(
+        managers
+            .iter()
+            .filter(|manager| manager.certificate().is_some())
+            .count(),
+        1
+    );
+
+    // The HTTP-01 response is removed once validated
+    assert_eq!(store.read(&http_challenge_key(TOKEN)).await.unwrap(), None);
+
+    // The other node installs the certificate from the store
+    for manager in &managers {
+        assert!(manager.process(store.as_ref()).await.unwrap() > Duration::from_secs(86400));
+        assert!(manager.certificate().is_some());
+    }
+    assert_eq!(state.lock().unwrap().orders, 1);
+
+    // Leases are exclusive until released or expired
+    let lock_key = format!("acme:lock:{DOMAIN}");
+    assert!(store
+        .lock(&lock_key, 1, Duration::from_secs(60))
+        .await
+        .unwrap());
+    assert!(store
+        .lock(&lock_key, 1, Duration::from_secs(60))
+        .await
+        .unwrap());
+    assert!(!store
+        .lock(&lock_key, 2, Duration::from_secs(60))
+        .await
+        .unwrap());
+    store.unlock(&lock_key, 2).await.unwrap();
+    assert!(!store
+        .lock(&lock_key, 2, Duration::from_secs(60))
+        .await
+        .unwrap());
+    store.unlock(&lock_key, 1).await.unwrap();
+    assert!(store
+        .lock(&lock_key, 2, Duration::from_secs(0))
+        .await
+        .unwrap());
+    assert!(store
+        .lock(&lock_key, 1, Duration::from_secs(60))
+        .await
+        .unwrap());
+    store.unlock(&lock_key, 1).await.unwrap();
+
+    // TLS-ALPN-01 challenges published by one node are answered by any node
+    managers[1].clone().spawn(store.clone());
+    assert!(managers[1].tls_alpn_certificate(DOMAIN).await.is_none());
+    AcmeCache::write(
+        store.as_ref(),
+        &tls_alpn_challenge_key(DOMAIN),
+        format!("{TOKEN}.thumbprint").into_bytes(),
+    )
+    .await
+    .unwrap();
+    assert!(managers[1].tls_alpn_certificate(DOMAIN).await.is_some());
+    assert!(managers[1]
+        .tls_alpn_certificate("other.example.org")
+        .await
+        .is_none());
+    store.delete(&tls_alpn_challenge_key(DOMAIN)).await.unwrap();
+    assert!(managers[1].tls_alpn_certificate(DOMAIN).await.is_none());
+
+    temp_dir.delete();
+}
+
+async fn spawn_mock_acme_server(store: Arc<Store>) -> Arc<Mutex<MockAcme>> {
+    let listener = TcpListener::bind("127.0.0.1:9940").await.unwrap();
+    let state = Arc::new(Mutex::new(MockAcme::default()));
+    let cert_pem = rcgen::generate_simple_self_signed(vec![DOMAIN.to_string()])
+        .unwrap()
+        .serialize_pem()
+        .unwrap();
+
+    let state_ = state.clone();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let state = state_.clone();
+            let store = store.clone();
+            let cert_pem = cert_pem.clone();
+
+            tokio::spawn(async move {
+                // Read request, signatures are not verified
+                let mut buf = Vec::new();
+                let mut bytes = [0u8; 4096];
+                let request_line = loop {
+                    match stream.read(&mut bytes).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => buf.extend_from_slice(&bytes[..n]),
+                    }
+                    if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                        let headers = String::from_utf8_lossy(&buf[..pos]).into_owned();
+                        let body_len = headers
+                            .lines()
+                            .find_map(|line| {
+                                let (name, value) = line.split_once(':')?;
+                                if name.eq_ignore_ascii_case("content-length") {
+                                    value.trim().parse::<usize>().ok()
+                                } else {
+                                    None
+                                }
+                            })
+                            .unwrap_or(0);
+                        if buf.len() >= pos + 4 + body_len {
+                            break headers.lines().next().unwrap_or_default().to_string();
+                        }
+                    }
+                };
+                let mut parts = request_line.split(' ');
+                let method = parts.next().unwrap_or_default();
+                let path = parts.next().unwrap_or_default();
+
+                // Validate the HTTP-01 response published in the store
+                if path == "/chall/1" {
+                    let key_authorization = store.read(&http_challenge_key(TOKEN)).await.unwrap();
+                    state.lock().unwrap().validated = key_authorization.map_or(false, |value| {
+                        value.starts_with(format!("{TOKEN}.").as_bytes())
+                    });
+                }
+
+                let (status, location, content_type, body) = {
+                    let mut state = state.lock().unwrap();
+                    match (method, path) {
+                        ("GET", "/directory") => (
+                            "200 OK",
+                            None,
+                            "application/json",
+                            serde_json::json!({
+                                "newNonce": format!("{BASE_URL}/nonce"),
+                                "newAccount": format!("{BASE_URL}/account"),
+                                "newOrder": format!("{BASE_URL}/order"),
+                            })
+                            .to_string(),
+                        ),
+                        ("HEAD", "/nonce") => ("200 OK", None, "text/plain", String::new()),
+                        ("POST", "/account") => (
+                            "201 Created",
+                            Some(format!("{BASE_URL}/account/1")),
+                            "application/json",
+                            "{}".to_string(),
+                        ),
+                        ("POST", "/order") => {
+                            state.orders += 1;
+                            (
+                                "201 Created",
+                                Some(format!("{BASE_URL}/order/1")),
+                                "application/json",
+                                mock_order(&state),
+                            )
+                        }
+                        ("POST", "/order/1") => {
+                            ("200 OK", None, "application/json", mock_order(&state))
+                        }
+                        ("POST", "/finalize/1") => {
+                            state.finalized = state.validated;
+                            ("200 OK", None, "application/json", mock_order(&state))
+                        }
+                        ("POST", "/authz/1") => (
+                            "200 OK",
+                            None,
+                            "application/json",
+                            serde_json::json!({
+                                "identifier": { "type": "dns", "value": DOMAIN },
+                                "status": if state.validated { "valid" } else { "pending" },
+                                "challenges": [{
+                                    "type": "http-01",
+                                    "url": format!("{BASE_URL}/chall/1"),
+                                    "token": TOKEN,
+                                }],
+                            })
+                            .to_string(),
+                        ),
+                        ("POST", "/chall/1") => {
+                            ("200 OK", None, "application/json", "{}".to_string())
+                        }
+                        ("POST", "/cert/1") if state.finalized => (
+                            "200 OK",
+                            None,
+                            "application/pem-certificate-chain",
+                            cert_pem,
+                        ),
+                        _ => (
+                            "404 Not Found",
+                            None,
+                            "application/problem+json",
+                            serde_json::json!({ "detail": "Not found" }).to_string(),
+                        ),
+                    }
+                };
+
+                stream
+                    .write_all(
+                        format!(
+                            concat!(
+                                "HTTP/1.1 {}\r\n",
+                                "Content-Type: {}\r\n",
+                                "Content-Length: {}\r\n",
+                                "Replay-Nonce: nonce\r\n",
+                                "{}",
+                                "Connection: close\r\n\r\n{}"
+                            ),
+                            status,
+                            content_type,
+                            body.len(),
+                            location
+                                .map(|location| format!("Location: {location}\r\n"))
+                                .unwrap_or_default(),
+                            if method != "HEAD" { body.as_str() } else { "" }
+                        )
+                        .as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+            });
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    state
+}
+
+fn mock_order(state: &MockAcme) -> String {
+    serde_json::json!({
+        "status": if state.finalized {
+            "valid"
+        } else if state.validated {
+            "ready"
+        } else {
+            "pending"
+        },
+        "authorizations": [format!("{BASE_URL}/authz/1")],
+        "finalize": format!("{BASE_URL}/finalize/1"),
+        "certificate": format!("{BASE_URL}/cert/1"),
+    })
+    .to_string()
+}
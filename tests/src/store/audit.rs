@@ -0,0 +1,164 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use ::store::{
+    audit::{AuditEvent, AuditFilter, AuditLog},
+    Store,
+};
+use utils::config::Config;
+
+use super::TempDir;
+
+#[tokio::test]
+async fn audit_log() {
+    let temp_dir = TempDir::new("audit_tests", true);
+    let store = Store::open(
+        &Config::parse(&format!(
+            concat!(
+                "store.db.path = \"{}/sqlite.db\"\n",
+                "store.blob.type = \"local\"\n",
+                "store.blob.local.path = \"{}\"\n"
+            ),
+            temp_dir.path.display(),
+            temp_dir.path.display()
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+    let audit = AuditLog {
+        enable: true,
+        retention: Duration::from_secs(86400),
+        syslog: None,
+    };
+
+    // Events older than the retention period are recorded with past timestamps
+    for (timestamp, account, action) in [
+        (1000, "admin", "account.delete"),
+        (2000, "admin", "queue.cancel"),
+        (3000, "jane", "acl.set"),
+    ] {
+        audit
+            .record(
+                &store,
+                AuditEvent {
+                    timestamp,
+                    ..AuditEvent::new("http", action).with_account(account)
+                },
+            )
+            .await;
+    }
+    audit
+        .record(
+            &store,
+            AuditEvent::new("http", "account.rename").with_account("admin"),
+        )
+        .await;
+    AuditLog::disabled()
+        .record(&store, AuditEvent::new("http", "report.cancel"))
+        .await;
+
+    // Events are returned newest first and can be filtered
+    for (filter, limit, expected) in [
+        (
+            AuditFilter::default(),
+            0,
+            vec![
+                "account.rename",
+                "acl.set",
+                "queue.cancel",
+                "account.delete",
+            ],
+        ),
+        (
+            AuditFilter {
+                account: "admin".to_string().into(),
+                ..Default::default()
+            },
+            0,
+            vec!["account.rename", "queue.cancel", "account.delete"],
+        ),
+        (
+            AuditFilter {
+                action: "account".to_string().into(),
+                ..Default::default()
+            },
+            0,
+            vec!["account.rename", "account.delete"],
+        ),
+        (
+            AuditFilter {
+                from: Some(2000),
+                to: Some(3000),
+                ..Default::default()
+            },
+            0,
+            vec!["acl.set", "queue.cancel"],
+        ),
+        (AuditFilter::default(), 2, vec!["account.rename", "acl.set"]),
+    ] {
+        assert_eq!(
+            store
+                .audit_query(filter.clone(), limit)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|event| event.action)
+                .collect::<Vec<_>>(),
+            expected,
+            "{filter:?}"
+        );
+    }
+
+    // Restores keep the events recorded after the backup was taken
+    let backup_path = temp_dir.path.join("backup");
+    store.backup(&backup_path, None).await.unwrap();
+    audit
+        .record(
+            &store,
+            AuditEvent::new("http", "account.delete")
+                .with_account("admin")
+                .with_target("john"),
+        )
+        .await;
+    store.restore(&backup_path, None).await.unwrap();
+    let events = store.audit_query(AuditFilter::default(), 0).await.unwrap();
+    assert_eq!(events.len(), 5, "{events:?}");
+    assert!(events.iter().any(|event| event.target == "john"));
+
+    // Events past their retention period are purged
+    store.purge_audit_log().await.unwrap();
+    let mut actions = store
+        .audit_query(AuditFilter::default(), 0)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|event| event.action)
+        .collect::<Vec<_>>();
+    actions.sort_unstable();
+    assert_eq!(actions, vec!["account.delete", "account.rename"]);
+
+    temp_dir.delete();
+}
@@ -0,0 +1,211 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use store::{
+    hold::LegalHold,
+    write::{log::ChangeLogBuilder, BatchBuilder, F_VALUE},
+    BlobKind, Store, ValueKey,
+};
+use utils::config::Config;
+
+use crate::store::TempDir;
+
+const CONFIG: &str = r#"
+[store.db]
+path = "{TMP}/_backup_test.db?mode=rwc"
+
+[store.blob]
+type = "local"
+
+[store.blob.local]
+path = "{TMP}/blobs"
+
+"#;
+
+#[tokio::test]
+pub async fn backup_tests() {
+    let temp_dir = TempDir::new("backup_tests", true);
+    let db = Store::open(
+        &Config::parse(&CONFIG.replace("{TMP}", temp_dir.path.as_path().to_str().unwrap()))
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+    db.destroy().await;
+    let full_path = temp_dir.path.join("full");
+    let incremental_path = temp_dir.path.join("incremental");
+
+    // Create a full backup
+    for account_id in [1, 2] {
+        write_document(&db, account_id, 0, "original").await;
+    }
+    set_list(&db, 2, "original").await;
+    let manifest = db.backup(&full_path, None).await.unwrap();
+    assert!(manifest.is_full());
+    assert_eq!(manifest.accounts.len(), 2);
+    assert!(db.backup(&full_path, None).await.is_err());
+
+    // Incremental backups should only include modified accounts
+    write_document(&db, 1, 1, "updated").await;
+    set_list(&db, 2, "updated").await;
+    db.set_legal_hold(
+        1,
+        LegalHold {
+            since: 1,
+            reason: "litigation".to_string(),
+        }
+        .into(),
+    )
+    .await
+    .unwrap();
+    let manifest = db
+        .backup(&incremental_path, Some(&full_path))
+        .await
+        .unwrap();
+    assert_eq!(manifest.included, Some(vec![1, u32::MAX]));
+
+    // Restore a single account
+    write_document(&db, 1, 2, "not backed up").await;
+    write_document(&db, 2, 1, "not backed up").await;
+    db.set_legal_hold(1, None).await.unwrap();
+    set_list(&db, 1, "not backed up").await;
+    db.restore(&incremental_path, Some(1)).await.unwrap();
+    assert_document(&db, 1, 0, Some("original")).await;
+    assert_document(&db, 1, 1, Some("updated")).await;
+    assert_document(&db, 1, 2, None).await;
+    assert_document(&db, 2, 1, Some("not backed up")).await;
+    assert_eq!(
+        db.get_legal_hold(1).await.unwrap().map(|hold| hold.reason),
+        Some("litigation".to_string())
+    );
+    assert_list(&db, 1, None).await;
+
+    // Server-wide values of accounts not included in an incremental
+    // backup are restored from the most recent backup
+    set_list(&db, 2, "not backed up").await;
+    db.restore(&incremental_path, Some(2)).await.unwrap();
+    assert_document(&db, 2, 0, Some("original")).await;
+    assert_document(&db, 2, 1, None).await;
+    assert_list(&db, 2, Some("updated")).await;
+
+    // Restore the whole server
+    db.restore(&incremental_path, None).await.unwrap();
+    assert_document(&db, 1, 1, Some("updated")).await;
+    assert_document(&db, 2, 0, Some("original")).await;
+    assert_document(&db, 2, 1, None).await;
+
+    temp_dir.delete();
+}
+
+#[cfg(feature = "foundationdb")]
+#[tokio::test]
+pub async fn backup_paged_tests() {
+    let temp_dir = TempDir::new("backup_paged_tests", true);
+    let db = Store::open(
+        &Config::parse(&CONFIG.replace("{TMP}", temp_dir.path.as_path().to_str().unwrap()))
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+    db.destroy().await;
+    let full_path = temp_dir.path.join("full");
+
+    // Write enough data to span multiple range reads
+    let text = "x".repeat(1024);
+    for document_id in 0..1000 {
+        write_document(&db, 1, document_id, &format!("{document_id}{text}")).await;
+    }
+    let manifest = db.backup(&full_path, None).await.unwrap();
+    let (from, to) = manifest.versions.expect("missing read versions");
+    assert!(from <= to);
+
+    db.destroy().await;
+    db.restore(&full_path, None).await.unwrap();
+    for document_id in [0, 499, 999] {
+        assert_document(&db, 1, document_id, Some(&format!("{document_id}{text}"))).await;
+    }
+
+    temp_dir.delete();
+}
+
+async fn set_list(db: &Store, account_id: u32, item: &str) {
+    db.set_sieve_list(account_id, "vip", vec![item.to_string()].into())
+        .await
+        .unwrap();
+}
+
+async fn assert_list(db: &Store, account_id: u32, expected: Option<&str>) {
+    assert_eq!(
+        db.get_sieve_list(account_id, "vip").await.unwrap(),
+        expected.map(|item| vec![item.to_string()]),
+        "list for account {account_id}"
+    );
+}
+
+async fn write_document(db: &Store, account_id: u32, document_id: u32, text: &str) {
+    let change_id = db.assign_change_id(account_id).await.unwrap();
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(account_id)
+        .with_collection(0u8)
+        .create_document(document_id)
+        .value(0u8, text, F_VALUE)
+        .custom(ChangeLogBuilder::with_change_id(change_id).with_log_insert(0u8, document_id));
+    db.write(batch.build()).await.unwrap();
+    db.put_blob(
+        &BlobKind::Linked {
+            account_id,
+            collection: 0,
+            document_id,
+        },
+        text.as_bytes(),
+    )
+    .await
+    .unwrap();
+}
+
+async fn assert_document(db: &Store, account_id: u32, document_id: u32, expected: Option<&str>) {
+    assert_eq!(
+        db.get_value::<String>(ValueKey::new(account_id, 0u8, document_id, 0u8))
+            .await
+            .unwrap()
+            .as_deref(),
+        expected,
+        "account {account_id}, document {document_id}"
+    );
+    assert_eq!(
+        db.get_blob(
+            &BlobKind::Linked {
+                account_id,
+                collection: 0,
+                document_id,
+            },
+            0..u32::MAX
+        )
+        .await
+        .unwrap()
+        .as_deref(),
+        expected.map(|text| text.as_bytes()),
+        "blob for account {account_id}, document {document_id}"
+    );
+}
@@ -0,0 +1,97 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use ::store::Store;
+use utils::config::Config;
+
+use super::TempDir;
+
+#[tokio::test]
+async fn sieve_lists() {
+    let temp_dir = TempDir::new("sieve_lists_tests", true);
+    let store = Store::open(
+        &Config::parse(&format!(
+            concat!(
+                "store.db.path = \"{}/sqlite.db\"\n",
+                "store.blob.type = \"local\"\n",
+                "store.blob.local.path = \"{}\"\n"
+            ),
+            temp_dir.path.display(),
+            temp_dir.path.display()
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+
+    // Large lists exceed the maximum value size of some backends
+    let items = (0..1000)
+        .map(|num| format!("{num:04}{}", "x".repeat(251)))
+        .collect::<Vec<_>>();
+    store
+        .set_sieve_list(0, "allow", items.clone().into())
+        .await
+        .unwrap();
+    store
+        .set_sieve_list(0, "block", vec!["spam@example.org".to_string()].into())
+        .await
+        .unwrap();
+    store
+        .set_sieve_list(1, "allow", vec!["jane@example.org".to_string()].into())
+        .await
+        .unwrap();
+    assert_eq!(store.get_sieve_list(0, "allow").await.unwrap(), Some(items));
+    assert_eq!(
+        store.get_sieve_list_names(0).await.unwrap(),
+        vec!["allow".to_string(), "block".to_string()]
+    );
+
+    // Replacing a list removes the items that are no longer present
+    store
+        .set_sieve_list(
+            0,
+            "allow",
+            vec!["bill@example.org".to_string(), "0001".to_string()].into(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_sieve_list(0, "allow").await.unwrap(),
+        Some(vec!["0001".to_string(), "bill@example.org".to_string()])
+    );
+    store.set_sieve_list(0, "block", None).await.unwrap();
+    assert_eq!(store.get_sieve_list(0, "block").await.unwrap(), None);
+
+    // Deleting the lists of an account leaves other accounts untouched
+    store.delete_sieve_lists(0).await.unwrap();
+    assert_eq!(
+        store.get_sieve_list_names(0).await.unwrap(),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        store.get_sieve_list(1, "allow").await.unwrap(),
+        Some(vec!["jane@example.org".to_string()])
+    );
+
+    temp_dir.delete();
+}
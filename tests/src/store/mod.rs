@@ -21,9 +21,13 @@
  * for more details.
 */
 
+pub mod acme;
+pub mod audit;
 #[cfg(feature = "foundationdb")]
 pub mod assign_id;
+pub mod backup;
 pub mod blob;
+pub mod lists;
 pub mod query;
 
 use std::{io::Read, sync::Arc};